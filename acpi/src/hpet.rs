@@ -0,0 +1,54 @@
+//! # HPET Description Table
+//!
+//! The `HPET` table tells the operating system where the High Precision Event Timer's register block lives. It is a fixed-size table: after the standard header come the hardware ID of the event timer block, a Generic Address Structure (GAS) holding the base address, the HPET sequence number, and the minimum periodic tick the hardware supports.
+
+use crate::SdtHeader;
+
+/// Parsed view of the HPET table.
+#[derive(Debug, Clone, Copy)]
+pub struct HpetTable {
+    header: &'static SdtHeader,
+}
+
+/// Offset of the first field after the standard header.
+const BODY: usize = core::mem::size_of::<SdtHeader>();
+/// Total length of the HPET table.
+const HPET_TABLE_LEN: usize = BODY + 20;
+
+impl HpetTable {
+    /// Wraps a table with the `HPET` signature.
+    ///
+    /// Returns `None` if the table is too short.
+    pub fn new(header: &'static SdtHeader) -> Option<Self> {
+        (header.length as usize >= HPET_TABLE_LEN).then_some(HpetTable { header })
+    }
+
+    fn bytes(&self) -> &'static [u8] {
+        self.header.bytes()
+    }
+
+    /// Returns the event timer block ID (vendor ID, comparator count, counter size, ...).
+    pub fn event_timer_block_id(&self) -> u32 {
+        u32::from_le_bytes(self.bytes()[BODY..BODY + 4].try_into().unwrap())
+    }
+
+    /// Returns the address space of the register block (0 = system memory).
+    pub fn address_space(&self) -> u8 {
+        self.bytes()[BODY + 4]
+    }
+
+    /// Returns the physical address of the HPET register block.
+    pub fn base_address(&self) -> u64 {
+        u64::from_le_bytes(self.bytes()[BODY + 8..BODY + 16].try_into().unwrap())
+    }
+
+    /// Returns the HPET sequence number (0 for the first HPET).
+    pub fn hpet_number(&self) -> u8 {
+        self.bytes()[BODY + 16]
+    }
+
+    /// Returns the minimum clock tick supported in periodic mode without losing interrupts.
+    pub fn minimum_tick(&self) -> u16 {
+        u16::from_le_bytes([self.bytes()[BODY + 17], self.bytes()[BODY + 18]])
+    }
+}
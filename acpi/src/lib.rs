@@ -0,0 +1,240 @@
+//! # ACPI Table Discovery
+//!
+//! This crate locates and parses the ACPI (Advanced Configuration and Power Interface) tables that firmware leaves in memory for the operating system. It only implements what Polished OS currently needs: walking the root table and decoding the MADT (interrupt controller description).
+//!
+//! ## How ACPI Tables Are Found
+//!
+//! - **RSDP (Root System Description Pointer):** A small structure whose address the bootloader obtains from the UEFI configuration table and passes to the kernel. It contains the address of the root table.
+//! - **RSDT / XSDT:** The root table is a list of pointers to every other table. ACPI 1.0 uses the RSDT (32-bit pointers); ACPI 2.0+ adds the XSDT (64-bit pointers), which is preferred when present.
+//! - **System Description Tables:** Every table starts with the same 36-byte [`SdtHeader`], identified by a four-character signature such as `APIC` (MADT), `HPET`, or `FACP`.
+//!
+//! All structures are validated with their checksums (the bytes of a table must sum to zero).
+//!
+//! ## Addressing
+//!
+//! Table addresses are physical. The kernel installs a [`PhysicalMapper`] with [`set_physical_mapper`] that maps each structure before it is read; the RSDP, the root table and every table found through it are mapped with their full length. Without a mapper, physical addresses are dereferenced directly, which relies on the identity mapping set up by UEFI.
+//!
+//! ## Modules
+//! - `hpet`: HPET description table (location of the High Precision Event Timer).
+//! - `madt`: Multiple APIC Description Table parsing (Local APICs, I/O APICs, interrupt source overrides).
+
+#![no_std]
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// HPET description table parsing.
+pub mod hpet;
+/// Multiple APIC Description Table parsing.
+pub mod madt;
+
+pub use hpet::HpetTable;
+pub use madt::{Madt, MadtEntry};
+
+/// Errors returned while locating ACPI tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// The RSDP address was null or did not carry the `"RSD PTR "` signature.
+    InvalidRsdp,
+    /// A structure failed checksum validation.
+    InvalidChecksum,
+    /// The installed [`PhysicalMapper`] could not map a structure.
+    Unmapped,
+}
+
+/// Maps `size` bytes of firmware memory at the physical address `address` and returns the virtual address they can be read at, or `None` if they cannot be mapped.
+pub type PhysicalMapper = fn(address: u64, size: usize) -> Option<u64>;
+
+static PHYSICAL_MAPPER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Installs the function used to map ACPI structures before they are read.
+pub fn set_physical_mapper(mapper: PhysicalMapper) {
+    PHYSICAL_MAPPER.store(mapper as *mut (), Ordering::Release);
+}
+
+/// Returns the virtual address of `size` bytes at the physical `address`, through the installed mapper or the identity mapping.
+fn map_physical(address: u64, size: usize) -> Option<u64> {
+    let mapper = PHYSICAL_MAPPER.load(Ordering::Acquire);
+    if mapper.is_null() {
+        return Some(address);
+    }
+    // Safety: only `PhysicalMapper` function pointers are ever stored.
+    unsafe { core::mem::transmute::<*mut (), PhysicalMapper>(mapper)(address, size) }
+}
+
+/// Maps the table at the physical `address`: first its header, then as many bytes as the header says.
+///
+/// # Safety
+/// `address` must be the physical address of an ACPI table.
+unsafe fn map_table(address: u64) -> Option<&'static SdtHeader> {
+    let header_len = core::mem::size_of::<SdtHeader>();
+    let header = unsafe { &*(map_physical(address, header_len)? as *const SdtHeader) };
+    let length = (header.length as usize).max(header_len);
+    Some(unsafe { &*(map_physical(address, length)? as *const SdtHeader) })
+}
+
+/// Root System Description Pointer (ACPI 2.0+ layout; ACPI 1.0 stops after `rsdt_address`).
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    /// Must be `"RSD PTR "`.
+    pub signature: [u8; 8],
+    /// Checksum of the first 20 bytes.
+    pub checksum: u8,
+    /// OEM identifier.
+    pub oem_id: [u8; 6],
+    /// 0 for ACPI 1.0, 2 for ACPI 2.0 and later.
+    pub revision: u8,
+    /// Physical address of the RSDT.
+    pub rsdt_address: u32,
+    /// Length of the whole structure (ACPI 2.0+).
+    pub length: u32,
+    /// Physical address of the XSDT (ACPI 2.0+).
+    pub xsdt_address: u64,
+    /// Checksum of the whole structure (ACPI 2.0+).
+    pub extended_checksum: u8,
+    /// Reserved.
+    pub reserved: [u8; 3],
+}
+
+/// Header shared by every System Description Table.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    /// Four-character table signature (e.g. `*b"APIC"`).
+    pub signature: [u8; 4],
+    /// Length of the table in bytes, including this header.
+    pub length: u32,
+    /// Table revision.
+    pub revision: u8,
+    /// Makes the bytes of the whole table sum to zero.
+    pub checksum: u8,
+    /// OEM identifier.
+    pub oem_id: [u8; 6],
+    /// OEM table identifier.
+    pub oem_table_id: [u8; 8],
+    /// OEM revision.
+    pub oem_revision: u32,
+    /// Vendor ID of the table compiler.
+    pub creator_id: u32,
+    /// Revision of the table compiler.
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    /// Returns the virtual address this table is mapped at.
+    pub fn address(&self) -> u64 {
+        self as *const SdtHeader as u64
+    }
+
+    /// Returns the bytes of the whole table, header included.
+    pub fn bytes(&self) -> &'static [u8] {
+        unsafe {
+            core::slice::from_raw_parts(self as *const SdtHeader as *const u8, self.length as usize)
+        }
+    }
+
+    /// Returns whether the table's checksum is valid.
+    pub fn is_valid(&self) -> bool {
+        checksum(self.bytes())
+    }
+}
+
+/// Returns whether `bytes` sum to zero (mod 256), the ACPI checksum rule.
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+/// Handle to the ACPI root table (RSDT or XSDT).
+#[derive(Debug, Clone, Copy)]
+pub struct AcpiTables {
+    root: &'static SdtHeader,
+    /// `true` for the XSDT (64-bit entries), `false` for the RSDT (32-bit entries).
+    extended: bool,
+    /// ACPI revision reported by the RSDP.
+    pub revision: u8,
+}
+
+impl AcpiTables {
+    /// Validates the RSDP at `rsdp_address` and locates the root table.
+    ///
+    /// # Safety
+    /// `rsdp_address` must be the RSDP address provided by firmware, and ACPI tables must be mapped at their physical addresses unless a [`PhysicalMapper`] is installed.
+    pub unsafe fn from_rsdp(rsdp_address: u64) -> Result<Self, AcpiError> {
+        if rsdp_address == 0 {
+            return Err(AcpiError::InvalidRsdp);
+        }
+        let rsdp_physical = rsdp_address;
+        let rsdp_address =
+            map_physical(rsdp_physical, core::mem::size_of::<Rsdp>()).ok_or(AcpiError::Unmapped)?;
+        let rsdp = unsafe { &*(rsdp_address as *const Rsdp) };
+        if &rsdp.signature != b"RSD PTR " {
+            return Err(AcpiError::InvalidRsdp);
+        }
+        let v1_bytes = unsafe { core::slice::from_raw_parts(rsdp_address as *const u8, 20) };
+        if !checksum(v1_bytes) {
+            return Err(AcpiError::InvalidChecksum);
+        }
+
+        let (root_address, extended) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+            let length = rsdp.length as usize;
+            let full_address = map_physical(rsdp_physical, length).ok_or(AcpiError::Unmapped)?;
+            let full = unsafe { core::slice::from_raw_parts(full_address as *const u8, length) };
+            if !checksum(full) {
+                return Err(AcpiError::InvalidChecksum);
+            }
+            (rsdp.xsdt_address, true)
+        } else {
+            (rsdp.rsdt_address as u64, false)
+        };
+
+        let root = unsafe { map_table(root_address) }.ok_or(AcpiError::Unmapped)?;
+        if !root.is_valid() {
+            return Err(AcpiError::InvalidChecksum);
+        }
+        Ok(AcpiTables {
+            root,
+            extended,
+            revision: rsdp.revision,
+        })
+    }
+
+    /// Iterates over the headers of all tables listed in the root table, skipping tables that cannot be mapped.
+    pub fn tables(&self) -> impl Iterator<Item = &'static SdtHeader> + '_ {
+        let header_len = core::mem::size_of::<SdtHeader>();
+        let entry_len = if self.extended { 8 } else { 4 };
+        let count = (self.root.length as usize).saturating_sub(header_len) / entry_len;
+        let entries = self.root.address() + header_len as u64;
+        (0..count).filter_map(move |i| {
+            let slot = entries + (i * entry_len) as u64;
+            let address = unsafe {
+                if self.extended {
+                    core::ptr::read_unaligned(slot as *const u64)
+                } else {
+                    core::ptr::read_unaligned(slot as *const u32) as u64
+                }
+            };
+            unsafe { map_table(address) }
+        })
+    }
+
+    /// Finds the first valid table with the given signature.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let hpet = tables.find_table(b"HPET");
+    /// ```
+    pub fn find_table(&self, signature: &[u8; 4]) -> Option<&'static SdtHeader> {
+        self.tables()
+            .find(|table| &table.signature == signature && table.is_valid())
+    }
+
+    /// Finds and parses the MADT (signature `APIC`).
+    pub fn madt(&self) -> Option<Madt> {
+        self.find_table(b"APIC").map(Madt::new)
+    }
+
+    /// Finds and parses the HPET table (signature `HPET`).
+    pub fn hpet(&self) -> Option<HpetTable> {
+        self.find_table(b"HPET").and_then(HpetTable::new)
+    }
+}
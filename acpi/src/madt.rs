@@ -0,0 +1,226 @@
+//! # MADT (Multiple APIC Description Table)
+//!
+//! The MADT describes the interrupt controllers of the machine: one Local APIC per processor, the I/O APICs with the range of Global System Interrupts (GSIs) each one serves, and *interrupt source overrides* that say where legacy ISA IRQs really end up.
+//!
+//! ## ISA IRQs vs. GSIs
+//!
+//! Legacy devices (PIT, keyboard, ATA disks, ...) are wired to ISA IRQs 0-15. Without overrides, ISA IRQ *n* is GSI *n* with edge triggering and active-high polarity. Firmware commonly overrides some of them; for example the PIT's IRQ 0 is usually connected to GSI 2. [`Madt::isa_irq`] applies these overrides.
+//!
+//! ## Table Layout
+//!
+//! After the standard header come the Local APIC address (32 bits) and flags (32 bits), followed by variable-length entries that each start with a type byte and a length byte.
+
+use crate::SdtHeader;
+
+/// MADT flag: the system also has dual 8259 PICs that must be masked when using the APIC.
+pub const PCAT_COMPAT: u32 = 1 << 0;
+
+/// One entry of the MADT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MadtEntry {
+    /// Type 0: a processor and its Local APIC.
+    LocalApic {
+        /// ACPI processor UID.
+        processor_id: u8,
+        /// Local APIC ID.
+        apic_id: u8,
+        /// Bit 0: enabled, bit 1: online capable.
+        flags: u32,
+    },
+    /// Type 1: an I/O APIC.
+    IoApic {
+        /// I/O APIC ID.
+        id: u8,
+        /// Physical address of the register block.
+        address: u32,
+        /// First GSI served by this I/O APIC.
+        gsi_base: u32,
+    },
+    /// Type 2: an ISA IRQ that is connected to a different GSI or with non-default polarity/trigger mode.
+    InterruptSourceOverride {
+        /// Always 0 (ISA).
+        bus: u8,
+        /// ISA IRQ number.
+        source: u8,
+        /// Global System Interrupt the IRQ is connected to.
+        gsi: u32,
+        /// MPS INTI flags (polarity in bits 0-1, trigger mode in bits 2-3).
+        flags: u16,
+    },
+    /// Type 3: a GSI that should be configured as an NMI.
+    NmiSource {
+        /// MPS INTI flags.
+        flags: u16,
+        /// Global System Interrupt.
+        gsi: u32,
+    },
+    /// Type 4: which LINT pin of a Local APIC is wired to NMI.
+    LocalApicNmi {
+        /// ACPI processor UID (0xFF means all processors).
+        processor_id: u8,
+        /// MPS INTI flags.
+        flags: u16,
+        /// LINT pin (0 or 1).
+        lint: u8,
+    },
+    /// Type 5: 64-bit Local APIC address, replacing the 32-bit one in the header.
+    LocalApicAddressOverride {
+        /// Physical address of the Local APIC register block.
+        address: u64,
+    },
+    /// Type 9: a processor whose APIC ID does not fit in 8 bits (x2APIC).
+    LocalX2Apic {
+        /// x2APIC ID.
+        x2apic_id: u32,
+        /// Bit 0: enabled, bit 1: online capable.
+        flags: u32,
+        /// ACPI processor UID.
+        processor_uid: u32,
+    },
+    /// Any entry type this parser does not decode.
+    Unknown {
+        /// Entry type byte.
+        entry_type: u8,
+        /// Entry length in bytes.
+        length: u8,
+    },
+}
+
+/// Routing of a legacy ISA IRQ after applying interrupt source overrides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsaIrq {
+    /// Global System Interrupt the IRQ arrives on.
+    pub gsi: u32,
+    /// `true` if the line is active-low.
+    pub active_low: bool,
+    /// `true` if the line is level-triggered.
+    pub level_triggered: bool,
+}
+
+/// Parsed view of the MADT.
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    header: &'static SdtHeader,
+}
+
+/// Size of the fixed part of the MADT (header + LAPIC address + flags).
+const MADT_FIXED_LEN: usize = core::mem::size_of::<SdtHeader>() + 8;
+
+impl Madt {
+    /// Wraps a table with the `APIC` signature.
+    pub fn new(header: &'static SdtHeader) -> Self {
+        Madt { header }
+    }
+
+    fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// Returns the MADT flags (see [`PCAT_COMPAT`]).
+    pub fn flags(&self) -> u32 {
+        Self::read_u32(self.header.bytes(), core::mem::size_of::<SdtHeader>() + 4)
+    }
+
+    /// Returns the physical address of the Local APIC, honoring a 64-bit override entry if present.
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::LocalApicAddressOverride { address } => Some(address),
+                _ => None,
+            })
+            .unwrap_or_else(|| {
+                Self::read_u32(self.header.bytes(), core::mem::size_of::<SdtHeader>()) as u64
+            })
+    }
+
+    /// Iterates over all entries of the table.
+    pub fn entries(&self) -> impl Iterator<Item = MadtEntry> {
+        let bytes = self.header.bytes();
+        let mut offset = MADT_FIXED_LEN;
+        core::iter::from_fn(move || {
+            if offset + 2 > bytes.len() {
+                return None;
+            }
+            let entry_type = bytes[offset];
+            let length = bytes[offset + 1];
+            if length < 2 || offset + length as usize > bytes.len() {
+                return None;
+            }
+            let e = &bytes[offset..offset + length as usize];
+            offset += length as usize;
+            let entry = match (entry_type, length) {
+                (0, 8..) => MadtEntry::LocalApic {
+                    processor_id: e[2],
+                    apic_id: e[3],
+                    flags: Self::read_u32(e, 4),
+                },
+                (1, 12..) => MadtEntry::IoApic {
+                    id: e[2],
+                    address: Self::read_u32(e, 4),
+                    gsi_base: Self::read_u32(e, 8),
+                },
+                (2, 10..) => MadtEntry::InterruptSourceOverride {
+                    bus: e[2],
+                    source: e[3],
+                    gsi: Self::read_u32(e, 4),
+                    flags: Self::read_u16(e, 8),
+                },
+                (3, 8..) => MadtEntry::NmiSource {
+                    flags: Self::read_u16(e, 2),
+                    gsi: Self::read_u32(e, 4),
+                },
+                (4, 6..) => MadtEntry::LocalApicNmi {
+                    processor_id: e[2],
+                    flags: Self::read_u16(e, 3),
+                    lint: e[5],
+                },
+                (5, 12..) => MadtEntry::LocalApicAddressOverride {
+                    address: Self::read_u64(e, 4),
+                },
+                (9, 16..) => MadtEntry::LocalX2Apic {
+                    x2apic_id: Self::read_u32(e, 4),
+                    flags: Self::read_u32(e, 8),
+                    processor_uid: Self::read_u32(e, 12),
+                },
+                _ => MadtEntry::Unknown { entry_type, length },
+            };
+            Some(entry)
+        })
+    }
+
+    /// Resolves an ISA IRQ (0-15) to its GSI, polarity, and trigger mode.
+    ///
+    /// Without a matching interrupt source override, ISA IRQs are identity mapped, edge-triggered, and active-high.
+    pub fn isa_irq(&self, irq: u8) -> IsaIrq {
+        let default = IsaIrq {
+            gsi: irq as u32,
+            active_low: false,
+            level_triggered: false,
+        };
+        self.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::InterruptSourceOverride {
+                    bus: 0,
+                    source,
+                    gsi,
+                    flags,
+                } if source == irq => Some(IsaIrq {
+                    gsi,
+                    // Polarity: 00 = bus default (high for ISA), 01 = high, 11 = low
+                    active_low: flags & 0b11 == 0b11,
+                    // Trigger mode: 00 = bus default (edge for ISA), 01 = edge, 11 = level
+                    level_triggered: (flags >> 2) & 0b11 == 0b11,
+                }),
+                _ => None,
+            })
+            .unwrap_or(default)
+    }
+}
@@ -18,7 +18,7 @@
 //! - Load the kernel binary from disk (using UEFI file protocols)
 //! - Set up a graphics framebuffer (using UEFI graphics protocols)
 //! - Output text to the screen (using UEFI console protocols)
-//! - Pass information (like framebuffer configuration) to the kernel
+//! - Pass information (like framebuffer configuration, the ACPI RSDP address and the memory map) to the kernel
 //! - Transfer control to the loaded kernel
 //!
 //! If you are new to UEFI, think of it as a set of helper functions provided by your computer's firmware
@@ -29,11 +29,13 @@
 use core::arch::asm;
 
 use log::info;
-use polished_elf_loader::load_kernel;
+use polished_elf_loader::load_kernel_at;
 use polished_graphics::framebuffer::{FramebufferInfo, initialize_framebuffer};
 use uefi::{
-    boot::{get_handle_for_protocol, open_protocol_exclusive},
+    boot::{MemoryType, get_handle_for_protocol, open_protocol_exclusive},
+    mem::memory_map::MemoryMap,
     proto::console::text::Output,
+    table::cfg::{ACPI_GUID, ACPI2_GUID},
 };
 
 /// Boots the system by loading the kernel, initializing the framebuffer, and transferring control to the kernel.
@@ -43,9 +45,9 @@ use uefi::{
 ///   `\\efi\\boot\\kernel` on a FAT-formatted EFI system partition.
 ///
 /// # How it works
-/// 1. Loads the kernel binary from disk using UEFI file services.
+/// 1. Loads the kernel binary from disk using UEFI file services, at a random offset if it is position independent.
 /// 2. Initializes the graphics framebuffer using UEFI graphics protocols, so the kernel can draw to the screen.
-/// 3. Passes the framebuffer configuration to the kernel as an argument.
+/// 3. Passes the framebuffer configuration, the ACPI RSDP address, the UEFI memory map and the KASLR slide to the kernel as arguments.
 /// 4. Uses inline assembly to jump to the kernel's entry point, transferring control to the OS.
 ///
 /// # Safety
@@ -58,7 +60,8 @@ use uefi::{
 /// directly to disk and graphics hardware, which is much more complex and less portable.
 pub fn boot_system(kernel_path: &str) {
     // Load the kernel binary from the specified UEFI path. Returns the entry point address and a callable function pointer to the kernel's entry.
-    let (entry_point, kernel_entry) = load_kernel(kernel_path);
+    // Position-independent kernels are slid to a random base (KASLR); others load at their link address.
+    let (entry_point, kernel_entry, slide) = load_kernel_at(kernel_path, kaslr_slide());
 
     // Log the kernel's entry point address for debugging purposes.
     info!("Kernel entry point: 0x{:x}", kernel_entry as usize);
@@ -71,23 +74,111 @@ pub fn boot_system(kernel_path: &str) {
     // Log the framebuffer information for debugging and diagnostics.
     info!("Framebuffer info: {framebuffer_info:?}");
 
+    // Locate the ACPI tables so the kernel can discover interrupt controllers and timers.
+    let rsdp = find_rsdp();
+    info!("ACPI RSDP at 0x{rsdp:x}");
+
     // Log again before transferring control to the kernel (redundant, but ensures visibility in logs).
     info!("Jumping to kernel entry point at 0x{entry_point:x}");
 
+    // Fetch the memory map last, so that it reflects every allocation made above.
+    let (memory_map, memory_map_size, descriptor_size) = memory_map();
+
     unsafe {
         // Prepare a pointer to the framebuffer info struct to pass to the kernel.
         let fb_ptr = &framebuffer_info as *const FramebufferInfo;
-        // Use inline assembly to set up the first argument (RDI) and call the kernel entry point.
-        // This transfers control to the kernel, passing the framebuffer info pointer as an argument.
+        // Use inline assembly to set up the arguments (RDI, RSI, RDX, RCX, R8, R9) and call the kernel entry point.
+        // This transfers control to the kernel, passing the framebuffer info pointer, the RSDP address, the memory map and the KASLR slide.
         asm!(
-            "mov rdi, {0}",
-            "call {1}",
-            in(reg) fb_ptr,
+            "call {0}",
             in(reg) kernel_entry,
+            in("rdi") fb_ptr,
+            in("rsi") rsdp,
+            in("rdx") memory_map,
+            in("rcx") memory_map_size,
+            in("r8") descriptor_size,
+            in("r9") slide,
         );
     }
 }
 
+/// Finds the ACPI RSDP in the UEFI configuration table.
+///
+/// Prefers the ACPI 2.0+ entry (which also gives access to the XSDT) and falls back to the ACPI 1.0 entry.
+/// Returns 0 if the firmware does not provide ACPI tables.
+///
+/// # UEFI for beginners
+/// The UEFI configuration table is a list of GUID/pointer pairs that firmware uses to hand extra tables (ACPI, SMBIOS, ...) to the OS.
+pub fn find_rsdp() -> u64 {
+    uefi::system::with_config_table(|entries| {
+        entries
+            .iter()
+            .find(|entry| entry.guid == ACPI2_GUID)
+            .or_else(|| entries.iter().find(|entry| entry.guid == ACPI_GUID))
+            .map(|entry| entry.address as u64)
+            .unwrap_or(0)
+    })
+}
+
+/// Alignment of the KASLR slide.
+pub const KASLR_ALIGN: u64 = 2 << 20;
+/// Number of possible KASLR slides; the kernel moves up by at most `KASLR_SLOTS * KASLR_ALIGN` bytes.
+pub const KASLR_SLOTS: u64 = 256;
+
+/// Picks a random KASLR slide: a multiple of [`KASLR_ALIGN`] below `KASLR_SLOTS * KASLR_ALIGN`.
+///
+/// Entropy comes from RDRAND when the CPU has it, mixed with the time stamp counter (which alone still differs between boots).
+pub fn kaslr_slide() -> u64 {
+    use core::arch::x86_64::{__cpuid, _rdtsc};
+
+    const RDRAND: u32 = 1 << 30;
+    let mut entropy = unsafe { _rdtsc() };
+    if __cpuid(1).ecx & RDRAND != 0
+        && let Some(random) = unsafe { rdrand() }
+    {
+        entropy ^= random;
+    }
+    // Fold the high bits in, since the low bits of the TSC alone are predictable at this point of boot.
+    entropy ^= entropy >> 29;
+    entropy = entropy.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    (entropy >> 32) % KASLR_SLOTS * KASLR_ALIGN
+}
+
+/// Reads RDRAND, retrying a few times as recommended by Intel. Returns `None` if the hardware has no entropy ready.
+///
+/// # Safety
+/// The CPU must support RDRAND.
+#[target_feature(enable = "rdrand")]
+unsafe fn rdrand() -> Option<u64> {
+    let mut value = 0;
+    (0..10)
+        .any(|_| core::arch::x86_64::_rdrand64_step(&mut value) == 1)
+        .then_some(value)
+}
+
+/// Returns the UEFI memory map as a pointer to its descriptors, their total size in bytes, and the size of one descriptor.
+///
+/// The buffer is allocated as loader data and deliberately leaked, so it stays valid for the kernel.
+/// Returns a null pointer if the firmware does not provide a memory map.
+///
+/// # UEFI for beginners
+/// The memory map lists every range of physical memory with its type: free, used by the firmware, ACPI tables,
+/// device memory, and so on. The kernel takes the free ranges for its own allocations.
+pub fn memory_map() -> (*const u8, usize, usize) {
+    match uefi::boot::memory_map(MemoryType::LOADER_DATA) {
+        Ok(map) => {
+            let meta = map.meta();
+            let buffer = map.buffer().as_ptr();
+            core::mem::forget(map);
+            (buffer, meta.map_size, meta.desc_size)
+        }
+        Err(e) => {
+            info!("Could not read the memory map: {e:?}");
+            (core::ptr::null(), 0, 0)
+        }
+    }
+}
+
 /// Initializes the UEFI environment and clears the screen.
 ///
 /// This function sets up the UEFI environment and clears the text output screen using the UEFI Output protocol.
@@ -9,6 +9,7 @@
 //! - Parses the ELF file and loads its segments into memory at the addresses specified by the ELF headers
 //! - Allocates memory using UEFI services, respecting the segment permissions
 //! - Copies segment data from the file into memory, zero-filling any uninitialized data (BSS)
+//! - Relocates position-independent kernels loaded above their link address (see [`load_kernel_at`]), which the bootloader uses for KASLR
 //! - Returns the entry point address and a callable function pointer to start the loaded kernel
 //!
 //! # Usage
@@ -34,12 +35,15 @@
 
 #![no_std]
 
+#[cfg(feature = "uefi")]
+use core::ptr::NonNull;
+
 #[cfg(feature = "uefi")]
 use polished_files::uefi::read_file;
 #[cfg(feature = "uefi")]
 use uefi::boot::{self, AllocateType, MemoryType};
 #[cfg(feature = "uefi")]
-use xmas_elf::{ElfFile, program};
+use xmas_elf::{ElfFile, header, program, sections};
 
 #[cfg(feature = "uefi")]
 /// Loads a kernel from the specified ELF file path.
@@ -74,6 +78,29 @@ use xmas_elf::{ElfFile, program};
 /// unsafe { kernel_entry() };
 /// ```
 pub fn load_kernel(file_path: &str) -> (usize, unsafe extern "C" fn() -> !) {
+    let (entry_point, kernel_entry, _) = load_kernel_at(file_path, 0);
+    (entry_point, kernel_entry)
+}
+
+#[cfg(feature = "uefi")]
+/// Maximum number of loadable segments in a kernel.
+pub const MAX_SEGMENTS: usize = 16;
+
+#[cfg(feature = "uefi")]
+/// Loads a kernel like [`load_kernel`], moved up by `slide` bytes if it is position independent.
+///
+/// # Returns
+///
+/// The entry point address, a function pointer to it, and the slide actually applied.
+///
+/// # Relocation
+///
+/// A position-independent kernel (ELF type `ET_DYN`, built with `-C relocation-model=pie` and linked with `-pie`) has every segment loaded `slide` bytes above its link address, and each `R_X86_64_RELATIVE` entry of its `RELA` sections patched to `slide + addend`. This is what makes KASLR possible.
+///
+/// The applied slide is 0 if the kernel is not position independent, or if the memory at the slid addresses is not free, in which case the kernel is loaded at its link address instead.
+///
+/// `slide` must be a multiple of the page size.
+pub fn load_kernel_at(file_path: &str, slide: u64) -> (usize, unsafe extern "C" fn() -> !, u64) {
     // Log the file path being loaded
     log::info!("Loading kernel from ELF file: {file_path}");
     // Read the entire ELF file into memory
@@ -81,11 +108,44 @@ pub fn load_kernel(file_path: &str) -> (usize, unsafe extern "C" fn() -> !) {
     // Parse the ELF file structure
     let elf = ElfFile::new(&bytes).expect("Failed to parse ELF file");
 
+    let relocatable = elf.header.pt2.type_().as_type() == header::Type::SharedObject;
+    let mut slide = if relocatable { slide } else { 0 };
+    if !relocatable {
+        log::info!("Kernel is not position independent, loading it at its link address");
+    }
+    if !load_segments(&elf, &bytes, slide) {
+        log::warn!(
+            "Memory at slide 0x{slide:x} is not free, loading the kernel at its link address"
+        );
+        slide = 0;
+        assert!(load_segments(&elf, &bytes, 0), "Failed to allocate pages");
+    }
+    if slide != 0 {
+        apply_relocations(&elf, slide);
+    }
+
+    // Get the entry point address from the ELF header
+    let entry_point = (elf.header.pt2.entry_point() + slide) as usize;
+    log::info!("Kernel entry point: 0x{entry_point:x} (slide 0x{slide:x})");
+    // Convert the entry point address to a function pointer
+    let kernel_entry: unsafe extern "C" fn() -> ! = unsafe { core::mem::transmute(entry_point) };
+
+    (entry_point, kernel_entry, slide)
+}
+
+#[cfg(feature = "uefi")]
+/// Allocates and fills every loadable segment `slide` bytes above its link address.
+///
+/// Returns `false`, with every page allocated so far freed again, if any segment's memory is not free.
+fn load_segments(elf: &ElfFile, bytes: &[u8], slide: u64) -> bool {
+    let mut allocated = [(NonNull::dangling(), 0); MAX_SEGMENTS];
+    let mut count = 0;
+
     // Iterate over each program header (segment) in the ELF file
     for ph in elf.program_iter() {
         let ph_type = ph.get_type().ok();
         log::info!("Found program header: {ph_type:?}");
-        // Skip dynamic segments (not needed for kernel loading)
+        // Dynamic segments only matter for relocation, which reads the section headers
         if ph_type == Some(program::Type::Dynamic) {
             log::warn!("Skipping dynamic segment");
         }
@@ -98,7 +158,7 @@ pub fn load_kernel(file_path: &str) -> (usize, unsafe extern "C" fn() -> !) {
         let file_offset = ph.offset() as usize;
         let file_size = ph.file_size() as usize;
         let mem_size = ph.mem_size() as usize;
-        let virt_addr = ph.virtual_addr() as usize;
+        let virt_addr = (ph.virtual_addr() + slide) as usize;
 
         log::info!(
             "Loading segment: file_offset=0x{file_offset:x}, file_size=0x{file_size:x}, mem_size=0x{mem_size:x}, virt_addr=0x{virt_addr:x}"
@@ -122,13 +182,26 @@ pub fn load_kernel(file_path: &str) -> (usize, unsafe extern "C" fn() -> !) {
         );
 
         // Allocate memory at the requested virtual address
-        let dest_ptr = boot::allocate_pages(
+        let dest_ptr = match boot::allocate_pages(
             AllocateType::Address(u64::try_from(aligned_virt_addr).unwrap()),
             mem_type,
             num_pages,
-        )
-        .expect("Failed to allocate pages")
-        .as_ptr();
+        ) {
+            Ok(ptr) if count < MAX_SEGMENTS => {
+                allocated[count] = (ptr, num_pages);
+                count += 1;
+                ptr.as_ptr()
+            }
+            result => {
+                if let Ok(ptr) = result {
+                    let _ = unsafe { boot::free_pages(ptr, num_pages) };
+                }
+                for &(ptr, pages) in &allocated[..count] {
+                    let _ = unsafe { boot::free_pages(ptr, pages) };
+                }
+                return false;
+            }
+        };
 
         unsafe {
             // Copy segment data from the ELF file into the allocated memory
@@ -149,12 +222,37 @@ pub fn load_kernel(file_path: &str) -> (usize, unsafe extern "C" fn() -> !) {
         }
         log::info!("Segment loaded at 0x{virt_addr:x}");
     }
+    true
+}
 
-    // Get the entry point address from the ELF header
-    let entry_point = elf.header.pt2.entry_point() as usize;
-    log::info!("Kernel entry point: 0x{entry_point:x}");
-    // Convert the entry point address to a function pointer
-    let kernel_entry: unsafe extern "C" fn() -> ! = unsafe { core::mem::transmute(entry_point) };
+#[cfg(feature = "uefi")]
+/// `R_X86_64_RELATIVE`: the word at the offset becomes the load slide plus the addend.
+const R_X86_64_RELATIVE: u32 = 8;
 
-    (entry_point, kernel_entry)
+#[cfg(feature = "uefi")]
+/// Patches the `R_X86_64_RELATIVE` relocations of every `RELA` section of a kernel loaded `slide` bytes above its link address.
+fn apply_relocations(elf: &ElfFile, slide: u64) {
+    let mut applied = 0usize;
+    for section in elf.section_iter() {
+        if section.get_type() != Ok(sections::ShType::Rela) {
+            continue;
+        }
+        let Ok(sections::SectionData::Rela64(relocations)) = section.get_data(elf) else {
+            continue;
+        };
+        for rela in relocations {
+            if rela.get_type() != R_X86_64_RELATIVE {
+                log::warn!(
+                    "Ignoring unsupported relocation type {} at 0x{:x}",
+                    rela.get_type(),
+                    rela.get_offset()
+                );
+                continue;
+            }
+            let target = (rela.get_offset() + slide) as *mut u64;
+            unsafe { target.write_unaligned(rela.get_addend().wrapping_add(slide)) };
+            applied += 1;
+        }
+    }
+    log::info!("Applied {applied} relocations for slide 0x{slide:x}");
 }
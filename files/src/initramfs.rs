@@ -0,0 +1,141 @@
+//! # Initramfs
+//!
+//! An initramfs is a `cpio` archive in the "newc" format, loaded into memory by the bootloader. [`Initramfs`] reads it in place and implements [`FileSystem`], so it can be mounted in the VFS without copying any file data.
+//!
+//! ## Archive Format
+//!
+//! Each entry is a 110-byte ASCII header (magic `070701` followed by 13 eight-digit hex fields), the NUL-terminated file name, padding to 4 bytes, the file data, and padding to 4 bytes again. The archive ends with an entry named `TRAILER!!!`.
+//! Create one with `find . | cpio -o -H newc > initramfs.cpio`.
+//!
+//! Inode IDs are the byte offsets of the entries' headers within the archive.
+
+use crate::vfs::{FileSystem, InodeId, VfsError};
+
+const MAGIC: &[u8] = b"070701";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &[u8] = b"TRAILER!!!";
+
+/// File type bits of `c_mode`.
+const MODE_TYPE_MASK: u32 = 0o170000;
+/// Regular file type.
+const MODE_REGULAR: u32 = 0o100000;
+
+/// A parsed archive entry.
+#[derive(Debug, Clone, Copy)]
+struct Entry<'a> {
+    mode: u32,
+    name: &'a [u8],
+    data: &'a [u8],
+    /// Offset of the next header.
+    next: usize,
+}
+
+/// A read-only filesystem over an in-memory `cpio` newc archive.
+#[derive(Debug, Clone, Copy)]
+pub struct Initramfs {
+    archive: &'static [u8],
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+/// Parses the 8-digit hex field `index` of the header at the start of `header`.
+fn field(header: &[u8], index: usize) -> Option<u32> {
+    let start = MAGIC.len() + index * 8;
+    let digits = core::str::from_utf8(header.get(start..start + 8)?).ok()?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+impl Initramfs {
+    /// Wraps `archive`, checking that it starts with a newc header.
+    pub fn new(archive: &'static [u8]) -> Result<Self, VfsError> {
+        if !archive.starts_with(MAGIC) {
+            return Err(VfsError::Io);
+        }
+        Ok(Initramfs { archive })
+    }
+
+    /// Parses the entry whose header is at `offset`, or `None` at the trailer.
+    fn entry(&self, offset: usize) -> Result<Option<Entry<'static>>, VfsError> {
+        let header = self
+            .archive
+            .get(offset..offset + HEADER_SIZE)
+            .filter(|header| header.starts_with(MAGIC))
+            .ok_or(VfsError::Io)?;
+        let mode = field(header, 1).ok_or(VfsError::Io)?;
+        let file_size = field(header, 6).ok_or(VfsError::Io)? as usize;
+        let name_size = field(header, 11).ok_or(VfsError::Io)? as usize;
+
+        let name_start = offset + HEADER_SIZE;
+        let name = self
+            .archive
+            .get(name_start..name_start + name_size.saturating_sub(1))
+            .ok_or(VfsError::Io)?;
+        if name == TRAILER {
+            return Ok(None);
+        }
+        let data_start = align4(name_start + name_size);
+        let data = self
+            .archive
+            .get(data_start..data_start + file_size)
+            .ok_or(VfsError::Io)?;
+        Ok(Some(Entry {
+            mode,
+            name,
+            data,
+            next: align4(data_start + file_size),
+        }))
+    }
+
+    /// Calls `f` with the path of every regular file in the archive and its size.
+    pub fn for_each_file(&self, mut f: impl FnMut(&str, u64)) -> Result<(), VfsError> {
+        let mut offset = 0;
+        while let Some(entry) = self.entry(offset)? {
+            if entry.mode & MODE_TYPE_MASK == MODE_REGULAR
+                && let Ok(name) = core::str::from_utf8(entry.name)
+            {
+                f(name.trim_start_matches("./"), entry.data.len() as u64);
+            }
+            offset = entry.next;
+        }
+        Ok(())
+    }
+
+    fn file(&self, inode: InodeId) -> Result<&'static [u8], VfsError> {
+        let entry = self.entry(inode as usize)?.ok_or(VfsError::NotFound)?;
+        if entry.mode & MODE_TYPE_MASK != MODE_REGULAR {
+            return Err(VfsError::IsDirectory);
+        }
+        Ok(entry.data)
+    }
+}
+
+impl FileSystem for Initramfs {
+    fn lookup(&self, path: &str) -> Result<InodeId, VfsError> {
+        let mut offset = 0;
+        while let Some(entry) = self.entry(offset)? {
+            let name = entry.name.strip_prefix(b"./").unwrap_or(entry.name);
+            if name == path.as_bytes() {
+                return match entry.mode & MODE_TYPE_MASK {
+                    MODE_REGULAR => Ok(offset as InodeId),
+                    _ => Err(VfsError::IsDirectory),
+                };
+            }
+            offset = entry.next;
+        }
+        Err(VfsError::NotFound)
+    }
+
+    fn size(&self, inode: InodeId) -> Result<u64, VfsError> {
+        Ok(self.file(inode)?.len() as u64)
+    }
+
+    fn read_at(&self, inode: InodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let data = self.file(inode)?;
+        let start = (offset as usize).min(data.len());
+        let len = buffer.len().min(data.len() - start);
+        buffer[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+}
@@ -1,4 +1,8 @@
 #![no_std]
 
+/// Read-only filesystem over an in-memory `cpio` (newc) initramfs archive.
+pub mod initramfs;
 #[cfg(feature = "uefi")]
 pub mod uefi;
+/// Virtual file system: mount table and file lookup.
+pub mod vfs;
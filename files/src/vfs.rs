@@ -0,0 +1,163 @@
+//! # Virtual File System
+//!
+//! The VFS gives the kernel one way to open and read files, whatever filesystem holds them. Filesystems implement [`FileSystem`] and are [`mount`]ed at a path prefix; [`open`] picks the mount with the longest matching prefix and asks its filesystem to look up the rest of the path.
+//!
+//! The interface is read-only and allocation-free, which is all the boot-time filesystems need (the initramfs in [`crate::initramfs`], and later ext2).
+//!
+//! ## Example
+//! ```ignore
+//! static INITRAMFS: OnceCell<Initramfs> = OnceCell::new();
+//! let fs = INITRAMFS.get_or_init(|| Initramfs::new(archive).unwrap());
+//! vfs::mount("/", fs)?;
+//!
+//! let file = vfs::open("/etc/motd")?;
+//! let mut buffer = [0u8; 128];
+//! let read = file.read_at(0, &mut buffer)?;
+//! ```
+
+use spin::Mutex;
+
+/// Maximum number of mounted filesystems.
+pub const MAX_MOUNTS: usize = 8;
+
+/// Identifies a file within its filesystem.
+pub type InodeId = u64;
+
+/// Errors returned by the VFS and filesystems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VfsError {
+    /// No file exists at the path.
+    NotFound,
+    /// The path is not absolute or contains invalid components.
+    InvalidPath,
+    /// The path names a directory, not a regular file.
+    IsDirectory,
+    /// The filesystem data is corrupt or could not be read.
+    Io,
+    /// [`MAX_MOUNTS`] filesystems are already mounted.
+    TooManyMounts,
+    /// A filesystem is already mounted at the prefix.
+    AlreadyMounted,
+}
+
+/// A read-only filesystem that can be mounted in the VFS.
+pub trait FileSystem: Sync {
+    /// Returns the inode of the regular file at `path`, relative to the mount point and without a leading `/`.
+    fn lookup(&self, path: &str) -> Result<InodeId, VfsError>;
+
+    /// Returns the size of `inode` in bytes.
+    fn size(&self, inode: InodeId) -> Result<u64, VfsError>;
+
+    /// Reads from `inode` at `offset` into `buffer`, returning the number of bytes read (0 at end of file).
+    fn read_at(&self, inode: InodeId, offset: u64, buffer: &mut [u8]) -> Result<usize, VfsError>;
+}
+
+#[derive(Clone, Copy)]
+struct Mount {
+    prefix: &'static str,
+    fs: &'static dyn FileSystem,
+}
+
+static MOUNTS: Mutex<[Option<Mount>; MAX_MOUNTS]> = Mutex::new([None; MAX_MOUNTS]);
+
+/// Mounts `fs` at the absolute path `prefix` (e.g. `"/"` or `"/boot"`).
+pub fn mount(prefix: &'static str, fs: &'static dyn FileSystem) -> Result<(), VfsError> {
+    if !prefix.starts_with('/') {
+        return Err(VfsError::InvalidPath);
+    }
+    let prefix = match prefix.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().flatten().any(|mount| mount.prefix == prefix) {
+        return Err(VfsError::AlreadyMounted);
+    }
+    let slot = mounts
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(VfsError::TooManyMounts)?;
+    *slot = Some(Mount { prefix, fs });
+    Ok(())
+}
+
+/// Unmounts the filesystem at `prefix`. Returns `false` if nothing was mounted there.
+pub fn unmount(prefix: &str) -> bool {
+    let mut mounts = MOUNTS.lock();
+    match mounts
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|mount| mount.prefix == prefix))
+    {
+        Some(slot) => {
+            *slot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the path of `path` relative to `prefix`, if `prefix` is a mount point containing it.
+fn strip_mount_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if prefix == "/" {
+        return Some(path.trim_start_matches('/'));
+    }
+    let rest = path.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('/')).then(|| rest.trim_start_matches('/'))
+}
+
+/// An open regular file.
+#[derive(Clone, Copy)]
+pub struct File {
+    fs: &'static dyn FileSystem,
+    inode: InodeId,
+    size: u64,
+}
+
+impl File {
+    /// Returns the inode of the file within its filesystem.
+    pub fn inode(&self) -> InodeId {
+        self.inode
+    }
+
+    /// Returns the size of the file in bytes, as of when it was opened.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Reads at `offset` into `buffer`, returning the number of bytes read (0 at end of file).
+    pub fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        self.fs.read_at(self.inode, offset, buffer)
+    }
+}
+
+impl core::fmt::Debug for File {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("File")
+            .field("inode", &self.inode)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+/// Opens the regular file at the absolute path `path`.
+pub fn open(path: &str) -> Result<File, VfsError> {
+    if !path.starts_with('/') || path.split('/').any(|component| component == "..") {
+        return Err(VfsError::InvalidPath);
+    }
+    let (fs, relative) = {
+        let mounts = MOUNTS.lock();
+        mounts
+            .iter()
+            .flatten()
+            .filter_map(|mount| {
+                strip_mount_prefix(path, mount.prefix)
+                    .map(|rest| (mount.prefix.len(), mount.fs, rest))
+            })
+            .max_by_key(|&(prefix_len, _, _)| prefix_len)
+            .map(|(_, fs, rest)| (fs, rest))
+            .ok_or(VfsError::NotFound)?
+    };
+    let inode = fs.lookup(relative)?;
+    let size = fs.size(inode)?;
+    Ok(File { fs, inode, size })
+}
@@ -16,126 +16,480 @@
 //!
 //! ## How does this module work?
 //!
-//! - Statically allocates and initializes the GDT and TSS
+//! - Gives every CPU its own GDT and TSS, allocated from the memory subsystem by [`init_gdt_for_cpu`]
 //! - Sets up segment descriptors for kernel and user code/data
-//! - Configures the TSS with dedicated stacks for critical exceptions (double fault, NMI)
-//! - Loads the GDT and updates the segment registers
+//! - Configures the TSS with guarded stacks for critical exceptions (double fault, NMI)
+//! - Loads the GDT and the TSS and updates the segment registers
+//! - Installs guarded stacks from the memory crate as IST or RSP0 stacks, or points IST entries at any stack with [`set_ist_stack`]
+//! - Switches the kernel stack used on entry from user mode per task with [`set_kernel_stack`]
 //!
 //! This is typically called early in kernel initialization, before enabling interrupts.
+//!
+//! ## Per-CPU Tables
+//!
+//! The TSS holds the stacks a CPU switches to, so two CPUs can never share one; and since loading a TSS marks its descriptor busy, they cannot share a GDT either. Each CPU's GDT, TSS and IST stacks live in a page from the frame allocator, found by CPU index. Functions that change the TSS act on the calling CPU, identified through its `polished_memory::percpu` block, with interrupts disabled; the tables are never borrowed mutably as a whole, so nothing here is `static mut`.
+//!
+//! The GDT layout is the same on every CPU, and [`init_gdt_for_cpu`] returns its [`Selectors`].
 
 #![no_std]
 
-use once_cell::unsync::OnceCell;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use polished_memory::frame;
+use polished_memory::hhdm::phys_to_virt;
+use polished_memory::paging::PAGE_SIZE;
+use polished_memory::percpu::{self, MAX_CPUS, PerCpu};
+use polished_memory::stack::{self, StackError, StackHandle};
+use polished_serial_logging::kprint;
+use spin::Once;
 use x86_64::VirtAddr;
-use x86_64::instructions::segmentation::{CS, DS, ES, SS, Segment};
+use x86_64::instructions::segmentation::{CS, DS, ES, FS, GS, SS, Segment};
+use x86_64::instructions::tables::{load_tss, sgdt};
+use x86_64::registers::model_specific::{GsBase, KernelGsBase};
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 
-/// Static OnceCell for the GDT and its segment selectors.
+/// Number of IST slots in the TSS.
+pub const IST_SLOTS: usize = 7;
+
+/// IST entry the double fault handler runs on.
+pub const DOUBLE_FAULT_IST_INDEX: usize = 1;
+
+/// IST entry the NMI handler runs on.
+pub const NMI_IST_INDEX: usize = 2;
+
+/// Size of each IST stack allocated by [`init_gdt_for_cpu`].
+pub const IST_STACK_SIZE: u64 = 4096 * 2;
+
+/// Size of the default kernel stack allocated by [`init_gdt_for_cpu`], used on entry from user mode until a task sets its own.
+pub const DEFAULT_KERNEL_STACK_SIZE: u64 = 4096 * 4;
+
+/// Offset of the kernel stack top in the per-CPU block, for `gs:[offset]` operands after `swapgs`.
+pub const GS_KERNEL_STACK_TOP: usize = percpu::KERNEL_STACK_TOP_OFFSET;
+
+/// Offset of the user stack pointer scratch slot in the per-CPU block, for `gs:[offset]` operands after `swapgs`.
+pub const GS_USER_RSP_SCRATCH: usize = percpu::USER_RSP_SCRATCH_OFFSET;
+
+/// Errors from setting up the tables of a CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdtError {
+    /// The CPU index is not below `MAX_CPUS`.
+    InvalidCpu,
+    /// The CPU already has its tables.
+    AlreadyInitialized,
+    /// No frame was left for the tables.
+    OutOfMemory,
+    /// An IST stack could not be allocated.
+    Stack(StackError),
+    /// The CPU has no `percpu` block to point the GS base at.
+    NoPerCpu,
+}
+
+/// Segment selectors of the GDT built by [`init_gdt_for_cpu`], the same on every CPU.
+///
+/// The order is the one `syscall` and `sysret` derive their selectors from (`IA32_STAR`): kernel data directly after kernel code, and user code directly after user data. Pass the fields straight to `Star::write`.
+///
+/// # Example
+/// ```ignore
+/// let s = gdt::selectors();
+/// Star::write(s.user_code, s.user_data, s.kernel_code, s.kernel_data)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selectors {
+    /// Kernel code segment (GDT index 1).
+    pub kernel_code: SegmentSelector,
+    /// Kernel data segment (GDT index 2).
+    pub kernel_data: SegmentSelector,
+    /// User data segment (GDT index 3), directly before user code as `sysret` requires.
+    pub user_data: SegmentSelector,
+    /// User code segment (GDT index 4).
+    pub user_code: SegmentSelector,
+    /// TSS (GDT indices 5 and 6).
+    pub tss: SegmentSelector,
+}
+
+/// The descriptor tables of one CPU.
 ///
-/// The tuple contains:
-/// - The `GlobalDescriptorTable` instance
-/// - An array of four `SegmentSelector`s for:
-///   0. Kernel code segment
-///   1. Kernel data segment
-///   2. User code segment
-///   3. User data segment
-static mut GDT: OnceCell<(GlobalDescriptorTable, [SegmentSelector; 4])> = OnceCell::new();
+/// Only shared references to the tables exist. The TSS and the stacks it refers to change through [`with_tss`], which only the owning CPU calls, with interrupts disabled.
+struct CpuTables {
+    gdt: GlobalDescriptorTable,
+    tss: UnsafeCell<TaskStateSegment>,
+    /// Stacks installed in the IST, kept alive while the TSS points at them.
+    ist: UnsafeCell<[Option<StackHandle>; IST_SLOTS]>,
+    /// Kernel stack used on entry from user mode when no task has set its own.
+    default_kernel_stack: StackHandle,
+}
 
-/// Number of IST entries (x86_64 supports up to 7)
-const IST_ENTRIES: usize = 3; // 0: unused, 1: double fault, 2: NMI (add more as needed)
+const _: () = assert!(size_of::<CpuTables>() <= PAGE_SIZE as usize);
 
-/// Size of each IST stack (8 KiB is typical)
-const IST_STACK_SIZE: usize = 4096 * 2;
+static CPUS: [AtomicPtr<CpuTables>; MAX_CPUS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CPUS];
 
-/// Statically allocate stacks for IST
+/// Selectors of the first GDT built; every later one must match.
+static SELECTORS: Once<Selectors> = Once::new();
+
+/// Returns the tables of the calling CPU.
 ///
-/// The Interrupt Stack Table (IST) allows the CPU to switch to a dedicated stack when handling certain critical exceptions (like double faults or NMIs).
-#[repr(align(16))]
-struct AlignedStacks([[u8; IST_STACK_SIZE]; IST_ENTRIES]);
+/// # Panics
+/// Panics if [`init_gdt_for_cpu`] has not run on this CPU.
+fn current_tables() -> &'static CpuTables {
+    let cpu_id = percpu::current().cpu_id as usize;
+    let tables = CPUS[cpu_id].load(Ordering::Acquire);
+    assert!(!tables.is_null(), "GDT used before init_gdt_for_cpu");
+    // Safety: the tables are never freed and never borrowed mutably as a whole.
+    unsafe { &*tables }
+}
 
-static mut IST_STACKS: AlignedStacks = AlignedStacks([[0; IST_STACK_SIZE]; IST_ENTRIES]);
+/// Calls `f` with the TSS of the calling CPU and the IST stacks it owns, with interrupts disabled.
+///
+/// The CPU reads the stack pointers from the TSS when it takes an interrupt, so entries may be changed after the TSS is loaded.
+///
+/// # Panics
+/// Panics if [`init_gdt_for_cpu`] has not run on this CPU.
+fn with_tss<R>(
+    f: impl FnOnce(&mut TaskStateSegment, &mut [Option<StackHandle>; IST_SLOTS]) -> R,
+) -> R {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let tables = current_tables();
+        // Safety: only the owning CPU gets here, and with interrupts disabled it cannot re-enter.
+        unsafe { f(&mut *tables.tss.get(), &mut *tables.ist.get()) }
+    })
+}
 
-/// Static OnceCell for the TSS (Task State Segment)
+/// Returns the selectors of the GDT, which is laid out the same on every CPU.
 ///
-/// The TSS is a special structure used by the CPU to store information about a task, including pointers to stacks for handling interrupts.
-static mut TSS: OnceCell<TaskStateSegment> = OnceCell::new();
+/// # Panics
+/// Panics if no GDT has been built yet.
+pub fn selectors() -> Selectors {
+    *SELECTORS.get().expect("GDT selectors used before init_gdt")
+}
 
-/// Returns a reference to the TSS, initializing it if needed.
+/// Returns a copy of the TSS of the calling CPU.
 ///
-/// The TSS is set up with dedicated stacks for double fault and NMI exceptions using the IST.
-pub fn get_tss() -> &'static TaskStateSegment {
-    unsafe {
-        #[allow(static_mut_refs)] // Allowed because OnceCell is used
-        TSS.get_or_init(|| {
-            let mut tss = TaskStateSegment::new();
-            // Set IST1 for double fault (critical error stack)
-            tss.interrupt_stack_table[1] = {
-                let stack_start = &IST_STACKS.0[1] as *const u8 as u64;
-                let stack_end = stack_start + IST_STACK_SIZE as u64;
-                VirtAddr::new(stack_end)
-            };
-            // Set IST2 for NMI (non-maskable interrupt stack)
-            tss.interrupt_stack_table[2] = {
-                let stack_start = &IST_STACKS.0[2] as *const u8 as u64;
-                let stack_end = stack_start + IST_STACK_SIZE as u64;
-                VirtAddr::new(stack_end)
-            };
-            tss
-        })
-    }
+/// # Panics
+/// Panics if [`init_gdt_for_cpu`] has not run on this CPU.
+pub fn get_tss() -> TaskStateSegment {
+    with_tss(|tss, _| *tss)
+}
+
+/// Makes `stack` the stack the CPU switches to for interrupts using IST entry `index` (the index passed to `set_stack_index`), on the calling CPU.
+///
+/// The TSS keeps the stack alive; a stack installed earlier at the same index is freed.
+///
+/// # Panics
+/// Panics if `index` is not below [`IST_SLOTS`].
+///
+/// # Example
+/// ```ignore
+/// let stack = polished_memory::stack::alloc_kernel_stack(16 * 1024)?;
+/// gdt::install_ist_stack(3, stack);
+/// ```
+pub fn install_ist_stack(index: usize, stack: StackHandle) {
+    assert!(index < IST_SLOTS, "IST index {index} out of range");
+    let previous = with_tss(|tss, ist| {
+        tss.interrupt_stack_table[index] = stack.top();
+        ist[index].replace(stack)
+    });
+    drop(previous);
+}
+
+/// Points IST entry `index` (the index passed to `set_stack_index`) of the calling CPU at the stack ending at `top`.
+///
+/// This is the low-level form of [`install_ist_stack`] for stacks the TSS does not own, such as ones set aside by the kernel itself. A stack installed earlier at the same index through [`install_ist_stack`] stays allocated until that index is given another owned stack.
+///
+/// # Safety
+/// `top` must be the 16-byte aligned top of a mapped stack that stays alive, and is used by nothing else, as long as the IST entry points at it.
+///
+/// # Panics
+/// Panics if `index` is not below [`IST_SLOTS`].
+///
+/// # Example
+/// ```ignore
+/// let stack = polished_memory::stack::alloc_kernel_stack(16 * 1024)?;
+/// unsafe { gdt::set_ist_stack(4, stack.top()) };
+/// core::mem::forget(stack);
+/// ```
+pub unsafe fn set_ist_stack(index: usize, top: VirtAddr) {
+    assert!(index < IST_SLOTS, "IST index {index} out of range");
+    with_tss(|tss, _| tss.interrupt_stack_table[index] = top);
+}
+
+/// Makes `stack` the stack the calling CPU switches to on an interrupt, exception or syscall from user mode.
+///
+/// See [`set_kernel_stack`].
+///
+/// # Safety
+/// `stack` must stay alive until another kernel stack is set, and must not be in use by anything else while user code runs on this CPU.
+pub unsafe fn install_rsp0_stack(stack: &StackHandle) {
+    unsafe { set_kernel_stack(stack.top()) };
 }
 
-/// Initializes and loads the Global Descriptor Table (GDT).
+/// Makes the stack ending at `top` the stack the calling CPU switches to on entry from user mode: RSP0 in the TSS for interrupts and exceptions, and the per-CPU kernel stack top for `syscall`.
+///
+/// The scheduler calls this with the kernel stack of the task it is about to run, before the task returns to user mode, so that each task enters the kernel on its own stack.
 ///
 /// # Safety
-/// This function is safe to call only once and before interrupts are enabled, as it modifies segment registers.
+/// `top` must be the 16-byte aligned top of a mapped stack that stays alive until another kernel stack is set, and must not be in use by anything else while user code runs on this CPU.
+///
+/// # Example
+/// ```ignore
+/// unsafe { gdt::set_kernel_stack(next_task.kernel_stack.top()) };
+/// ```
+pub unsafe fn set_kernel_stack(top: VirtAddr) {
+    with_tss(|tss, _| {
+        tss.privilege_stack_table[0] = top;
+        percpu::current()
+            .kernel_stack_top
+            .store(top.as_u64(), Ordering::Release);
+    });
+}
+
+/// Returns the top of the stack the calling CPU switches to on entry from user mode.
+pub fn kernel_stack() -> VirtAddr {
+    with_tss(|tss, _| tss.privilege_stack_table[0])
+}
+
+/// Switches the calling CPU back to the default kernel stack allocated by [`init_gdt_for_cpu`], e.g. when the last user task has exited.
+pub fn reset_kernel_stack() {
+    let top = current_tables().default_kernel_stack.top();
+    // Safety: the default stack lives as long as the CPU's tables and is only used for entry from user mode.
+    unsafe { set_kernel_stack(top) };
+}
+
+/// Initializes and loads the Global Descriptor Table (GDT) of the bootstrap processor, returning its selectors.
+///
+/// See [`init_gdt_for_cpu`].
+///
+/// # Panics
+/// Panics if the tables cannot be allocated.
+///
+/// # Example
+/// ```ignore
+/// let selectors = gdt::init_gdt();
+/// ```
+pub fn init_gdt() -> Selectors {
+    init_gdt_for_cpu(0).unwrap_or_else(|e| panic!("Could not set up the GDT: {e:?}"))
+}
+
+/// Builds the GDT and TSS of CPU `cpu_id`, loads them and updates the segment registers.
+///
+/// Must run on the CPU it sets up, once, with interrupts disabled, after the memory subsystem and the CPU's `percpu` block are initialized.
 ///
-/// The GDT is initialized with four segments:
+/// The GDT is initialized with four segments and the TSS:
 /// - Kernel code
 /// - Kernel data
-/// - User code
 /// - User data
+/// - User code
 ///
-/// After loading the GDT, the segment registers (CS, SS, DS, ES) are set to the appropriate selectors.
+/// The user data segment must directly precede the user code segment: `sysret` loads SS and CS from
+/// consecutive GDT entries (see the `polished_syscalls` crate).
 ///
 /// # How it works
-/// 1. Initializes the GDT and appends descriptors for kernel/user code and data segments.
-/// 2. Appends a TSS descriptor, which is required for stack switching on interrupts.
+/// 1. Allocates a page for the CPU's tables, guarded stacks for the double fault and NMI IST entries, and a default kernel stack for entry from user mode (RSP0 and the per-CPU syscall stack).
+/// 2. Appends descriptors for kernel/user code and data segments, and a TSS descriptor.
 /// 3. Loads the GDT into the CPU using the `lgdt` instruction.
-/// 4. Updates the segment registers to use the new selectors.
+/// 4. Updates the segment registers to use the new selectors and loads the TSS with `ltr`.
+/// 5. Points `GS_BASE` at the CPU's `percpu` block and clears `KERNEL_GS_BASE`.
 ///
-/// # Example
-/// ```ignore
-/// gdt::init_gdt();
-/// ```
-pub fn init_gdt() {
-    // Safety: single-threaded, called only once before interrupts enabled.
-    let (gdt, selectors) = unsafe {
-        #[allow(static_mut_refs)]
-        GDT.get_or_init(|| {
-            let mut gdt = GlobalDescriptorTable::new();
-            // Append kernel code segment (index 1, selector 0x08)
-            let code_sel = gdt.append(Descriptor::kernel_code_segment());
-            // Append kernel data segment (index 2, selector 0x10)
-            let data_sel = gdt.append(Descriptor::kernel_data_segment());
-            // Append user code segment (index 3, selector 0x18)
-            let user_code_sel = gdt.append(Descriptor::user_code_segment());
-            // Append user data segment (index 4, selector 0x20)
-            let user_data_sel = gdt.append(Descriptor::user_data_segment());
-            // Append TSS descriptor (index 5, selector 0x28)
-            let tss = get_tss();
-            gdt.append(Descriptor::tss_segment(tss));
-            (gdt, [code_sel, data_sel, user_code_sel, user_data_sel])
-        })
+/// # Errors
+/// Fails if `cpu_id` is out of range, already set up or without a `percpu` block, or if memory runs out.
+pub fn init_gdt_for_cpu(cpu_id: usize) -> Result<Selectors, GdtError> {
+    let slot = CPUS.get(cpu_id).ok_or(GdtError::InvalidCpu)?;
+    if !slot.load(Ordering::Acquire).is_null() {
+        return Err(GdtError::AlreadyInitialized);
+    }
+    let block = percpu::cpu(cpu_id).ok_or(GdtError::NoPerCpu)?;
+    let double_fault_stack = stack::alloc_kernel_stack(IST_STACK_SIZE).map_err(GdtError::Stack)?;
+    let nmi_stack = stack::alloc_kernel_stack(IST_STACK_SIZE).map_err(GdtError::Stack)?;
+    let default_kernel_stack =
+        stack::alloc_kernel_stack(DEFAULT_KERNEL_STACK_SIZE).map_err(GdtError::Stack)?;
+    let frame = frame::allocate_frame().ok_or(GdtError::OutOfMemory)?;
+    let tables = phys_to_virt(frame.start_address().as_u64()) as *mut CpuTables;
+
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] = double_fault_stack.top();
+    tss.interrupt_stack_table[NMI_IST_INDEX] = nmi_stack.top();
+    tss.privilege_stack_table[0] = default_kernel_stack.top();
+    block
+        .kernel_stack_top
+        .store(default_kernel_stack.top().as_u64(), Ordering::Release);
+    let mut ist = [const { None }; IST_SLOTS];
+    ist[DOUBLE_FAULT_IST_INDEX] = Some(double_fault_stack);
+    ist[NMI_IST_INDEX] = Some(nmi_stack);
+    unsafe {
+        tables.write(CpuTables {
+            gdt: GlobalDescriptorTable::new(),
+            tss: UnsafeCell::new(tss),
+            ist: UnsafeCell::new(ist),
+            default_kernel_stack,
+        });
+    }
+    // Safety: the page belongs to this CPU for good, and nothing else can see it before it is stored below.
+    let gdt = unsafe { &mut (*tables).gdt };
+    let tss = unsafe { (*tables).tss.get() };
+    let selectors = Selectors {
+        // Kernel code segment (index 1, selector 0x08)
+        kernel_code: gdt.append(Descriptor::kernel_code_segment()),
+        // Kernel data segment (index 2, selector 0x10)
+        kernel_data: gdt.append(Descriptor::kernel_data_segment()),
+        // User data segment (index 3, selector 0x1b), before user code as sysret requires
+        user_data: gdt.append(Descriptor::user_data_segment()),
+        // User code segment (index 4, selector 0x23)
+        user_code: gdt.append(Descriptor::user_code_segment()),
+        // TSS descriptor (index 5, selector 0x28); the TSS lives in the same page as the GDT
+        tss: gdt.append(unsafe { Descriptor::tss_segment_unchecked(tss) }),
     };
-    gdt.load();
+    assert_eq!(
+        *SELECTORS.call_once(|| selectors),
+        selectors,
+        "GDT layout differs between CPUs"
+    );
+    slot.store(tables, Ordering::Release);
 
+    gdt.load();
     unsafe {
         // Set all segment registers that might be used during interrupts
-        CS::set_reg(selectors[0]); // kernel code segment
-        SS::set_reg(selectors[1]); // kernel data segment
-        DS::set_reg(selectors[1]);
-        ES::set_reg(selectors[1]);
+        CS::set_reg(selectors.kernel_code);
+        SS::set_reg(selectors.kernel_data);
+        DS::set_reg(selectors.kernel_data);
+        ES::set_reg(selectors.kernel_data);
+        // Loading FS and GS clears their bases, so the GS base is written afterwards
+        FS::set_reg(SegmentSelector::NULL);
+        GS::set_reg(SegmentSelector::NULL);
+        load_tss(selectors.tss);
+    }
+    GsBase::write(VirtAddr::from_ptr(block));
+    KernelGsBase::write(VirtAddr::zero());
+    Ok(selectors)
+}
+
+/// Returns the `PerCpu` block of the calling CPU, through the GS base.
+///
+/// Only valid in kernel mode after the entry path's `swapgs` (see the module docs).
+///
+/// # Panics
+/// Panics if the calling CPU has no `percpu` block.
+pub fn percpu_block() -> &'static PerCpu {
+    percpu::current()
+}
+
+/// Returns the GS base user code runs with, parked in `KERNEL_GS_BASE` while in kernel mode.
+pub fn user_gs_base() -> VirtAddr {
+    KernelGsBase::read()
+}
+
+/// Sets the GS base user code runs with from its next return to user mode, e.g. when switching tasks.
+///
+/// # Safety
+/// Must be called in kernel mode after the entry path's `swapgs`, or the per-CPU block pointer is overwritten.
+pub unsafe fn set_user_gs_base(base: VirtAddr) {
+    KernelGsBase::write(base);
+}
+
+/// Exchanges `GS_BASE` and `KERNEL_GS_BASE`.
+///
+/// # Safety
+/// Must only be used on a transition between user and kernel mode, as described in the module docs; an unpaired swap leaves the kernel without its per-CPU data.
+pub unsafe fn swapgs() {
+    unsafe { core::arch::asm!("swapgs", options(nostack, preserves_flags)) };
+}
+
+/// Describes the descriptor type from the access byte.
+fn descriptor_kind(access: u64, long_mode: bool) -> &'static str {
+    let system = access & (1 << 4) == 0;
+    match (system, access & 0xf) {
+        (false, t) if t & 0x8 != 0 && long_mode => "code (64-bit)",
+        (false, t) if t & 0x8 != 0 => "code",
+        (false, _) => "data",
+        (true, 0x2) => "LDT",
+        (true, 0x9) => "TSS (available)",
+        (true, 0xb) => "TSS (busy)",
+        (true, _) => "system",
+    }
+}
+
+/// Prints every descriptor of the loaded GDT (base, limit, type, DPL, present) and the stack pointers of the calling CPU's TSS over serial.
+///
+/// The GDT is read back with `sgdt`, so the dump shows what the CPU actually uses; 16-byte system descriptors (the TSS) are decoded with their upper half. Meant for segment-related faults, which otherwise need the raw descriptors decoded by hand.
+///
+/// # Example
+/// ```ignore
+/// gdt::dump();
+/// ```
+pub fn dump() {
+    let pointer = sgdt();
+    let count = (usize::from(pointer.limit) + 1) / 8;
+    let entries = pointer.base.as_ptr::<u64>();
+    let tr: u16;
+    unsafe { core::arch::asm!("str {0:x}", out(reg) tr, options(nomem, nostack, preserves_flags)) };
+    kprint!(
+        "[INFO] GDT at {:#018x}, {} entries, TR = {:#06x}:\r\n",
+        pointer.base.as_u64(),
+        count,
+        tr
+    );
+    let mut index = 1;
+    while index < count {
+        // Safety: `sgdt` reports the table the CPU uses, `count` entries long.
+        let raw = unsafe { entries.add(index).read() };
+        if raw == 0 {
+            kprint!("  [{}] {:#06x} null\r\n", index, index * 8);
+            index += 1;
+            continue;
+        }
+        let access = (raw >> 40) & 0xff;
+        let mut base = ((raw >> 16) & 0xff_ffff) | (((raw >> 56) & 0xff) << 24);
+        let mut limit = (raw & 0xffff) | (((raw >> 48) & 0xf) << 16);
+        if raw & (1 << 55) != 0 {
+            limit = (limit << 12) | 0xfff;
+        }
+        let system = access & (1 << 4) == 0;
+        let width = if system && index + 1 < count {
+            // Safety: as above; system descriptors take two entries in long mode.
+            base |= unsafe { entries.add(index + 1).read() } << 32;
+            2
+        } else {
+            1
+        };
+        kprint!(
+            "  [{}] {:#06x} raw={:#018x} base={:#018x} limit={:#07x} {} DPL={} {}\r\n",
+            index,
+            index * 8,
+            raw,
+            base,
+            limit,
+            descriptor_kind(access, raw & (1 << 53) != 0),
+            (access >> 5) & 3,
+            if access & (1 << 7) != 0 {
+                "present"
+            } else {
+                "not present"
+            }
+        );
+        index += width;
+    }
+
+    let Some(tables) = (!GsBase::read().is_null())
+        .then(|| CPUS[percpu::current().cpu_id as usize].load(Ordering::Acquire))
+        .filter(|tables| !tables.is_null())
+    else {
+        kprint!("[INFO] No TSS set up on this CPU\r\n");
+        return;
+    };
+    // Safety: the tables are never freed, and only this CPU changes its TSS.
+    let tss = unsafe { (*tables).tss.get() };
+    kprint!("[INFO] TSS at {:#018x}:\r\n", tss as u64);
+    let tss = x86_64::instructions::interrupts::without_interrupts(|| unsafe { *tss });
+    for (level, rsp) in { tss.privilege_stack_table }.iter().enumerate() {
+        kprint!("  RSP{} = {:#018x}\r\n", level, rsp.as_u64());
+    }
+    for (index, ist) in { tss.interrupt_stack_table }.iter().enumerate() {
+        kprint!(
+            "  IST[{}] = {:#018x}{}\r\n",
+            index,
+            ist.as_u64(),
+            if ist.is_null() { " (unused)" } else { "" }
+        );
     }
 }
@@ -26,8 +26,10 @@ use uefi::{
 #[repr(C)]
 #[derive(Debug)]
 pub struct FramebufferInfo {
-    /// Physical address of the framebuffer in memory.
+    /// Address at which the framebuffer is mapped for drawing. The bootloader sets it to the physical address (UEFI identity maps memory); the kernel changes it when it remaps the framebuffer write-combining.
     pub address: u64,
+    /// Physical address of the framebuffer in memory.
+    pub physical_address: u64,
     /// Total size of the framebuffer in bytes.
     pub size: usize,
     /// Width of the framebuffer in pixels.
@@ -79,6 +81,7 @@ pub fn initialize_framebuffer() -> FramebufferInfo {
 
     FramebufferInfo {
         address: gop_buffer.as_mut_ptr() as u64,
+        physical_address: gop_buffer.as_mut_ptr() as u64,
         size: gop_buffer.size(),
         width: resolution.0,
         height: resolution.1,
@@ -0,0 +1,153 @@
+//! # Input Events
+//!
+//! This crate collects input from all devices in one place. Interrupt handlers push an [`InputEvent`] (a decoded key from the keyboard driver, a movement, wheel or button change from the mouse driver) with [`push`]; the kernel main loop, a shell or a GUI pops them with [`pop`], or waits for the next one with [`wait`].
+//!
+//! Events go through a lock-free [`EventQueue`] of [`QUEUE_SIZE`] events: pushing never blocks, so it is safe from any interrupt handler on any CPU. When the queue is full, new events are dropped and counted (see [`dropped`]) rather than overwriting unread ones.
+//!
+//! Any number of consumers may pop at the same time (the kernel main loop, and user programs reading standard input); each event goes to exactly one of them, taking it away from everyone else.
+//!
+//! ## Example
+//! ```ignore
+//! loop {
+//!     match polished_input::wait() {
+//!         InputEvent::Key(key) => {
+//!             if let Some(c) = key.character {
+//!                 shell.input(c);
+//!             }
+//!         }
+//!         InputEvent::Mouse(mouse) => cursor.move_by(mouse.dx, mouse.dy),
+//!     }
+//! }
+//! ```
+
+#![no_std]
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use polished_scancodes::keymap::{self, Keymap};
+use polished_scancodes::{KeyCode, KeyEvent, KeyState, Modifiers};
+use x86_64::instructions::interrupts;
+
+pub use polished_ps2::mouse::{MouseButtons, MouseEvent};
+
+/// Lock-free multi-producer, multi-consumer queue.
+pub mod queue;
+
+/// Debug key combos (Ctrl+Alt+Del, Alt+SysRq+key).
+pub mod sysrq;
+
+pub use queue::EventQueue;
+
+/// Capacity of the input event queue.
+pub const QUEUE_SIZE: usize = 256;
+
+/// A key press or release decoded by the keyboard driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyboardEvent {
+    /// Set 1 make code of the key; Set 2 codes are translated.
+    pub scancode: u8,
+    /// The key, whether it was pressed or released, and the modifier state after this event.
+    pub key: KeyEvent,
+    /// Character typed by the key with the active keymap, for presses of printable keys.
+    pub character: Option<char>,
+    /// [`character`](Self::character) if it is ASCII.
+    pub ascii: Option<u8>,
+}
+
+impl KeyboardEvent {
+    /// Decodes a single-byte Set 1 scancode, without modifiers, on the US layout. Returns `None` for codes that name no key.
+    pub fn from_scancode(scancode: u8) -> Option<Self> {
+        let code = scancode & 0x7F;
+        let state = if scancode & 0x80 == 0 {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        };
+        let key = KeyEvent {
+            code: KeyCode::from_set1(code, false)?,
+            state,
+            modifiers: Modifiers::default(),
+        };
+        Some(Self::from_key(code, key, &keymap::US))
+    }
+
+    /// Builds the event for the key with Set 1 make code `scancode`, typing characters from `keymap`.
+    pub fn from_key(scancode: u8, key: KeyEvent, keymap: &dyn Keymap) -> Self {
+        let character = key.to_char(keymap);
+        KeyboardEvent {
+            scancode,
+            key,
+            character,
+            ascii: character.filter(char::is_ascii).map(|c| c as u8),
+        }
+    }
+    /// Encodes [`character`](Self::character) as UTF-8 into `buffer`, returning the 1 to 4 bytes written, or `None` if the event types nothing.
+    ///
+    /// Consumers of byte streams (standard input, terminals) use this instead of [`ascii`](Self::ascii) so layouts typing `é` or `ß` are not dropped.
+    pub fn encode_utf8<'a>(&self, buffer: &'a mut [u8; 4]) -> Option<&'a [u8]> {
+        let c = self.character?;
+        Some(c.encode_utf8(buffer).as_bytes())
+    }
+
+    /// Returns the event typing `character` instead, e.g. a letter combined with a dead key, or nothing.
+    pub fn with_character(self, character: Option<char>) -> Self {
+        KeyboardEvent {
+            character,
+            ascii: character.filter(char::is_ascii).map(|c| c as u8),
+            ..self
+        }
+    }
+}
+
+/// An event from an input device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// A key was pressed or released.
+    Key(KeyboardEvent),
+    /// The mouse moved, its wheel turned or a button changed.
+    Mouse(MouseEvent),
+}
+
+static EVENTS: EventQueue<InputEvent, QUEUE_SIZE> = EventQueue::new();
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Queues `event`. Returns `false`, counting the event as dropped, if the queue is full.
+///
+/// Safe to call from interrupt handlers.
+pub fn push(event: InputEvent) -> bool {
+    let queued = EVENTS.push(event);
+    if !queued {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+    queued
+}
+
+/// Pops the oldest pending event without waiting.
+pub fn pop() -> Option<InputEvent> {
+    EVENTS.pop()
+}
+
+/// Waits for the next event, halting the CPU until an interrupt arrives while the queue is empty.
+///
+/// Interrupts are enabled on return. Must not be called from an interrupt handler.
+pub fn wait() -> InputEvent {
+    loop {
+        // Checking with interrupts disabled and then enabling them together with `hlt` means an event pushed in between still wakes the CPU.
+        interrupts::disable();
+        if let Some(event) = EVENTS.pop() {
+            interrupts::enable();
+            return event;
+        }
+        interrupts::enable_and_hlt();
+    }
+}
+
+/// Returns whether no events are pending.
+pub fn is_empty() -> bool {
+    EVENTS.is_empty()
+}
+
+/// Returns the number of events dropped because the queue was full.
+pub fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
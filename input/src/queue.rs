@@ -0,0 +1,194 @@
+//! # Lock-Free Event Queue
+//!
+//! [`EventQueue`] is a bounded multi-producer, multi-consumer ring buffer. Producers are interrupt handlers, possibly running at the same time on different CPUs, so pushing must neither block nor take a lock an interrupted thread could hold. Several consumers (the kernel main loop, user programs reading standard input) may pop at the same time, each value going to exactly one of them.
+//!
+//! Every slot carries a sequence number that says whose turn it is:
+//! - `seq == position`: the slot is free for the producer that claims `position`.
+//! - `seq == position + 1`: the slot holds the value pushed at `position`, ready for the consumer.
+//!
+//! A producer claims a position by advancing `head` with a compare-and-swap, writes the value and publishes it by storing `position + 1`. A consumer claims the published value at `tail` the same way, by advancing `tail` with a compare-and-swap, reads it, then frees the slot for the next lap by storing `position + N`.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Bounded lock-free queue of `N` values with any number of producers and consumers.
+pub struct EventQueue<T, const N: usize> {
+    slots: [Slot<T>; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// Safety: values only move between threads through slots handed over by the sequence protocol.
+unsafe impl<T: Send, const N: usize> Sync for EventQueue<T, N> {}
+
+impl<T: Copy, const N: usize> EventQueue<T, N> {
+    /// Creates an empty queue.
+    pub const fn new() -> Self {
+        let mut slots = [const {
+            Slot {
+                sequence: AtomicUsize::new(0),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }; N];
+        let mut i = 0;
+        while i < N {
+            slots[i].sequence = AtomicUsize::new(i);
+            i += 1;
+        }
+        EventQueue {
+            slots,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends `value`. Returns `false` if the queue is full.
+    pub fn push(&self, value: T) -> bool {
+        let mut position = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % N];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(position) as isize {
+                0 => match self.head.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Safety: the successful CAS gave this producer exclusive use of the slot.
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence
+                            .store(position.wrapping_add(1), Ordering::Release);
+                        return true;
+                    }
+                    Err(current) => position = current,
+                },
+                // The slot still holds a value from the previous lap.
+                diff if diff < 0 => return false,
+                _ => position = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Removes the oldest value. Returns `None` if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        let mut position = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % N];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(position.wrapping_add(1)) as isize {
+                0 => match self.tail.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Safety: the value at `position` is published, and the successful CAS gave this consumer exclusive use of the slot.
+                        let value = unsafe { (*slot.value.get()).assume_init() };
+                        slot.sequence
+                            .store(position.wrapping_add(N), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => position = current,
+                },
+                // Nothing has been published at `position` yet.
+                diff if diff < 0 => return None,
+                _ => position = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Returns whether no published value is waiting.
+    pub fn is_empty(&self) -> bool {
+        let position = self.tail.load(Ordering::Relaxed);
+        self.slots[position % N].sequence.load(Ordering::Acquire) != position.wrapping_add(1)
+    }
+}
+
+impl<T: Copy, const N: usize> Default for EventQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use std::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn pops_in_order_and_rejects_when_full() {
+        let queue = EventQueue::<u32, 4>::new();
+        assert!(queue.is_empty());
+        for i in 0..4 {
+            assert!(queue.push(i));
+        }
+        assert!(!queue.push(4));
+        assert_eq!(queue.pop(), Some(0));
+        assert!(queue.push(4));
+        assert_eq!(
+            core::iter::from_fn(|| queue.pop()).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn concurrent_consumers_get_each_value_once() {
+        const PRODUCERS: u32 = 4;
+        const PER_PRODUCER: u32 = 10_000;
+        let queue = EventQueue::<u32, 64>::new();
+        let done = AtomicBool::new(false);
+        let mut popped: Vec<u32> = thread::scope(|scope| {
+            let consumers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut values = Vec::new();
+                        loop {
+                            match queue.pop() {
+                                Some(value) => values.push(value),
+                                None if done.load(Ordering::Acquire) && queue.is_empty() => {
+                                    break values;
+                                }
+                                None => thread::yield_now(),
+                            }
+                        }
+                    })
+                })
+                .collect();
+            let producers: Vec<_> = (0..PRODUCERS)
+                .map(|p| {
+                    let queue = &queue;
+                    scope.spawn(move || {
+                        for i in 0..PER_PRODUCER {
+                            while !queue.push(p * PER_PRODUCER + i) {
+                                thread::yield_now();
+                            }
+                        }
+                    })
+                })
+                .collect();
+            producers.into_iter().for_each(|p| p.join().unwrap());
+            done.store(true, Ordering::Release);
+            consumers
+                .into_iter()
+                .flat_map(|c| c.join().unwrap())
+                .collect()
+        });
+        popped.sort_unstable();
+        assert_eq!(popped, (0..PRODUCERS * PER_PRODUCER).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,169 @@
+//! # Debug Key Combos
+//!
+//! Special chords that run registered debug actions straight from the keyboard interrupt, in the spirit of the Magic SysRq key of other kernels. They still work when the main loop is stuck, as long as interrupts are enabled.
+//!
+//! Two kinds of [`Chord`] are recognized:
+//!
+//! - **Ctrl+Alt+Del**, conventionally registered to reboot.
+//! - **Alt+SysRq+key**: hold Alt, hold SysRq (Print Screen) and press a key, e.g. `T` to dump the task list or `M` for memory statistics. Alt+SysRq+H lists the registered actions unless `H` is registered itself.
+//!
+//! The keyboard driver calls [`dispatch`] for every key event with the keys held; a key completing a chord is consumed and not queued as input.
+//!
+//! Actions run in the keyboard IRQ handler with interrupts disabled, so they must be short and should not take locks that the interrupted code may hold.
+//!
+//! ## Example
+//! ```ignore
+//! fn dump_memory() {
+//!     polished_memory::stats::dump();
+//! }
+//!
+//! sysrq::register(Chord::SysRq(KeyCode::M), "dump memory stats", dump_memory)?;
+//! sysrq::register(Chord::CtrlAltDel, "reboot", || polished_ps2::reboot())?;
+//! ```
+
+use polished_scancodes::{KeyBitmap, KeyCode, KeyEvent};
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// A debug action, run from the keyboard IRQ handler.
+pub type DebugAction = fn();
+
+/// Maximum number of registered actions.
+pub const MAX_ACTIONS: usize = 16;
+
+/// A key combination that triggers a debug action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chord {
+    /// Ctrl+Alt+Del (or the keypad Delete).
+    CtrlAltDel,
+    /// Alt+SysRq with this key.
+    SysRq(KeyCode),
+}
+
+impl Chord {
+    /// Returns the chord `key` completes while the keys of `held` are down, for presses only.
+    pub fn detect(key: &KeyEvent, held: KeyBitmap) -> Option<Self> {
+        if !key.is_pressed() {
+            return None;
+        }
+        let modifiers = key.modifiers;
+        if modifiers.ctrl()
+            && modifiers.alt()
+            && matches!(key.code, KeyCode::Delete | KeyCode::KeypadPeriod)
+        {
+            return Some(Chord::CtrlAltDel);
+        }
+        let sysrq = held.is_pressed(KeyCode::SysRq) || held.is_pressed(KeyCode::PrintScreen);
+        let chord_key = !matches!(
+            key.code,
+            KeyCode::SysRq
+                | KeyCode::PrintScreen
+                | KeyCode::LeftAlt
+                | KeyCode::RightAlt
+                | KeyCode::LeftShift
+                | KeyCode::RightShift
+                | KeyCode::LeftCtrl
+                | KeyCode::RightCtrl
+        );
+        (modifiers.alt() && sysrq && chord_key).then_some(Chord::SysRq(key.code))
+    }
+}
+
+/// Errors from [`register`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysRqError {
+    /// [`MAX_ACTIONS`] actions are already registered.
+    TableFull,
+    /// Another action is registered for this chord.
+    AlreadyRegistered,
+}
+
+#[derive(Clone, Copy)]
+struct Registration {
+    chord: Chord,
+    name: &'static str,
+    action: DebugAction,
+}
+
+/// Registered actions; only locked with interrupts disabled.
+static ACTIONS: Mutex<[Option<Registration>; MAX_ACTIONS]> = Mutex::new([None; MAX_ACTIONS]);
+
+/// Registers `action`, described by `name` in the help listing, to run when `chord` is pressed.
+///
+/// # Errors
+/// Fails if the chord already has an action or the table is full.
+pub fn register(chord: Chord, name: &'static str, action: DebugAction) -> Result<(), SysRqError> {
+    interrupts::without_interrupts(|| {
+        let mut actions = ACTIONS.lock();
+        if actions.iter().flatten().any(|r| r.chord == chord) {
+            return Err(SysRqError::AlreadyRegistered);
+        }
+        let slot = actions
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(SysRqError::TableFull)?;
+        *slot = Some(Registration {
+            chord,
+            name,
+            action,
+        });
+        Ok(())
+    })
+}
+
+/// Removes the action registered for `chord`, if any.
+pub fn unregister(chord: Chord) {
+    interrupts::without_interrupts(|| {
+        for slot in ACTIONS.lock().iter_mut() {
+            if slot.is_some_and(|r| r.chord == chord) {
+                *slot = None;
+            }
+        }
+    });
+}
+
+/// Runs the action for the chord `key` completes while the keys of `held` are down. Returns whether `key` was part of a chord and should not be queued as input.
+///
+/// Called by the keyboard driver from its IRQ handler. An Alt+SysRq chord without an action prints the help listing.
+pub fn dispatch(key: &KeyEvent, held: KeyBitmap) -> bool {
+    let Some(chord) = Chord::detect(key, held) else {
+        return false;
+    };
+    // The lock is released before running the action, so an action may register others.
+    let registration = interrupts::without_interrupts(|| {
+        ACTIONS
+            .lock()
+            .iter()
+            .flatten()
+            .find(|r| r.chord == chord)
+            .copied()
+    });
+    match (registration, chord) {
+        (Some(registration), _) => {
+            kprint!("[INFO] SysRq: {}\r\n", registration.name);
+            (registration.action)();
+            true
+        }
+        (None, Chord::SysRq(_)) => {
+            print_help();
+            true
+        }
+        // Without an action, Ctrl+Alt+Del is an ordinary key press.
+        (None, Chord::CtrlAltDel) => false,
+    }
+}
+
+/// Prints the registered actions over serial.
+pub fn print_help() {
+    let actions = interrupts::without_interrupts(|| *ACTIONS.lock());
+    kprint!("[INFO] SysRq: hold Alt+SysRq and press\r\n");
+    for registration in actions.iter().flatten() {
+        match registration.chord {
+            Chord::SysRq(code) => kprint!("[INFO]   {:?}: {}\r\n", code, registration.name),
+            Chord::CtrlAltDel => {
+                kprint!("[INFO]   (Ctrl+Alt+Del): {}\r\n", registration.name)
+            }
+        }
+    }
+}
@@ -0,0 +1,428 @@
+//! # Local APIC Driver
+//!
+//! This module detects, enables, and programs the processor's Local Advanced Programmable Interrupt Controller (LAPIC), in x2APIC mode when the CPU supports it and in xAPIC (memory-mapped) mode otherwise.
+//!
+//! ## What is the Local APIC?
+//!
+//! Every x86_64 CPU core has its own Local APIC. It receives interrupts from the I/O APIC, from other cores (IPIs), and from local sources such as its built-in timer, and delivers them to the core. It replaces the legacy 8259 PIC, which can only serve a single CPU and has a fixed, small number of IRQ lines.
+//!
+//! ## How this module works
+//!
+//! - **Detection:** CPUID leaf 1 (EDX bit 9) reports whether a LAPIC is present.
+//! - **Enabling:** The `IA32_APIC_BASE` MSR holds the physical address of the register block (normally 0xFEE0_0000) and a global enable bit. Software enabling is done through the Spurious Interrupt Vector Register (SVR).
+//! - **Register access (xAPIC):** All registers are 32 bits wide, 16-byte aligned, and accessed with volatile loads and stores. [`init`] maps the register block at the physical base address through [`crate::mmio::map`] (uncached, through the mapper the memory subsystem installs) and accesses it at the returned virtual address.
+//! - **Register access (x2APIC):** CPUID leaf 1 (ECX bit 21) reports x2APIC support. Setting the `EXTD` bit in `IA32_APIC_BASE` switches the LAPIC to MSR access: register offset `reg` becomes MSR `0x800 + reg / 16`. The MMIO window is then disabled. x2APIC is required by some hypervisors and by systems with more than 255 CPUs (the APIC ID widens to 32 bits).
+//! - **Unified API:** [`read`], [`write`], [`id`] and [`eoi`] pick the access method chosen by [`init`], so the rest of the kernel uses the same register offsets in both modes. [`mode`] reports which one is active.
+//! - **End of interrupt:** Writing 0 to the EOI register acknowledges the interrupt being serviced. Handlers for LAPIC-delivered interrupts must call [`eoi`] (spurious interrupts must **not**).
+//! - **Timer:** The LAPIC timer counts down from an initial value at the bus/core crystal frequency divided by a configurable divider, and raises a vector in one-shot or periodic mode.
+//! - **Calibration:** The timer's input frequency is not reported by the hardware, so [`calibrate_timer`] measures it against the HPET (or PIT channel 2 when no HPET is available). [`set_periodic`] and [`set_oneshot`] then take real units instead of raw counts.
+//!
+//! Once [`init`] has run, `hardware_interrupts` acknowledges interrupts at the LAPIC instead of the legacy PIC.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{hpet, pit};
+
+use polished_x86_commands::msr::{self, rdmsr, wrmsr};
+
+/// `IA32_APIC_BASE` model-specific register.
+pub const IA32_APIC_BASE_MSR: u32 = msr::IA32_APIC_BASE;
+/// Global enable bit in `IA32_APIC_BASE`.
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+/// x2APIC enable (`EXTD`) bit in `IA32_APIC_BASE`.
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+/// First MSR of the x2APIC register range.
+const X2APIC_MSR_BASE: u32 = 0x800;
+/// Mask for the physical base address in `IA32_APIC_BASE`.
+const APIC_BASE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Vector used for spurious LAPIC interrupts (low 4 bits must be all ones on older CPUs).
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+/// Vector raised by the LAPIC timer (the high-priority clock vector, dispatched as IRQ line 0).
+pub const TIMER_VECTOR: u8 = crate::irq::CLOCK_VECTOR;
+
+/// Local APIC ID register.
+pub const REG_ID: u32 = 0x020;
+/// Local APIC version register.
+pub const REG_VERSION: u32 = 0x030;
+/// Task priority register.
+pub const REG_TPR: u32 = 0x080;
+/// End-of-interrupt register.
+pub const REG_EOI: u32 = 0x0B0;
+/// Spurious interrupt vector register.
+pub const REG_SVR: u32 = 0x0F0;
+/// Error status register.
+pub const REG_ESR: u32 = 0x280;
+/// Interrupt command register, low half (xAPIC; a single 64-bit MSR in x2APIC mode).
+pub const REG_ICR_LOW: u32 = 0x300;
+/// Interrupt command register, high half (destination, xAPIC only).
+pub const REG_ICR_HIGH: u32 = 0x310;
+/// LVT timer register.
+pub const REG_LVT_TIMER: u32 = 0x320;
+/// LVT LINT0 register.
+pub const REG_LVT_LINT0: u32 = 0x350;
+/// LVT LINT1 register.
+pub const REG_LVT_LINT1: u32 = 0x360;
+/// LVT error register.
+pub const REG_LVT_ERROR: u32 = 0x370;
+/// Timer initial count register.
+pub const REG_TIMER_INITIAL: u32 = 0x380;
+/// Timer current count register.
+pub const REG_TIMER_CURRENT: u32 = 0x390;
+/// Timer divide configuration register.
+pub const REG_TIMER_DIVIDE: u32 = 0x3E0;
+
+/// SVR bit: APIC software enable.
+const SVR_ENABLE: u32 = 1 << 8;
+/// LVT bit: interrupt masked.
+const LVT_MASKED: u32 = 1 << 16;
+/// LVT timer bit: periodic mode.
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+/// ICR bit: the previous IPI is still being delivered (xAPIC only).
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+/// LVT delivery mode: NMI.
+const LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+
+/// Virtual address of the LAPIC register block, or 0 if the LAPIC has not been enabled.
+static LAPIC_BASE: AtomicU64 = AtomicU64::new(0);
+/// Whether [`init`] switched the LAPIC to x2APIC mode.
+static X2APIC: AtomicBool = AtomicBool::new(false);
+/// Measured timer frequency in Hz at [`CALIBRATED_DIVIDE`], or 0 if not calibrated.
+static TIMER_FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// Divider used by the calibrated timer APIs.
+const CALIBRATED_DIVIDE: TimerDivide = TimerDivide::By16;
+/// Length of the calibration window in milliseconds.
+const CALIBRATION_MS: u16 = 10;
+
+/// Errors returned by [`init`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicError {
+    /// CPUID reports no Local APIC on this processor.
+    NotSupported,
+    /// [`calibrate_timer`] has not been run.
+    NotCalibrated,
+    /// The register block could not be mapped.
+    Unmapped,
+}
+
+/// LAPIC register access mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicMode {
+    /// Memory-mapped registers (xAPIC).
+    XApic,
+    /// MSR-based registers (x2APIC).
+    X2Apic,
+}
+
+/// LAPIC timer operating mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Fire once when the count reaches zero.
+    OneShot,
+    /// Reload the initial count and fire repeatedly.
+    Periodic,
+}
+
+/// LAPIC timer clock divider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerDivide {
+    /// Divide by 1.
+    By1,
+    /// Divide by 2.
+    By2,
+    /// Divide by 4.
+    By4,
+    /// Divide by 8.
+    By8,
+    /// Divide by 16.
+    By16,
+    /// Divide by 32.
+    By32,
+    /// Divide by 64.
+    By64,
+    /// Divide by 128.
+    By128,
+}
+
+impl TimerDivide {
+    /// Encoding for the divide configuration register (bits 0, 1 and 3).
+    fn encoding(&self) -> u32 {
+        match self {
+            TimerDivide::By1 => 0b1011,
+            TimerDivide::By2 => 0b0000,
+            TimerDivide::By4 => 0b0001,
+            TimerDivide::By8 => 0b0010,
+            TimerDivide::By16 => 0b0011,
+            TimerDivide::By32 => 0b1000,
+            TimerDivide::By64 => 0b1001,
+            TimerDivide::By128 => 0b1010,
+        }
+    }
+}
+
+/// Returns whether the CPU has a Local APIC (CPUID.01h:EDX bit 9).
+pub fn is_supported() -> bool {
+    let cpuid = core::arch::x86_64::__cpuid(1);
+    cpuid.edx & (1 << 9) != 0
+}
+
+/// Returns whether the CPU supports x2APIC mode (CPUID.01h:ECX bit 21).
+pub fn is_x2apic_supported() -> bool {
+    let cpuid = core::arch::x86_64::__cpuid(1);
+    cpuid.ecx & (1 << 21) != 0
+}
+
+/// Returns the register access mode selected by [`init`].
+pub fn mode() -> ApicMode {
+    if X2APIC.load(Ordering::Acquire) {
+        ApicMode::X2Apic
+    } else {
+        ApicMode::XApic
+    }
+}
+
+/// Returns whether [`init`] has enabled the Local APIC.
+pub fn is_enabled() -> bool {
+    LAPIC_BASE.load(Ordering::Acquire) != 0
+}
+
+/// Returns the physical base address of the LAPIC register block from `IA32_APIC_BASE`.
+pub fn base_address() -> u64 {
+    unsafe { rdmsr(IA32_APIC_BASE_MSR) & APIC_BASE_ADDR_MASK }
+}
+
+/// Reads a LAPIC register.
+///
+/// # Safety
+/// The LAPIC must be enabled and `reg` must be a valid register offset.
+pub unsafe fn read(reg: u32) -> u32 {
+    if X2APIC.load(Ordering::Acquire) {
+        return unsafe { rdmsr(X2APIC_MSR_BASE + (reg >> 4)) } as u32;
+    }
+    let base = LAPIC_BASE.load(Ordering::Acquire);
+    unsafe { core::ptr::read_volatile((base + reg as u64) as *const u32) }
+}
+
+/// Writes a LAPIC register.
+///
+/// # Safety
+/// The LAPIC must be enabled and `reg` must be a valid, writable register offset.
+pub unsafe fn write(reg: u32, value: u32) {
+    if X2APIC.load(Ordering::Acquire) {
+        return unsafe { wrmsr(X2APIC_MSR_BASE + (reg >> 4), value as u64) };
+    }
+    let base = LAPIC_BASE.load(Ordering::Acquire);
+    unsafe { core::ptr::write_volatile((base + reg as u64) as *mut u32, value) }
+}
+
+/// Detects and enables the Local APIC of the calling CPU.
+///
+/// This function:
+/// 1. Checks CPUID for LAPIC support.
+/// 2. Sets the global enable bit in `IA32_APIC_BASE`, plus the x2APIC enable bit if the CPU supports it.
+/// 3. Masks the legacy 8259 PIC, which the LAPIC replaces as the primary interrupt controller.
+/// 4. Masks all local vector table entries except LINT1 (NMI), clears the error status and task priority.
+/// 5. Software-enables the LAPIC with [`SPURIOUS_VECTOR`] as the spurious vector.
+///
+/// Device IRQs must be routed through the I/O APIC after this call, since the PIC no longer delivers them.
+/// The calling CPU is registered as CPU 0 for its per-CPU interrupt state (see [`crate::percpu`]).
+///
+/// # Safety
+/// Must be called with interrupts disabled, after the IDT has been loaded.
+pub unsafe fn init() -> Result<(), ApicError> {
+    if !is_supported() {
+        return Err(ApicError::NotSupported);
+    }
+
+    let apic_base = unsafe { rdmsr(IA32_APIC_BASE_MSR) };
+    let physical = apic_base & APIC_BASE_ADDR_MASK;
+    // x2APIC registers are MSRs, but the mapping is kept for a later switch back to xAPIC.
+    let base = crate::mmio::map("local apic", physical, 0x1000).ok_or(ApicError::Unmapped)?;
+    // xAPIC must be enabled before switching to x2APIC
+    unsafe { wrmsr(IA32_APIC_BASE_MSR, apic_base | APIC_BASE_ENABLE) };
+    if is_x2apic_supported() {
+        unsafe {
+            wrmsr(
+                IA32_APIC_BASE_MSR,
+                apic_base | APIC_BASE_ENABLE | APIC_BASE_X2APIC,
+            )
+        };
+        X2APIC.store(true, Ordering::Release);
+    }
+    LAPIC_BASE.store(base, Ordering::Release);
+    crate::percpu::register_cpu(0);
+
+    polished_x86_commands::disable_pic();
+
+    unsafe { program_local() };
+    Ok(())
+}
+
+/// Enables the Local APIC of an application processor, in the mode [`init`] chose on the bootstrap processor, and registers the processor as CPU `cpu_index` for its per-CPU interrupt state (see [`crate::percpu`]).
+///
+/// The register block mapping and the PIC are shared with the bootstrap processor and left alone; only this CPU's `IA32_APIC_BASE`, local vector table and SVR are programmed, as in steps 2, 4 and 5 of [`init`].
+///
+/// # Errors
+/// Fails with [`ApicError::NotSupported`] if [`init`] has not enabled the bootstrap processor's LAPIC.
+///
+/// # Safety
+/// Must be called on the application processor, with interrupts disabled, after the IDT has been loaded.
+pub unsafe fn init_secondary(cpu_index: usize) -> Result<(), ApicError> {
+    if !is_enabled() {
+        return Err(ApicError::NotSupported);
+    }
+    let mut apic_base = unsafe { rdmsr(IA32_APIC_BASE_MSR) } | APIC_BASE_ENABLE;
+    unsafe { wrmsr(IA32_APIC_BASE_MSR, apic_base) };
+    if X2APIC.load(Ordering::Acquire) {
+        apic_base |= APIC_BASE_X2APIC;
+        unsafe { wrmsr(IA32_APIC_BASE_MSR, apic_base) };
+    }
+    crate::percpu::register_cpu(cpu_index);
+    unsafe { program_local() };
+    Ok(())
+}
+
+/// Masks the local vector table except LINT1 (NMI), clears the error status and task priority, and software-enables the calling CPU's LAPIC.
+unsafe fn program_local() {
+    unsafe {
+        write(REG_LVT_TIMER, LVT_MASKED);
+        write(REG_LVT_LINT0, LVT_MASKED);
+        write(REG_LVT_LINT1, LVT_DELIVERY_NMI);
+        write(REG_LVT_ERROR, LVT_MASKED);
+        // The ESR must be written before it is read
+        write(REG_ESR, 0);
+        write(REG_ESR, 0);
+        write(REG_TPR, 0);
+        write(REG_SVR, SVR_ENABLE | SPURIOUS_VECTOR as u32);
+        eoi();
+    }
+}
+
+/// Returns the LAPIC ID of the calling CPU (8 bits in xAPIC mode, 32 bits in x2APIC mode).
+pub fn id() -> u32 {
+    match mode() {
+        ApicMode::X2Apic => unsafe { read(REG_ID) },
+        ApicMode::XApic => unsafe { read(REG_ID) >> 24 },
+    }
+}
+
+/// Sends an inter-processor interrupt by writing the interrupt command register.
+///
+/// `command` is the low 32 bits of the ICR (vector, delivery mode, level, destination shorthand) and `destination` the target APIC ID.
+/// In xAPIC mode this waits until the previous IPI has been accepted; x2APIC has no such handshake.
+///
+/// # Safety
+/// The LAPIC must be enabled and `command` must be a valid ICR value; INIT and NMI IPIs affect the target CPU's execution.
+pub unsafe fn write_icr(destination: u32, command: u32) {
+    if X2APIC.load(Ordering::Acquire) {
+        let value = (destination as u64) << 32 | command as u64;
+        return unsafe { wrmsr(X2APIC_MSR_BASE + (REG_ICR_LOW >> 4), value) };
+    }
+    unsafe {
+        while read(REG_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+        write(REG_ICR_HIGH, destination << 24);
+        // Writing the low half sends the IPI
+        write(REG_ICR_LOW, command);
+    }
+}
+
+/// Signals end-of-interrupt to the Local APIC.
+///
+/// Must be called at the end of every LAPIC-delivered interrupt handler, except the spurious handler.
+pub fn eoi() {
+    unsafe { write(REG_EOI, 0) };
+}
+
+/// Starts the LAPIC timer.
+///
+/// # Arguments
+/// * `vector` - IDT vector raised when the count reaches zero.
+/// * `mode` - One-shot or periodic.
+/// * `divide` - Divider applied to the timer's input clock.
+/// * `initial_count` - Number of (divided) clock ticks until the interrupt fires.
+pub fn configure_timer(vector: u8, mode: TimerMode, divide: TimerDivide, initial_count: u32) {
+    let mode_bits = match mode {
+        TimerMode::OneShot => 0,
+        TimerMode::Periodic => LVT_TIMER_PERIODIC,
+    };
+    unsafe {
+        write(REG_TIMER_DIVIDE, divide.encoding());
+        write(REG_LVT_TIMER, vector as u32 | mode_bits);
+        // Writing the initial count starts the timer
+        write(REG_TIMER_INITIAL, initial_count);
+    }
+}
+
+/// Stops the LAPIC timer and masks its interrupt.
+pub fn stop_timer() {
+    unsafe {
+        write(REG_LVT_TIMER, LVT_MASKED);
+        write(REG_TIMER_INITIAL, 0);
+    }
+}
+
+/// Returns the current count of the LAPIC timer.
+pub fn timer_current_count() -> u32 {
+    unsafe { read(REG_TIMER_CURRENT) }
+}
+
+/// Measures the LAPIC timer frequency and stores it for [`set_periodic`] and [`set_oneshot`].
+///
+/// The timer is run masked for a short window timed by the HPET if it is initialized, or by PIT channel 2 otherwise.
+/// Returns the measured frequency in Hz (at the divider used by the calibrated APIs).
+///
+/// # Example
+/// ```ignore
+/// let hz = apic::calibrate_timer();
+/// apic::set_periodic(100)?; // 100 interrupts per second on TIMER_VECTOR
+/// ```
+pub fn calibrate_timer() -> u64 {
+    unsafe {
+        write(REG_TIMER_DIVIDE, CALIBRATED_DIVIDE.encoding());
+        write(REG_LVT_TIMER, LVT_MASKED);
+        write(REG_TIMER_INITIAL, u32::MAX);
+    }
+    if hpet::is_available() {
+        let end = hpet::nanos() + CALIBRATION_MS as u64 * 1_000_000;
+        while hpet::nanos() < end {
+            core::hint::spin_loop();
+        }
+    } else {
+        pit::wait_polled_ms(CALIBRATION_MS);
+    }
+    let elapsed = u32::MAX - timer_current_count();
+    stop_timer();
+    let hz = elapsed as u64 * (1000 / CALIBRATION_MS as u64);
+    TIMER_FREQUENCY_HZ.store(hz, Ordering::Release);
+    hz
+}
+
+/// Returns the calibrated timer frequency in Hz, or 0 if [`calibrate_timer`] has not run.
+pub fn timer_frequency_hz() -> u64 {
+    TIMER_FREQUENCY_HZ.load(Ordering::Acquire)
+}
+
+/// Starts the LAPIC timer in periodic mode, raising [`TIMER_VECTOR`] `hz` times per second.
+pub fn set_periodic(hz: u32) -> Result<(), ApicError> {
+    let frequency = timer_frequency_hz();
+    if frequency == 0 {
+        return Err(ApicError::NotCalibrated);
+    }
+    let count = (frequency / hz.max(1) as u64).clamp(1, u32::MAX as u64) as u32;
+    configure_timer(TIMER_VECTOR, TimerMode::Periodic, CALIBRATED_DIVIDE, count);
+    Ok(())
+}
+
+/// Arms the LAPIC timer to raise [`TIMER_VECTOR`] once, `ns` nanoseconds from now.
+pub fn set_oneshot(ns: u64) -> Result<(), ApicError> {
+    let frequency = timer_frequency_hz();
+    if frequency == 0 {
+        return Err(ApicError::NotCalibrated);
+    }
+    let count = (ns as u128 * frequency as u128 / 1_000_000_000).clamp(1, u32::MAX as u128) as u32;
+    configure_timer(TIMER_VECTOR, TimerMode::OneShot, CALIBRATED_DIVIDE, count);
+    Ok(())
+}
@@ -0,0 +1,295 @@
+//! # Exception Context and Crash Dumps
+//!
+//! The `x86-interrupt` calling convention only exposes the stack frame pushed by the CPU (RIP, CS, RFLAGS, RSP, SS), which is rarely enough to understand a crash. This module provides assembly entry stubs that save every general-purpose register before calling a Rust handler, and routines that dump the saved registers, control registers, and a window of the faulting stack over serial.
+//!
+//! ## How the Entry Stubs Work
+//!
+//! For exceptions that push an error code, the CPU leaves `error code, RIP, CS, RFLAGS, RSP, SS` on the stack. The stub generated by [`exception_entry_with_error!`](crate::exception_entry_with_error) pushes RAX through R15 on top of that, so the whole block can be viewed as one [`ExceptionContext`] and passed to the handler by pointer. If the handler returns, the registers are restored, the error code is discarded, and `iretq` resumes the interrupted code.
+//!
+//! Interrupts without an error code use [`interrupt_entry!`](crate::interrupt_entry) and an [`InterruptContext`] instead. The handler may modify the context, e.g. to redirect a user task to a signal handler.
+//!
+//! Below the registers, both stubs save the interrupted code's x87/SSE/AVX state with `polished_x86_commands::simd::save_state` and restore it after the handler, because the handler is compiled Rust that may use the SIMD registers itself.
+//!
+//! Both stubs follow the `swapgs` discipline of the `polished_gdt` crate: when the saved CS has RPL 3 they swap the GS base before saving anything and again right before `iretq`, so handlers always see the kernel's per-CPU block. Handlers using the `x86-interrupt` convention get no such swap and must not use per-CPU data when they interrupt user mode.
+//!
+//! ## Reading the Stack Safely
+//!
+//! A crash often comes with a corrupted stack pointer. Before reading memory around RSP, [`dump_stack`] checks that each page is mapped by walking the active page tables (the kernel runs on the identity mapping left by UEFI).
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use polished_serial_logging::kprint;
+#[doc(hidden)]
+pub use polished_x86_commands::simd as __simd;
+use x86_64::VirtAddr;
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::structures::paging::{OffsetPageTable, PageTable, Translate};
+
+/// General-purpose registers saved by the entry stubs, in stack order (R15 at the lowest address).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SavedRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// Complete state of the interrupted code, as laid out on the stack by an entry stub.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionContext {
+    /// General-purpose registers.
+    pub registers: SavedRegisters,
+    /// Error code pushed by the CPU.
+    pub error_code: u64,
+    /// Instruction pointer of the faulting (or next) instruction.
+    pub rip: u64,
+    /// Code segment selector.
+    pub cs: u64,
+    /// Flags register.
+    pub rflags: u64,
+    /// Stack pointer of the interrupted code.
+    pub rsp: u64,
+    /// Stack segment selector.
+    pub ss: u64,
+}
+
+/// Complete state of code interrupted by an interrupt without an error code, as laid out on the stack by [`interrupt_entry!`](crate::interrupt_entry).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptContext {
+    /// General-purpose registers.
+    pub registers: SavedRegisters,
+    /// Instruction pointer to resume at.
+    pub rip: u64,
+    /// Code segment selector.
+    pub cs: u64,
+    /// Flags register.
+    pub rflags: u64,
+    /// Stack pointer of the interrupted code.
+    pub rsp: u64,
+    /// Stack segment selector.
+    pub ss: u64,
+}
+
+impl InterruptContext {
+    /// Returns whether the interrupted code ran in user mode (ring 3).
+    pub fn is_user_mode(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
+/// Generates a naked entry stub for an interrupt that does not push an error code.
+///
+/// `$handler` must be an `extern "C" fn(&mut InterruptContext)`. The stub saves all general-purpose registers and the SIMD state, calls the handler, and resumes the (possibly modified) context.
+#[macro_export]
+macro_rules! interrupt_entry {
+    ($name:ident, $handler:path) => {
+        #[unsafe(naked)]
+        pub extern "C" fn $name() {
+            core::arch::naked_asm!(
+                // Swap to the kernel GS base if user mode was interrupted (CS RPL 3)
+                "test byte ptr [rsp + 8], 3",
+                "jz 1f",
+                "swapgs",
+                "1:",
+                "push rax", "push rbx", "push rcx", "push rdx", "push rsi", "push rdi", "push rbp",
+                "push r8", "push r9", "push r10", "push r11", "push r12", "push r13", "push r14", "push r15",
+                "mov rbp, rsp",
+                // SIMD state below the registers, 64-byte aligned as `xsave` requires
+                "sub rsp, qword ptr [rip + {simd_size}]",
+                "and rsp, -64",
+                "cld",
+                "mov rdi, rsp",
+                "call {simd_save}",
+                "mov rdi, rbp",
+                "call {handler}",
+                "mov rdi, rsp",
+                "call {simd_restore}",
+                "mov rsp, rbp",
+                "pop r15", "pop r14", "pop r13", "pop r12", "pop r11", "pop r10", "pop r9", "pop r8",
+                "pop rbp", "pop rdi", "pop rsi", "pop rdx", "pop rcx", "pop rbx", "pop rax",
+                "test byte ptr [rsp + 8], 3",
+                "jz 2f",
+                "swapgs",
+                "2:",
+                "iretq",
+                handler = sym $handler,
+                simd_size = sym $crate::context::__simd::ENTRY_STATE_SIZE,
+                simd_save = sym $crate::context::__simd::save_state,
+                simd_restore = sym $crate::context::__simd::restore_state,
+            );
+        }
+    };
+}
+
+/// Generates a naked entry stub for an exception that pushes an error code.
+///
+/// `$handler` must be an `extern "C" fn(&mut ExceptionContext)`. The stub saves all general-purpose registers and the SIMD state, calls the handler, and resumes the interrupted code if it returns.
+#[macro_export]
+macro_rules! exception_entry_with_error {
+    ($name:ident, $handler:path) => {
+        #[unsafe(naked)]
+        pub extern "C" fn $name() {
+            core::arch::naked_asm!(
+                // CS follows the error code and RIP
+                "test byte ptr [rsp + 16], 3",
+                "jz 1f",
+                "swapgs",
+                "1:",
+                "push rax", "push rbx", "push rcx", "push rdx", "push rsi", "push rdi", "push rbp",
+                "push r8", "push r9", "push r10", "push r11", "push r12", "push r13", "push r14", "push r15",
+                "mov rbp, rsp",
+                // SIMD state below the registers, 64-byte aligned as `xsave` requires
+                "sub rsp, qword ptr [rip + {simd_size}]",
+                "and rsp, -64",
+                "cld",
+                "mov rdi, rsp",
+                "call {simd_save}",
+                "mov rdi, rbp",
+                "call {handler}",
+                "mov rdi, rsp",
+                "call {simd_restore}",
+                "mov rsp, rbp",
+                "pop r15", "pop r14", "pop r13", "pop r12", "pop r11", "pop r10", "pop r9", "pop r8",
+                "pop rbp", "pop rdi", "pop rsi", "pop rdx", "pop rcx", "pop rbx", "pop rax",
+                // Discard the error code
+                "add rsp, 8",
+                "test byte ptr [rsp + 8], 3",
+                "jz 2f",
+                "swapgs",
+                "2:",
+                "iretq",
+                handler = sym $handler,
+                simd_size = sym $crate::context::__simd::ENTRY_STATE_SIZE,
+                simd_save = sym $crate::context::__simd::save_state,
+                simd_restore = sym $crate::context::__simd::restore_state,
+            );
+        }
+    };
+}
+
+/// Returns whether the page containing `address` is mapped in the active page tables.
+pub fn is_mapped(address: u64) -> bool {
+    let Ok(address) = VirtAddr::try_new(address) else {
+        return false;
+    };
+    let (frame, _) = Cr3::read();
+    // Safety: page tables are identity mapped, so the physical address of the PML4 is also its virtual address.
+    let table = unsafe {
+        let pml4 = &mut *(frame.start_address().as_u64() as *mut PageTable);
+        OffsetPageTable::new(pml4, VirtAddr::new(0))
+    };
+    table.translate_addr(address).is_some()
+}
+
+static KERNEL_SLIDE: AtomicU64 = AtomicU64::new(0);
+
+/// Records how far the bootloader moved the kernel above its link address (KASLR), so crash dumps can print link-time addresses that match the symbols of the kernel ELF.
+pub fn set_kernel_slide(slide: u64) {
+    KERNEL_SLIDE.store(slide, Ordering::Relaxed);
+}
+
+/// Returns the KASLR slide recorded with [`set_kernel_slide`].
+pub fn kernel_slide() -> u64 {
+    KERNEL_SLIDE.load(Ordering::Relaxed)
+}
+
+/// Prints the saved general-purpose registers, the CPU frame, and the control registers.
+pub fn dump_context(context: &ExceptionContext) {
+    let r = &context.registers;
+    kprint!("[ERROR] Registers:\r\n");
+    kprint!(
+        "  RAX={:016x} RBX={:016x} RCX={:016x} RDX={:016x}\r\n",
+        r.rax,
+        r.rbx,
+        r.rcx,
+        r.rdx
+    );
+    kprint!(
+        "  RSI={:016x} RDI={:016x} RBP={:016x} RSP={:016x}\r\n",
+        r.rsi,
+        r.rdi,
+        r.rbp,
+        context.rsp
+    );
+    kprint!(
+        "  R8 ={:016x} R9 ={:016x} R10={:016x} R11={:016x}\r\n",
+        r.r8,
+        r.r9,
+        r.r10,
+        r.r11
+    );
+    kprint!(
+        "  R12={:016x} R13={:016x} R14={:016x} R15={:016x}\r\n",
+        r.r12,
+        r.r13,
+        r.r14,
+        r.r15
+    );
+    kprint!(
+        "  RIP={:016x} RFLAGS={:016x} CS={:04x} SS={:04x} ERR={:#x}\r\n",
+        context.rip,
+        context.rflags,
+        context.cs,
+        context.ss,
+        context.error_code
+    );
+    let slide = kernel_slide();
+    if slide != 0 && context.cs & 3 == 0 {
+        kprint!(
+            "  Link-time RIP={:016x} (KASLR slide {:#x})\r\n",
+            context.rip.wrapping_sub(slide),
+            slide
+        );
+    }
+    kprint!(
+        "  CR0={:016x} CR2={:016x} CR3={:016x} CR4={:016x}\r\n",
+        Cr0::read_raw(),
+        Cr2::read_raw(),
+        Cr3::read_raw().0.start_address().as_u64(),
+        Cr4::read_raw()
+    );
+}
+
+/// Prints `words` quadwords of memory starting at `rsp`, skipping unmapped pages.
+pub fn dump_stack(rsp: u64, words: usize) {
+    kprint!("[ERROR] Stack at {:#x}:\r\n", rsp);
+    let start = rsp & !0x7;
+    for row in 0..words.div_ceil(4) {
+        let address = start + row as u64 * 32;
+        kprint!("  {:016x}:", address);
+        for column in 0..4.min(words - row * 4) {
+            let slot = address + column as u64 * 8;
+            if is_mapped(slot) {
+                let value = unsafe { core::ptr::read_volatile(slot as *const u64) };
+                kprint!(" {:016x}", value);
+            } else {
+                kprint!(" ????????????????");
+            }
+        }
+        kprint!("\r\n");
+    }
+}
+
+/// Number of stack quadwords printed by [`dump_crash`].
+pub const STACK_DUMP_WORDS: usize = 32;
+
+/// Prints a full crash dump: registers, control registers, and the top of the interrupted stack.
+pub fn dump_crash(name: &str, context: &ExceptionContext) {
+    kprint!("[ERROR] EXCEPTION: {}\r\n", name);
+    dump_context(context);
+    dump_stack(context.rsp, STACK_DUMP_WORDS);
+}
@@ -7,18 +7,58 @@
 //! CPU exceptions are interrupts generated by the processor when it detects error conditions during program execution. Examples include division by zero, invalid opcode, page faults, and double faults. Handling these exceptions is critical for OS stability and debugging.
 //!
 //! This module provides a function to register exception handlers in the Interrupt Descriptor Table (IDT).
+//!
+//! ## Fatal Exceptions
+//!
+//! Exceptions that cannot be recovered from are logged and then turned into a kernel panic whose message names the exception, the faulting RIP, and the error code. The panic handler thus reports CPU exceptions the same way as any other panic.
+//!
+//! ## Error Codes
+//!
+//! Selector error codes (general protection, invalid TSS, segment not present, stack segment faults) and page fault error codes are decoded by [`crate::error_code`] and logged as text, e.g. which GDT or IDT entry was involved.
+//!
+//! ## Crash Dumps
+//!
+//! General protection faults, double faults, and page faults enter through the register-saving stubs of [`crate::context`], so their reports include all general-purpose registers, the control registers, and a hex dump of the stack around RSP.
+//!
+//! ## Stack Overflows
+//!
+//! The double fault handler compares the faulting RSP against the kernel stacks registered in [`crate::stacks`] and reports a kernel stack overflow, with the stack's limits, when RSP ran past the bottom of one.
+//!
+//! ## Hooks
+//!
+//! Every exception except double faults and machine checks runs the hooks registered with [`crate::exception_hooks`] before it is treated as fatal, so the kernel can emulate, recover from, or annotate it at runtime.
+//!
+//! ## Breakpoints and Debug Exceptions
+//!
+//! `int3` breakpoints and debug exceptions (single-step, hardware breakpoints) are not fatal: they are logged and execution resumes. A debugger or gdbstub can take over by installing a hook with [`set_debug_hook`].
+//!
+//! ## Page Faults
+//!
+//! The page fault handler reads the faulting address from CR2 and decodes the error code (read/write/instruction fetch, not-present/protection violation, user/kernel mode) with [`crate::error_code`]. Before declaring the fault fatal it calls the hook installed with [`set_page_fault_hook`], which lets the memory subsystem resolve faults such as demand-paged allocations.
 
 use core::arch::asm;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::context::{ExceptionContext, dump_crash};
+use crate::error_code::{PageFaultDescription, SelectorErrorCode};
+use crate::exception_entry_with_error;
+use crate::exception_hooks::{self, Exception};
+use crate::stacks;
 
 use polished_serial_logging::kprint;
+use x86_64::registers::control::Cr2;
+use x86_64::registers::debug::Dr6;
+use x86_64::registers::rflags::RFlags;
 use x86_64::structures::idt::InterruptDescriptorTable;
 use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::{PrivilegeLevel, VirtAddr};
 
 pub fn setup_cpu_exceptions(idt: &mut InterruptDescriptorTable) {
     // Set IST index for double fault (IST1)
     unsafe {
         idt.double_fault
-            .set_handler_fn(double_fault_handler)
+            .set_handler_addr(VirtAddr::new(double_fault_entry as *const () as u64))
             .set_stack_index(1);
         // Set IST index for NMI (IST2)
         idt.non_maskable_interrupt
@@ -27,10 +67,18 @@ pub fn setup_cpu_exceptions(idt: &mut InterruptDescriptorTable) {
     }
     // Other exceptions can be set similarly if needed
     idt.divide_error.set_handler_fn(divide_by_zero_handler);
-    idt.general_protection_fault
-        .set_handler_fn(general_protection_fault_handler);
+    unsafe {
+        idt.general_protection_fault.set_handler_addr(VirtAddr::new(
+            general_protection_fault_entry as *const () as u64,
+        ));
+        idt.page_fault
+            .set_handler_addr(VirtAddr::new(page_fault_entry as *const () as u64));
+    }
     idt.debug.set_handler_fn(debug_handler);
-    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    // DPL 3 so that `int3` also works as a breakpoint in user mode
+    idt.breakpoint
+        .set_handler_fn(breakpoint_handler)
+        .set_privilege_level(PrivilegeLevel::Ring3);
     idt.overflow.set_handler_fn(overflow_handler);
     idt.bound_range_exceeded
         .set_handler_fn(bound_range_exceeded_handler);
@@ -42,7 +90,6 @@ pub fn setup_cpu_exceptions(idt: &mut InterruptDescriptorTable) {
         .set_handler_fn(segment_not_present_handler);
     idt.stack_segment_fault
         .set_handler_fn(stack_segment_fault_handler);
-    idt.page_fault.set_handler_fn(page_fault_handler);
     idt.x87_floating_point
         .set_handler_fn(x87_floating_point_handler);
     idt.alignment_check.set_handler_fn(alignment_check_handler);
@@ -53,191 +100,382 @@ pub fn setup_cpu_exceptions(idt: &mut InterruptDescriptorTable) {
         .set_handler_fn(virtualization_exception_handler);
 }
 
-pub extern "x86-interrupt" fn divide_by_zero_handler(_stack_frame: InterruptStackFrame) {
+/// Ends a fatal exception by panicking with its context, so it is reported by the panic handler like any other kernel panic.
+fn exception_panic(name: &str, stack_frame: &InterruptStackFrame, error_code: Option<u64>) -> ! {
+    let rip = stack_frame.instruction_pointer.as_u64();
+    match error_code {
+        Some(code) => panic!("CPU exception: {name} at RIP {rip:#x} (error code {code:#x})"),
+        None => panic!("CPU exception: {name} at RIP {rip:#x}"),
+    }
+}
+
+pub extern "x86-interrupt" fn divide_by_zero_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(Exception::DivideError, &mut stack_frame, None, |_| false) {
+        return;
+    }
     kprint!("[ERROR] EXCEPTION: DIVIDE BY ZERO\r\n");
     kprint!(
         "[SUGGESTION] Possible cause: Division by zero. Solution: Check divisor before division.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Divide By Zero", &stack_frame, None);
 }
 
-pub extern "x86-interrupt" fn general_protection_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: u64,
-) {
-    kprint!("[ERROR] General Protection Fault: {:#?}\r\n", stack_frame);
-    kprint!("[ERROR] Error Code: {:#x}\r\n", error_code);
+exception_entry_with_error!(
+    general_protection_fault_entry,
+    general_protection_fault_handler
+);
+exception_entry_with_error!(double_fault_entry, double_fault_handler);
+exception_entry_with_error!(page_fault_entry, page_fault_handler);
+
+extern "C" fn general_protection_fault_handler(context: &mut ExceptionContext) {
+    if exception_hooks::run_context(Exception::GeneralProtectionFault, context, |_| false) {
+        return;
+    }
+    dump_crash("GENERAL PROTECTION FAULT", context);
+    kprint!(
+        "[ERROR] Error Code: {:#x}: {}\r\n",
+        context.error_code,
+        SelectorErrorCode::new(context.error_code)
+    );
     kprint!(
         "[SUGGESTION] Possible cause: Invalid memory access or segment. Solution: Check segment selectors and memory accesses.\r\n"
     );
-    panic!("General Protection Fault: {:#?}", stack_frame);
+    panic!(
+        "CPU exception: General Protection Fault at RIP {:#x} (error code {:#x})",
+        context.rip, context.error_code
+    );
 }
 
-pub extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame,
-    _error_code: u64,
-) -> ! {
+extern "C" fn double_fault_handler(context: &mut ExceptionContext) {
+    dump_crash("DOUBLE FAULT", context);
+    if let Some(stack) = stacks::find_overflow(context.rsp) {
+        kprint!(
+            "[ERROR] Kernel stack overflow: RSP {:#x} is past the bottom of the '{}' stack ({:#x}..{:#x}, {} KiB)\r\n",
+            context.rsp,
+            stack.name,
+            stack.bottom,
+            stack.top,
+            stack.size() / 1024
+        );
+        kprint!(
+            "[SUGGESTION] Possible cause: Deep recursion or large stack allocations. Solution: Move large buffers to the heap or enlarge the stack.\r\n"
+        );
+        panic!(
+            "CPU exception: Double Fault (kernel stack overflow on '{}') at RIP {:#x}",
+            stack.name, context.rip
+        );
+    }
+    match stacks::find_stack(context.rsp) {
+        Some(stack) => kprint!(
+            "[ERROR] RSP {:#x} is within the '{}' stack ({:#x}..{:#x})\r\n",
+            context.rsp,
+            stack.name,
+            stack.bottom,
+            stack.top
+        ),
+        None => kprint!(
+            "[ERROR] RSP {:#x} is not within any registered kernel stack\r\n",
+            context.rsp
+        ),
+    }
     kprint!(
         "[SUGGESTION] Possible cause: Exception during exception handling. Solution: Check stack overflows and handler correctness.\r\n"
     );
-    panic!("Double Fault: {:#?}", stack_frame);
+    panic!("CPU exception: Double Fault at RIP {:#x}", context.rip);
 }
 
-pub extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
-    kprint!("[DEBUG] Debug Exception: {:#?}\r\n", stack_frame);
-    kprint!(
-        "[SUGGESTION] Possible cause: Debug exception (breakpoint, single-step). Solution: Check debug registers and breakpoints.\r\n"
-    );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
+/// Event passed to a [`DebugHook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugEvent {
+    /// An `int3` instruction was executed; RIP points just past it.
+    Breakpoint,
+    /// A debug exception (#DB) fired: single-step, hardware breakpoint, or task switch.
+    Debug {
+        /// Value of DR6, describing which condition(s) triggered the exception.
+        dr6: u64,
+    },
+}
+
+/// Hook called by the breakpoint and debug handlers, e.g. by a debugger or gdbstub.
+///
+/// Receives the event and the interrupted context, which it may modify (for example to set or clear
+/// the trap flag for single-stepping). Returning `true` means the hook handled the event and the
+/// default logging is skipped. In every case the interrupted code resumes when the handler returns.
+pub type DebugHook = fn(event: DebugEvent, stack_frame: &mut InterruptStackFrame) -> bool;
+
+static DEBUG_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Installs the hook consulted by the breakpoint and debug handlers, replacing any previous one.
+pub fn set_debug_hook(hook: DebugHook) {
+    DEBUG_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Removes the debug hook.
+pub fn clear_debug_hook() {
+    DEBUG_HOOK.store(core::ptr::null_mut(), Ordering::Release);
+}
+
+fn debug_hook() -> Option<DebugHook> {
+    let ptr = DEBUG_HOOK.load(Ordering::Acquire);
+    // Safety: only `DebugHook` function pointers are ever stored.
+    (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), DebugHook>(ptr) })
+}
+
+/// DR6 bit: the exception was caused by single-stepping.
+const DR6_SINGLE_STEP: u64 = 1 << 14;
+
+pub extern "x86-interrupt" fn debug_handler(mut stack_frame: InterruptStackFrame) {
+    let dr6 = Dr6::read_raw();
+    // DR6 bits are sticky; clear them so the next exception reports only its own cause.
+    unsafe { asm!("mov dr6, {}", in(reg) 0u64, options(nomem, nostack, preserves_flags)) };
+
+    if let Some(hook) = debug_hook()
+        && hook(DebugEvent::Debug { dr6 }, &mut stack_frame)
+    {
+        return;
     }
+    exception_hooks::run_frame(Exception::Debug, &mut stack_frame, None, |info| {
+        kprint!(
+            "[DEBUG] Debug Exception at RIP {:#x}, DR6 {:#x}\r\n",
+            info.rip,
+            dr6
+        );
+        if dr6 & DR6_SINGLE_STEP != 0 {
+            // Nobody is single-stepping: clear the trap flag instead of trapping on every instruction.
+            info.rflags &= !RFlags::TRAP_FLAG.bits();
+        }
+        true
+    });
 }
 
-pub extern "x86-interrupt" fn non_maskable_interrupt_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn non_maskable_interrupt_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(
+        Exception::NonMaskableInterrupt,
+        &mut stack_frame,
+        None,
+        |_| false,
+    ) {
+        return;
+    }
     kprint!("[NMI] Non-Maskable Interrupt: {:#?}\r\n", stack_frame);
     kprint!(
         "[SUGGESTION] Possible cause: Hardware failure or NMI source. Solution: Check hardware and NMI sources.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Non-Maskable Interrupt", &stack_frame, None);
 }
 
-pub extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    kprint!("[DEBUG] Breakpoint Exception: {:#?}\r\n", stack_frame);
-    kprint!(
-        "[SUGGESTION] Possible cause: Breakpoint instruction (int3). Solution: Check for intentional breakpoints.\r\n"
-    );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
+pub extern "x86-interrupt" fn breakpoint_handler(mut stack_frame: InterruptStackFrame) {
+    if let Some(hook) = debug_hook()
+        && hook(DebugEvent::Breakpoint, &mut stack_frame)
+    {
+        return;
     }
+    let frame = *stack_frame;
+    exception_hooks::run_frame(Exception::Breakpoint, &mut stack_frame, None, |info| {
+        kprint!(
+            "[DEBUG] Breakpoint at RIP {:#x}: {:#?}\r\n",
+            info.rip - 1,
+            frame
+        );
+        true
+    });
 }
 
-pub extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn overflow_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(Exception::Overflow, &mut stack_frame, None, |_| false) {
+        return;
+    }
     kprint!("[ERROR] Stack Overflow: {:#?}\r\n", stack_frame);
     kprint!(
         "[SUGGESTION] Possible cause: INTO instruction overflow. Solution: Check arithmetic operations for overflow.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Overflow", &stack_frame, None);
 }
 
-pub extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn bound_range_exceeded_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(
+        Exception::BoundRangeExceeded,
+        &mut stack_frame,
+        None,
+        |_| false,
+    ) {
+        return;
+    }
     kprint!("[ERROR] Bound Range Exceeded: {:#?}\r\n", stack_frame);
     kprint!(
         "[SUGGESTION] Possible cause: BOUND instruction out of range. Solution: Check array bounds.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Bound Range Exceeded", &stack_frame, None);
 }
 
-pub extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn invalid_opcode_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(Exception::InvalidOpcode, &mut stack_frame, None, |_| false) {
+        return;
+    }
     kprint!("[ERROR] Invalid Opcode: {:#?}\r\n", stack_frame);
     kprint!(
         "[SUGGESTION] Possible cause: Invalid or undefined instruction. Solution: Check for unsupported CPU instructions.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Invalid Opcode", &stack_frame, None);
 }
 
-pub extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn device_not_available_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(
+        Exception::DeviceNotAvailable,
+        &mut stack_frame,
+        None,
+        |_| false,
+    ) {
+        return;
+    }
     kprint!("[ERROR] Device Not Available: {:#?}\r\n", stack_frame);
     kprint!(
         "[SUGGESTION] Possible cause: FPU or device not available. Solution: Check FPU usage and TS flag.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Device Not Available", &stack_frame, None);
 }
 
 pub extern "x86-interrupt" fn invalid_tss_handler(
-    stack_frame: InterruptStackFrame,
+    mut stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if exception_hooks::run_frame(
+        Exception::InvalidTss,
+        &mut stack_frame,
+        Some(error_code),
+        |_| false,
+    ) {
+        return;
+    }
     kprint!("[ERROR] Invalid TSS: {:#?}\r\n", stack_frame);
-    kprint!("[ERROR] Error Code: {:#x}\r\n", error_code);
+    kprint!(
+        "[ERROR] Error Code: {:#x}: {}\r\n",
+        error_code,
+        SelectorErrorCode::new(error_code)
+    );
     kprint!(
         "[SUGGESTION] Possible cause: Invalid Task State Segment. Solution: Check TSS setup and task switching.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Invalid TSS", &stack_frame, Some(error_code));
 }
 
 pub extern "x86-interrupt" fn segment_not_present_handler(
-    stack_frame: InterruptStackFrame,
+    mut stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if exception_hooks::run_frame(
+        Exception::SegmentNotPresent,
+        &mut stack_frame,
+        Some(error_code),
+        |_| false,
+    ) {
+        return;
+    }
     kprint!("[ERROR] Segment Not Present: {:#?}\r\n", stack_frame);
-    kprint!("[ERROR] Error Code: {:#x}\r\n", error_code);
+    kprint!(
+        "[ERROR] Error Code: {:#x}: {}\r\n",
+        error_code,
+        SelectorErrorCode::new(error_code)
+    );
     kprint!(
         "[SUGGESTION] Possible cause: Segment not present in memory. Solution: Check segment descriptors.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Segment Not Present", &stack_frame, Some(error_code));
 }
 
 pub extern "x86-interrupt" fn stack_segment_fault_handler(
-    stack_frame: InterruptStackFrame,
+    mut stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if exception_hooks::run_frame(
+        Exception::StackSegmentFault,
+        &mut stack_frame,
+        Some(error_code),
+        |_| false,
+    ) {
+        return;
+    }
     kprint!("[ERROR] Stack Segment Fault: {:#?}\r\n", stack_frame);
-    kprint!("[ERROR] Error Code: {:#x}\r\n", error_code);
+    kprint!(
+        "[ERROR] Error Code: {:#x}: {}\r\n",
+        error_code,
+        SelectorErrorCode::new(error_code)
+    );
     kprint!(
         "[SUGGESTION] Possible cause: Stack segment error. Solution: Check stack pointers and segment limits.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Stack Segment Fault", &stack_frame, Some(error_code));
 }
 
-use x86_64::structures::idt::PageFaultErrorCode;
+/// Hook called by the page fault handler before the fault is treated as fatal.
+///
+/// Receives the faulting address (from CR2) and the error code, and returns `true` if it resolved the fault
+/// (e.g. by mapping a page on demand), in which case the faulting instruction is retried.
+pub type PageFaultHook = fn(address: u64, error_code: PageFaultErrorCode) -> bool;
 
-pub extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
-    kprint!("[ERROR] Page Fault: {:#?}\r\n", stack_frame);
-    kprint!("[ERROR] Error Code: {:?}\r\n", error_code);
+static PAGE_FAULT_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Installs the hook consulted by the page fault handler, replacing any previous one.
+///
+/// # Example
+/// ```ignore
+/// fn demand_page(address: u64, error_code: PageFaultErrorCode) -> bool {
+///     // map a fresh frame at `address` if it lies in a lazily allocated region
+///     false
+/// }
+/// polished_interrupts::cpu_exceptions::set_page_fault_hook(demand_page);
+/// ```
+pub fn set_page_fault_hook(hook: PageFaultHook) {
+    PAGE_FAULT_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Removes the page fault hook.
+pub fn clear_page_fault_hook() {
+    PAGE_FAULT_HOOK.store(core::ptr::null_mut(), Ordering::Release);
+}
+
+fn page_fault_hook() -> Option<PageFaultHook> {
+    let ptr = PAGE_FAULT_HOOK.load(Ordering::Acquire);
+    // Safety: only `PageFaultHook` function pointers are ever stored.
+    (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), PageFaultHook>(ptr) })
+}
+
+extern "C" fn page_fault_handler(context: &mut ExceptionContext) {
+    let address = Cr2::read_raw();
+    let error_code = PageFaultErrorCode::from_bits_truncate(context.error_code);
+    if exception_hooks::run_context(Exception::PageFault, context, |_| {
+        page_fault_hook().is_some_and(|hook| hook(address, error_code))
+    }) {
+        return;
+    }
+
+    dump_crash("PAGE FAULT", context);
+    kprint!(
+        "[ERROR] Page Fault at {:#x}: {}, RIP {:#x}\r\n",
+        address,
+        PageFaultDescription::new(error_code),
+        context.rip
+    );
+    kprint!(
+        "[ERROR] Error Code: {:#x} ({:?})\r\n",
+        context.error_code,
+        error_code
+    );
     kprint!(
         "[SUGGESTION] Possible cause: Invalid memory access. Solution: Check page tables and memory accesses.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    panic!(
+        "CPU exception: Page Fault at RIP {:#x} accessing {:#x} (error code {:#x})",
+        context.rip, address, context.error_code
+    );
 }
 
-pub extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn x87_floating_point_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(Exception::X87FloatingPoint, &mut stack_frame, None, |_| {
+        false
+    }) {
+        return;
+    }
     kprint!(
         "[ERROR] x87 Floating Point Exception: {:#?}\r\n",
         stack_frame
@@ -245,27 +483,27 @@ pub extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptS
     kprint!(
         "[SUGGESTION] Possible cause: x87 FPU error. Solution: Check floating point operations.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("x87 Floating Point Exception", &stack_frame, None);
 }
 
 pub extern "x86-interrupt" fn alignment_check_handler(
-    stack_frame: InterruptStackFrame,
+    mut stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if exception_hooks::run_frame(
+        Exception::AlignmentCheck,
+        &mut stack_frame,
+        Some(error_code),
+        |_| false,
+    ) {
+        return;
+    }
     kprint!("[ERROR] Alignment Check Exception: {:#?}\r\n", stack_frame);
     kprint!("[ERROR] Error Code: {:#x}\r\n", error_code);
     kprint!(
         "[SUGGESTION] Possible cause: Unaligned memory access. Solution: Check data alignment.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Alignment Check", &stack_frame, Some(error_code));
 }
 
 pub extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
@@ -273,34 +511,32 @@ pub extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackF
     kprint!(
         "[SUGGESTION] Possible cause: Hardware error. Solution: Check hardware status and logs.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Machine Check", &stack_frame, None);
 }
 
-pub extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn simd_floating_point_handler(mut stack_frame: InterruptStackFrame) {
+    if exception_hooks::run_frame(Exception::SimdFloatingPoint, &mut stack_frame, None, |_| {
+        false
+    }) {
+        return;
+    }
     kprint!(
         "[ERROR] SIMD Floating Point Exception: {:#?}\r\n",
         stack_frame
     );
     kprint!("[SUGGESTION] Possible cause: SIMD FPU error. Solution: Check SIMD operations.\r\n");
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("SIMD Floating Point Exception", &stack_frame, None);
 }
 
-pub extern "x86-interrupt" fn virtualization_exception_handler(stack_frame: InterruptStackFrame) {
+pub extern "x86-interrupt" fn virtualization_exception_handler(
+    mut stack_frame: InterruptStackFrame,
+) {
+    if exception_hooks::run_frame(Exception::Virtualization, &mut stack_frame, None, |_| false) {
+        return;
+    }
     kprint!("[ERROR] Virtualization Exception: {:#?}\r\n", stack_frame);
     kprint!(
         "[SUGGESTION] Possible cause: Virtualization instruction error. Solution: Check virtualization support and usage.\r\n"
     );
-    loop {
-        unsafe {
-            asm!("cli; hlt");
-        }
-    }
+    exception_panic("Virtualization Exception", &stack_frame, None);
 }
@@ -0,0 +1,91 @@
+//! # Deferred Interrupt Work
+//!
+//! IRQ handlers run with interrupts disabled, so anything slow they do (parsing a network packet, completing a disk read, waking up waiters) delays every other interrupt. This module lets a handler do only the urgent part (acknowledge the device, grab the data) and queue the rest as *deferred work*, similar to softirqs/tasklets in other kernels.
+//!
+//! ## How It Works
+//!
+//! - A handler calls [`defer`] with a function pointer and a `usize` argument (an index, a pointer cast to `usize`, ...).
+//! - The work item is stored in a fixed-size queue. The queue is protected by a spinlock that is only taken with interrupts disabled, so a handler can never deadlock against the code draining it.
+//! - The kernel main loop (or the idle task) calls [`run_deferred`], which pops the items one at a time and runs them **with interrupts enabled**.
+//!
+//! ## Example
+//! ```ignore
+//! fn finish_read(request: usize) {
+//!     // copy the completed sectors to the waiting buffer...
+//! }
+//!
+//! fn disk_irq(_irq: u8) {
+//!     let request = ack_controller();
+//!     deferred::defer(finish_read, request);
+//! }
+//! ```
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// A unit of deferred work, called with the argument given to [`defer`].
+pub type DeferredFn = fn(arg: usize);
+
+/// Maximum number of pending work items.
+pub const DEFERRED_QUEUE_SIZE: usize = 64;
+
+struct DeferredQueue {
+    items: [Option<(DeferredFn, usize)>; DEFERRED_QUEUE_SIZE],
+    head: usize,
+    len: usize,
+}
+
+static QUEUE: Mutex<DeferredQueue> = Mutex::new(DeferredQueue {
+    items: [None; DEFERRED_QUEUE_SIZE],
+    head: 0,
+    len: 0,
+});
+
+/// Queues `work` to be called with `arg` by the next [`run_deferred`].
+///
+/// Safe to call from interrupt handlers. Returns `false` if the queue is full.
+pub fn defer(work: DeferredFn, arg: usize) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        if queue.len == DEFERRED_QUEUE_SIZE {
+            return false;
+        }
+        let slot = (queue.head + queue.len) % DEFERRED_QUEUE_SIZE;
+        queue.items[slot] = Some((work, arg));
+        queue.len += 1;
+        true
+    })
+}
+
+fn pop() -> Option<(DeferredFn, usize)> {
+    interrupts::without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        if queue.len == 0 {
+            return None;
+        }
+        let head = queue.head;
+        let item = queue.items[head].take();
+        queue.head = (head + 1) % DEFERRED_QUEUE_SIZE;
+        queue.len -= 1;
+        item
+    })
+}
+
+/// Returns the number of pending work items.
+pub fn pending() -> usize {
+    interrupts::without_interrupts(|| QUEUE.lock().len)
+}
+
+/// Runs all pending work items in FIFO order, including items queued while running.
+///
+/// Must not be called from an interrupt handler. Each item runs with interrupts enabled.
+/// Returns the number of items run.
+pub fn run_deferred() -> usize {
+    let mut count = 0;
+    while let Some((work, arg)) = pop() {
+        interrupts::enable();
+        work(arg);
+        count += 1;
+    }
+    count
+}
@@ -0,0 +1,174 @@
+//! # Exception Error Code Decoding
+//!
+//! Several CPU exceptions push an error code that says *why* they were raised. The raw value is hard to read in a crash log, so this module turns it into explicit text.
+//!
+//! ## Selector Error Codes
+//!
+//! General protection faults, invalid TSS, segment-not-present, and stack segment faults push a *selector error code* when a segment selector or IDT entry is involved:
+//! - **Bit 0 (EXT):** The exception happened while delivering an external event (an interrupt or an earlier exception).
+//! - **Bit 1 (IDT):** The index refers to the IDT.
+//! - **Bit 2 (TI):** If bit 1 is clear, the index refers to the LDT (set) or the GDT (clear).
+//! - **Bits 3-15 (index):** Index of the descriptor in that table.
+//!
+//! An error code of 0 means the fault is not related to a segment (for example a non-canonical address).
+//!
+//! ## Page Fault Error Codes
+//!
+//! [`PageFaultErrorCode`] flags describe the access (read, write, instruction fetch), the privilege level, and whether the page was missing or its protection was violated.
+//!
+//! ## Example
+//! ```ignore
+//! kprint!("[ERROR] {}\r\n", SelectorErrorCode::new(0x6b)); // IDT entry 13 (vector 0xd), external event
+//! kprint!("[ERROR] {}\r\n", PageFaultDescription::new(PageFaultErrorCode::CAUSED_BY_WRITE));
+//! ```
+
+use core::fmt;
+
+use x86_64::structures::idt::PageFaultErrorCode;
+
+/// Descriptor table referenced by a selector error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    /// Global Descriptor Table.
+    Gdt,
+    /// Interrupt Descriptor Table.
+    Idt,
+    /// Local Descriptor Table.
+    Ldt,
+}
+
+/// Decoded selector error code of a #GP, #TS, #NP, or #SS exception.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorErrorCode {
+    /// Raw error code.
+    pub raw: u64,
+    /// Whether the exception occurred while delivering an external event.
+    pub external: bool,
+    /// Table holding the offending descriptor.
+    pub table: DescriptorTable,
+    /// Index of the offending descriptor.
+    pub index: u16,
+}
+
+impl SelectorErrorCode {
+    /// Decodes a selector error code.
+    pub fn new(raw: u64) -> Self {
+        let table = if raw & 0b010 != 0 {
+            DescriptorTable::Idt
+        } else if raw & 0b100 != 0 {
+            DescriptorTable::Ldt
+        } else {
+            DescriptorTable::Gdt
+        };
+        SelectorErrorCode {
+            raw,
+            external: raw & 1 != 0,
+            table,
+            index: ((raw >> 3) & 0x1FFF) as u16,
+        }
+    }
+
+    /// Returns whether the error code refers to a descriptor at all (a zero code does not).
+    pub fn is_segment_related(&self) -> bool {
+        self.raw != 0
+    }
+}
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.is_segment_related() {
+            return write!(f, "not segment related (error code 0)");
+        }
+        match self.table {
+            DescriptorTable::Idt => {
+                write!(f, "IDT entry {} (vector {:#x})", self.index, self.index)?
+            }
+            DescriptorTable::Gdt => write!(
+                f,
+                "GDT entry {} (selector {:#x})",
+                self.index,
+                self.index << 3
+            )?,
+            DescriptorTable::Ldt => write!(
+                f,
+                "LDT entry {} (selector {:#x})",
+                self.index,
+                self.index << 3 | 0b100
+            )?,
+        }
+        if self.external {
+            write!(f, ", external event")?;
+        }
+        Ok(())
+    }
+}
+
+/// Human-readable description of a page fault error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageFaultDescription(pub PageFaultErrorCode);
+
+impl PageFaultDescription {
+    /// Wraps a page fault error code for display.
+    pub fn new(error_code: PageFaultErrorCode) -> Self {
+        PageFaultDescription(error_code)
+    }
+
+    /// Returns the kind of access that faulted: `"read"`, `"write"`, or `"instruction fetch"`.
+    pub fn access(&self) -> &'static str {
+        if self.0.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            "instruction fetch"
+        } else if self.0.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            "write"
+        } else {
+            "read"
+        }
+    }
+
+    /// Returns why the access faulted: `"page not present"` or `"protection violation"`.
+    pub fn reason(&self) -> &'static str {
+        if self.0.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+            "protection violation"
+        } else {
+            "page not present"
+        }
+    }
+
+    /// Returns the privilege level of the access: `"user"` or `"kernel"`.
+    pub fn mode(&self) -> &'static str {
+        if self.0.contains(PageFaultErrorCode::USER_MODE) {
+            "user"
+        } else {
+            "kernel"
+        }
+    }
+}
+
+impl fmt::Display for PageFaultDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({} mode)",
+            self.access(),
+            self.reason(),
+            self.mode()
+        )?;
+        let extra = [
+            (
+                PageFaultErrorCode::MALFORMED_TABLE,
+                "reserved bit set in a page table entry",
+            ),
+            (
+                PageFaultErrorCode::PROTECTION_KEY,
+                "protection key violation",
+            ),
+            (PageFaultErrorCode::SHADOW_STACK, "shadow stack access"),
+            (PageFaultErrorCode::SGX, "SGX access-control violation"),
+        ];
+        for (flag, text) in extra {
+            if self.0.contains(flag) {
+                write!(f, ", {text}")?;
+            }
+        }
+        Ok(())
+    }
+}
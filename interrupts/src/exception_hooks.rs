@@ -0,0 +1,221 @@
+//! # Exception Hooks
+//!
+//! The handlers in [`crate::cpu_exceptions`] log the exception and panic. Some exceptions are not errors for a kernel that expects them: a #UD can be emulated, a #PF can be resolved by demand paging, a #GP in user mode can be turned into a signal. This module lets the kernel register that behavior at runtime.
+//!
+//! ## Hook Kinds
+//!
+//! Each exception has three hook slots, see [`HookKind`]:
+//! - **Pre:** Runs first. If it returns `true` the exception is considered handled and the interrupted code resumes immediately.
+//! - **Replace:** Runs *instead of* the built-in recovery: the logging of breakpoints and debug exceptions, and the page fault hook. Other exceptions have no built-in recovery.
+//! - **Post:** Runs after the built-in recovery or the replacement, for example to record extra state before a panic.
+//!
+//! Every hook receives the [`ExceptionInfo`] of the interrupted code and returns whether execution should resume. Changes a hook makes to `rip`, `rflags`, or `rsp` (e.g. skipping an emulated instruction) are written back to the interrupt frame before resuming.
+//! If nothing resumes the exception, the handler reports it as fatal (crash dump and panic).
+//!
+//! Double faults and machine checks are aborts that cannot be resumed and do not run hooks.
+//!
+//! ## Example
+//! ```ignore
+//! fn emulate_ud2(info: &mut ExceptionInfo) -> bool {
+//!     // skip the 2-byte instruction and carry on
+//!     info.rip += 2;
+//!     true
+//! }
+//! exception_hooks::set_exception_hook(Exception::InvalidOpcode, HookKind::Pre, emulate_ud2);
+//! ```
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use x86_64::VirtAddr;
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::context::{ExceptionContext, SavedRegisters};
+
+/// Exceptions that support hooks, with their vector numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Exception {
+    /// #DE, division by zero or overflow.
+    DivideError = 0,
+    /// #DB, debug exception.
+    Debug = 1,
+    /// Non-maskable interrupt.
+    NonMaskableInterrupt = 2,
+    /// #BP, `int3`.
+    Breakpoint = 3,
+    /// #OF, `into`.
+    Overflow = 4,
+    /// #BR, `bound`.
+    BoundRangeExceeded = 5,
+    /// #UD, invalid opcode.
+    InvalidOpcode = 6,
+    /// #NM, FPU not available.
+    DeviceNotAvailable = 7,
+    /// #TS, invalid TSS.
+    InvalidTss = 10,
+    /// #NP, segment not present.
+    SegmentNotPresent = 11,
+    /// #SS, stack segment fault.
+    StackSegmentFault = 12,
+    /// #GP, general protection fault.
+    GeneralProtectionFault = 13,
+    /// #PF, page fault.
+    PageFault = 14,
+    /// #MF, x87 floating point exception.
+    X87FloatingPoint = 16,
+    /// #AC, alignment check.
+    AlignmentCheck = 17,
+    /// #XM, SIMD floating point exception.
+    SimdFloatingPoint = 19,
+    /// #VE, virtualization exception.
+    Virtualization = 20,
+}
+
+/// When a hook runs relative to the built-in handling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    /// Before the built-in recovery; returning `true` skips everything else.
+    Pre = 0,
+    /// Instead of the built-in recovery.
+    Replace = 1,
+    /// After the built-in recovery (or the replacement).
+    Post = 2,
+}
+
+/// State of the interrupted code passed to an [`ExceptionHook`].
+pub struct ExceptionInfo<'a> {
+    /// The exception being handled.
+    pub exception: Exception,
+    /// Error code pushed by the CPU, for exceptions that have one.
+    pub error_code: Option<u64>,
+    /// Instruction pointer; written back when the exception resumes.
+    pub rip: u64,
+    /// Code segment selector (its low two bits are the privilege level of the interrupted code).
+    pub cs: u64,
+    /// Flags register; written back when the exception resumes.
+    pub rflags: u64,
+    /// Stack pointer; written back when the exception resumes.
+    pub rsp: u64,
+    /// General-purpose registers, for exceptions entered through a register-saving stub (#GP and #PF).
+    pub registers: Option<&'a mut SavedRegisters>,
+}
+
+impl<'a> ExceptionInfo<'a> {
+    /// Returns whether the exception was raised in user mode (ring 3).
+    pub fn is_user_mode(&self) -> bool {
+        self.cs & 0b11 == 3
+    }
+
+    pub(crate) fn from_frame(
+        exception: Exception,
+        stack_frame: &InterruptStackFrame,
+        error_code: Option<u64>,
+    ) -> Self {
+        ExceptionInfo {
+            exception,
+            error_code,
+            rip: stack_frame.instruction_pointer.as_u64(),
+            cs: stack_frame.code_segment.0 as u64,
+            rflags: stack_frame.cpu_flags.bits(),
+            rsp: stack_frame.stack_pointer.as_u64(),
+            registers: None,
+        }
+    }
+
+    pub(crate) fn write_back_frame(&self, stack_frame: &mut InterruptStackFrame) {
+        // Safety: only the hook-visible fields are changed, with values supplied by the kernel.
+        unsafe {
+            stack_frame.as_mut().update(|frame| {
+                frame.instruction_pointer = VirtAddr::new_truncate(self.rip);
+                frame.cpu_flags = RFlags::from_bits_retain(self.rflags);
+                frame.stack_pointer = VirtAddr::new_truncate(self.rsp);
+            });
+        }
+    }
+}
+
+/// An exception hook. Returns `true` if the interrupted code should resume.
+pub type ExceptionHook = fn(info: &mut ExceptionInfo) -> bool;
+
+const VECTORS: usize = 32;
+const KINDS: usize = 3;
+
+static HOOKS: [[AtomicPtr<()>; KINDS]; VECTORS] =
+    [const { [const { AtomicPtr::new(core::ptr::null_mut()) }; KINDS] }; VECTORS];
+
+/// Installs `hook` in the `kind` slot of `exception`, replacing any previous one.
+pub fn set_exception_hook(exception: Exception, kind: HookKind, hook: ExceptionHook) {
+    HOOKS[exception as usize][kind as usize].store(hook as *mut (), Ordering::Release);
+}
+
+/// Removes the hook in the `kind` slot of `exception`.
+pub fn clear_exception_hook(exception: Exception, kind: HookKind) {
+    HOOKS[exception as usize][kind as usize].store(core::ptr::null_mut(), Ordering::Release);
+}
+
+fn exception_hook(exception: Exception, kind: HookKind) -> Option<ExceptionHook> {
+    let ptr = HOOKS[exception as usize][kind as usize].load(Ordering::Acquire);
+    // Safety: only `ExceptionHook` function pointers are ever stored.
+    (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), ExceptionHook>(ptr) })
+}
+
+/// Runs the hooks of `info.exception` around the built-in recovery `default`.
+///
+/// Returns `true` if the interrupted code should resume.
+fn run(info: &mut ExceptionInfo, default: impl FnOnce(&mut ExceptionInfo) -> bool) -> bool {
+    let exception = info.exception;
+    if let Some(pre) = exception_hook(exception, HookKind::Pre)
+        && pre(info)
+    {
+        return true;
+    }
+    let mut resume = match exception_hook(exception, HookKind::Replace) {
+        Some(replace) => replace(info),
+        None => default(info),
+    };
+    if let Some(post) = exception_hook(exception, HookKind::Post) {
+        resume |= post(info);
+    }
+    resume
+}
+
+/// Runs the hooks for an exception entered through an `x86-interrupt` handler, writing changes back to `stack_frame` when resuming.
+pub(crate) fn run_frame(
+    exception: Exception,
+    stack_frame: &mut InterruptStackFrame,
+    error_code: Option<u64>,
+    default: impl FnOnce(&mut ExceptionInfo) -> bool,
+) -> bool {
+    let mut info = ExceptionInfo::from_frame(exception, stack_frame, error_code);
+    let resume = run(&mut info, default);
+    if resume {
+        info.write_back_frame(stack_frame);
+    }
+    resume
+}
+
+/// Runs the hooks for an exception entered through a register-saving stub, writing changes back to `context` when resuming.
+pub(crate) fn run_context(
+    exception: Exception,
+    context: &mut ExceptionContext,
+    default: impl FnOnce(&mut ExceptionInfo) -> bool,
+) -> bool {
+    let mut info = ExceptionInfo {
+        exception,
+        error_code: Some(context.error_code),
+        rip: context.rip,
+        cs: context.cs,
+        rflags: context.rflags,
+        rsp: context.rsp,
+        registers: Some(&mut context.registers),
+    };
+    let resume = run(&mut info, default);
+    let (rip, rflags, rsp) = (info.rip, info.rflags, info.rsp);
+    if resume {
+        context.rip = rip;
+        context.rflags = rflags;
+        context.rsp = rsp;
+    }
+    resume
+}
@@ -0,0 +1,87 @@
+//! # User-Callable Interrupt Gates
+//!
+//! Every IDT entry has a *descriptor privilege level* (DPL). A software interrupt (`int n`, `int3`, `into`) executed in ring 3 only reaches its handler if the gate's DPL is 3; otherwise the CPU raises a general protection fault instead. Hardware interrupts and exceptions ignore the DPL, so the kernel's gates default to DPL 0.
+//!
+//! This module installs gates that user mode may invoke, for example a legacy `int 0x80` system call gate, and changes the privilege level of existing gates such as the breakpoint (`int3`).
+//!
+//! ## Example
+//! ```ignore
+//! extern "x86-interrupt" fn int80_handler(_frame: InterruptStackFrame) {
+//!     // dispatch the system call...
+//! }
+//! unsafe { gates::set_user_gate(0x80, int80_handler) }?;
+//! ```
+
+use x86_64::PrivilegeLevel;
+use x86_64::structures::idt::{HandlerFunc, InterruptDescriptorTable};
+
+use crate::apic::SPURIOUS_VECTOR;
+use crate::ipi::TLB_SHOOTDOWN_VECTOR;
+use crate::irq::{CLOCK_VECTOR, IRQ_BASE, IRQ_LINES};
+
+/// Errors returned by the gate helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GateError {
+    /// [`crate::init_idt`] has not been called yet.
+    NotInitialized,
+    /// The vector is used by an exception, IRQ line, IPI, or the APIC spurious interrupt.
+    ReservedVector,
+}
+
+/// Returns whether `vector` is owned by the kernel's exception or IRQ handling.
+fn is_reserved(vector: u8) -> bool {
+    // Exceptions occupy the vectors below IRQ_BASE, the IRQ lines follow directly
+    (vector as usize) < IRQ_BASE as usize + IRQ_LINES
+        || vector == CLOCK_VECTOR
+        || vector == TLB_SHOOTDOWN_VECTOR
+        || vector == SPURIOUS_VECTOR
+}
+
+/// Installs `handler` on `vector` with DPL 3 in `idt`, so `int vector` works from user mode.
+///
+/// Intended for building the IDT before it is loaded; see [`set_user_gate`] for the live IDT.
+pub fn install_user_gate(
+    idt: &mut InterruptDescriptorTable,
+    vector: u8,
+    handler: HandlerFunc,
+) -> Result<(), GateError> {
+    if is_reserved(vector) {
+        return Err(GateError::ReservedVector);
+    }
+    idt[vector]
+        .set_handler_fn(handler)
+        .set_privilege_level(PrivilegeLevel::Ring3);
+    Ok(())
+}
+
+/// Installs `handler` on `vector` with DPL 3 in the loaded IDT.
+///
+/// # Safety
+/// Must not race with other modifications of the IDT. The entry takes effect immediately.
+pub unsafe fn set_user_gate(vector: u8, handler: HandlerFunc) -> Result<(), GateError> {
+    unsafe { crate::with_idt(|idt| install_user_gate(idt, vector, handler)) }
+        .ok_or(GateError::NotInitialized)?
+}
+
+/// Changes the privilege level of the software-interrupt gate `vector` in the loaded IDT.
+///
+/// The gate must already have a handler. Besides the freely assignable vectors, the breakpoint (3) and overflow (4) exceptions are accepted, since they are raised by the `int3` and `into` instructions.
+///
+/// # Safety
+/// Must not race with other modifications of the IDT.
+pub unsafe fn set_gate_privilege(vector: u8, level: PrivilegeLevel) -> Result<(), GateError> {
+    unsafe {
+        crate::with_idt(|idt| {
+            // Re-installing the current handler resets the options, keeping the handler address.
+            let (address, entry) = match vector {
+                3 => (idt.breakpoint.handler_addr(), &mut idt.breakpoint),
+                4 => (idt.overflow.handler_addr(), &mut idt.overflow),
+                v if !is_reserved(v) => (idt[v].handler_addr(), &mut idt[v]),
+                _ => return Err(GateError::ReservedVector),
+            };
+            entry.set_handler_addr(address).set_privilege_level(level);
+            Ok(())
+        })
+    }
+    .ok_or(GateError::NotInitialized)?
+}
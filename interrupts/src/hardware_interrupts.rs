@@ -6,96 +6,165 @@
 //!
 //! Hardware interrupts (IRQs) are signals sent by external devices to the CPU, requesting immediate attention. Examples include timer ticks, keyboard presses, and disk I/O completions. The OS must register handlers for these events in the Interrupt Descriptor Table (IDT) to respond appropriately.
 //!
-//! This module provides a function to register hardware interrupt handlers in the IDT.
+//! This module provides a function to register hardware interrupt handlers in the IDT, along with the default timer and keyboard handlers. Handlers for other devices are installed at runtime through [`crate::irq`].
+//!
+//! ## Interrupt Controllers
+//!
+//! IRQ lines are first delivered by the legacy 8259 PICs, remapped by [`init_legacy_pic`], and move to the I/O APIC once the Local APIC is enabled and the kernel routes them with [`route_isa_irq_to_io_apic`]. Drivers do not need to know which one is active: [`set_irq_masked`] enables or disables a line, [`irq_vector`] gives its IDT vector, and the dispatcher sends the end-of-interrupt to the right controller. [`active_controller`] tells which one it is.
 
-use core::arch::asm;
+use core::sync::atomic::{AtomicU16, Ordering};
 
+use polished_acpi::Madt;
 use polished_serial_logging::kprint;
 use x86_64::structures::idt::InterruptStackFrame;
 
-pub fn setup_hardware_interrupts(idt: &mut x86_64::structures::idt::InterruptDescriptorTable) {
-    idt[32].set_handler_fn(timer_interrupt_handler);
-    idt[33].set_handler_fn(keyboard_interrupt_handler);
-    idt[44].set_handler_fn(mouse_interrupt_handler);
-    idt[46].set_handler_fn(disk_interrupt_handler);
-    idt[43].set_handler_fn(network_interrupt_handler);
-    idt[55].set_handler_fn(usb_interrupt_handler);
-    idt[47].set_handler_fn(other_hardware_interrupt_handler);
+use polished_x86_commands::pic8259;
+use polished_x86_commands::port::Port;
+
+use crate::{apic, ioapic, ipi, irq, keyboard, spurious, time, timer};
+
+/// Data port of the PS/2 controller, where the keyboard's scancodes arrive.
+const PS2_DATA_PORT: Port<u8> = Port::new(0x60);
+
+/// ISA lines 0-15 that are enabled, one bit per line, whichever controller delivers them.
+///
+/// Kept in software because [`crate::apic::init`] masks the whole PIC before the lines move to the I/O APIC.
+static ISA_ENABLED: AtomicU16 = AtomicU16::new(0);
+
+/// Interrupt controller delivering the IRQ lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptController {
+    /// The cascaded 8259 PICs (lines 0-15).
+    LegacyPic,
+    /// The I/O APIC(s), with the Local APIC receiving the interrupts.
+    IoApic,
 }
 
-fn send_eoi() {
-    unsafe {
-        asm!(
-            "mov al, 0x20",
-            "out 0x20, al",
-            options(nomem, nostack, preserves_flags)
-        );
+/// Returns the controller IRQ lines are delivered and acknowledged through.
+pub fn active_controller() -> InterruptController {
+    if apic::is_enabled() {
+        InterruptController::IoApic
+    } else {
+        InterruptController::LegacyPic
     }
 }
 
-pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    // kprint!("[INFO] INT 0x20: Timer interrupt\r\n"); // uncomment this if you want timer to scream at you
-    send_eoi();
+/// Returns the IDT vector IRQ line `irq` is delivered on by the active controller.
+///
+/// This is [`irq::IRQ_BASE`] + `irq`, except for the timer (line 0), which the I/O APIC delivers on [`irq::CLOCK_VECTOR`].
+pub fn irq_vector(irq: u8) -> u8 {
+    if irq == 0 && active_controller() == InterruptController::IoApic {
+        irq::CLOCK_VECTOR
+    } else {
+        irq::IRQ_BASE + irq
+    }
 }
 
-pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    let scancode: u8;
-    unsafe {
-        asm!(
-            "in al, 0x60",
-            out("al") scancode,
-            options(nomem, nostack, preserves_flags)
-        );
-    }
+/// Remaps the legacy PICs to deliver IRQ lines 0-15 on [`irq::IRQ_BASE`] and up, clear of the CPU exceptions, and masks every line except the timer (line 0) and the cascade. Drivers enable their lines with [`set_irq_masked`].
+pub fn init_legacy_pic() {
+    pic8259::remap(irq::IRQ_BASE, irq::IRQ_BASE + 8);
+    pic8259::set_masks(0xFFFF);
+    pic8259::unmask(2);
+    ISA_ENABLED.store(0, Ordering::Relaxed);
+    set_irq_masked(0, false);
+}
 
-    if scancode == 0xFA {
-        kprint!(
-            "[INFO] INT 0x21: Keyboard interrupt, received 0xFA (possible ACK, not a keypress)\r\n"
-        );
-    } else if scancode & 0x80 == 0 {
-        // Only handle key press (make) codes, ignore break codes
-        let converted = polished_scancodes::scancode_to_ascii(scancode);
-        match converted {
-            Some(ascii) if ascii.is_ascii_graphic() || ascii == b' ' => {
-                kprint!(
-                    "[INFO] INT 0x21: Keyboard interrupt, scancode: {:#x} | ASCII: '{}'\r\n",
-                    scancode,
-                    ascii as char
-                );
-            }
-            _ => {
-                kprint!(
-                    "[INFO] INT 0x21: Keyboard interrupt, scancode: {:#x} | ASCII: Unknown\r\n",
-                    scancode
-                );
+/// Masks or unmasks IRQ line `irq` at the active interrupt controller.
+///
+/// With the I/O APIC, ISA lines 0-15 use the pin [`route_isa_irq_to_io_apic`] routed them to, and other lines the pin with the same GSI. Returns `false` if the controller has no such line, e.g. for MSI lines, which are masked at the device.
+pub fn set_irq_masked(irq: u8, masked: bool) -> bool {
+    if irq < 16 {
+        if masked {
+            ISA_ENABLED.fetch_and(!(1 << irq), Ordering::Relaxed);
+        } else {
+            ISA_ENABLED.fetch_or(1 << irq, Ordering::Relaxed);
+        }
+    }
+    match active_controller() {
+        InterruptController::LegacyPic if irq < 16 => {
+            if masked {
+                pic8259::mask(irq);
+            } else {
+                pic8259::unmask(irq);
             }
+            true
         }
+        InterruptController::LegacyPic => false,
+        InterruptController::IoApic if irq < irq::MSI_FIRST_LINE => {
+            let gsi = ioapic::isa_irq_gsi(irq).unwrap_or(u32::from(irq));
+            ioapic::set_gsi_masked(gsi, masked)
+        }
+        InterruptController::IoApic => false,
     }
+}
 
-    send_eoi();
+/// Moves ISA IRQ `irq` from the legacy PIC to the I/O APIC, delivering it on [`irq_vector`] to the CPU with Local APIC ID `destination`.
+///
+/// The line stays enabled if a driver enabled it with [`set_irq_masked`] (or [`init_legacy_pic`] for the timer), so drivers set up on the PIC keep receiving interrupts, and it is masked at the PIC so it is not delivered twice. Call it after the Local APIC is enabled. Returns `false` if no I/O APIC serves the line.
+pub fn route_isa_irq_to_io_apic(madt: &Madt, irq: u8, destination: u8) -> bool {
+    let masked = ISA_ENABLED.load(Ordering::Relaxed) & (1 << irq) == 0;
+    if !ioapic::route_isa_irq(madt, irq, irq_vector(irq), destination) {
+        return false;
+    }
+    let gsi = ioapic::isa_irq_gsi(irq).unwrap_or(u32::from(irq));
+    ioapic::set_gsi_masked(gsi, masked);
+    pic8259::mask(irq);
+    true
 }
 
-pub extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    kprint!("[INFO] INT 0x2C: Mouse interrupt\r\n");
-    // TODO: Read mouse data, send EOI
+/// Registers the built-in IRQ handlers and the LAPIC spurious vector.
+///
+/// All IRQ vectors point at the dispatch stubs from [`crate::irq`]; the timer (IRQ 0) and keyboard (IRQ 1)
+/// handlers below are installed as the default handlers for their lines. Other drivers register theirs at runtime.
+pub fn setup_hardware_interrupts(idt: &mut x86_64::structures::idt::InterruptDescriptorTable) {
+    irq::setup_irq_stubs(idt);
+    let _ = irq::register_irq_handler(0, timer_interrupt_handler);
+    let _ = irq::register_irq_handler(1, keyboard_interrupt_handler);
+    idt[apic::SPURIOUS_VECTOR].set_handler_fn(apic_spurious_interrupt_handler);
+    ipi::setup_ipi_handlers(idt);
 }
 
-pub extern "x86-interrupt" fn disk_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    kprint!("[INFO] INT 0x2E: Disk controller interrupt\r\n");
-    // TODO: Handle disk I/O, send EOI
+/// Acknowledges IRQ line `irq` at the active interrupt controller.
+///
+/// Uses the Local APIC once it has been enabled, and the legacy PICs otherwise (both of them for slave IRQs 8-15).
+pub(crate) fn send_eoi(irq: u8) {
+    if apic::is_enabled() {
+        apic::eoi();
+    } else if irq < 16 {
+        pic8259::eoi(irq);
+    }
 }
 
-pub extern "x86-interrupt" fn network_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    kprint!("[INFO] INT 0x2B: Network card interrupt\r\n");
-    // TODO: Handle network I/O, send EOI
+/// Default handler for IRQ 0 (PIT or LAPIC timer).
+///
+/// Advances the tick counter behind [`crate::time::uptime_ms`] and checks the software timers of [`crate::timer`].
+pub fn timer_interrupt_handler(_irq: u8) {
+    time::tick();
+    timer::on_tick();
+    // kprint!("[INFO] INT 0x20: Timer interrupt\r\n"); // uncomment this if you want timer to scream at you
 }
 
-pub extern "x86-interrupt" fn usb_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    kprint!("[INFO] INT 0x37: USB controller interrupt\r\n");
-    // TODO: Handle USB I/O, send EOI
+/// Default handler for IRQ 1 (PS/2 keyboard).
+///
+/// Reads the scancode and passes it to [`crate::keyboard`], which queues decoded keys as input events; controller ACKs (0xFA) are ignored.
+///
+/// It works the same whichever controller delivers IRQ 1, as the dispatcher sends the end-of-interrupt.
+pub fn keyboard_interrupt_handler(_irq: u8) {
+    // Safety: IRQ 1 means the PS/2 controller has a byte from the keyboard.
+    let scancode = unsafe { PS2_DATA_PORT.read() };
+
+    if scancode == 0xFA {
+        kprint!(
+            "[INFO] IRQ 1: Keyboard interrupt, received 0xFA (possible ACK, not a keypress)\r\n"
+        );
+    } else {
+        keyboard::push_scancode(scancode);
+    }
 }
 
-pub extern "x86-interrupt" fn other_hardware_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    kprint!("[INFO] INT 0x2F: Other hardware device interrupt\r\n");
-    // TODO: Handle other hardware, send EOI
+/// Handler for the LAPIC spurious vector.
+///
+/// Spurious interrupts are not in service at the LAPIC, so they are only counted and no EOI is sent.
+pub extern "x86-interrupt" fn apic_spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    spurious::record_apic_spurious();
 }
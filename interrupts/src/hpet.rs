@@ -0,0 +1,234 @@
+//! # High Precision Event Timer (HPET)
+//!
+//! This module drives the HPET, a memory-mapped timer found on all modern x86 PCs. It provides a high-resolution monotonic counter and one-shot comparator interrupts, and is a better time source than the PIT.
+//!
+//! ## How the HPET Works
+//!
+//! - **Main counter:** A 64-bit (sometimes 32-bit) up-counter that increments at a fixed rate. The period of one tick is reported in femtoseconds (10^-15 s) by the capabilities register, and is at most 100 ns.
+//! - **Comparators:** Each HPET has 3 to 32 timers. A timer raises an interrupt when the main counter reaches its comparator value. Timers can be one-shot or (if supported) periodic.
+//! - **Routing:** Each timer reports which I/O APIC inputs (GSIs) it can be connected to. The caller selects one with [`route_timer`] and programs the I/O APIC pin for it.
+//!
+//! The register block's address comes from the ACPI `HPET` table. It is accessed at its physical address, relying on the identity mapping left by UEFI.
+//!
+//! ## Usage
+//!
+//! ```ignore
+//! let table = acpi_tables.hpet().unwrap();
+//! unsafe { hpet::init(table.base_address()) }?;
+//! let start = hpet::nanos();
+//! // ...
+//! let elapsed = hpet::nanos() - start;
+//! ```
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// General capabilities and ID register.
+const REG_CAPABILITIES: u64 = 0x000;
+/// General configuration register.
+const REG_CONFIG: u64 = 0x010;
+/// General interrupt status register.
+const REG_INTERRUPT_STATUS: u64 = 0x020;
+/// Main counter value register.
+const REG_MAIN_COUNTER: u64 = 0x0F0;
+
+/// General configuration: enable the main counter.
+const CONFIG_ENABLE: u64 = 1 << 0;
+/// General configuration: legacy replacement routing (timers 0/1 replace the PIT/RTC).
+const CONFIG_LEGACY_ROUTE: u64 = 1 << 1;
+
+/// Timer configuration: level-triggered interrupt.
+const TIMER_LEVEL_TRIGGERED: u64 = 1 << 1;
+/// Timer configuration: interrupt enable.
+const TIMER_INTERRUPT_ENABLE: u64 = 1 << 2;
+/// Timer configuration: periodic mode.
+const TIMER_PERIODIC: u64 = 1 << 3;
+/// Timer configuration: force 32-bit mode.
+const TIMER_32BIT: u64 = 1 << 8;
+/// Timer configuration: I/O APIC routing field shift (5 bits).
+const TIMER_ROUTE_SHIFT: u64 = 9;
+
+/// Femtoseconds per nanosecond.
+const FS_PER_NS: u64 = 1_000_000;
+
+/// Address of the HPET register block, or 0 if not initialized.
+static HPET_BASE: AtomicU64 = AtomicU64::new(0);
+/// Counter tick period in femtoseconds.
+static PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+
+/// Errors returned by the HPET driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpetError {
+    /// The address is null or the capabilities register reports an invalid period.
+    NotPresent,
+    /// [`init`] has not been called.
+    NotInitialized,
+    /// The timer index is larger than the number of comparators.
+    InvalidTimer,
+    /// The timer cannot be routed to the requested GSI.
+    InvalidRoute,
+    /// The register block could not be mapped.
+    Unmapped,
+}
+
+fn read(reg: u64) -> u64 {
+    let base = HPET_BASE.load(Ordering::Acquire);
+    unsafe { core::ptr::read_volatile((base + reg) as *const u64) }
+}
+
+fn write(reg: u64, value: u64) {
+    let base = HPET_BASE.load(Ordering::Acquire);
+    unsafe { core::ptr::write_volatile((base + reg) as *mut u64, value) }
+}
+
+const fn timer_config_reg(timer: u8) -> u64 {
+    0x100 + 0x20 * timer as u64
+}
+
+const fn timer_comparator_reg(timer: u8) -> u64 {
+    0x108 + 0x20 * timer as u64
+}
+
+/// Initializes the HPET whose register block lives at `base_address` and starts its main counter.
+///
+/// All comparators are left with their interrupts disabled.
+///
+/// The register block is mapped through [`crate::mmio::map`].
+///
+/// # Safety
+/// `base_address` must be the physical address from the ACPI `HPET` table.
+pub unsafe fn init(base_address: u64) -> Result<(), HpetError> {
+    if base_address == 0 {
+        return Err(HpetError::NotPresent);
+    }
+    let base_address = crate::mmio::map("hpet", base_address, 0x400).ok_or(HpetError::Unmapped)?;
+    let capabilities = unsafe { core::ptr::read_volatile(base_address as *const u64) };
+    let period = capabilities >> 32;
+    // The specification caps the period at 100 ns.
+    if period == 0 || period > 100 * FS_PER_NS {
+        return Err(HpetError::NotPresent);
+    }
+    HPET_BASE.store(base_address, Ordering::Release);
+    PERIOD_FS.store(period, Ordering::Release);
+
+    write(
+        REG_CONFIG,
+        read(REG_CONFIG) & !(CONFIG_ENABLE | CONFIG_LEGACY_ROUTE),
+    );
+    for timer in 0..timer_count() {
+        let config = read(timer_config_reg(timer));
+        write(
+            timer_config_reg(timer),
+            config & !(TIMER_INTERRUPT_ENABLE | TIMER_PERIODIC),
+        );
+    }
+    write(REG_MAIN_COUNTER, 0);
+    write(REG_CONFIG, read(REG_CONFIG) | CONFIG_ENABLE);
+    Ok(())
+}
+
+/// Returns whether [`init`] has succeeded.
+pub fn is_available() -> bool {
+    HPET_BASE.load(Ordering::Acquire) != 0
+}
+
+/// Returns the counter tick period in femtoseconds, or 0 if the HPET is not initialized.
+pub fn period_fs() -> u64 {
+    PERIOD_FS.load(Ordering::Acquire)
+}
+
+/// Returns the counter frequency in Hz, or 0 if the HPET is not initialized.
+pub fn frequency_hz() -> u64 {
+    match period_fs() {
+        0 => 0,
+        period => 1_000_000_000_000_000 / period,
+    }
+}
+
+/// Returns the number of comparators (timers).
+pub fn timer_count() -> u8 {
+    (((read(REG_CAPABILITIES) >> 8) & 0x1F) + 1) as u8
+}
+
+/// Returns the raw main counter value, or 0 if the HPET is not initialized.
+pub fn counter() -> u64 {
+    if !is_available() {
+        return 0;
+    }
+    read(REG_MAIN_COUNTER)
+}
+
+/// Converts a number of counter ticks to nanoseconds.
+pub fn ticks_to_nanos(ticks: u64) -> u64 {
+    (ticks as u128 * period_fs() as u128 / FS_PER_NS as u128) as u64
+}
+
+/// Converts nanoseconds to a number of counter ticks (rounded up).
+pub fn nanos_to_ticks(nanos: u64) -> u64 {
+    match period_fs() {
+        0 => 0,
+        period => (nanos as u128 * FS_PER_NS as u128).div_ceil(period as u128) as u64,
+    }
+}
+
+/// Returns the time since [`init`] in nanoseconds (monotonic).
+pub fn nanos() -> u64 {
+    ticks_to_nanos(counter())
+}
+
+/// Returns the bitmask of GSIs that `timer` can be routed to.
+pub fn route_capabilities(timer: u8) -> Result<u32, HpetError> {
+    check_timer(timer)?;
+    Ok((read(timer_config_reg(timer)) >> 32) as u32)
+}
+
+/// Connects `timer` to I/O APIC input `gsi` (edge-triggered).
+///
+/// The caller is responsible for programming the I/O APIC pin for `gsi`.
+pub fn route_timer(timer: u8, gsi: u8) -> Result<(), HpetError> {
+    if gsi >= 32 || route_capabilities(timer)? & (1 << gsi) == 0 {
+        return Err(HpetError::InvalidRoute);
+    }
+    let reg = timer_config_reg(timer);
+    let config = read(reg) & !(0x1F << TIMER_ROUTE_SHIFT) & !TIMER_LEVEL_TRIGGERED;
+    write(reg, config | (gsi as u64) << TIMER_ROUTE_SHIFT);
+    Ok(())
+}
+
+/// Arms `timer` to fire a single interrupt `delay_ns` nanoseconds from now.
+///
+/// The timer must have been connected with [`route_timer`].
+pub fn set_oneshot(timer: u8, delay_ns: u64) -> Result<(), HpetError> {
+    check_timer(timer)?;
+    let reg = timer_config_reg(timer);
+    let config = read(reg) & !(TIMER_PERIODIC | TIMER_32BIT);
+    write(reg, config & !TIMER_INTERRUPT_ENABLE);
+    let deadline = counter().wrapping_add(nanos_to_ticks(delay_ns).max(1));
+    write(timer_comparator_reg(timer), deadline);
+    write(reg, config | TIMER_INTERRUPT_ENABLE);
+    Ok(())
+}
+
+/// Disables the interrupt of `timer`.
+pub fn stop_timer(timer: u8) -> Result<(), HpetError> {
+    check_timer(timer)?;
+    let reg = timer_config_reg(timer);
+    write(reg, read(reg) & !(TIMER_INTERRUPT_ENABLE | TIMER_PERIODIC));
+    Ok(())
+}
+
+/// Clears the interrupt status bit of `timer` (only needed for level-triggered timers).
+pub fn acknowledge(timer: u8) {
+    if is_available() && timer < 32 {
+        write(REG_INTERRUPT_STATUS, 1 << timer);
+    }
+}
+
+fn check_timer(timer: u8) -> Result<(), HpetError> {
+    if !is_available() {
+        Err(HpetError::NotInitialized)
+    } else if timer >= timer_count() {
+        Err(HpetError::InvalidTimer)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+//! # I/O APIC Configuration and IRQ Routing
+//!
+//! This module programs the I/O APIC(s) described by the ACPI MADT, so that device interrupts can be delivered to a chosen vector on a chosen CPU.
+//!
+//! ## What is the I/O APIC?
+//!
+//! The I/O APIC collects interrupt lines from devices and forwards them as messages to the Local APICs. Each input pin is identified by a *Global System Interrupt* (GSI) number and has a 64-bit *redirection entry* that selects the vector, delivery mode, polarity, trigger mode, mask bit, and destination CPU. A system can have several I/O APICs, each serving a contiguous range of GSIs starting at its `gsi_base`.
+//!
+//! ## Register Access
+//!
+//! The I/O APIC exposes only two memory-mapped registers: `IOREGSEL` (offset 0x00) selects an internal register and `IOWIN` (offset 0x10) reads or writes it. Redirection entry *n* occupies internal registers `0x10 + 2n` (low half) and `0x11 + 2n` (high half).
+//!
+//! A select followed by a window access must not be interleaved with another one, or one of them reaches the wrong register. Every pair therefore runs under one lock with interrupts disabled, so IRQ handlers (which mask and unmask lines) and other CPUs can program the I/O APIC at any time. [`route_gsi`] and [`set_gsi_masked`] also keep the table of I/O APICs locked across their read-modify-write of a redirection entry.
+//!
+//! ## Usage
+//!
+//! ```ignore
+//! let madt = acpi_tables.madt().unwrap();
+//! unsafe { ioapic::init_from_madt(&madt) };
+//! ioapic::route_isa_irq(&madt, 1, 33, apic::id() as u8); // keyboard -> vector 33 on this CPU
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use polished_acpi::{Madt, MadtEntry};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// Internal register: I/O APIC ID.
+const IOAPIC_ID: u32 = 0x00;
+/// Internal register: version and maximum redirection entry.
+const IOAPIC_VERSION: u32 = 0x01;
+/// Internal register: first redirection table entry.
+const IOAPIC_REDTBL: u32 = 0x10;
+
+/// Maximum number of I/O APICs tracked.
+pub const MAX_IO_APICS: usize = 8;
+
+/// Serializes `IOREGSEL`/`IOWIN` pairs on every I/O APIC.
+static REGISTER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Interrupt delivery mode of a redirection entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryMode {
+    /// Deliver to the destination CPU(s) at the given vector.
+    Fixed,
+    /// Deliver to the CPU with the lowest priority among the destinations.
+    LowestPriority,
+    /// System management interrupt.
+    Smi,
+    /// Non-maskable interrupt (vector ignored).
+    Nmi,
+    /// INIT signal.
+    Init,
+    /// External (8259-compatible) interrupt.
+    ExtInt,
+}
+
+impl DeliveryMode {
+    fn bits(&self) -> u64 {
+        match self {
+            DeliveryMode::Fixed => 0b000,
+            DeliveryMode::LowestPriority => 0b001,
+            DeliveryMode::Smi => 0b010,
+            DeliveryMode::Nmi => 0b100,
+            DeliveryMode::Init => 0b101,
+            DeliveryMode::ExtInt => 0b111,
+        }
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        match bits & 0b111 {
+            0b001 => DeliveryMode::LowestPriority,
+            0b010 => DeliveryMode::Smi,
+            0b100 => DeliveryMode::Nmi,
+            0b101 => DeliveryMode::Init,
+            0b111 => DeliveryMode::ExtInt,
+            _ => DeliveryMode::Fixed,
+        }
+    }
+}
+
+/// Decoded I/O APIC redirection table entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectionEntry {
+    /// IDT vector to raise.
+    pub vector: u8,
+    /// How the interrupt is delivered.
+    pub delivery_mode: DeliveryMode,
+    /// `true` for logical destination mode, `false` for physical (APIC ID).
+    pub logical_destination: bool,
+    /// `true` if the input pin is active-low.
+    pub active_low: bool,
+    /// `true` if the input pin is level-triggered.
+    pub level_triggered: bool,
+    /// `true` if the pin is masked.
+    pub masked: bool,
+    /// Destination APIC ID (physical mode) or logical set.
+    pub destination: u8,
+}
+
+impl RedirectionEntry {
+    /// Creates an unmasked, edge-triggered, active-high fixed-delivery entry targeting one CPU.
+    pub fn new(vector: u8, destination: u8) -> Self {
+        RedirectionEntry {
+            vector,
+            delivery_mode: DeliveryMode::Fixed,
+            logical_destination: false,
+            active_low: false,
+            level_triggered: false,
+            masked: false,
+            destination,
+        }
+    }
+
+    /// Decodes a raw 64-bit redirection entry.
+    pub fn from_raw(raw: u64) -> Self {
+        RedirectionEntry {
+            vector: (raw & 0xFF) as u8,
+            delivery_mode: DeliveryMode::from_bits(raw >> 8),
+            logical_destination: raw & (1 << 11) != 0,
+            active_low: raw & (1 << 13) != 0,
+            level_triggered: raw & (1 << 15) != 0,
+            masked: raw & (1 << 16) != 0,
+            destination: (raw >> 56) as u8,
+        }
+    }
+
+    /// Encodes the entry as a raw 64-bit value.
+    pub fn to_raw(&self) -> u64 {
+        self.vector as u64
+            | self.delivery_mode.bits() << 8
+            | (self.logical_destination as u64) << 11
+            | (self.active_low as u64) << 13
+            | (self.level_triggered as u64) << 15
+            | (self.masked as u64) << 16
+            | (self.destination as u64) << 56
+    }
+}
+
+/// A single I/O APIC.
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    base: u64,
+    gsi_base: u32,
+}
+
+impl IoApic {
+    /// Creates a handle for the I/O APIC whose registers live at `base`.
+    ///
+    /// # Safety
+    /// `base` must be the mapped address of a real I/O APIC (see [`crate::mmio::map`]).
+    pub unsafe fn new(base: u64, gsi_base: u32) -> Self {
+        IoApic { base, gsi_base }
+    }
+
+    fn read(&self, reg: u32) -> u32 {
+        interrupts::without_interrupts(|| {
+            let _registers = REGISTER_LOCK.lock();
+            unsafe {
+                core::ptr::write_volatile(self.base as *mut u32, reg);
+                core::ptr::read_volatile((self.base + 0x10) as *const u32)
+            }
+        })
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        interrupts::without_interrupts(|| {
+            let _registers = REGISTER_LOCK.lock();
+            unsafe {
+                core::ptr::write_volatile(self.base as *mut u32, reg);
+                core::ptr::write_volatile((self.base + 0x10) as *mut u32, value);
+            }
+        })
+    }
+
+    /// Returns the I/O APIC ID.
+    pub fn id(&self) -> u8 {
+        ((self.read(IOAPIC_ID) >> 24) & 0x0F) as u8
+    }
+
+    /// Returns the I/O APIC version.
+    pub fn version(&self) -> u8 {
+        (self.read(IOAPIC_VERSION) & 0xFF) as u8
+    }
+
+    /// Returns the number of redirection entries (input pins).
+    pub fn redirection_entries(&self) -> u32 {
+        ((self.read(IOAPIC_VERSION) >> 16) & 0xFF) + 1
+    }
+
+    /// Returns the first GSI served by this I/O APIC.
+    pub fn gsi_base(&self) -> u32 {
+        self.gsi_base
+    }
+
+    /// Returns whether `gsi` is served by this I/O APIC.
+    pub fn handles_gsi(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.redirection_entries()
+    }
+
+    /// Reads redirection entry `index`.
+    pub fn read_redirection(&self, index: u32) -> RedirectionEntry {
+        let low = self.read(IOAPIC_REDTBL + index * 2) as u64;
+        let high = self.read(IOAPIC_REDTBL + index * 2 + 1) as u64;
+        RedirectionEntry::from_raw(low | high << 32)
+    }
+
+    /// Writes redirection entry `index`.
+    pub fn write_redirection(&self, index: u32, entry: RedirectionEntry) {
+        let raw = entry.to_raw();
+        // Mask the pin while the two halves are inconsistent
+        self.write(IOAPIC_REDTBL + index * 2, (1 << 16) as u32);
+        self.write(IOAPIC_REDTBL + index * 2 + 1, (raw >> 32) as u32);
+        self.write(IOAPIC_REDTBL + index * 2, raw as u32);
+    }
+
+    /// Masks every input pin.
+    pub fn mask_all(&self) {
+        for index in 0..self.redirection_entries() {
+            let mut entry = self.read_redirection(index);
+            entry.masked = true;
+            self.write_redirection(index, entry);
+        }
+    }
+}
+
+/// GSI each ISA IRQ was routed to by [`route_isa_irq`], `u32::MAX` if it was not.
+static ISA_ROUTES: [AtomicU32; 16] = [const { AtomicU32::new(u32::MAX) }; 16];
+
+/// I/O APICs registered by [`init_from_madt`].
+static IO_APICS: Mutex<[Option<IoApic>; MAX_IO_APICS]> = Mutex::new([None; MAX_IO_APICS]);
+
+/// Runs `f` on the registered I/O APIC serving `gsi`, with the table locked and interrupts disabled.
+fn with_io_apic_for_gsi<R>(gsi: u32, f: impl FnOnce(&IoApic) -> R) -> Option<R> {
+    interrupts::without_interrupts(|| {
+        IO_APICS
+            .lock()
+            .iter()
+            .flatten()
+            .find(|io_apic| io_apic.handles_gsi(gsi))
+            .map(f)
+    })
+}
+
+/// Registers every I/O APIC listed in the MADT and masks all of their pins.
+///
+/// Register blocks are mapped through [`crate::mmio::map`]; I/O APICs whose block cannot be mapped are skipped.
+///
+/// # Safety
+/// The MADT must describe the running machine.
+pub unsafe fn init_from_madt(madt: &Madt) {
+    let mut io_apics = [None; MAX_IO_APICS];
+    let mut slot = 0;
+    for entry in madt.entries() {
+        if let MadtEntry::IoApic {
+            address, gsi_base, ..
+        } = entry
+        {
+            if slot == MAX_IO_APICS {
+                break;
+            }
+            let Some(base) = crate::mmio::map("io apic", address as u64, 0x20) else {
+                continue;
+            };
+            let io_apic = unsafe { IoApic::new(base, gsi_base) };
+            io_apic.mask_all();
+            io_apics[slot] = Some(io_apic);
+            slot += 1;
+        }
+    }
+    interrupts::without_interrupts(|| *IO_APICS.lock() = io_apics);
+}
+
+/// Returns the registered I/O APIC serving `gsi`, if any.
+pub fn io_apic_for_gsi(gsi: u32) -> Option<IoApic> {
+    with_io_apic_for_gsi(gsi, |io_apic| *io_apic)
+}
+
+/// Programs the redirection entry for `gsi`.
+///
+/// Returns `false` if no registered I/O APIC serves `gsi`.
+pub fn route_gsi(gsi: u32, entry: RedirectionEntry) -> bool {
+    with_io_apic_for_gsi(gsi, |io_apic| {
+        io_apic.write_redirection(gsi - io_apic.gsi_base(), entry);
+    })
+    .is_some()
+}
+
+/// Routes a legacy ISA IRQ (0-15) to `vector` on the CPU with Local APIC ID `destination`.
+///
+/// Interrupt source overrides from the MADT are applied, so e.g. the PIT (IRQ 0) is programmed on GSI 2 when the firmware says so.
+/// Returns `false` if no registered I/O APIC serves the resulting GSI.
+pub fn route_isa_irq(madt: &Madt, irq: u8, vector: u8, destination: u8) -> bool {
+    let isa = madt.isa_irq(irq);
+    let mut entry = RedirectionEntry::new(vector, destination);
+    entry.active_low = isa.active_low;
+    entry.level_triggered = isa.level_triggered;
+    let routed = route_gsi(isa.gsi, entry);
+    if routed && let Some(route) = ISA_ROUTES.get(irq as usize) {
+        route.store(isa.gsi, Ordering::Relaxed);
+    }
+    routed
+}
+
+/// Returns the GSI that ISA IRQ `irq` was routed to with [`route_isa_irq`], if it was.
+pub fn isa_irq_gsi(irq: u8) -> Option<u32> {
+    let gsi = ISA_ROUTES.get(irq as usize)?.load(Ordering::Relaxed);
+    (gsi != u32::MAX).then_some(gsi)
+}
+
+/// Masks or unmasks the pin for `gsi`, leaving the rest of its entry untouched.
+///
+/// Returns `false` if no registered I/O APIC serves `gsi`.
+pub fn set_gsi_masked(gsi: u32, masked: bool) -> bool {
+    with_io_apic_for_gsi(gsi, |io_apic| {
+        let index = gsi - io_apic.gsi_base();
+        let mut entry = io_apic.read_redirection(index);
+        entry.masked = masked;
+        io_apic.write_redirection(index, entry);
+    })
+    .is_some()
+}
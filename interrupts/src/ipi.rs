@@ -0,0 +1,163 @@
+//! # Inter-Processor Interrupts
+//!
+//! CPUs signal each other through their Local APICs by writing the Interrupt Command Register (ICR). This module wraps the ICR in typed APIs for the IPIs an SMP kernel needs:
+//! - **Fixed:** Raises an ordinary vector on the target CPU(s), e.g. the [TLB shootdown](request_tlb_shootdown) or a reschedule.
+//! - **NMI:** Delivers a non-maskable interrupt, e.g. to stop all CPUs on panic.
+//! - **INIT / SIPI:** The INIT-SIPI-SIPI sequence wakes application processors (APs) and starts them in real mode at a page-aligned address below 1 MiB.
+//!
+//! ## TLB Shootdown
+//!
+//! A CPU only invalidates its own TLB. After changing a mapping that other CPUs may have cached, [`request_tlb_shootdown`] flushes the local TLB and sends [`TLB_SHOOTDOWN_VECTOR`] to every other CPU, whose handler flushes theirs.
+//!
+//! All APIs require the Local APIC to be enabled ([`crate::apic::init`]).
+//!
+//! ## Example
+//! ```ignore
+//! ipi::send_fixed(Destination::AllExcludingSelf, RESCHEDULE_VECTOR)?;
+//! ipi::send_init(ap_apic_id)?;
+//! time::sleep_busy(10);
+//! ipi::send_startup(ap_apic_id, 0x8)?; // start at 0x8000
+//! ```
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use polished_serial_logging::kprint;
+use x86_64::VirtAddr;
+use x86_64::instructions::tlb;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+use crate::{apic, percpu};
+
+/// Vector of the TLB shootdown IPI.
+pub const TLB_SHOOTDOWN_VECTOR: u8 = 0xFD;
+
+/// ICR delivery mode: fixed vector.
+const DELIVERY_FIXED: u32 = 0b000 << 8;
+/// ICR delivery mode: NMI.
+const DELIVERY_NMI: u32 = 0b100 << 8;
+/// ICR delivery mode: INIT.
+const DELIVERY_INIT: u32 = 0b101 << 8;
+/// ICR delivery mode: start-up.
+const DELIVERY_STARTUP: u32 = 0b110 << 8;
+/// ICR level bit: assert.
+const LEVEL_ASSERT: u32 = 1 << 14;
+
+/// Address flushed by the last shootdown, or `u64::MAX` for a full flush.
+static SHOOTDOWN_ADDRESS: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Errors returned by the IPI APIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpiError {
+    /// The Local APIC has not been enabled.
+    ApicDisabled,
+}
+
+/// Target of an IPI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    /// The CPU with this Local APIC ID.
+    Cpu(u32),
+    /// The sending CPU.
+    SelfOnly,
+    /// Every CPU, including the sender.
+    AllIncludingSelf,
+    /// Every CPU except the sender.
+    AllExcludingSelf,
+}
+
+impl Destination {
+    /// Returns the APIC ID and the ICR destination shorthand bits.
+    fn encode(self) -> (u32, u32) {
+        match self {
+            Destination::Cpu(id) => (id, 0b00 << 18),
+            Destination::SelfOnly => (0, 0b01 << 18),
+            Destination::AllIncludingSelf => (0, 0b10 << 18),
+            Destination::AllExcludingSelf => (0, 0b11 << 18),
+        }
+    }
+}
+
+fn send(destination: Destination, command: u32) -> Result<(), IpiError> {
+    if !apic::is_enabled() {
+        return Err(IpiError::ApicDisabled);
+    }
+    let (id, shorthand) = destination.encode();
+    // Safety: the APIC is enabled and the callers build valid ICR values.
+    unsafe { apic::write_icr(id, command | shorthand) };
+    Ok(())
+}
+
+/// Raises `vector` on the `destination` CPU(s). Their handler must call [`crate::apic::eoi`].
+pub fn send_fixed(destination: Destination, vector: u8) -> Result<(), IpiError> {
+    send(destination, DELIVERY_FIXED | LEVEL_ASSERT | vector as u32)
+}
+
+/// Sends a non-maskable interrupt to the `destination` CPU(s).
+pub fn send_nmi(destination: Destination) -> Result<(), IpiError> {
+    send(destination, DELIVERY_NMI | LEVEL_ASSERT)
+}
+
+/// Sends an INIT IPI, resetting the CPU with APIC ID `apic_id` into the wait-for-SIPI state.
+pub fn send_init(apic_id: u32) -> Result<(), IpiError> {
+    send(Destination::Cpu(apic_id), DELIVERY_INIT | LEVEL_ASSERT)
+}
+
+/// Sends a start-up IPI: the CPU with APIC ID `apic_id` starts executing in real mode at physical address `page * 0x1000`.
+///
+/// The INIT-SIPI-SIPI sequence sends this twice, about 200 µs apart, after [`send_init`] and a 10 ms delay.
+pub fn send_startup(apic_id: u32, page: u8) -> Result<(), IpiError> {
+    send(
+        Destination::Cpu(apic_id),
+        DELIVERY_STARTUP | LEVEL_ASSERT | page as u32,
+    )
+}
+
+/// Invalidates `address` (or the whole TLB, for `None`) on this CPU and on every other CPU.
+///
+/// Falls back to a local flush when the APIC is not enabled, since no other CPU is running then.
+pub fn request_tlb_shootdown(address: Option<VirtAddr>) {
+    SHOOTDOWN_ADDRESS.store(
+        address.map_or(u64::MAX, VirtAddr::as_u64),
+        Ordering::Release,
+    );
+    flush_local();
+    let _ = send_fixed(Destination::AllExcludingSelf, TLB_SHOOTDOWN_VECTOR);
+}
+
+fn flush_local() {
+    match SHOOTDOWN_ADDRESS.load(Ordering::Acquire) {
+        u64::MAX => tlb::flush_all(),
+        address => tlb::flush(VirtAddr::new_truncate(address)),
+    }
+    percpu::current().record_tlb_shootdown();
+}
+
+extern "x86-interrupt" fn tlb_shootdown_handler(_stack_frame: InterruptStackFrame) {
+    percpu::current().record_ipi();
+    flush_local();
+    apic::eoi();
+}
+
+/// Installs the IPI handlers in `idt`.
+pub fn setup_ipi_handlers(idt: &mut InterruptDescriptorTable) {
+    idt[TLB_SHOOTDOWN_VECTOR].set_handler_fn(tlb_shootdown_handler);
+}
+
+/// Prints the interrupt counters of every CPU that has taken an interrupt.
+pub fn dump_cpu_counters() {
+    for index in 0..percpu::MAX_CPUS {
+        let Some(state) = percpu::cpu(index) else {
+            break;
+        };
+        if state.irqs() + state.ipis() + state.tlb_shootdowns() == 0 {
+            continue;
+        }
+        kprint!(
+            "[INFO] CPU {}: {} IRQs, {} IPIs, {} TLB shootdowns\r\n",
+            index,
+            state.irqs(),
+            state.ipis(),
+            state.tlb_shootdowns()
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! # Runtime IRQ Handler Registration
+//!
+//! This module owns the IDT entries for hardware IRQ lines and dispatches each interrupt to a handler registered at runtime, so drivers living in other crates (virtio-blk, network cards, the PS/2 mouse, ...) can install their handlers without editing the interrupts crate.
+//!
+//! ## How Dispatch Works
+//!
+//! - IRQ line *n* (0 to [`IRQ_LINES`] - 1) is delivered on IDT vector [`IRQ_BASE`] + *n*, by the remapped legacy PIC (lines 0-15), by the I/O APIC routes set up by the kernel (lines 0-23), and by message-signaled interrupts (lines [`MSI_FIRST_LINE`] and up).
+//! - Every one of these vectors gets a tiny `x86-interrupt` stub, generated by a macro, that calls a common dispatcher with its line number. The timer (line 0) instead gets a stub saving every register (see [`crate::context::InterruptContext`]), so its return to user mode can be redirected.
+//! - The dispatcher looks up the handler registered for that line in a table of atomics (so registering from normal code can never deadlock against an interrupt), calls it, and then sends the end-of-interrupt (EOI) to the active interrupt controller.
+//! - Lines without a handler are logged and acknowledged.
+//! - Spurious IRQ 7/15 from the legacy PIC are filtered out before dispatch (see [`crate::spurious`]).
+//! - Timer interrupts (line 0) hand the interrupted RIP to the sampling profiler (see [`crate::profiler`]) before dispatch.
+//!
+//! Handlers run with interrupts disabled and must **not** send an EOI themselves.
+//!
+//! ## Shared Lines
+//!
+//! Legacy PCI interrupt lines are level-triggered and often wired to several devices. Such drivers register with [`register_shared_irq_handler`] instead: up to [`MAX_SHARED_HANDLERS`] handlers per line are chained, and each one reports whether its device actually raised the interrupt. All of them are called, since several devices may assert the line at once; interrupts that no handler claims are counted (see [`unclaimed_count`]). A line is either exclusive or shared, never both.
+//!
+//! ## Nesting
+//!
+//! Slow handlers can be marked with [`set_nestable`]; they then run with interrupts enabled so that higher-priority interrupts (most importantly the timer) are still serviced. The current nesting depth is available through [`nesting_depth`]; it is tracked per CPU in [`crate::percpu`], along with the number of IRQs each CPU dispatched.
+//!
+//! ## Returning to User Mode
+//!
+//! When a timer interrupt is about to return to user mode, the hook installed with [`set_user_return_hook`] gets the interrupted context after the EOI. The syscalls crate uses it to deliver signals to a task that never makes a syscall.
+//!
+//! ## Example
+//! ```ignore
+//! fn mouse_irq(_irq: u8) {
+//!     // read the mouse packet byte from port 0x60
+//! }
+//! polished_interrupts::irq::register_irq_handler(12, mouse_irq).unwrap();
+//!
+//! fn nic_irq(_irq: u8) -> bool {
+//!     // read and clear the NIC's interrupt status; false if it was not set
+//!     true
+//! }
+//! polished_interrupts::irq::register_shared_irq_handler(11, nic_irq).unwrap();
+//! ```
+
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+use polished_serial_logging::kprint;
+use x86_64::VirtAddr;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+use crate::context::InterruptContext;
+use crate::interrupt_entry;
+
+/// IDT vector of IRQ line 0.
+pub const IRQ_BASE: u8 = 32;
+
+/// Number of IRQ lines with dispatch stubs.
+///
+/// Lines below [`MSI_FIRST_LINE`] cover the pins of a standard I/O APIC; the rest are handed out to message-signaled interrupts by [`crate::msi`].
+pub const IRQ_LINES: usize = 48;
+
+/// First IRQ line reserved for MSI/MSI-X vectors.
+pub const MSI_FIRST_LINE: u8 = 24;
+
+/// High-priority vector that also dispatches IRQ line 0 (the system timer).
+///
+/// The Local APIC prioritizes interrupts by vector (`vector >> 4`), so the timer is delivered on this vector
+/// when the APIC is in use; otherwise it would sit in the lowest class and could not preempt nested handlers.
+pub const CLOCK_VECTOR: u8 = 0xF0;
+
+/// A hardware IRQ handler, called with the IRQ line number.
+pub type IrqHandler = fn(irq: u8);
+
+/// A handler on a shared IRQ line, called with the IRQ line number. Returns `true` if its device raised the interrupt.
+pub type SharedIrqHandler = fn(irq: u8) -> bool;
+
+/// Maximum number of handlers chained on one shared IRQ line.
+pub const MAX_SHARED_HANDLERS: usize = 4;
+
+/// Errors returned by [`register_irq_handler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The IRQ line is outside `0..IRQ_LINES`.
+    InvalidIrq,
+    /// Another handler is already installed on this line (or, for shared handlers, the line is exclusive).
+    AlreadyRegistered,
+    /// The shared line already has [`MAX_SHARED_HANDLERS`] handlers.
+    TooManyHandlers,
+}
+
+static HANDLERS: [AtomicPtr<()>; IRQ_LINES] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; IRQ_LINES];
+
+static SHARED_HANDLERS: [[AtomicPtr<()>; MAX_SHARED_HANDLERS]; IRQ_LINES] =
+    [const { [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_SHARED_HANDLERS] }; IRQ_LINES];
+
+/// Interrupts on shared lines that no handler claimed, per line.
+static UNCLAIMED: [AtomicU64; IRQ_LINES] = [const { AtomicU64::new(0) }; IRQ_LINES];
+
+/// Bitmask of lines whose handlers run with interrupts enabled.
+static NESTABLE: AtomicU64 = AtomicU64::new(0);
+
+/// Hook run on the interrupted user context before a timer interrupt returns to user mode.
+pub type UserReturnHook = fn(context: &mut InterruptContext);
+
+static USER_RETURN_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Installs the hook run before a timer interrupt returns to user mode, with interrupts disabled and the EOI already sent.
+///
+/// The hook may modify the context (the registers are restored from it), or never return, e.g. to terminate the task.
+pub fn set_user_return_hook(hook: UserReturnHook) {
+    USER_RETURN_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Installs `handler` for IRQ line `irq`.
+///
+/// # Errors
+/// Returns [`IrqError::InvalidIrq`] for lines without a dispatch stub and [`IrqError::AlreadyRegistered`] if the line is taken, including by shared handlers.
+pub fn register_irq_handler(irq: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    let slot = HANDLERS.get(irq as usize).ok_or(IrqError::InvalidIrq)?;
+    if is_shared(irq) {
+        return Err(IrqError::AlreadyRegistered);
+    }
+    slot.compare_exchange(
+        core::ptr::null_mut(),
+        handler as *mut (),
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    )
+    .map(|_| ())
+    .map_err(|_| IrqError::AlreadyRegistered)
+}
+
+/// Removes the handler for IRQ line `irq`, returning it if one was installed.
+pub fn unregister_irq_handler(irq: u8) -> Option<IrqHandler> {
+    let slot = HANDLERS.get(irq as usize)?;
+    let ptr = slot.swap(core::ptr::null_mut(), Ordering::AcqRel);
+    // Safety: only `IrqHandler` function pointers are ever stored in the table.
+    (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), IrqHandler>(ptr) })
+}
+
+/// Returns the handler registered for IRQ line `irq`, if any.
+pub fn irq_handler(irq: u8) -> Option<IrqHandler> {
+    let ptr = HANDLERS.get(irq as usize)?.load(Ordering::Acquire);
+    // Safety: only `IrqHandler` function pointers are ever stored in the table.
+    (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), IrqHandler>(ptr) })
+}
+
+/// Adds `handler` to the chain of handlers for the shared IRQ line `irq`.
+///
+/// # Errors
+/// Returns [`IrqError::InvalidIrq`] for lines without a dispatch stub, [`IrqError::AlreadyRegistered`] if an exclusive handler owns the line,
+/// and [`IrqError::TooManyHandlers`] if the chain is full.
+pub fn register_shared_irq_handler(irq: u8, handler: SharedIrqHandler) -> Result<(), IrqError> {
+    let slots = SHARED_HANDLERS
+        .get(irq as usize)
+        .ok_or(IrqError::InvalidIrq)?;
+    if irq_handler(irq).is_some() {
+        return Err(IrqError::AlreadyRegistered);
+    }
+    slots
+        .iter()
+        .find(|slot| {
+            slot.compare_exchange(
+                core::ptr::null_mut(),
+                handler as *mut (),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        })
+        .map(|_| ())
+        .ok_or(IrqError::TooManyHandlers)
+}
+
+/// Removes `handler` from the chain of the shared IRQ line `irq`. Returns `false` if it was not registered.
+pub fn unregister_shared_irq_handler(irq: u8, handler: SharedIrqHandler) -> bool {
+    let Some(slots) = SHARED_HANDLERS.get(irq as usize) else {
+        return false;
+    };
+    slots.iter().any(|slot| {
+        slot.compare_exchange(
+            handler as *mut (),
+            core::ptr::null_mut(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_ok()
+    })
+}
+
+/// Returns whether IRQ line `irq` has shared handlers.
+pub fn is_shared(irq: u8) -> bool {
+    SHARED_HANDLERS.get(irq as usize).is_some_and(|slots| {
+        slots
+            .iter()
+            .any(|slot| !slot.load(Ordering::Acquire).is_null())
+    })
+}
+
+/// Returns the number of interrupts on the shared line `irq` that no handler claimed.
+pub fn unclaimed_count(irq: u8) -> u64 {
+    UNCLAIMED
+        .get(irq as usize)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Calls every handler chained on the shared line `irq`, counting the interrupt as unclaimed if none of them claims it.
+fn dispatch_shared(irq: u8) {
+    let mut claimed = false;
+    for slot in &SHARED_HANDLERS[irq as usize] {
+        let ptr = slot.load(Ordering::Acquire);
+        if ptr.is_null() {
+            continue;
+        }
+        // Safety: only `SharedIrqHandler` function pointers are ever stored in the shared table.
+        let handler = unsafe { core::mem::transmute::<*mut (), SharedIrqHandler>(ptr) };
+        claimed |= handler(irq);
+    }
+    if !claimed {
+        UNCLAIMED[irq as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Lets the handler of IRQ line `irq` run with interrupts enabled (or not), so that higher-priority interrupts such as the timer can preempt it.
+///
+/// The interrupt controller keeps the line itself and all lower-priority lines blocked until the handler returns and the EOI is sent:
+/// the legacy PIC by IRQ number (IRQ 0 highest), the Local APIC by vector class (see [`CLOCK_VECTOR`] and [`crate::irql`]).
+/// Returns `false` if `irq` is not a valid line.
+pub fn set_nestable(irq: u8, nestable: bool) -> bool {
+    if irq as usize >= IRQ_LINES {
+        return false;
+    }
+    if nestable {
+        NESTABLE.fetch_or(1 << irq, Ordering::AcqRel);
+    } else {
+        NESTABLE.fetch_and(!(1 << irq), Ordering::AcqRel);
+    }
+    true
+}
+
+/// Returns the number of IRQ handlers currently executing on this CPU (greater than 1 when interrupts are nested).
+pub fn nesting_depth() -> usize {
+    crate::percpu::current().nesting_depth()
+}
+
+/// Returns whether the caller is running inside an IRQ handler.
+pub fn in_interrupt() -> bool {
+    nesting_depth() != 0
+}
+
+/// Common entry point of all IRQ stubs.
+fn dispatch(irq: u8, interrupted_rip: u64) {
+    if crate::spurious::check_pic_spurious(irq) {
+        return;
+    }
+    if irq == 0 {
+        crate::profiler::sample(interrupted_rip);
+    }
+    let cpu = crate::percpu::current();
+    cpu.enter_irq();
+    let nestable = NESTABLE.load(Ordering::Relaxed) & (1 << irq) != 0;
+    match irq_handler(irq) {
+        Some(handler) if nestable => {
+            x86_64::instructions::interrupts::enable();
+            handler(irq);
+            x86_64::instructions::interrupts::disable();
+        }
+        Some(handler) => handler(irq),
+        None if is_shared(irq) => {
+            if nestable {
+                x86_64::instructions::interrupts::enable();
+            }
+            dispatch_shared(irq);
+            x86_64::instructions::interrupts::disable();
+        }
+        None => kprint!(
+            "[INFO] INT {:#x}: Unhandled IRQ {}\r\n",
+            IRQ_BASE + irq,
+            irq
+        ),
+    }
+    crate::hardware_interrupts::send_eoi(irq);
+    cpu.leave_irq();
+}
+
+macro_rules! irq_stubs {
+    ($($irq:literal => $name:ident),* $(,)?) => {
+        $(
+            extern "x86-interrupt" fn $name(stack_frame: InterruptStackFrame) {
+                dispatch($irq, stack_frame.instruction_pointer.as_u64());
+            }
+        )*
+
+        /// Stub for each IRQ line but the timer, indexed by line number minus one.
+        const IRQ_STUBS: [extern "x86-interrupt" fn(InterruptStackFrame); IRQ_LINES - 1] = [$($name),*];
+    };
+}
+
+irq_stubs! {
+    1 => irq1, 2 => irq2, 3 => irq3, 4 => irq4, 5 => irq5,
+    6 => irq6, 7 => irq7, 8 => irq8, 9 => irq9, 10 => irq10, 11 => irq11,
+    12 => irq12, 13 => irq13, 14 => irq14, 15 => irq15, 16 => irq16, 17 => irq17,
+    18 => irq18, 19 => irq19, 20 => irq20, 21 => irq21, 22 => irq22, 23 => irq23,
+    24 => irq24, 25 => irq25, 26 => irq26, 27 => irq27, 28 => irq28, 29 => irq29,
+    30 => irq30, 31 => irq31, 32 => irq32, 33 => irq33, 34 => irq34, 35 => irq35,
+    36 => irq36, 37 => irq37, 38 => irq38, 39 => irq39, 40 => irq40, 41 => irq41,
+    42 => irq42, 43 => irq43, 44 => irq44, 45 => irq45, 46 => irq46, 47 => irq47,
+}
+
+/// Dispatches IRQ line 0, then runs the user return hook if the timer interrupted user mode.
+extern "C" fn timer_dispatch(context: &mut InterruptContext) {
+    dispatch(0, context.rip);
+    if !context.is_user_mode() {
+        return;
+    }
+    let hook = USER_RETURN_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // Safety: only `UserReturnHook` function pointers are ever stored.
+        unsafe { core::mem::transmute::<*mut (), UserReturnHook>(hook)(context) };
+    }
+}
+
+interrupt_entry!(timer_entry, timer_dispatch);
+
+/// Points the IDT vectors `IRQ_BASE..IRQ_BASE + IRQ_LINES` and [`CLOCK_VECTOR`] at the dispatch stubs.
+pub fn setup_irq_stubs(idt: &mut InterruptDescriptorTable) {
+    for (index, stub) in IRQ_STUBS.iter().enumerate() {
+        idt[IRQ_BASE + 1 + index as u8].set_handler_fn(*stub);
+    }
+    let timer = VirtAddr::new(timer_entry as *const () as u64);
+    // Safety: `timer_entry` is an interrupt entry stub ending in `iretq`.
+    unsafe {
+        idt[IRQ_BASE].set_handler_addr(timer);
+        idt[CLOCK_VECTOR].set_handler_addr(timer);
+    }
+}
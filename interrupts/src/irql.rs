@@ -0,0 +1,80 @@
+//! # Interrupt Request Levels (IRQL)
+//!
+//! This module exposes the Local APIC's task priority as an *interrupt request level*: while the CPU runs at level *n*, every interrupt whose priority class (`vector >> 4`) is *n* or lower is held back by the APIC and delivered once the level drops again.
+//!
+//! ## Levels
+//!
+//! - [`Irql::PASSIVE`] (0): Normal code, every interrupt is delivered.
+//! - [`Irql::DEVICE`]: Blocks all device IRQ lines (vectors up to the last IRQ stub) but still lets the clock through.
+//! - [`Irql::HIGH`]: Blocks every maskable interrupt, including the clock.
+//!
+//! Levels are raised and restored in pairs:
+//!
+//! ```ignore
+//! let old = irql::raise_irql(Irql::DEVICE);
+//! // touch state shared with device IRQ handlers; timer ticks still arrive
+//! irql::lower_irql(old);
+//! ```
+//!
+//! When the Local APIC is not enabled (legacy PIC mode), levels are tracked but have no effect on delivery; the PIC only offers its fixed IRQ priority order.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::apic;
+use crate::irq::{CLOCK_VECTOR, IRQ_BASE, IRQ_LINES};
+
+/// An interrupt request level: the APIC priority class (0-15) below or at which interrupts are blocked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Irql(u8);
+
+impl Irql {
+    /// No interrupts are blocked.
+    pub const PASSIVE: Irql = Irql(0);
+    /// Device IRQ lines are blocked; the clock vector is not.
+    pub const DEVICE: Irql = Irql(((IRQ_BASE as usize + IRQ_LINES - 1) >> 4) as u8);
+    /// All maskable interrupts, including the clock, are blocked.
+    pub const HIGH: Irql = Irql(CLOCK_VECTOR >> 4);
+
+    /// Creates a level from an APIC priority class (clamped to 0-15).
+    pub const fn new(class: u8) -> Self {
+        Irql(if class > 15 { 15 } else { class })
+    }
+
+    /// Returns the APIC priority class of this level.
+    pub const fn class(&self) -> u8 {
+        self.0
+    }
+}
+
+/// Current level, mirrored in the LAPIC task priority register when the APIC is enabled.
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+fn apply(level: Irql) {
+    CURRENT.store(level.0, Ordering::Relaxed);
+    if apic::is_enabled() {
+        unsafe { apic::write(apic::REG_TPR, (level.0 as u32) << 4) };
+    }
+}
+
+/// Returns the current interrupt request level.
+pub fn current_irql() -> Irql {
+    Irql(CURRENT.load(Ordering::Relaxed))
+}
+
+/// Raises the level to `level` and returns the previous one, to be passed to [`lower_irql`].
+///
+/// If the current level is already higher, it is left unchanged.
+pub fn raise_irql(level: Irql) -> Irql {
+    let old = current_irql();
+    if level > old {
+        apply(level);
+    }
+    old
+}
+
+/// Restores a level previously returned by [`raise_irql`].
+pub fn lower_irql(old: Irql) {
+    if old < current_irql() {
+        apply(old);
+    }
+}
@@ -0,0 +1,137 @@
+//! # Keyboard Input
+//!
+//! This module turns the scancodes the IRQ 1 handler reads into key events. Each byte is decoded and every completed key is pushed as an `InputEvent::Key` into the `polished_input` queue, where the kernel main loop (or a shell) pops it together with mouse events.
+//!
+//! Decoding goes through a `polished_scancodes::Keyboard`, which tracks the modifier keys, for the scancode set the keyboard actually sends, which the PS/2 driver detects at initialization and the kernel passes to [`set_scancode_set`]. Until then Set 2 is assumed, the power-on default. The decoder also tracks which keys are held, queried with [`is_pressed`] and [`held_keys`] for chords such as Ctrl+C.
+//!
+//! Characters come from the layout selected with [`set_keymap`], US QWERTY by default. Dead keys of the layout go through a `polished_scancodes::Compose`: the dead key's event carries no character and the next key's event carries the combined one, e.g. `ê` for `^` then `e`. An accent that does not combine is queued as an extra key event before the character.
+//!
+//! A keyboard that is unplugged and plugged back in (or resets itself) sends its self-test result `0xAA`. The decoder then starts over and the function set with [`set_reset_handler`] runs as deferred work to restore the scancode set, the LEDs and scanning.
+
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+use polished_input::InputEvent;
+use polished_scancodes::keymap::{self, Keymap};
+use polished_scancodes::{Compose, Composed, KeyBitmap, KeyCode, Keyboard, Modifiers, ScancodeSet};
+use spin::Mutex;
+
+use crate::deferred;
+
+pub use polished_input::KeyboardEvent;
+
+/// Decoder state across scancode bytes; only locked by the IRQ handler and, with interrupts disabled, by [`set_scancode_set`].
+static DECODER: Mutex<Keyboard> = Mutex::new(Keyboard::new(ScancodeSet::Set2));
+
+/// Layout used for the characters of key events.
+static KEYMAP: Mutex<&'static dyn Keymap> = Mutex::new(&keymap::US);
+
+/// Dead key state; only locked by the IRQ handler and, with interrupts disabled, by [`set_keymap`].
+static COMPOSE: Mutex<Compose> = Mutex::new(Compose::new());
+
+/// Selects the keyboard layout, e.g. one of `polished_scancodes::keymap::LAYOUTS`. Takes effect for the next key event and drops a pending dead key.
+pub fn set_keymap(keymap: &'static dyn Keymap) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *KEYMAP.lock() = keymap;
+        COMPOSE.lock().reset();
+    });
+}
+
+/// Returns the active keyboard layout.
+pub fn keymap() -> &'static dyn Keymap {
+    x86_64::instructions::interrupts::without_interrupts(|| *KEYMAP.lock())
+}
+
+/// Sets the scancode set the keyboard sends, e.g. as detected by `polished_ps2::scancode_set`.
+pub fn set_scancode_set(set: ScancodeSet) {
+    x86_64::instructions::interrupts::without_interrupts(|| DECODER.lock().set_set(set));
+}
+
+/// Returns the current modifier state of the keyboard.
+pub fn modifiers() -> Modifiers {
+    x86_64::instructions::interrupts::without_interrupts(|| DECODER.lock().modifiers())
+}
+
+/// Returns whether `code` is held down, e.g. `is_pressed(KeyCode::C) && modifiers().ctrl()` for Ctrl+C.
+pub fn is_pressed(code: KeyCode) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| DECODER.lock().is_pressed(code))
+}
+
+/// Returns a snapshot of the keys held down.
+pub fn held_keys() -> KeyBitmap {
+    x86_64::instructions::interrupts::without_interrupts(|| DECODER.lock().held())
+}
+
+/// Returns the scancode set being decoded.
+pub fn scancode_set() -> ScancodeSet {
+    x86_64::instructions::interrupts::without_interrupts(|| DECODER.lock().set())
+}
+
+/// Reconfigures the keyboard after a reset; see [`set_reset_handler`].
+pub type ResetHandler = fn(Modifiers) -> ScancodeSet;
+
+static RESET_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static RESETS: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the function that reconfigures the keyboard when it reports a reset (unplugged and plugged back in, or reset by itself), e.g. one calling `polished_ps2::reconfigure_keyboard`.
+///
+/// It runs as deferred work, with the lock state to restore, and returns the scancode set the keyboard sends afterwards.
+pub fn set_reset_handler(handler: ResetHandler) {
+    RESET_HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+/// Returns the number of keyboard resets seen since boot.
+pub fn resets() -> u64 {
+    RESETS.load(Ordering::Relaxed)
+}
+
+/// Deferred work running the reset handler.
+fn reconfigure(_arg: usize) {
+    let handler = RESET_HANDLER.load(Ordering::Acquire);
+    if handler.is_null() {
+        return;
+    }
+    // Safety: only `ResetHandler` function pointers are ever stored.
+    let handler = unsafe { core::mem::transmute::<*mut (), ResetHandler>(handler) };
+    let set = handler(modifiers());
+    set_scancode_set(set);
+}
+
+/// Feeds a scancode byte to the decoder and queues the completed key event, if any. Called from the keyboard IRQ handler.
+///
+/// Presses completing a debug chord (see `polished_input::sysrq`) run its action instead of being queued.
+///
+/// A self-test result from a keyboard that was reset restarts the decoder, as the keyboard is back in Set 2 with no keys held, and defers its reconfiguration.
+pub(crate) fn push_scancode(byte: u8) {
+    let mut decoder = DECODER.lock();
+    if decoder.is_reset(byte) {
+        decoder.reset(ScancodeSet::Set2);
+        drop(decoder);
+        RESETS.fetch_add(1, Ordering::Relaxed);
+        deferred::defer(reconfigure, 0);
+        return;
+    }
+    let Some((raw, key)) = decoder.feed_raw(byte) else {
+        return;
+    };
+    let held = decoder.held();
+    drop(decoder);
+    if polished_input::sysrq::dispatch(&key, held) {
+        return;
+    }
+    let keymap = *KEYMAP.lock();
+    let event = KeyboardEvent::from_key(raw.code, key, keymap);
+    let Some(c) = event.character else {
+        polished_input::push(InputEvent::Key(event));
+        return;
+    };
+    let dead = keymap.is_dead(key.code, key.modifiers);
+    let event = match COMPOSE.lock().feed(c, dead) {
+        Composed::Pending => event.with_character(None),
+        Composed::One(c) => event.with_character(Some(c)),
+        Composed::Two(accent, c) => {
+            polished_input::push(InputEvent::Key(event.with_character(Some(accent))));
+            event.with_character(Some(c))
+        }
+    };
+    polished_input::push(InputEvent::Key(event));
+}
@@ -22,13 +22,35 @@
 //!
 //! There are two main approaches for implementing syscalls on x86_64:
 //! 1. **Software Interrupts:** Using instructions like `int 0x80` (legacy, slower, not used here).
-//! 2. **Fast Syscall Instructions:** Using `syscall`/`sysret` (preferred for modern OSes, not implemented in this library).
+//! 2. **Fast Syscall Instructions:** Using `syscall`/`sysret` (preferred for modern OSes, implemented by the `polished_syscalls` crate).
 //!
 //! This library focuses on exception and hardware interrupt handling, not syscall dispatch.
 //!
 //! ## Modules
+//! - `apic`: Local APIC detection, enabling (x2APIC or xAPIC mode), EOI, and timer configuration.
+//! - `ioapic`: I/O APIC programming and ISA IRQ/GSI routing (using the ACPI MADT).
+//! - `context`: Register-saving exception entry stubs and crash dumps (registers and stack).
 //! - `cpu_exceptions`: Sets up handlers for CPU exceptions (e.g., page fault, double fault).
+//! - `error_code`: Human-readable decoding of selector and page fault error codes.
+//! - `exception_hooks`: Runtime pre/post/replace hooks for CPU exceptions.
+//! - `gates`: Software interrupt gates callable from user mode (DPL 3), e.g. `int 0x80`.
 //! - `hardware_interrupts`: Sets up handlers for hardware IRQs (e.g., timer, keyboard).
+//! - `deferred`: Work queued by IRQ handlers and run later with interrupts enabled.
+//! - `hpet`: High Precision Event Timer counter and one-shot comparators.
+//! - `ipi`: Inter-processor interrupts (fixed, NMI, INIT/SIPI) and TLB shootdown.
+//! - `mmio`: Mapping of the APIC and HPET register blocks through a hook installed by the memory subsystem.
+//! - `msi`: MSI/MSI-X vector allocation and PCI capability programming.
+//! - `percpu`: Per-CPU interrupt counters and nesting depth, indexed by CPU index.
+//! - `pit`: Legacy PIT channel 0 programming and the configured tick rate.
+//! - `irql`: Interrupt request levels on top of the Local APIC task priority.
+//! - `keyboard`: Lock-free queue of keyboard events filled by the IRQ 1 handler.
+//! - `profiler`: Sampling profiler recording the interrupted RIP on every timer tick.
+//! - `rtc`: CMOS real-time clock (wall-clock date and time, periodic IRQ 8).
+//! - `spurious`: Detection and counting of spurious PIC and APIC interrupts.
+//! - `stacks`: Registry of kernel stack bounds for stack overflow detection on double faults.
+//! - `time`: Tick counter, `uptime_ms()`, and the `sleep_busy()`/`sleep()` delays.
+//! - `timer`: Software timers (`after`/`every` callbacks) run as deferred work.
+//! - `irq`: Runtime registration of exclusive and shared IRQ handlers (`register_irq_handler`/`register_shared_irq_handler`) and dispatch.
 //!
 //! ## Usage
 //! Call `init_idt()` early in kernel initialization to set up the IDT and enable interrupt handling.
@@ -39,10 +61,59 @@
 use once_cell::unsync::OnceCell;
 use x86_64::structures::idt::InterruptDescriptorTable;
 
+/// Local APIC driver (x2APIC mode when supported, xAPIC otherwise).
+pub mod apic;
+/// Exception context capture and crash dumps.
+pub mod context;
 /// CPU exception handler setup (e.g., page fault, double fault).
 pub mod cpu_exceptions;
+/// Deferred interrupt work (softirq-style).
+pub mod deferred;
+/// Exception error code decoding.
+pub mod error_code;
+/// Runtime CPU exception hooks.
+pub mod exception_hooks;
+/// User-callable (DPL 3) interrupt gates.
+pub mod gates;
 /// Hardware interrupt handler setup (e.g., timer, keyboard).
 pub mod hardware_interrupts;
+/// Runtime IRQ handler registration and dispatch.
+pub mod irq;
+
+pub use irq::{
+    register_irq_handler, register_shared_irq_handler, unregister_irq_handler,
+    unregister_shared_irq_handler,
+};
+/// High Precision Event Timer driver.
+pub mod hpet;
+/// I/O APIC configuration and IRQ routing.
+pub mod ioapic;
+/// Inter-processor interrupts.
+pub mod ipi;
+/// Interrupt request levels (APIC task priority).
+pub mod irql;
+/// Keyboard event queue filled from the IRQ handler.
+pub mod keyboard;
+/// Register block mapping.
+pub mod mmio;
+/// Message-signaled interrupts (MSI/MSI-X) for PCI devices.
+pub mod msi;
+/// Per-CPU interrupt state.
+pub mod percpu;
+/// Programmable Interval Timer (channel 0) configuration.
+pub mod pit;
+/// Timer-driven sampling profiler.
+pub mod profiler;
+/// Real-time clock (CMOS) driver.
+pub mod rtc;
+/// Spurious interrupt detection and counters.
+pub mod spurious;
+/// Kernel stack bounds for overflow detection.
+pub mod stacks;
+/// Monotonic tick counter and delay functions.
+pub mod time;
+/// One-shot and periodic software timers.
+pub mod timer;
 
 // Static OnceCell for the IDT
 static mut IDT: OnceCell<InterruptDescriptorTable> = OnceCell::new();
@@ -53,6 +124,9 @@ static mut IDT: OnceCell<InterruptDescriptorTable> = OnceCell::new();
 /// initializing the IDT and loading it with the `lidt` instruction. It must be
 /// called before enabling interrupts (with `sti`).
 ///
+/// All CPUs share one IDT: application processors call this function too, which only loads the
+/// already initialized table. Per-CPU interrupt state lives in [`percpu`].
+///
 /// # Safety
 /// This function is safe to call once during early kernel initialization, before
 /// interrupts are enabled. It uses a static mutable variable, but OnceCell ensures
@@ -70,3 +144,13 @@ pub fn init_idt() {
     };
     idt.load();
 }
+
+/// Runs `f` on the loaded IDT, returning `None` if [`init_idt`] has not been called.
+///
+/// # Safety
+/// Must not race with other modifications of the IDT. Changes take effect immediately, since the CPU reads the table in place.
+pub(crate) unsafe fn with_idt<R>(f: impl FnOnce(&mut InterruptDescriptorTable) -> R) -> Option<R> {
+    #[allow(static_mut_refs)] // Allowed because OnceCell is used
+    let idt = unsafe { IDT.get_mut() }?;
+    Some(f(idt))
+}
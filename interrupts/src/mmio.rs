@@ -0,0 +1,25 @@
+//! # Register Block Mapping
+//!
+//! The Local APIC, the I/O APICs and the HPET are programmed through register blocks at physical addresses reported by the CPU or by ACPI. Their drivers never dereference those addresses directly: they ask [`map`] for a virtual address, which calls the [`MmioMapper`] the memory subsystem installs with [`set_mmio_mapper`] to map the block uncached. Without a mapper, the physical address is returned as is, which relies on the identity mapping set up by UEFI.
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Maps the `size` byte register block called `name` at the physical address `physical` uncached, returning its virtual address, or `None` if it cannot be mapped.
+pub type MmioMapper = fn(name: &'static str, physical: u64, size: u64) -> Option<u64>;
+
+static MMIO_MAPPER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Installs the function used to map register blocks.
+pub fn set_mmio_mapper(mapper: MmioMapper) {
+    MMIO_MAPPER.store(mapper as *mut (), Ordering::Release);
+}
+
+/// Returns the virtual address of the register block at `physical`, through the installed mapper or the identity mapping.
+pub fn map(name: &'static str, physical: u64, size: u64) -> Option<u64> {
+    let mapper = MMIO_MAPPER.load(Ordering::Acquire);
+    if mapper.is_null() {
+        return Some(physical);
+    }
+    // Safety: only `MmioMapper` function pointers are ever stored.
+    unsafe { core::mem::transmute::<*mut (), MmioMapper>(mapper)(name, physical, size) }
+}
@@ -0,0 +1,340 @@
+//! # Message-Signaled Interrupts (MSI and MSI-X)
+//!
+//! This module allocates interrupt vectors for PCI devices and programs their MSI or MSI-X capabilities, which modern devices such as NVMe controllers and virtio devices rely on.
+//!
+//! ## How MSI Works
+//!
+//! Instead of asserting a physical interrupt pin, a device signals an interrupt by writing a 32-bit *data* value to a special *address*. On x86 that address lies in the Local APIC range (`0xFEE0_0000`) and encodes the destination APIC ID; the data encodes the vector and delivery mode. No I/O APIC is involved, interrupts are never shared, and each device can have several vectors.
+//!
+//! - **MSI** (capability ID `0x05`): The address and data live directly in the device's PCI configuration space.
+//! - **MSI-X** (capability ID `0x11`): The device has a table of up to 2048 address/data entries in one of its memory BARs, each individually maskable.
+//!
+//! ## Vector Allocation
+//!
+//! MSI vectors are taken from the IRQ lines [`crate::irq::MSI_FIRST_LINE`] and up, so their handlers are registered with [`crate::irq::register_irq_handler`] like any other IRQ and get the same dispatch and EOI handling.
+//!
+//! ## Usage
+//!
+//! ```ignore
+//! let device = PciAddress::new(0, 4, 0);
+//! let irq = msi::allocate_irq()?;
+//! irq::register_irq_handler(irq, nvme_irq)?;
+//! msi::enable_msi(device, irq, apic::id() as u8)?;
+//! ```
+//!
+//! PCI configuration space is accessed through the legacy `0xCF8`/`0xCFC` I/O ports, and MSI-X tables are accessed at their physical BAR address (identity mapped by UEFI).
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use polished_x86_commands::port::Port;
+
+use crate::irq::{IRQ_BASE, IRQ_LINES, MSI_FIRST_LINE};
+
+/// PCI configuration address port.
+const PCI_CONFIG_ADDRESS: u16 = 0xCF8;
+/// PCI configuration data port.
+const PCI_CONFIG_DATA: u16 = 0xCFC;
+
+/// Configuration register: command (low half) and status (high half).
+const PCI_COMMAND_STATUS: u8 = 0x04;
+/// Command bit: disable legacy INTx interrupts.
+const PCI_COMMAND_INTX_DISABLE: u32 = 1 << 10;
+/// Status bit: the device has a capability list.
+const PCI_STATUS_CAPABILITIES: u32 = 1 << 20;
+/// Configuration register: pointer to the first capability.
+const PCI_CAPABILITIES_POINTER: u8 = 0x34;
+
+/// Capability ID of MSI.
+const CAP_MSI: u8 = 0x05;
+/// Capability ID of MSI-X.
+const CAP_MSIX: u8 = 0x11;
+
+/// MSI message control: enable.
+const MSI_ENABLE: u32 = 1 << 0;
+/// MSI message control: multiple message enable field.
+const MSI_MULTIPLE_MESSAGE_ENABLE: u32 = 0b111 << 4;
+/// MSI message control: 64-bit address capable.
+const MSI_64BIT: u32 = 1 << 7;
+
+/// MSI-X message control: function mask.
+const MSIX_FUNCTION_MASK: u32 = 1 << 14;
+/// MSI-X message control: enable.
+const MSIX_ENABLE: u32 = 1 << 15;
+/// MSI-X table entry vector control: masked.
+const MSIX_ENTRY_MASKED: u32 = 1 << 0;
+
+/// Base of the Local APIC message address range.
+const MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+
+/// Bitmap of allocated MSI lines, bit *n* standing for line `MSI_FIRST_LINE + n`.
+static ALLOCATED: AtomicU32 = AtomicU32::new(0);
+
+/// Number of IRQ lines available for MSI.
+const MSI_LINES: u8 = IRQ_LINES as u8 - MSI_FIRST_LINE;
+
+/// Errors returned by the MSI helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsiError {
+    /// All MSI lines are allocated.
+    NoFreeVector,
+    /// The line was not allocated by [`allocate_irq`].
+    InvalidIrq,
+    /// The device has no MSI (or MSI-X) capability.
+    NotSupported,
+    /// The MSI-X table index is out of range.
+    InvalidEntry,
+}
+
+/// Location of a PCI function in configuration space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciAddress {
+    /// Bus number.
+    pub bus: u8,
+    /// Device number (0-31).
+    pub device: u8,
+    /// Function number (0-7).
+    pub function: u8,
+}
+
+impl PciAddress {
+    /// Creates a PCI address.
+    pub fn new(bus: u8, device: u8, function: u8) -> Self {
+        PciAddress {
+            bus,
+            device,
+            function,
+        }
+    }
+
+    fn select(&self, offset: u8) {
+        let address = 1 << 31
+            | (self.bus as u32) << 16
+            | ((self.device & 0x1F) as u32) << 11
+            | ((self.function & 0x07) as u32) << 8
+            | (offset & 0xFC) as u32;
+        unsafe { Port::<u32>::new(PCI_CONFIG_ADDRESS).write(address) };
+    }
+
+    /// Reads the 32-bit configuration register at `offset` (rounded down to a multiple of 4).
+    pub fn read_config(&self, offset: u8) -> u32 {
+        self.select(offset);
+        unsafe { Port::<u32>::new(PCI_CONFIG_DATA).read() }
+    }
+
+    /// Writes the 32-bit configuration register at `offset` (rounded down to a multiple of 4).
+    pub fn write_config(&self, offset: u8, value: u32) {
+        self.select(offset);
+        unsafe { Port::<u32>::new(PCI_CONFIG_DATA).write(value) };
+    }
+
+    /// Returns the configuration offset of the capability with ID `id`, if present.
+    pub fn find_capability(&self, id: u8) -> Option<u8> {
+        if self.read_config(PCI_COMMAND_STATUS) & PCI_STATUS_CAPABILITIES == 0 {
+            return None;
+        }
+        let mut offset = (self.read_config(PCI_CAPABILITIES_POINTER) & 0xFC) as u8;
+        // Bounded walk, in case of a malformed (cyclic) list
+        for _ in 0..48 {
+            if offset == 0 {
+                return None;
+            }
+            let header = self.read_config(offset);
+            if header as u8 == id {
+                return Some(offset);
+            }
+            offset = ((header >> 8) & 0xFC) as u8;
+        }
+        None
+    }
+
+    /// Disables legacy pin-based (INTx) interrupts.
+    pub fn disable_intx(&self) {
+        let command = self.read_config(PCI_COMMAND_STATUS) & 0xFFFF;
+        self.write_config(PCI_COMMAND_STATUS, command | PCI_COMMAND_INTX_DISABLE);
+    }
+}
+
+/// Address/data pair a device writes to raise an interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsiMessage {
+    /// Message address (Local APIC range, encoding the destination).
+    pub address: u64,
+    /// Message data (encoding the vector).
+    pub data: u32,
+}
+
+impl MsiMessage {
+    /// Encodes an edge-triggered, fixed-delivery interrupt for IRQ line `irq` on the CPU with Local APIC ID `destination`.
+    pub fn new(irq: u8, destination: u8) -> Self {
+        MsiMessage {
+            address: MSI_ADDRESS_BASE | (destination as u64) << 12,
+            data: (IRQ_BASE + irq) as u32,
+        }
+    }
+}
+
+/// Allocates a free IRQ line for a message-signaled interrupt.
+///
+/// The returned line is used both with [`crate::irq::register_irq_handler`] and with [`enable_msi`] / [`MsiX::set_entry`].
+pub fn allocate_irq() -> Result<u8, MsiError> {
+    let mut current = ALLOCATED.load(Ordering::Acquire);
+    loop {
+        let free = (!current).trailing_zeros() as u8;
+        if free >= MSI_LINES {
+            return Err(MsiError::NoFreeVector);
+        }
+        match ALLOCATED.compare_exchange_weak(
+            current,
+            current | 1 << free,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => return Ok(MSI_FIRST_LINE + free),
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+/// Returns an IRQ line obtained from [`allocate_irq`].
+pub fn free_irq(irq: u8) -> Result<(), MsiError> {
+    let bit = irq
+        .checked_sub(MSI_FIRST_LINE)
+        .filter(|bit| *bit < MSI_LINES)
+        .ok_or(MsiError::InvalidIrq)?;
+    let previous = ALLOCATED.fetch_and(!(1 << bit), Ordering::AcqRel);
+    if previous & 1 << bit == 0 {
+        return Err(MsiError::InvalidIrq);
+    }
+    Ok(())
+}
+
+/// Programs the device's MSI capability to deliver IRQ line `irq` to the CPU with Local APIC ID `destination`, and enables it.
+///
+/// A single message is used; legacy INTx interrupts are disabled.
+pub fn enable_msi(device: PciAddress, irq: u8, destination: u8) -> Result<(), MsiError> {
+    let cap = device
+        .find_capability(CAP_MSI)
+        .ok_or(MsiError::NotSupported)?;
+    let message = MsiMessage::new(irq, destination);
+    let control = device.read_config(cap) >> 16;
+
+    device.write_config(cap + 4, message.address as u32);
+    if control & MSI_64BIT != 0 {
+        device.write_config(cap + 8, (message.address >> 32) as u32);
+        device.write_config(cap + 12, message.data);
+    } else {
+        device.write_config(cap + 8, message.data);
+    }
+    let control = (control & !MSI_MULTIPLE_MESSAGE_ENABLE) | MSI_ENABLE;
+    let header = device.read_config(cap) & 0xFFFF;
+    device.write_config(cap, header | control << 16);
+    device.disable_intx();
+    Ok(())
+}
+
+/// Disables the device's MSI capability.
+pub fn disable_msi(device: PciAddress) -> Result<(), MsiError> {
+    let cap = device
+        .find_capability(CAP_MSI)
+        .ok_or(MsiError::NotSupported)?;
+    let value = device.read_config(cap);
+    device.write_config(cap, value & !(MSI_ENABLE << 16));
+    Ok(())
+}
+
+/// Handle to a device's MSI-X capability and vector table.
+#[derive(Debug, Clone, Copy)]
+pub struct MsiX {
+    device: PciAddress,
+    cap: u8,
+    table: u64,
+    size: u16,
+}
+
+impl MsiX {
+    /// Locates the MSI-X capability of `device` and its vector table.
+    ///
+    /// Returns [`MsiError::NotSupported`] if the device has no MSI-X capability or its table BAR is not a memory BAR.
+    pub fn new(device: PciAddress) -> Result<Self, MsiError> {
+        let cap = device
+            .find_capability(CAP_MSIX)
+            .ok_or(MsiError::NotSupported)?;
+        let control = device.read_config(cap) >> 16;
+        let table_info = device.read_config(cap + 4);
+        let bir = (table_info & 0b111) as u8;
+        if bir > 5 {
+            return Err(MsiError::NotSupported);
+        }
+        let bar = device.read_config(0x10 + bir * 4);
+        if bar & 1 != 0 {
+            return Err(MsiError::NotSupported);
+        }
+        let mut base = (bar & !0xF) as u64;
+        if (bar >> 1) & 0b11 == 0b10 && bir < 5 {
+            base |= (device.read_config(0x10 + (bir + 1) * 4) as u64) << 32;
+        }
+        Ok(MsiX {
+            device,
+            cap,
+            table: base + (table_info & !0b111) as u64,
+            size: (control & 0x7FF) as u16 + 1,
+        })
+    }
+
+    /// Returns the number of entries in the vector table.
+    pub fn table_size(&self) -> u16 {
+        self.size
+    }
+
+    fn entry_address(&self, index: u16) -> Result<*mut u32, MsiError> {
+        if index >= self.size {
+            return Err(MsiError::InvalidEntry);
+        }
+        Ok((self.table + index as u64 * 16) as *mut u32)
+    }
+
+    /// Programs table entry `index` to deliver IRQ line `irq` to the CPU with Local APIC ID `destination` and unmasks it.
+    pub fn set_entry(&self, index: u16, irq: u8, destination: u8) -> Result<(), MsiError> {
+        let entry = self.entry_address(index)?;
+        let message = MsiMessage::new(irq, destination);
+        unsafe {
+            core::ptr::write_volatile(entry.add(3), MSIX_ENTRY_MASKED);
+            core::ptr::write_volatile(entry, message.address as u32);
+            core::ptr::write_volatile(entry.add(1), (message.address >> 32) as u32);
+            core::ptr::write_volatile(entry.add(2), message.data);
+            core::ptr::write_volatile(entry.add(3), 0);
+        }
+        Ok(())
+    }
+
+    /// Masks or unmasks table entry `index`.
+    pub fn set_masked(&self, index: u16, masked: bool) -> Result<(), MsiError> {
+        let entry = self.entry_address(index)?;
+        unsafe {
+            let control = core::ptr::read_volatile(entry.add(3));
+            let control = if masked {
+                control | MSIX_ENTRY_MASKED
+            } else {
+                control & !MSIX_ENTRY_MASKED
+            };
+            core::ptr::write_volatile(entry.add(3), control);
+        }
+        Ok(())
+    }
+
+    /// Enables MSI-X for the device (clearing the function mask) and disables legacy INTx interrupts.
+    pub fn enable(&self) {
+        let value = self.device.read_config(self.cap);
+        let control = ((value >> 16) | MSIX_ENABLE) & !MSIX_FUNCTION_MASK;
+        self.device
+            .write_config(self.cap, (value & 0xFFFF) | control << 16);
+        self.device.disable_intx();
+    }
+
+    /// Disables MSI-X for the device.
+    pub fn disable(&self) {
+        let value = self.device.read_config(self.cap);
+        self.device
+            .write_config(self.cap, value & !(MSIX_ENABLE << 16));
+    }
+}
@@ -0,0 +1,110 @@
+//! # Per-CPU Interrupt State
+//!
+//! With several CPUs taking interrupts at the same time, global counters such as the IRQ nesting depth become meaningless. This module keeps that state per CPU, so the same dispatch code runs correctly on every processor.
+//!
+//! APIC IDs are often sparse, so the state is indexed by a dense CPU index instead: the bootstrap processor (BSP) is CPU 0, and every application processor is registered under the index it was started as (the one its `percpu` block uses) when its Local APIC is enabled. [`cpu_index`] finds the calling CPU's index by its APIC ID. Before that (or without an APIC) all state belongs to slot 0.
+//!
+//! ## Example
+//! ```ignore
+//! let state = percpu::current();
+//! kprint!("CPU {}: {} IRQs, {} IPIs\r\n", percpu::cpu_index(), state.irqs(), state.ipis());
+//! ```
+
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::apic;
+
+/// Maximum number of CPUs with their own interrupt state; CPUs with larger indices share slot 0.
+pub const MAX_CPUS: usize = 64;
+
+/// Marks a slot of [`APIC_IDS`] without a CPU (the x2APIC broadcast ID, which no CPU has).
+const NO_CPU: u32 = u32::MAX;
+
+/// Interrupt state of one CPU.
+pub struct CpuInterruptState {
+    irqs: AtomicU64,
+    ipis: AtomicU64,
+    tlb_shootdowns: AtomicU64,
+    nesting_depth: AtomicUsize,
+}
+
+impl CpuInterruptState {
+    const fn new() -> Self {
+        CpuInterruptState {
+            irqs: AtomicU64::new(0),
+            ipis: AtomicU64::new(0),
+            tlb_shootdowns: AtomicU64::new(0),
+            nesting_depth: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of device IRQs dispatched on this CPU.
+    pub fn irqs(&self) -> u64 {
+        self.irqs.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of inter-processor interrupts received by this CPU.
+    pub fn ipis(&self) -> u64 {
+        self.ipis.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of TLB shootdowns performed by this CPU.
+    pub fn tlb_shootdowns(&self) -> u64 {
+        self.tlb_shootdowns.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of IRQ handlers currently executing on this CPU.
+    pub fn nesting_depth(&self) -> usize {
+        self.nesting_depth.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn enter_irq(&self) {
+        self.irqs.fetch_add(1, Ordering::Relaxed);
+        self.nesting_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn leave_irq(&self) {
+        self.nesting_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_ipi(&self) {
+        self.ipis.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_tlb_shootdown(&self) {
+        self.tlb_shootdowns.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+static STATE: [CpuInterruptState; MAX_CPUS] = [const { CpuInterruptState::new() }; MAX_CPUS];
+/// APIC ID of the CPU with each index.
+static APIC_IDS: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(NO_CPU) }; MAX_CPUS];
+
+/// Registers the calling CPU, identified by its Local APIC ID, as CPU `index`. Indices of [`MAX_CPUS`] and above are ignored.
+pub(crate) fn register_cpu(index: usize) {
+    if let Some(slot) = APIC_IDS.get(index) {
+        slot.store(apic::id(), Ordering::Release);
+    }
+}
+
+/// Returns the index of the calling CPU's state: the index it was registered under when its Local APIC was enabled, or 0 if it has none.
+pub fn cpu_index() -> usize {
+    if !apic::is_enabled() {
+        return 0;
+    }
+    let id = apic::id();
+    APIC_IDS
+        .iter()
+        .position(|slot| slot.load(Ordering::Acquire) == id)
+        .unwrap_or(0)
+}
+
+/// Returns the interrupt state of the calling CPU.
+pub fn current() -> &'static CpuInterruptState {
+    &STATE[cpu_index()]
+}
+
+/// Returns the interrupt state of CPU `index`, if it is below [`MAX_CPUS`].
+pub fn cpu(index: usize) -> Option<&'static CpuInterruptState> {
+    STATE.get(index)
+}
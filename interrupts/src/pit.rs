@@ -0,0 +1,123 @@
+//! # Programmable Interval Timer (PIT)
+//!
+//! This module programs channel 0 of the legacy Intel 8253/8254 PIT, which raises IRQ 0 at a configurable rate.
+//!
+//! ## How the PIT Works
+//!
+//! The PIT has an input clock of 1.193182 MHz and three 16-bit down-counters ("channels"). Channel 0 is wired to IRQ 0. When programmed in *rate generator* mode with a reload value (divisor) *d*, it fires IRQ 0 every *d* input ticks, i.e. at `1193182 / d` Hz. The divisor is limited to 16 bits, so the slowest rate is about 18.2 Hz (divisor 65536, written as 0).
+//!
+//! Firmware leaves the PIT in an unknown mode, so the kernel should call [`set_frequency`] before relying on timer ticks. The configured rate is then available to the rest of the kernel through [`tick_rate_hz`].
+//!
+//! ## Ports
+//! - `0x40`: Channel 0 data port.
+//! - `0x42`: Channel 2 data port (used for polled delays while calibrating other timers).
+//! - `0x43`: Mode/command register.
+//! - `0x61`: Channel 2 gate and output.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use polished_x86_commands::port::Port;
+
+/// Input clock frequency of the PIT in Hz.
+pub const PIT_BASE_FREQUENCY_HZ: u32 = 1_193_182;
+
+/// Tick rate used by the kernel by default.
+pub const DEFAULT_FREQUENCY_HZ: u32 = 1000;
+
+/// Channel 0 data port.
+const PIT_CHANNEL0: u16 = 0x40;
+/// Channel 2 data port.
+const PIT_CHANNEL2: u16 = 0x42;
+/// Mode/command register.
+const PIT_COMMAND: u16 = 0x43;
+
+/// Command: channel 0, access lobyte/hibyte, mode 2 (rate generator), binary.
+const CMD_CHANNEL0_RATE_GENERATOR: u8 = 0x34;
+/// Command: channel 0, latch count value.
+const CMD_CHANNEL0_LATCH: u8 = 0x00;
+/// Command: channel 2, access lobyte/hibyte, mode 0 (interrupt on terminal count), binary.
+const CMD_CHANNEL2_ONESHOT: u8 = 0xB0;
+
+/// NMI status and control port (PIT channel 2 gate and output).
+const SPEAKER_CONTROL: u16 = 0x61;
+/// Port `0x61` bit: channel 2 output.
+const SPEAKER_CONTROL_OUT2: u8 = 1 << 5;
+
+/// Configured IRQ 0 rate in Hz, or 0 if the PIT has not been programmed.
+static TICK_RATE_HZ: AtomicU32 = AtomicU32::new(0);
+
+/// Programs channel 0 to raise IRQ 0 at approximately `hz` times per second.
+///
+/// The requested frequency is clamped to what the 16-bit divisor can express (about 19 Hz to 1.19 MHz).
+/// Returns the actual frequency, which is also stored for [`tick_rate_hz`].
+///
+/// # Example
+/// ```ignore
+/// let hz = pit::set_frequency(1000);
+/// ```
+pub fn set_frequency(hz: u32) -> u32 {
+    let divisor = divisor_for(hz);
+    unsafe {
+        Port::<u8>::new(PIT_COMMAND).write(CMD_CHANNEL0_RATE_GENERATOR);
+        let data = Port::<u8>::new(PIT_CHANNEL0);
+        data.write((divisor & 0xFF) as u8);
+        data.write((divisor >> 8) as u8);
+    }
+    let divisor = if divisor == 0 { 65536 } else { divisor as u32 };
+    let actual = PIT_BASE_FREQUENCY_HZ / divisor;
+    TICK_RATE_HZ.store(actual, Ordering::Release);
+    actual
+}
+
+/// Returns the 16-bit reload value (0 meaning 65536) for the requested frequency.
+fn divisor_for(hz: u32) -> u16 {
+    let hz = hz.max(1);
+    let divisor = (PIT_BASE_FREQUENCY_HZ + hz / 2) / hz;
+    match divisor {
+        0 | 1 => 2,
+        2..=65535 => divisor as u16,
+        _ => 0,
+    }
+}
+
+/// Returns the configured IRQ 0 rate in Hz, or 0 if [`set_frequency`] has not been called.
+pub fn tick_rate_hz() -> u32 {
+    TICK_RATE_HZ.load(Ordering::Acquire)
+}
+
+/// Latches and returns the current count of channel 0.
+///
+/// Useful for short busy-wait measurements (e.g. calibrating other timers).
+pub fn read_count() -> u16 {
+    unsafe {
+        Port::<u8>::new(PIT_COMMAND).write(CMD_CHANNEL0_LATCH);
+        let data = Port::<u8>::new(PIT_CHANNEL0);
+        let low = data.read() as u16;
+        let high = data.read() as u16;
+        high << 8 | low
+    }
+}
+
+/// Busy-waits for `ms` milliseconds (at most 54) using PIT channel 2, without interrupts.
+///
+/// Channel 2 is normally connected to the PC speaker; its gate and output are controlled through port `0x61`.
+/// This is used to calibrate other timers at boot and leaves channel 0 untouched.
+pub fn wait_polled_ms(ms: u16) {
+    let count = (PIT_BASE_FREQUENCY_HZ / 1000 * ms.min(54) as u32) as u16;
+    unsafe {
+        let control = Port::<u8>::new(SPEAKER_CONTROL);
+        // Gate low and speaker output disabled while programming
+        let value = control.read() & !0x02;
+        control.write(value & !0x01);
+        Port::<u8>::new(PIT_COMMAND).write(CMD_CHANNEL2_ONESHOT);
+        let data = Port::<u8>::new(PIT_CHANNEL2);
+        data.write((count & 0xFF) as u8);
+        data.write((count >> 8) as u8);
+        // A rising edge on the gate starts the count
+        control.write(value | 0x01);
+        while control.read() & SPEAKER_CONTROL_OUT2 == 0 {
+            core::hint::spin_loop();
+        }
+        control.write(value & !0x01);
+    }
+}
@@ -0,0 +1,129 @@
+//! # Sampling Profiler
+//!
+//! A poor man's `perf` for kernel code: while the profiler runs, every timer interrupt records the instruction pointer of the code it interrupted. Code that runs often is interrupted often, so a histogram of the recorded addresses points at the hot spots.
+//!
+//! ## How It Works
+//!
+//! - The timer IRQ (line 0, from the PIT or the Local APIC timer) calls [`sample`] from [`crate::irq`] dispatch with the RIP of the interrupted stack frame.
+//! - Samples go into a fixed ring buffer of [`SAMPLE_BUFFER_SIZE`] addresses; once it is full the oldest samples are overwritten.
+//! - [`dump_histogram`] stops the profiler, groups the samples into address buckets, and prints the most frequent buckets over serial. Resolve the addresses with `addr2line -e <kernel binary>` or `nm`.
+//!
+//! The sampling rate is the timer rate (see [`crate::pit::set_frequency`] and [`crate::apic::set_periodic`]). Code that runs with interrupts disabled cannot be sampled; its time is attributed to the first instruction after interrupts are re-enabled.
+//!
+//! ## Example
+//! ```ignore
+//! profiler::start();
+//! run_workload();
+//! profiler::dump_histogram(4, 20); // 16-byte buckets, top 20
+//! ```
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use polished_serial_logging::kprint;
+
+/// Number of samples kept in the ring buffer.
+pub const SAMPLE_BUFFER_SIZE: usize = 4096;
+/// Maximum number of buckets printed by [`dump_histogram`].
+pub const MAX_REPORTED_BUCKETS: usize = 32;
+
+struct SampleBuffer(UnsafeCell<[u64; SAMPLE_BUFFER_SIZE]>);
+
+// Safety: the buffer is written only by the timer interrupt while the profiler runs, and read only after it has been stopped.
+unsafe impl Sync for SampleBuffer {}
+
+static SAMPLES: SampleBuffer = SampleBuffer(UnsafeCell::new([0; SAMPLE_BUFFER_SIZE]));
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Index of the next slot to write.
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+/// Samples recorded since the last reset, including overwritten ones.
+static TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// Starts recording samples, discarding any previous ones.
+pub fn start() {
+    reset();
+    RUNNING.store(true, Ordering::Release);
+}
+
+/// Stops recording samples. The recorded samples are kept for [`dump_histogram`].
+pub fn stop() {
+    RUNNING.store(false, Ordering::Release);
+}
+
+/// Returns whether the profiler is recording.
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Acquire)
+}
+
+/// Returns the number of samples recorded since [`start`], including overwritten ones.
+pub fn total_samples() -> u64 {
+    TOTAL.load(Ordering::Relaxed)
+}
+
+/// Discards all recorded samples. Must not be called while the profiler runs.
+fn reset() {
+    NEXT.store(0, Ordering::Relaxed);
+    TOTAL.store(0, Ordering::Relaxed);
+}
+
+/// Records `rip` as a sample if the profiler is running. Called from the timer interrupt.
+pub(crate) fn sample(rip: u64) {
+    if !RUNNING.load(Ordering::Acquire) {
+        return;
+    }
+    let index = NEXT.load(Ordering::Relaxed);
+    // Safety: only the timer interrupt writes, and it does not nest with itself.
+    unsafe { (*SAMPLES.0.get())[index] = rip };
+    NEXT.store((index + 1) % SAMPLE_BUFFER_SIZE, Ordering::Relaxed);
+    TOTAL.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Stops the profiler and prints a histogram of the recorded samples over serial.
+///
+/// # Arguments
+/// * `bucket_bits` - Samples are grouped by `rip >> bucket_bits`, e.g. 0 for exact instructions, 4 for 16-byte buckets, 12 for pages.
+/// * `top` - Number of buckets to print, at most [`MAX_REPORTED_BUCKETS`].
+///
+/// The samples are sorted in place, so the buffer should be treated as consumed; call [`start`] for a new run.
+pub fn dump_histogram(bucket_bits: u32, top: usize) {
+    stop();
+    let total = total_samples();
+    let count = (total as usize).min(SAMPLE_BUFFER_SIZE);
+    // Safety: the profiler is stopped, so the timer interrupt no longer writes to the buffer.
+    let samples = unsafe { &mut (&mut *SAMPLES.0.get())[..count] };
+    let bucket_bits = bucket_bits.min(63);
+    samples.sort_unstable_by_key(|rip| rip >> bucket_bits);
+
+    // Collect the `top` largest runs of equal buckets
+    let top = top.min(MAX_REPORTED_BUCKETS);
+    let mut best = [(0u64, 0usize); MAX_REPORTED_BUCKETS];
+    let mut start = 0;
+    while start < count {
+        let bucket = samples[start] >> bucket_bits;
+        let end = start + samples[start..].partition_point(|rip| rip >> bucket_bits == bucket);
+        let hits = end - start;
+        if let Some(slot) = (0..top).min_by_key(|&i| best[i].1)
+            && hits > best[slot].1
+        {
+            best[slot] = (bucket << bucket_bits, hits);
+        }
+        start = end;
+    }
+    best[..top].sort_unstable_by_key(|&(_, hits)| core::cmp::Reverse(hits));
+
+    kprint!(
+        "[PROFILE] {} samples ({} kept), {}-byte buckets\r\n",
+        total,
+        count,
+        1u64 << bucket_bits
+    );
+    for &(address, hits) in best[..top].iter().filter(|(_, hits)| *hits != 0) {
+        kprint!(
+            "[PROFILE] {:#018x}: {:6} ({}.{}%)\r\n",
+            address,
+            hits,
+            hits * 100 / count,
+            hits * 1000 / count % 10
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! # Real-Time Clock (RTC/CMOS)
+//!
+//! This module reads the wall-clock date and time from the battery-backed Real-Time Clock found in the CMOS chip of every PC, and can enable its periodic interrupt on IRQ 8.
+//!
+//! ## How the RTC Works
+//!
+//! - **Access:** CMOS registers are selected by writing their index to port `0x70` and read or written through port `0x71`.
+//! - **Time registers:** Seconds (0x00), minutes (0x02), hours (0x04), day of month (0x07), month (0x08), and year (0x09, two digits).
+//! - **Formats:** Status register B says whether values are BCD or binary, and whether hours are 12- or 24-hour (in 12-hour mode, bit 7 of the hour marks PM).
+//! - **Updates:** Once per second the RTC updates its registers; reading during an update can return torn values. [`now`] waits for the update-in-progress flag to clear and reads until two consecutive reads agree.
+//! - **Periodic interrupt:** The RTC can raise IRQ 8 at `32768 >> (rate - 1)` Hz. Register C must be read after every interrupt, or no further interrupts are raised.
+//!
+//! The century is not read from CMOS (its register location is firmware specific); years are assumed to be 2000-2099.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use polished_x86_commands::port::Port;
+use x86_64::instructions::interrupts;
+
+use crate::irq::{self, IrqError};
+
+/// CMOS register index port.
+const CMOS_ADDRESS: u16 = 0x70;
+/// CMOS data port.
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+const REG_STATUS_C: u8 = 0x0C;
+
+/// Status A: update in progress.
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Status B: hours are in 24-hour format.
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Status B: values are binary rather than BCD.
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Status B: periodic interrupt enable.
+const STATUS_B_PERIODIC: u8 = 1 << 6;
+/// Hour register bit: PM in 12-hour mode.
+const HOUR_PM: u8 = 1 << 7;
+
+/// ISA IRQ line of the RTC.
+pub const RTC_IRQ: u8 = 8;
+
+/// Number of periodic interrupts received.
+static PERIODIC_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// A calendar date and time of day, as kept by the RTC (normally UTC).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// Full year (e.g. 2025).
+    pub year: u16,
+    /// Month, 1-12.
+    pub month: u8,
+    /// Day of month, 1-31.
+    pub day: u8,
+    /// Hour, 0-23.
+    pub hour: u8,
+    /// Minute, 0-59.
+    pub minute: u8,
+    /// Second, 0-59.
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the number of seconds since 1970-01-01 00:00:00, treating this time as UTC.
+    pub fn unix_timestamp(&self) -> u64 {
+        // Days from civil, shifted so the year starts in March (no leap day at the end of a year)
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        (days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64)
+            as u64
+    }
+}
+
+impl core::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn read_register(reg: u8) -> u8 {
+    unsafe {
+        Port::<u8>::new(CMOS_ADDRESS).write(reg);
+        Port::<u8>::new(CMOS_DATA).read()
+    }
+}
+
+fn write_register(reg: u8, value: u8) {
+    unsafe {
+        Port::<u8>::new(CMOS_ADDRESS).write(reg);
+        Port::<u8>::new(CMOS_DATA).write(value);
+    }
+}
+
+fn update_in_progress() -> bool {
+    read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+}
+
+/// Raw register values, before BCD and 12-hour conversion.
+fn read_raw() -> [u8; 6] {
+    while update_in_progress() {
+        core::hint::spin_loop();
+    }
+    [
+        read_register(REG_SECONDS),
+        read_register(REG_MINUTES),
+        read_register(REG_HOURS),
+        read_register(REG_DAY),
+        read_register(REG_MONTH),
+        read_register(REG_YEAR),
+    ]
+}
+
+fn bcd_to_binary(value: u8) -> u8 {
+    (value & 0x0F) + (value >> 4) * 10
+}
+
+/// Reads the current date and time from the RTC.
+///
+/// # Example
+/// ```ignore
+/// let now = rtc::now();
+/// info(&format!("Boot time: {now}"));
+/// ```
+pub fn now() -> DateTime {
+    let raw = interrupts::without_interrupts(|| {
+        let mut previous = read_raw();
+        loop {
+            let current = read_raw();
+            if current == previous {
+                return current;
+            }
+            previous = current;
+        }
+    });
+    let status_b = read_register(REG_STATUS_B);
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let convert = |value: u8| if binary { value } else { bcd_to_binary(value) };
+
+    let [second, minute, raw_hour, day, month, year] = raw;
+    let pm = raw_hour & HOUR_PM != 0;
+    let mut hour = convert(raw_hour & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12-hour mode: 12 AM is midnight and 12 PM is noon
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+    DateTime {
+        year: 2000 + convert(year) as u16,
+        month: convert(month),
+        day: convert(day),
+        hour,
+        minute: convert(minute),
+        second: convert(second),
+    }
+}
+
+/// Enables the RTC periodic interrupt at `32768 >> (rate - 1)` Hz and installs its IRQ 8 handler.
+///
+/// `rate` is clamped to 3-15 (8192 Hz down to 2 Hz). The caller must route or unmask IRQ 8 at the interrupt controller.
+pub fn enable_periodic_interrupt(rate: u8) -> Result<(), IrqError> {
+    irq::register_irq_handler(RTC_IRQ, rtc_interrupt_handler)?;
+    let rate = rate.clamp(3, 15);
+    interrupts::without_interrupts(|| {
+        let status_a = read_register(REG_STATUS_A);
+        write_register(REG_STATUS_A, (status_a & 0xF0) | rate);
+        let status_b = read_register(REG_STATUS_B);
+        write_register(REG_STATUS_B, status_b | STATUS_B_PERIODIC);
+        // Clear any pending interrupt so the next one is raised
+        read_register(REG_STATUS_C);
+    });
+    Ok(())
+}
+
+/// Disables the RTC periodic interrupt and removes its IRQ 8 handler.
+pub fn disable_periodic_interrupt() {
+    interrupts::without_interrupts(|| {
+        let status_b = read_register(REG_STATUS_B);
+        write_register(REG_STATUS_B, status_b & !STATUS_B_PERIODIC);
+    });
+    irq::unregister_irq_handler(RTC_IRQ);
+}
+
+/// Returns the number of RTC periodic interrupts received.
+pub fn periodic_ticks() -> u64 {
+    PERIODIC_TICKS.load(Ordering::Relaxed)
+}
+
+/// IRQ 8 handler: counts the interrupt and acknowledges it at the RTC.
+fn rtc_interrupt_handler(_irq: u8) {
+    PERIODIC_TICKS.fetch_add(1, Ordering::Relaxed);
+    read_register(REG_STATUS_C);
+}
@@ -0,0 +1,73 @@
+//! # Spurious Interrupt Detection
+//!
+//! Interrupt controllers occasionally raise an interrupt that no device actually requested. These *spurious* interrupts must not be dispatched to device handlers (which would misattribute them) and must be acknowledged carefully (a bogus EOI can acknowledge a different, real interrupt).
+//!
+//! ## Legacy PIC
+//!
+//! When an IRQ line drops before the 8259 PIC delivers it, the PIC reports its lowest-priority line instead: IRQ 7 on the master or IRQ 15 on the slave. The genuine case is distinguished by reading the In-Service Register (ISR):
+//! - **IRQ 7:** If bit 7 of the master ISR is clear, the interrupt is spurious and no EOI is sent.
+//! - **IRQ 15:** If bit 7 of the slave ISR is clear, the interrupt is spurious; the master still saw a real interrupt on its cascade line (IRQ 2), so only the master receives an EOI.
+//!
+//! ## Local APIC
+//!
+//! The Local APIC delivers spurious interrupts on its dedicated spurious vector ([`crate::apic::SPURIOUS_VECTOR`]). They are not in service, so they are only counted and never acknowledged.
+//!
+//! The number of spurious interrupts of each kind is available through [`spurious_counts`].
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use polished_x86_commands::pic8259;
+
+use crate::apic;
+
+static PIC_MASTER: AtomicU64 = AtomicU64::new(0);
+static PIC_SLAVE: AtomicU64 = AtomicU64::new(0);
+static APIC: AtomicU64 = AtomicU64::new(0);
+
+/// Number of spurious interrupts observed since boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpuriousCounts {
+    /// Spurious IRQ 7 from the master PIC.
+    pub pic_master: u64,
+    /// Spurious IRQ 15 from the slave PIC.
+    pub pic_slave: u64,
+    /// Interrupts on the Local APIC spurious vector.
+    pub apic: u64,
+}
+
+/// Returns the number of spurious interrupts observed since boot.
+pub fn spurious_counts() -> SpuriousCounts {
+    SpuriousCounts {
+        pic_master: PIC_MASTER.load(Ordering::Relaxed),
+        pic_slave: PIC_SLAVE.load(Ordering::Relaxed),
+        apic: APIC.load(Ordering::Relaxed),
+    }
+}
+
+/// Checks whether IRQ line `irq` is a spurious interrupt from the legacy PIC.
+///
+/// Returns `true` if it is, after performing any acknowledgement the PIC still needs; the caller must then neither dispatch it nor send an EOI.
+/// Always returns `false` once the Local APIC has taken over, since the PIC is masked and its lines are no longer delivered.
+pub(crate) fn check_pic_spurious(irq: u8) -> bool {
+    if apic::is_enabled() {
+        return false;
+    }
+    match irq {
+        7 if pic8259::in_service() & (1 << 7) == 0 => {
+            PIC_MASTER.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        15 if pic8259::in_service() & (1 << 15) == 0 => {
+            PIC_SLAVE.fetch_add(1, Ordering::Relaxed);
+            // Acknowledge the cascade line (IRQ 2) on the master only
+            pic8259::eoi(2);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Records an interrupt on the Local APIC spurious vector.
+pub(crate) fn record_apic_spurious() {
+    APIC.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,109 @@
+//! # Kernel Stack Registry
+//!
+//! By far the most common cause of a double fault is a kernel stack overflow: the stack grows into an unmapped page, the resulting page fault cannot push its frame onto that same stack, and the CPU escalates to a double fault. The double fault handler runs on its own IST stack, so it can still inspect the faulting RSP.
+//!
+//! The kernel registers the bounds of its stacks here (boot stack, task stacks, ...). When a double fault occurs, [`find_overflow`] compares the faulting RSP against them and the handler reports "kernel stack overflow" along with the stack limits, instead of a bare register dump.
+//!
+//! ## Example
+//! ```ignore
+//! stacks::register_kernel_stack("boot", stack_bottom, stack_top);
+//! ```
+
+use spin::Mutex;
+
+/// Maximum number of registered stacks.
+pub const MAX_KERNEL_STACKS: usize = 16;
+
+/// How far below a stack's bottom an RSP still counts as an overflow of that stack.
+///
+/// A single large stack frame can skip past the guard page, so this is generous.
+pub const OVERFLOW_WINDOW: u64 = 16 * 1024;
+
+/// Distance above a stack's bottom within which RSP counts as exhausted: the CPU needs room to push an exception frame.
+pub const EXHAUSTED_MARGIN: u64 = 256;
+
+/// Bounds of a kernel stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelStack {
+    /// Name used in crash reports.
+    pub name: &'static str,
+    /// Lowest address of the stack (the limit it grows towards).
+    pub bottom: u64,
+    /// Highest address of the stack (its initial RSP).
+    pub top: u64,
+}
+
+impl KernelStack {
+    /// Returns the size of the stack in bytes.
+    pub fn size(&self) -> u64 {
+        self.top - self.bottom
+    }
+
+    /// Returns whether `rsp` lies within the stack.
+    pub fn contains(&self, rsp: u64) -> bool {
+        (self.bottom..=self.top).contains(&rsp)
+    }
+
+    /// Returns whether `rsp` is below the stack (within [`OVERFLOW_WINDOW`]) or too close to its bottom to take an exception.
+    pub fn is_overflowed_by(&self, rsp: u64) -> bool {
+        rsp < self.bottom + EXHAUSTED_MARGIN && rsp >= self.bottom.saturating_sub(OVERFLOW_WINDOW)
+    }
+}
+
+static STACKS: Mutex<[Option<KernelStack>; MAX_KERNEL_STACKS]> =
+    Mutex::new([None; MAX_KERNEL_STACKS]);
+
+/// Registers the kernel stack `bottom..top` under `name`. Returns `false` if the registry is full.
+pub fn register_kernel_stack(name: &'static str, bottom: u64, top: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut stacks = STACKS.lock();
+        match stacks.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(KernelStack { name, bottom, top });
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Removes the stack starting at `bottom` from the registry. Returns `false` if it was not registered.
+pub fn unregister_kernel_stack(bottom: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut stacks = STACKS.lock();
+        match stacks
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|stack| stack.bottom == bottom))
+        {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Returns the registered stack that `rsp` overflowed, if any.
+///
+/// Safe to call from exception handlers: returns `None` instead of spinning if the registry is locked.
+pub fn find_overflow(rsp: u64) -> Option<KernelStack> {
+    let stacks = STACKS.try_lock()?;
+    stacks
+        .iter()
+        .flatten()
+        .copied()
+        .find(|stack| stack.is_overflowed_by(rsp))
+}
+
+/// Returns the registered stack containing `rsp`, if any.
+///
+/// Safe to call from exception handlers: returns `None` instead of spinning if the registry is locked.
+pub fn find_stack(rsp: u64) -> Option<KernelStack> {
+    let stacks = STACKS.try_lock()?;
+    stacks
+        .iter()
+        .flatten()
+        .copied()
+        .find(|stack| stack.contains(rsp))
+}
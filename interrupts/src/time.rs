@@ -0,0 +1,112 @@
+//! # Monotonic Clock and Delays
+//!
+//! This module counts timer interrupts and turns them into a monotonic clock, and provides delay functions for drivers that must wait for hardware (PS/2 controller resets, disk spin-up, ...).
+//!
+//! ## How Time is Kept
+//!
+//! - The default IRQ 0 handler calls [`tick`] on every timer interrupt, incrementing a global 64-bit counter.
+//! - The tick rate is the frequency programmed with [`crate::pit::set_frequency`], so [`uptime_ms`] is `ticks * 1000 / rate`.
+//! - Before the timer is running (interrupts disabled or PIT not programmed), [`uptime_ms`] returns 0 and the delay functions fall back to polled hardware timers.
+//!
+//! ## Delay Functions
+//!
+//! - [`sleep_busy`]: Spins until the delay has passed. Works in any context, including with interrupts disabled.
+//! - [`sleep`]: Halts the CPU between timer interrupts, which saves power. Requires interrupts to be enabled; otherwise it behaves like [`sleep_busy`].
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{hpet, pit};
+
+/// Number of timer interrupts since boot.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Records one timer interrupt. Called from the IRQ 0 handler.
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of milliseconds since the timer started ticking, or 0 if its rate is unknown.
+pub fn uptime_ms() -> u64 {
+    match pit::tick_rate_hz() {
+        0 => 0,
+        rate => ticks() * 1000 / rate as u64,
+    }
+}
+
+/// Largest value returned by [`uptime_ns`], which keeps it monotonic when the clock source changes.
+static LAST_UPTIME_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of nanoseconds since boot (monotonic).
+///
+/// Uses the HPET counter when available (nanosecond resolution), otherwise the tick counter (resolution of one tick).
+/// The result never decreases, even when the HPET takes over from the tick counter.
+pub fn uptime_ns() -> u64 {
+    let now = if hpet::is_available() {
+        hpet::nanos()
+    } else {
+        match pit::tick_rate_hz() {
+            0 => 0,
+            rate => (ticks() as u128 * 1_000_000_000 / rate as u128) as u64,
+        }
+    };
+    let last = LAST_UPTIME_NS.fetch_max(now, Ordering::Relaxed);
+    now.max(last)
+}
+
+/// Returns whether timer interrupts are currently advancing the tick counter.
+fn ticking() -> bool {
+    pit::tick_rate_hz() != 0 && x86_64::instructions::interrupts::are_enabled()
+}
+
+/// Number of ticks covering at least `ms` milliseconds (rounded up, plus one for the partial current tick).
+fn ticks_for(ms: u64) -> u64 {
+    (ms * pit::tick_rate_hz() as u64).div_ceil(1000) + 1
+}
+
+/// Spins for at least `ms` milliseconds.
+///
+/// Uses the HPET counter when available, then the tick counter, and finally polled PIT channel 2 delays.
+///
+/// # Example
+/// ```ignore
+/// time::sleep_busy(10); // give the controller time to reset
+/// ```
+pub fn sleep_busy(ms: u64) {
+    if hpet::is_available() {
+        let end = hpet::nanos() + ms * 1_000_000;
+        while hpet::nanos() < end {
+            core::hint::spin_loop();
+        }
+    } else if ticking() {
+        let end = ticks() + ticks_for(ms);
+        while ticks() < end {
+            core::hint::spin_loop();
+        }
+    } else {
+        let mut remaining = ms;
+        while remaining > 0 {
+            let chunk = remaining.min(50);
+            pit::wait_polled_ms(chunk as u16);
+            remaining -= chunk;
+        }
+    }
+}
+
+/// Halts the CPU until at least `ms` milliseconds have passed.
+///
+/// Falls back to [`sleep_busy`] if interrupts are disabled or the timer is not running.
+pub fn sleep(ms: u64) {
+    if !ticking() {
+        sleep_busy(ms);
+        return;
+    }
+    let end = ticks() + ticks_for(ms);
+    while ticks() < end {
+        x86_64::instructions::hlt();
+    }
+}
@@ -0,0 +1,195 @@
+//! # Software Timers
+//!
+//! This module lets drivers schedule callbacks after a delay ([`after`]) or at a fixed interval ([`every`]), so they can implement timeouts and polling without busy loops.
+//!
+//! ## How It Works
+//!
+//! - Timers are kept in a small table sorted by deadline (in timer ticks, see [`crate::time`]), so the next one to expire is always first.
+//! - On every tick the timer interrupt compares the current tick count with the earliest deadline, a single atomic load. When it has passed, expiry processing is queued as [deferred work](crate::deferred) instead of running in the interrupt handler.
+//! - The deferred work removes every expired timer, runs its callback with interrupts enabled, and re-arms periodic timers.
+//!
+//! Callbacks therefore run from [`crate::deferred::run_deferred`], typically in the kernel main loop, and their precision is one timer tick.
+//!
+//! ## Example
+//! ```ignore
+//! fn reset_timeout(_id: TimerId) {
+//!     warn("PS/2 controller did not answer");
+//! }
+//! let id = timer::after(500, reset_timeout)?;
+//! // ... answer received:
+//! timer::cancel(id);
+//! ```
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::{deferred, pit, time};
+
+/// Maximum number of armed timers.
+pub const MAX_TIMERS: usize = 32;
+
+/// Identifies an armed timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(u32);
+
+/// A timer callback, called with the ID of the timer that expired.
+pub type TimerCallback = fn(id: TimerId);
+
+/// Errors returned when arming a timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// The timer interrupt rate is unknown (the PIT has not been programmed).
+    NoClock,
+    /// [`MAX_TIMERS`] timers are already armed.
+    TableFull,
+}
+
+#[derive(Clone, Copy)]
+struct Timer {
+    id: TimerId,
+    deadline: u64,
+    /// Interval in ticks for periodic timers, 0 for one-shot timers.
+    period: u64,
+    callback: TimerCallback,
+}
+
+struct TimerTable {
+    /// Armed timers sorted by deadline; only the first `len` entries are used.
+    timers: [Option<Timer>; MAX_TIMERS],
+    len: usize,
+}
+
+impl TimerTable {
+    fn insert(&mut self, timer: Timer) -> bool {
+        if self.len == MAX_TIMERS {
+            return false;
+        }
+        let mut index = self.len;
+        while index > 0 && self.timers[index - 1].is_some_and(|t| t.deadline > timer.deadline) {
+            self.timers[index] = self.timers[index - 1];
+            index -= 1;
+        }
+        self.timers[index] = Some(timer);
+        self.len += 1;
+        self.publish_next_deadline();
+        true
+    }
+
+    fn remove_at(&mut self, index: usize) -> Option<Timer> {
+        let timer = self.timers[index].take();
+        for i in index..self.len - 1 {
+            self.timers[i] = self.timers[i + 1];
+        }
+        self.len -= 1;
+        self.timers[self.len] = None;
+        self.publish_next_deadline();
+        timer
+    }
+
+    fn publish_next_deadline(&self) {
+        let next = self.timers[0].map_or(u64::MAX, |t| t.deadline);
+        NEXT_DEADLINE.store(next, Ordering::Release);
+    }
+}
+
+static TIMERS: Mutex<TimerTable> = Mutex::new(TimerTable {
+    timers: [None; MAX_TIMERS],
+    len: 0,
+});
+/// Deadline of the earliest armed timer, or `u64::MAX`.
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+/// Whether expiry processing is already queued.
+static EXPIRY_QUEUED: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+fn ms_to_ticks(ms: u64) -> Result<u64, TimerError> {
+    match pit::tick_rate_hz() {
+        0 => Err(TimerError::NoClock),
+        rate => Ok((ms * rate as u64).div_ceil(1000).max(1)),
+    }
+}
+
+fn arm(ms: u64, period_ms: Option<u64>, callback: TimerCallback) -> Result<TimerId, TimerError> {
+    let delay = ms_to_ticks(ms)?;
+    let period = match period_ms {
+        Some(period_ms) => ms_to_ticks(period_ms)?,
+        None => 0,
+    };
+    let id = TimerId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let timer = Timer {
+        id,
+        deadline: time::ticks() + delay,
+        period,
+        callback,
+    };
+    interrupts::without_interrupts(|| TIMERS.lock().insert(timer))
+        .then_some(id)
+        .ok_or(TimerError::TableFull)
+}
+
+/// Calls `callback` once, at least `ms` milliseconds from now.
+pub fn after(ms: u64, callback: TimerCallback) -> Result<TimerId, TimerError> {
+    arm(ms, None, callback)
+}
+
+/// Calls `callback` every `ms` milliseconds until the timer is cancelled.
+pub fn every(ms: u64, callback: TimerCallback) -> Result<TimerId, TimerError> {
+    arm(ms, Some(ms), callback)
+}
+
+/// Disarms the timer `id`. Returns `false` if it already expired (one-shot) or was cancelled.
+pub fn cancel(id: TimerId) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut table = TIMERS.lock();
+        match (0..table.len).find(|&i| table.timers[i].is_some_and(|t| t.id == id)) {
+            Some(index) => table.remove_at(index).is_some(),
+            None => false,
+        }
+    })
+}
+
+/// Returns the number of armed timers.
+pub fn armed() -> usize {
+    interrupts::without_interrupts(|| TIMERS.lock().len)
+}
+
+/// Called from the timer interrupt: queues expiry processing when the earliest deadline has passed.
+pub(crate) fn on_tick() {
+    if time::ticks() >= NEXT_DEADLINE.load(Ordering::Acquire)
+        && !EXPIRY_QUEUED.swap(true, Ordering::AcqRel)
+        && !deferred::defer(run_expired, 0)
+    {
+        // Queue full: retry on the next tick
+        EXPIRY_QUEUED.store(false, Ordering::Release);
+    }
+}
+
+/// Deferred work: runs the callbacks of all expired timers and re-arms periodic ones.
+fn run_expired(_arg: usize) {
+    EXPIRY_QUEUED.store(false, Ordering::Release);
+    loop {
+        let now = time::ticks();
+        let expired = interrupts::without_interrupts(|| {
+            let mut table = TIMERS.lock();
+            match table.timers[0] {
+                Some(timer) if timer.deadline <= now => {
+                    table.remove_at(0);
+                    if timer.period != 0 {
+                        table.insert(Timer {
+                            deadline: now + timer.period,
+                            ..timer
+                        });
+                    }
+                    Some(timer)
+                }
+                _ => None,
+            }
+        });
+        match expired {
+            Some(timer) => (timer.callback)(timer.id),
+            None => break,
+        }
+    }
+}
@@ -3,20 +3,90 @@
 
 extern crate alloc;
 
-use polished_interrupts::init_idt;
-use polished_memory as _;
+use polished_acpi::AcpiTables;
+use polished_interrupts::{
+    apic, context, cpu_exceptions, deferred, hardware_interrupts, hpet, init_idt, ioapic, irq,
+    keyboard, mmio, pit, rtc, stacks,
+};
+use polished_memory::address_space;
+use polished_memory::boot_modules;
+use polished_memory::firmware::{self, FirmwareKind};
+use polished_memory::frame::{self, GlobalFrameAllocator};
+use polished_memory::heap::{self, KernelHeap};
+use polished_memory::hhdm;
+use polished_memory::memory_map::{self, MemoryKind, MemoryMap};
+use polished_memory::paging::{self, PAGE_SIZE, Protection};
+use polished_memory::percpu;
+use polished_memory::stack;
+use polished_memory::uefi_map::UefiMemoryMap;
+use polished_memory::vmm::{self, Region, RegionKind};
 use polished_panic_handler as _; // Import the panic handler // Import the memory module for memset, memcpy, etc.
 
 use alloc::format;
 use core::arch::{asm, naked_asm};
-use linked_list_allocator::LockedHeap;
 use polished_graphics::drawing::framebuffer_x_demo;
-use polished_graphics::framebuffer::FramebufferInfo;
+use polished_graphics::framebuffer::{FramebufferFormat, FramebufferInfo};
+use polished_input::sysrq::{self, Chord, DebugAction};
+use polished_input::{InputEvent, KeyboardEvent};
+use polished_ps2::mouse::{self, MouseEvent};
 use polished_ps2::ps2_init;
-use polished_serial_logging::{info, warn};
+use polished_scancodes::{KeyCode, keymap};
+use polished_serial_logging::{info, init_logging, warn};
+use polished_syscalls::framebuffer;
+use polished_syscalls::mm::{self, UserPageMapper};
+use polished_syscalls::task::{self, TaskId};
+use polished_x86_commands::{simd, tsc};
+use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
 
+/// The kernel heap, wrapped in redzone checks with the `redzone-heap` feature.
+#[cfg(not(feature = "redzone-heap"))]
+type CheckedHeap = KernelHeap;
+#[cfg(feature = "redzone-heap")]
+type CheckedHeap = polished_memory::redzone::RedzoneHeap<KernelHeap>;
+
+/// Returns the kernel heap before initialization, wrapped as [`CheckedHeap`].
+const fn empty_heap() -> CheckedHeap {
+    #[cfg(feature = "redzone-heap")]
+    return polished_memory::redzone::RedzoneHeap::new(KernelHeap::empty());
+    #[cfg(not(feature = "redzone-heap"))]
+    return KernelHeap::empty();
+}
+
+#[cfg(not(feature = "debug-heap"))]
+#[global_allocator]
+static ALLOCATOR: CheckedHeap = empty_heap();
+
+#[cfg(feature = "debug-heap")]
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: polished_memory::debug_heap::DebugHeap<CheckedHeap> =
+    polished_memory::debug_heap::DebugHeap::new(empty_heap());
+
+/// Returns the global allocator without the debug heap wrapper.
+fn checked_heap() -> &'static CheckedHeap {
+    #[cfg(feature = "debug-heap")]
+    return ALLOCATOR.inner();
+    #[cfg(not(feature = "debug-heap"))]
+    return &ALLOCATOR;
+}
+
+/// Returns the kernel heap behind the global allocator.
+fn kernel_heap() -> &'static KernelHeap {
+    #[cfg(feature = "redzone-heap")]
+    return checked_heap().inner();
+    #[cfg(not(feature = "redzone-heap"))]
+    return checked_heap();
+}
+
+/// Checks the redzones of every live heap allocation when built with the `redzone-heap` feature.
+fn check_heap_redzones() {
+    #[cfg(feature = "redzone-heap")]
+    match checked_heap().check_all() {
+        0 => info("Heap redzones intact"),
+        damaged => warn(&format!("{damaged} heap allocations have damaged redzones")),
+    }
+}
 
 #[unsafe(naked)]
 #[unsafe(no_mangle)]
@@ -24,7 +94,7 @@ unsafe extern "C" fn naked_start() {
     // Set up the stack pointer to the top of the stack
     naked_asm!(
         "cli",
-        "lea rsp, STACK_TOP",
+        "lea rsp, [rip + STACK_TOP]",
         "call kernel_entry",
         "2:",
         "cli",
@@ -33,13 +103,119 @@ unsafe extern "C" fn naked_start() {
     );
 }
 
-fn init_allocator() {
-    let heap_start = 0x1000_0000; // Example heap start address
-    let heap_size = 0x0100_0000; // Example heap size (16 MB)
+/// Builds the kernel's memory map from the UEFI one, hands its free memory to the frame allocator and sets up the kernel heap on top of it.
+///
+/// The buffer holding the UEFI map is reserved as a boot module; it can be released once the map has been read.
+fn init_allocator(uefi_map: &UefiMemoryMap) {
+    unsafe extern "C" {
+        static KERNEL_START: u8;
+        static STACK_TOP: u8;
+    }
+    let mut map = MemoryMap::from_uefi(uefi_map);
+    map.mark(
+        &raw const KERNEL_START as u64,
+        &raw const STACK_TOP as u64,
+        MemoryKind::KernelImage,
+    );
+    let (map_start, map_end) = uefi_map.buffer_range();
+    if let Err(e) = boot_modules::reserve(
+        &mut map,
+        UEFI_MEMORY_MAP_MODULE,
+        map_start,
+        map_end,
+        MemoryKind::Loader,
+    ) {
+        warn(&format!("Could not reserve the UEFI memory map: {e:?}"));
+    }
+    frame::init(map.usable_ranges());
+    // Before any new mapping, so the direct map and the heap get NO_EXECUTE.
+    paging::enable_nx();
+    if !paging::init_pat() {
+        warn("No PAT support, the framebuffer will not be write-combining");
+    }
+    if let Err(e) = unsafe { hhdm::init(map.ram_end()) } {
+        panic!("Could not map physical memory at the direct map offset: {e:?}");
+    }
+    let initial_size = heap::initial_size(frame::free_frames());
+    if let Err(e) = unsafe { kernel_heap().init(initial_size) } {
+        panic!("Could not set up the kernel heap: {e:?}");
+    }
+    protect_kernel_image();
+    if let Err(e) = unsafe { address_space::init_kernel_space() } {
+        panic!("Could not share the kernel page tables with user address spaces: {e:?}");
+    }
+    #[cfg(feature = "heap-bench")]
+    polished_memory::heap_bench::run(&ALLOCATOR);
+    memory_map::install(map);
+}
 
-    unsafe {
-        ALLOCATOR.lock().init(heap_start as *mut u8, heap_size);
+/// Name of the boot module holding the UEFI memory map.
+const UEFI_MEMORY_MAP_MODULE: &str = "uefi memory map";
+
+/// The parts of the kernel image, as laid out by the linker script, with the protection each is mapped with: text is executable but read-only, everything else is not executable.
+fn kernel_sections() -> [(&'static str, u64, u64, RegionKind, Protection); 5] {
+    unsafe extern "C" {
+        static TEXT_START: u8;
+        static TEXT_END: u8;
+        static DATA_START: u8;
+        static DATA_END: u8;
+        static RODATA_START: u8;
+        static RODATA_END: u8;
+        static BSS_START: u8;
+        static STACK_BOTTOM: u8;
+        static STACK_TOP: u8;
     }
+    let symbol = |s: *const u8| s as u64;
+    let image = RegionKind::KernelImage;
+    [
+        (
+            "kernel text",
+            symbol(&raw const TEXT_START),
+            paging::page_align_up(symbol(&raw const TEXT_END)),
+            image,
+            Protection::READ | Protection::EXECUTE,
+        ),
+        (
+            "kernel data",
+            symbol(&raw const DATA_START),
+            paging::page_align_up(symbol(&raw const DATA_END)),
+            image,
+            Protection::READ_WRITE,
+        ),
+        (
+            "kernel rodata",
+            symbol(&raw const RODATA_START),
+            paging::page_align_up(symbol(&raw const RODATA_END)),
+            image,
+            Protection::READ,
+        ),
+        (
+            "kernel bss",
+            symbol(&raw const BSS_START),
+            symbol(&raw const STACK_BOTTOM),
+            image,
+            Protection::READ_WRITE,
+        ),
+        (
+            "boot stack",
+            symbol(&raw const STACK_BOTTOM),
+            symbol(&raw const STACK_TOP),
+            RegionKind::KernelStack,
+            Protection::READ_WRITE,
+        ),
+    ]
+}
+
+/// Remaps the kernel image with the protection of each section and turns on write protection for the kernel (W^X).
+fn protect_kernel_image() {
+    for (name, start, end, _, protection) in kernel_sections() {
+        if let Err(e) =
+            unsafe { paging::protect_range(start, end, protection, &mut GlobalFrameAllocator) }
+        {
+            warn(&format!("Could not protect {name}: {e:?}"));
+        }
+    }
+    paging::enable_write_protect();
 }
 
 fn log_framebuffer_info(fb_info_ptr: *const FramebufferInfo) {
@@ -73,22 +249,450 @@ fn init_interrupts() {
     info("Loading IDT...");
     init_idt();
     info("IDT loaded");
+    hardware_interrupts::init_legacy_pic();
+    polished_ps2::set_irq_mask_hook(hardware_interrupts::set_irq_masked);
+    register_boot_stack();
+    let hz = pit::set_frequency(pit::DEFAULT_FREQUENCY_HZ);
+    info(&format!("PIT channel 0 programmed to {hz} Hz"));
+    let tsc_hz = tsc::calibrate();
+    info(&format!("TSC runs at {} MHz", tsc_hz / 1_000_000));
+    info(&format!("RTC time: {} UTC", rtc::now()));
+}
+
+/// Records the memory the kernel already uses as VMM regions, resolves demand-paged faults and explains the others with them.
+fn init_memory_regions(fb_info_ptr: *const FramebufferInfo) {
+    let regions = kernel_sections()
+        .into_iter()
+        .filter(|&(_, start, end, _, _)| end > start)
+        .map(|(name, start, end, kind, protection)| {
+            Region::new(name, start, end - start, kind, protection)
+        });
+    for region in regions {
+        if let Err(e) = vmm::reserve(region) {
+            warn(&format!("Could not record region {}: {e:?}", region.name));
+        }
+    }
+    remap_framebuffer(fb_info_ptr);
+    cpu_exceptions::set_page_fault_hook(handle_page_fault);
+    mm::set_user_page_mapper(UserPageMapper {
+        map: map_user_page,
+        map_physical: map_user_physical,
+        unmap: unmap_user_page,
+        reserve_on_demand: reserve_user_on_demand,
+    });
+    task::set_address_space_switch_hook(switch_address_space);
+    task::set_address_space_release_hook(release_address_space);
+    vmm::dump();
+}
+
+/// Virtual address the framebuffer is mapped at, right after the kernel stack slots.
+const FRAMEBUFFER_START: u64 =
+    stack::KERNEL_STACKS_START + stack::MAX_KERNEL_STACKS as u64 * stack::STACK_SLOT_SIZE;
+
+/// Maps the framebuffer at [`FRAMEBUFFER_START`], write-combining if the PAT allows it, and points `FramebufferInfo::address` there.
+///
+/// If the mapping fails, drawing keeps using the firmware's identity mapping.
+fn remap_framebuffer(fb_info_ptr: *const FramebufferInfo) {
+    if fb_info_ptr.is_null() {
+        return;
+    }
+    let fb = unsafe { &mut *(fb_info_ptr as *mut FramebufferInfo) };
+    let offset = fb.physical_address % 4096;
+    let region = Region::new(
+        "framebuffer",
+        FRAMEBUFFER_START,
+        fb.size as u64 + offset,
+        RegionKind::Framebuffer,
+        Protection::READ_WRITE,
+    );
+    let physical = fb.physical_address - offset;
+    match unsafe { vmm::map_region_to(region, physical, &mut GlobalFrameAllocator) } {
+        Ok(()) => {
+            fb.address = FRAMEBUFFER_START + offset;
+            info(&format!(
+                "Framebuffer remapped at {:#x} ({})",
+                fb.address,
+                if paging::write_combining_enabled() {
+                    "write-combining"
+                } else {
+                    "default caching"
+                }
+            ));
+        }
+        Err(e) => warn(&format!("Could not remap the framebuffer: {e:?}")),
+    }
+}
+
+/// Page fault hook: populates demand-paged regions, and otherwise reports the VMM region of the faulting address. Task IDs double as address space IDs.
+fn handle_page_fault(address: u64, error_code: PageFaultErrorCode) -> bool {
+    let space = polished_syscalls::task::current_task_id();
+    if vmm::handle_page_fault(space, address, error_code) {
+        return true;
+    }
+    vmm::report_fault(space, address);
+    false
+}
+
+/// User page mapper hooks: map into the task's own page tables, creating them on first use.
+fn map_user_page(task: TaskId, page: VirtAddr, flags: PageTableFlags) -> bool {
+    let Some(frame) = frame::allocate_frame() else {
+        return false;
+    };
+    let physical = frame.start_address().as_u64();
+    let zeroed = hhdm::phys_to_virt(physical) as *mut u8;
+    unsafe { core::ptr::write_bytes(zeroed, 0, PAGE_SIZE as usize) };
+    let mapped = address_space::with_space(task, |space| unsafe {
+        space.map_page(page.as_u64(), physical, flags).is_ok()
+    })
+    .unwrap_or(false);
+    if !mapped {
+        unsafe { frame::deallocate_frame(frame) };
+    }
+    mapped
+}
+
+fn map_user_physical(task: TaskId, page: VirtAddr, frame: PhysAddr, flags: PageTableFlags) -> bool {
+    address_space::with_space(task, |space| unsafe {
+        space.map_page(page.as_u64(), frame.as_u64(), flags).is_ok()
+    })
+    .unwrap_or(false)
+}
+
+fn unmap_user_page(task: TaskId, page: VirtAddr) {
+    let unmapped =
+        address_space::with_space(task, |space| unsafe { space.unmap_page(page.as_u64()) });
+    if let Ok(Ok(frame)) = unmapped
+        && memory_map::kind_of(frame.start_address().as_u64()) == Some(MemoryKind::Usable)
+    {
+        unsafe { frame::deallocate_frame(frame) };
+    }
+}
+
+fn reserve_user_on_demand(
+    task: TaskId,
+    start: VirtAddr,
+    end: VirtAddr,
+    flags: PageTableFlags,
+) -> bool {
+    let mut protection = Protection::READ | Protection::USER;
+    if flags.contains(PageTableFlags::WRITABLE) {
+        protection = protection | Protection::WRITE;
+    }
+    if !flags.contains(PageTableFlags::NO_EXECUTE) {
+        protection = protection | Protection::EXECUTE;
+    }
+    let region = Region::new(
+        "mmap",
+        start.as_u64(),
+        end - start,
+        RegionKind::UserHeap,
+        protection,
+    );
+    vmm::reserve(region.in_space(task).demand_paged()).is_ok()
+}
+
+/// Address space switch hook: loads the page tables of the task becoming current.
+fn switch_address_space(task: TaskId) {
+    if let Err(e) = unsafe { address_space::switch_to(task) } {
+        warn(&format!(
+            "Could not switch to the address space of task {task}: {e:?}"
+        ));
+    }
+}
+
+/// Address space release hook: frees the page tables, frames and regions of an exited task.
+fn release_address_space(task: TaskId) {
+    // A task that never ran or mapped anything has no address space.
+    let _ = address_space::destroy(task);
+}
+
+/// Registers the boot stack reserved by the linker script, so double faults can report overflows of it.
+fn register_boot_stack() {
+    unsafe extern "C" {
+        static STACK_BOTTOM: u8;
+        static STACK_TOP: u8;
+    }
+    let bottom = &raw const STACK_BOTTOM as u64;
+    let top = &raw const STACK_TOP as u64;
+    stacks::register_kernel_stack("boot", bottom, top);
+}
+
+/// Switches from the legacy PIC to the Local APIC and I/O APIC described by the ACPI MADT.
+///
+/// The PIT is routed to the high-priority clock vector and the keyboard, mouse and ATA IRQs to the vectors already used by the IDT, on the boot CPU.
+/// If ACPI or the APIC is unavailable, the legacy PIC configuration is kept.
+fn init_interrupt_controllers(rsdp_address: u64) {
+    polished_acpi::set_physical_mapper(map_acpi_table);
+    mmio::set_mmio_mapper(map_firmware_mmio);
+    let tables = match unsafe { AcpiTables::from_rsdp(rsdp_address) } {
+        Ok(tables) => tables,
+        Err(e) => {
+            warn(&format!(
+                "ACPI tables unavailable ({e:?}), keeping the legacy PIC"
+            ));
+            return;
+        }
+    };
+    init_hpet(&tables);
+    let Some(madt) = tables.madt() else {
+        warn("No MADT found, keeping the legacy PIC");
+        return;
+    };
+    unsafe { ioapic::init_from_madt(&madt) };
+    if let Err(e) = unsafe { apic::init() } {
+        warn(&format!(
+            "Local APIC unavailable ({e:?}), keeping the legacy PIC"
+        ));
+        return;
+    }
+    info(&format!(
+        "Local APIC enabled in {:?} mode, ID {}",
+        apic::mode(),
+        apic::id()
+    ));
+    info(&format!(
+        "Local APIC timer calibrated at {} Hz",
+        apic::calibrate_timer()
+    ));
+    let cpu = apic::id() as u8;
+    for irq in [0, 1, 12, 14, 15] {
+        if !hardware_interrupts::route_isa_irq_to_io_apic(&madt, irq, cpu) {
+            warn(&format!("No I/O APIC serves ISA IRQ {irq}"));
+        }
+    }
+    info(&format!(
+        "Local APIC {cpu} enabled at 0x{:x}, ISA IRQs routed through the I/O APIC",
+        apic::base_address()
+    ));
+    match polished_smp::start_secondary_cpus(&madt) {
+        Ok(started) => info(&format!("{} CPUs online", started + 1)),
+        Err(e) => warn(&format!(
+            "Application processors not started ({e:?}), running on the boot CPU only"
+        )),
+    }
+}
+
+/// ACPI mapper hook: maps tables read-only through the firmware window.
+fn map_acpi_table(address: u64, size: usize) -> Option<u64> {
+    firmware::map("acpi table", address, size as u64, FirmwareKind::Table).ok()
+}
+
+/// MMIO mapper hook: maps APIC and HPET registers uncached through the firmware window.
+fn map_firmware_mmio(name: &'static str, physical: u64, size: u64) -> Option<u64> {
+    match firmware::map(name, physical, size, FirmwareKind::Mmio) {
+        Ok(virt) => Some(virt),
+        Err(e) => {
+            warn(&format!("Could not map {name} at {physical:#x}: {e:?}"));
+            None
+        }
+    }
+}
+
+/// Starts the HPET main counter if the firmware describes one.
+fn init_hpet(tables: &AcpiTables) {
+    let Some(table) = tables.hpet() else {
+        info("No HPET found");
+        return;
+    };
+    match unsafe { hpet::init(table.base_address()) } {
+        Ok(()) => info(&format!(
+            "HPET at 0x{:x}: {} Hz, {} timers",
+            table.base_address(),
+            hpet::frequency_hz(),
+            hpet::timer_count()
+        )),
+        Err(e) => warn(&format!("HPET unavailable ({e:?})")),
+    }
+}
+
+/// Keyboard layout name, set at build time through `POLISHED_KEYMAP` as the kernel has no command line yet.
+const KEYMAP: Option<&str> = option_env!("POLISHED_KEYMAP");
+
+/// Selects the keyboard layout named by [`KEYMAP`], keeping US QWERTY if it is unset or unknown.
+fn init_keymap() {
+    let Some(name) = KEYMAP else {
+        return;
+    };
+    match keymap::by_name(name) {
+        Some(layout) => {
+            keyboard::set_keymap(layout);
+            info(&format!("Keyboard layout: {}", layout.name()));
+        }
+        None => warn(&format!("Unknown keyboard layout \"{name}\", using us")),
+    }
+}
+
+/// Logs a key press popped from the input queue.
+fn log_key_event(event: KeyboardEvent) {
+    if !event.key.is_pressed() {
+        return;
+    }
+    match event.character {
+        Some(c) if !c.is_control() => info(&format!(
+            "Key pressed: {:?}, scancode: {:#x} | Character: '{}'",
+            event.key.code, event.scancode, c
+        )),
+        _ => info(&format!(
+            "Key pressed: {:?}, scancode: {:#x} | Character: Unknown",
+            event.key.code, event.scancode
+        )),
+    }
+}
+
+/// Registers the debug key combos: Alt+SysRq+T dumps the task list, Alt+SysRq+M the memory statistics, and Alt+SysRq+B or Ctrl+Alt+Del reboot.
+fn init_sysrq() {
+    let actions: [(Chord, &'static str, DebugAction); 4] = [
+        (Chord::SysRq(KeyCode::T), "dump task list", task::dump),
+        (
+            Chord::SysRq(KeyCode::M),
+            "dump memory stats",
+            polished_memory::stats::dump,
+        ),
+        (Chord::SysRq(KeyCode::B), "reboot", || {
+            polished_ps2::reboot()
+        }),
+        (Chord::CtrlAltDel, "reboot", || polished_ps2::reboot()),
+    ];
+    for (chord, name, action) in actions {
+        if let Err(e) = sysrq::register(chord, name, action) {
+            warn(&format!("Could not register {chord:?} ({name}): {e:?}"));
+        }
+    }
+}
+
+/// Queues a mouse event from the IRQ 12 handler as an input event.
+fn queue_mouse_event(event: MouseEvent) {
+    polished_input::push(InputEvent::Mouse(event));
+}
+
+/// Logs mouse button presses and releases and wheel scrolling; movement alone is not logged.
+fn log_mouse_event(event: MouseEvent) {
+    if event.changed != mouse::MouseButtons::default() {
+        info(&format!(
+            "Mouse buttons: {:?}, movement ({}, {})",
+            event.buttons, event.dx, event.dy
+        ));
+    }
+    if event.wheel != 0 {
+        info(&format!("Mouse wheel: {}", event.wheel));
+    }
+}
+
+/// Initializes the PS/2 controller, the keyboard and the mouse, continuing without those that are missing.
+fn init_ps2() {
+    let devices = match ps2_init() {
+        Ok(devices) => devices,
+        Err(e) => {
+            warn(&format!(
+                "No usable PS/2 controller ({e:?}), continuing without keyboard and mouse"
+            ));
+            return;
+        }
+    };
+    if devices.keyboard() {
+        keyboard::set_scancode_set(polished_ps2::scancode_set());
+        keyboard::set_reset_handler(polished_ps2::reconfigure_keyboard);
+        init_keymap();
+        init_sysrq();
+    }
+    if devices.mouse() {
+        init_mouse();
+    } else {
+        warn("No PS/2 mouse");
+    }
+}
+
+/// Initializes the PS/2 mouse and installs its IRQ 12 handler.
+fn init_mouse() {
+    if !mouse::init() {
+        warn("No PS/2 mouse");
+        return;
+    }
+    mouse::set_event_handler(queue_mouse_event);
+    if let Err(e) = irq::register_irq_handler(mouse::MOUSE_IRQ, mouse::irq_handler) {
+        warn(&format!("Could not install the mouse IRQ handler: {e:?}"));
+    }
+}
+
+/// Makes the framebuffer available to user programs through the `fb_map` syscall.
+fn share_framebuffer(fb_info_ptr: *const FramebufferInfo) {
+    if fb_info_ptr.is_null() {
+        return;
+    }
+    let fb = unsafe { &*fb_info_ptr };
+    let format = match fb.format {
+        FramebufferFormat::Rgb => framebuffer::FB_FORMAT_RGB,
+        FramebufferFormat::Bgr => framebuffer::FB_FORMAT_BGR,
+        FramebufferFormat::Bitmask => framebuffer::FB_FORMAT_BITMASK,
+        FramebufferFormat::BltOnly => return,
+    };
+    framebuffer::set_framebuffer(
+        fb.physical_address,
+        fb.size as u64,
+        fb.width as u64,
+        fb.height as u64,
+        fb.stride as u64,
+        format,
+    );
 }
 
 /// # Safety
 /// This function must be called only as the kernel entry point, and the provided
 /// `fb_info_ptr` must be a valid pointer to a `FramebufferInfo` structure, or null.
+/// `rsdp_address` is the physical address of the ACPI RSDP, or 0 if unavailable.
+/// `memory_map` points to `memory_map_size` bytes of UEFI memory descriptors, each
+/// `descriptor_size` bytes long, or is null. `kernel_slide` is how far the bootloader
+/// moved the kernel above its link address (KASLR), or 0.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn kernel_entry(fb_info_ptr: *const FramebufferInfo) -> ! {
-    init_allocator();
+pub unsafe extern "C" fn kernel_entry(
+    fb_info_ptr: *const FramebufferInfo,
+    rsdp_address: u64,
+    memory_map: *const u8,
+    memory_map_size: usize,
+    descriptor_size: usize,
+    kernel_slide: u64,
+) -> ! {
+    // Before any code the compiler may have vectorized.
+    let simd = simd::enable_simd();
+    init_logging();
+    context::set_kernel_slide(kernel_slide);
+    let uefi_map = unsafe { UefiMemoryMap::new(memory_map, memory_map_size, descriptor_size) };
+    init_allocator(&uefi_map);
+    // The kernel has its own copy of the memory map now.
+    let _ = unsafe { boot_modules::release(UEFI_MEMORY_MAP_MODULE) };
     info("Hello from the kernel!");
+    info(&format!("Kernel loaded with KASLR slide {kernel_slide:#x}"));
+    info(&format!("SIMD enabled: {simd:?}"));
+    memory_map::with(|map| {
+        info(&format!(
+            "Memory map: {} regions, {} MiB usable, kernel heap {} KiB",
+            map.regions().len(),
+            map.usable_bytes() >> 20,
+            kernel_heap().size() / 1024
+        ));
+        map.dump();
+    });
+    match percpu::init_cpu(0) {
+        Ok(cpu) => info(&format!("Per-CPU data for CPU 0 (APIC ID {})", cpu.apic_id)),
+        Err(e) => panic!("Could not set up per-CPU data: {e:?}"),
+    }
     info("Initializing GDT...");
     polished_gdt::init_gdt();
     info("GDT initialized");
+    polished_syscalls::init_syscalls();
+    info("syscall/sysret enabled");
     init_interrupts();
-    ps2_init();
+    init_ps2();
+    init_interrupt_controllers(rsdp_address);
+    match polished_syscalls::vdso::init() {
+        Ok(()) => info("Shared time page initialized"),
+        Err(e) => warn(&format!("Shared time page unavailable ({e:?})")),
+    }
     log_framebuffer_info(fb_info_ptr);
+    init_memory_regions(fb_info_ptr);
+    polished_memory::stats::dump();
     clear_framebuffer(fb_info_ptr);
+    share_framebuffer(fb_info_ptr);
     x86_64::instructions::interrupts::enable();
     // Only disable the PIC after confirming interrupts work, or comment out for now
     // info("Disabling legacy PIC...");
@@ -96,12 +700,20 @@ pub unsafe extern "C" fn kernel_entry(fb_info_ptr: *const FramebufferInfo) -> !
     // info("Legacy PIC disabled");
     // simulate_divide_by_zero();
 
+    check_heap_redzones();
     // Loop forever to keep the kernel running
     info("Kernel initialized successfully, entering main loop...");
     unsafe {
         asm!("sti");
     }
     loop {
+        deferred::run_deferred();
+        while let Some(event) = polished_input::pop() {
+            match event {
+                InputEvent::Key(key) => log_key_event(key),
+                InputEvent::Mouse(mouse) => log_mouse_event(mouse),
+            }
+        }
         unsafe { asm!("hlt") }; // Halt the CPU until the next interrupt
     }
 
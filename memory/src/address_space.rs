@@ -0,0 +1,376 @@
+//! # Address Spaces
+//!
+//! Every user task runs on its own page tables. An [`AddressSpace`] owns a PML4 whose user half is private and whose kernel half points at the same page tables as the kernel's PML4, so kernel code, the heap, kernel stacks and the direct map look the same whichever address space is active. Switching tasks is then a single CR3 write ([`switch_to`]).
+//!
+//! ## Layout
+//!
+//! | PML4 entries | Range | Owner |
+//! | --- | --- | --- |
+//! | 0 | `0..USER_SPACE_START` | Kernel: the kernel image and the firmware's identity mapping |
+//! | 1..256 | `USER_SPACE_START..USER_SPACE_END` | The address space |
+//! | 256..512 | Upper half | Kernel: direct map, heap, stacks, framebuffer |
+//!
+//! Kernel entries are copied when an address space is created, so they must point at page tables that never change: [`init_kernel_space`] gives every empty upper half entry of the kernel PML4 an empty page directory pointer table up front, after which kernel mappings only ever change tables below the PML4 and show up in all address spaces at once. The kernel entries are not user accessible, so user code cannot reach kernel memory through them.
+//!
+//! ## Lifetime
+//!
+//! Address spaces are kept in a table keyed by [`AddressSpaceId`] (task IDs double as address space IDs). [`with_space`] creates the address space of a task on first use, and [`destroy`] frees its user page tables, the frames mapped in it that came from the frame allocator, and its VMM regions. Pages of the user half are mapped with [`AddressSpace::map_page`], which works whether or not the address space is active.
+//!
+//! ## Example
+//! ```ignore
+//! address_space::init_kernel_space()?;
+//! address_space::with_space(task, |space| unsafe { space.map_page(page, frame, flags) })??;
+//! unsafe { address_space::switch_to(task) }?;
+//! // ... run the task ...
+//! address_space::destroy(task)?;
+//! ```
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{
+    Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::frame::{self, GlobalFrameAllocator};
+use crate::hhdm::phys_to_virt;
+use crate::memory_map::{self, MemoryKind};
+use crate::paging::physical_memory_offset;
+use crate::vmm::{self, AddressSpaceId, KERNEL_SPACE};
+
+/// First address of the user half, the start of PML4 entry [`FIRST_USER_ENTRY`].
+pub const USER_SPACE_START: u64 = 1 << 39;
+/// End of the user half, the start of the canonical hole.
+pub const USER_SPACE_END: u64 = 1 << 47;
+/// First PML4 entry owned by an address space.
+pub const FIRST_USER_ENTRY: usize = 1;
+/// PML4 entry after the last one owned by an address space.
+pub const END_USER_ENTRY: usize = 256;
+/// Maximum number of user address spaces.
+pub const MAX_ADDRESS_SPACES: usize = 64;
+
+/// Errors from creating, using or destroying address spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpaceError {
+    /// No frame was free for a page table.
+    OutOfFrames,
+    /// [`init_kernel_space`] has not run.
+    NotInitialized,
+    /// The ID is [`KERNEL_SPACE`], which has no address space of its own.
+    KernelSpace,
+    /// [`MAX_ADDRESS_SPACES`] address spaces already exist.
+    TableFull,
+    /// No address space has this ID.
+    NotFound,
+    /// The address is outside `USER_SPACE_START..USER_SPACE_END`.
+    NotUserAddress,
+    /// The page is already mapped.
+    AlreadyMapped,
+    /// The page is not mapped.
+    NotMapped,
+}
+
+/// Physical address of the kernel's PML4, 0 until [`init_kernel_space`].
+static KERNEL_PML4: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the kernel's PML4, the one active while no user task runs.
+pub fn kernel_pml4() -> Option<PhysFrame> {
+    match KERNEL_PML4.load(Ordering::Acquire) {
+        0 => None,
+        address => Some(PhysFrame::containing_address(PhysAddr::new(address))),
+    }
+}
+
+/// Returns the page table in `frame`, reached through the direct map.
+///
+/// # Safety
+/// `frame` must hold a page table, and no other reference to it may be live.
+unsafe fn table_at(frame: PhysFrame) -> &'static mut PageTable {
+    unsafe { &mut *(phys_to_virt(frame.start_address().as_u64()) as *mut PageTable) }
+}
+
+/// Allocates a zeroed page table.
+fn allocate_table() -> Result<PhysFrame, AddressSpaceError> {
+    let frame = frame::allocate_frame().ok_or(AddressSpaceError::OutOfFrames)?;
+    unsafe { core::ptr::write_bytes(table_at(frame) as *mut PageTable, 0, 1) };
+    Ok(frame)
+}
+
+/// Returns whether PML4 entry `index` is shared with the kernel.
+const fn is_kernel_entry(index: usize) -> bool {
+    index < FIRST_USER_ENTRY || index >= END_USER_ENTRY
+}
+
+/// Records the active PML4 as the kernel's and gives each of its empty upper half entries an empty table, so later kernel mappings are shared by every address space.
+///
+/// Mappings the firmware left in the user half of the kernel PML4 stay visible to the kernel only.
+///
+/// # Errors
+/// Fails if page table frames run out.
+///
+/// # Safety
+/// Must be called once, after [`crate::hhdm::init`], while the kernel's page tables are active and no other code changes them.
+pub unsafe fn init_kernel_space() -> Result<(), AddressSpaceError> {
+    let (pml4, _) = Cr3::read();
+    let table = unsafe { table_at(pml4) };
+    for index in END_USER_ENTRY..512 {
+        if table[index].is_unused() {
+            let pdpt = allocate_table()?;
+            table[index].set_frame(pdpt, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        }
+    }
+    let private = (FIRST_USER_ENTRY..END_USER_ENTRY)
+        .filter(|&index| !table[index].is_unused())
+        .count();
+    if private > 0 {
+        polished_serial_logging::warn!(
+            "{} user half PML4 entries are mapped in the kernel and hidden from user address spaces",
+            private
+        );
+    }
+    KERNEL_PML4.store(pml4.start_address().as_u64(), Ordering::Release);
+    Ok(())
+}
+
+/// A user address space: a PML4 with a private user half and the kernel half shared. Its page tables and user frames are freed when it is dropped.
+#[derive(Debug)]
+pub struct AddressSpace {
+    id: AddressSpaceId,
+    pml4: PhysFrame,
+}
+
+impl AddressSpace {
+    /// Creates an address space with an empty user half.
+    ///
+    /// # Errors
+    /// Fails for [`KERNEL_SPACE`], before [`init_kernel_space`], or if no frame is free.
+    pub fn new(id: AddressSpaceId) -> Result<Self, AddressSpaceError> {
+        if id == KERNEL_SPACE {
+            return Err(AddressSpaceError::KernelSpace);
+        }
+        let kernel = kernel_pml4().ok_or(AddressSpaceError::NotInitialized)?;
+        let pml4 = allocate_table()?;
+        let (kernel_table, table) = unsafe { (table_at(kernel), table_at(pml4)) };
+        for index in (0..512).filter(|&index| is_kernel_entry(index)) {
+            table[index] = kernel_table[index].clone();
+        }
+        Ok(AddressSpace { id, pml4 })
+    }
+
+    /// Returns the ID.
+    pub fn id(&self) -> AddressSpaceId {
+        self.id
+    }
+
+    /// Returns the frame holding the PML4.
+    pub fn pml4(&self) -> PhysFrame {
+        self.pml4
+    }
+
+    /// Returns whether the address space is in CR3.
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.pml4
+    }
+
+    /// Loads the address space into CR3, unless it is active already.
+    ///
+    /// # Safety
+    /// The code and stack in use must be mapped in the kernel half, and the address space must stay alive while it is active.
+    pub unsafe fn activate(&self) {
+        let (current, flags) = Cr3::read();
+        if current != self.pml4 {
+            unsafe { Cr3::write(self.pml4, flags) };
+        }
+    }
+
+    /// Returns a mapper over the address space's page tables.
+    ///
+    /// # Safety
+    /// No other mapper of these page tables may be live.
+    unsafe fn page_table(&self) -> OffsetPageTable<'_> {
+        let offset = VirtAddr::new(physical_memory_offset());
+        unsafe { OffsetPageTable::new(table_at(self.pml4), offset) }
+    }
+
+    /// Maps the user page at `virtual_address` to the frame at `physical_address`.
+    ///
+    /// # Errors
+    /// Fails if the address is not in the user half, the page is mapped already, or page table frames run out.
+    ///
+    /// # Safety
+    /// The mapping must not break memory safety, and no other mapper of this address space may be active.
+    pub unsafe fn map_page(
+        &self,
+        virtual_address: u64,
+        physical_address: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), AddressSpaceError> {
+        let page = user_page(virtual_address)?;
+        let frame = PhysFrame::containing_address(PhysAddr::new(physical_address));
+        let parent_flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | (flags & PageTableFlags::USER_ACCESSIBLE);
+        let flush = unsafe {
+            self.page_table().map_to_with_table_flags(
+                page,
+                frame,
+                flags,
+                parent_flags,
+                &mut GlobalFrameAllocator,
+            )
+        }
+        .map_err(|e| match e {
+            MapToError::FrameAllocationFailed => AddressSpaceError::OutOfFrames,
+            _ => AddressSpaceError::AlreadyMapped,
+        })?;
+        // Inactive address spaces have nothing of theirs in the TLB.
+        if self.is_active() {
+            flush.flush();
+        } else {
+            flush.ignore();
+        }
+        Ok(())
+    }
+
+    /// Removes the mapping of the user page at `virtual_address`, returning the frame it mapped.
+    ///
+    /// # Errors
+    /// Fails if the address is not in the user half or the page is not mapped.
+    ///
+    /// # Safety
+    /// Nothing may use the page anymore, and no other mapper of this address space may be active.
+    pub unsafe fn unmap_page(&self, virtual_address: u64) -> Result<PhysFrame, AddressSpaceError> {
+        let page = user_page(virtual_address)?;
+        let (frame, flush) = unsafe { self.page_table() }
+            .unmap(page)
+            .map_err(|_| AddressSpaceError::NotMapped)?;
+        if self.is_active() {
+            flush.flush();
+        } else {
+            flush.ignore();
+        }
+        Ok(frame)
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        if self.is_active()
+            && let Some(kernel) = kernel_pml4()
+        {
+            // Safety: the kernel half, which the running code lives in, is the same in both.
+            unsafe { Cr3::write(kernel, Cr3::read().1) };
+        }
+        let table = unsafe { table_at(self.pml4) };
+        for index in FIRST_USER_ENTRY..END_USER_ENTRY {
+            if !table[index].is_unused() {
+                unsafe { free_table(table[index].addr().as_u64(), 3) };
+            }
+        }
+        unsafe { frame::deallocate_frame(self.pml4) };
+        vmm::release_space(self.id);
+    }
+}
+
+/// Returns the page at `virtual_address` if it lies in the user half.
+fn user_page(virtual_address: u64) -> Result<Page<Size4KiB>, AddressSpaceError> {
+    if !(USER_SPACE_START..USER_SPACE_END).contains(&virtual_address) {
+        return Err(AddressSpaceError::NotUserAddress);
+    }
+    Ok(Page::containing_address(VirtAddr::new(virtual_address)))
+}
+
+/// Frees the page table at `physical` of the given level (3 for a PDPT down to 1 for a page table), every table below it, and the 4 KiB frames it maps that the memory map lists as usable RAM. Device memory and huge pages are only unmapped.
+///
+/// # Safety
+/// The table must belong to an address space that is not active and is being destroyed.
+unsafe fn free_table(physical: u64, level: u8) {
+    let frame = PhysFrame::containing_address(PhysAddr::new(physical));
+    let table = unsafe { table_at(frame) };
+    for entry in table.iter() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let address = entry.addr().as_u64();
+        if level > 1 && !flags.contains(PageTableFlags::HUGE_PAGE) {
+            unsafe { free_table(address, level - 1) };
+        } else if level == 1 && memory_map::kind_of(address) == Some(MemoryKind::Usable) {
+            unsafe { frame::deallocate_frame(PhysFrame::containing_address(entry.addr())) };
+        }
+    }
+    unsafe { frame::deallocate_frame(frame) };
+}
+
+static SPACES: Mutex<[Option<AddressSpace>; MAX_ADDRESS_SPACES]> =
+    Mutex::new([const { None }; MAX_ADDRESS_SPACES]);
+
+fn with_spaces<R>(f: impl FnOnce(&mut [Option<AddressSpace>; MAX_ADDRESS_SPACES]) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut SPACES.lock()))
+}
+
+/// Runs `f` on the address space `id`, creating it first if it does not exist yet.
+///
+/// The table stays locked while `f` runs, which serializes changes to the page tables of all address spaces.
+///
+/// # Errors
+/// Fails if the address space does not exist and cannot be created.
+pub fn with_space<R>(
+    id: AddressSpaceId,
+    f: impl FnOnce(&AddressSpace) -> R,
+) -> Result<R, AddressSpaceError> {
+    with_spaces(|spaces| {
+        if let Some(space) = spaces.iter().flatten().find(|space| space.id == id) {
+            return Ok(f(space));
+        }
+        let slot = spaces
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(AddressSpaceError::TableFull)?;
+        Ok(f(slot.insert(AddressSpace::new(id)?)))
+    })
+}
+
+/// Returns the number of user address spaces.
+pub fn count() -> usize {
+    with_spaces(|spaces| spaces.iter().flatten().count())
+}
+
+/// Makes the address space `id` active, creating it if needed; [`KERNEL_SPACE`] activates the kernel's page tables.
+///
+/// # Errors
+/// Fails before [`init_kernel_space`], or if the address space cannot be created; the active page tables are left unchanged then.
+///
+/// # Safety
+/// The code and stack in use must be mapped in the kernel half, and the address space must not be destroyed while it is active.
+pub unsafe fn switch_to(id: AddressSpaceId) -> Result<(), AddressSpaceError> {
+    if id == KERNEL_SPACE {
+        let kernel = kernel_pml4().ok_or(AddressSpaceError::NotInitialized)?;
+        let (current, flags) = Cr3::read();
+        if current != kernel {
+            unsafe { Cr3::write(kernel, flags) };
+        }
+        return Ok(());
+    }
+    with_space(id, |space| unsafe { space.activate() })
+}
+
+/// Destroys the address space `id`: switches to the kernel's page tables if it is active, then frees its user page tables and frames and releases its VMM regions.
+///
+/// # Errors
+/// Fails if no address space has this ID.
+pub fn destroy(id: AddressSpaceId) -> Result<(), AddressSpaceError> {
+    let space = with_spaces(|spaces| {
+        spaces
+            .iter_mut()
+            .find(|slot| slot.as_ref().is_some_and(|space| space.id == id))
+            .and_then(Option::take)
+    })
+    .ok_or(AddressSpaceError::NotFound)?;
+    drop(space);
+    Ok(())
+}
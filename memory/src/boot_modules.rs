@@ -0,0 +1,149 @@
+//! # Boot Modules
+//!
+//! Memory the loader hands to the kernel (an initrd, boot information, the UEFI memory map itself) lives in ordinary RAM that nothing protects once the kernel takes over. [`reserve`] records such a range under a name and marks it in the [`MemoryMap`] before the frame allocator is fed, so its frames are never handed out. When the kernel has consumed the data, [`release`] marks the range usable again and gives its whole frames to the [`frame`] allocator.
+//!
+//! Ranges need not be page aligned: a module that shares its first or last page with other data keeps that page reserved for good.
+//!
+//! ## Example
+//! ```ignore
+//! let mut map = MemoryMap::from_uefi(&uefi_map);
+//! boot_modules::reserve(&mut map, "initrd", initrd_start, initrd_end, MemoryKind::Initrd)?;
+//! frame::init(map.usable_ranges());
+//! memory_map::install(map);
+//! // ... unpack the initrd ...
+//! unsafe { boot_modules::release("initrd") }?;
+//! ```
+
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::frame;
+use crate::memory_map::{self, MemoryKind, MemoryMap};
+use crate::paging::PAGE_SIZE;
+
+/// Maximum number of reserved boot modules.
+pub const MAX_MODULES: usize = 16;
+
+/// A named range of physical memory provided by the loader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootModule {
+    /// Name used by [`find`] and [`release`].
+    pub name: &'static str,
+    /// First physical address.
+    pub start: u64,
+    /// Physical address after the last byte.
+    pub end: u64,
+    /// How the range is marked in the memory map while reserved.
+    pub kind: MemoryKind,
+}
+
+impl BootModule {
+    /// Returns the size in bytes.
+    pub const fn size(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// Errors from reserving or releasing boot modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleError {
+    /// The range is empty, or the kind is [`MemoryKind::Usable`].
+    InvalidRange,
+    /// The range overlaps this module.
+    Overlap(BootModule),
+    /// [`MAX_MODULES`] modules are already reserved, or the memory map is full.
+    TableFull,
+    /// No module has this name.
+    NotFound,
+}
+
+static MODULES: Mutex<[Option<BootModule>; MAX_MODULES]> = Mutex::new([None; MAX_MODULES]);
+
+fn with_modules<R>(f: impl FnOnce(&mut [Option<BootModule>; MAX_MODULES]) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut MODULES.lock()))
+}
+
+/// Records the loader-provided range `start..end` as `name` and marks it as `kind` in `map`, so the frame allocator fed from `map` never hands it out.
+///
+/// # Errors
+/// Fails if the range is empty or `kind` is usable, if it overlaps another module, or if a table is full.
+pub fn reserve(
+    map: &mut MemoryMap,
+    name: &'static str,
+    start: u64,
+    end: u64,
+    kind: MemoryKind,
+) -> Result<(), ModuleError> {
+    if start >= end || kind == MemoryKind::Usable {
+        return Err(ModuleError::InvalidRange);
+    }
+    let module = BootModule {
+        name,
+        start,
+        end,
+        kind,
+    };
+    with_modules(|modules| {
+        if let Some(existing) = modules
+            .iter()
+            .flatten()
+            .find(|m| m.start < end && start < m.end)
+        {
+            return Err(ModuleError::Overlap(*existing));
+        }
+        let slot = modules
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(ModuleError::TableFull)?;
+        if !map.mark(start, end, kind) {
+            return Err(ModuleError::TableFull);
+        }
+        *slot = Some(module);
+        Ok(())
+    })
+}
+
+/// Returns the reserved module called `name`.
+pub fn find(name: &str) -> Option<BootModule> {
+    with_modules(|modules| modules.iter().flatten().find(|m| m.name == name).copied())
+}
+
+/// Calls `f` for every reserved module.
+pub fn for_each(mut f: impl FnMut(&BootModule)) {
+    let snapshot = with_modules(|modules| *modules);
+    for module in snapshot.iter().flatten() {
+        f(module);
+    }
+}
+
+/// Gives the memory of the module called `name` back: marks it usable in the installed memory map and adds its whole frames to the frame allocator. Returns the number of frames freed.
+///
+/// # Safety
+/// Nothing may use the module's memory anymore, including pointers the loader passed into it.
+///
+/// # Errors
+/// Fails if no module has this name.
+pub unsafe fn release(name: &str) -> Result<u64, ModuleError> {
+    let module = with_modules(|modules| {
+        modules
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|m| m.name == name))
+            .and_then(Option::take)
+    })
+    .ok_or(ModuleError::NotFound)?;
+    // Partial pages at either end may hold other data and stay reserved.
+    let start = module.start.next_multiple_of(PAGE_SIZE);
+    let end = module.end / PAGE_SIZE * PAGE_SIZE;
+    if start >= end {
+        return Ok(0);
+    }
+    memory_map::mark(start, end, MemoryKind::Usable);
+    let freed = unsafe { frame::add_range(start, end) };
+    kprint!(
+        "[INFO] Released boot module \"{}\": {} KiB\r\n",
+        module.name,
+        freed * PAGE_SIZE / 1024
+    );
+    Ok(freed)
+}
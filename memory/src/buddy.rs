@@ -0,0 +1,181 @@
+//! # Buddy Allocator
+//!
+//! [`BuddyHeap`] is a binary buddy allocator, the alternative kernel heap backend (enabled with the `buddy-heap` feature). Every allocation is rounded up to a power-of-two block of at least [`MIN_BLOCK_SIZE`] bytes; each block size has its own free list, and a block of order `n` is aligned to `2^n` bytes relative to the heap start.
+//!
+//! ## Splitting and Merging
+//!
+//! An allocation takes the smallest free block that fits and splits it in halves until it has the requested order, putting the unused halves on their free lists. A freed block is merged with its buddy (the other half of the block it was split from) whenever that buddy is free too, and the merged block is freed again, up to the largest block the heap holds.
+//!
+//! Compared to the linked list allocator, allocation and free are bounded by the number of orders instead of the number of free holes, at the cost of internal fragmentation: a 33 byte allocation uses a 64 byte block.
+//!
+//! ## Growth
+//!
+//! Memory added with [`HeapBackend::extend`] is cut into the largest blocks its alignment allows and merged with free blocks before it, so a heap grown a page at a time still ends up with large blocks.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::heap::HeapBackend;
+
+/// Order of the smallest block; a free block holds the link to the next one.
+pub const MIN_ORDER: usize = 4;
+/// Size of the smallest block handed out.
+pub const MIN_BLOCK_SIZE: usize = 1 << MIN_ORDER;
+/// Number of block orders, so blocks can be as large as the address space allows.
+const ORDERS: usize = usize::BITS as usize;
+
+/// A binary buddy allocator over one contiguous, growable range.
+pub struct BuddyHeap {
+    start: usize,
+    size: usize,
+    used: usize,
+    /// Address of the first free block of each order, 0 if there is none.
+    free_lists: [usize; ORDERS],
+}
+
+impl BuddyHeap {
+    /// Creates a heap without memory; every allocation fails until [`init`](HeapBackend::init).
+    pub const fn empty() -> Self {
+        BuddyHeap {
+            start: 0,
+            size: 0,
+            used: 0,
+            free_lists: [0; ORDERS],
+        }
+    }
+
+    /// Returns the order of the block used for `layout`.
+    pub fn order_for(layout: Layout) -> usize {
+        layout
+            .size()
+            .max(layout.align())
+            .max(MIN_BLOCK_SIZE)
+            .next_power_of_two()
+            .trailing_zeros() as usize
+    }
+
+    /// Returns the number of free blocks of each order, for dumps and benchmarks.
+    pub fn free_blocks(&self) -> [usize; ORDERS] {
+        let mut counts = [0; ORDERS];
+        for (order, count) in counts.iter_mut().enumerate() {
+            let mut block = self.free_lists[order];
+            while block != 0 {
+                *count += 1;
+                block = unsafe { *(block as *const usize) };
+            }
+        }
+        counts
+    }
+
+    fn push(&mut self, block: usize, order: usize) {
+        unsafe { *(block as *mut usize) = self.free_lists[order] };
+        self.free_lists[order] = block;
+    }
+
+    fn pop(&mut self, order: usize) -> Option<usize> {
+        let block = self.free_lists[order];
+        if block == 0 {
+            return None;
+        }
+        self.free_lists[order] = unsafe { *(block as *const usize) };
+        Some(block)
+    }
+
+    /// Takes `block` off the free list of `order` and returns whether it was there.
+    fn remove(&mut self, block: usize, order: usize) -> bool {
+        let mut link = &raw mut self.free_lists[order];
+        unsafe {
+            while *link != 0 {
+                if *link == block {
+                    *link = *(block as *const usize);
+                    return true;
+                }
+                link = *link as *mut usize;
+            }
+        }
+        false
+    }
+
+    /// Frees `block`, merging it with its buddy as long as the buddy is free.
+    fn release(&mut self, mut block: usize, mut order: usize) {
+        while order + 1 < ORDERS {
+            let buddy_offset = (block - self.start) ^ (1 << order);
+            let buddy = self.start + buddy_offset;
+            if buddy_offset + (1 << order) > self.size || !self.remove(buddy, order) {
+                break;
+            }
+            block = block.min(buddy);
+            order += 1;
+        }
+        self.push(block, order);
+    }
+}
+
+impl Default for BuddyHeap {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl HeapBackend for BuddyHeap {
+    const EMPTY: Self = BuddyHeap::empty();
+
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        *self = BuddyHeap::empty();
+        self.start = start as usize;
+        unsafe { self.extend(size) };
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        let end = (self.start + self.size + by) & !(MIN_BLOCK_SIZE - 1);
+        let mut block = self.start + self.size;
+        while block + MIN_BLOCK_SIZE <= end {
+            let offset = block - self.start;
+            let mut order = if offset == 0 {
+                ORDERS - 1
+            } else {
+                offset.trailing_zeros() as usize
+            };
+            while 1 << order > end - block {
+                order -= 1;
+            }
+            self.size = block + (1 << order) - self.start;
+            self.release(block, order);
+            block += 1 << order;
+        }
+    }
+
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        // Blocks are only aligned as far as the heap start is.
+        if self.size == 0 || layout.align() > 1 << self.start.trailing_zeros() {
+            return None;
+        }
+        let order = Self::order_for(layout);
+        let found = (order..ORDERS).find(|&o| self.free_lists[o] != 0)?;
+        let block = self.pop(found)?;
+        for split in (order..found).rev() {
+            self.push(block + (1 << split), split);
+        }
+        self.used += 1 << order;
+        NonNull::new(block as *mut u8)
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let order = Self::order_for(layout);
+        self.used -= 1 << order;
+        self.release(ptr.as_ptr() as usize, order);
+    }
+
+    fn used(&self) -> usize {
+        self.used
+    }
+
+    fn free(&self) -> usize {
+        self.size - self.used
+    }
+
+    fn growth_needed(layout: Layout) -> usize {
+        // Twice the block size always contains a block of that size aligned to it.
+        (1usize << Self::order_for(layout)).saturating_mul(2)
+    }
+}
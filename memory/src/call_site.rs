@@ -0,0 +1,44 @@
+//! # Call Sites
+//!
+//! Return addresses of the callers of the allocator, recorded by the heap debugging wrappers (`debug_heap` and `redzone`) to say who allocated or freed a block.
+//!
+//! Addresses are found by walking the RBP chain, which only works when the kernel is built with `-C force-frame-pointers=yes`. Without frame pointers the recorded addresses are meaningless. With KASLR, subtract the slide printed at boot to look them up in the kernel ELF.
+
+use core::arch::asm;
+
+use polished_serial_logging::kprint;
+
+/// Number of return addresses recorded per allocation.
+pub const CALLER_DEPTH: usize = 4;
+
+/// Prints the non-zero addresses of `callers` and ends the line.
+pub(crate) fn print_callers(callers: &[u64; CALLER_DEPTH]) {
+    for &caller in callers.iter().take_while(|&&c| c != 0) {
+        kprint!(" {:#x}", caller);
+    }
+    kprint!("\r\n");
+}
+
+/// Returns up to [`CALLER_DEPTH`] return addresses of the callers of the allocator, found by following saved RBP values.
+#[inline(always)]
+pub(crate) fn callers() -> [u64; CALLER_DEPTH] {
+    let mut callers = [0; CALLER_DEPTH];
+    let mut frame: u64;
+    unsafe { asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack, preserves_flags)) };
+    for caller in callers.iter_mut() {
+        if frame == 0 || !frame.is_multiple_of(8) {
+            break;
+        }
+        let (next, return_address) = unsafe {
+            let slot = frame as *const u64;
+            (slot.read(), slot.add(1).read())
+        };
+        *caller = return_address;
+        // Frames grow down, so the chain must move up the stack.
+        if next <= frame {
+            break;
+        }
+        frame = next;
+    }
+    callers
+}
@@ -0,0 +1,231 @@
+//! # Debug Heap
+//!
+//! [`DebugHeap`] wraps a global allocator to catch heap corruption (enabled with the `debug-heap` feature):
+//! - **Poisoning:** new allocations are filled with [`ALLOC_POISON`] and freed ones with [`FREE_POISON`], so reads of uninitialized or freed memory show up as recognizable patterns in crash dumps.
+//! - **Double and invalid frees:** every live allocation is recorded; freeing a pointer that is not live is reported (naming the earlier free if it was recent) and *not* passed on, so the inner allocator's free list stays intact.
+//! - **Call sites:** each allocation records the return addresses of its callers, so [`DebugHeap::dump_outstanding`] can show who holds memory that is never freed.
+//!
+//! ## Call Sites
+//!
+//! Return addresses are read by walking the RBP chain, which only works when the kernel is built with `-C force-frame-pointers=yes` (`make DEBUG_HEAP=1` does this). Without frame pointers the recorded addresses are meaningless. With KASLR, subtract the slide printed at boot to look them up in the kernel ELF.
+//!
+//! ## Example
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: DebugHeap<KernelHeap> = DebugHeap::new(KernelHeap::empty());
+//!
+//! ALLOCATOR.dump_outstanding();
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+pub use crate::call_site::CALLER_DEPTH;
+use crate::call_site::{callers, print_callers};
+
+/// Byte pattern written over new allocations.
+pub const ALLOC_POISON: u8 = 0xAA;
+/// Byte pattern written over freed allocations.
+pub const FREE_POISON: u8 = 0xDD;
+/// Maximum number of live allocations tracked.
+pub const MAX_TRACKED: usize = 4096;
+/// Number of recent frees remembered to tell double frees from invalid ones.
+pub const RECENT_FREES: usize = 256;
+
+#[derive(Clone, Copy)]
+struct Allocation {
+    address: usize,
+    size: usize,
+    align: usize,
+    callers: [u64; CALLER_DEPTH],
+}
+
+const EMPTY: Allocation = Allocation {
+    address: 0,
+    size: 0,
+    align: 0,
+    callers: [0; CALLER_DEPTH],
+};
+
+struct Tracker {
+    live: [Allocation; MAX_TRACKED],
+    live_count: usize,
+    recent: [Allocation; RECENT_FREES],
+    recent_next: usize,
+    overflowed: bool,
+}
+
+/// What a free found in the tracking table.
+enum Lookup {
+    /// The allocation was live and is now recorded as freed.
+    Live(Allocation),
+    /// The allocation was not found, but the table overflowed, so it may never have been tracked.
+    Untracked,
+    /// The allocation is not live, with the recent free of the same address if there was one.
+    NotLive(Option<Allocation>),
+}
+
+impl Tracker {
+    fn track(&mut self, allocation: Allocation) {
+        if self.live_count == MAX_TRACKED {
+            self.overflowed = true;
+        } else {
+            self.live[self.live_count] = allocation;
+            self.live_count += 1;
+        }
+    }
+
+    /// Removes the live allocation at `address` and remembers it as freed from `freed_from`.
+    fn untrack(&mut self, address: usize, freed_from: [u64; CALLER_DEPTH]) -> Lookup {
+        let count = self.live_count;
+        let Some(index) = self.live[..count].iter().position(|a| a.address == address) else {
+            if self.overflowed {
+                return Lookup::Untracked;
+            }
+            return Lookup::NotLive(self.recent.iter().find(|a| a.address == address).copied());
+        };
+        let allocation = self.live[index];
+        self.live[index] = self.live[count - 1];
+        self.live_count -= 1;
+        let slot = self.recent_next;
+        self.recent[slot] = Allocation {
+            callers: freed_from,
+            ..allocation
+        };
+        self.recent_next = (slot + 1) % RECENT_FREES;
+        Lookup::Live(allocation)
+    }
+}
+
+/// A global allocator wrapper that poisons memory and tracks allocations. See the module documentation.
+pub struct DebugHeap<A> {
+    inner: A,
+    tracker: Mutex<Tracker>,
+}
+
+impl<A> DebugHeap<A> {
+    /// Wraps `inner`.
+    pub const fn new(inner: A) -> Self {
+        DebugHeap {
+            inner,
+            tracker: Mutex::new(Tracker {
+                live: [EMPTY; MAX_TRACKED],
+                live_count: 0,
+                recent: [EMPTY; RECENT_FREES],
+                recent_next: 0,
+                overflowed: false,
+            }),
+        }
+    }
+
+    /// Returns the wrapped allocator.
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Runs `f` on the tracking table with interrupts disabled, so an interrupt handler that allocates cannot spin on the lock held by the code it interrupted.
+    fn with_tracker<R>(&self, f: impl FnOnce(&mut Tracker) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.tracker.lock()))
+    }
+
+    /// Returns the number of live allocations and their total size.
+    pub fn outstanding(&self) -> (usize, usize) {
+        self.with_tracker(|tracker| {
+            let live = &tracker.live[..tracker.live_count];
+            (live.len(), live.iter().map(|a| a.size).sum())
+        })
+    }
+
+    /// Prints every live allocation with its size and call sites.
+    pub fn dump_outstanding(&self) {
+        self.with_tracker(|tracker| Self::dump_tracker(tracker));
+    }
+
+    fn dump_tracker(tracker: &Tracker) {
+        let live = &tracker.live[..tracker.live_count];
+        kprint!(
+            "[INFO] {} outstanding allocations, {} bytes{}:\r\n",
+            live.len(),
+            live.iter().map(|a| a.size).sum::<usize>(),
+            if tracker.overflowed {
+                " (tracking table overflowed, list incomplete)"
+            } else {
+                ""
+            }
+        );
+        for a in live {
+            kprint!(
+                "  {:#018x} {:>8} bytes (align {}) from",
+                a.address,
+                a.size,
+                a.align
+            );
+            print_callers(&a.callers);
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugHeap<A> {
+    #[inline(never)]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if ptr.is_null() {
+            return ptr;
+        }
+        unsafe { ptr.write_bytes(ALLOC_POISON, layout.size()) };
+        let allocation = Allocation {
+            address: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            callers: callers(),
+        };
+        self.with_tracker(|tracker| tracker.track(allocation));
+        ptr
+    }
+
+    #[inline(never)]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let address = ptr as usize;
+        let freed_from = callers();
+        match self.with_tracker(|tracker| tracker.untrack(address, freed_from)) {
+            // The allocation may simply not have been tracked.
+            Lookup::Untracked => {}
+            Lookup::NotLive(earlier) => {
+                match earlier {
+                    Some(a) => {
+                        kprint!(
+                            "[ERROR] Double free of {:#x} ({} bytes), first freed from",
+                            address,
+                            a.size
+                        );
+                        print_callers(&a.callers);
+                    }
+                    None => kprint!(
+                        "[ERROR] Free of {:#x}, which was never allocated\r\n",
+                        address
+                    ),
+                }
+                kprint!("[ERROR]   this free from");
+                print_callers(&freed_from);
+                return;
+            }
+            Lookup::Live(allocation) => {
+                if allocation.size != layout.size() || allocation.align != layout.align() {
+                    kprint!(
+                        "[ERROR] Free of {:#x} with size {} (align {}), allocated with size {} (align {})\r\n",
+                        address,
+                        layout.size(),
+                        layout.align(),
+                        allocation.size,
+                        allocation.align
+                    );
+                }
+                unsafe { ptr.write_bytes(FREE_POISON, layout.size()) };
+            }
+        }
+        unsafe { self.inner.dealloc(ptr, layout) };
+    }
+}
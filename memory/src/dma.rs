@@ -0,0 +1,121 @@
+//! # DMA Buffers
+//!
+//! Devices that access memory on their own (AHCI command lists, NVMe queues, virtio rings) need buffers that are physically contiguous and aligned, and whose physical address the driver can hand to the device. [`alloc_dma`] cuts such a buffer out of the frame allocator and returns a [`DmaBuffer`] exposing both its physical address (for the device) and its virtual address (for the CPU).
+//!
+//! Devices limited to 32-bit addresses ask for memory below 4 GiB with `below_4g`.
+//!
+//! ## Example
+//! ```ignore
+//! let ring = dma::alloc_dma(4096, true)?;
+//! port.write_command_list_base(ring.physical());
+//! ring.as_mut_slice()[0] = 0;
+//! ```
+
+use core::ptr::NonNull;
+
+use x86_64::PhysAddr;
+use x86_64::structures::paging::PhysFrame;
+
+use crate::frame;
+use crate::hhdm::phys_to_virt;
+use crate::paging::{PAGE_SIZE, page_align_up};
+
+/// Highest address (exclusive) reachable by 32-bit DMA.
+pub const DMA_32BIT_LIMIT: u64 = 1 << 32;
+
+/// Errors from allocating DMA memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaError {
+    /// The length is zero or the alignment is not a power of two.
+    InvalidLayout,
+    /// No contiguous block of that size is free.
+    OutOfMemory,
+}
+
+/// A physically contiguous, zeroed buffer for device access. The memory is given back when the buffer is dropped.
+#[derive(Debug)]
+pub struct DmaBuffer {
+    physical: u64,
+    virt: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the buffer owns its memory exclusively.
+unsafe impl Send for DmaBuffer {}
+unsafe impl Sync for DmaBuffer {}
+
+impl DmaBuffer {
+    /// Returns the physical address, to be given to the device.
+    pub fn physical(&self) -> u64 {
+        self.physical
+    }
+
+    /// Returns the virtual address, for CPU access.
+    pub fn virt(&self) -> u64 {
+        self.virt.as_ptr() as u64
+    }
+
+    /// Returns the requested length in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the buffer is empty (never true).
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a pointer to the start of the buffer.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.virt.as_ptr()
+    }
+
+    /// Returns the buffer as a byte slice.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.virt.as_ptr(), self.len) }
+    }
+
+    /// Returns the buffer as a mutable byte slice.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.virt.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        for offset in (0..page_align_up(self.len as u64)).step_by(PAGE_SIZE as usize) {
+            let frame = PhysFrame::containing_address(PhysAddr::new(self.physical + offset));
+            unsafe { frame::deallocate_frame(frame) };
+        }
+    }
+}
+
+/// Allocates `len` bytes of zeroed, page aligned, physically contiguous memory, below 4 GiB if `below_4g` is set.
+///
+/// # Errors
+/// [`DmaError::InvalidLayout`] for a zero length, [`DmaError::OutOfMemory`] if no block is free.
+pub fn alloc_dma(len: usize, below_4g: bool) -> Result<DmaBuffer, DmaError> {
+    alloc_dma_aligned(len, PAGE_SIZE, below_4g)
+}
+
+/// Like [`alloc_dma`], but with the physical address aligned to `align` bytes (rounded up to a page), for devices with stricter requirements.
+///
+/// # Errors
+/// [`DmaError::InvalidLayout`] for a zero length or an alignment that is not a power of two, [`DmaError::OutOfMemory`] if no block is free.
+pub fn alloc_dma_aligned(len: usize, align: u64, below_4g: bool) -> Result<DmaBuffer, DmaError> {
+    if len == 0 || !align.is_power_of_two() {
+        return Err(DmaError::InvalidLayout);
+    }
+    let pages = page_align_up(len as u64) / PAGE_SIZE;
+    let limit = if below_4g { DMA_32BIT_LIMIT } else { u64::MAX };
+    let frame = frame::allocate_contiguous(pages, align.max(PAGE_SIZE), limit)
+        .ok_or(DmaError::OutOfMemory)?;
+    let physical = frame.start_address().as_u64();
+    let virt = phys_to_virt(physical) as *mut u8;
+    unsafe { core::ptr::write_bytes(virt, 0, (pages * PAGE_SIZE) as usize) };
+    Ok(DmaBuffer {
+        physical,
+        virt: NonNull::new(virt).ok_or(DmaError::OutOfMemory)?,
+        len,
+    })
+}
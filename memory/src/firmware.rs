@@ -0,0 +1,135 @@
+//! # Firmware Regions
+//!
+//! ACPI tables, SMBIOS structures and the register blocks of the Local APIC, I/O APICs and HPET sit at physical addresses the firmware chose. The kernel must not rely on the firmware's identity mapping to reach them, so [`map`] maps such a range on demand into a window of kernel virtual memory starting at [`FIRMWARE_START`] and returns the virtual address of its first byte.
+//!
+//! ## Attributes
+//!
+//! [`FirmwareKind`] picks how a range is mapped:
+//! - **[`FirmwareKind::Table`]:** read-only and cached, for ACPI tables and SMBIOS, which live in RAM.
+//! - **[`FirmwareKind::Mmio`]:** read-write and uncached, for device registers.
+//!
+//! Every mapping is recorded as a [`RegionKind::Firmware`] or [`RegionKind::Mmio`] region of the kernel address space, and none is ever executable.
+//!
+//! ## Reuse
+//!
+//! Firmware structures stay in place for the life of the system, so mappings are never removed. A request that lies within an earlier mapping of the same kind returns that mapping, which keeps parsers that map a table header and then the whole table from using up the window.
+//!
+//! ## Example
+//! ```ignore
+//! let madt = firmware::map("acpi table", address, length, FirmwareKind::Table)?;
+//! let lapic = firmware::map("local apic", apic_base, 4096, FirmwareKind::Mmio)?;
+//! ```
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::frame::GlobalFrameAllocator;
+use crate::paging::{PAGE_SIZE, Protection};
+use crate::stack::{KERNEL_STACKS_START, MAX_KERNEL_STACKS, STACK_SLOT_SIZE};
+use crate::vmm::{self, Region, RegionKind, VmmError};
+
+/// Start of the virtual window firmware regions are mapped in, 1 GiB above the kernel stacks to leave room for the framebuffer.
+pub const FIRMWARE_START: u64 =
+    KERNEL_STACKS_START + MAX_KERNEL_STACKS as u64 * STACK_SLOT_SIZE + (1 << 30);
+/// Size of the firmware window.
+pub const FIRMWARE_WINDOW_SIZE: u64 = 1 << 30;
+/// Maximum number of firmware mappings.
+pub const MAX_FIRMWARE_MAPPINGS: usize = 64;
+
+/// How a firmware range is mapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirmwareKind {
+    /// Tables in RAM (ACPI, SMBIOS): read-only, cached.
+    Table,
+    /// Device registers: read-write, uncached.
+    Mmio,
+}
+
+/// Errors from mapping firmware regions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirmwareError {
+    /// The range is empty or wraps around.
+    InvalidRange,
+    /// The firmware window or the mapping table is full.
+    WindowFull,
+    /// The range could not be mapped.
+    Map(VmmError),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    physical: u64,
+    size: u64,
+    virt: u64,
+    kind: FirmwareKind,
+}
+
+struct Window {
+    mappings: [Option<Mapping>; MAX_FIRMWARE_MAPPINGS],
+    next: u64,
+}
+
+static WINDOW: Mutex<Window> = Mutex::new(Window {
+    mappings: [None; MAX_FIRMWARE_MAPPINGS],
+    next: FIRMWARE_START,
+});
+
+fn with_window<R>(f: impl FnOnce(&mut Window) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut WINDOW.lock()))
+}
+
+/// Maps `size` bytes of firmware memory at `physical` (if not mapped already) and returns the virtual address of `physical`.
+///
+/// `name` labels the VMM region, e.g. in fault reports.
+///
+/// # Errors
+/// Fails if the range is empty, the firmware window is exhausted, or page table frames run out.
+pub fn map(
+    name: &'static str,
+    physical: u64,
+    size: u64,
+    kind: FirmwareKind,
+) -> Result<u64, FirmwareError> {
+    let end = physical
+        .checked_add(size)
+        .filter(|_| size > 0)
+        .ok_or(FirmwareError::InvalidRange)?;
+    let start = physical & !(PAGE_SIZE - 1);
+    let end = end.next_multiple_of(PAGE_SIZE);
+    with_window(|window| {
+        if let Some(mapping) = window
+            .mappings
+            .iter()
+            .flatten()
+            .find(|m| m.kind == kind && m.physical <= start && end <= m.physical + m.size)
+        {
+            return Ok(mapping.virt + (physical - mapping.physical));
+        }
+        let size = end - start;
+        if window.next + size > FIRMWARE_START + FIRMWARE_WINDOW_SIZE {
+            return Err(FirmwareError::WindowFull);
+        }
+        let slot = window
+            .mappings
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(FirmwareError::WindowFull)?;
+        let (region_kind, protection) = match kind {
+            FirmwareKind::Table => (RegionKind::Firmware, Protection::READ),
+            FirmwareKind::Mmio => (RegionKind::Mmio, Protection::READ_WRITE),
+        };
+        let region = Region::new(name, window.next, size, region_kind, protection);
+        // Safety: the window is reserved for firmware mappings and `next` only grows.
+        unsafe { vmm::map_region_to(region, start, &mut GlobalFrameAllocator) }
+            .map_err(FirmwareError::Map)?;
+        let virt = window.next;
+        *slot = Some(Mapping {
+            physical: start,
+            size,
+            virt,
+            kind,
+        });
+        window.next += size;
+        Ok(virt + (physical - start))
+    })
+}
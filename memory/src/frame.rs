@@ -0,0 +1,223 @@
+//! # Physical Frame Allocator
+//!
+//! Hands out 4 KiB physical frames from the usable ranges of the [`MemoryMap`](crate::memory_map::MemoryMap). Frames are first taken in order from the ranges given to [`init`]; frames given back with [`deallocate_frame`] go on a free list that is threaded through the frames themselves (each free frame stores the address of the next one), so the allocator needs no memory of its own.
+//!
+//! Memory below 1 MiB is never handed out: it holds firmware data and is kept for real-mode code such as AP trampolines.
+//!
+//! [`GlobalFrameAllocator`] implements the `x86_64` `FrameAllocator` trait on top of the global state, so it can be passed to [`crate::paging::map_page`] and [`crate::vmm::map_region`].
+//!
+//! ## Example
+//! ```ignore
+//! frame::init(memory_map.usable_ranges());
+//! unsafe { vmm::map_region(region, &mut GlobalFrameAllocator)? };
+//! ```
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::PhysAddr;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
+
+use crate::hhdm::phys_to_virt;
+use crate::paging::PAGE_SIZE;
+
+/// Maximum number of free physical ranges tracked.
+pub const MAX_RANGES: usize = 64;
+
+/// Lowest physical address handed out.
+pub const LOW_MEMORY_END: u64 = 0x10_0000;
+
+struct FrameState {
+    ranges: [(u64, u64); MAX_RANGES],
+    range_count: usize,
+    current: usize,
+    next: u64,
+    free_list: u64,
+    free_frames: u64,
+    total_frames: u64,
+}
+
+static FAILED_ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+static FRAMES: Mutex<FrameState> = Mutex::new(FrameState {
+    ranges: [(0, 0); MAX_RANGES],
+    range_count: 0,
+    current: 0,
+    next: 0,
+    free_list: 0,
+    free_frames: 0,
+    total_frames: 0,
+});
+
+/// Runs `f` on the allocator state with interrupts disabled, so an interrupt handler that allocates cannot spin on the lock held by the code it interrupted.
+fn with_frames<R>(f: impl FnOnce(&mut FrameState) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut FRAMES.lock()))
+}
+
+/// Makes the physical `(start, end)` ranges available for allocation, replacing any earlier ranges.
+///
+/// Ranges are trimmed to whole frames above [`LOW_MEMORY_END`]; ranges beyond [`MAX_RANGES`] are ignored.
+pub fn init(ranges: impl Iterator<Item = (u64, u64)>) {
+    with_frames(|state| init_state(state, ranges));
+}
+
+fn init_state(state: &mut FrameState, ranges: impl Iterator<Item = (u64, u64)>) {
+    state.range_count = 0;
+    state.total_frames = 0;
+    for (start, end) in ranges {
+        let start = start.max(LOW_MEMORY_END).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        let end = end / PAGE_SIZE * PAGE_SIZE;
+        if start >= end || state.range_count == MAX_RANGES {
+            continue;
+        }
+        let index = state.range_count;
+        state.ranges[index] = (start, end);
+        state.range_count += 1;
+        state.total_frames += (end - start) / PAGE_SIZE;
+    }
+    state.current = 0;
+    state.next = state.ranges[0].0;
+    state.free_list = 0;
+    state.free_frames = state.total_frames;
+}
+
+/// Allocates a physical frame. Its contents are undefined.
+pub fn allocate_frame() -> Option<PhysFrame> {
+    let address = with_frames(allocate_from);
+    if address.is_none() {
+        FAILED_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+    Some(PhysFrame::containing_address(PhysAddr::new(address?)))
+}
+
+fn allocate_from(state: &mut FrameState) -> Option<u64> {
+    let address = if state.free_list != 0 {
+        let address = state.free_list;
+        state.free_list = unsafe { (phys_to_virt(address) as *const u64).read() };
+        address
+    } else {
+        while state.current < state.range_count && state.next >= state.ranges[state.current].1 {
+            state.current += 1;
+            if state.current < state.range_count {
+                state.next = state.ranges[state.current].0;
+            }
+        }
+        if state.current == state.range_count {
+            return None;
+        }
+        let address = state.next;
+        state.next += PAGE_SIZE;
+        address
+    };
+    state.free_frames -= 1;
+    Some(address)
+}
+
+/// Allocates `count` physically contiguous frames whose first address is a multiple of `align` and whose last byte lies below `limit`, e.g. for DMA.
+///
+/// Blocks are cut from the top of a free range, so they don't get in the way of single-frame allocations; frames cut off above the block or for alignment go on the free list. Frames returned with [`deallocate_frame`] are not reused for contiguous blocks.
+///
+/// `align` must be a power of two of at least [`PAGE_SIZE`].
+pub fn allocate_contiguous(count: u64, align: u64, limit: u64) -> Option<PhysFrame> {
+    if count == 0 || !align.is_power_of_two() || align < PAGE_SIZE {
+        return None;
+    }
+    let size = count.checked_mul(PAGE_SIZE)?;
+    let block = with_frames(|state| allocate_block(state, count, size, align, limit))?;
+    Some(PhysFrame::containing_address(PhysAddr::new(block)))
+}
+
+fn allocate_block(
+    state: &mut FrameState,
+    count: u64,
+    size: u64,
+    align: u64,
+    limit: u64,
+) -> Option<u64> {
+    let first = state.current;
+    for index in (first..state.range_count).rev() {
+        let (start, end) = state.ranges[index];
+        let lowest = if index == first { state.next } else { start };
+        let top = end.min(limit);
+        let Some(block) = top.checked_sub(size).map(|b| b & !(align - 1)) else {
+            continue;
+        };
+        if block < lowest || top <= lowest {
+            continue;
+        }
+        for leftover in (block + size..end).step_by(PAGE_SIZE as usize) {
+            unsafe { (phys_to_virt(leftover) as *mut u64).write(state.free_list) };
+            state.free_list = leftover;
+        }
+        state.ranges[index].1 = block;
+        state.free_frames -= count;
+        return Some(block);
+    }
+    None
+}
+
+/// Returns `frame` to the allocator.
+///
+/// # Safety
+/// `frame` must have come from [`allocate_frame`] and must no longer be mapped or used.
+pub unsafe fn deallocate_frame(frame: PhysFrame) {
+    let address = frame.start_address().as_u64();
+    with_frames(|state| {
+        unsafe { (phys_to_virt(address) as *mut u64).write(state.free_list) };
+        state.free_list = address;
+        state.free_frames += 1;
+    });
+}
+
+/// Adds the whole frames in the physical range `start..end` to the allocator, e.g. memory that was reserved for the loader and is no longer needed.
+///
+/// Returns the number of frames added.
+///
+/// # Safety
+/// Nothing may use the range anymore, it must be usable RAM covered by the direct map, and no part of it may already belong to the allocator.
+pub unsafe fn add_range(start: u64, end: u64) -> u64 {
+    let start = start.max(LOW_MEMORY_END).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    let end = end / PAGE_SIZE * PAGE_SIZE;
+    with_frames(|state| {
+        for address in (start..end).step_by(PAGE_SIZE as usize) {
+            unsafe { (phys_to_virt(address) as *mut u64).write(state.free_list) };
+            state.free_list = address;
+        }
+        let added = end.saturating_sub(start) / PAGE_SIZE;
+        state.free_frames += added;
+        state.total_frames += added;
+        added
+    })
+}
+
+/// Returns the number of allocations that failed because no frame was left.
+pub fn failed_allocations() -> u64 {
+    FAILED_ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of frames that can still be allocated.
+pub fn free_frames() -> u64 {
+    with_frames(|state| state.free_frames)
+}
+
+/// Returns the number of frames managed by the allocator.
+pub fn total_frames() -> u64 {
+    with_frames(|state| state.total_frames)
+}
+
+/// The global frame allocator, for APIs that take a `FrameAllocator`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for GlobalFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        allocate_frame()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for GlobalFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        unsafe { deallocate_frame(frame) }
+    }
+}
@@ -0,0 +1,320 @@
+//! # Kernel Heap
+//!
+//! The kernel heap lives at a fixed virtual range, [`KERNEL_HEAP_START`] up to [`KERNEL_HEAP_MAX_SIZE`] bytes. Its VMM region covers only the mapped part and is grown in place with [`vmm::resize`]; its pages are mapped to frames from the [`frame`](crate::frame) allocator, so the heap never lands on memory the firmware, the bootloader or the framebuffer uses.
+//!
+//! ## Growth
+//!
+//! [`KernelHeap`] is a `GlobalAlloc` around a [`HeapBackend`]. When an allocation does not fit, or when free space drops below [`LOW_WATERMARK`], the heap resizes its region to map more frames right after its current end (at least [`GROWTH_STEP`] bytes) and extends itself. Growth stops at [`KERNEL_HEAP_MAX_SIZE`] or when physical memory runs out, at which point allocations fail as usual.
+//!
+//! ## Interrupts
+//!
+//! The heap lock is only taken with interrupts disabled, as are the locks of the frame allocator it grows from and of the [`debug_heap`](crate::debug_heap) and [`redzone`](crate::redzone) wrappers. An interrupt handler can therefore allocate (a `format!` in a log line is enough) without spinning forever on a lock held by the code it interrupted. Interrupts stay off for the whole allocation, including any growth of the heap.
+//!
+//! ## Backends
+//!
+//! The allocator itself sits behind the [`HeapBackend`] trait, which [`KernelHeap`] uses for everything but mapping memory. [`Backend`] picks the one the kernel is built with: `linked_list_allocator`'s first-fit [`Heap`] by default, or the [`BuddyHeap`](crate::buddy::BuddyHeap) with the `buddy-heap` feature. [`heap_bench`](crate::heap_bench) measures either through the global allocator.
+//!
+//! ## Example
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: KernelHeap = KernelHeap::empty();
+//!
+//! unsafe { ALLOCATOR.init(heap::initial_size(frame::free_frames())) }?;
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use linked_list_allocator::Heap;
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::frame::GlobalFrameAllocator;
+use crate::paging::{PAGE_SIZE, Protection, page_align_up};
+use crate::vmm::{self, KERNEL_SPACE, Region, RegionKind, VmmError};
+
+/// Virtual address of the start of the kernel heap.
+pub const KERNEL_HEAP_START: u64 = 0xFFFF_C000_0000_0000;
+/// Size of the virtual range reserved for the kernel heap.
+pub const KERNEL_HEAP_MAX_SIZE: u64 = 1 << 30;
+/// Largest heap mapped at boot.
+pub const INITIAL_HEAP_SIZE: u64 = 16 << 20;
+/// Smallest amount by which the heap grows.
+pub const GROWTH_STEP: u64 = 1 << 20;
+/// Free bytes below which the heap grows ahead of need.
+pub const LOW_WATERMARK: u64 = 256 << 10;
+
+/// Returns the size of the heap to map at boot when `free_frames` frames are available: an eighth of physical memory, between [`GROWTH_STEP`] and [`INITIAL_HEAP_SIZE`].
+pub fn initial_size(free_frames: u64) -> u64 {
+    page_align_up((free_frames * PAGE_SIZE / 8).clamp(GROWTH_STEP, INITIAL_HEAP_SIZE))
+}
+
+/// An allocator managing one contiguous range of memory that can grow at its end.
+///
+/// [`KernelHeap`] does the locking and maps the memory; a backend only decides where allocations go.
+pub trait HeapBackend {
+    /// A backend without memory, for `const` initialization of the global allocator.
+    const EMPTY: Self;
+
+    /// Hands the backend `size` bytes starting at `start`.
+    ///
+    /// # Safety
+    /// The range must be mapped, writable and unused, and stay so for as long as the backend is used.
+    unsafe fn init(&mut self, start: *mut u8, size: usize);
+
+    /// Adds the `by` bytes right after the end of the managed range.
+    ///
+    /// # Safety
+    /// Same as [`init`](Self::init), for the added bytes.
+    unsafe fn extend(&mut self, by: usize);
+
+    /// Allocates memory for `layout`, or returns `None` if no free block fits.
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Frees memory returned by [`allocate`](Self::allocate).
+    ///
+    /// # Safety
+    /// `ptr` must come from this backend's `allocate` with the same `layout` and not have been freed yet.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Returns the number of bytes in use.
+    fn used(&self) -> usize;
+
+    /// Returns the number of free bytes.
+    fn free(&self) -> usize;
+
+    /// Returns how many bytes to add after a failed allocation so that retrying it succeeds.
+    fn growth_needed(layout: Layout) -> usize {
+        layout.size() + layout.align()
+    }
+}
+
+impl HeapBackend for Heap {
+    const EMPTY: Self = Heap::empty();
+
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        unsafe { Heap::init(self, start, size) }
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        unsafe { Heap::extend(self, by) }
+    }
+
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        self.allocate_first_fit(layout).ok()
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { Heap::deallocate(self, ptr, layout) }
+    }
+
+    fn used(&self) -> usize {
+        Heap::used(self)
+    }
+
+    fn free(&self) -> usize {
+        Heap::free(self)
+    }
+}
+
+/// The backend of the kernel heap.
+#[cfg(not(feature = "buddy-heap"))]
+pub type Backend = Heap;
+/// The backend of the kernel heap.
+#[cfg(feature = "buddy-heap")]
+pub type Backend = crate::buddy::BuddyHeap;
+
+static MAPPED_BYTES: AtomicU64 = AtomicU64::new(0);
+static USED_BYTES: AtomicU64 = AtomicU64::new(0);
+static FAILED_ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the mapped size of the global kernel heap, its bytes in use, and the number of failed allocations, without taking the heap lock.
+pub fn usage() -> (u64, u64, u64) {
+    (
+        MAPPED_BYTES.load(Ordering::Relaxed),
+        USED_BYTES.load(Ordering::Relaxed),
+        FAILED_ALLOCATIONS.load(Ordering::Relaxed),
+    )
+}
+
+struct HeapState {
+    heap: Backend,
+    mapped: u64,
+}
+
+impl HeapState {
+    /// Maps at least `bytes` more bytes after the mapped part of the heap and returns how many were added.
+    ///
+    /// Tries a full [`GROWTH_STEP`] first and only `bytes` if memory is too short for that.
+    fn grow(&mut self, bytes: u64) -> u64 {
+        let room = KERNEL_HEAP_MAX_SIZE - self.mapped;
+        let wanted = page_align_up(bytes.max(GROWTH_STEP)).min(room);
+        let needed = page_align_up(bytes).min(room);
+        let added = [wanted, needed]
+            .into_iter()
+            .filter(|&size| size > 0)
+            .find(|&size| {
+                let new_size = self.mapped + size;
+                // Safety: the heap region is part of the kernel address space, which is always active.
+                unsafe {
+                    vmm::resize(
+                        KERNEL_SPACE,
+                        KERNEL_HEAP_START,
+                        new_size,
+                        &mut GlobalFrameAllocator,
+                    )
+                }
+                .is_ok()
+            })
+            .unwrap_or(0);
+        if added > 0 {
+            self.mapped += added;
+            MAPPED_BYTES.store(self.mapped, Ordering::Relaxed);
+            unsafe { self.heap.extend(added as usize) };
+            kprint!("[INFO] Kernel heap grew to {} KiB\r\n", self.mapped / 1024);
+        }
+        added
+    }
+}
+
+/// Errors from setting up the kernel heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapError {
+    /// The heap range could not be reserved.
+    Reserve(VmmError),
+    /// Not even the first page could be mapped.
+    OutOfMemory,
+}
+
+/// The kernel's growable global allocator.
+pub struct KernelHeap {
+    state: Mutex<HeapState>,
+}
+
+impl KernelHeap {
+    /// Creates a heap without memory; every allocation fails until [`init`](Self::init).
+    pub const fn empty() -> Self {
+        KernelHeap {
+            state: Mutex::new(HeapState {
+                heap: Backend::EMPTY,
+                mapped: 0,
+            }),
+        }
+    }
+
+    /// Maps the first page of the heap as its VMM region and grows it to `initial_size` bytes.
+    ///
+    /// Less than `initial_size` may be mapped if memory is short.
+    ///
+    /// # Safety
+    /// Must be called once, after [`frame::init`](crate::frame::init), while no other code changes the page tables.
+    pub unsafe fn init(&self, initial_size: u64) -> Result<(), HeapError> {
+        let region = Region::new(
+            "kernel heap",
+            KERNEL_HEAP_START,
+            PAGE_SIZE,
+            RegionKind::KernelHeap,
+            Protection::READ_WRITE,
+        );
+        match unsafe { vmm::map_region(region, &mut GlobalFrameAllocator) } {
+            Ok(()) => {}
+            Err(VmmError::MapFailed) => return Err(HeapError::OutOfMemory),
+            Err(e) => return Err(HeapError::Reserve(e)),
+        }
+        let first = initial_size.clamp(PAGE_SIZE, KERNEL_HEAP_MAX_SIZE);
+        self.with_state(|state| unsafe { state.init(first) });
+        Ok(())
+    }
+
+    /// Runs `f` on the heap state with interrupts disabled, so an interrupt handler that allocates cannot spin on the lock held by the code it interrupted.
+    fn with_state<R>(&self, f: impl FnOnce(&mut HeapState) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+
+    /// Returns the number of bytes currently mapped for the heap.
+    pub fn size(&self) -> u64 {
+        self.with_state(|state| state.mapped)
+    }
+
+    /// Returns the number of bytes in use.
+    pub fn used(&self) -> u64 {
+        self.with_state(|state| state.heap.used() as u64)
+    }
+
+    /// Returns the number of free bytes in the mapped part of the heap.
+    pub fn free(&self) -> u64 {
+        self.with_state(|state| state.heap.free() as u64)
+    }
+}
+
+impl HeapState {
+    /// Hands the first page of the heap, mapped by [`KernelHeap::init`], to the backend and grows the heap to `first` bytes.
+    ///
+    /// # Safety
+    /// See [`KernelHeap::init`].
+    unsafe fn init(&mut self, first: u64) {
+        self.mapped = PAGE_SIZE;
+        MAPPED_BYTES.store(PAGE_SIZE, Ordering::Relaxed);
+        unsafe {
+            self.heap
+                .init(KERNEL_HEAP_START as *mut u8, PAGE_SIZE as usize)
+        };
+        if first > PAGE_SIZE {
+            self.grow(first - PAGE_SIZE);
+        }
+    }
+
+    /// Allocates from the backend, growing the heap if the allocation does not fit or free space runs low.
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let result = match self.heap.allocate(layout) {
+            Some(allocation) => Some(allocation),
+            None => {
+                let needed = Backend::growth_needed(layout) as u64;
+                if self.grow(needed) == 0 {
+                    None
+                } else {
+                    self.heap.allocate(layout)
+                }
+            }
+        };
+        if (self.heap.free() as u64) < LOW_WATERMARK && self.mapped < KERNEL_HEAP_MAX_SIZE {
+            self.grow(GROWTH_STEP);
+        }
+        USED_BYTES.store(self.heap.used() as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(result) =
+            self.with_state(|state| (state.mapped != 0).then(|| state.allocate(layout)))
+        else {
+            return ptr::null_mut();
+        };
+        match result {
+            Some(allocation) => allocation.as_ptr(),
+            None => {
+                FAILED_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+                kprint!(
+                    "[ERROR] Kernel heap allocation of {} bytes (align {}) failed\r\n",
+                    layout.size(),
+                    layout.align()
+                );
+                crate::stats::dump();
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            self.with_state(|state| {
+                unsafe { state.heap.deallocate(ptr, layout) };
+                USED_BYTES.store(state.heap.used() as u64, Ordering::Relaxed);
+            });
+        }
+    }
+}
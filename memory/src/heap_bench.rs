@@ -0,0 +1,174 @@
+//! # Heap Benchmarks
+//!
+//! A small harness that runs fixed allocation patterns against a `GlobalAlloc` and reports the average cost of each operation in TSC cycles, so heap backends (see [`HeapBackend`](crate::heap::HeapBackend)) can be compared on real hardware or under QEMU. The kernel runs it at boot when built with the `heap-bench` feature (`make HEAP_BENCH=1`).
+//!
+//! ## Patterns
+//! - **pairs:** allocate and immediately free the same small size, the common case of short-lived buffers.
+//! - **lifo / fifo:** allocate [`BATCH`] small blocks, then free them in reverse or in allocation order.
+//! - **mixed:** allocate [`BATCH`] blocks of pseudo-random sizes between 16 bytes and 4 KiB and free every other one before the rest, which fragments a first-fit heap.
+//! - **pages:** allocate and free page-sized, page-aligned blocks.
+//!
+//! Cycle counts include the heap lock and any growth of the heap, as every caller of the global allocator pays them too. The TSC is not serializing, so single runs are noisy; compare averages over several boots.
+//!
+//! ## Example
+//! ```ignore
+//! heap_bench::run(&ALLOCATOR);
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+
+use polished_serial_logging::kprint;
+use polished_x86_commands::tsc::rdtsc;
+
+/// Number of blocks live at once in the batch patterns.
+pub const BATCH: usize = 256;
+/// Number of times each pattern is repeated.
+pub const ROUNDS: usize = 16;
+
+/// The result of one benchmark pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    /// Name of the pattern.
+    pub name: &'static str,
+    /// Number of allocations and frees performed.
+    pub operations: u64,
+    /// Total TSC cycles spent.
+    pub cycles: u64,
+    /// Number of allocations that returned null.
+    pub failures: u64,
+}
+
+impl BenchResult {
+    /// Returns the average number of cycles per allocation or free.
+    pub fn cycles_per_operation(&self) -> u64 {
+        self.cycles / self.operations.max(1)
+    }
+}
+
+/// Runs every pattern against `allocator`, prints the results and returns them.
+pub fn run<A: GlobalAlloc>(allocator: &A) -> [BenchResult; 5] {
+    let results = [
+        pairs(allocator),
+        batch(allocator, "lifo", true),
+        batch(allocator, "fifo", false),
+        mixed(allocator),
+        pages(allocator),
+    ];
+    kprint!("[INFO] Heap benchmark ({} rounds):\r\n", ROUNDS);
+    for result in &results {
+        kprint!(
+            "  {:<6} {:>8} ops {:>6} cycles/op {:>4} failed\r\n",
+            result.name,
+            result.operations,
+            result.cycles_per_operation(),
+            result.failures
+        );
+    }
+    results
+}
+
+/// Allocates with `layout`, counting a null result in `result`.
+fn allocate<A: GlobalAlloc>(allocator: &A, layout: Layout, result: &mut BenchResult) -> *mut u8 {
+    let ptr = unsafe { allocator.alloc(layout) };
+    if ptr.is_null() {
+        result.failures += 1;
+    }
+    result.operations += 1;
+    ptr
+}
+
+/// Frees `ptr` unless the allocation failed.
+fn free<A: GlobalAlloc>(allocator: &A, ptr: *mut u8, layout: Layout, result: &mut BenchResult) {
+    if !ptr.is_null() {
+        unsafe { allocator.dealloc(ptr, layout) };
+        result.operations += 1;
+    }
+}
+
+fn new_result(name: &'static str) -> BenchResult {
+    BenchResult {
+        name,
+        operations: 0,
+        cycles: 0,
+        failures: 0,
+    }
+}
+
+fn pairs<A: GlobalAlloc>(allocator: &A) -> BenchResult {
+    let mut result = new_result("pairs");
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let start = rdtsc();
+    for _ in 0..ROUNDS * BATCH {
+        let ptr = allocate(allocator, layout, &mut result);
+        free(allocator, ptr, layout, &mut result);
+    }
+    result.cycles = rdtsc().wrapping_sub(start);
+    result
+}
+
+fn batch<A: GlobalAlloc>(allocator: &A, name: &'static str, reverse: bool) -> BenchResult {
+    let mut result = new_result(name);
+    let layout = Layout::from_size_align(48, 8).unwrap();
+    let mut blocks = [ptr::null_mut(); BATCH];
+    let start = rdtsc();
+    for _ in 0..ROUNDS {
+        for block in blocks.iter_mut() {
+            *block = allocate(allocator, layout, &mut result);
+        }
+        if reverse {
+            for &block in blocks.iter().rev() {
+                free(allocator, block, layout, &mut result);
+            }
+        } else {
+            for &block in blocks.iter() {
+                free(allocator, block, layout, &mut result);
+            }
+        }
+    }
+    result.cycles = rdtsc().wrapping_sub(start);
+    result
+}
+
+fn mixed<A: GlobalAlloc>(allocator: &A) -> BenchResult {
+    let mut result = new_result("mixed");
+    let mut blocks = [(ptr::null_mut(), Layout::new::<u8>()); BATCH];
+    // xorshift, seeded the same on every run so backends see identical sizes.
+    let mut state: u32 = 0x2545_F491;
+    let start = rdtsc();
+    for _ in 0..ROUNDS {
+        for block in blocks.iter_mut() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let size = 16 + (state as usize % 4081);
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            *block = (allocate(allocator, layout, &mut result), layout);
+        }
+        for &(block, layout) in blocks.iter().step_by(2) {
+            free(allocator, block, layout, &mut result);
+        }
+        for &(block, layout) in blocks.iter().skip(1).step_by(2) {
+            free(allocator, block, layout, &mut result);
+        }
+    }
+    result.cycles = rdtsc().wrapping_sub(start);
+    result
+}
+
+fn pages<A: GlobalAlloc>(allocator: &A) -> BenchResult {
+    let mut result = new_result("pages");
+    let layout = Layout::from_size_align(4096, 4096).unwrap();
+    let mut blocks = [ptr::null_mut(); BATCH / 4];
+    let start = rdtsc();
+    for _ in 0..ROUNDS {
+        for block in blocks.iter_mut() {
+            *block = allocate(allocator, layout, &mut result);
+        }
+        for &block in blocks.iter() {
+            free(allocator, block, layout, &mut result);
+        }
+    }
+    result.cycles = rdtsc().wrapping_sub(start);
+    result
+}
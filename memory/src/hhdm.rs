@@ -0,0 +1,66 @@
+//! # Higher-Half Direct Map
+//!
+//! All physical RAM is mapped at [`HHDM_OFFSET`] during memory init, so the kernel can reach any physical address (page tables, free frames, DMA buffers) at `HHDM_OFFSET + physical` without relying on the identity mapping UEFI happens to leave behind. [`init`] builds the map with 1 GiB pages where the CPU supports them and 2 MiB pages otherwise and then makes it the [`physical_memory_offset`](crate::paging::physical_memory_offset) used by the page table code.
+//!
+//! [`phys_to_virt`] and [`virt_to_phys`] convert between the two views; drivers should use them instead of casting physical addresses to pointers.
+//!
+//! ## Caching
+//!
+//! The direct map is write-back cached and covers RAM only. Device memory must be mapped separately with [`crate::vmm::map_region_to`], which maps MMIO regions uncached.
+
+use x86_64::VirtAddr;
+use x86_64::structures::paging::mapper::Translate;
+
+use crate::frame::GlobalFrameAllocator;
+use crate::paging::{
+    self, MapError, PageSize, Protection, physical_memory_offset, set_physical_memory_offset,
+};
+use crate::vmm::{self, Region, RegionKind};
+
+/// Virtual address at which physical address 0 is mapped.
+pub const HHDM_OFFSET: u64 = 0xFFFF_8000_0000_0000;
+/// Largest amount of physical memory the direct map can cover.
+pub const HHDM_MAX_SIZE: u64 = 1 << 46;
+
+/// Maps physical memory `0..physical_end` at [`HHDM_OFFSET`] and switches the page table code over to it.
+///
+/// # Errors
+/// Fails if page table frames run out or part of the range is already mapped. The identity mapping stays in use in that case.
+///
+/// # Safety
+/// Must be called once during memory init, after [`crate::frame::init`], while no other code changes the page tables.
+pub unsafe fn init(physical_end: u64) -> Result<(), MapError> {
+    let end = physical_end.div_ceil(PageSize::Large.bytes()) * PageSize::Large.bytes();
+    let end = end.min(HHDM_MAX_SIZE);
+    let flags = Protection::READ_WRITE.page_table_flags();
+    unsafe { paging::map_range(HHDM_OFFSET, 0, end, flags, &mut GlobalFrameAllocator)? };
+    let region = Region::new(
+        "direct map",
+        HHDM_OFFSET,
+        end,
+        RegionKind::Other,
+        Protection::READ_WRITE,
+    );
+    let _ = vmm::reserve(region);
+    unsafe { set_physical_memory_offset(HHDM_OFFSET) };
+    Ok(())
+}
+
+/// Returns the virtual address at which `physical` can be accessed.
+pub fn phys_to_virt(physical: u64) -> u64 {
+    physical_memory_offset() + physical
+}
+
+/// Returns the physical address mapped at `virt`, or `None` if it is not mapped.
+///
+/// Direct map addresses are converted arithmetically; others are looked up in the active page tables.
+pub fn virt_to_phys(virt: u64) -> Option<u64> {
+    let offset = physical_memory_offset();
+    if offset != 0 && (offset..offset + HHDM_MAX_SIZE).contains(&virt) {
+        return Some(virt - offset);
+    }
+    let table = unsafe { paging::active_page_table() };
+    table
+        .translate_addr(VirtAddr::new(virt))
+        .map(|p| p.as_u64())
+}
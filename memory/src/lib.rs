@@ -1,6 +1,6 @@
 //! # memory
 //!
-//! This crate provides fundamental memory manipulation routines (`memset`, `memcmp`, `memcpy`, and `memmove`) for use in `no_std` Rust environments, such as kernels, bootloaders, or embedded systems.
+//! This crate provides fundamental memory manipulation routines (`memset`, `memcmp`, `memcpy`, and `memmove`), the basic C string routines (`strlen`, `strcmp`, `strncmp`, and `strcpy`) for use in `no_std` Rust environments, such as kernels, bootloaders, or embedded systems, and the kernel's virtual memory management on top of the page tables.
 //!
 //! ## Why is this needed?
 //!
@@ -12,14 +12,79 @@
 //!
 //! All functions in this crate are `unsafe` and require the caller to uphold strict invariants regarding pointer validity, alignment, and region overlap. See each function's documentation for details.
 //!
+//! ## Modules
+//! - `address_space`: Per-task page tables with a private user half and the kernel half shared, switched through CR3.
+//! - `boot_modules`: Named reservations of loader-provided memory (initrd, boot information), released once consumed.
+//! - `buddy`: A binary buddy allocator, the alternative kernel heap backend (feature `buddy-heap`).
+//! - `debug_heap`: A global allocator wrapper that poisons memory, catches double frees and tracks outstanding allocations (feature `debug-heap`).
+//! - `dma`: Physically contiguous, aligned buffers for device DMA.
+//! - `firmware`: On-demand mappings of ACPI tables, SMBIOS and firmware-described MMIO with the right caching.
+//! - `frame`: The physical frame allocator, fed from the usable ranges of the memory map.
+//! - `heap`: The kernel heap, backed by allocated frames and grown on demand, over a pluggable `HeapBackend`.
+//! - `heap_bench`: Allocation patterns timed in TSC cycles, to compare heap backends.
+//! - `hhdm`: The direct map of all physical RAM at a fixed high offset, with `phys_to_virt`/`virt_to_phys`.
+//! - `memory_map`: The kernel's typed map of physical memory, with queries for usable and reserved ranges.
+//! - `paging`: Access to the active page tables, single-page mapping, and the `Protection` of a mapping.
+//! - `percpu`: Per-CPU data blocks reached through the GS base, and the `per_cpu!` accessor.
+//! - `redzone`: A global allocator wrapper that surrounds allocations with canary redzones and reports overruns (feature `redzone-heap`).
+//! - `stack`: Guarded kernel stacks for tasks and the TSS (`alloc_kernel_stack`).
+//! - `stats`: Frame, heap and region usage statistics (`memory::stats()`).
+//! - `uefi_map`: The raw UEFI memory map handed over by the bootloader.
+//! - `vmm`: Named virtual memory regions with overlap detection, lookup by address, and dumps.
+//!
 //! ## Usage
 //!
 //! Link this crate into your `no_std` project to satisfy the compiler's requirements for these memory routines. You may also use these functions directly if needed.
 
 #![no_std]
 
+use core::ffi::{c_char, c_int};
 use core::ptr;
 
+/// User address spaces.
+pub mod address_space;
+/// Loader-provided memory reservations.
+pub mod boot_modules;
+/// The buddy allocator heap backend.
+pub mod buddy;
+/// Allocator call site recording.
+#[cfg(any(feature = "debug-heap", feature = "redzone-heap"))]
+mod call_site;
+/// Heap debugging.
+#[cfg(feature = "debug-heap")]
+pub mod debug_heap;
+/// DMA buffer allocation.
+pub mod dma;
+/// Firmware region mapping.
+pub mod firmware;
+/// Physical frame allocation.
+pub mod frame;
+/// The growable kernel heap.
+pub mod heap;
+/// Heap allocator benchmarks.
+pub mod heap_bench;
+/// The higher-half direct map of physical memory.
+pub mod hhdm;
+/// The physical memory map.
+pub mod memory_map;
+/// Page table access and mapping.
+pub mod paging;
+/// Per-CPU data.
+pub mod percpu;
+/// Heap redzone checking.
+#[cfg(feature = "redzone-heap")]
+pub mod redzone;
+/// Kernel stack allocation.
+pub mod stack;
+/// Memory usage statistics.
+pub mod stats;
+/// The UEFI memory map.
+pub mod uefi_map;
+
+pub use stats::{MemoryStats, stats};
+/// Virtual memory region tracking.
+pub mod vmm;
+
 /// Sets `count` bytes starting at `dest` to the given `value`.
 ///
 /// # Safety
@@ -90,3 +155,137 @@ pub unsafe extern "C" fn memmove(dest: *mut u8, src: *const u8, count: usize) {
         }
     }
 }
+
+/// Returns the number of bytes in the NUL-terminated string `s`, not counting the terminator.
+///
+/// # Safety
+///
+/// - `s` must point to a readable, NUL-terminated sequence of bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strlen(s: *const c_char) -> usize {
+    let mut len = 0;
+    while unsafe { ptr::read(s.add(len)) } != 0 {
+        len += 1;
+    }
+    len
+}
+
+/// Compares the NUL-terminated strings `s1` and `s2`.
+///
+/// Returns a negative value, zero, or a positive value if `s1` sorts before, equal to, or after `s2`, comparing bytes as unsigned.
+///
+/// # Safety
+///
+/// - Both `s1` and `s2` must point to readable, NUL-terminated sequences of bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strcmp(s1: *const c_char, s2: *const c_char) -> c_int {
+    unsafe { strncmp(s1, s2, usize::MAX) }
+}
+
+/// Compares at most `n` bytes of the NUL-terminated strings `s1` and `s2`.
+///
+/// Returns a negative value, zero, or a positive value if `s1` sorts before, equal to, or after `s2`, comparing bytes as unsigned.
+///
+/// # Safety
+///
+/// - Both `s1` and `s2` must be readable up to their NUL terminator or `n` bytes, whichever comes first.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strncmp(s1: *const c_char, s2: *const c_char, n: usize) -> c_int {
+    for i in 0..n {
+        let a = unsafe { ptr::read(s1.add(i)) } as u8;
+        let b = unsafe { ptr::read(s2.add(i)) } as u8;
+        if a != b {
+            return a as c_int - b as c_int;
+        }
+        if a == 0 {
+            break;
+        }
+    }
+    0
+}
+
+/// Copies the NUL-terminated string `src`, including its terminator, to `dest` and returns `dest`.
+///
+/// # Safety
+///
+/// - `src` must point to a readable, NUL-terminated sequence of bytes.
+/// - `dest` must be valid for writes of `strlen(src) + 1` bytes.
+/// - The strings must not overlap.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strcpy(dest: *mut c_char, src: *const c_char) -> *mut c_char {
+    let mut i = 0;
+    loop {
+        let byte = unsafe { ptr::read(src.add(i)) };
+        unsafe { ptr::write(dest.add(i), byte) };
+        if byte == 0 {
+            return dest;
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &[u8]) -> *const c_char {
+        s.as_ptr().cast()
+    }
+
+    #[test]
+    fn strlen_counts_up_to_the_terminator() {
+        unsafe {
+            assert_eq!(strlen(c(b"\0")), 0);
+            assert_eq!(strlen(c(b"polished\0")), 8);
+            assert_eq!(strlen(c(b"ab\0cd\0")), 2);
+        }
+    }
+
+    #[test]
+    fn strcmp_orders_bytes_as_unsigned() {
+        unsafe {
+            assert_eq!(strcmp(c(b"\0"), c(b"\0")), 0);
+            assert_eq!(strcmp(c(b"abc\0"), c(b"abc\0")), 0);
+            assert!(strcmp(c(b"abc\0"), c(b"abd\0")) < 0);
+            assert!(strcmp(c(b"abd\0"), c(b"abc\0")) > 0);
+            assert!(strcmp(c(b"\0"), c(b"a\0")) < 0);
+            assert!(strcmp(c(b"ab\0"), c(b"abc\0")) < 0);
+            assert!(strcmp(c(b"abc\0"), c(b"ab\0")) > 0);
+            // 0x80 and above sort after ASCII, even where `c_char` is signed
+            assert!(strcmp(c(b"\x80\0"), c(b"\x7f\0")) > 0);
+            assert!(strcmp(c(b"a\xff\0"), c(b"a\x01\0")) > 0);
+            assert!(strcmp(c(b"\x01\0"), c(b"\xfe\0")) < 0);
+        }
+    }
+
+    #[test]
+    fn strncmp_stops_after_n_bytes() {
+        unsafe {
+            assert_eq!(strncmp(c(b"abcx\0"), c(b"abcy\0"), 0), 0);
+            assert_eq!(strncmp(c(b"abcx\0"), c(b"abcy\0"), 3), 0);
+            assert!(strncmp(c(b"abcx\0"), c(b"abcy\0"), 4) < 0);
+            // Past the terminator of equal strings nothing more is compared
+            assert_eq!(strncmp(c(b"abc\0x"), c(b"abc\0y"), 10), 0);
+            assert!(strncmp(c(b"ab\0"), c(b"abc\0"), 10) < 0);
+            assert!(strncmp(c(b"\x90\0"), c(b"\x10\0"), 1) > 0);
+        }
+    }
+
+    #[test]
+    fn strcpy_copies_the_terminator_and_returns_dest() {
+        let mut dest = [0x55 as c_char; 8];
+        unsafe {
+            let returned = strcpy(dest.as_mut_ptr(), c(b"hi!\0"));
+            assert_eq!(returned, dest.as_mut_ptr());
+            assert_eq!(strcmp(dest.as_ptr(), c(b"hi!\0")), 0);
+        }
+        assert_eq!(dest[3], 0);
+        assert_eq!(dest[4], 0x55);
+
+        let mut empty = [0x55 as c_char; 2];
+        unsafe {
+            assert_eq!(strcpy(empty.as_mut_ptr(), c(b"\0")), empty.as_mut_ptr());
+        }
+        assert_eq!(empty, [0, 0x55]);
+    }
+}
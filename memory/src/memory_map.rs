@@ -0,0 +1,292 @@
+//! # Physical Memory Map
+//!
+//! The kernel keeps its own [`MemoryMap`] after boot: a sorted list of typed physical ranges built from the UEFI memory map, refined with what the kernel knows better than the firmware (where its image is, where an initrd was loaded). The frame allocator is fed from [`MemoryMap::usable_ranges`], and drivers ask [`is_usable`] or [`kind_of`] before touching physical memory so that they stay out of ACPI tables, MMIO windows and loader data.
+//!
+//! ## Refining
+//!
+//! [`MemoryMap::mark`] retypes a range, splitting the regions it overlaps. Adjacent regions of the same kind are merged so the table stays small.
+//!
+//! ## Example
+//! ```ignore
+//! let mut map = MemoryMap::from_uefi(&uefi_map);
+//! map.mark(kernel_start, kernel_end, MemoryKind::KernelImage);
+//! frame::init(map.usable_ranges());
+//! memory_map::install(map);
+//! ```
+
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::uefi_map::{self, UefiMemoryMap};
+
+/// Maximum number of regions in a [`MemoryMap`].
+pub const MAX_MEMORY_REGIONS: usize = 256;
+
+/// What a range of physical memory holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Free RAM.
+    Usable,
+    /// Anything the kernel must not touch.
+    Reserved,
+    /// ACPI tables, reusable once they have been parsed.
+    AcpiReclaimable,
+    /// ACPI non-volatile storage.
+    AcpiNvs,
+    /// Device memory.
+    Mmio,
+    /// Bootloader code and data, including the data it passed to the kernel.
+    Loader,
+    /// Firmware boot services code and data.
+    BootServices,
+    /// The kernel image and boot stack.
+    KernelImage,
+    /// An initial ramdisk.
+    Initrd,
+}
+
+impl MemoryKind {
+    /// Returns the kind of a UEFI memory type.
+    pub fn from_uefi(ty: u32) -> MemoryKind {
+        match ty {
+            uefi_map::CONVENTIONAL_MEMORY => MemoryKind::Usable,
+            uefi_map::LOADER_CODE | uefi_map::LOADER_DATA => MemoryKind::Loader,
+            uefi_map::BOOT_SERVICES_CODE | uefi_map::BOOT_SERVICES_DATA => MemoryKind::BootServices,
+            uefi_map::ACPI_RECLAIM_MEMORY => MemoryKind::AcpiReclaimable,
+            uefi_map::ACPI_MEMORY_NVS => MemoryKind::AcpiNvs,
+            uefi_map::MEMORY_MAPPED_IO => MemoryKind::Mmio,
+            _ => MemoryKind::Reserved,
+        }
+    }
+}
+
+/// A range of physical memory of one kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    /// First address.
+    pub start: u64,
+    /// Address after the last byte.
+    pub end: u64,
+    /// What the range holds.
+    pub kind: MemoryKind,
+}
+
+impl MemoryRegion {
+    /// Returns the size in bytes.
+    pub const fn size(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// A sorted table of typed physical memory regions.
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    regions: [MemoryRegion; MAX_MEMORY_REGIONS],
+    len: usize,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryMap {
+    /// Creates an empty map.
+    pub const fn new() -> Self {
+        MemoryMap {
+            regions: [MemoryRegion {
+                start: 0,
+                end: 0,
+                kind: MemoryKind::Reserved,
+            }; MAX_MEMORY_REGIONS],
+            len: 0,
+        }
+    }
+
+    /// Builds a map from the UEFI memory map.
+    pub fn from_uefi(uefi: &UefiMemoryMap) -> Self {
+        let mut map = MemoryMap::new();
+        for descriptor in uefi.iter() {
+            map.mark(
+                descriptor.physical_start,
+                descriptor.physical_end(),
+                MemoryKind::from_uefi(descriptor.ty),
+            );
+        }
+        map
+    }
+
+    /// Returns the regions in address order.
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions[..self.len]
+    }
+
+    /// Iterates over the regions in address order.
+    pub fn iter(&self) -> impl Iterator<Item = &MemoryRegion> {
+        self.regions().iter()
+    }
+
+    /// Iterates over the usable ranges as `(start, end)` pairs, as taken by [`crate::frame::init`].
+    pub fn usable_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.iter()
+            .filter(|r| r.kind == MemoryKind::Usable)
+            .map(|r| (r.start, r.end))
+    }
+
+    /// Returns the total number of usable bytes.
+    pub fn usable_bytes(&self) -> u64 {
+        self.usable_ranges().map(|(start, end)| end - start).sum()
+    }
+
+    /// Returns the end of the highest region holding RAM (anything but MMIO and reserved ranges).
+    pub fn ram_end(&self) -> u64 {
+        self.iter()
+            .filter(|r| !matches!(r.kind, MemoryKind::Mmio | MemoryKind::Reserved))
+            .map(|r| r.end)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the kind of memory at `address`, or `None` if the map does not describe it.
+    pub fn kind_of(&self, address: u64) -> Option<MemoryKind> {
+        self.iter()
+            .find(|r| r.start <= address && address < r.end)
+            .map(|r| r.kind)
+    }
+
+    /// Returns whether all of `start..end` is usable RAM.
+    pub fn is_usable(&self, start: u64, end: u64) -> bool {
+        self.covered_by(start, end, |kind| kind == MemoryKind::Usable)
+    }
+
+    /// Returns whether any part of `start..end` is described as something other than usable RAM.
+    pub fn overlaps_reserved(&self, start: u64, end: u64) -> bool {
+        self.iter()
+            .any(|r| r.kind != MemoryKind::Usable && r.start < end && start < r.end)
+    }
+
+    /// Returns whether `start..end` is completely covered by regions whose kind satisfies `accept`.
+    fn covered_by(&self, start: u64, end: u64, accept: impl Fn(MemoryKind) -> bool) -> bool {
+        let mut covered = start;
+        for r in self.iter().filter(|r| r.end > start && r.start < end) {
+            if r.start > covered || !accept(r.kind) {
+                return false;
+            }
+            covered = r.end;
+        }
+        covered >= end
+    }
+
+    /// Marks `start..end` as `kind`, splitting the regions it overlaps.
+    ///
+    /// Returns `false` (and leaves the map unchanged) if the table would overflow.
+    pub fn mark(&mut self, start: u64, end: u64, kind: MemoryKind) -> bool {
+        if start >= end {
+            return true;
+        }
+        let mut next = MemoryMap::new();
+        let mut inserted = false;
+        for &r in self.regions() {
+            if r.end <= start || r.start >= end {
+                if !inserted && r.start >= end {
+                    inserted = true;
+                    if !next.push(MemoryRegion { start, end, kind }) {
+                        return false;
+                    }
+                }
+                if !next.push(r) {
+                    return false;
+                }
+                continue;
+            }
+            if r.start < start && !next.push(MemoryRegion { end: start, ..r }) {
+                return false;
+            }
+            if !inserted {
+                inserted = true;
+                if !next.push(MemoryRegion { start, end, kind }) {
+                    return false;
+                }
+            }
+            if r.end > end && !next.push(MemoryRegion { start: end, ..r }) {
+                return false;
+            }
+        }
+        if !inserted && !next.push(MemoryRegion { start, end, kind }) {
+            return false;
+        }
+        *self = next;
+        true
+    }
+
+    /// Appends `region`, merging it into the last region if they are adjacent and of the same kind.
+    fn push(&mut self, region: MemoryRegion) -> bool {
+        if let Some(last) = self.regions[..self.len].last_mut()
+            && last.end == region.start
+            && last.kind == region.kind
+        {
+            last.end = region.end;
+            return true;
+        }
+        if self.len == MAX_MEMORY_REGIONS {
+            return false;
+        }
+        self.regions[self.len] = region;
+        self.len += 1;
+        true
+    }
+
+    /// Prints the map to the serial log.
+    pub fn dump(&self) {
+        kprint!("[INFO] Physical memory map:\r\n");
+        for r in self.iter() {
+            kprint!(
+                "  {:#014x}-{:#014x} {:>8} KiB {:?}\r\n",
+                r.start,
+                r.end,
+                r.size() / 1024,
+                r.kind
+            );
+        }
+    }
+}
+
+static MEMORY_MAP: Mutex<Option<MemoryMap>> = Mutex::new(None);
+
+/// Installs the kernel's memory map. Only the first call has an effect.
+pub fn install(map: MemoryMap) {
+    interrupts::without_interrupts(|| {
+        MEMORY_MAP.lock().get_or_insert(map);
+    });
+}
+
+/// Runs `f` on the kernel's memory map, if it has been installed.
+pub fn with<R>(f: impl FnOnce(&MemoryMap) -> R) -> Option<R> {
+    interrupts::without_interrupts(|| MEMORY_MAP.lock().as_ref().map(f))
+}
+
+/// Retypes `start..end` in the installed map, e.g. when reserved memory is given back (see [`MemoryMap::mark`]).
+///
+/// Returns `false` if no map is installed or the table is full.
+pub fn mark(start: u64, end: u64, kind: MemoryKind) -> bool {
+    interrupts::without_interrupts(|| {
+        MEMORY_MAP
+            .lock()
+            .as_mut()
+            .is_some_and(|map| map.mark(start, end, kind))
+    })
+}
+
+/// Returns whether all of `start..end` is usable RAM according to the installed map.
+///
+/// Without a map nothing is considered usable.
+pub fn is_usable(start: u64, end: u64) -> bool {
+    with(|map| map.is_usable(start, end)).unwrap_or(false)
+}
+
+/// Returns the kind of memory at `address` according to the installed map.
+pub fn kind_of(address: u64) -> Option<MemoryKind> {
+    with(|map| map.kind_of(address)).flatten()
+}
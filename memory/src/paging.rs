@@ -0,0 +1,529 @@
+//! # Page Table Access
+//!
+//! The kernel still runs on the page tables UEFI set up, which identity map physical memory. This module gives the rest of the memory subsystem one way to reach them: [`active_page_table`] wraps the PML4 in CR3 in an `OffsetPageTable`, [`map_page`]/[`unmap_page`] change single 4 KiB mappings and flush the TLB entry, and [`page_mapping`] tells how a page is mapped.
+//!
+//! Page tables are reached through [`physical_memory_offset`], the virtual address at which physical address 0 is mapped: 0 while the kernel relies on the identity mapping, [`crate::hhdm::HHDM_OFFSET`] once the direct map is built.
+//!
+//! ## Flags
+//!
+//! [`Protection`] is the architecture-neutral protection of a mapping. [`Protection::page_table_flags`] only sets `NO_EXECUTE` when `EFER.NXE` is enabled, since the bit is reserved (and faults) otherwise.
+//!
+//! ## Memory Types
+//!
+//! [`init_pat`] reprograms the page attribute table so that [`WRITE_COMBINING`] selects write-combining memory, which the framebuffer is mapped with: pixel writes are then buffered and sent in bursts instead of one uncached bus transaction each.
+//!
+//! ## W^X
+//!
+//! [`enable_nx`] turns on `EFER.NXE` and [`enable_write_protect`] makes read-only pages read-only for the kernel too (`CR0.WP`). [`protect_range`] then changes the protection of memory that is already mapped, splitting the 1 GiB and 2 MiB pages UEFI maps memory with where needed, so the kernel image can be mapped with text executable but not writable and everything else not executable.
+
+use core::ops::BitOr;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use polished_x86_commands::msr::{self, IA32_PAT};
+use x86_64::instructions::tlb;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr3};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::mapper::{
+    MapToError, MappedFrame, Translate, TranslateResult, UnmapError,
+};
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size1GiB,
+    Size2MiB, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Size of a page.
+pub const PAGE_SIZE: u64 = 4096;
+
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the virtual address at which physical memory is mapped (0 for the identity mapping).
+pub fn physical_memory_offset() -> u64 {
+    PHYSICAL_MEMORY_OFFSET.load(Ordering::Acquire)
+}
+
+/// Sets the virtual address at which physical memory is mapped.
+///
+/// # Safety
+/// All physical memory holding page tables must be mapped at `offset`.
+pub unsafe fn set_physical_memory_offset(offset: u64) {
+    PHYSICAL_MEMORY_OFFSET.store(offset, Ordering::Release);
+}
+
+/// Returns the page tables in CR3.
+///
+/// # Safety
+/// The caller must not create two mappers at once that modify the same tables, e.g. by serializing all changes behind a lock.
+pub unsafe fn active_page_table() -> OffsetPageTable<'static> {
+    let (frame, _) = Cr3::read();
+    let offset = VirtAddr::new(physical_memory_offset());
+    unsafe {
+        let pml4 = &mut *(offset + frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
+        OffsetPageTable::new(pml4, offset)
+    }
+}
+
+/// Rounds `value` up to a multiple of [`PAGE_SIZE`].
+pub const fn page_align_up(value: u64) -> u64 {
+    value.div_ceil(PAGE_SIZE) * PAGE_SIZE
+}
+
+/// Access rights of a mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Protection(u8);
+
+impl Protection {
+    /// No access.
+    pub const NONE: Protection = Protection(0);
+    /// Readable.
+    pub const READ: Protection = Protection(1 << 0);
+    /// Writable.
+    pub const WRITE: Protection = Protection(1 << 1);
+    /// Executable.
+    pub const EXECUTE: Protection = Protection(1 << 2);
+    /// Accessible from user mode.
+    pub const USER: Protection = Protection(1 << 3);
+    /// Readable and writable.
+    pub const READ_WRITE: Protection = Protection(Self::READ.0 | Self::WRITE.0);
+
+    /// Returns whether all rights in `other` are included.
+    pub const fn contains(self, other: Protection) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the page table flags of a present mapping with this protection.
+    pub fn page_table_flags(self) -> PageTableFlags {
+        let mut flags = PageTableFlags::PRESENT;
+        if self.contains(Protection::WRITE) {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if self.contains(Protection::USER) {
+            flags |= PageTableFlags::USER_ACCESSIBLE;
+        }
+        if !self.contains(Protection::EXECUTE)
+            && Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE)
+        {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        flags
+    }
+}
+
+impl BitOr for Protection {
+    type Output = Protection;
+
+    fn bitor(self, other: Protection) -> Protection {
+        Protection(self.0 | other.0)
+    }
+}
+
+impl core::fmt::Display for Protection {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let flag = |right, c| if self.contains(right) { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}{}",
+            flag(Protection::READ, 'r'),
+            flag(Protection::WRITE, 'w'),
+            flag(Protection::EXECUTE, 'x'),
+            flag(Protection::USER, 'u')
+        )
+    }
+}
+
+/// Maps the page at `virtual_address` to the frame at `physical_address` in the active page tables and flushes it from the TLB.
+///
+/// Page table frames needed on the way are taken from `frames`.
+///
+/// # Safety
+/// The new mapping must not break memory safety, e.g. by aliasing memory in use, and no other mapper may be active.
+pub unsafe fn map_page(
+    virtual_address: u64,
+    physical_address: u64,
+    flags: PageTableFlags,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(virtual_address));
+    let frame = PhysFrame::containing_address(PhysAddr::new(physical_address));
+    let parent_flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | (flags & PageTableFlags::USER_ACCESSIBLE);
+    unsafe {
+        active_page_table()
+            .map_to_with_table_flags(page, frame, flags, parent_flags, frames)?
+            .flush();
+    }
+    Ok(())
+}
+
+/// Removes the mapping of the page at `virtual_address` from the active page tables, returning the frame it mapped.
+///
+/// # Safety
+/// Nothing may use the page anymore, and no other mapper may be active.
+pub unsafe fn unmap_page(virtual_address: u64) -> Result<PhysFrame, UnmapError> {
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(virtual_address));
+    let (frame, flush) = unsafe { active_page_table() }.unmap(page)?;
+    flush.flush();
+    Ok(frame)
+}
+
+/// How one page is mapped in the active page tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageMapping {
+    /// Nothing is mapped.
+    Unmapped,
+    /// A 4 KiB page maps this frame with these flags.
+    Small(PhysFrame, PageTableFlags),
+    /// The page is part of a larger page.
+    Large(PageSize),
+}
+
+/// Returns how the page at `virtual_address` is mapped in the active page tables.
+pub fn page_mapping(virtual_address: u64) -> PageMapping {
+    let table = unsafe { active_page_table() };
+    match table.translate(VirtAddr::new(virtual_address)) {
+        TranslateResult::Mapped { frame, flags, .. } => match frame {
+            MappedFrame::Size4KiB(frame) => PageMapping::Small(frame, flags),
+            MappedFrame::Size2MiB(_) => PageMapping::Large(PageSize::Large),
+            MappedFrame::Size1GiB(_) => PageMapping::Large(PageSize::Huge),
+        },
+        _ => PageMapping::Unmapped,
+    }
+}
+
+/// Sizes of pages the MMU can map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    /// 4 KiB pages, mapped by a page table entry.
+    Small,
+    /// 2 MiB pages, mapped by a page directory entry.
+    Large,
+    /// 1 GiB pages, mapped by a page directory pointer table entry (needs CPU support).
+    Huge,
+}
+
+impl PageSize {
+    /// Returns the size in bytes.
+    pub const fn bytes(self) -> u64 {
+        match self {
+            PageSize::Small => PAGE_SIZE,
+            PageSize::Large => 512 * PAGE_SIZE,
+            PageSize::Huge => 512 * 512 * PAGE_SIZE,
+        }
+    }
+
+    /// Returns the largest page size that maps `virtual_address` to `physical_address` within `remaining` bytes.
+    pub fn largest_fitting(
+        virtual_address: u64,
+        physical_address: u64,
+        remaining: u64,
+    ) -> PageSize {
+        let fits = |size: PageSize| {
+            let bytes = size.bytes();
+            virtual_address.is_multiple_of(bytes)
+                && physical_address.is_multiple_of(bytes)
+                && remaining >= bytes
+        };
+        if fits(PageSize::Huge) && gigabyte_pages_supported() {
+            PageSize::Huge
+        } else if fits(PageSize::Large) {
+            PageSize::Large
+        } else {
+            PageSize::Small
+        }
+    }
+}
+
+/// Returns whether the CPU supports 1 GiB pages.
+pub fn gigabyte_pages_supported() -> bool {
+    const PDPE1GB: u32 = 1 << 26;
+    core::arch::x86_64::__cpuid(0x8000_0000).eax >= 0x8000_0001
+        && core::arch::x86_64::__cpuid(0x8000_0001).edx & PDPE1GB != 0
+}
+
+/// Errors from [`map_range`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// No frame was left for a page table.
+    FrameAllocationFailed,
+    /// The page at this address is already mapped, or lies in a huge page.
+    AlreadyMapped(u64),
+}
+
+impl MapError {
+    fn at<S: x86_64::structures::paging::PageSize>(
+        error: MapToError<S>,
+        address: VirtAddr,
+    ) -> Self {
+        match error {
+            MapToError::FrameAllocationFailed => MapError::FrameAllocationFailed,
+            MapToError::ParentEntryHugePage | MapToError::PageAlreadyMapped(_) => {
+                MapError::AlreadyMapped(address.as_u64())
+            }
+        }
+    }
+}
+
+/// Maps `size` bytes at `virtual_address` to the physically contiguous memory at `physical_address` in the active page tables, using 1 GiB and 2 MiB pages wherever both addresses are aligned for them.
+///
+/// `flags` are the flags of a 4 KiB mapping; `HUGE_PAGE` is added for larger pages. Page table frames come from `frames`. On failure, everything mapped by this call is unmapped again.
+///
+/// # Safety
+/// The new mappings must not break memory safety, e.g. by aliasing memory in use, and no other mapper may be active.
+pub unsafe fn map_range(
+    virtual_address: u64,
+    physical_address: u64,
+    size: u64,
+    flags: PageTableFlags,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapError> {
+    let size = page_align_up(size);
+    let parent_flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | (flags & PageTableFlags::USER_ACCESSIBLE);
+    let mut table = unsafe { active_page_table() };
+    let mut offset = 0;
+    while offset < size {
+        let virt = VirtAddr::new(virtual_address + offset);
+        let phys = PhysAddr::new(physical_address + offset);
+        let page_size = PageSize::largest_fitting(virt.as_u64(), phys.as_u64(), size - offset);
+        let result = unsafe {
+            match page_size {
+                PageSize::Huge => table
+                    .map_to_with_table_flags(
+                        Page::<Size1GiB>::containing_address(virt),
+                        PhysFrame::<Size1GiB>::containing_address(phys),
+                        flags,
+                        parent_flags,
+                        frames,
+                    )
+                    .map(|flush| flush.flush())
+                    .map_err(|e| MapError::at(e, virt)),
+                PageSize::Large => table
+                    .map_to_with_table_flags(
+                        Page::<Size2MiB>::containing_address(virt),
+                        PhysFrame::<Size2MiB>::containing_address(phys),
+                        flags,
+                        parent_flags,
+                        frames,
+                    )
+                    .map(|flush| flush.flush())
+                    .map_err(|e| MapError::at(e, virt)),
+                PageSize::Small => table
+                    .map_to_with_table_flags(
+                        Page::<Size4KiB>::containing_address(virt),
+                        PhysFrame::<Size4KiB>::containing_address(phys),
+                        flags,
+                        parent_flags,
+                        frames,
+                    )
+                    .map(|flush| flush.flush())
+                    .map_err(|e| MapError::at(e, virt)),
+            }
+        };
+        if let Err(error) = result {
+            unsafe { unmap_range(virtual_address, offset) };
+            return Err(error);
+        }
+        offset += page_size.bytes();
+    }
+    Ok(())
+}
+
+/// Unmaps every page in `virtual_address..virtual_address + size` from the active page tables, whatever its size, skipping holes. Frames are not freed.
+///
+/// A huge page that only partly overlaps the range is unmapped whole.
+///
+/// # Safety
+/// Nothing may use the memory anymore, and no other mapper may be active.
+pub unsafe fn unmap_range(virtual_address: u64, size: u64) {
+    let mut table = unsafe { active_page_table() };
+    let end = virtual_address + size;
+    let mut address = virtual_address & !(PAGE_SIZE - 1);
+    while address < end {
+        let virt = VirtAddr::new(address);
+        let step = match table.translate(virt) {
+            TranslateResult::Mapped { frame, .. } => {
+                let page_size = match frame {
+                    MappedFrame::Size4KiB(_) => PageSize::Small,
+                    MappedFrame::Size2MiB(_) => PageSize::Large,
+                    MappedFrame::Size1GiB(_) => PageSize::Huge,
+                };
+                match page_size {
+                    PageSize::Small => {
+                        if let Ok((_, flush)) =
+                            table.unmap(Page::<Size4KiB>::containing_address(virt))
+                        {
+                            flush.flush();
+                        }
+                    }
+                    PageSize::Large => {
+                        if let Ok((_, flush)) =
+                            table.unmap(Page::<Size2MiB>::containing_address(virt))
+                        {
+                            flush.flush();
+                        }
+                    }
+                    PageSize::Huge => {
+                        if let Ok((_, flush)) =
+                            table.unmap(Page::<Size1GiB>::containing_address(virt))
+                        {
+                            flush.flush();
+                        }
+                    }
+                }
+                page_size.bytes()
+            }
+            _ => PAGE_SIZE,
+        };
+        address = (address & !(step - 1)) + step;
+    }
+}
+
+/// Enables no-execute pages (`EFER.NXE`) if the CPU supports them, and returns whether they are enabled.
+///
+/// Mappings made before this call have no `NO_EXECUTE` bit.
+pub fn enable_nx() -> bool {
+    const NX: u32 = 1 << 20;
+    if core::arch::x86_64::__cpuid(0x8000_0000).eax < 0x8000_0001
+        || core::arch::x86_64::__cpuid(0x8000_0001).edx & NX == 0
+    {
+        return false;
+    }
+    unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+    true
+}
+
+/// Makes read-only pages read-only in kernel mode too (`CR0.WP`).
+pub fn enable_write_protect() {
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)) };
+}
+
+/// Page attribute table programmed by [`init_pat`], entries 0 to 7: write-back, write-combining, uncached-minus, uncached, write-back, write-protect, uncached-minus, write-through.
+///
+/// Entries 0, 2 and 3 keep their power-on types, so mappings that only use `WRITE_THROUGH` and `NO_CACHE` for uncached memory behave as before; entry 1 (`WRITE_THROUGH` alone) becomes write-combining.
+pub const PAT_VALUE: u64 = 0x0407_0506_0007_0106;
+
+static WRITE_COMBINING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Page table flags selecting the write-combining entry of the PAT set up by [`init_pat`], for 4 KiB, 2 MiB and 1 GiB pages alike.
+pub const WRITE_COMBINING: PageTableFlags = PageTableFlags::WRITE_THROUGH;
+
+/// Programs the page attribute table with [`PAT_VALUE`] if the CPU supports it, and returns whether [`WRITE_COMBINING`] is available.
+///
+/// Caches and the TLB are flushed afterwards, as the SDM requires when memory types change.
+pub fn init_pat() -> bool {
+    const PAT: u32 = 1 << 16;
+    if core::arch::x86_64::__cpuid(1).edx & PAT == 0 {
+        return false;
+    }
+    unsafe {
+        msr::wrmsr(IA32_PAT, PAT_VALUE);
+        core::arch::asm!("wbinvd", options(nostack, preserves_flags));
+    }
+    tlb::flush_all();
+    WRITE_COMBINING_ENABLED.store(true, Ordering::Release);
+    true
+}
+
+/// Returns whether [`init_pat`] made [`WRITE_COMBINING`] available.
+pub fn write_combining_enabled() -> bool {
+    WRITE_COMBINING_ENABLED.load(Ordering::Acquire)
+}
+
+/// Errors from [`protect_range`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectError {
+    /// The page at this address is not mapped.
+    NotMapped(u64),
+    /// No frame was left for the page table of a split huge page.
+    OutOfFrames,
+}
+
+/// Sets the protection of the mapped pages covering `start..end` in the active page tables, keeping their frames and caching attributes.
+///
+/// Huge pages that are only partly covered are split into smaller pages first, with page tables from `frames`.
+///
+/// # Safety
+/// The new protection must not break code that is running, e.g. by making the current stack read-only, and no other mapper may be active.
+pub unsafe fn protect_range(
+    start: u64,
+    end: u64,
+    protection: Protection,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), ProtectError> {
+    let flags = protection.page_table_flags();
+    let kept = PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH | PageTableFlags::GLOBAL;
+    let mut address = start & !(PAGE_SIZE - 1);
+    while address < end {
+        let entry = unsafe { leaf_entry(address, frames)? };
+        entry.set_flags(flags | (entry.flags() & kept));
+        tlb::flush(VirtAddr::new(address));
+        address += PAGE_SIZE;
+    }
+    Ok(())
+}
+
+/// Returns the page table at physical address `physical`.
+unsafe fn table_at(physical: u64) -> &'static mut PageTable {
+    unsafe { &mut *((physical_memory_offset() + physical) as *mut PageTable) }
+}
+
+/// Returns the 4 KiB page table entry mapping `address`, splitting huge pages on the way.
+unsafe fn leaf_entry(
+    address: u64,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<&'static mut PageTableEntry, ProtectError> {
+    let virt = VirtAddr::new(address);
+    let (pml4, _) = Cr3::read();
+    let mut table = unsafe { table_at(pml4.start_address().as_u64()) };
+    for (level, index) in [virt.p4_index(), virt.p3_index(), virt.p2_index()]
+        .into_iter()
+        .enumerate()
+    {
+        let entry = &mut table[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return Err(ProtectError::NotMapped(address));
+        }
+        if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            unsafe { split_huge_page(entry, level, frames)? };
+        }
+        table = unsafe { table_at(entry.addr().as_u64()) };
+    }
+    let entry = &mut table[virt.p1_index()];
+    if !entry.flags().contains(PageTableFlags::PRESENT) {
+        return Err(ProtectError::NotMapped(address));
+    }
+    Ok(entry)
+}
+
+/// Replaces the huge page mapped by `entry` (at table `level`: 1 for 1 GiB, 2 for 2 MiB pages) with a table of 512 smaller pages mapping the same memory with the same flags.
+unsafe fn split_huge_page(
+    entry: &mut PageTableEntry,
+    level: usize,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), ProtectError> {
+    let child_size = if level == 1 {
+        512 * PAGE_SIZE
+    } else {
+        PAGE_SIZE
+    };
+    let mut child_flags = entry.flags();
+    if level == 2 {
+        // In 4 KiB entries, bit 7 selects the PAT instead of a huge page.
+        child_flags.remove(PageTableFlags::HUGE_PAGE);
+    }
+    // Masking to the huge page size also drops the PAT bit (bit 12) of huge entries.
+    let base = entry.addr().as_u64() & !(512 * child_size - 1);
+    let frame = frames.allocate_frame().ok_or(ProtectError::OutOfFrames)?;
+    let child = unsafe { table_at(frame.start_address().as_u64()) };
+    for (i, child_entry) in child.iter_mut().enumerate() {
+        child_entry.set_addr(PhysAddr::new(base + i as u64 * child_size), child_flags);
+    }
+    let parent_flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | (child_flags & PageTableFlags::USER_ACCESSIBLE);
+    entry.set_addr(frame.start_address(), parent_flags);
+    Ok(())
+}
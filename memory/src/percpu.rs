@@ -0,0 +1,134 @@
+//! # Per-CPU Data
+//!
+//! Each CPU gets its own [`PerCpu`] block, allocated from the frame allocator and reached through the GS segment base, so code can find "its" CPU's data with a single `gs`-relative load and without locks. This is what SMP, per-CPU run queues and fast current-task lookup build on.
+//!
+//! ## GS Base Convention
+//!
+//! While the CPU runs kernel code, `GS_BASE` points to its [`PerCpu`] block and `KERNEL_GS_BASE` holds the user's GS base (0). Entry paths from user mode execute `swapgs` to exchange the two, and exit paths swap them back. The first word of the block points to the block itself, so [`current`] is one `mov reg, gs:[0]`.
+//!
+//! ## Example
+//! ```ignore
+//! percpu::init_cpu(0)?;
+//! let task = per_cpu!(current_task).load(Ordering::Relaxed);
+//! ```
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+use x86_64::VirtAddr;
+use x86_64::registers::model_specific::{GsBase, KernelGsBase};
+
+use crate::frame;
+use crate::hhdm::phys_to_virt;
+use crate::paging::PAGE_SIZE;
+
+/// Maximum number of CPUs.
+pub const MAX_CPUS: usize = 64;
+
+/// The data every CPU keeps for itself.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PerCpu {
+    /// Address of this block (read through `gs:[0]`).
+    self_ptr: *const PerCpu,
+    /// Index of the CPU, 0 for the bootstrap processor.
+    pub cpu_id: u64,
+    /// Local APIC ID of the CPU.
+    pub apic_id: u64,
+    /// ID of the task running on the CPU.
+    pub current_task: AtomicU64,
+    /// Top of the kernel stack used when entering from user mode.
+    pub kernel_stack_top: AtomicU64,
+    /// Scratch slot for the user stack pointer while an entry stub switches stacks.
+    pub user_rsp_scratch: AtomicU64,
+    /// Number of interrupts currently being handled on the CPU (nesting depth).
+    pub interrupt_depth: AtomicU64,
+}
+
+const _: () = assert!(size_of::<PerCpu>() <= PAGE_SIZE as usize);
+
+/// Offset of [`PerCpu::kernel_stack_top`], for `gs:[offset]` operands in entry stubs.
+pub const KERNEL_STACK_TOP_OFFSET: usize = core::mem::offset_of!(PerCpu, kernel_stack_top);
+/// Offset of [`PerCpu::user_rsp_scratch`], for `gs:[offset]` operands in entry stubs.
+pub const USER_RSP_SCRATCH_OFFSET: usize = core::mem::offset_of!(PerCpu, user_rsp_scratch);
+
+/// Errors from [`init_cpu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerCpuError {
+    /// The CPU index is not below [`MAX_CPUS`].
+    InvalidCpu,
+    /// The CPU already has a block.
+    AlreadyInitialized,
+    /// No frame was left for the block.
+    OutOfMemory,
+}
+
+static CPUS: [AtomicPtr<PerCpu>; MAX_CPUS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CPUS];
+
+/// Allocates the [`PerCpu`] block of the calling CPU and points `GS_BASE` at it.
+///
+/// Must run on the CPU it sets up, once, before anything uses [`current`]. `KERNEL_GS_BASE` is cleared to the user's GS base.
+pub fn init_cpu(cpu_id: usize) -> Result<&'static PerCpu, PerCpuError> {
+    let slot = CPUS.get(cpu_id).ok_or(PerCpuError::InvalidCpu)?;
+    if !slot.load(Ordering::Acquire).is_null() {
+        return Err(PerCpuError::AlreadyInitialized);
+    }
+    let frame = frame::allocate_frame().ok_or(PerCpuError::OutOfMemory)?;
+    let block = phys_to_virt(frame.start_address().as_u64()) as *mut PerCpu;
+    let apic_id = u64::from(core::arch::x86_64::__cpuid(1).ebx >> 24);
+    unsafe {
+        block.write(PerCpu {
+            self_ptr: block,
+            cpu_id: cpu_id as u64,
+            apic_id,
+            current_task: AtomicU64::new(0),
+            kernel_stack_top: AtomicU64::new(0),
+            user_rsp_scratch: AtomicU64::new(0),
+            interrupt_depth: AtomicU64::new(0),
+        });
+    }
+    slot.store(block, Ordering::Release);
+    GsBase::write(VirtAddr::new(block as u64));
+    KernelGsBase::write(VirtAddr::new(0));
+    Ok(unsafe { &*block })
+}
+
+/// Returns the [`PerCpu`] block of the calling CPU.
+///
+/// # Panics
+/// Panics if [`init_cpu`] has not run on this CPU.
+pub fn current() -> &'static PerCpu {
+    let block: *const PerCpu;
+    if GsBase::read().is_null() {
+        panic!("per-CPU data used before percpu::init_cpu");
+    }
+    unsafe { asm!("mov {}, gs:[0]", out(reg) block, options(nostack, readonly, preserves_flags)) };
+    unsafe { &*block }
+}
+
+/// Returns the [`PerCpu`] block of CPU `cpu_id`, if it has one.
+pub fn cpu(cpu_id: usize) -> Option<&'static PerCpu> {
+    let block = CPUS.get(cpu_id)?.load(Ordering::Acquire);
+    (!block.is_null()).then(|| unsafe { &*block })
+}
+
+/// Returns the number of CPUs with a [`PerCpu`] block.
+pub fn cpu_count() -> usize {
+    CPUS.iter()
+        .filter(|slot| !slot.load(Ordering::Acquire).is_null())
+        .count()
+}
+
+/// Returns a reference to `field` of the calling CPU's [`PerCpu`] block.
+///
+/// # Example
+/// ```ignore
+/// per_cpu!(current_task).store(id, Ordering::Relaxed);
+/// ```
+#[macro_export]
+macro_rules! per_cpu {
+    ($field:ident) => {
+        &$crate::percpu::current().$field
+    };
+}
@@ -0,0 +1,239 @@
+//! # Heap Redzones
+//!
+//! [`RedzoneHeap`] wraps a global allocator to catch buffer overruns (enabled with the `redzone-heap` feature). Every allocation is padded with a redzone of at least [`REDZONE_SIZE`] bytes on each side, filled with [`CANARY`]:
+//!
+//! ```text
+//! | front redzone (max(REDZONE_SIZE, align)) | allocation | back redzone (REDZONE_SIZE) |
+//! ```
+//!
+//! The redzones are checked when the allocation is freed, and for every live allocation by [`RedzoneHeap::check_all`]. A damaged redzone is reported with the allocation's address and size, the offset of the first overwritten byte relative to the start of the allocation (negative for underruns), and the call sites of the allocation, then freeing it panics so the overrun is caught right where it is noticed instead of corrupting the heap's own bookkeeping later.
+//!
+//! Call sites need frame pointers; `make REDZONE_HEAP=1` builds the kernel with them. [`DebugHeap`](crate::debug_heap) can wrap a `RedzoneHeap` to get poisoning and leak tracking as well.
+//!
+//! ## Example
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: RedzoneHeap<KernelHeap> = RedzoneHeap::new(KernelHeap::empty());
+//!
+//! if ALLOCATOR.check_all() > 0 {
+//!     warn("Heap redzones damaged");
+//! }
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::call_site::{CALLER_DEPTH, callers, print_callers};
+
+/// Smallest number of canary bytes on each side of an allocation.
+pub const REDZONE_SIZE: usize = 16;
+/// Byte pattern the redzones are filled with.
+pub const CANARY: u8 = 0xFC;
+/// Maximum number of live allocations [`RedzoneHeap::check_all`] can check.
+pub const MAX_TRACKED: usize = 4096;
+
+#[derive(Clone, Copy)]
+struct Zone {
+    address: usize,
+    size: usize,
+    align: usize,
+    callers: [u64; CALLER_DEPTH],
+}
+
+const EMPTY: Zone = Zone {
+    address: 0,
+    size: 0,
+    align: 0,
+    callers: [0; CALLER_DEPTH],
+};
+
+struct Tracker {
+    live: [Zone; MAX_TRACKED],
+    live_count: usize,
+    overflowed: bool,
+}
+
+/// Damage found in the redzones of one allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Damage {
+    /// Offset of the overwritten byte closest to the allocation, relative to its start.
+    pub offset: isize,
+    /// Number of overwritten canary bytes on both sides.
+    pub bytes: usize,
+}
+
+/// A global allocator wrapper that surrounds allocations with checked redzones. See the module documentation.
+pub struct RedzoneHeap<A> {
+    inner: A,
+    tracker: Mutex<Tracker>,
+}
+
+/// Returns the size of the front redzone for allocations aligned to `align`, which keeps the allocation aligned.
+fn front_size(align: usize) -> usize {
+    REDZONE_SIZE.max(align)
+}
+
+/// Returns the layout passed to the inner allocator for `layout`, or `None` if it overflows.
+fn padded(layout: Layout) -> Option<Layout> {
+    let size = front_size(layout.align())
+        .checked_add(layout.size())?
+        .checked_add(REDZONE_SIZE)?;
+    Layout::from_size_align(size, layout.align()).ok()
+}
+
+/// Checks the redzones around the allocation of `size` bytes at `address` aligned to `align`.
+///
+/// # Safety
+/// The allocation must have been made by a [`RedzoneHeap`] with that size and alignment and not be freed yet.
+unsafe fn check(address: usize, size: usize, align: usize) -> Option<Damage> {
+    let front = front_size(align);
+    let base = (address - front) as *const u8;
+    let mut damage = Damage {
+        offset: 0,
+        bytes: 0,
+    };
+    // Back redzone, nearest byte first.
+    for i in (0..REDZONE_SIZE).rev() {
+        if unsafe { base.add(front + size + i).read() } != CANARY {
+            damage.offset = (size + i) as isize;
+            damage.bytes += 1;
+        }
+    }
+    // Front redzone, nearest byte last, so an underrun wins over an overrun.
+    for i in 0..front {
+        if unsafe { base.add(i).read() } != CANARY {
+            damage.offset = i as isize - front as isize;
+            damage.bytes += 1;
+        }
+    }
+    (damage.bytes > 0).then_some(damage)
+}
+
+fn report(address: usize, size: usize, damage: Damage, callers: Option<&[u64; CALLER_DEPTH]>) {
+    kprint!(
+        "[ERROR] Heap redzone damaged around {:#x} ({} bytes): {} canary bytes overwritten, nearest at offset {}\r\n",
+        address,
+        size,
+        damage.bytes,
+        damage.offset
+    );
+    if let Some(callers) = callers {
+        kprint!("[ERROR]   allocated from");
+        print_callers(callers);
+    }
+}
+
+impl<A> RedzoneHeap<A> {
+    /// Wraps `inner`.
+    pub const fn new(inner: A) -> Self {
+        RedzoneHeap {
+            inner,
+            tracker: Mutex::new(Tracker {
+                live: [EMPTY; MAX_TRACKED],
+                live_count: 0,
+                overflowed: false,
+            }),
+        }
+    }
+
+    /// Returns the wrapped allocator.
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Runs `f` on the tracking table with interrupts disabled, so an interrupt handler that allocates cannot spin on the lock held by the code it interrupted.
+    fn with_tracker<R>(&self, f: impl FnOnce(&mut Tracker) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.tracker.lock()))
+    }
+
+    /// Checks the redzones of every tracked live allocation, reports each damaged one, and returns how many were damaged.
+    pub fn check_all(&self) -> usize {
+        self.with_tracker(|tracker| Self::check_tracked(tracker))
+    }
+
+    fn check_tracked(tracker: &Tracker) -> usize {
+        let mut damaged = 0;
+        for zone in &tracker.live[..tracker.live_count] {
+            if let Some(damage) = unsafe { check(zone.address, zone.size, zone.align) } {
+                report(zone.address, zone.size, damage, Some(&zone.callers));
+                damaged += 1;
+            }
+        }
+        if tracker.overflowed {
+            polished_serial_logging::warn!(
+                "Redzone tracking table overflowed, not every allocation was checked"
+            );
+        }
+        damaged
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for RedzoneHeap<A> {
+    #[inline(never)]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(padded) = padded(layout) else {
+            return core::ptr::null_mut();
+        };
+        let base = unsafe { self.inner.alloc(padded) };
+        if base.is_null() {
+            return base;
+        }
+        let front = front_size(layout.align());
+        let ptr = unsafe {
+            base.write_bytes(CANARY, front);
+            base.add(front + layout.size())
+                .write_bytes(CANARY, REDZONE_SIZE);
+            base.add(front)
+        };
+        let zone = Zone {
+            address: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            callers: callers(),
+        };
+        self.with_tracker(|tracker| {
+            if tracker.live_count == MAX_TRACKED {
+                tracker.overflowed = true;
+            } else {
+                tracker.live[tracker.live_count] = zone;
+                tracker.live_count += 1;
+            }
+        });
+        ptr
+    }
+
+    #[inline(never)]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let address = ptr as usize;
+        let zone = self.with_tracker(|tracker| {
+            let count = tracker.live_count;
+            let index = tracker.live[..count]
+                .iter()
+                .position(|z| z.address == address)?;
+            let zone = tracker.live[index];
+            tracker.live[index] = tracker.live[count - 1];
+            tracker.live_count -= 1;
+            Some(zone)
+        });
+        if let Some(damage) = unsafe { check(address, layout.size(), layout.align()) } {
+            report(
+                address,
+                layout.size(),
+                damage,
+                zone.as_ref().map(|z| &z.callers),
+            );
+            kprint!("[ERROR]   freed from");
+            print_callers(&callers());
+            panic!("Heap redzone damaged around {address:#x}");
+        }
+        let front = front_size(layout.align());
+        // `padded` succeeded when this allocation was made.
+        let padded = unsafe {
+            Layout::from_size_align_unchecked(front + layout.size() + REDZONE_SIZE, layout.align())
+        };
+        unsafe { self.inner.dealloc(ptr.sub(front), padded) };
+    }
+}
@@ -0,0 +1,150 @@
+//! # Kernel Stacks
+//!
+//! [`alloc_kernel_stack`] is the one way to create a kernel stack after boot, for tasks as well as for interrupt stacks in the TSS. Each stack gets its own slot of [`STACK_SLOT_SIZE`] bytes in a dedicated virtual range starting at [`KERNEL_STACKS_START`]:
+//!
+//! ```text
+//! slot start                                              slot end = top
+//! | unmapped ... | guard page (GUARD_SIZE) | stack (size bytes, mapped) |
+//! ```
+//!
+//! The stack sits at the top of its slot and everything below it stays unmapped, so an overflow faults instead of silently running into another stack. Both the stack and its guard page are recorded as VMM regions, which makes page fault reports name the guard page of an overflowed stack.
+//!
+//! The returned [`StackHandle`] owns the stack and frees it when dropped. Stacks used by the CPU itself are installed through the `polished_gdt` crate (`install_ist_stack`, `install_rsp0_stack`), which keeps them alive as long as the TSS refers to them.
+//!
+//! ## Example
+//! ```ignore
+//! let stack = stack::alloc_kernel_stack(16 * 1024)?;
+//! polished_gdt::install_ist_stack(3, stack);
+//! ```
+
+use spin::Mutex;
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
+
+use crate::frame::GlobalFrameAllocator;
+use crate::heap::{KERNEL_HEAP_MAX_SIZE, KERNEL_HEAP_START};
+use crate::paging::{PAGE_SIZE, Protection, page_align_up};
+use crate::vmm::{self, KERNEL_SPACE, Region, RegionKind, VmmError};
+
+/// Start of the virtual range kernel stacks are allocated from, right after the kernel heap.
+pub const KERNEL_STACKS_START: u64 = KERNEL_HEAP_START + KERNEL_HEAP_MAX_SIZE;
+/// Virtual space reserved for each stack, including its guard.
+pub const STACK_SLOT_SIZE: u64 = 1 << 20;
+/// Number of stack slots.
+pub const MAX_KERNEL_STACKS: usize = 1024;
+/// Size of the guard region recorded below each stack.
+pub const GUARD_SIZE: u64 = PAGE_SIZE;
+/// Largest stack [`alloc_kernel_stack`] hands out.
+pub const MAX_STACK_SIZE: u64 = STACK_SLOT_SIZE - GUARD_SIZE;
+
+/// Errors from allocating a kernel stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    /// The size is zero or larger than [`MAX_STACK_SIZE`].
+    InvalidSize,
+    /// All [`MAX_KERNEL_STACKS`] slots are in use.
+    NoSlot,
+    /// The stack could not be recorded or mapped.
+    Map(VmmError),
+}
+
+/// Slots in use, one bit per slot.
+static SLOTS: Mutex<[u64; MAX_KERNEL_STACKS / 64]> = Mutex::new([0; MAX_KERNEL_STACKS / 64]);
+
+fn with_slots<R>(f: impl FnOnce(&mut [u64; MAX_KERNEL_STACKS / 64]) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut SLOTS.lock()))
+}
+
+fn take_slot() -> Option<usize> {
+    with_slots(|slots| {
+        let (word, bits) = slots
+            .iter_mut()
+            .enumerate()
+            .find(|(_, b)| **b != u64::MAX)?;
+        let bit = bits.trailing_ones() as usize;
+        *bits |= 1 << bit;
+        Some(word * 64 + bit)
+    })
+}
+
+fn free_slot(slot: usize) {
+    with_slots(|slots| slots[slot / 64] &= !(1 << (slot % 64)));
+}
+
+/// A guarded kernel stack, freed when dropped.
+#[derive(Debug)]
+pub struct StackHandle {
+    slot: usize,
+    bottom: u64,
+    top: u64,
+}
+
+impl StackHandle {
+    /// Returns the initial stack pointer: the first address above the stack, 16-byte aligned.
+    pub fn top(&self) -> VirtAddr {
+        VirtAddr::new(self.top)
+    }
+
+    /// Returns the lowest address of the stack; the guard page lies right below it.
+    pub fn bottom(&self) -> VirtAddr {
+        VirtAddr::new(self.bottom)
+    }
+
+    /// Returns the size of the stack in bytes, without the guard.
+    pub fn size(&self) -> u64 {
+        self.top - self.bottom
+    }
+
+    /// Returns whether `address` lies in the guard page below the stack.
+    pub fn is_guard(&self, address: u64) -> bool {
+        (self.bottom - GUARD_SIZE..self.bottom).contains(&address)
+    }
+}
+
+impl Drop for StackHandle {
+    fn drop(&mut self) {
+        // Safety: the handle owns the stack, and the kernel address space is always active.
+        let _ = unsafe { vmm::unmap_region(KERNEL_SPACE, self.bottom) };
+        let _ = vmm::release(KERNEL_SPACE, self.bottom - GUARD_SIZE);
+        free_slot(self.slot);
+    }
+}
+
+/// Allocates a zeroed kernel stack of `size` bytes (rounded up to whole pages) with an unmapped guard page below it.
+///
+/// # Errors
+/// Fails if `size` is zero or above [`MAX_STACK_SIZE`], if every slot is taken, or if the stack cannot be mapped.
+pub fn alloc_kernel_stack(size: u64) -> Result<StackHandle, StackError> {
+    let size = page_align_up(size);
+    if size == 0 || size > MAX_STACK_SIZE {
+        return Err(StackError::InvalidSize);
+    }
+    let slot = take_slot().ok_or(StackError::NoSlot)?;
+    let top = KERNEL_STACKS_START + (slot as u64 + 1) * STACK_SLOT_SIZE;
+    let bottom = top - size;
+    let guard = Region::new(
+        "kernel stack guard",
+        bottom - GUARD_SIZE,
+        GUARD_SIZE,
+        RegionKind::KernelStack,
+        Protection::NONE,
+    );
+    if let Err(e) = vmm::reserve(guard) {
+        free_slot(slot);
+        return Err(StackError::Map(e));
+    }
+    let stack = Region::new(
+        "kernel stack",
+        bottom,
+        size,
+        RegionKind::KernelStack,
+        Protection::READ_WRITE,
+    );
+    if let Err(e) = unsafe { vmm::map_region(stack, &mut GlobalFrameAllocator) } {
+        let _ = vmm::release(KERNEL_SPACE, guard.start);
+        free_slot(slot);
+        return Err(StackError::Map(e));
+    }
+    unsafe { core::ptr::write_bytes(bottom as *mut u8, 0, size as usize) };
+    Ok(StackHandle { slot, bottom, top })
+}
@@ -0,0 +1,94 @@
+//! # Memory Statistics
+//!
+//! [`stats`] gathers the numbers needed to see where memory goes: physical frames (total, free, used), the kernel heap (mapped, used, free), failed allocations of either, and the size of the VMM regions by kind. [`dump`] prints them over serial; the kernel heap calls it when an allocation fails, so an out-of-memory condition shows up with context instead of as a bare allocation panic.
+//!
+//! Reading the statistics takes no heap lock, so [`dump`] is safe to call from the allocator itself.
+
+use polished_serial_logging::kprint;
+
+use crate::paging::PAGE_SIZE;
+use crate::vmm::{self, RegionKind};
+use crate::{frame, heap};
+
+/// A snapshot of memory usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Frames managed by the frame allocator.
+    pub total_frames: u64,
+    /// Frames that can still be allocated.
+    pub free_frames: u64,
+    /// Frames handed out.
+    pub used_frames: u64,
+    /// Frame allocations that failed.
+    pub failed_frame_allocations: u64,
+    /// Bytes mapped for the kernel heap.
+    pub heap_size: u64,
+    /// Bytes of the kernel heap in use.
+    pub heap_used: u64,
+    /// Kernel heap allocations that failed.
+    pub failed_heap_allocations: u64,
+    /// Number of VMM regions and their total size in bytes, in the order of [`RegionKind::ALL`].
+    pub regions: [(usize, u64); RegionKind::ALL.len()],
+}
+
+impl MemoryStats {
+    /// Returns the free bytes in the mapped part of the kernel heap.
+    pub const fn heap_free(&self) -> u64 {
+        self.heap_size.saturating_sub(self.heap_used)
+    }
+
+    /// Returns the number of regions and their total size for `kind`.
+    pub fn regions_of(&self, kind: RegionKind) -> (usize, u64) {
+        RegionKind::ALL
+            .iter()
+            .position(|&k| k == kind)
+            .map_or((0, 0), |index| self.regions[index])
+    }
+}
+
+/// Returns the current memory usage.
+pub fn stats() -> MemoryStats {
+    let total_frames = frame::total_frames();
+    let free_frames = frame::free_frames();
+    let (heap_size, heap_used, failed_heap_allocations) = heap::usage();
+    MemoryStats {
+        total_frames,
+        free_frames,
+        used_frames: total_frames - free_frames,
+        failed_frame_allocations: frame::failed_allocations(),
+        heap_size,
+        heap_used,
+        failed_heap_allocations,
+        regions: vmm::usage_by_kind(),
+    }
+}
+
+/// Prints the current memory usage over serial.
+pub fn dump() {
+    let s = stats();
+    kprint!(
+        "[INFO] Frames: {} total, {} used, {} free ({} KiB free), {} failed allocations\r\n",
+        s.total_frames,
+        s.used_frames,
+        s.free_frames,
+        s.free_frames * PAGE_SIZE / 1024,
+        s.failed_frame_allocations
+    );
+    kprint!(
+        "[INFO] Kernel heap: {} KiB mapped, {} KiB used, {} KiB free, {} failed allocations\r\n",
+        s.heap_size / 1024,
+        s.heap_used / 1024,
+        s.heap_free() / 1024,
+        s.failed_heap_allocations
+    );
+    for (kind, (count, bytes)) in RegionKind::ALL.iter().zip(s.regions) {
+        if count > 0 {
+            kprint!(
+                "[INFO]   {:?}: {} regions, {} KiB\r\n",
+                kind,
+                count,
+                bytes / 1024
+            );
+        }
+    }
+}
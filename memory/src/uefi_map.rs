@@ -0,0 +1,135 @@
+//! # UEFI Memory Map
+//!
+//! The bootloader hands the kernel the raw UEFI memory map: a buffer of memory descriptors, its size in bytes, and the size of one descriptor. The descriptor size is given separately because firmware may append fields, so descriptors must be walked with that stride rather than `size_of`.
+//!
+//! [`UefiMemoryMap::usable_ranges`] yields the physical ranges that are free to use. Boot services code and data are not included, since the kernel runs before `ExitBootServices`.
+
+/// `EfiLoaderCode`: code of the bootloader and the kernel image.
+pub const LOADER_CODE: u32 = 1;
+/// `EfiLoaderData`: data allocated by the bootloader (including this map).
+pub const LOADER_DATA: u32 = 2;
+/// `EfiBootServicesCode`.
+pub const BOOT_SERVICES_CODE: u32 = 3;
+/// `EfiBootServicesData`.
+pub const BOOT_SERVICES_DATA: u32 = 4;
+/// `EfiConventionalMemory`: free memory.
+pub const CONVENTIONAL_MEMORY: u32 = 7;
+/// `EfiACPIReclaimMemory`: ACPI tables, reusable once they have been parsed.
+pub const ACPI_RECLAIM_MEMORY: u32 = 9;
+/// `EfiACPIMemoryNVS`.
+pub const ACPI_MEMORY_NVS: u32 = 10;
+/// `EfiMemoryMappedIO`.
+pub const MEMORY_MAPPED_IO: u32 = 11;
+
+/// Size of a UEFI page.
+pub const UEFI_PAGE_SIZE: u64 = 4096;
+
+/// One entry of the UEFI memory map, as laid out by the firmware.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryDescriptor {
+    /// Memory type (one of the constants in this module).
+    pub ty: u32,
+    /// Physical start address.
+    pub physical_start: u64,
+    /// Virtual start address (unused before `SetVirtualAddressMap`).
+    pub virtual_start: u64,
+    /// Number of 4 KiB pages.
+    pub page_count: u64,
+    /// Attribute bits (cacheability, runtime, ...).
+    pub attribute: u64,
+}
+
+impl MemoryDescriptor {
+    /// Returns the physical end address (exclusive).
+    pub const fn physical_end(&self) -> u64 {
+        self.physical_start + self.page_count * UEFI_PAGE_SIZE
+    }
+}
+
+/// The memory map handed over by the bootloader.
+#[derive(Debug, Clone, Copy)]
+pub struct UefiMemoryMap {
+    buffer: *const u8,
+    size: usize,
+    descriptor_size: usize,
+}
+
+// SAFETY: the buffer is never written after boot.
+unsafe impl Send for UefiMemoryMap {}
+unsafe impl Sync for UefiMemoryMap {}
+
+impl UefiMemoryMap {
+    /// Wraps the memory map in `buffer`.
+    ///
+    /// A null `buffer` or a `descriptor_size` smaller than [`MemoryDescriptor`] gives an empty map.
+    ///
+    /// # Safety
+    /// `buffer` must point to `size` readable bytes of memory descriptors that stay valid and unmodified for as long as the map is used.
+    pub unsafe fn new(buffer: *const u8, size: usize, descriptor_size: usize) -> Self {
+        if buffer.is_null() || descriptor_size < size_of::<MemoryDescriptor>() {
+            return Self::empty();
+        }
+        UefiMemoryMap {
+            buffer,
+            size,
+            descriptor_size,
+        }
+    }
+
+    /// Returns a map without entries.
+    pub const fn empty() -> Self {
+        UefiMemoryMap {
+            buffer: core::ptr::null(),
+            size: 0,
+            descriptor_size: size_of::<MemoryDescriptor>(),
+        }
+    }
+
+    /// Returns the number of descriptors.
+    pub fn len(&self) -> usize {
+        self.size / self.descriptor_size
+    }
+
+    /// Returns whether the map has no descriptors.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the physical range of the buffer holding the map, which the loader allocated. UEFI identity maps memory, so the buffer's address is its physical address.
+    pub fn buffer_range(&self) -> (u64, u64) {
+        let start = self.buffer as u64;
+        (start, start + self.size as u64)
+    }
+
+    /// Iterates over the descriptors.
+    pub fn iter(&self) -> impl Iterator<Item = MemoryDescriptor> + '_ {
+        (0..self.len()).map(|i| unsafe {
+            self.buffer
+                .add(i * self.descriptor_size)
+                .cast::<MemoryDescriptor>()
+                .read_unaligned()
+        })
+    }
+
+    /// Iterates over the free physical ranges as `(start, end)` pairs, merging adjacent ones.
+    pub fn usable_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let mut usable = self
+            .iter()
+            .filter(|d| d.ty == CONVENTIONAL_MEMORY)
+            .peekable();
+        core::iter::from_fn(move || {
+            let first = usable.next()?;
+            let (start, mut end) = (first.physical_start, first.physical_end());
+            while let Some(next) = usable.next_if(|d| d.physical_start == end) {
+                end = next.physical_end();
+            }
+            Some((start, end))
+        })
+    }
+
+    /// Returns the total number of bytes in [`usable_ranges`](Self::usable_ranges).
+    pub fn usable_bytes(&self) -> u64 {
+        self.usable_ranges().map(|(start, end)| end - start).sum()
+    }
+}
@@ -0,0 +1,640 @@
+//! # Virtual Memory Regions
+//!
+//! The virtual memory manager keeps a table of named [`Region`]s (kernel image, heap, stacks, MMIO, firmware tables, user segments, ...) with their protection, so that mappings are not made ad hoc: every mapping belongs to a region that was checked against all others for overlap.
+//!
+//! ## Address Spaces
+//!
+//! Each region belongs to an address space, identified by an [`AddressSpaceId`]. The kernel ([`KERNEL_SPACE`]) is part of every address space, so kernel regions may not overlap any region, while regions of two different user address spaces may. The page tables behind each user address space are kept by [`crate::address_space`].
+//!
+//! ## Demand Paging
+//!
+//! A region marked with [`Region::demand_paged`] is recorded without backing frames. The first touch of each of its pages faults, and [`handle_page_fault`] (called from the kernel's page fault hook) maps a zeroed frame there, so large heaps and stacks only use the memory they actually touch.
+//!
+//! ## Resizing
+//!
+//! [`resize`] grows or shrinks a region in place, mapping or unmapping pages at its end, as long as the space after it is free. When it is not, [`move_region`] relocates the region by moving its page table entries, without copying the memory, and [`remap`] combines the two like `mremap`. The kernel heap grows with [`resize`].
+//!
+//! ## Auditing
+//!
+//! [`find`] returns the region containing an address and [`report_fault`] prints it, which the kernel's page fault hook uses to explain a fault; [`dump`] prints the whole table.
+//!
+//! ## Example
+//! ```ignore
+//! let region = Region::new("ahci", base, size, RegionKind::Mmio, Protection::READ_WRITE);
+//! unsafe { vmm::map_region_to(region, physical_base, &mut frames)? };
+//! ```
+
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::structures::paging::{FrameAllocator, PageTableFlags, Size4KiB};
+
+use crate::frame::{self, GlobalFrameAllocator};
+use crate::hhdm::phys_to_virt;
+use crate::memory_map::{self, MemoryKind};
+use crate::paging::{self, PAGE_SIZE, PageMapping, Protection};
+
+/// Maximum number of regions in all address spaces.
+pub const MAX_REGIONS: usize = 128;
+
+/// Identifies an address space.
+pub type AddressSpaceId = u64;
+
+/// The kernel address space, shared by all others.
+pub const KERNEL_SPACE: AddressSpaceId = 0;
+
+/// What a region is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Kernel code and data.
+    KernelImage,
+    /// The kernel heap.
+    KernelHeap,
+    /// A kernel stack.
+    KernelStack,
+    /// Device registers.
+    Mmio,
+    /// The framebuffer.
+    Framebuffer,
+    /// Tables left by the firmware (ACPI, SMBIOS).
+    Firmware,
+    /// Code or data of a user program.
+    UserSegment,
+    /// A user heap.
+    UserHeap,
+    /// A user stack.
+    UserStack,
+    /// Anything else.
+    Other,
+}
+
+impl RegionKind {
+    /// Every kind, in declaration order.
+    pub const ALL: [RegionKind; 10] = [
+        RegionKind::KernelImage,
+        RegionKind::KernelHeap,
+        RegionKind::KernelStack,
+        RegionKind::Mmio,
+        RegionKind::Framebuffer,
+        RegionKind::Firmware,
+        RegionKind::UserSegment,
+        RegionKind::UserHeap,
+        RegionKind::UserStack,
+        RegionKind::Other,
+    ];
+}
+
+/// A named range of virtual memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// Name shown in dumps and fault reports.
+    pub name: &'static str,
+    /// Page-aligned first address.
+    pub start: u64,
+    /// Size in bytes, a multiple of [`PAGE_SIZE`].
+    pub size: u64,
+    /// What the region is used for.
+    pub kind: RegionKind,
+    /// Access rights of its pages.
+    pub protection: Protection,
+    /// Address space the region belongs to.
+    pub space: AddressSpaceId,
+    /// Whether pages are mapped on first touch instead of up front.
+    pub demand_paged: bool,
+}
+
+impl Region {
+    /// Creates a kernel region. `size` is rounded up to whole pages.
+    pub const fn new(
+        name: &'static str,
+        start: u64,
+        size: u64,
+        kind: RegionKind,
+        protection: Protection,
+    ) -> Self {
+        Region {
+            name,
+            start,
+            size: paging::page_align_up(size),
+            kind,
+            protection,
+            space: KERNEL_SPACE,
+            demand_paged: false,
+        }
+    }
+
+    /// Returns the region with its pages mapped to zeroed frames on first touch.
+    pub const fn demand_paged(self) -> Self {
+        Region {
+            demand_paged: true,
+            ..self
+        }
+    }
+
+    /// Returns the region moved to address space `space`.
+    pub const fn in_space(self, space: AddressSpaceId) -> Self {
+        Region { space, ..self }
+    }
+
+    /// Returns the first address after the region.
+    pub const fn end(&self) -> u64 {
+        self.start + self.size
+    }
+
+    /// Returns whether `address` lies in the region.
+    pub const fn contains(&self, address: u64) -> bool {
+        self.start <= address && address < self.end()
+    }
+
+    /// Returns whether the two regions share an address in a common address space.
+    pub const fn overlaps(&self, other: &Region) -> bool {
+        let shared =
+            self.space == other.space || self.space == KERNEL_SPACE || other.space == KERNEL_SPACE;
+        shared && self.start < other.end() && other.start < self.end()
+    }
+
+    /// Returns the number of pages in the region.
+    pub const fn page_count(&self) -> u64 {
+        self.size / PAGE_SIZE
+    }
+}
+
+/// Errors returned by the VMM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmmError {
+    /// The region is empty, not page aligned, or wraps around.
+    InvalidRange,
+    /// The region overlaps this existing region.
+    Overlap(Region),
+    /// [`MAX_REGIONS`] regions already exist.
+    TableFull,
+    /// No region starts at this address.
+    NotFound,
+    /// A page could not be mapped (out of frames, or already mapped).
+    MapFailed,
+}
+
+static REGIONS: Mutex<[Option<Region>; MAX_REGIONS]> = Mutex::new([None; MAX_REGIONS]);
+
+fn with_regions<R>(f: impl FnOnce(&mut [Option<Region>; MAX_REGIONS]) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut REGIONS.lock()))
+}
+
+/// Records `region` without mapping anything, e.g. for memory that is already mapped.
+///
+/// # Errors
+/// Fails if the region is invalid, overlaps another one, or the table is full.
+pub fn reserve(region: Region) -> Result<(), VmmError> {
+    if region.size == 0
+        || !region.start.is_multiple_of(PAGE_SIZE)
+        || region.start.checked_add(region.size).is_none()
+    {
+        return Err(VmmError::InvalidRange);
+    }
+    with_regions(|regions| {
+        if let Some(existing) = regions.iter().flatten().find(|r| r.overlaps(&region)) {
+            return Err(VmmError::Overlap(*existing));
+        }
+        let slot = regions
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(VmmError::TableFull)?;
+        *slot = Some(region);
+        Ok(())
+    })
+}
+
+/// Removes the region of address space `space` starting at `start` from the table, without unmapping it.
+pub fn release(space: AddressSpaceId, start: u64) -> Result<Region, VmmError> {
+    with_regions(|regions| {
+        regions
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|r| r.space == space && r.start == start))
+            .and_then(Option::take)
+            .ok_or(VmmError::NotFound)
+    })
+}
+
+/// Removes every region of address space `space` from the table, without unmapping them, and returns how many there were.
+///
+/// Used when a user address space is destroyed together with its page tables.
+pub fn release_space(space: AddressSpaceId) -> usize {
+    with_regions(|regions| {
+        let mut released = 0;
+        for slot in regions.iter_mut() {
+            if slot.is_some_and(|r| r.space == space) {
+                *slot = None;
+                released += 1;
+            }
+        }
+        released
+    })
+}
+
+/// Returns the region containing `address` as seen from address space `space` (its own regions and the kernel's).
+pub fn find(space: AddressSpaceId, address: u64) -> Option<Region> {
+    with_regions(|regions| {
+        regions
+            .iter()
+            .flatten()
+            .find(|r| (r.space == space || r.space == KERNEL_SPACE) && r.contains(address))
+            .copied()
+    })
+}
+
+/// Calls `f` for every region, sorted by address space and start address.
+pub fn for_each(mut f: impl FnMut(&Region)) {
+    let mut snapshot = with_regions(|regions| *regions);
+    snapshot.sort_unstable_by_key(|slot| slot.map(|r| (r.space, r.start)));
+    for region in snapshot.iter().flatten() {
+        f(region);
+    }
+}
+
+/// Returns the number of regions and their total size in bytes, per [`RegionKind`] in the order of [`RegionKind::ALL`].
+pub fn usage_by_kind() -> [(usize, u64); RegionKind::ALL.len()] {
+    let mut usage = [(0, 0); RegionKind::ALL.len()];
+    for_each(|r| {
+        if let Some(index) = RegionKind::ALL.iter().position(|&kind| kind == r.kind) {
+            usage[index].0 += 1;
+            usage[index].1 += r.size;
+        }
+    });
+    usage
+}
+
+/// Prints every region over serial.
+pub fn dump() {
+    kprint!("[INFO] Virtual memory regions:\r\n");
+    for_each(|r| {
+        kprint!(
+            "  [{}] {:#018x}-{:#018x} {:>10} KiB {} {:?} {}{}\r\n",
+            r.space,
+            r.start,
+            r.end(),
+            r.size / 1024,
+            r.protection,
+            r.kind,
+            r.name,
+            if r.demand_paged { " (on demand)" } else { "" }
+        );
+    });
+}
+
+/// Records `region` and maps it to the physically contiguous memory at `physical_start`, e.g. for MMIO or the framebuffer.
+///
+/// MMIO regions are mapped uncached, framebuffer regions write-combining once [`paging::init_pat`] has run.
+///
+/// Large regions are mapped with 2 MiB and 1 GiB pages where the addresses allow it.
+///
+/// # Safety
+/// Mapping the physical range at the region's addresses must not break memory safety.
+pub unsafe fn map_region_to(
+    region: Region,
+    physical_start: u64,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), VmmError> {
+    let mut flags = region.protection.page_table_flags();
+    if region.kind == RegionKind::Mmio {
+        flags |= PageTableFlags::NO_CACHE;
+    }
+    if region.kind == RegionKind::Framebuffer && paging::write_combining_enabled() {
+        flags |= paging::WRITE_COMBINING;
+    }
+    reserve(region)?;
+    let mapped =
+        unsafe { paging::map_range(region.start, physical_start, region.size, flags, frames) };
+    if mapped.is_err() {
+        let _ = release(region.space, region.start);
+        return Err(VmmError::MapFailed);
+    }
+    Ok(())
+}
+
+/// Records `region` and backs it with fresh frames from `frames`.
+///
+/// The frames are not cleared.
+///
+/// # Safety
+/// Mapping memory at the region's addresses must not break memory safety.
+pub unsafe fn map_region(
+    region: Region,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), VmmError> {
+    let flags = region.protection.page_table_flags();
+    unsafe {
+        map_with(region, flags, frames, |_, frames| {
+            frames.allocate_frame().map(|f| f.start_address().as_u64())
+        })
+    }
+}
+
+/// Reserves `region` and maps each page offset to the physical address returned by `frame_for`, undoing everything on failure.
+unsafe fn map_with<A: FrameAllocator<Size4KiB>>(
+    region: Region,
+    flags: PageTableFlags,
+    frames: &mut A,
+    mut frame_for: impl FnMut(u64, &mut A) -> Option<u64>,
+) -> Result<(), VmmError> {
+    reserve(region)?;
+    for page in (0..region.size).step_by(PAGE_SIZE as usize) {
+        let mapped = frame_for(page, frames).is_some_and(|physical| {
+            unsafe { paging::map_page(region.start + page, physical, flags, frames) }.is_ok()
+        });
+        if !mapped {
+            for done in (0..page).step_by(PAGE_SIZE as usize) {
+                let _ = unsafe { paging::unmap_page(region.start + done) };
+            }
+            let _ = release(region.space, region.start);
+            return Err(VmmError::MapFailed);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the region of address space `space` starting at `start` and unmaps its pages from the active page tables.
+///
+/// Frames that the memory map lists as usable RAM (i.e. that came from the frame allocator) are given back; device memory is only unmapped. Pages of demand-paged regions that were never touched are skipped.
+///
+/// # Safety
+/// Nothing may use the region's memory anymore, and the region's address space must be the active one.
+pub unsafe fn unmap_region(space: AddressSpaceId, start: u64) -> Result<Region, VmmError> {
+    let region = release(space, start)?;
+    unsafe { unmap_pages(region.start, region.size) };
+    Ok(region)
+}
+
+/// Unmaps `start..start + size` from the active page tables, giving frames that are usable RAM back to the frame allocator.
+///
+/// # Safety
+/// Nothing may use the memory anymore.
+unsafe fn unmap_pages(start: u64, size: u64) {
+    for page in (start..start + size).step_by(PAGE_SIZE as usize) {
+        if let Ok(frame) = unsafe { paging::unmap_page(page) }
+            && memory_map::kind_of(frame.start_address().as_u64()) == Some(MemoryKind::Usable)
+        {
+            unsafe { frame::deallocate_frame(frame) };
+        }
+    }
+    // Huge pages (from `map_region_to`) are not unmapped page by page.
+    unsafe { paging::unmap_range(start, size) };
+}
+
+/// Sets the size of the region of address space `space` starting at `start` to `size`.
+fn set_size(space: AddressSpaceId, start: u64, size: u64) {
+    with_regions(|regions| {
+        if let Some(region) = regions
+            .iter_mut()
+            .flatten()
+            .find(|r| r.space == space && r.start == start)
+        {
+            region.size = size;
+        }
+    });
+}
+
+/// Grows or shrinks the region of address space `space` starting at `start` in place to `new_size` bytes (rounded up to whole pages), returning the resized region.
+///
+/// Shrinking unmaps the pages past the new end and frees their frames like [`unmap_region`]. Growing needs the space right after the region to be free; the new pages are backed with fresh frames from `frames` (not cleared), or left to the page fault handler for demand-paged regions.
+///
+/// # Errors
+/// Fails if the size is zero or the region would wrap around, if no region starts at `start`, if the grown part would overlap another region ([`VmmError::Overlap`], see [`remap`] to move the region instead), or if the new pages cannot be mapped, in which case the region keeps its old size.
+///
+/// # Safety
+/// Nothing may use the memory cut off by shrinking, and the region's address space must be the active one.
+pub unsafe fn resize(
+    space: AddressSpaceId,
+    start: u64,
+    new_size: u64,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<Region, VmmError> {
+    let new_size = paging::page_align_up(new_size);
+    if new_size == 0 || start.checked_add(new_size).is_none() {
+        return Err(VmmError::InvalidRange);
+    }
+    let (old, resized) = with_regions(|regions| {
+        let index = regions
+            .iter()
+            .position(|slot| slot.is_some_and(|r| r.space == space && r.start == start))
+            .ok_or(VmmError::NotFound)?;
+        let old = regions[index].ok_or(VmmError::NotFound)?;
+        let resized = Region {
+            size: new_size,
+            ..old
+        };
+        if new_size > old.size {
+            let grown = Region {
+                start: old.end(),
+                size: new_size - old.size,
+                ..old
+            };
+            if let Some(existing) = regions.iter().flatten().find(|r| r.overlaps(&grown)) {
+                return Err(VmmError::Overlap(*existing));
+            }
+        }
+        regions[index] = Some(resized);
+        Ok((old, resized))
+    })?;
+    if new_size < old.size {
+        unsafe { unmap_pages(resized.end(), old.size - new_size) };
+    } else if new_size > old.size && !old.demand_paged {
+        let flags = old.protection.page_table_flags();
+        for page in (old.end()..resized.end()).step_by(PAGE_SIZE as usize) {
+            let mapped = frames.allocate_frame().is_some_and(|frame| {
+                let physical = frame.start_address().as_u64();
+                unsafe { paging::map_page(page, physical, flags, frames) }.is_ok()
+            });
+            if !mapped {
+                unsafe { unmap_pages(old.end(), page - old.end()) };
+                set_size(space, start, old.size);
+                return Err(VmmError::MapFailed);
+            }
+        }
+    }
+    Ok(resized)
+}
+
+/// Moves the region of address space `space` starting at `start` to `new_start`, returning the moved region.
+///
+/// The memory is not copied: the page table entries of its 4 KiB pages are moved, so the region keeps its frames and contents. Untouched pages of demand-paged regions stay unmapped. The new range may overlap the old one.
+///
+/// # Errors
+/// Fails if `new_start` is not page aligned or the region would wrap around, if no region starts at `start`, if the new range overlaps another region, if the region is mapped with huge pages ([`VmmError::InvalidRange`]), or if page tables for the new range cannot be allocated, in which case the region stays where it was.
+///
+/// # Safety
+/// Nothing may use the region's old addresses anymore, and the region's address space must be the active one.
+pub unsafe fn move_region(
+    space: AddressSpaceId,
+    start: u64,
+    new_start: u64,
+) -> Result<Region, VmmError> {
+    if !new_start.is_multiple_of(PAGE_SIZE) {
+        return Err(VmmError::InvalidRange);
+    }
+    let old = find(space, start)
+        .filter(|r| r.space == space && r.start == start)
+        .ok_or(VmmError::NotFound)?;
+    let moved = Region {
+        start: new_start,
+        ..old
+    };
+    if new_start.checked_add(old.size).is_none() {
+        return Err(VmmError::InvalidRange);
+    }
+    if (0..old.size)
+        .step_by(PAGE_SIZE as usize)
+        .any(|offset| matches!(paging::page_mapping(start + offset), PageMapping::Large(_)))
+    {
+        return Err(VmmError::InvalidRange);
+    }
+    with_regions(|regions| {
+        if let Some(existing) = regions
+            .iter()
+            .flatten()
+            .find(|r| !(r.space == space && r.start == start) && r.overlaps(&moved))
+        {
+            return Err(VmmError::Overlap(*existing));
+        }
+        let slot = regions
+            .iter_mut()
+            .flatten()
+            .find(|r| r.space == space && r.start == start)
+            .ok_or(VmmError::NotFound)?;
+        *slot = moved;
+        Ok(())
+    })?;
+    // Move pages in the order that never overwrites a page not moved yet.
+    let pages = old.size / PAGE_SIZE;
+    let offset = |i: u64| {
+        if new_start <= start {
+            i * PAGE_SIZE
+        } else {
+            old.size - (i + 1) * PAGE_SIZE
+        }
+    };
+    for i in 0..pages {
+        if unsafe { !move_page(start + offset(i), new_start + offset(i)) } {
+            for j in (0..i).rev() {
+                unsafe { move_page(new_start + offset(j), start + offset(j)) };
+            }
+            with_regions(|regions| {
+                if let Some(slot) = regions
+                    .iter_mut()
+                    .flatten()
+                    .find(|r| r.space == space && r.start == new_start)
+                {
+                    *slot = old;
+                }
+            });
+            return Err(VmmError::MapFailed);
+        }
+    }
+    Ok(moved)
+}
+
+/// Moves the 4 KiB mapping of the page at `from` to `to`. Returns `false`, with the page still mapped at `from`, if the page tables for `to` cannot be allocated.
+///
+/// # Safety
+/// See [`move_region`].
+unsafe fn move_page(from: u64, to: u64) -> bool {
+    let PageMapping::Small(frame, flags) = paging::page_mapping(from) else {
+        return true;
+    };
+    let physical = frame.start_address().as_u64();
+    let _ = unsafe { paging::unmap_page(from) };
+    if unsafe { paging::map_page(to, physical, flags, &mut GlobalFrameAllocator) }.is_ok() {
+        return true;
+    }
+    let _ = unsafe { paging::map_page(from, physical, flags, &mut GlobalFrameAllocator) };
+    false
+}
+
+/// Resizes the region of address space `space` starting at `start` to `new_size` bytes like `mremap`: in place if the space after it is free, otherwise, if `relocate_to` is given, by moving it there first with [`move_region`]. Returns the resulting region.
+///
+/// # Errors
+/// See [`resize`] and [`move_region`]. A region that cannot grow in place and may not move fails with [`VmmError::Overlap`]; a region that moved but then could not be mapped at its new size stays moved with its old size.
+///
+/// # Safety
+/// Nothing may use memory cut off by shrinking or, if the region moves, its old addresses; the region's address space must be the active one.
+pub unsafe fn remap(
+    space: AddressSpaceId,
+    start: u64,
+    new_size: u64,
+    relocate_to: Option<u64>,
+    frames: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<Region, VmmError> {
+    match (
+        unsafe { resize(space, start, new_size, frames) },
+        relocate_to,
+    ) {
+        (Err(VmmError::Overlap(_)), Some(new_start)) => {
+            unsafe { move_region(space, start, new_start) }?;
+            unsafe { resize(space, new_start, new_size, frames) }
+        }
+        (result, _) => result,
+    }
+}
+
+/// Resolves a page fault at `address` in address space `space` by mapping a zeroed frame, if the address lies in a demand-paged region whose protection allows the access.
+///
+/// Returns whether the fault was resolved; the faulting instruction can then be retried.
+pub fn handle_page_fault(
+    space: AddressSpaceId,
+    address: u64,
+    error_code: PageFaultErrorCode,
+) -> bool {
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        return false;
+    }
+    let Some(region) = find(space, address).filter(|r| r.demand_paged) else {
+        return false;
+    };
+    let required = [
+        (PageFaultErrorCode::CAUSED_BY_WRITE, Protection::WRITE),
+        (PageFaultErrorCode::USER_MODE, Protection::USER),
+        (PageFaultErrorCode::INSTRUCTION_FETCH, Protection::EXECUTE),
+    ];
+    if required
+        .iter()
+        .any(|&(cause, right)| error_code.contains(cause) && !region.protection.contains(right))
+    {
+        return false;
+    }
+    let Some(frame) = frame::allocate_frame() else {
+        return false;
+    };
+    let physical = frame.start_address().as_u64();
+    unsafe {
+        core::ptr::write_bytes(phys_to_virt(physical) as *mut u8, 0, PAGE_SIZE as usize);
+    }
+    let page = address & !(PAGE_SIZE - 1);
+    let flags = region.protection.page_table_flags();
+    if unsafe { paging::map_page(page, physical, flags, &mut GlobalFrameAllocator) }.is_err() {
+        unsafe { frame::deallocate_frame(frame) };
+        return false;
+    }
+    true
+}
+
+/// Prints which region of address space `space` contains the faulting `address`, for page fault reports.
+pub fn report_fault(space: AddressSpaceId, address: u64) {
+    match find(space, address) {
+        Some(r) => kprint!(
+            "[ERROR] Fault address {:#x} is in region \"{}\" ({:?}, {}{}, {:#x}-{:#x}, space {})\r\n",
+            address,
+            r.name,
+            r.kind,
+            r.protection,
+            if r.demand_paged { ", on demand" } else { "" },
+            r.start,
+            r.end(),
+            r.space
+        ),
+        None => kprint!(
+            "[ERROR] Fault address {:#x} is not in any region of address space {}\r\n",
+            address,
+            space
+        ),
+    }
+}
@@ -8,6 +8,7 @@
 //! This implementation:
 //! - Uses serial logging (via the `serial_logging` crate) to output panic information to a serial port, which is essential for debugging in early boot or kernel code where no display is available.
 //! - Prints the panic location (file, line, column) and message, if available.
+//! - Also reports fatal CPU exceptions, which the interrupts crate turns into panics carrying the exception name, RIP, and error code.
 //! - Halts the CPU after logging, preventing further execution.
 //!
 //! ## Usage
@@ -18,7 +19,7 @@
 extern crate alloc;
 
 use alloc::string::ToString;
-use polished_serial_logging::serial_write_str;
+use polished_serial_logging::{kprint, serial_write_str};
 
 /// Custom panic handler for the kernel.
 ///
@@ -36,6 +37,8 @@ use polished_serial_logging::serial_write_str;
 #[cfg(not(test))]
 #[panic_handler]
 pub fn panic(info: &core::panic::PanicInfo) -> ! {
+    // Make sure output buffered during early boot (and this report) reaches the serial port.
+    polished_serial_logging::init_logging();
     // Log a generic error message to the serial port.
     polished_serial_logging::error("Kernel panic occurred!");
     // Print detailed panic information (location, message) to the serial port.
@@ -79,14 +82,8 @@ pub fn print_panic_info_serial(info: &core::panic::PanicInfo) {
         serial_write_str("Location: <unknown>\n");
     }
 
-    // Print the panic message, if available.
-    if let Some(message) = info.message().as_str() {
-        serial_write_str("Message: ");
-        serial_write_str(message);
-        serial_write_str("\n");
-    } else {
-        serial_write_str("Message: <none>\n");
-    }
+    // Print the panic message, including formatted arguments (e.g. CPU exception context).
+    kprint!("Message: {}\n", info.message());
 
     // Print a footer to mark the end of the panic info.
     serial_write_str("=============\n");
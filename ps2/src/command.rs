@@ -0,0 +1,217 @@
+//! # Device Commands
+//!
+//! Keyboards and mice take commands of one or two bytes through the controller. The device answers every byte with ACK (`0xFA`), or with RESEND (`0xFE`) if it wants that byte again, and some commands are followed by response bytes (an ID, the scancode set, a self-test result).
+//!
+//! `Ps2Controller::send_to_device` runs one [`Command`] through that protocol:
+//! - Each byte waits for the controller's input buffer, so it is never written while the controller is busy.
+//! - A RESEND repeats the byte, up to [`MAX_RESENDS`] times.
+//! - Anything else than ACK or RESEND fails the command with [`CommandError::Unexpected`].
+//! - After the ACKs, up to [`Command::expecting`] response bytes are collected into a [`Response`].
+//!
+//! [`CommandQueue`] sends a sequence of commands to one device in order and stops at the first failure, so a setup sequence either completes or says which step failed.
+//!
+//! ## Timeouts
+//!
+//! Commands run before interrupts and timers are available, so timeouts are measured with the TSC through `polished_x86_commands::tsc::poll_us` (which falls back to about a microsecond per poll before the TSC is calibrated). ACKs wait up to [`ACK_TIMEOUT_US`], responses of slow commands such as reset ([`Command::slow`]) up to [`SLOW_TIMEOUT_US`].
+//!
+//! ## Example
+//! ```ignore
+//! let mut queue = CommandQueue::<4>::new(Ps2Controller::new(), Port::Second);
+//! queue.push(Command::new(0xF6))?;
+//! queue.push(Command::with_argument(0xF3, 100))?;
+//! queue.push(Command::new(0xF4))?;
+//! queue.run()?;
+//! ```
+
+use crate::Port;
+use crate::controller::Ps2Controller;
+
+/// Response byte acknowledging a command byte.
+pub const ACK: u8 = 0xFA;
+/// Response byte asking for the last byte again.
+pub const RESEND: u8 = 0xFE;
+/// How often a byte is sent again on RESEND before the command fails.
+pub const MAX_RESENDS: u32 = 3;
+/// Microseconds to wait for an ACK or a response byte.
+pub const ACK_TIMEOUT_US: u64 = 20_000;
+/// Microseconds to wait for the response of a [`Command::slow`] command.
+pub const SLOW_TIMEOUT_US: u64 = 1_000_000;
+/// Maximum number of response bytes of a command.
+pub const MAX_RESPONSE: usize = 2;
+
+/// Errors from sending a command to a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    /// The controller's input buffer did not drain, so the byte could not be written.
+    ControllerBusy,
+    /// The device did not answer a byte in time.
+    Timeout,
+    /// The device answered RESEND more than [`MAX_RESENDS`] times.
+    TooManyResends,
+    /// The device answered a byte with this instead of ACK.
+    Unexpected(u8),
+    /// The [`CommandQueue`] is full.
+    QueueFull,
+}
+
+/// A command for a keyboard or mouse, with an optional argument byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    bytes: [u8; 2],
+    len: usize,
+    expected: usize,
+    slow: bool,
+}
+
+impl Command {
+    /// A command byte without argument and without response.
+    pub const fn new(command: u8) -> Self {
+        Command {
+            bytes: [command, 0],
+            len: 1,
+            expected: 0,
+            slow: false,
+        }
+    }
+
+    /// A command byte followed by an argument byte, without response.
+    pub const fn with_argument(command: u8, argument: u8) -> Self {
+        Command {
+            bytes: [command, argument],
+            len: 2,
+            expected: 0,
+            slow: false,
+        }
+    }
+
+    /// Collects up to `count` (at most [`MAX_RESPONSE`]) response bytes after the ACKs. Missing bytes are not an error.
+    pub const fn expecting(mut self, count: usize) -> Self {
+        self.expected = if count > MAX_RESPONSE {
+            MAX_RESPONSE
+        } else {
+            count
+        };
+        self
+    }
+
+    /// Waits up to [`SLOW_TIMEOUT_US`] for the first response byte, for commands like reset that run a self-test first.
+    pub const fn slow(mut self) -> Self {
+        self.slow = true;
+        self
+    }
+
+    /// Returns the command byte.
+    pub const fn command(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Returns the command byte and its argument, if any.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Returns the number of response bytes to collect.
+    pub const fn expected(&self) -> usize {
+        self.expected
+    }
+
+    /// Returns whether the first response byte may take up to [`SLOW_TIMEOUT_US`].
+    pub const fn is_slow(&self) -> bool {
+        self.slow
+    }
+}
+
+/// Response bytes of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Response {
+    bytes: [u8; MAX_RESPONSE],
+    len: usize,
+}
+
+impl Response {
+    /// Returns the bytes received.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Returns the first byte received, if any.
+    pub fn first(&self) -> Option<u8> {
+        self.bytes().first().copied()
+    }
+
+    /// Appends a received byte; bytes beyond [`MAX_RESPONSE`] are dropped.
+    pub(crate) fn push(&mut self, byte: u8) {
+        if let Some(slot) = self.bytes.get_mut(self.len) {
+            *slot = byte;
+            self.len += 1;
+        }
+    }
+}
+
+/// A failed command of a [`CommandQueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueError {
+    /// The command that failed.
+    pub command: Command,
+    /// Its position in the queue.
+    pub index: usize,
+    pub error: CommandError,
+}
+
+/// Commands for one device, sent in order by [`run`](Self::run).
+#[derive(Debug, Clone)]
+pub struct CommandQueue<const N: usize> {
+    controller: Ps2Controller,
+    port: Port,
+    commands: [Option<Command>; N],
+    len: usize,
+}
+
+impl<const N: usize> CommandQueue<N> {
+    /// Creates an empty queue for the device on `port` of `controller`.
+    pub const fn new(controller: Ps2Controller, port: Port) -> Self {
+        CommandQueue {
+            controller,
+            port,
+            commands: [None; N],
+            len: 0,
+        }
+    }
+
+    /// Appends `command`.
+    ///
+    /// # Errors
+    /// Fails with [`CommandError::QueueFull`] if `N` commands are queued.
+    pub fn push(&mut self, command: Command) -> Result<(), CommandError> {
+        let slot = self
+            .commands
+            .get_mut(self.len)
+            .ok_or(CommandError::QueueFull)?;
+        *slot = Some(command);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Sends the queued commands in order and empties the queue. Returns the response of the last command.
+    ///
+    /// # Errors
+    /// Stops at the first command that fails and returns it; the commands after it are dropped.
+    pub fn run(&mut self) -> Result<Response, QueueError> {
+        let len = core::mem::take(&mut self.len);
+        let mut last = Response::default();
+        for (index, slot) in self.commands[..len].iter_mut().enumerate() {
+            let Some(command) = slot.take() else {
+                continue;
+            };
+            last = self
+                .controller
+                .send_to_device(self.port, command)
+                .map_err(|error| QueueError {
+                    command,
+                    index,
+                    error,
+                })?;
+        }
+        Ok(last)
+    }
+}
@@ -0,0 +1,276 @@
+//! # PS/2 Controller
+//!
+//! The 8042-compatible PS/2 controller has two I/O ports:
+//! - `0x60` (data): bytes from and to the devices, and arguments and results of controller commands.
+//! - `0x64`: the status register when read, the command register when written.
+//!
+//! [`Ps2Controller`] wraps both as `polished_x86_commands::port::Port<u8>`s and offers typed operations on top: controller commands ([`read_config`](Ps2Controller::read_config), [`self_test`](Ps2Controller::self_test), [`test_port`](Ps2Controller::test_port), ...) and device commands through [`send_to_device`](Ps2Controller::send_to_device). Every wait for the controller is bounded in time, so a missing or stuck controller makes calls fail instead of hanging.
+//!
+//! ## Configuration Byte
+//!
+//! | Bit | Meaning                                   |
+//! |-----|-------------------------------------------|
+//! | 0   | First port interrupt (IRQ 1)              |
+//! | 1   | Second port interrupt (IRQ 12)            |
+//! | 4   | First port clock disabled                 |
+//! | 5   | Second port clock disabled                |
+//! | 6   | First port translation to scancode set 1  |
+//!
+//! ## Example
+//! ```ignore
+//! let controller = Ps2Controller::new();
+//! controller.self_test()?;
+//! let config = controller.read_config().ok_or(Ps2Error::NoController)?;
+//! controller.write_config(config & !CONFIG_TRANSLATION);
+//! let response = controller.send_to_device(Port::First, Command::new(0xF2).expecting(2))?;
+//! ```
+
+use polished_x86_commands::port::Port as IoPort;
+use polished_x86_commands::tsc::poll_us;
+
+use crate::command::{
+    ACK, ACK_TIMEOUT_US, Command, CommandError, MAX_RESENDS, RESEND, Response, SLOW_TIMEOUT_US,
+};
+use crate::{Port, Ps2Error};
+
+/// Standard data port, shared by both devices.
+pub const DATA_PORT: u16 = 0x60;
+/// Standard status (read) and command (write) port.
+pub const STATUS_PORT: u16 = 0x64;
+/// Microseconds before a wait for the controller gives up.
+pub const WAIT_TIMEOUT_US: u64 = 10_000;
+
+/// Status bit: a byte is waiting in the output buffer (data port).
+pub const STATUS_OUTPUT_FULL: u8 = 0x01;
+/// Status bit: the input buffer still holds a byte for the controller.
+pub const STATUS_INPUT_FULL: u8 = 0x02;
+
+/// Configuration bit: first port interrupt.
+pub const CONFIG_FIRST_IRQ: u8 = 0x01;
+/// Configuration bit: second port interrupt.
+pub const CONFIG_SECOND_IRQ: u8 = 0x02;
+/// Configuration bit: second port clock disabled.
+pub const CONFIG_SECOND_CLOCK_DISABLED: u8 = 0x20;
+/// Configuration bit: translation of the first port to scancode set 1.
+pub const CONFIG_TRANSLATION: u8 = 0x40;
+
+/// Failure of a port's interface test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortTestError {
+    ClockStuckLow,
+    ClockStuckHigh,
+    DataStuckLow,
+    DataStuckHigh,
+    /// Any other result byte.
+    Unknown(u8),
+    /// The controller did not answer.
+    NoResponse,
+}
+
+/// An 8042-compatible PS/2 controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ps2Controller {
+    data: IoPort<u8>,
+    command: IoPort<u8>,
+}
+
+impl Default for Ps2Controller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ps2Controller {
+    /// The controller at the standard ports [`DATA_PORT`] and [`STATUS_PORT`].
+    pub const fn new() -> Self {
+        Self::at(DATA_PORT, STATUS_PORT)
+    }
+
+    /// A controller with its data port at `data` and its status/command port at `command`.
+    pub const fn at(data: u16, command: u16) -> Self {
+        Ps2Controller {
+            data: IoPort::new(data),
+            command: IoPort::new(command),
+        }
+    }
+
+    /// Reads the status register.
+    pub fn status(&self) -> u8 {
+        unsafe { self.command.read() }
+    }
+
+    /// Returns whether anything answers at the ports; a missing controller reads as all ones.
+    pub fn is_present(&self) -> bool {
+        self.status() != 0xFF
+    }
+
+    /// Waits up to [`WAIT_TIMEOUT_US`] until the input buffer is empty and the controller accepts a byte. Returns `false` on timeout.
+    pub fn wait_input_clear(&self) -> bool {
+        poll_us(WAIT_TIMEOUT_US, || self.status() & STATUS_INPUT_FULL == 0)
+    }
+
+    /// Waits up to `timeout_us` microseconds until the output buffer holds a byte. Returns `false` on timeout.
+    pub fn wait_output_set(&self, timeout_us: u64) -> bool {
+        poll_us(timeout_us, || self.status() & STATUS_OUTPUT_FULL != 0)
+    }
+
+    /// Sends `command` to the controller.
+    pub fn command(&self, command: u8) {
+        self.wait_input_clear();
+        unsafe { self.command.write(command) };
+    }
+
+    /// Sends `command` to the controller and returns its result byte.
+    pub fn query(&self, command: u8) -> Option<u8> {
+        self.command(command);
+        self.read_data()
+    }
+
+    /// Writes `value` to the data port.
+    pub fn write_data(&self, value: u8) {
+        self.wait_input_clear();
+        unsafe { self.data.write(value) };
+    }
+
+    /// Reads a byte from the data port once one is available.
+    pub fn read_data(&self) -> Option<u8> {
+        self.read_data_within(WAIT_TIMEOUT_US)
+    }
+
+    /// Reads a byte from the data port if one arrives within `timeout_us` microseconds.
+    pub fn read_data_within(&self, timeout_us: u64) -> Option<u8> {
+        self.wait_output_set(timeout_us)
+            .then(|| unsafe { self.data.read() })
+    }
+
+    /// Reads the byte in the data port without waiting, e.g. in an IRQ handler.
+    pub fn read_data_now(&self) -> u8 {
+        unsafe { self.data.read() }
+    }
+
+    /// Discards every byte waiting in the output buffer.
+    pub fn flush(&self) {
+        poll_us(WAIT_TIMEOUT_US, || {
+            let empty = self.status() & STATUS_OUTPUT_FULL == 0;
+            if !empty {
+                self.read_data_now();
+            }
+            empty
+        });
+    }
+
+    /// Reads the configuration byte (`0x20`).
+    pub fn read_config(&self) -> Option<u8> {
+        self.query(0x20)
+    }
+
+    /// Writes the configuration byte (`0x60`).
+    pub fn write_config(&self, config: u8) {
+        self.command(0x60);
+        self.write_data(config);
+    }
+
+    /// Enables the clock of `port` (`0xAE`, `0xA8`).
+    pub fn enable_port(&self, port: Port) {
+        self.command(match port {
+            Port::First => 0xAE,
+            Port::Second => 0xA8,
+        });
+    }
+
+    /// Disables the clock of `port` (`0xAD`, `0xA7`).
+    pub fn disable_port(&self, port: Port) {
+        self.command(match port {
+            Port::First => 0xAD,
+            Port::Second => 0xA7,
+        });
+    }
+
+    /// Pulses the CPU reset line (`0xFE`), rebooting the machine on most chipsets.
+    pub fn pulse_reset(&self) {
+        self.command(0xFE);
+    }
+
+    /// Runs the controller self-test (`0xAA`).
+    ///
+    /// Some controllers reset their configuration byte during the test; callers restore it.
+    ///
+    /// # Errors
+    /// Fails if the result is not `0x55`, or with [`Ps2Error::NoController`] if there is none.
+    pub fn self_test(&self) -> Result<(), Ps2Error> {
+        match self.query(0xAA) {
+            Some(0x55) => Ok(()),
+            Some(response) => Err(Ps2Error::SelfTestFailed(response)),
+            None => Err(Ps2Error::NoController),
+        }
+    }
+
+    /// Runs the interface test of `port` (`0xAB`, `0xA9`).
+    ///
+    /// # Errors
+    /// Returns the line fault the controller reported.
+    pub fn test_port(&self, port: Port) -> Result<(), PortTestError> {
+        let command = match port {
+            Port::First => 0xAB,
+            Port::Second => 0xA9,
+        };
+        match self.query(command) {
+            Some(0x00) => Ok(()),
+            Some(0x01) => Err(PortTestError::ClockStuckLow),
+            Some(0x02) => Err(PortTestError::ClockStuckHigh),
+            Some(0x03) => Err(PortTestError::DataStuckLow),
+            Some(0x04) => Err(PortTestError::DataStuckHigh),
+            Some(other) => Err(PortTestError::Unknown(other)),
+            None => Err(PortTestError::NoResponse),
+        }
+    }
+
+    /// Writes `byte` to the device on `port`, through `0xD4` for the second port.
+    fn write_device(&self, port: Port, byte: u8) -> Result<(), CommandError> {
+        if port == Port::Second {
+            self.command(0xD4);
+        }
+        if !self.wait_input_clear() {
+            return Err(CommandError::ControllerBusy);
+        }
+        unsafe { self.data.write(byte) };
+        Ok(())
+    }
+
+    /// Sends `byte` to the device on `port` and waits for its ACK, repeating it on RESEND.
+    fn send_byte(&self, port: Port, byte: u8) -> Result<(), CommandError> {
+        for _ in 0..=MAX_RESENDS {
+            self.write_device(port, byte)?;
+            match self.read_data_within(ACK_TIMEOUT_US) {
+                Some(ACK) => return Ok(()),
+                Some(RESEND) => continue,
+                Some(other) => return Err(CommandError::Unexpected(other)),
+                None => return Err(CommandError::Timeout),
+            }
+        }
+        Err(CommandError::TooManyResends)
+    }
+
+    /// Sends `command` to the device on `port` and collects its response bytes.
+    ///
+    /// # Errors
+    /// Fails if the controller stays busy, or if a byte is not acknowledged in time.
+    pub fn send_to_device(&self, port: Port, command: Command) -> Result<Response, CommandError> {
+        for &byte in command.bytes() {
+            self.send_byte(port, byte)?;
+        }
+        let mut response = Response::default();
+        for i in 0..command.expected() {
+            let timeout_us = if command.is_slow() && i == 0 {
+                SLOW_TIMEOUT_US
+            } else {
+                ACK_TIMEOUT_US
+            };
+            let Some(byte) = self.read_data_within(timeout_us) else {
+                break;
+            };
+            response.push(byte);
+        }
+        Ok(response)
+    }
+}
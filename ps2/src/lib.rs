@@ -4,9 +4,12 @@
 //! It is intended for use in OS kernels or bootloaders where direct hardware access is required.
 //!
 //! # Features
-//! - Remaps the Programmable Interrupt Controller (PIC) to avoid conflicts with CPU exceptions.
-//! - Configures the PS/2 controller and keyboard device, including IRQ unmasking and device enabling.
-//! - Provides safe wrappers for port I/O using inline assembly.
+//! - Enables IRQ 1 and IRQ 12 through a hook for whichever interrupt controller is active (see [`set_irq_mask_hook`]), the legacy PIC by default.
+//! - Self-tests the PS/2 controller, detects and tests both ports, and identifies the attached devices, returning what was found as [`Ps2Devices`] or a [`Ps2Error`].
+//! - Configures the keyboard device, including IRQ unmasking and device enabling, and again after it is reconnected (see [`reconfigure_keyboard`]).
+//! - Initializes a mouse on the second port and decodes its packets (see [`mouse`]).
+//! - Wraps the controller in [`Ps2Controller`], built on `polished_x86_commands::port::Port`, with typed methods for its commands (see [`controller`]).
+//! - Sends device commands through [`command`], which waits for ACKs, resends on request and times out instead of hanging.
 //! - Logs initialization steps using the `serial_logging` crate.
 //!
 //! # Safety
@@ -18,223 +21,340 @@ extern crate alloc;
 
 // PS/2 controller initialization for keyboard (and optionally mouse)
 use alloc::format;
-use polished_serial_logging::info;
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+use polished_scancodes::{Modifiers, ScancodeSet};
+use polished_serial_logging::{info, warn};
+use polished_x86_commands::pic8259;
 
-/// Write a byte to an I/O port using the `out` instruction.
-///
-/// # Safety
-/// This function performs raw hardware access and is unsafe.
-///
-/// # Arguments
-/// * `port` - The I/O port to write to.
-/// * `val` - The byte value to write.
-///
-/// # Inline Assembly
-/// Uses the `out dx, al` instruction to send `val` to `port`.
-#[inline]
-unsafe fn outb(port: u16, val: u8) {
-    // Inline assembly: out dx, al
-    // Sends the value in AL to the port in DX
-    unsafe {
-        core::arch::asm!(
-            "out dx, al",
-            in("dx") port,
-            in("al") val,
-            options(nomem, nostack, preserves_flags)
-        );
-    }
-}
-
-/// Read a byte from an I/O port using the `in` instruction.
+/// Keyboard and mouse commands with ACK, resend and timeout handling.
+pub mod command;
+/// Typed access to the PS/2 controller's ports.
+pub mod controller;
+/// PS/2 mouse on the second controller port.
+pub mod mouse;
+
+pub use command::{ACK, Command, CommandError, CommandQueue};
+pub use controller::Ps2Controller;
+use controller::{
+    CONFIG_FIRST_IRQ, CONFIG_SECOND_CLOCK_DISABLED, CONFIG_SECOND_IRQ, CONFIG_TRANSLATION,
+};
+
+/// The controller at the standard ports, used by [`ps2_init`] and the [`mouse`] driver.
+pub(crate) const CONTROLLER: Ps2Controller = Ps2Controller::new();
+
+/// Masks (`true`) or unmasks (`false`) an IRQ line at the active interrupt controller, returning whether the controller has the line.
+pub type IrqMaskHook = fn(irq: u8, masked: bool) -> bool;
+
+static IRQ_MASK_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the function that masks and unmasks the keyboard and mouse IRQs, e.g. `polished_interrupts::hardware_interrupts::set_irq_masked`, so the driver works with the legacy PIC and the I/O APIC alike.
 ///
-/// # Safety
-/// This function performs raw hardware access and is unsafe.
+/// Without a hook the lines are unmasked at the legacy PIC, which must already be remapped.
+pub fn set_irq_mask_hook(hook: IrqMaskHook) {
+    IRQ_MASK_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Masks or unmasks `irq` through the hook set with [`set_irq_mask_hook`], or at the legacy PIC.
+pub(crate) fn set_irq_masked(irq: u8, masked: bool) -> bool {
+    let hook = IRQ_MASK_HOOK.load(Ordering::Acquire);
+    if hook.is_null() {
+        if masked {
+            pic8259::mask(irq);
+        } else {
+            pic8259::unmask(irq);
+        }
+        return true;
+    }
+    // Safety: only `IrqMaskHook` function pointers are ever stored.
+    let hook = unsafe { core::mem::transmute::<*mut (), IrqMaskHook>(hook) };
+    hook(irq, masked)
+}
+
+/// IRQ line of the first PS/2 port.
+pub const KEYBOARD_IRQ: u8 = 1;
+
+/// Scancode set the keyboard sends, as a [`ScancodeSet`] discriminant (1 or 2).
+static SCANCODE_SET: AtomicU8 = AtomicU8::new(2);
+
+/// Returns the scancode set arriving at the data port, as detected by [`ps2_init`].
 ///
-/// # Arguments
-/// * `port` - The I/O port to read from.
+/// Translation is disabled in the controller, so this is the keyboard's own set.
+pub fn scancode_set() -> ScancodeSet {
+    match SCANCODE_SET.load(Ordering::Relaxed) {
+        1 => ScancodeSet::Set1,
+        _ => ScancodeSet::Set2,
+    }
+}
+
+/// Asks the keyboard for its scancode set (`0xF0 0x00`) and records it. A keyboard in set 3, or one that does not answer, is switched to set 2.
+fn detect_scancode_set() {
+    let set = CONTROLLER
+        .send_to_device(Port::First, Command::with_argument(0xF0, 0x00).expecting(1))
+        .ok()
+        .and_then(|response| response.first())
+        .and_then(ScancodeSet::from_query_response);
+    let set = match set {
+        Some(set) => set,
+        None => {
+            if let Err(e) =
+                CONTROLLER.send_to_device(Port::First, Command::with_argument(0xF0, 0x02))
+            {
+                warn(&format!(
+                    "Keyboard did not accept scancode set 2 ({e:?}), assuming it anyway"
+                ));
+            }
+            ScancodeSet::Set2
+        }
+    };
+    let value = match set {
+        ScancodeSet::Set1 => 1,
+        ScancodeSet::Set2 => 2,
+    };
+    SCANCODE_SET.store(value, Ordering::Relaxed);
+    info(&format!("Keyboard scancode set: {value}"));
+}
+
+/// Brings a freshly reset keyboard into the state the kernel expects: scancode set, LEDs matching `locks`, scanning enabled.
+fn configure_keyboard(locks: Modifiers) {
+    detect_scancode_set();
+    let leds = u8::from(locks.num_lock) << 1 | u8::from(locks.caps_lock) << 2;
+    let mut queue = CommandQueue::<2>::new(CONTROLLER, Port::First);
+    let queued = queue
+        .push(Command::with_argument(0xED, leds))
+        .and_then(|()| queue.push(Command::new(0xF4)));
+    if let Err(e) = queued {
+        warn(&format!("Could not queue keyboard commands: {e:?}"));
+        return;
+    }
+    if let Err(e) = queue.run() {
+        warn(&format!(
+            "Keyboard command {:#x} failed: {:?}",
+            e.command.command(),
+            e.error
+        ));
+    }
+}
+
+/// Configures the keyboard again after it sent an unsolicited self-test result (`0xAA`) because it was plugged back in or reset itself. Returns the scancode set it now sends.
 ///
-/// # Returns
-/// The byte read from the port.
+/// The keyboard IRQ is disabled in the controller meanwhile, so the replies are read here and not by the IRQ handler. Call it outside IRQ context, e.g. as deferred work, since the keyboard takes a while to answer.
+pub fn reconfigure_keyboard(locks: Modifiers) -> ScancodeSet {
+    info("Keyboard reset or reconnected, reconfiguring...");
+    let config = read_config().ok();
+    if let Some(config) = config {
+        CONTROLLER.write_config(config & !CONFIG_FIRST_IRQ);
+    }
+    configure_keyboard(locks);
+    if let Some(config) = config {
+        CONTROLLER.write_config(config);
+    }
+    scancode_set()
+}
+
+/// Reboots the machine through the controller's CPU reset line, e.g. for Ctrl+Alt+Del.
 ///
-/// # Inline Assembly
-/// Uses the `in al, dx` instruction to read a byte from `port` into `val`.
-#[inline]
-unsafe fn inb(port: u16) -> u8 {
-    let val: u8;
-    unsafe {
-        core::arch::asm!(
-            "in al, dx",
-            in("dx") port,
-            out("al") val,
-            options(nomem, nostack, preserves_flags)
-        );
-    }
-    val
-}
-
-/// Initialize the PS/2 controller and keyboard device.
+/// Halts with interrupts disabled if the chipset ignores the reset.
+pub fn reboot() -> ! {
+    warn("Rebooting through the PS/2 controller...");
+    CONTROLLER.pulse_reset();
+    loop {
+        // Safety: only stops the CPU; nothing is left to run.
+        unsafe { core::arch::asm!("cli", "hlt") };
+    }
+}
+
+/// Errors from initializing the PS/2 controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// No controller answers: the status register reads `0xFF`, or the controller stopped responding.
+    NoController,
+    /// The controller self-test (`0xAA`) returned this byte instead of `0x55`.
+    SelfTestFailed(u8),
+    /// Neither port passed its interface test.
+    NoWorkingPort,
+}
+
+/// Kind of device attached to a PS/2 port, from its reply to "identify" (`0xF2`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    /// AT keyboard, which does not answer "identify".
+    AtKeyboard,
+    /// MF2 keyboard (`0xAB` followed by a second ID byte).
+    Mf2Keyboard,
+    /// Standard mouse (`0x00`).
+    Mouse,
+    /// Mouse with a scroll wheel (`0x03`).
+    WheelMouse,
+    /// Mouse with a scroll wheel and five buttons (`0x04`).
+    FiveButtonMouse,
+    /// Any other reply, with its first byte.
+    Unknown(u8),
+}
+
+impl DeviceType {
+    /// Decodes the ID bytes sent after the ACK of "identify"; `None` if none arrived.
+    pub fn from_id(first: Option<u8>) -> Self {
+        match first {
+            None => DeviceType::AtKeyboard,
+            Some(0xAB) => DeviceType::Mf2Keyboard,
+            Some(0x00) => DeviceType::Mouse,
+            Some(0x03) => DeviceType::WheelMouse,
+            Some(0x04) => DeviceType::FiveButtonMouse,
+            Some(other) => DeviceType::Unknown(other),
+        }
+    }
+
+    /// Returns whether the device is a keyboard.
+    pub fn is_keyboard(self) -> bool {
+        matches!(self, DeviceType::AtKeyboard | DeviceType::Mf2Keyboard)
+    }
+
+    /// Returns whether the device is a mouse.
+    pub fn is_mouse(self) -> bool {
+        matches!(
+            self,
+            DeviceType::Mouse | DeviceType::WheelMouse | DeviceType::FiveButtonMouse
+        )
+    }
+}
+
+/// What [`ps2_init`] found on the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ps2Devices {
+    /// Whether the controller has a second port.
+    pub dual_channel: bool,
+    /// Device on the first port, if the port works and a device answered.
+    pub first: Option<DeviceType>,
+    /// Device on the second port, likewise.
+    pub second: Option<DeviceType>,
+}
+
+impl Ps2Devices {
+    /// Returns whether a keyboard is on the first port, where it is initialized and raises IRQ 1.
+    pub fn keyboard(&self) -> bool {
+        self.first.is_some_and(DeviceType::is_keyboard)
+    }
+
+    /// Returns whether a mouse is on the second port, where [`mouse::init`] expects it.
+    pub fn mouse(&self) -> bool {
+        self.second.is_some_and(DeviceType::is_mouse)
+    }
+}
+
+/// A port of the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// The first port, usually the keyboard (IRQ 1).
+    First,
+    /// The second (auxiliary) port, usually the mouse (IRQ 12).
+    Second,
+}
+
+/// Reads the controller configuration byte.
+fn read_config() -> Result<u8, Ps2Error> {
+    CONTROLLER.read_config().ok_or(Ps2Error::NoController)
+}
+
+/// Runs the interface test of `port`. Returns whether it passed, logging the failure otherwise.
+fn test_port(port: Port) -> bool {
+    match CONTROLLER.test_port(port) {
+        Ok(()) => true,
+        Err(e) => {
+            warn(&format!("PS/2 {port:?} port failed its test: {e:?}"));
+            false
+        }
+    }
+}
+
+/// Resets the device on `port` and asks for its ID, leaving scanning disabled. Returns `None` if no device answers.
+fn identify(port: Port) -> Option<DeviceType> {
+    // Self-test result (0xAA), then a mouse sends its ID; drain whatever else arrives.
+    let reset = CONTROLLER
+        .send_to_device(port, Command::new(0xFF).expecting(1).slow())
+        .ok()?;
+    info(&format!(
+        "PS/2 {port:?} device reset, self-test: {:#x?}",
+        reset.first()
+    ));
+    while CONTROLLER.read_data().is_some() {}
+    CONTROLLER.send_to_device(port, Command::new(0xF5)).ok()?;
+    let id = CONTROLLER
+        .send_to_device(port, Command::new(0xF2).expecting(2))
+        .ok()?;
+    let device = DeviceType::from_id(id.first());
+    info(&format!("PS/2 {port:?} port: {device:?}"));
+    Some(device)
+}
+
+/// Initializes the PS/2 controller and the devices on its ports.
 ///
 /// This function performs the following steps:
-/// 1. Remaps the PIC to avoid conflicts with CPU exceptions.
-/// 2. Unmasks the keyboard IRQ and masks all slave IRQs.
-/// 3. Flushes the PS/2 controller output buffer.
-/// 4. Disables both keyboard and mouse devices.
-/// 5. Configures the PS/2 controller to enable keyboard IRQ and disable mouse IRQ.
-/// 6. Enables the keyboard device and resets it.
-/// 7. Enables keyboard scanning.
-/// 8. Logs all major steps and responses for debugging.
+/// 1. Masks the PS/2 IRQs at the interrupt controller.
+/// 2. Disables both ports and flushes the output buffer.
+/// 3. Disables IRQs and translation in the configuration byte.
+/// 4. Runs the controller self-test (`0xAA`) and restores the configuration byte, which some controllers reset.
+/// 5. Detects a second port by enabling it and checking the configuration byte.
+/// 6. Tests each port (`0xAB`, `0xA9`).
+/// 7. Enables the working ports, resets their devices and identifies them (`0xF2`).
+/// 8. For a keyboard on the first port: detects its scancode set (see [`scancode_set`]), turns the LEDs off, enables scanning, its configuration bit and IRQ 1 (through [`set_irq_mask_hook`]).
+///
+/// A mouse on the second port is set up separately by [`mouse::init`].
 ///
-/// # Safety
-/// This function must be called in a context where direct hardware access is permitted (e.g., kernel mode).
-#[allow(clippy::uninlined_format_args)]
-pub fn ps2_init() {
+/// # Errors
+/// Fails if there is no controller, if its self-test fails, or if no port works. The kernel then runs without PS/2 input.
+pub fn ps2_init() -> Result<Ps2Devices, Ps2Error> {
     info("Initializing PS/2 controller...");
-    unsafe {
-        // --- PIC Remapping ---
-        // The PIC (Programmable Interrupt Controller) is remapped so that its IRQs do not overlap with CPU exceptions (0x00-0x1F).
-        // Master PIC is mapped to 0x20-0x27, Slave PIC to 0x28-0x2F.
-        outb(0x20, 0x11);
-        outb(0xA0, 0x11);
-        outb(0x21, 0x20); // Master offset 0x20
-        outb(0xA1, 0x28); // Slave offset 0x28
-        outb(0x21, 0x04); // Tell Master about Slave at IRQ2
-        outb(0xA1, 0x02); // Tell Slave its cascade identity
-        outb(0x21, 0x01); // 8086 mode
-        outb(0xA1, 0x01); // 8086 mode
-        // Unmask IRQ1 (keyboard) and IRQ2 (cascade) at master PIC, mask all slave IRQs
-        let master_mask = inb(0x21);
-        let new_master = master_mask & !((1 << 1) | (1 << 2)); // unmask IRQ1 & IRQ2
-        outb(0x21, new_master);
-        outb(0xA1, 0xFF); // mask all slave interrupts
-        // Read port 0x60 once to clear any stale scancode after remap
-        let _ = inb(0x60);
-
-        // --- Helper Closures for Buffer Status ---
-        // Wait for input buffer to be clear (ready to accept commands)
-        let wait_input_clear = || {
-            for _ in 0..10000 {
-                let status: u8;
-                core::arch::asm!(
-                    "in al, dx",
-                    in("dx") 0x64u16,
-                    out("al") status,
-                    options(nomem, nostack, preserves_flags)
-                );
-                if status & 0x02 == 0 {
-                    break;
-                }
-            }
-        };
-        // Wait for output buffer to be set (data available to read)
-        let wait_output_set = || {
-            for _ in 0..10000 {
-                let status: u8;
-                core::arch::asm!(
-                    "in al, dx",
-                    in("dx") 0x64u16,
-                    out("al") status,
-                    options(nomem, nostack, preserves_flags)
-                );
-                if status & 0x01 != 0 {
-                    break;
-                }
-            }
-        };
-
-        // --- Flush Output Buffer ---
-        wait_output_set();
-        let mut _dummy: u8 = 0;
-        core::arch::asm!(
-            "in al, dx",
-            in("dx") 0x60u16,
-            out("al") _dummy,
-            options(nomem, nostack, preserves_flags)
-        );
-
-        // --- Disable Devices ---
-        // Disable keyboard
-        wait_input_clear();
-        core::arch::asm!(
-            "mov al, 0xAD", // disable keyboard
-            "out 0x64, al",
-            options(nomem, nostack, preserves_flags)
-        );
-        // Disable mouse
-        wait_input_clear();
-        core::arch::asm!(
-            "mov al, 0xA7", // disable mouse
-            "out 0x64, al",
-            options(nomem, nostack, preserves_flags)
-        );
-
-        // --- Set Controller Configuration Byte ---
-        // Enable keyboard IRQ, disable mouse IRQ, no translation
-        wait_input_clear();
-        core::arch::asm!(
-            "mov al, 0x20",
-            "out 0x64, al",
-            options(nomem, nostack, preserves_flags)
-        );
-        wait_output_set();
-        let mut _config: u8 = 0;
-        core::arch::asm!(
-            "in al, dx",
-            in("dx") 0x60u16,
-            out("al") _config,
-            options(nomem, nostack, preserves_flags)
-        );
-        // Set: enable keyboard IRQ (bit 0), disable mouse IRQ (bit 1), clear translation (bit 6)
-        _config = (_config | 0x01 | 0x40) & !0x02;
-        wait_input_clear();
-        core::arch::asm!(
-            "mov al, 0x60",
-            "out 0x64, al",
-            options(nomem, nostack, preserves_flags)
-        );
-        wait_input_clear();
-        core::arch::asm!(
-            "out 0x60, al",
-            in("al") _config,
-            options(nomem, nostack, preserves_flags)
-        );
-
-        // --- Enable Keyboard Device ---
-        wait_input_clear();
-        core::arch::asm!(
-            "mov al, 0xAE",
-            "out 0x64, al",
-            options(nomem, nostack, preserves_flags)
-        );
-
-        // --- Keyboard Reset and Enable Scanning ---
-        // Send reset command (0xFF) to keyboard
-        wait_input_clear();
-        outb(0x60, 0xFF);
-        wait_output_set();
-        let ack = inb(0x60);
-        let msg = format!("Keyboard RESET ACK: {:#x}", ack);
-        info(&msg);
-        if ack == 0xFA {
-            // If ACK received, read BAT (Basic Assurance Test) response
-            wait_output_set();
-            let bat = inb(0x60);
-            let msg = format!("Keyboard BAT response: {:#x}", bat);
-            info(&msg);
-        } else {
-            let msg = format!("Keyboard did not ACK reset as expected: {:#x}", ack);
-            info(&msg);
+    // IRQ 1 is unmasked once a keyboard is found, IRQ 12 by `mouse::init`.
+    set_irq_masked(KEYBOARD_IRQ, true);
+    set_irq_masked(mouse::MOUSE_IRQ, true);
+
+    if !CONTROLLER.is_present() {
+        return Err(Ps2Error::NoController);
+    }
+    CONTROLLER.disable_port(Port::First);
+    CONTROLLER.disable_port(Port::Second);
+    CONTROLLER.flush();
+
+    let config = read_config()? & !(CONFIG_FIRST_IRQ | CONFIG_SECOND_IRQ | CONFIG_TRANSLATION);
+    CONTROLLER.write_config(config);
+    CONTROLLER.self_test()?;
+    CONTROLLER.write_config(config);
+
+    // With the second port disabled its clock bit is set; enabling it clears the bit on dual-channel controllers.
+    let dual_channel = config & CONFIG_SECOND_CLOCK_DISABLED != 0 && {
+        CONTROLLER.enable_port(Port::Second);
+        let enabled = read_config()? & CONFIG_SECOND_CLOCK_DISABLED == 0;
+        CONTROLLER.disable_port(Port::Second);
+        enabled
+    };
+    info(&format!("PS/2 controller dual channel: {dual_channel}"));
+
+    let first_works = test_port(Port::First);
+    let second_works = dual_channel && test_port(Port::Second);
+    if !first_works && !second_works {
+        return Err(Ps2Error::NoWorkingPort);
+    }
+    if first_works {
+        CONTROLLER.enable_port(Port::First);
+    }
+    if second_works {
+        CONTROLLER.enable_port(Port::Second);
+    }
+    let devices = Ps2Devices {
+        dual_channel,
+        first: first_works.then(|| identify(Port::First)).flatten(),
+        second: second_works.then(|| identify(Port::Second)).flatten(),
+    };
+
+    if devices.keyboard() {
+        configure_keyboard(Modifiers::default());
+        CONTROLLER.write_config(read_config()? | CONFIG_FIRST_IRQ);
+        if !set_irq_masked(KEYBOARD_IRQ, false) {
+            warn("The interrupt controller has no IRQ 1 for the keyboard");
         }
-        // Enable keyboard scanning (0xF4)
-        wait_input_clear();
-        outb(0x60, 0xF4);
-        wait_output_set();
-        let scan_ack = inb(0x60);
-        let msg = format!("Keyboard scanning ACK: {:#x}", scan_ack);
-        info(&msg);
-        // Unmask IRQ1 (keyboard) again after all initialization
-        let master_mask = inb(0x21);
-        let new_master = master_mask & !(1 << 1);
-        outb(0x21, new_master);
+    } else {
+        warn("No PS/2 keyboard on the first port");
     }
     info("PS/2 controller initialized");
+    Ok(devices)
 }
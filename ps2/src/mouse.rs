@@ -0,0 +1,311 @@
+//! # PS/2 Mouse
+//!
+//! A PS/2 mouse sits on the controller's second (auxiliary) port. Bytes for it go through the controller with command `0xD4` ("write to second port"), and its data arrives on port 0x60 like keyboard data, but raises IRQ 12 instead of IRQ 1.
+//!
+//! ## Initialization
+//!
+//! [`init`] enables the auxiliary port and its IRQ in the controller configuration byte, then sends the mouse:
+//! 1. `0xF6`: restore defaults.
+//! 2. The IntelliMouse "magic" sample rates `200, 100, 80`, then `0xF2` (identify). A mouse with a scroll wheel now reports ID 3.
+//! 3. If it did, the rates `200, 200, 80` and `0xF2` again. A mouse with five buttons now reports ID 4.
+//! 4. `0xF3 100`: 100 samples per second.
+//! 5. `0xF4`: enable data reporting (streaming mode).
+//!
+//! A plain mouse ignores the sequences and keeps reporting ID 0. [`device_type`] returns the protocol that was enabled.
+//!
+//! ## Packets
+//!
+//! In streaming mode every movement or button change produces a 3-byte packet:
+//!
+//! ```text
+//! byte 0: Y overflow | X overflow | Y sign | X sign | 1 | middle | right | left
+//! byte 1: X movement (low 8 bits of a 9-bit two's complement value)
+//! byte 2: Y movement (likewise; positive is up)
+//! ```
+//!
+//! With the wheel (ID 3) or five buttons (ID 4) enabled, packets have a fourth byte:
+//!
+//! ```text
+//! ID 3: Z movement (8-bit two's complement; positive is towards the user)
+//! ID 4: 0 | 0 | button 5 | button 4 | Z movement (4-bit two's complement)
+//! ```
+//!
+//! [`irq_handler`] feeds each byte into a [`PacketParser`], which resynchronizes on bit 3 of the first byte (always set), and hands every complete packet as a [`MouseEvent`] to the handler set with [`set_event_handler`].
+//!
+//! ## Example
+//! ```ignore
+//! let devices = polished_ps2::ps2_init()?;
+//! if devices.mouse() && polished_ps2::mouse::init() {
+//!     mouse::set_event_handler(on_mouse_event);
+//!     irq::register_irq_handler(12, mouse::irq_handler)?;
+//! }
+//! ```
+
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+
+use alloc::format;
+use polished_serial_logging::{info, warn};
+
+use crate::command::{Command, CommandQueue};
+use crate::controller::{CONFIG_SECOND_CLOCK_DISABLED, CONFIG_SECOND_IRQ};
+use crate::{CONTROLLER, DeviceType, Port, set_irq_masked};
+
+/// IRQ line of the second PS/2 port.
+pub const MOUSE_IRQ: u8 = 12;
+
+/// Sample rate set by [`init`], in packets per second.
+pub const SAMPLE_RATE: u8 = 100;
+
+/// Sample rates that enable the scroll wheel of an IntelliMouse.
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+
+/// Sample rates that enable buttons 4 and 5, once the wheel is enabled.
+const FIVE_BUTTON_SEQUENCE: [u8; 3] = [200, 200, 80];
+
+/// State of the mouse buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+    /// Fourth button, usually "back", on five-button mice.
+    pub back: bool,
+    /// Fifth button, usually "forward", on five-button mice.
+    pub forward: bool,
+}
+
+impl MouseButtons {
+    /// Decodes button bits: left, right and middle in bits 0-2 as in the first packet byte, the fourth and fifth buttons in bits 3 and 4.
+    pub const fn from_bits(bits: u8) -> Self {
+        MouseButtons {
+            left: bits & 0x01 != 0,
+            right: bits & 0x02 != 0,
+            middle: bits & 0x04 != 0,
+            back: bits & 0x08 != 0,
+            forward: bits & 0x10 != 0,
+        }
+    }
+
+    /// Returns the buttons as bits, in the layout of [`from_bits`](Self::from_bits).
+    pub const fn bits(self) -> u8 {
+        self.left as u8
+            | (self.right as u8) << 1
+            | (self.middle as u8) << 2
+            | (self.back as u8) << 3
+            | (self.forward as u8) << 4
+    }
+}
+
+/// One decoded mouse packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Horizontal movement since the last packet; positive is right.
+    pub dx: i16,
+    /// Vertical movement since the last packet; positive is up.
+    pub dy: i16,
+    /// Scroll wheel movement in notches; positive is away from the user (scrolling up). Always 0 without a wheel.
+    pub wheel: i8,
+    /// Buttons held down.
+    pub buttons: MouseButtons,
+    /// Buttons whose state differs from the previous packet.
+    pub changed: MouseButtons,
+}
+
+/// Assembles 3-byte packets, or 4-byte IntelliMouse packets, from the mouse's byte stream.
+///
+/// Every field is atomic so a `static` parser can be fed from the IRQ handler without a lock; there must be a single feeder.
+pub struct PacketParser {
+    bytes: [AtomicU8; 4],
+    index: AtomicU8,
+    buttons: AtomicU8,
+    /// ID of the mouse protocol: 0 (standard), 3 (wheel) or 4 (five buttons).
+    protocol: AtomicU8,
+}
+
+impl PacketParser {
+    /// Creates a parser for standard 3-byte packets, waiting for the first byte of a packet.
+    pub const fn new() -> Self {
+        PacketParser {
+            bytes: [const { AtomicU8::new(0) }; 4],
+            index: AtomicU8::new(0),
+            buttons: AtomicU8::new(0),
+            protocol: AtomicU8::new(0),
+        }
+    }
+
+    /// Switches to the packet format of `device`, dropping a partially received packet. Devices other than [`DeviceType::WheelMouse`] and [`DeviceType::FiveButtonMouse`] send standard packets.
+    pub fn set_device(&self, device: DeviceType) {
+        let protocol = match device {
+            DeviceType::WheelMouse => 3,
+            DeviceType::FiveButtonMouse => 4,
+            _ => 0,
+        };
+        self.protocol.store(protocol, Ordering::Relaxed);
+        self.reset();
+    }
+
+    /// Returns the device whose packet format is being parsed.
+    pub fn device(&self) -> DeviceType {
+        DeviceType::from_id(Some(self.protocol.load(Ordering::Relaxed)))
+    }
+
+    /// Feeds one byte from the mouse. Returns the event once a packet is complete.
+    ///
+    /// A first byte without bit 3 set cannot start a packet and is dropped, which resynchronizes the parser after a lost byte. Movement is discarded for packets with an overflow bit set.
+    pub fn feed(&self, byte: u8) -> Option<MouseEvent> {
+        let index = self.index.load(Ordering::Relaxed);
+        if index == 0 && byte & 0x08 == 0 {
+            return None;
+        }
+        self.bytes[index as usize].store(byte, Ordering::Relaxed);
+        let protocol = self.protocol.load(Ordering::Relaxed);
+        let last = if protocol == 0 { 2 } else { 3 };
+        if index < last {
+            self.index.store(index + 1, Ordering::Relaxed);
+            return None;
+        }
+        self.index.store(0, Ordering::Relaxed);
+        let flags = self.bytes[0].load(Ordering::Relaxed);
+        let movement =
+            |value: u8, sign: u8| i16::from(value) - if flags & sign != 0 { 256 } else { 0 };
+        let overflow = flags & 0xC0 != 0;
+        let (dx, dy) = if overflow {
+            (0, 0)
+        } else {
+            (
+                movement(self.bytes[1].load(Ordering::Relaxed), 0x10),
+                movement(self.bytes[2].load(Ordering::Relaxed), 0x20),
+            )
+        };
+        let mut buttons = flags & 0x07;
+        // The packet's Z movement is positive towards the user, the event's away from them.
+        let wheel = match protocol {
+            3 => (byte as i8).saturating_neg(),
+            4 => {
+                buttons |= (byte & 0x30) >> 1;
+                // Sign-extend the 4-bit value.
+                -(((byte << 4) as i8) >> 4)
+            }
+            _ => 0,
+        };
+        let previous = self.buttons.swap(buttons, Ordering::Relaxed);
+        Some(MouseEvent {
+            dx,
+            dy,
+            wheel,
+            buttons: MouseButtons::from_bits(buttons),
+            changed: MouseButtons::from_bits(buttons ^ previous),
+        })
+    }
+
+    /// Drops a partially received packet.
+    pub fn reset(&self) {
+        self.index.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for PacketParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handler for decoded mouse events, called from IRQ context.
+pub type MouseEventHandler = fn(MouseEvent);
+
+static PARSER: PacketParser = PacketParser::new();
+static EVENT_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the function that receives every decoded [`MouseEvent`]. It runs in IRQ context and must not block.
+pub fn set_event_handler(handler: MouseEventHandler) {
+    EVENT_HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+/// Returns the protocol [`init`] enabled: [`DeviceType::Mouse`], [`DeviceType::WheelMouse`] or [`DeviceType::FiveButtonMouse`].
+pub fn device_type() -> DeviceType {
+    PARSER.device()
+}
+
+/// Sends the sample rates of `sequence` and returns the ID the mouse reports afterwards, `None` if a command failed.
+fn knock(sequence: [u8; 3]) -> Option<DeviceType> {
+    let mut queue = CommandQueue::<4>::new(CONTROLLER, Port::Second);
+    for rate in sequence {
+        queue.push(Command::with_argument(0xF3, rate)).ok()?;
+    }
+    queue.push(Command::new(0xF2).expecting(1)).ok()?;
+    let id = queue.run().ok()?;
+    Some(DeviceType::from_id(id.first()))
+}
+
+/// Enables the scroll wheel and then the extra buttons of an IntelliMouse, returning the resulting protocol.
+fn enable_extensions() -> DeviceType {
+    if knock(WHEEL_SEQUENCE) != Some(DeviceType::WheelMouse) {
+        return DeviceType::Mouse;
+    }
+    if knock(FIVE_BUTTON_SEQUENCE) != Some(DeviceType::FiveButtonMouse) {
+        return DeviceType::WheelMouse;
+    }
+    DeviceType::FiveButtonMouse
+}
+
+/// Enables the second PS/2 port, IRQ 12 and a mouse on it, with the IntelliMouse wheel and buttons if it has them, in streaming mode at [`SAMPLE_RATE`].
+///
+/// Must run after [`crate::ps2_init`] found a mouse on the second port. Returns `false` if no mouse answered or IRQ 12 could not be unmasked (see [`crate::set_irq_mask_hook`]); the port is then left enabled but IRQ 12 stays masked.
+pub fn init() -> bool {
+    info("Initializing PS/2 mouse...");
+    // Enable the second port
+    CONTROLLER.enable_port(Port::Second);
+    // Configuration byte: enable IRQ 12, enable the second port's clock
+    let Some(config) = CONTROLLER.read_config() else {
+        warn("PS/2 controller did not return its configuration byte");
+        return false;
+    };
+    CONTROLLER.write_config((config | CONFIG_SECOND_IRQ) & !CONFIG_SECOND_CLOCK_DISABLED);
+
+    if let Err(e) = CONTROLLER.send_to_device(Port::Second, Command::new(0xF6)) {
+        warn(&format!("Mouse command 0xf6 failed: {e:?}"));
+        return false;
+    }
+    let device = enable_extensions();
+    info(&format!("PS/2 mouse protocol: {device:?}"));
+
+    let mut queue = CommandQueue::<2>::new(CONTROLLER, Port::Second);
+    let queued = queue
+        .push(Command::with_argument(0xF3, SAMPLE_RATE))
+        .and_then(|()| queue.push(Command::new(0xF4)));
+    if let Err(e) = queued {
+        warn(&format!("Could not queue mouse commands: {e:?}"));
+        return false;
+    }
+    if let Err(e) = queue.run() {
+        warn(&format!(
+            "Mouse command {:#x} failed: {:?}",
+            e.command.command(),
+            e.error
+        ));
+        return false;
+    }
+    PARSER.set_device(device);
+    if !set_irq_masked(MOUSE_IRQ, false) {
+        warn("The interrupt controller has no IRQ 12 for the mouse");
+        return false;
+    }
+    info("PS/2 mouse initialized");
+    true
+}
+
+/// Handler for IRQ 12: reads one byte from the mouse and passes completed packets to the event handler.
+///
+/// Its signature matches the IRQ handlers of `polished_interrupts::irq`.
+pub fn irq_handler(_irq: u8) {
+    let byte = CONTROLLER.read_data_now();
+    let Some(event) = PARSER.feed(byte) else {
+        return;
+    };
+    let handler = EVENT_HANDLER.load(Ordering::Acquire);
+    if !handler.is_null() {
+        // Safety: only `MouseEventHandler` function pointers are ever stored.
+        let handler = unsafe { core::mem::transmute::<*mut (), MouseEventHandler>(handler) };
+        handler(event);
+    }
+}
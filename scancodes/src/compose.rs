@@ -0,0 +1,154 @@
+//! # Compose
+//!
+//! [`Compose`] is the state machine behind dead keys. Feeding it the characters of key presses, together with whether the key was dead ([`Keymap::is_dead`](crate::Keymap::is_dead)), gives the characters to type:
+//!
+//! | Pending accent | Next input          | Output                               |
+//! |----------------|---------------------|--------------------------------------|
+//! | none           | dead accent         | nothing, the accent becomes pending  |
+//! | none           | character           | the character                        |
+//! | accent         | combinable letter   | the combined letter (`^` + `e` = `ê`) |
+//! | accent         | space or same accent| the accent itself                    |
+//! | accent         | another dead accent | the first accent, the second pends   |
+//! | accent         | control character   | the control character, accent dropped|
+//! | accent         | anything else       | the accent, then the character       |
+//!
+//! ## Example
+//! ```ignore
+//! let mut compose = Compose::new();
+//! if let Some(c) = event.to_char(keymap) {
+//!     let dead = keymap.is_dead(event.code, event.modifiers);
+//!     for c in compose.feed(c, dead).chars() {
+//!         console.write_char(c);
+//!     }
+//! }
+//! ```
+
+/// Accent, base letter and combined letter.
+const COMBINATIONS: [(char, char, char); 44] = [
+    ('´', 'a', 'á'),
+    ('´', 'e', 'é'),
+    ('´', 'i', 'í'),
+    ('´', 'o', 'ó'),
+    ('´', 'u', 'ú'),
+    ('´', 'y', 'ý'),
+    ('´', 'A', 'Á'),
+    ('´', 'E', 'É'),
+    ('´', 'I', 'Í'),
+    ('´', 'O', 'Ó'),
+    ('´', 'U', 'Ú'),
+    ('´', 'Y', 'Ý'),
+    ('`', 'a', 'à'),
+    ('`', 'e', 'è'),
+    ('`', 'i', 'ì'),
+    ('`', 'o', 'ò'),
+    ('`', 'u', 'ù'),
+    ('`', 'A', 'À'),
+    ('`', 'E', 'È'),
+    ('`', 'I', 'Ì'),
+    ('`', 'O', 'Ò'),
+    ('`', 'U', 'Ù'),
+    ('^', 'a', 'â'),
+    ('^', 'e', 'ê'),
+    ('^', 'i', 'î'),
+    ('^', 'o', 'ô'),
+    ('^', 'u', 'û'),
+    ('^', 'A', 'Â'),
+    ('^', 'E', 'Ê'),
+    ('^', 'I', 'Î'),
+    ('^', 'O', 'Ô'),
+    ('^', 'U', 'Û'),
+    ('¨', 'a', 'ä'),
+    ('¨', 'e', 'ë'),
+    ('¨', 'i', 'ï'),
+    ('¨', 'o', 'ö'),
+    ('¨', 'u', 'ü'),
+    ('¨', 'y', 'ÿ'),
+    ('¨', 'A', 'Ä'),
+    ('¨', 'E', 'Ë'),
+    ('¨', 'I', 'Ï'),
+    ('¨', 'O', 'Ö'),
+    ('¨', 'U', 'Ü'),
+    ('¨', 'Y', 'Ÿ'),
+];
+
+/// Returns the letter `accent` and `base` combine into.
+pub fn combine(accent: char, base: char) -> Option<char> {
+    if base == ' ' {
+        return Some(accent);
+    }
+    COMBINATIONS
+        .iter()
+        .find(|&&(a, b, _)| a == accent && b == base)
+        .map(|&(_, _, combined)| combined)
+}
+
+/// Characters produced by one [`Compose::feed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Composed {
+    /// Nothing yet: an accent is pending.
+    Pending,
+    /// One character.
+    One(char),
+    /// A pending accent that did not combine, then the character.
+    Two(char, char),
+}
+
+impl Composed {
+    /// Iterates over the characters in typing order.
+    pub fn chars(self) -> impl Iterator<Item = char> {
+        let (first, second) = match self {
+            Composed::Pending => (None, None),
+            Composed::One(c) => (Some(c), None),
+            Composed::Two(a, b) => (Some(a), Some(b)),
+        };
+        first.into_iter().chain(second)
+    }
+}
+
+/// Dead key state: the accent waiting for the next character, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Compose {
+    pending: Option<char>,
+}
+
+impl Compose {
+    /// Creates the state with no accent pending.
+    pub const fn new() -> Self {
+        Compose { pending: None }
+    }
+
+    /// Returns the pending accent.
+    pub fn pending(&self) -> Option<char> {
+        self.pending
+    }
+
+    /// Drops the pending accent, e.g. when the layout changes.
+    pub fn reset(&mut self) {
+        self.pending = None;
+    }
+
+    /// Feeds the character `c` of a key press; `dead` says whether the key was a dead key.
+    pub fn feed(&mut self, c: char, dead: bool) -> Composed {
+        let Some(accent) = self.pending.take() else {
+            if dead {
+                self.pending = Some(c);
+                return Composed::Pending;
+            }
+            return Composed::One(c);
+        };
+        if c.is_control() {
+            return Composed::One(c);
+        }
+        if c == accent && dead {
+            return Composed::One(accent);
+        }
+        if let Some(combined) = combine(accent, c) {
+            return Composed::One(combined);
+        }
+        if dead {
+            self.pending = Some(c);
+            return Composed::One(accent);
+        }
+        Composed::Two(accent, c)
+    }
+}
@@ -0,0 +1,457 @@
+//! # Key Codes
+//!
+//! [`KeyCode`] names every key of a standard 104/105-key PC keyboard independently of the scancode set and of the layout's characters, so consumers can match on `KeyCode::F1` or `KeyCode::ArrowUp` instead of decoding raw codes. A [`KeyEvent`] combines the key with its [`KeyState`] and the [`Modifiers`] in effect, and [`KeyEvent::to_char`] turns it into a character with a [`Keymap`]. A [`KeyBitmap`] records which keys are held.
+
+use crate::Modifiers;
+use crate::keymap::Keymap;
+
+/// A physical key, named after its US QWERTY legend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    Escape,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    Enter,
+    LeftCtrl,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Backtick,
+    LeftShift,
+    Backslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+    LeftAlt,
+    Space,
+    CapsLock,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    NumLock,
+    ScrollLock,
+    Keypad0,
+    Keypad1,
+    Keypad2,
+    Keypad3,
+    Keypad4,
+    Keypad5,
+    Keypad6,
+    Keypad7,
+    Keypad8,
+    Keypad9,
+    KeypadMultiply,
+    KeypadMinus,
+    KeypadPlus,
+    KeypadPeriod,
+    KeypadEnter,
+    KeypadSlash,
+    /// The extra key next to Left Shift on ISO keyboards.
+    NonUsBackslash,
+    RightCtrl,
+    RightAlt,
+    ArrowUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    PrintScreen,
+    LeftGui,
+    RightGui,
+    Menu,
+    /// Print Screen while Alt is held, which keyboards report as a separate key.
+    SysRq,
+}
+
+impl KeyCode {
+    /// Number of keys; every `KeyCode as usize` is below it.
+    pub const COUNT: usize = KeyCode::SysRq as usize + 1;
+
+    /// Returns the key with Set 1 make code `code`, `0xE0`-prefixed if `extended`.
+    pub fn from_set1(code: u8, extended: bool) -> Option<Self> {
+        use KeyCode::*;
+        let key = match (code, extended) {
+            (0x01, false) => Escape,
+            (0x02, false) => Digit1,
+            (0x03, false) => Digit2,
+            (0x04, false) => Digit3,
+            (0x05, false) => Digit4,
+            (0x06, false) => Digit5,
+            (0x07, false) => Digit6,
+            (0x08, false) => Digit7,
+            (0x09, false) => Digit8,
+            (0x0A, false) => Digit9,
+            (0x0B, false) => Digit0,
+            (0x0C, false) => Minus,
+            (0x0D, false) => Equals,
+            (0x0E, false) => Backspace,
+            (0x0F, false) => Tab,
+            (0x10, false) => Q,
+            (0x11, false) => W,
+            (0x12, false) => E,
+            (0x13, false) => R,
+            (0x14, false) => T,
+            (0x15, false) => Y,
+            (0x16, false) => U,
+            (0x17, false) => I,
+            (0x18, false) => O,
+            (0x19, false) => P,
+            (0x1A, false) => LeftBracket,
+            (0x1B, false) => RightBracket,
+            (0x1C, false) => Enter,
+            (0x1C, true) => KeypadEnter,
+            (0x1D, false) => LeftCtrl,
+            (0x1D, true) => RightCtrl,
+            (0x1E, false) => A,
+            (0x1F, false) => S,
+            (0x20, false) => D,
+            (0x21, false) => F,
+            (0x22, false) => G,
+            (0x23, false) => H,
+            (0x24, false) => J,
+            (0x25, false) => K,
+            (0x26, false) => L,
+            (0x27, false) => Semicolon,
+            (0x28, false) => Quote,
+            (0x29, false) => Backtick,
+            (0x2A, false) => LeftShift,
+            (0x2B, false) => Backslash,
+            (0x2C, false) => Z,
+            (0x2D, false) => X,
+            (0x2E, false) => C,
+            (0x2F, false) => V,
+            (0x30, false) => B,
+            (0x31, false) => N,
+            (0x32, false) => M,
+            (0x33, false) => Comma,
+            (0x34, false) => Period,
+            (0x35, false) => Slash,
+            (0x35, true) => KeypadSlash,
+            (0x36, false) => RightShift,
+            (0x37, false) => KeypadMultiply,
+            (0x37, true) => PrintScreen,
+            (0x38, false) => LeftAlt,
+            (0x38, true) => RightAlt,
+            (0x39, false) => Space,
+            (0x3A, false) => CapsLock,
+            (0x3B, false) => F1,
+            (0x3C, false) => F2,
+            (0x3D, false) => F3,
+            (0x3E, false) => F4,
+            (0x3F, false) => F5,
+            (0x40, false) => F6,
+            (0x41, false) => F7,
+            (0x42, false) => F8,
+            (0x43, false) => F9,
+            (0x44, false) => F10,
+            (0x45, false) => NumLock,
+            (0x46, false) => ScrollLock,
+            (0x47, false) => Keypad7,
+            (0x47, true) => Home,
+            (0x48, false) => Keypad8,
+            (0x48, true) => ArrowUp,
+            (0x49, false) => Keypad9,
+            (0x49, true) => PageUp,
+            (0x4A, false) => KeypadMinus,
+            (0x4B, false) => Keypad4,
+            (0x4B, true) => ArrowLeft,
+            (0x4C, false) => Keypad5,
+            (0x4D, false) => Keypad6,
+            (0x4D, true) => ArrowRight,
+            (0x4E, false) => KeypadPlus,
+            (0x4F, false) => Keypad1,
+            (0x4F, true) => End,
+            (0x50, false) => Keypad2,
+            (0x50, true) => ArrowDown,
+            (0x51, false) => Keypad3,
+            (0x51, true) => PageDown,
+            (0x52, false) => Keypad0,
+            (0x52, true) => Insert,
+            (0x53, false) => KeypadPeriod,
+            (0x53, true) => Delete,
+            (0x56, false) => NonUsBackslash,
+            (0x57, false) => F11,
+            (0x58, false) => F12,
+            (0x5B, true) => LeftGui,
+            (0x5C, true) => RightGui,
+            (0x5D, true) => Menu,
+            (0x54, false) => SysRq,
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    /// Returns whether the key is on the numeric keypad.
+    pub fn is_keypad(self) -> bool {
+        use KeyCode::*;
+        matches!(
+            self,
+            Keypad0
+                | Keypad1
+                | Keypad2
+                | Keypad3
+                | Keypad4
+                | Keypad5
+                | Keypad6
+                | Keypad7
+                | Keypad8
+                | Keypad9
+                | KeypadMultiply
+                | KeypadMinus
+                | KeypadPlus
+                | KeypadPeriod
+                | KeypadEnter
+                | KeypadSlash
+        )
+    }
+
+    /// Returns the unshifted ASCII byte of the key on a US QWERTY layout (lowercase for letters).
+    ///
+    /// Keypad digits and the keypad period only produce characters with Num Lock on.
+    pub fn to_ascii(self, num_lock: bool) -> Option<u8> {
+        use KeyCode::*;
+        let ascii = match self {
+            Escape => 0x1B,
+            Digit1 => b'1',
+            Digit2 => b'2',
+            Digit3 => b'3',
+            Digit4 => b'4',
+            Digit5 => b'5',
+            Digit6 => b'6',
+            Digit7 => b'7',
+            Digit8 => b'8',
+            Digit9 => b'9',
+            Digit0 => b'0',
+            Minus => b'-',
+            Equals => b'=',
+            Backspace => 0x08,
+            Tab => b'\t',
+            Q => b'q',
+            W => b'w',
+            E => b'e',
+            R => b'r',
+            T => b't',
+            Y => b'y',
+            U => b'u',
+            I => b'i',
+            O => b'o',
+            P => b'p',
+            LeftBracket => b'[',
+            RightBracket => b']',
+            Enter | KeypadEnter => b'\n',
+            A => b'a',
+            S => b's',
+            D => b'd',
+            F => b'f',
+            G => b'g',
+            H => b'h',
+            J => b'j',
+            K => b'k',
+            L => b'l',
+            Semicolon => b';',
+            Quote => b'\'',
+            Backtick => b'`',
+            Backslash | NonUsBackslash => b'\\',
+            Z => b'z',
+            X => b'x',
+            C => b'c',
+            V => b'v',
+            B => b'b',
+            N => b'n',
+            M => b'm',
+            Comma => b',',
+            Period => b'.',
+            Slash | KeypadSlash => b'/',
+            Space => b' ',
+            KeypadMultiply => b'*',
+            KeypadMinus => b'-',
+            KeypadPlus => b'+',
+            Keypad0 if num_lock => b'0',
+            Keypad1 if num_lock => b'1',
+            Keypad2 if num_lock => b'2',
+            Keypad3 if num_lock => b'3',
+            Keypad4 if num_lock => b'4',
+            Keypad5 if num_lock => b'5',
+            Keypad6 if num_lock => b'6',
+            Keypad7 if num_lock => b'7',
+            Keypad8 if num_lock => b'8',
+            Keypad9 if num_lock => b'9',
+            KeypadPeriod if num_lock => b'.',
+            _ => return None,
+        };
+        Some(ascii)
+    }
+}
+
+/// Whether a key went down or up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    /// The key was pressed, or repeats while held.
+    Pressed,
+    /// The key was released.
+    Released,
+}
+
+/// The set of keys currently held down, one bit per [`KeyCode`].
+///
+/// [`Keyboard`](crate::Keyboard) keeps one up to date from make and break codes, so programs can query chords such as Ctrl+C without tracking events themselves.
+///
+/// # Example
+/// ```ignore
+/// let held = keyboard.held();
+/// if held.is_pressed(KeyCode::LeftCtrl) && held.is_pressed(KeyCode::C) {
+///     shell.interrupt();
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyBitmap {
+    bits: u128,
+}
+
+const _: () = assert!(KeyCode::COUNT <= u128::BITS as usize);
+
+impl KeyBitmap {
+    /// Creates the bitmap with no key held.
+    pub const fn new() -> Self {
+        KeyBitmap { bits: 0 }
+    }
+
+    /// Marks `code` as held if `pressed`, released otherwise.
+    pub fn set(&mut self, code: KeyCode, pressed: bool) {
+        let bit = 1u128 << code as usize;
+        if pressed {
+            self.bits |= bit;
+        } else {
+            self.bits &= !bit;
+        }
+    }
+
+    /// Returns whether `code` is held.
+    pub fn is_pressed(&self, code: KeyCode) -> bool {
+        self.bits & (1u128 << code as usize) != 0
+    }
+
+    /// Returns whether every key of `codes` is held.
+    pub fn all_pressed(&self, codes: &[KeyCode]) -> bool {
+        codes.iter().all(|&code| self.is_pressed(code))
+    }
+
+    /// Returns the number of keys held.
+    pub fn count(&self) -> u32 {
+        self.bits.count_ones()
+    }
+
+    /// Returns whether no key is held.
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Marks every key as released.
+    pub fn clear(&mut self) {
+        self.bits = 0;
+    }
+}
+
+/// A key press or release with the modifier state after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// Returns whether the key was pressed.
+    pub fn is_pressed(&self) -> bool {
+        self.state == KeyState::Pressed
+    }
+
+    /// Returns the character typed by this event with `keymap`, for presses of keys that type one.
+    ///
+    /// Enter, Tab, Backspace, Escape, Space and the keypad type the same on every layout; the keypad is never shifted. Ctrl with an ASCII letter gives its control code (`Ctrl+C` is `'\x03'`), except with AltGr.
+    pub fn to_char(&self, keymap: &dyn Keymap) -> Option<char> {
+        if !self.is_pressed() {
+            return None;
+        }
+        let layout_independent = self.code.is_keypad()
+            || matches!(
+                self.code,
+                KeyCode::Enter
+                    | KeyCode::Tab
+                    | KeyCode::Backspace
+                    | KeyCode::Escape
+                    | KeyCode::Space
+            );
+        if layout_independent {
+            return self.code.to_ascii(self.modifiers.num_lock).map(char::from);
+        }
+        let c = keymap.map_key(self.code, self.modifiers)?;
+        if self.modifiers.ctrl() && !self.modifiers.right_alt && c.is_ascii_alphabetic() {
+            return Some(char::from(c.to_ascii_uppercase() as u8 - b'A' + 1));
+        }
+        Some(c)
+    }
+
+    /// Returns the ASCII byte typed by this event on a US QWERTY layout, for presses of printable keys.
+    ///
+    /// Shift, Caps Lock and Ctrl are applied as by [`to_char`](Self::to_char) with [`keymap::US`](crate::keymap::US).
+    pub fn ascii(&self) -> Option<u8> {
+        self.to_char(&crate::keymap::US)
+            .filter(char::is_ascii)
+            .map(|c| c as u8)
+    }
+}
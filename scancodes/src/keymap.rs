@@ -0,0 +1,519 @@
+//! # Keymaps
+//!
+//! A [`Keymap`] turns a [`KeyCode`] and the [`Modifiers`] into the character the key types on a given keyboard layout. [`KeyCode`]s name the physical keys after their US legends, so the same key is `KeyCode::Q` on every layout while the keymap decides whether it types `q` (QWERTY), `a` (AZERTY) or `'` (Dvorak).
+//!
+//! ## Built-in Layouts
+//!
+//! | Name     | Static      | Layout                |
+//! |----------|-------------|-----------------------|
+//! | `us`     | [`US`]      | US QWERTY             |
+//! | `uk`     | [`UK`]      | UK QWERTY             |
+//! | `azerty` | [`AZERTY`]  | French AZERTY         |
+//! | `qwertz` | [`QWERTZ`]  | German QWERTZ         |
+//! | `dvorak` | [`DVORAK`]  | US Dvorak             |
+//!
+//! [`by_name`] looks a layout up by name, e.g. from a configuration option. Custom layouts implement [`Keymap`] directly or are built as a [`TableKeymap`], most easily with the [`keymap!`](crate::keymap!) macro.
+//!
+//! ## Modifiers
+//!
+//! Table layouts have three levels per key: base, Shift, and AltGr (the right Alt key). Caps Lock acts as Shift for keys whose shifted character is an uppercase letter. Ctrl is not part of the keymap: [`KeyEvent::to_char`](crate::KeyEvent::to_char) turns Ctrl with an ASCII letter into a control code for every layout.
+//!
+//! Keys that type the same on every layout (Enter, Tab, Backspace, Escape, Space and the keypad) are handled by [`KeyEvent::to_char`](crate::KeyEvent::to_char) and need not be in a keymap.
+//!
+//! ## Dead Keys
+//!
+//! Some keys of the AZERTY and QWERTZ layouts type an accent (`´`, `` ` ``, `^`, `¨`) that combines with the next character instead of appearing by itself. Mappings mark them with [`KeyMapping::dead`] and [`Keymap::is_dead`] reports them; [`Compose`](crate::compose::Compose) then produces the combined character, e.g. `^` then `e` gives `ê`.
+//!
+//! ## Example
+//! ```ignore
+//! let keymap = keymap::by_name("qwertz").unwrap_or(&keymap::US);
+//! if let Some(c) = event.to_char(keymap) {
+//!     console.write_char(c);
+//! }
+//! ```
+
+use crate::{KeyCode, Modifiers};
+
+/// Defines a [`TableKeymap`] static from a list of keys, e.g. for a custom layout.
+///
+/// Each entry names a [`KeyCode`] variant and its characters:
+///
+/// - `Q: 'q'`: an ASCII letter, uppercase with Shift and Caps Lock ([`KeyMapping::letter`]).
+/// - `Digit1: '1' '!'`: base and Shift characters ([`KeyMapping::new`]).
+/// - `Digit4: '4' '$' '€'`: base, Shift and AltGr characters ([`KeyMapping::with_alt_gr`]).
+///
+/// Any entry may end with `dead(...)`, listing the levels (`base`, `shifted`, `alt_gr`) whose character is a dead key ([`KeyMapping::dead`]). The result is the same table representation as the built-in layouts.
+///
+/// # Example
+/// ```ignore
+/// polished_scancodes::keymap! {
+///     /// Swiss German.
+///     pub static SWISS = "ch" {
+///         Backtick: '§' '°',
+///         Digit1: '1' '+' '¦',
+///         Equals: '^' '`' '~' dead(base | shifted | alt_gr),
+///         Q: 'q',
+///         Y: 'z',
+///         Z: 'y',
+///     }
+/// }
+/// keyboard::set_keymap(&SWISS);
+/// ```
+#[macro_export]
+macro_rules! keymap {
+    (
+        $(#[$meta:meta])*
+        $vis:vis static $ident:ident = $name:literal {
+            $($code:ident: $($c:literal)+ $(dead($($level:ident)|+))?),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis static $ident: $crate::keymap::TableKeymap = $crate::keymap::TableKeymap::new(
+            $name,
+            &[$(
+                $crate::keymap!(@key $code $($c)+)
+                    .dead(0 $($(| $crate::keymap!(@dead $level))+)?)
+            ),*],
+        );
+    };
+    (@key $code:ident $letter:literal) => {
+        $crate::keymap::KeyMapping::letter($crate::KeyCode::$code, $letter)
+    };
+    (@key $code:ident $base:literal $shifted:literal) => {
+        $crate::keymap::KeyMapping::new($crate::KeyCode::$code, $base, $shifted)
+    };
+    (@key $code:ident $base:literal $shifted:literal $alt_gr:literal) => {
+        $crate::keymap::KeyMapping::with_alt_gr($crate::KeyCode::$code, $base, $shifted, $alt_gr)
+    };
+    (@dead base) => {
+        $crate::keymap::DEAD_BASE
+    };
+    (@dead shifted) => {
+        $crate::keymap::DEAD_SHIFTED
+    };
+    (@dead alt_gr) => {
+        $crate::keymap::DEAD_ALT_GR
+    };
+}
+
+/// A keyboard layout.
+pub trait Keymap: Sync {
+    /// Returns the short name of the layout, as accepted by [`by_name`] for built-in layouts.
+    fn name(&self) -> &'static str;
+
+    /// Returns the character `code` types with `modifiers`, or `None` if it types none. Ctrl is ignored.
+    fn map_key(&self, code: KeyCode, modifiers: Modifiers) -> Option<char>;
+
+    /// Returns whether `code` is a dead key with `modifiers`: its character is an accent for the next key rather than typed by itself (see [`Compose`](crate::compose::Compose)).
+    fn is_dead(&self, code: KeyCode, modifiers: Modifiers) -> bool {
+        let _ = (code, modifiers);
+        false
+    }
+}
+
+/// [`KeyMapping::dead`] level: the base character is dead.
+pub const DEAD_BASE: u8 = 1 << 0;
+/// [`KeyMapping::dead`] level: the Shift character is dead.
+pub const DEAD_SHIFTED: u8 = 1 << 1;
+/// [`KeyMapping::dead`] level: the AltGr character is dead.
+pub const DEAD_ALT_GR: u8 = 1 << 2;
+
+/// The characters of one key in a [`TableKeymap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMapping {
+    pub code: KeyCode,
+    /// Character without modifiers.
+    pub base: char,
+    /// Character with Shift.
+    pub shifted: char,
+    /// Character with AltGr, if any.
+    pub alt_gr: Option<char>,
+    /// Levels whose character is a dead key, a combination of [`DEAD_BASE`], [`DEAD_SHIFTED`] and [`DEAD_ALT_GR`].
+    pub dead: u8,
+}
+
+impl KeyMapping {
+    /// A key typing `base`, and `shifted` with Shift.
+    pub const fn new(code: KeyCode, base: char, shifted: char) -> Self {
+        KeyMapping {
+            code,
+            base,
+            shifted,
+            alt_gr: None,
+            dead: 0,
+        }
+    }
+
+    /// A key typing `base`, `shifted` with Shift, and `alt_gr` with AltGr.
+    pub const fn with_alt_gr(code: KeyCode, base: char, shifted: char, alt_gr: char) -> Self {
+        KeyMapping {
+            code,
+            base,
+            shifted,
+            alt_gr: Some(alt_gr),
+            dead: 0,
+        }
+    }
+
+    /// A letter key typing the lowercase ASCII letter `letter`, uppercase with Shift.
+    pub const fn letter(code: KeyCode, letter: char) -> Self {
+        Self::new(code, letter, letter.to_ascii_uppercase())
+    }
+
+    /// Marks the characters of `levels` as dead keys.
+    pub const fn dead(mut self, levels: u8) -> Self {
+        self.dead = levels;
+        self
+    }
+}
+
+/// Which of the characters of a [`KeyMapping`] a key press selects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Base,
+    Shifted,
+    AltGr,
+}
+
+impl Level {
+    /// Returns the level `modifiers` select for `key`. Caps Lock acts as Shift for letters.
+    fn select(key: &KeyMapping, modifiers: Modifiers) -> Self {
+        if modifiers.right_alt {
+            return Level::AltGr;
+        }
+        let caps = modifiers.caps_lock && key.shifted.is_uppercase();
+        if modifiers.shift() != caps {
+            Level::Shifted
+        } else {
+            Level::Base
+        }
+    }
+
+    fn dead_bit(self) -> u8 {
+        match self {
+            Level::Base => DEAD_BASE,
+            Level::Shifted => DEAD_SHIFTED,
+            Level::AltGr => DEAD_ALT_GR,
+        }
+    }
+}
+
+/// A [`Keymap`] defined by a table of [`KeyMapping`]s.
+#[derive(Debug, Clone, Copy)]
+pub struct TableKeymap {
+    name: &'static str,
+    keys: &'static [KeyMapping],
+}
+
+impl TableKeymap {
+    /// Creates a layout called `name` from `keys`. Keys not in the table type nothing.
+    pub const fn new(name: &'static str, keys: &'static [KeyMapping]) -> Self {
+        TableKeymap { name, keys }
+    }
+
+    /// Returns the mapping of `code`.
+    pub fn mapping(&self, code: KeyCode) -> Option<&KeyMapping> {
+        self.keys.iter().find(|key| key.code == code)
+    }
+}
+
+impl Keymap for TableKeymap {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn map_key(&self, code: KeyCode, modifiers: Modifiers) -> Option<char> {
+        let key = self.mapping(code)?;
+        match Level::select(key, modifiers) {
+            Level::Base => Some(key.base),
+            Level::Shifted => Some(key.shifted),
+            Level::AltGr => key.alt_gr,
+        }
+    }
+
+    fn is_dead(&self, code: KeyCode, modifiers: Modifiers) -> bool {
+        self.mapping(code)
+            .is_some_and(|key| key.dead & Level::select(key, modifiers).dead_bit() != 0)
+    }
+}
+
+keymap! {
+    /// US QWERTY.
+    pub static US = "us" {
+        Backtick: '`' '~',
+        Digit1: '1' '!',
+        Digit2: '2' '@',
+        Digit3: '3' '#',
+        Digit4: '4' '$',
+        Digit5: '5' '%',
+        Digit6: '6' '^',
+        Digit7: '7' '&',
+        Digit8: '8' '*',
+        Digit9: '9' '(',
+        Digit0: '0' ')',
+        Minus: '-' '_',
+        Equals: '=' '+',
+        Q: 'q',
+        W: 'w',
+        E: 'e',
+        R: 'r',
+        T: 't',
+        Y: 'y',
+        U: 'u',
+        I: 'i',
+        O: 'o',
+        P: 'p',
+        LeftBracket: '[' '{',
+        RightBracket: ']' '}',
+        Backslash: '\\' '|',
+        A: 'a',
+        S: 's',
+        D: 'd',
+        F: 'f',
+        G: 'g',
+        H: 'h',
+        J: 'j',
+        K: 'k',
+        L: 'l',
+        Semicolon: ';' ':',
+        Quote: '\'' '"',
+        NonUsBackslash: '\\' '|',
+        Z: 'z',
+        X: 'x',
+        C: 'c',
+        V: 'v',
+        B: 'b',
+        N: 'n',
+        M: 'm',
+        Comma: ',' '<',
+        Period: '.' '>',
+        Slash: '/' '?',
+    }
+}
+
+keymap! {
+    /// UK QWERTY.
+    pub static UK = "uk" {
+        Backtick: '`' '¬' '¦',
+        Digit1: '1' '!',
+        Digit2: '2' '"',
+        Digit3: '3' '£',
+        Digit4: '4' '$' '€',
+        Digit5: '5' '%',
+        Digit6: '6' '^',
+        Digit7: '7' '&',
+        Digit8: '8' '*',
+        Digit9: '9' '(',
+        Digit0: '0' ')',
+        Minus: '-' '_',
+        Equals: '=' '+',
+        Q: 'q',
+        W: 'w',
+        E: 'e' 'E' 'é',
+        R: 'r',
+        T: 't',
+        Y: 'y',
+        U: 'u',
+        I: 'i',
+        O: 'o',
+        P: 'p',
+        LeftBracket: '[' '{',
+        RightBracket: ']' '}',
+        Backslash: '#' '~',
+        A: 'a',
+        S: 's',
+        D: 'd',
+        F: 'f',
+        G: 'g',
+        H: 'h',
+        J: 'j',
+        K: 'k',
+        L: 'l',
+        Semicolon: ';' ':',
+        Quote: '\'' '@',
+        NonUsBackslash: '\\' '|',
+        Z: 'z',
+        X: 'x',
+        C: 'c',
+        V: 'v',
+        B: 'b',
+        N: 'n',
+        M: 'm',
+        Comma: ',' '<',
+        Period: '.' '>',
+        Slash: '/' '?',
+    }
+}
+
+keymap! {
+    /// French AZERTY.
+    pub static AZERTY = "azerty" {
+        Backtick: '²' '³',
+        Digit1: '&' '1',
+        Digit2: 'é' '2' '~',
+        Digit3: '"' '3' '#',
+        Digit4: '\'' '4' '{',
+        Digit5: '(' '5' '[',
+        Digit6: '-' '6' '|',
+        Digit7: 'è' '7' '`' dead(alt_gr),
+        Digit8: '_' '8' '\\',
+        Digit9: 'ç' '9' '^',
+        Digit0: 'à' '0' '@',
+        Minus: ')' '°' ']',
+        Equals: '=' '+' '}',
+        Q: 'a',
+        W: 'z',
+        E: 'e' 'E' '€',
+        R: 'r',
+        T: 't',
+        Y: 'y',
+        U: 'u',
+        I: 'i',
+        O: 'o',
+        P: 'p',
+        LeftBracket: '^' '¨' dead(base | shifted),
+        RightBracket: '$' '£' '¤',
+        Backslash: '*' 'µ',
+        A: 'q',
+        S: 's',
+        D: 'd',
+        F: 'f',
+        G: 'g',
+        H: 'h',
+        J: 'j',
+        K: 'k',
+        L: 'l',
+        Semicolon: 'm',
+        Quote: 'ù' '%',
+        NonUsBackslash: '<' '>',
+        Z: 'w',
+        X: 'x',
+        C: 'c',
+        V: 'v',
+        B: 'b',
+        N: 'n',
+        M: ',' '?',
+        Comma: ';' '.',
+        Period: ':' '/',
+        Slash: '!' '§',
+    }
+}
+
+keymap! {
+    /// German QWERTZ.
+    pub static QWERTZ = "qwertz" {
+        Backtick: '^' '°' dead(base),
+        Digit1: '1' '!',
+        Digit2: '2' '"' '²',
+        Digit3: '3' '§' '³',
+        Digit4: '4' '$',
+        Digit5: '5' '%',
+        Digit6: '6' '&',
+        Digit7: '7' '/' '{',
+        Digit8: '8' '(' '[',
+        Digit9: '9' ')' ']',
+        Digit0: '0' '=' '}',
+        Minus: 'ß' '?' '\\',
+        Equals: '´' '`' dead(base | shifted),
+        Q: 'q' 'Q' '@',
+        W: 'w',
+        E: 'e' 'E' '€',
+        R: 'r',
+        T: 't',
+        Y: 'z',
+        U: 'u',
+        I: 'i',
+        O: 'o',
+        P: 'p',
+        LeftBracket: 'ü' 'Ü',
+        RightBracket: '+' '*' '~',
+        Backslash: '#' '\'',
+        A: 'a',
+        S: 's',
+        D: 'd',
+        F: 'f',
+        G: 'g',
+        H: 'h',
+        J: 'j',
+        K: 'k',
+        L: 'l',
+        Semicolon: 'ö' 'Ö',
+        Quote: 'ä' 'Ä',
+        NonUsBackslash: '<' '>' '|',
+        Z: 'y',
+        X: 'x',
+        C: 'c',
+        V: 'v',
+        B: 'b',
+        N: 'n',
+        M: 'm' 'M' 'µ',
+        Comma: ',' ';',
+        Period: '.' ':',
+        Slash: '-' '_',
+    }
+}
+
+keymap! {
+    /// US Dvorak.
+    pub static DVORAK = "dvorak" {
+        Backtick: '`' '~',
+        Digit1: '1' '!',
+        Digit2: '2' '@',
+        Digit3: '3' '#',
+        Digit4: '4' '$',
+        Digit5: '5' '%',
+        Digit6: '6' '^',
+        Digit7: '7' '&',
+        Digit8: '8' '*',
+        Digit9: '9' '(',
+        Digit0: '0' ')',
+        Minus: '[' '{',
+        Equals: ']' '}',
+        Q: '\'' '"',
+        W: ',' '<',
+        E: '.' '>',
+        R: 'p',
+        T: 'y',
+        Y: 'f',
+        U: 'g',
+        I: 'c',
+        O: 'r',
+        P: 'l',
+        LeftBracket: '/' '?',
+        RightBracket: '=' '+',
+        Backslash: '\\' '|',
+        A: 'a',
+        S: 'o',
+        D: 'e',
+        F: 'u',
+        G: 'i',
+        H: 'd',
+        J: 'h',
+        K: 't',
+        L: 'n',
+        Semicolon: 's',
+        Quote: '-' '_',
+        NonUsBackslash: '\\' '|',
+        Z: ';' ':',
+        X: 'q',
+        C: 'j',
+        V: 'k',
+        B: 'x',
+        N: 'b',
+        M: 'm',
+        Comma: 'w',
+        Period: 'v',
+        Slash: 'z',
+    }
+}
+
+/// The built-in layouts.
+pub static LAYOUTS: [&dyn Keymap; 5] = [&US, &UK, &AZERTY, &QWERTZ, &DVORAK];
+
+/// Returns the built-in layout called `name` (case-insensitive).
+pub fn by_name(name: &str) -> Option<&'static dyn Keymap> {
+    LAYOUTS
+        .iter()
+        .copied()
+        .find(|keymap| keymap.name().eq_ignore_ascii_case(name))
+}
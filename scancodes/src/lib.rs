@@ -13,13 +13,30 @@
 //!   - For printable keys, this is the ASCII code (e.g., 'A' as u16).
 //!   - For special keys (Ctrl, Shift, Fn, etc.), a unique code is used (e.g., 0x0100 for Left Ctrl).
 //!
+//! # Scancode Sets
+//!
+//! Keyboards power up in Set 2, where a release is the make code prefixed with `0xF0`. The PS/2 controller can translate Set 2 into Set 1 (releases have bit 7 set) before the bytes reach port 0x60, but only if translation is enabled in its configuration byte. Which set arrives therefore depends on the controller setup and the keyboard, and is determined at runtime (see the `polished_ps2` crate).
+//!
+//! [`Decoder`] consumes the byte stream of either set ([`ScancodeSet`]), including `0xE0` (extended) and `0xE1` (Pause) prefixes, and produces one [`RawKeyEvent`] per press or release. Set 2 codes are mapped to their Set 1 equivalents, so a single table serves both sets.
+//!
+//! # Modifiers
+//!
+//! [`Keyboard`] wraps a [`Decoder`] and tracks Shift, Ctrl, Alt, Caps Lock and Num Lock across events in [`Modifiers`], so the character of a key press reflects them: `'1'` or `'!'`, `'a'` or `'A'`, and control codes for Ctrl with a letter.
+//!
+//! # Key Events
+//!
+//! [`Keyboard::feed`] produces a [`KeyEvent`]: a [`KeyCode`] naming the key (letters, digits, F-keys, arrows, keypad, ...), its [`KeyState`], and the [`Modifiers`]. [`KeyEvent::to_char`] gives the typed character on a [`Keymap`] layout, [`KeyEvent::ascii`] the US QWERTY one.
+//!
 //! # Usage
-//! - Use `scancode_to_keysym` to convert a scancode to a symbolic key value.
-//! - Use `scancode_to_ascii` to convert a scancode to an ASCII byte (if possible).
+//! - Use [`Keyboard::feed`] in the keyboard interrupt handler to turn bytes into key events.
+//! - Use [`Decoder::feed`] for the key events alone.
+//! - Use `scancode_to_keysym` to convert a Set 1 make code to a symbolic key value.
+//! - Use `scancode_to_ascii` to convert a Set 1 make code to an ASCII byte (if possible), unshifted with uppercase letters.
+//! - Use [`scancode_to_char`] for the unshifted character of a Set 1 make code on any [`Keymap`], including non-ASCII ones such as `é` or `ß`.
 //!
 //! # Limitations
-//! - Only Set 1 scancodes are supported.
-//! - Extended keys (E0/E1 prefix) and key release events are not handled here.
+//! - Set 3 is not supported.
+//! - The legacy [`Keysym`] lookups only know Set 1 codes without the `0xE0` prefix; [`KeyCode`] covers extended keys.
 //! - This table is designed for US QWERTY layout.
 //!
 //! # Safety
@@ -27,6 +44,18 @@
 
 #![no_std]
 
+/// Layout-independent key codes and key events.
+pub mod keycode;
+
+/// Dead key composition of accented characters.
+pub mod compose;
+/// Keyboard layouts mapping key codes to characters.
+pub mod keymap;
+
+pub use compose::{Compose, Composed};
+pub use keycode::{KeyBitmap, KeyCode, KeyEvent, KeyState};
+pub use keymap::Keymap;
+
 /// Lookup table for PS/2 Set 1 scancodes to keysyms/ASCII.
 ///
 /// The index is the scancode (0-88). Values are:
@@ -127,7 +156,7 @@ static PS2_SET1: [u16; 89] = [
 
 /// Symbolic representation of a key (keysym).
 ///
-/// This wraps a `u16` value, which may be an ASCII code or a special code for modifiers/function keys.
+/// This wraps a `u16` value, which may be an ASCII code or a special code for modifiers/function keys. New code should use [`KeyCode`], which names keys instead of packing them into magic values.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Keysym(u16);
 
@@ -198,7 +227,7 @@ pub fn scancode_to_keysym(scancode: u8) -> Keysym {
 /// `Some(ascii)` if the key is a printable ASCII character, or `None` otherwise.
 ///
 /// # Note
-/// This is useful for simple text input, but does not handle modifiers or non-ASCII keys.
+/// This is useful for simple text input, but does not handle modifiers or non-ASCII keys; see [`scancode_to_char`] and [`KeyEvent::to_char`] for those.
 pub fn scancode_to_ascii(scancode: u8) -> Option<u8> {
     if scancode as usize >= PS2_SET1.len() {
         return None;
@@ -211,3 +240,471 @@ pub fn scancode_to_ascii(scancode: u8) -> Option<u8> {
         None
     }
 }
+
+/// Converts a PS/2 Set 1 make code to the character its key types on `keymap` without modifiers.
+///
+/// Unlike [`scancode_to_ascii`], the result can be any Unicode character, e.g. `'é'` for the `2` key on [`keymap::AZERTY`]. Returns `None` for break codes and keys that type nothing.
+pub fn scancode_to_char(scancode: u8, keymap: &dyn Keymap) -> Option<char> {
+    let event = KeyEvent {
+        code: KeyCode::from_set1(scancode, false)?,
+        state: KeyState::Pressed,
+        modifiers: Modifiers::default(),
+    };
+    event.to_char(keymap)
+}
+
+/// Scancode set delivered at the PS/2 data port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    /// Set 1 (XT): releases have bit 7 set. Also what the controller delivers with translation enabled.
+    Set1,
+    /// Set 2 (AT): releases are prefixed with `0xF0`.
+    Set2,
+}
+
+impl ScancodeSet {
+    /// Interprets the keyboard's answer to "get scancode set" (`0xF0 0x00`).
+    ///
+    /// With controller translation enabled the answer is translated too (`0x43`, `0x41`, `0x3F` for sets 1, 2 and 3). Returns `None` for set 3 or an unknown answer.
+    pub fn from_query_response(response: u8) -> Option<Self> {
+        match response {
+            0x01 | 0x43 => Some(ScancodeSet::Set1),
+            0x02 | 0x41 => Some(ScancodeSet::Set2),
+            _ => None,
+        }
+    }
+}
+
+/// Set 2 make codes and the Set 1 make codes of the same keys.
+const SET2_PAIRS: [(u8, u8); 90] = [
+    (0x76, 0x01), // Escape
+    (0x16, 0x02), // '1'
+    (0x1E, 0x03), // '2'
+    (0x26, 0x04), // '3'
+    (0x25, 0x05), // '4'
+    (0x2E, 0x06), // '5'
+    (0x36, 0x07), // '6'
+    (0x3D, 0x08), // '7'
+    (0x3E, 0x09), // '8'
+    (0x46, 0x0A), // '9'
+    (0x45, 0x0B), // '0'
+    (0x4E, 0x0C), // '-'
+    (0x55, 0x0D), // '='
+    (0x66, 0x0E), // Backspace
+    (0x0D, 0x0F), // Tab
+    (0x15, 0x10), // 'Q'
+    (0x1D, 0x11), // 'W'
+    (0x24, 0x12), // 'E'
+    (0x2D, 0x13), // 'R'
+    (0x2C, 0x14), // 'T'
+    (0x35, 0x15), // 'Y'
+    (0x3C, 0x16), // 'U'
+    (0x43, 0x17), // 'I'
+    (0x44, 0x18), // 'O'
+    (0x4D, 0x19), // 'P'
+    (0x54, 0x1A), // '['
+    (0x5B, 0x1B), // ']'
+    (0x5A, 0x1C), // Enter
+    (0x14, 0x1D), // Left Ctrl
+    (0x1C, 0x1E), // 'A'
+    (0x1B, 0x1F), // 'S'
+    (0x23, 0x20), // 'D'
+    (0x2B, 0x21), // 'F'
+    (0x34, 0x22), // 'G'
+    (0x33, 0x23), // 'H'
+    (0x3B, 0x24), // 'J'
+    (0x42, 0x25), // 'K'
+    (0x4B, 0x26), // 'L'
+    (0x4C, 0x27), // ';'
+    (0x52, 0x28), // '''
+    (0x0E, 0x29), // '`'
+    (0x12, 0x2A), // Left Shift
+    (0x5D, 0x2B), // '\'
+    (0x1A, 0x2C), // 'Z'
+    (0x22, 0x2D), // 'X'
+    (0x21, 0x2E), // 'C'
+    (0x2A, 0x2F), // 'V'
+    (0x32, 0x30), // 'B'
+    (0x31, 0x31), // 'N'
+    (0x3A, 0x32), // 'M'
+    (0x41, 0x33), // ','
+    (0x49, 0x34), // '.'
+    (0x4A, 0x35), // '/'
+    (0x59, 0x36), // Right Shift
+    (0x7C, 0x37), // Keypad '*'
+    (0x11, 0x38), // Left Alt
+    (0x29, 0x39), // Space
+    (0x58, 0x3A), // Caps Lock
+    (0x05, 0x3B), // F1
+    (0x06, 0x3C), // F2
+    (0x04, 0x3D), // F3
+    (0x0C, 0x3E), // F4
+    (0x03, 0x3F), // F5
+    (0x0B, 0x40), // F6
+    (0x83, 0x41), // F7
+    (0x0A, 0x42), // F8
+    (0x01, 0x43), // F9
+    (0x09, 0x44), // F10
+    (0x77, 0x45), // Num Lock
+    (0x7E, 0x46), // Scroll Lock
+    (0x6C, 0x47), // Keypad '7'
+    (0x75, 0x48), // Keypad '8'
+    (0x7D, 0x49), // Keypad '9'
+    (0x7B, 0x4A), // Keypad '-'
+    (0x6B, 0x4B), // Keypad '4'
+    (0x73, 0x4C), // Keypad '5'
+    (0x74, 0x4D), // Keypad '6'
+    (0x79, 0x4E), // Keypad '+'
+    (0x69, 0x4F), // Keypad '1'
+    (0x72, 0x50), // Keypad '2'
+    (0x7A, 0x51), // Keypad '3'
+    (0x70, 0x52), // Keypad '0'
+    (0x71, 0x53), // Keypad '.'
+    (0x61, 0x56), // Non-US '\'
+    (0x78, 0x57), // F11
+    (0x07, 0x58), // F12
+    (0x1F, 0x5B), // Left GUI (extended)
+    (0x27, 0x5C), // Right GUI (extended)
+    (0x2F, 0x5D), // Menu (extended)
+    (0x84, 0x54), // SysRq (Alt+Print Screen)
+];
+
+/// Set 2 make code to Set 1 make code, 0 for unused codes. Extended (`0xE0`) codes translate the same way.
+static SET2_TO_SET1: [u8; 0x85] = {
+    let mut table = [0; 0x85];
+    let mut i = 0;
+    while i < SET2_PAIRS.len() {
+        table[SET2_PAIRS[i].0 as usize] = SET2_PAIRS[i].1;
+        i += 1;
+    }
+    table
+};
+
+/// Converts a Set 2 make code to the Set 1 make code of the same key, if it has one.
+pub fn set2_to_set1(code: u8) -> Option<u8> {
+    SET2_TO_SET1
+        .get(code as usize)
+        .copied()
+        .filter(|&code| code != 0)
+}
+
+/// A key press or release decoded from the scancode stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawKeyEvent {
+    /// Set 1 make code of the key, whatever set was decoded.
+    pub code: u8,
+    /// Whether the code was prefixed with `0xE0`.
+    pub extended: bool,
+    /// `true` for a press (or typematic repeat), `false` for a release.
+    pub pressed: bool,
+}
+
+impl RawKeyEvent {
+    /// Returns the key, if the code names one.
+    pub fn key_code(&self) -> Option<KeyCode> {
+        KeyCode::from_set1(self.code, self.extended)
+    }
+}
+
+/// Sent by a keyboard that passed its Basic Assurance Test, after power-on, a reset or being plugged in.
+pub const BAT_OK: u8 = 0xAA;
+/// Sent by a keyboard that failed its Basic Assurance Test.
+pub const BAT_FAILED: u8 = 0xFC;
+
+/// Stateful decoder for the byte stream of a [`ScancodeSet`].
+///
+/// # Example
+/// ```ignore
+/// let mut decoder = Decoder::new(ScancodeSet::Set2);
+/// if let Some(event) = decoder.feed(byte) {
+///     handle(event.key_code(), event.pressed);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    set: ScancodeSet,
+    extended: bool,
+    release: bool,
+    /// Bytes of a Pause sequence still to skip.
+    skip: u8,
+}
+
+impl Decoder {
+    /// Creates a decoder for `set`.
+    pub const fn new(set: ScancodeSet) -> Self {
+        Decoder {
+            set,
+            extended: false,
+            release: false,
+            skip: 0,
+        }
+    }
+
+    /// Returns the set being decoded.
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    /// Switches to decoding `set`, dropping any partial sequence.
+    pub fn set_set(&mut self, set: ScancodeSet) {
+        *self = Decoder::new(set);
+    }
+
+    /// Returns whether the decoder is between sequences, with no prefix byte pending.
+    pub fn is_idle(&self) -> bool {
+        !self.extended && !self.release && self.skip == 0
+    }
+
+    /// Feeds one byte from the keyboard. Returns an event once a key press or release is complete.
+    ///
+    /// Pause (which sends no release) and the fake Shift codes some keyboards wrap around extended keys produce no event.
+    pub fn feed(&mut self, byte: u8) -> Option<RawKeyEvent> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return None;
+        }
+        match (self.set, byte) {
+            (_, 0xE0) => {
+                self.extended = true;
+                return None;
+            }
+            (ScancodeSet::Set1, 0xE1) => {
+                self.skip = 5;
+                return None;
+            }
+            (ScancodeSet::Set2, 0xE1) => {
+                self.skip = 7;
+                return None;
+            }
+            (ScancodeSet::Set2, 0xF0) => {
+                self.release = true;
+                return None;
+            }
+            _ => {}
+        }
+        let extended = core::mem::take(&mut self.extended);
+        let release = core::mem::take(&mut self.release);
+        let (code, pressed) = match self.set {
+            ScancodeSet::Set1 => (byte & 0x7F, byte & 0x80 == 0),
+            ScancodeSet::Set2 => (set2_to_set1(byte)?, !release),
+        };
+        // Fake Shifts around extended keys (Print Screen, keypad with Num Lock)
+        if extended && matches!(code, 0x2A | 0x36) {
+            return None;
+        }
+        Some(RawKeyEvent {
+            code,
+            extended,
+            pressed,
+        })
+    }
+}
+
+/// State of the modifier and lock keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub left_alt: bool,
+    pub right_alt: bool,
+    /// Toggled by each press of Caps Lock.
+    pub caps_lock: bool,
+    /// Toggled by each press of Num Lock.
+    pub num_lock: bool,
+}
+
+impl Modifiers {
+    /// Returns whether either Shift key is held.
+    pub fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    /// Returns whether either Ctrl key is held.
+    pub fn ctrl(&self) -> bool {
+        self.left_ctrl || self.right_ctrl
+    }
+
+    /// Returns whether either Alt key is held.
+    pub fn alt(&self) -> bool {
+        self.left_alt || self.right_alt
+    }
+
+    /// Updates the state for a press or release of `code`. Returns whether it was a modifier or lock key.
+    ///
+    /// Lock keys toggle on press only, so typematic repeats of a held lock key toggle again, as on real hardware.
+    pub fn update(&mut self, code: KeyCode, pressed: bool) -> bool {
+        match code {
+            KeyCode::LeftShift => self.left_shift = pressed,
+            KeyCode::RightShift => self.right_shift = pressed,
+            KeyCode::LeftCtrl => self.left_ctrl = pressed,
+            KeyCode::RightCtrl => self.right_ctrl = pressed,
+            KeyCode::LeftAlt => self.left_alt = pressed,
+            KeyCode::RightAlt => self.right_alt = pressed,
+            KeyCode::CapsLock => self.caps_lock ^= pressed,
+            KeyCode::NumLock => self.num_lock ^= pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Applies the modifiers to the unshifted ASCII byte of a key (US QWERTY).
+    ///
+    /// - Letters are lowercase unless exactly one of Shift and Caps Lock is active.
+    /// - Shift selects the upper symbol of digit and punctuation keys (`'1'` becomes `'!'`).
+    /// - Ctrl turns letters into control codes (`Ctrl-C` is `0x03`).
+    pub fn apply(&self, ascii: u8) -> u8 {
+        if ascii.is_ascii_alphabetic() {
+            if self.ctrl() {
+                return ascii.to_ascii_uppercase() - b'A' + 1;
+            }
+            return if self.shift() != self.caps_lock {
+                ascii.to_ascii_uppercase()
+            } else {
+                ascii.to_ascii_lowercase()
+            };
+        }
+        if !self.shift() {
+            return ascii;
+        }
+        match ascii {
+            b'1' => b'!',
+            b'2' => b'@',
+            b'3' => b'#',
+            b'4' => b'$',
+            b'5' => b'%',
+            b'6' => b'^',
+            b'7' => b'&',
+            b'8' => b'*',
+            b'9' => b'(',
+            b'0' => b')',
+            b'-' => b'_',
+            b'=' => b'+',
+            b'[' => b'{',
+            b']' => b'}',
+            b';' => b':',
+            b'\'' => b'"',
+            b'`' => b'~',
+            b'\\' => b'|',
+            b',' => b'<',
+            b'.' => b'>',
+            b'/' => b'?',
+            other => other,
+        }
+    }
+}
+
+/// Scancode decoder that also tracks the modifier keys.
+///
+/// # Example
+/// ```ignore
+/// let mut keyboard = Keyboard::new(ScancodeSet::Set2);
+/// if let Some(event) = keyboard.feed(byte) {
+///     match event.code {
+///         KeyCode::ArrowUp if event.is_pressed() => history.previous(),
+///         _ => {
+///             if let Some(ascii) = event.ascii() {
+///                 shell.input(ascii);
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    decoder: Decoder,
+    modifiers: Modifiers,
+    held: KeyBitmap,
+}
+
+impl Keyboard {
+    /// Creates a keyboard decoding `set`, with no modifiers active.
+    pub const fn new(set: ScancodeSet) -> Self {
+        Keyboard {
+            decoder: Decoder::new(set),
+            modifiers: Modifiers {
+                left_shift: false,
+                right_shift: false,
+                left_ctrl: false,
+                right_ctrl: false,
+                left_alt: false,
+                right_alt: false,
+                caps_lock: false,
+                num_lock: false,
+            },
+            held: KeyBitmap::new(),
+        }
+    }
+
+    /// Returns the set being decoded.
+    pub fn set(&self) -> ScancodeSet {
+        self.decoder.set()
+    }
+
+    /// Switches to decoding `set`, dropping any partial sequence. The modifier state is kept.
+    pub fn set_set(&mut self, set: ScancodeSet) {
+        self.decoder.set_set(set);
+    }
+
+    /// Returns the current modifier state.
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Returns the keys currently held down.
+    pub fn held(&self) -> KeyBitmap {
+        self.held
+    }
+
+    /// Returns whether `code` is held down.
+    pub fn is_pressed(&self, code: KeyCode) -> bool {
+        self.held.is_pressed(code)
+    }
+
+    /// Returns whether `byte`, about to be fed, is the [`BAT_OK`] code of a keyboard that was plugged in or reset rather than a key.
+    ///
+    /// In Set 2 no key sends `0xAA`. In Set 1 it is also the release of Left Shift, so it only counts as a reset while Left Shift is up and no sequence is pending.
+    pub fn is_reset(&self, byte: u8) -> bool {
+        byte == BAT_OK
+            && self.decoder.is_idle()
+            && (self.decoder.set() == ScancodeSet::Set2 || !self.modifiers.left_shift)
+    }
+
+    /// Forgets held keys and any partial sequence after the keyboard was reset, switching to `set`. Caps Lock and Num Lock are kept so they can be restored on the keyboard.
+    pub fn reset(&mut self, set: ScancodeSet) {
+        self.decoder.set_set(set);
+        self.held.clear();
+        self.modifiers = Modifiers {
+            caps_lock: self.modifiers.caps_lock,
+            num_lock: self.modifiers.num_lock,
+            ..Modifiers::default()
+        };
+    }
+
+    /// Feeds one byte from the keyboard, updating the modifiers and held keys. Returns the key event once a press or release of a known key is complete.
+    pub fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        self.feed_raw(byte).map(|(_, event)| event)
+    }
+
+    /// Like [`feed`](Self::feed), but also returns the decoded scancode.
+    pub fn feed_raw(&mut self, byte: u8) -> Option<(RawKeyEvent, KeyEvent)> {
+        let raw = self.decoder.feed(byte)?;
+        let code = raw.key_code()?;
+        self.modifiers.update(code, raw.pressed);
+        self.held.set(code, raw.pressed);
+        let state = if raw.pressed {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        };
+        Some((
+            raw,
+            KeyEvent {
+                code,
+                state,
+                modifiers: self.modifiers,
+            },
+        ))
+    }
+}
@@ -9,6 +9,9 @@
 ///
 /// # QEMU Usage
 /// QEMU will display all output sent to the serial port (0x3F8) if run with `-serial stdio`.
+///
+/// Output goes to the UART selected with [`crate::set_uart_backend`] (COM1 unless changed).
+/// Anything printed before [`crate::init_logging`] is buffered and replayed once logging is initialized.
 #[macro_export]
 macro_rules! kprint {
     ($($args:tt)*) => ({
@@ -23,11 +26,9 @@ macro_rules! kprint {
 pub struct DebugSerial;
 
 impl core::fmt::Write for DebugSerial {
-    /// Writes a string to the serial port, byte by byte.
+    /// Writes a string to the serial port and registered sinks (or the early-boot buffer).
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        for b in s.bytes() {
-            Self::put_byte(b);
-        }
+        crate::sink::write_bytes(s.as_bytes());
         Ok(())
     }
 }
@@ -36,29 +37,13 @@ impl DebugSerial {
     /// Reads a byte from the serial port if available.
     ///
     /// Returns `Some(u8)` if a byte is ready, or `None` otherwise.
-    ///
-    /// # Safety
-    /// Uses inline assembly to access the port directly.
     pub fn get_byte() -> Option<u8> {
-        #[allow(unused_assignments)]
-        let mut byte = 0;
-        unsafe {
-            core::arch::asm!("in al, dx", out("al") byte, in("dx") 0x3f8 + 5);
-            if byte & 0x01 != 0 {
-                core::arch::asm!("in al, dx", out("al") byte, in("dx") 0x3f8);
-                Some(byte)
-            } else {
-                None
-            }
-        }
+        crate::uart_backend().try_read_byte()
     }
-    /// Writes a byte directly to the serial port (0x3F8).
+    /// Writes a byte directly to the serial port (0x3F8 or the selected MMIO UART).
     ///
-    /// # Safety
-    /// Uses inline assembly for direct port output.
+    /// This bypasses the early-boot buffer and registered sinks.
     pub fn put_byte(b: u8) {
-        unsafe {
-            core::arch::asm!("out dx, al", in("al") b, in("dx") 0x3f8 );
-        }
+        crate::uart_backend().write_byte(b);
     }
 }
@@ -0,0 +1,93 @@
+//! # Log Levels and Compile-Time Filtering
+//!
+//! Every leveled logging macro (`error!`, `warn!`, `info!`, `debug!`) and function (`error`, `warn`, `info`, `info_hex`) checks its level against [`STATIC_MAX_LEVEL`] before doing any work. Because that check is a comparison between two constants, the compiler removes disabled log statements entirely in optimized builds, including their format strings and argument formatting code.
+//!
+//! ## Cargo Features
+//!
+//! The maximum level is chosen with cargo features on this crate:
+//!
+//! - `max-level-off`, `max-level-error`, `max-level-warn`, `max-level-info`, `max-level-debug`: apply to all builds.
+//! - `release-max-level-off`, `release-max-level-error`, ...: apply only to builds without `debug_assertions` (i.e. `--release`).
+//!
+//! If several features are enabled (for example by different crates in the dependency graph), the most restrictive one wins. Without any of them, every level is enabled.
+//!
+//! ```toml
+//! [dependencies]
+//! polished_serial_logging = { path = "../serial_logging", features = ["release-max-level-warn"] }
+//! ```
+//!
+//! `kprint!` and the raw `serial_write_*` functions are not leveled and are never compiled out.
+
+/// Severity of a log message, from most to least severe.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Something failed.
+    Error = 1,
+    /// Something unexpected happened, but execution can continue.
+    Warn = 2,
+    /// Normal progress messages.
+    Info = 3,
+    /// Verbose output for debugging.
+    Debug = 4,
+}
+
+impl Level {
+    /// Returns the prefix printed in front of messages of this level (e.g. `"[INFO] "`).
+    pub const fn prefix(&self) -> &'static str {
+        match self {
+            Level::Error => "[ERROR] ",
+            Level::Warn => "[WARNING] ",
+            Level::Info => "[INFO] ",
+            Level::Debug => "[DEBUG] ",
+        }
+    }
+}
+
+/// The most verbose level that may be logged, or `Off` to disable leveled logging.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LevelFilter {
+    /// No leveled messages are logged.
+    Off = 0,
+    /// Only errors are logged.
+    Error = 1,
+    /// Errors and warnings are logged.
+    Warn = 2,
+    /// Errors, warnings, and info messages are logged.
+    Info = 3,
+    /// Everything is logged.
+    Debug = 4,
+}
+
+/// The maximum log level selected by this crate's `max-level-*` / `release-max-level-*` features.
+pub const STATIC_MAX_LEVEL: LevelFilter = static_max_level();
+
+const fn static_max_level() -> LevelFilter {
+    let release = !cfg!(debug_assertions);
+    if cfg!(feature = "max-level-off") || (release && cfg!(feature = "release-max-level-off")) {
+        LevelFilter::Off
+    } else if cfg!(feature = "max-level-error")
+        || (release && cfg!(feature = "release-max-level-error"))
+    {
+        LevelFilter::Error
+    } else if cfg!(feature = "max-level-warn")
+        || (release && cfg!(feature = "release-max-level-warn"))
+    {
+        LevelFilter::Warn
+    } else if cfg!(feature = "max-level-info")
+        || (release && cfg!(feature = "release-max-level-info"))
+    {
+        LevelFilter::Info
+    } else {
+        LevelFilter::Debug
+    }
+}
+
+/// Returns whether messages of `level` survive compile-time filtering.
+///
+/// This is a `const fn` so that `if level_enabled(...)` around a log statement is folded away by the compiler.
+#[inline(always)]
+pub const fn level_enabled(level: Level) -> bool {
+    level as u8 <= STATIC_MAX_LEVEL as u8
+}
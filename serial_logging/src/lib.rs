@@ -8,15 +8,19 @@
 //!
 //! ## When to Use `serial_log!`/`serial_print!` vs `kprint!`
 //!
-//! - Use `serial_log!`, `serial_print!`, and related macros for most kernel logging. These use a full-featured serial driver (with buffering, formatting, and thread safety) and are suitable once the kernel is initialized.
-//! - Use `kprint!` for minimal, dependency-free output in very early boot stages, or in environments where only core formatting is available. `kprint!` writes directly to the serial port using inline assembly, bypassing all higher-level abstractions.
+//! - Use `serial_log!`, `serial_print!`, and related macros for most kernel logging. `serial_print!` holds a lock for the whole formatted message, so messages from different CPUs do not interleave.
+//! - Use `kprint!` for minimal, dependency-free output in very early boot stages, or in environments where only core formatting is available. `kprint!` writes directly to the serial port's registers, bypassing all higher-level abstractions.
 //!
 //! ## Features
 //! - Thread-safe, formatted serial output via `serial_print!`, `serial_log!`, etc.
-//! - Log level macros for info, warning, and error messages.
+//! - Log level macros for debug, info, warning, and error messages.
+//! - Compile-time maximum log level via `max-level-*` cargo features (see [`level`]).
 //! - Hexadecimal logging support.
 //! - Minimal `kprint!` macro for direct serial output.
 //! - Enable/disable logging at runtime.
+//! - Port I/O or memory-mapped 16550 UARTs via [`uart::UartBackend`].
+//! - Configurable baud rate, parity, and stop bits via [`configure_serial`].
+//! - Early-boot buffering: output produced before [`init_logging`] is kept and replayed, along with any extra sinks registered via [`register_sink`] (see [`sink`]).
 //!
 //! ## Example (QEMU):
 //!
@@ -33,22 +37,23 @@
 
 #![no_std]
 
-use lazy_static::lazy_static;
 use spin::Mutex;
-use uart_16550::SerialPort;
-use x86_64::instructions::port::Port;
 
 pub mod kprint;
+pub mod level;
+pub mod sink;
+pub mod uart;
 
 pub use crate::kprint::DebugSerial;
+pub use crate::level::{Level, LevelFilter, STATIC_MAX_LEVEL, level_enabled};
+pub use crate::sink::{LogSink, init_logging, is_logging_initialized, register_sink};
+pub use crate::uart::{
+    BaudRate, DataBits, LineConfig, Parity, StopBits, UartBackend, configure_serial, line_config,
+    set_uart_backend, uart_backend,
+};
 
-lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
-        serial_port.init();
-        Mutex::new(serial_port)
-    };
-}
+/// Serializes `serial_print!` calls, so the output of two CPUs does not interleave.
+static PRINT_LOCK: Mutex<()> = Mutex::new(());
 
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
@@ -56,21 +61,19 @@ pub fn _print(args: ::core::fmt::Arguments) {
     use x86_64::instructions::interrupts;
 
     interrupts::without_interrupts(|| {
-        SERIAL1
-            .lock()
-            .write_fmt(args)
-            .expect("Printing to serial failed");
+        let _print = PRINT_LOCK.lock();
+        let _ = DebugSerial.write_fmt(args);
     });
 }
 
-/// Prints to the host through the serial interface (COM1, 0x3F8).
+/// Prints to the host through the selected UART (COM1, 0x3F8, unless changed with [`set_uart_backend`]) and the registered sinks.
 ///
-/// This macro uses the main serial driver, which is thread-safe and supports formatting.
+/// Unlike `kprint!`, a whole `serial_print!` is written under a lock, so concurrent prints do not interleave.
 /// Output will appear in QEMU's terminal if run with `-serial stdio`.
 #[macro_export]
 macro_rules! serial_print {
     ($($arg:tt)*) => {
-        $crate::serial::_print(format_args!($($arg)*));
+        $crate::_print(format_args!($($arg)*));
     };
 }
 
@@ -139,8 +142,6 @@ macro_rules! serial_log_hex {
     };
 }
 
-const SERIAL_PORT: u16 = 0x3F8; // COM1
-
 static mut LOGGING_ENABLED: bool = true;
 
 /// Enables serial logging output.
@@ -168,9 +169,12 @@ pub fn is_serial_logging_enabled() -> bool {
     unsafe { LOGGING_ENABLED }
 }
 
-/// Writes a single byte to the serial port (COM1, 0x3F8).
+/// Writes a single byte to the selected UART (COM1, 0x3F8 by default).
 ///
 /// Blocks until the port is ready to accept a byte. Used internally by all higher-level output functions.
+/// See [`set_uart_backend`] to use a memory-mapped UART instead.
+///
+/// Before [`init_logging`] runs, the byte is buffered instead and replayed later.
 ///
 /// # QEMU
 /// Output will appear in the QEMU terminal if run with `-serial stdio`.
@@ -178,12 +182,7 @@ pub fn serial_write_byte(byte: u8) {
     if !is_serial_logging_enabled() {
         return;
     }
-    unsafe {
-        let mut line_status = Port::<u8>::new(SERIAL_PORT + 5);
-        while (line_status.read() & 0x20) == 0 {}
-        let mut data = Port::new(SERIAL_PORT);
-        data.write(byte);
-    }
+    sink::write_bytes(&[byte]);
 }
 
 /// Writes a string to the serial port, byte by byte.
@@ -193,9 +192,7 @@ pub fn serial_write_str(s: &str) {
     if !is_serial_logging_enabled() {
         return;
     }
-    for byte in s.bytes() {
-        serial_write_byte(byte);
-    }
+    sink::write_bytes(s.as_bytes());
 }
 
 /// Writes a hexadecimal representation of a `u64` value to the serial port.
@@ -226,55 +223,64 @@ pub fn serial_write_hex(mut value: u64) {
 
 /// Logs an info-level message to the serial port.
 ///
-/// Equivalent to `serial_log!("[INFO] ", ...)`.
+/// Equivalent to `serial_log!(Level::Info.prefix(), ...)`.
 ///
 /// # Examples
 /// ```
 /// serial::info("System started");
 /// ```
 pub fn info(text: &str) {
-    serial_log!("[INFO] ", "{}", text);
+    if level_enabled(Level::Info) {
+        serial_log!(Level::Info.prefix(), "{}", text);
+    }
 }
 
 /// Logs an info-level hexadecimal value to the serial port.
 ///
-/// Equivalent to `serial_log_hex!("[INFO] ", value)`.
+/// Equivalent to `serial_log_hex!(Level::Info.prefix(), value)`.
 ///
 /// # Examples
 /// ```
 /// serial::info_hex(0xdeadbeef);
 /// ```
 pub fn info_hex(value: u64) {
-    serial_log_hex!("[INFO] ", value);
+    if level_enabled(Level::Info) {
+        serial_log_hex!(Level::Info.prefix(), value);
+    }
 }
 
 /// Logs an error-level message to the serial port.
 ///
-/// Equivalent to `serial_log!("[ERROR] ", ...)`.
+/// Equivalent to `serial_log!(Level::Error.prefix(), ...)`.
 ///
 /// # Examples
 /// ```
 /// serial::error("An error occurred");
 /// ```
 pub fn error(text: &str) {
-    serial_log!("[ERROR] ", "{}", text);
+    if level_enabled(Level::Error) {
+        serial_log!(Level::Error.prefix(), "{}", text);
+    }
 }
 
 /// Logs a warning-level message to the serial port.
 ///
-/// Equivalent to `serial_log!("[WARNING] ", ...)`.
+/// Equivalent to `serial_log!(Level::Warn.prefix(), ...)`.
 ///
 /// # Examples
 /// ```
 /// serial::warn("Low disk space");
 /// ```
 pub fn warn(text: &str) {
-    serial_log!("[WARNING] ", "{}", text);
+    if level_enabled(Level::Warn) {
+        serial_log!(Level::Warn.prefix(), "{}", text);
+    }
 }
 
 /// Logs an error-level message to the serial port with formatting support.
 ///
-/// Equivalent to `serial_log!("[ERROR] ", ...)` but as a macro for formatting.
+/// Equivalent to `serial_log!(Level::Error.prefix(), ...)` but as a macro for formatting.
+/// Compiled out when the `max-level-off` feature is enabled.
 ///
 /// # Examples
 /// ```
@@ -283,9 +289,82 @@ pub fn warn(text: &str) {
 #[macro_export]
 macro_rules! error {
     ($fmt:expr, $($arg:tt)*) => {
-        $crate::serial_log!("[ERROR] ", $fmt, $($arg)*);
+        if $crate::level_enabled($crate::Level::Error) {
+            $crate::serial_log!($crate::Level::Error.prefix(), $fmt, $($arg)*);
+        }
+    };
+    ($msg:expr) => {
+        if $crate::level_enabled($crate::Level::Error) {
+            $crate::serial_log!($crate::Level::Error.prefix(), $msg);
+        }
+    };
+}
+
+/// Logs a warning-level message to the serial port with formatting support.
+///
+/// Compiled out when the maximum level is `error` or lower.
+///
+/// # Examples
+/// ```no_run
+/// # use polished_serial_logging::warn;
+/// warn!("Retrying command {:#x}", 0xF4);
+/// ```
+#[macro_export]
+macro_rules! warn {
+    ($fmt:expr, $($arg:tt)*) => {
+        if $crate::level_enabled($crate::Level::Warn) {
+            $crate::serial_log!($crate::Level::Warn.prefix(), $fmt, $($arg)*);
+        }
+    };
+    ($msg:expr) => {
+        if $crate::level_enabled($crate::Level::Warn) {
+            $crate::serial_log!($crate::Level::Warn.prefix(), $msg);
+        }
+    };
+}
+
+/// Logs an info-level message to the serial port with formatting support.
+///
+/// Compiled out when the maximum level is `warn` or lower.
+///
+/// # Examples
+/// ```no_run
+/// # use polished_serial_logging::info;
+/// info!("Heap size: {} bytes", 0x100_0000);
+/// ```
+#[macro_export]
+macro_rules! info {
+    ($fmt:expr, $($arg:tt)*) => {
+        if $crate::level_enabled($crate::Level::Info) {
+            $crate::serial_log!($crate::Level::Info.prefix(), $fmt, $($arg)*);
+        }
+    };
+    ($msg:expr) => {
+        if $crate::level_enabled($crate::Level::Info) {
+            $crate::serial_log!($crate::Level::Info.prefix(), $msg);
+        }
+    };
+}
+
+/// Logs a debug-level message to the serial port with formatting support.
+///
+/// Compiled out when the maximum level is `info` or lower.
+///
+/// # Examples
+/// ```no_run
+/// # use polished_serial_logging::debug;
+/// debug!("Scancode: {:#x}", 0x1E);
+/// ```
+#[macro_export]
+macro_rules! debug {
+    ($fmt:expr, $($arg:tt)*) => {
+        if $crate::level_enabled($crate::Level::Debug) {
+            $crate::serial_log!($crate::Level::Debug.prefix(), $fmt, $($arg)*);
+        }
     };
     ($msg:expr) => {
-        $crate::serial_log!("[ERROR] ", $msg);
+        if $crate::level_enabled($crate::Level::Debug) {
+            $crate::serial_log!($crate::Level::Debug.prefix(), $msg);
+        }
     };
 }
@@ -0,0 +1,126 @@
+//! # Log Sinks and Early-Boot Buffering
+//!
+//! All text written through `kprint!`, `serial_write_str`, `serial_write_byte`, and the leveled logging macros ends up in [`write_bytes`]. What happens there depends on whether [`init_logging`] has run yet:
+//!
+//! - **Before `init_logging()`:** Output is appended to a fixed-size static buffer ([`EARLY_BUFFER_SIZE`] bytes). Nothing touches the UART, which may not be configured (or even selected, see [`crate::set_uart_backend`]) yet. If the buffer fills up, further bytes are counted and dropped.
+//! - **`init_logging()`:** Initializes the selected UART, closes the buffer, waits for writers still copying into it, then replays the buffered bytes into the UART and every registered sink, so no early message is lost. A writer that reserves space after the buffer is closed writes straight to the UART and sinks instead.
+//! - **After `init_logging()`:** Output goes straight to the UART and to every registered sink.
+//!
+//! ## Sinks
+//!
+//! A sink is a plain function receiving raw output bytes, for example a framebuffer console. Up to [`MAX_SINKS`] sinks can be registered with [`register_sink`]. Sinks are stored in atomics rather than behind a lock, so logging from an interrupt handler can never deadlock on the sink table. Sinks registered before `init_logging()` also receive the replayed early output.
+
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// A function receiving raw log output bytes.
+pub type LogSink = fn(&[u8]);
+
+/// Capacity of the early-boot buffer in bytes.
+pub const EARLY_BUFFER_SIZE: usize = 4096;
+
+/// Maximum number of registered sinks (in addition to the UART).
+pub const MAX_SINKS: usize = 4;
+
+static LOGGING_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+static mut EARLY_BUFFER: [u8; EARLY_BUFFER_SIZE] = [0; EARLY_BUFFER_SIZE];
+/// Number of bytes reserved in `EARLY_BUFFER` (may exceed the capacity if output was dropped), plus [`EARLY_CLOSED`] once [`init_logging`] replays it.
+static EARLY_LEN: AtomicUsize = AtomicUsize::new(0);
+/// Set in `EARLY_LEN` when the buffer is closed; reservations carrying it are not replayed.
+const EARLY_CLOSED: usize = 1 << (usize::BITS - 1);
+/// Number of reserved bytes whose writers are done with the buffer (copied or dropped).
+static EARLY_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+static SINKS: [AtomicPtr<()>; MAX_SINKS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_SINKS];
+
+/// Registers an additional output sink.
+///
+/// Returns `false` if all [`MAX_SINKS`] slots are taken.
+///
+/// # Example
+/// ```ignore
+/// fn console_sink(bytes: &[u8]) { /* draw to the framebuffer */ }
+/// register_sink(console_sink);
+/// ```
+pub fn register_sink(sink: LogSink) -> bool {
+    let ptr = sink as *mut ();
+    SINKS.iter().any(|slot| {
+        slot.compare_exchange(
+            core::ptr::null_mut(),
+            ptr,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_ok()
+    })
+}
+
+/// Initializes the selected UART and replays all output buffered since boot.
+///
+/// Call this once, as early as possible (after [`crate::set_uart_backend`] if a non-default UART is used). Calling it again has no effect.
+/// It waits for writers that reserved buffer space but have not copied their bytes yet, so it must not interrupt one of them on the same CPU.
+pub fn init_logging() {
+    if LOGGING_INITIALIZED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    crate::uart_backend().init_with(crate::uart::line_config());
+
+    let reserved = EARLY_LEN.fetch_or(EARLY_CLOSED, Ordering::AcqRel);
+    while EARLY_WRITTEN.load(Ordering::Acquire) != reserved {
+        core::hint::spin_loop();
+    }
+    let len = reserved.min(EARLY_BUFFER_SIZE);
+    // Safety: the buffer is closed and every writer that reserved part of it is done.
+    let early = unsafe { core::slice::from_raw_parts(&raw const EARLY_BUFFER as *const u8, len) };
+    emit(early);
+    if reserved > len {
+        crate::warn!(
+            "Early log buffer overflowed, {} bytes dropped",
+            reserved - len
+        );
+    }
+}
+
+/// Returns whether [`init_logging`] has run.
+pub fn is_logging_initialized() -> bool {
+    LOGGING_INITIALIZED.load(Ordering::Acquire)
+}
+
+/// Writes raw output bytes to the UART and all sinks, or to the early buffer before [`init_logging`].
+pub fn write_bytes(bytes: &[u8]) {
+    if is_logging_initialized() {
+        emit(bytes);
+        return;
+    }
+    let start = EARLY_LEN.fetch_add(bytes.len(), Ordering::AcqRel);
+    if start & EARLY_CLOSED != 0 {
+        // `init_logging` closed the buffer after the check above and will not replay this
+        emit(bytes);
+        return;
+    }
+    if start < EARLY_BUFFER_SIZE {
+        let count = bytes.len().min(EARLY_BUFFER_SIZE - start);
+        // Safety: the range [start, start + count) was reserved exclusively by the fetch_add above.
+        unsafe {
+            let dest = (&raw mut EARLY_BUFFER as *mut u8).add(start);
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), dest, count);
+        }
+    }
+    EARLY_WRITTEN.fetch_add(bytes.len(), Ordering::AcqRel);
+}
+
+fn emit(bytes: &[u8]) {
+    let uart = crate::uart_backend();
+    for &byte in bytes {
+        uart.write_byte(byte);
+    }
+    for slot in SINKS.iter() {
+        let ptr = slot.load(Ordering::Acquire);
+        if !ptr.is_null() {
+            // Safety: only `LogSink` function pointers are ever stored in the table.
+            let sink: LogSink = unsafe { core::mem::transmute::<*mut (), LogSink>(ptr) };
+            sink(bytes);
+        }
+    }
+}
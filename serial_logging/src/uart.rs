@@ -0,0 +1,335 @@
+//! # 16550 UART Backends
+//!
+//! This module describes *where* the debug UART lives and how to talk to its registers. Two access methods are supported:
+//!
+//! - **Port I/O:** The classic PC layout, where the UART registers sit at consecutive I/O ports (COM1 is at 0x3F8). This is what QEMU's `-serial stdio` uses on the PC and q35 machines.
+//! - **Memory-mapped I/O (MMIO):** Many platforms and virtual machines (and some PCIe serial cards) expose a 16550-compatible UART as a block of memory instead. The registers are placed at `base + register * stride`, where the stride is usually 1 or 4 bytes.
+//!
+//! All serial output (`serial_print!`, `serial_write_byte`, `kprint!`, and friends) goes through the currently selected [`UartBackend`], so switching to an MMIO UART is a single call to [`set_uart_backend`] early in boot.
+//!
+//! ## Register Layout
+//!
+//! Both backends address the same eight 16550 registers; only the way each register is reached differs. See [OSDev.org Serial Ports](https://wiki.osdev.org/Serial_Ports) for details on each register.
+//!
+//! ## Line Configuration
+//!
+//! The baud rate is set by writing a 16-bit *divisor* of the UART's 115200 Hz base clock into the divisor latch (registers 0 and 1 while the DLAB bit of the line control register is set). Parity, stop bits, and word length live in the line control register itself. [`LineConfig`] bundles these settings; the default is 38400/8-N-1, but some physical debug headers only run reliably at lower rates, so [`configure_serial`] lets the kernel pick another one. The choice is remembered ([`line_config`]) and applied whenever the UART is initialized later.
+
+use polished_x86_commands::port::Port;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// Receive/transmit buffer register (or divisor latch low byte when DLAB is set).
+pub const REG_DATA: u8 = 0;
+/// Interrupt enable register (or divisor latch high byte when DLAB is set).
+pub const REG_INT_ENABLE: u8 = 1;
+/// FIFO control register (write only).
+pub const REG_FIFO_CTRL: u8 = 2;
+/// Line control register (data bits, parity, stop bits, DLAB).
+pub const REG_LINE_CTRL: u8 = 3;
+/// Modem control register (DTR, RTS, OUT2).
+pub const REG_MODEM_CTRL: u8 = 4;
+/// Line status register (data ready, transmitter empty, errors).
+pub const REG_LINE_STATUS: u8 = 5;
+
+/// Line control bit: divisor latch access (DLAB).
+const LCR_DLAB: u8 = 0x80;
+
+/// Line status bit: a received byte is waiting in the data register.
+const LSR_DATA_READY: u8 = 0x01;
+/// Line status bit: the transmit holding register is empty.
+const LSR_THR_EMPTY: u8 = 0x20;
+
+/// Supported baud rates for the raw serial path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaudRate {
+    /// 115200 baud (divisor 1).
+    Baud115200,
+    /// 57600 baud (divisor 2).
+    Baud57600,
+    /// 38400 baud (divisor 3), the default.
+    Baud38400,
+    /// 19200 baud (divisor 6).
+    Baud19200,
+    /// 9600 baud (divisor 12).
+    Baud9600,
+}
+
+impl BaudRate {
+    /// Returns the divisor latch value for this baud rate (115200 / baud).
+    pub fn divisor(&self) -> u16 {
+        match self {
+            BaudRate::Baud115200 => 1,
+            BaudRate::Baud57600 => 2,
+            BaudRate::Baud38400 => 3,
+            BaudRate::Baud19200 => 6,
+            BaudRate::Baud9600 => 12,
+        }
+    }
+}
+
+/// Parity mode (line control bits 3-5).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    /// No parity bit.
+    None,
+    /// Odd parity.
+    Odd,
+    /// Even parity.
+    Even,
+    /// Parity bit always 1.
+    Mark,
+    /// Parity bit always 0.
+    Space,
+}
+
+/// Number of stop bits (line control bit 2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    /// One stop bit.
+    One,
+    /// Two stop bits (1.5 for 5-bit words).
+    Two,
+}
+
+/// Number of data bits per character (line control bits 0-1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataBits {
+    /// 5-bit characters.
+    Five,
+    /// 6-bit characters.
+    Six,
+    /// 7-bit characters.
+    Seven,
+    /// 8-bit characters.
+    Eight,
+}
+
+/// Baud rate and framing for a 16550 UART.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineConfig {
+    /// Transmission speed.
+    pub baud: BaudRate,
+    /// Character width.
+    pub data_bits: DataBits,
+    /// Parity mode.
+    pub parity: Parity,
+    /// Stop bits.
+    pub stop_bits: StopBits,
+}
+
+impl LineConfig {
+    /// Creates an 8-bit, no parity, one stop bit configuration at the given baud rate.
+    pub const fn new_8n1(baud: BaudRate) -> Self {
+        LineConfig {
+            baud,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+
+    /// Encodes the framing settings as a line control register value (with DLAB clear).
+    pub fn line_control(&self) -> u8 {
+        let data = match self.data_bits {
+            DataBits::Five => 0b00,
+            DataBits::Six => 0b01,
+            DataBits::Seven => 0b10,
+            DataBits::Eight => 0b11,
+        };
+        let stop = match self.stop_bits {
+            StopBits::One => 0,
+            StopBits::Two => 1 << 2,
+        };
+        let parity = match self.parity {
+            Parity::None => 0b000 << 3,
+            Parity::Odd => 0b001 << 3,
+            Parity::Even => 0b011 << 3,
+            Parity::Mark => 0b101 << 3,
+            Parity::Space => 0b111 << 3,
+        };
+        data | stop | parity
+    }
+}
+
+impl Default for LineConfig {
+    /// 38400/8-N-1.
+    fn default() -> Self {
+        LineConfig::new_8n1(BaudRate::Baud38400)
+    }
+}
+
+/// Describes how to reach the registers of a 16550-compatible UART.
+///
+/// The methods below drive the registers from safe code, so a backend can only be created by vouching for the device: with the `unsafe` constructors [`port_io`](Self::port_io) and [`mmio`](Self::mmio), or as [`UartBackend::COM1`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartBackend {
+    registers: Registers,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Registers {
+    /// Consecutive I/O ports starting at `base`.
+    PortIo { base: u16 },
+    /// Memory-mapped starting at `base`, `stride` bytes apart.
+    Mmio { base: usize, stride: usize },
+}
+
+impl UartBackend {
+    /// The legacy COM1 port (0x3F8), used by default.
+    pub const COM1: UartBackend = UartBackend {
+        registers: Registers::PortIo { base: 0x3F8 },
+    };
+
+    /// A UART whose registers are consecutive I/O ports starting at `base` (e.g. 0x3F8 for COM1).
+    ///
+    /// # Safety
+    /// `base` must be the first port of a real 16550-compatible UART.
+    pub const unsafe fn port_io(base: u16) -> Self {
+        UartBackend {
+            registers: Registers::PortIo { base },
+        }
+    }
+
+    /// A UART whose registers are memory-mapped starting at `base`, `stride` bytes apart (typically 1 or 4).
+    ///
+    /// # Safety
+    /// `base` must be the virtual address of register 0 of a real 16550-compatible UART, mapped uncached for as long as the backend is used.
+    pub const unsafe fn mmio(base: usize, stride: usize) -> Self {
+        UartBackend {
+            registers: Registers::Mmio { base, stride },
+        }
+    }
+
+    /// Reads a UART register.
+    ///
+    /// # Safety
+    /// `reg` must be one of the eight 16550 registers, and the access must not confuse the raw serial path (e.g. by leaving DLAB set).
+    pub unsafe fn read_reg(&self, reg: u8) -> u8 {
+        match self.registers {
+            Registers::PortIo { base } => unsafe { Port::<u8>::new(base + reg as u16).read() },
+            Registers::Mmio { base, stride } => {
+                let addr = base + reg as usize * stride;
+                unsafe { core::ptr::read_volatile(addr as *const u8) }
+            }
+        }
+    }
+
+    /// Writes a UART register.
+    ///
+    /// # Safety
+    /// `reg` must be one of the eight 16550 registers, and the access must not confuse the raw serial path (e.g. by leaving DLAB set).
+    pub unsafe fn write_reg(&self, reg: u8, value: u8) {
+        match self.registers {
+            Registers::PortIo { base } => unsafe {
+                Port::<u8>::new(base + reg as u16).write(value)
+            },
+            Registers::Mmio { base, stride } => {
+                let addr = base + reg as usize * stride;
+                unsafe { core::ptr::write_volatile(addr as *mut u8, value) }
+            }
+        }
+    }
+
+    /// Initializes the UART with the default 38400/8-N-1 configuration and FIFOs enabled.
+    pub fn init(&self) {
+        self.init_with(LineConfig::default());
+    }
+
+    /// Initializes the UART with the given line configuration and FIFOs enabled.
+    pub fn init_with(&self, config: LineConfig) {
+        unsafe {
+            // Disable interrupts
+            self.write_reg(REG_INT_ENABLE, 0x00);
+        }
+        self.set_line_config(config);
+        unsafe {
+            // Enable FIFO, clear TX/RX queues, 14-byte threshold
+            self.write_reg(REG_FIFO_CTRL, 0xC7);
+            // DTR, RTS and OUT2
+            self.write_reg(REG_MODEM_CTRL, 0x0B);
+        }
+    }
+
+    /// Programs the divisor latch and line control register.
+    ///
+    /// Waits for the transmitter to drain first, so bytes already queued are not garbled by the speed change.
+    pub fn set_line_config(&self, config: LineConfig) {
+        let divisor = config.baud.divisor();
+        unsafe {
+            while (self.read_reg(REG_LINE_STATUS) & LSR_THR_EMPTY) == 0 {}
+            // Enable DLAB so registers 0 and 1 address the divisor latch
+            self.write_reg(REG_LINE_CTRL, LCR_DLAB);
+            self.write_reg(REG_DATA, (divisor & 0xFF) as u8);
+            self.write_reg(REG_INT_ENABLE, (divisor >> 8) as u8);
+            // Disable DLAB and apply the framing settings
+            self.write_reg(REG_LINE_CTRL, config.line_control());
+        }
+    }
+
+    /// Writes a byte, blocking until the transmitter can accept it.
+    pub fn write_byte(&self, byte: u8) {
+        unsafe {
+            while (self.read_reg(REG_LINE_STATUS) & LSR_THR_EMPTY) == 0 {}
+            self.write_reg(REG_DATA, byte);
+        }
+    }
+
+    /// Reads a byte if one has been received.
+    ///
+    /// Returns `Some(u8)` if a byte is ready, or `None` otherwise.
+    pub fn try_read_byte(&self) -> Option<u8> {
+        unsafe {
+            if self.read_reg(REG_LINE_STATUS) & LSR_DATA_READY != 0 {
+                Some(self.read_reg(REG_DATA))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+static mut UART_BACKEND: UartBackend = UartBackend::COM1;
+
+/// Selects the UART used by the raw serial path (`serial_write_byte`, `kprint!`, ...).
+///
+/// Call this early in boot, before other CPUs or interrupt handlers start logging.
+///
+/// # Safety
+/// No other CPU or interrupt handler may be using the raw serial path at the same time.
+///
+/// # Example
+/// ```ignore
+/// unsafe { set_uart_backend(UartBackend::mmio(0xFEDC_9000, 4)) };
+/// ```
+pub unsafe fn set_uart_backend(backend: UartBackend) {
+    unsafe {
+        UART_BACKEND = backend;
+    }
+}
+
+/// Returns the UART backend currently used by the raw serial path.
+pub fn uart_backend() -> UartBackend {
+    unsafe { UART_BACKEND }
+}
+
+/// Line configuration chosen with [`configure_serial`].
+static LINE_CONFIG: Mutex<LineConfig> = Mutex::new(LineConfig::new_8n1(BaudRate::Baud38400));
+
+/// Reprograms the baud rate and framing of the UART used by the raw serial path.
+///
+/// The setting is kept: [`crate::init_logging`] initializes the UART with it, so it may be chosen before logging starts. The host side (QEMU, a USB serial adapter, ...) must be set to the same configuration, or output will be garbled.
+///
+/// # Example
+/// ```ignore
+/// configure_serial(LineConfig::new_8n1(BaudRate::Baud57600));
+/// ```
+pub fn configure_serial(config: LineConfig) {
+    interrupts::without_interrupts(|| *LINE_CONFIG.lock() = config);
+    uart_backend().set_line_config(config);
+}
+
+/// Returns the line configuration chosen with [`configure_serial`], 38400/8-N-1 by default.
+pub fn line_config() -> LineConfig {
+    interrupts::without_interrupts(|| *LINE_CONFIG.lock())
+}
@@ -0,0 +1,425 @@
+//! # SMP: Application Processor Startup
+//!
+//! At boot only the bootstrap processor (BSP) runs; every other CPU, an *application processor* (AP), waits in a halted state until it is sent the INIT-SIPI-SIPI sequence through the Local APIC. This crate wakes the APs listed in the ACPI MADT, brings each one up to the same state as the BSP, and parks it in an idle loop.
+//!
+//! ## Startup Sequence
+//!
+//! A start-up IPI starts an AP in 16-bit real mode at a page-aligned address below 1 MiB, so [`start_secondary_cpus`]:
+//! 1. Copies a small trampoline to [`TRAMPOLINE_ADDRESS`] and identity-maps that page in the kernel's page tables, unless the firmware's executable identity mapping of low memory (4 KiB or 2 MiB pages) still covers it.
+//! 2. For each AP: allocates a guarded kernel stack, fills the trampoline's data block (page table root, `IA32_EFER`, stack, entry point, CPU index), and sends INIT, waits 10 ms, and sends up to two SIPIs.
+//! 3. Waits until the AP reports that it is online, then moves on to the next one, so the data block is only ever used by one AP at a time. An AP that misses the timeout, even one already running Rust code, is sent INIT before the next one starts.
+//! 4. Removes the identity mapping it added once every AP has started.
+//!
+//! The trampoline switches to protected mode with its own temporary GDT, enables PAE, loads the kernel's page tables and `IA32_EFER`, turns on paging to enter long mode, and calls the Rust entry point on the AP's stack.
+//!
+//! ## Per-CPU Setup
+//!
+//! In Rust, each AP copies the BSP's `CR4` and enables SSE/AVX (`polished_x86_commands::simd::enable_simd`), then sets up its `percpu` block, its own GDT, TSS and IST stacks (`polished_gdt::init_gdt_for_cpu`), loads the shared IDT and enables its Local APIC. It then runs the hook installed with [`set_ap_hook`], if any, and halts with interrupts enabled until an IPI arrives. This is the foundation for per-CPU run queues: a scheduler installs its idle loop as the hook.
+//!
+//! ## Example
+//! ```ignore
+//! polished_smp::set_ap_hook(|cpu_id| kprint!("[INFO] CPU {cpu_id} idle\r\n"));
+//! let started = polished_smp::start_secondary_cpus(&madt)?;
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicPtr, AtomicU8, AtomicU64, AtomicUsize, Ordering};
+
+use polished_acpi::{Madt, MadtEntry};
+use polished_interrupts::ipi::{self, IpiError};
+use polished_interrupts::{apic, stacks};
+use polished_memory::frame::GlobalFrameAllocator;
+use polished_memory::hhdm::phys_to_virt;
+use polished_memory::memory_map::{self, MemoryKind};
+use polished_memory::paging::{self, PAGE_SIZE};
+use polished_memory::percpu::{self, MAX_CPUS};
+use polished_memory::stack::{self, StackError};
+use polished_serial_logging::{info, kprint, warn};
+use polished_x86_commands::msr::{self, EFER_LMA, IA32_EFER};
+use polished_x86_commands::simd;
+use polished_x86_commands::tsc::{delay_ms, poll_us};
+use x86_64::VirtAddr;
+use x86_64::registers::control::{Cr3, Cr4};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::structures::paging::mapper::{Translate, TranslateResult};
+
+/// Physical (and, while APs start, virtual) address the trampoline is copied to.
+pub const TRAMPOLINE_ADDRESS: u64 = 0x8000;
+/// Size of the kernel stack each AP starts on.
+pub const AP_STACK_SIZE: u64 = 4096 * 4;
+/// Microseconds to wait for an AP to reach the Rust entry point after a SIPI.
+const STARTUP_TIMEOUT_US: u64 = 1_000;
+/// Microseconds to wait for a started AP to finish its per-CPU setup.
+const ONLINE_TIMEOUT_US: u64 = 1_000_000;
+/// MADT Local APIC flags: enabled, or online capable.
+const LOCAL_APIC_USABLE: u32 = 0b11;
+
+/// States of the AP currently being started, in [`AP_STATE`].
+const AP_WAITING: u8 = 0;
+const AP_STARTED: u8 = 1;
+const AP_ONLINE: u8 = 2;
+const AP_FAILED: u8 = 3;
+/// The AP started but missed [`ONLINE_TIMEOUT_US`]; it must not go online any more.
+const AP_ABANDONED: u8 = 4;
+
+/// Called on every AP once it is online, with its CPU index.
+pub type ApHook = fn(usize);
+
+static AP_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+/// Progress of the AP currently being started.
+static AP_STATE: AtomicU8 = AtomicU8::new(AP_WAITING);
+/// `CR4` of the BSP, copied by every AP.
+static BSP_CR4: AtomicU64 = AtomicU64::new(0);
+/// Number of APs that came online.
+static ONLINE_APS: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors that prevent starting any AP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmpError {
+    /// The BSP's Local APIC has not been enabled, so no IPI can be sent.
+    ApicDisabled,
+    /// The kernel's top-level page table lies above 4 GiB, out of reach of the 32-bit trampoline.
+    PageTableAbove4GiB,
+    /// The memory map does not describe the trampoline page as free RAM.
+    NoTrampolineMemory,
+    /// The trampoline page could not be identity-mapped.
+    TrampolineUnmapped,
+}
+
+/// Errors from starting one AP; [`start_secondary_cpus`] logs them and continues with the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuStartError {
+    /// No stack could be allocated for the AP.
+    Stack(StackError),
+    /// The INIT or start-up IPI could not be sent.
+    Ipi(IpiError),
+    /// The AP did not reach the entry point, or did not finish its setup, in time.
+    NoResponse,
+    /// The AP reached the entry point, but its per-CPU setup failed.
+    SetupFailed,
+}
+
+impl From<IpiError> for CpuStartError {
+    fn from(e: IpiError) -> Self {
+        CpuStartError::Ipi(e)
+    }
+}
+
+/// Data block at the end of the trampoline, filled in by the BSP before each SIPI.
+///
+/// The layout is fixed: the trampoline reads the fields at these offsets.
+#[repr(C)]
+struct TrampolineData {
+    /// Physical address of the kernel's PML4 (below 4 GiB).
+    page_table: u64,
+    /// `IA32_EFER` bits to set before enabling paging.
+    efer: u64,
+    /// Top of the AP's kernel stack.
+    stack_top: u64,
+    /// Address of [`ap_entry`].
+    entry: u64,
+    /// CPU index, passed to [`ap_entry`].
+    cpu_id: u64,
+}
+
+unsafe extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_data: u8;
+    static ap_trampoline_end: u8;
+}
+
+// Runs from a copy at TRAMPOLINE_ADDRESS, so every address is computed relative to that base.
+global_asm!(
+    ".global ap_trampoline_start",
+    ".global ap_trampoline_data",
+    ".global ap_trampoline_end",
+    ".code16",
+    "ap_trampoline_start:",
+    "    cli",
+    "    cld",
+    "    xorw %ax, %ax",
+    "    movw %ax, %ds",
+    "    lgdtl (ap_gdtr - ap_trampoline_start + {base})",
+    "    movl %cr0, %eax",
+    "    orl $1, %eax",
+    "    movl %eax, %cr0",
+    "    ljmpl $0x08, $(ap_protected - ap_trampoline_start + {base})",
+    ".code32",
+    "ap_protected:",
+    "    movw $0x10, %ax",
+    "    movw %ax, %ds",
+    "    movw %ax, %es",
+    "    movw %ax, %ss",
+    // PAE
+    "    movl %cr4, %eax",
+    "    orl $0x20, %eax",
+    "    movl %eax, %cr4",
+    "    movl (ap_trampoline_data - ap_trampoline_start + {base}), %eax",
+    "    movl %eax, %cr3",
+    // IA32_EFER: long mode, plus NXE and SCE as on the BSP
+    "    movl $0xC0000080, %ecx",
+    "    rdmsr",
+    "    orl (ap_trampoline_data + 8 - ap_trampoline_start + {base}), %eax",
+    "    wrmsr",
+    // Paging and write protection; INIT leaves the caches disabled (CD, NW)
+    "    movl %cr0, %eax",
+    "    andl $0x9FFFFFFF, %eax",
+    "    orl $0x80010000, %eax",
+    "    movl %eax, %cr0",
+    "    ljmpl $0x18, $(ap_long - ap_trampoline_start + {base})",
+    ".code64",
+    "ap_long:",
+    "    xorw %ax, %ax",
+    "    movw %ax, %ds",
+    "    movw %ax, %es",
+    "    movw %ax, %ss",
+    "    movq (ap_trampoline_data + 16 - ap_trampoline_start + {base}), %rsp",
+    "    movq (ap_trampoline_data + 32 - ap_trampoline_start + {base}), %rdi",
+    "    movq (ap_trampoline_data + 24 - ap_trampoline_start + {base}), %rax",
+    "    callq *%rax",
+    "2:",
+    "    hlt",
+    "    jmp 2b",
+    ".balign 8",
+    // Null, 32-bit code (0x08), data (0x10), 64-bit code (0x18)
+    "ap_gdt:",
+    "    .quad 0",
+    "    .quad 0x00CF9A000000FFFF",
+    "    .quad 0x00CF92000000FFFF",
+    "    .quad 0x00AF9A000000FFFF",
+    "ap_gdtr:",
+    "    .word ap_gdtr - ap_gdt - 1",
+    "    .long ap_gdt - ap_trampoline_start + {base}",
+    ".balign 8",
+    "ap_trampoline_data:",
+    "    .fill 5, 8, 0",
+    "ap_trampoline_end:",
+    base = const TRAMPOLINE_ADDRESS,
+    options(att_syntax)
+);
+
+/// Installs `hook` to run on every AP once it is online, before it enters the idle loop.
+///
+/// The hook runs with interrupts disabled; it may return, or never return to take the CPU over.
+pub fn set_ap_hook(hook: ApHook) {
+    AP_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Returns the number of application processors that came online.
+pub fn online_aps() -> usize {
+    ONLINE_APS.load(Ordering::Acquire)
+}
+
+/// Starts every enabled AP listed in `madt` and returns how many came online.
+///
+/// APs are given CPU indices after the CPUs that already have a `percpu` block. A CPU that does not come up is logged and skipped; if it missed the timeout, it is sent INIT again so it cannot run on another AP's data later.
+///
+/// Call on the BSP with interrupts disabled, after `apic::init`, `polished_x86_commands::tsc::calibrate` and the memory subsystem.
+///
+/// # Errors
+/// Fails if the Local APIC is disabled or the trampoline cannot be installed.
+pub fn start_secondary_cpus(madt: &Madt) -> Result<usize, SmpError> {
+    if !apic::is_enabled() {
+        return Err(SmpError::ApicDisabled);
+    }
+    let page_table = Cr3::read().0.start_address().as_u64();
+    if page_table > u64::from(u32::MAX) {
+        return Err(SmpError::PageTableAbove4GiB);
+    }
+    let unmap_afterwards = install_trampoline()?;
+    BSP_CR4.store(Cr4::read_raw(), Ordering::Relaxed);
+    let bsp = apic::id();
+    let mut cpu_id = percpu::cpu_count();
+    let mut started = 0;
+    for entry in madt.entries() {
+        let MadtEntry::LocalApic { apic_id, flags, .. } = entry else {
+            continue;
+        };
+        if u32::from(apic_id) == bsp || flags & LOCAL_APIC_USABLE == 0 {
+            continue;
+        }
+        if cpu_id >= MAX_CPUS {
+            warn(&format!(
+                "More than {MAX_CPUS} CPUs, not starting APIC ID {apic_id}"
+            ));
+            break;
+        }
+        match start_cpu(u32::from(apic_id), cpu_id, page_table) {
+            Ok(()) => {
+                info(&format!("CPU {cpu_id} (APIC ID {apic_id}) online"));
+                started += 1;
+            }
+            Err(e) => warn(&format!(
+                "Could not start CPU {cpu_id} (APIC ID {apic_id}): {e:?}"
+            )),
+        }
+        cpu_id += 1;
+    }
+    if unmap_afterwards {
+        // Safety: every AP has left the trampoline or been sent back to wait-for-SIPI.
+        let _ = unsafe { paging::unmap_page(TRAMPOLINE_ADDRESS) };
+    }
+    Ok(started)
+}
+
+/// Copies the trampoline to [`TRAMPOLINE_ADDRESS`] and identity-maps it. Returns whether the mapping was added (and must be removed again).
+fn install_trampoline() -> Result<bool, SmpError> {
+    let free = |address| {
+        matches!(
+            memory_map::kind_of(address),
+            Some(MemoryKind::Usable | MemoryKind::BootServices)
+        )
+    };
+    if !free(TRAMPOLINE_ADDRESS) || !free(TRAMPOLINE_ADDRESS + PAGE_SIZE - 1) {
+        return Err(SmpError::NoTrampolineMemory);
+    }
+    let start = &raw const ap_trampoline_start;
+    let size = &raw const ap_trampoline_end as usize - start as usize;
+    // Safety: the page below 1 MiB is free RAM that the frame allocator never hands out.
+    unsafe {
+        core::ptr::copy_nonoverlapping(start, phys_to_virt(TRAMPOLINE_ADDRESS) as *mut u8, size);
+    }
+    // Safety: only reads the active page tables.
+    let translation =
+        unsafe { paging::active_page_table() }.translate(VirtAddr::new(TRAMPOLINE_ADDRESS));
+    match translation {
+        TranslateResult::NotMapped => {
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+            // Safety: nothing else uses the lowest megabyte of virtual memory.
+            unsafe {
+                paging::map_page(
+                    TRAMPOLINE_ADDRESS,
+                    TRAMPOLINE_ADDRESS,
+                    flags,
+                    &mut GlobalFrameAllocator,
+                )
+            }
+            .map_err(|_| SmpError::TrampolineUnmapped)?;
+            Ok(true)
+        }
+        // Firmware identity-maps low memory, usually with 2 MiB pages; any executable identity mapping will do.
+        TranslateResult::Mapped {
+            frame,
+            offset,
+            flags,
+        } if frame.start_address().as_u64() + offset == TRAMPOLINE_ADDRESS
+            && !flags.contains(PageTableFlags::NO_EXECUTE) =>
+        {
+            Ok(false)
+        }
+        _ => Err(SmpError::TrampolineUnmapped),
+    }
+}
+
+/// Runs INIT-SIPI-SIPI for the AP with `apic_id` and waits until it is online as CPU `cpu_id`.
+fn start_cpu(apic_id: u32, cpu_id: usize, page_table: u64) -> Result<(), CpuStartError> {
+    let stack = stack::alloc_kernel_stack(AP_STACK_SIZE).map_err(CpuStartError::Stack)?;
+    let offset = &raw const ap_trampoline_data as u64 - &raw const ap_trampoline_start as u64;
+    let data = phys_to_virt(TRAMPOLINE_ADDRESS + offset) as *mut TrampolineData;
+    // Safety: the data block lies in the trampoline page, and no AP is running the trampoline now.
+    unsafe {
+        data.write_volatile(TrampolineData {
+            page_table,
+            efer: msr::rdmsr(IA32_EFER) & !EFER_LMA,
+            stack_top: stack.top().as_u64(),
+            entry: ap_entry as *const () as u64,
+            cpu_id: cpu_id as u64,
+        });
+    }
+    AP_STATE.store(AP_WAITING, Ordering::Release);
+
+    ipi::send_init(apic_id)?;
+    delay_ms(10);
+    let page = (TRAMPOLINE_ADDRESS / PAGE_SIZE) as u8;
+    for _ in 0..2 {
+        ipi::send_startup(apic_id, page)?;
+        if poll_us(STARTUP_TIMEOUT_US, || {
+            AP_STATE.load(Ordering::Acquire) != AP_WAITING
+        }) {
+            break;
+        }
+    }
+    poll_us(ONLINE_TIMEOUT_US, || {
+        matches!(AP_STATE.load(Ordering::Acquire), AP_ONLINE | AP_FAILED)
+    });
+    let state = AP_STATE.load(Ordering::Acquire);
+    if state == AP_WAITING {
+        ipi::send_init(apic_id)?;
+        return Err(CpuStartError::NoResponse);
+    }
+    // The AP ran on the stack, so it is never freed.
+    let (bottom, top) = (stack.bottom().as_u64(), stack.top().as_u64());
+    core::mem::forget(stack);
+    // A started AP that is still setting up is claimed before it can go online, then parked, so it cannot keep running on the data block and state of the next AP.
+    let state = match AP_STATE.compare_exchange(
+        AP_STARTED,
+        AP_ABANDONED,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    ) {
+        Ok(_) => {
+            ipi::send_init(apic_id)?;
+            return Err(CpuStartError::NoResponse);
+        }
+        Err(state) => state,
+    };
+    match state {
+        AP_ONLINE => {
+            ONLINE_APS.fetch_add(1, Ordering::AcqRel);
+            stacks::register_kernel_stack("ap boot", bottom, top);
+            Ok(())
+        }
+        AP_FAILED => Err(CpuStartError::SetupFailed),
+        _ => Err(CpuStartError::NoResponse),
+    }
+}
+
+/// Rust entry point of an AP, called by the trampoline in long mode on the AP's stack.
+extern "C" fn ap_entry(cpu_id: u64) -> ! {
+    let cpu_id = cpu_id as usize;
+    AP_STATE.store(AP_STARTED, Ordering::Release);
+    // Safety: the CPUs are identical, so the BSP's CR4 features exist here too.
+    unsafe { Cr4::write_raw(BSP_CR4.load(Ordering::Relaxed)) };
+    simd::enable_simd();
+    let state = match init_cpu(cpu_id) {
+        Ok(()) => AP_ONLINE,
+        Err(e) => {
+            kprint!("[ERROR] CPU {}: {}\r\n", cpu_id, e);
+            AP_FAILED
+        }
+    };
+    // Fails if the BSP gave up waiting for this CPU; it is then about to send INIT.
+    let reported = AP_STATE
+        .compare_exchange(AP_STARTED, state, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok();
+    if state != AP_ONLINE || !reported {
+        loop {
+            // Safety: only stops this CPU.
+            unsafe { core::arch::asm!("cli", "hlt") };
+        }
+    }
+    let hook = AP_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // Safety: only `ApHook` function pointers are ever stored.
+        let hook: ApHook = unsafe { core::mem::transmute(hook) };
+        hook(cpu_id);
+    }
+    loop {
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
+/// Gives the calling AP its `percpu` block, GDT and TSS, the shared IDT and an enabled Local APIC.
+fn init_cpu(cpu_id: usize) -> Result<(), &'static str> {
+    percpu::init_cpu(cpu_id).map_err(|_| "per-CPU block setup failed")?;
+    polished_gdt::init_gdt_for_cpu(cpu_id).map_err(|_| "GDT setup failed")?;
+    polished_interrupts::init_idt();
+    // Safety: runs on the AP with interrupts disabled, after its IDT is loaded.
+    unsafe { apic::init_secondary(cpu_id) }.map_err(|_| "Local APIC setup failed")?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! # Syscall Register ABI
+//!
+//! This crate is the single definition of how a system call is passed between user code and the kernel, including the syscall numbers in [`nr`]. Both sides build on it: the kernel entry stub (`polished_syscalls::entry`) reads a [`SyscallFrame`] out of the saved registers, and the userspace wrappers (`polished_usys::raw`) load one into registers. A change here changes both, so they cannot drift apart.
+//!
+//! ## Registers
+//!
+//! | Register | On entry | On return |
+//! | --- | --- | --- |
+//! | RAX | [`SyscallFrame::number`] | [`SyscallReturn`] |
+//! | RDI, RSI, RDX, R10, R8, R9 | [`SyscallFrame::args`]`[0..6]` | Preserved |
+//! | RCX, R11 | Anything | Clobbered ([`CLOBBERED_REGISTERS`]) |
+//! | Everything else | Anything | Preserved |
+//!
+//! This is the Linux x86_64 convention. R10 replaces RCX as the fourth argument, since `syscall` overwrites RCX with the return address and R11 with RFLAGS.
+//!
+//! ## Stack
+//!
+//! The kernel switches to its own stack on entry, so the user stack needs no particular alignment at `syscall` and nothing below the user stack pointer is touched (the red zone is safe). The kernel stack is [`KERNEL_STACK_ALIGNMENT`]-byte aligned when the dispatcher runs.
+//!
+//! ## Return Value
+//!
+//! A [`SyscallReturn`] in `-MAX_ERRNO..=-1` (as a two's complement `u64`) is a negated error number; anything else is a successful result.
+
+#![no_std]
+
+pub mod nr;
+
+/// Number of argument registers.
+pub const ARGUMENT_COUNT: usize = 6;
+
+/// Names of the argument registers, in argument order.
+pub const ARGUMENT_REGISTERS: [&str; ARGUMENT_COUNT] = ["rdi", "rsi", "rdx", "r10", "r8", "r9"];
+
+/// Register holding the syscall number on entry and the return value on exit.
+pub const NUMBER_REGISTER: &str = "rax";
+
+/// Registers a syscall may change besides RAX.
+pub const CLOBBERED_REGISTERS: [&str; 2] = ["rcx", "r11"];
+
+/// Alignment of the kernel stack when the syscall dispatcher is called.
+pub const KERNEL_STACK_ALIGNMENT: usize = 16;
+
+/// Largest error number; raw return values above `-(MAX_ERRNO)` are errors.
+pub const MAX_ERRNO: u64 = 4095;
+
+/// A system call as passed in registers: the number in RAX and up to six arguments.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyscallFrame {
+    /// Syscall number (RAX).
+    pub number: u64,
+    /// Arguments (RDI, RSI, RDX, R10, R8, R9); unused ones are ignored by the kernel.
+    pub args: [u64; ARGUMENT_COUNT],
+}
+
+impl SyscallFrame {
+    /// Creates a frame for syscall `number` with all six arguments.
+    pub const fn new(number: u64, args: [u64; ARGUMENT_COUNT]) -> Self {
+        SyscallFrame { number, args }
+    }
+
+    /// Creates a frame for syscall `number` with the leading arguments `args`; the rest are 0.
+    ///
+    /// # Panics
+    /// Panics if more than [`ARGUMENT_COUNT`] arguments are given.
+    pub const fn with_args(number: u64, args: &[u64]) -> Self {
+        assert!(
+            args.len() <= ARGUMENT_COUNT,
+            "a syscall takes at most 6 arguments"
+        );
+        let mut frame = SyscallFrame::new(number, [0; ARGUMENT_COUNT]);
+        let mut index = 0;
+        while index < args.len() {
+            frame.args[index] = args[index];
+            index += 1;
+        }
+        frame
+    }
+}
+
+/// The raw value a syscall returns in RAX.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyscallReturn(pub u64);
+
+impl SyscallReturn {
+    /// Encodes a successful result.
+    pub const fn ok(value: u64) -> Self {
+        SyscallReturn(value)
+    }
+
+    /// Encodes error number `errno` (`1..=MAX_ERRNO`) as its negation.
+    pub const fn error(errno: u64) -> Self {
+        SyscallReturn(errno.wrapping_neg())
+    }
+
+    /// Returns whether the value is a negated error number.
+    pub const fn is_error(self) -> bool {
+        self.0 > MAX_ERRNO.wrapping_neg()
+    }
+
+    /// Splits the value into a result or an error number.
+    pub const fn into_result(self) -> Result<u64, u64> {
+        if self.is_error() {
+            Err(self.0.wrapping_neg())
+        } else {
+            Ok(self.0)
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Syscall numbers.
+//!
+//! They follow Linux x86_64 where an equivalent exists, so existing tooling (and muscle memory) applies.
+//! Polished-specific syscalls start at [`POLISHED_SYSCALL_BASE`], above every Linux call implemented here.
+//! The kernel (`polished_syscalls::abi`) and userspace (`polished_usys::nr`) both re-export this module.
+
+/// First number of the Polished-specific syscalls.
+pub const POLISHED_SYSCALL_BASE: u64 = 240;
+
+/// `read(fd, buf, len)`.
+pub const SYS_READ: u64 = 0;
+/// `write(fd, buf, len)`.
+pub const SYS_WRITE: u64 = 1;
+/// `open(path, flags)`.
+pub const SYS_OPEN: u64 = 2;
+/// `close(fd)`.
+pub const SYS_CLOSE: u64 = 3;
+/// `lseek(fd, offset, whence)`.
+pub const SYS_LSEEK: u64 = 8;
+/// `mmap(len, prot)`.
+pub const SYS_MMAP: u64 = 9;
+/// `brk(addr)`.
+pub const SYS_BRK: u64 = 12;
+/// `sigaction(sig, act, oldact)` (`rt_sigaction` on Linux).
+pub const SYS_SIGACTION: u64 = 13;
+/// `sigreturn()` (`rt_sigreturn` on Linux). Handled by the entry stub, not the syscall table.
+pub const SYS_SIGRETURN: u64 = 15;
+/// `sched_yield()`.
+pub const SYS_SCHED_YIELD: u64 = 24;
+/// `getpid()`.
+pub const SYS_GETPID: u64 = 39;
+/// `exit(code)`.
+pub const SYS_EXIT: u64 = 60;
+/// `kill(pid, sig)`.
+pub const SYS_KILL: u64 = 62;
+/// `getppid()`.
+pub const SYS_GETPPID: u64 = 110;
+/// `gettid()`.
+pub const SYS_GETTID: u64 = 186;
+/// `futex(addr, op, val)`.
+pub const SYS_FUTEX: u64 = 202;
+/// `clock_gettime(clock, ts)`.
+pub const SYS_CLOCK_GETTIME: u64 = 228;
+/// `fb_map(info)` (Polished-specific).
+pub const SYS_FB_MAP: u64 = POLISHED_SYSCALL_BASE;
@@ -0,0 +1,105 @@
+//! # Syscall Numbers
+//!
+//! The `SYS_*` numbers are defined in `polished_syscall_abi::nr`, shared with userspace, and re-exported here.
+//! They follow Linux x86_64 where an equivalent exists; Polished-specific syscalls use the range from [`POLISHED_SYSCALL_BASE`] to the end of the table.
+//! [`Syscall::decode`] turns a raw number and the argument registers into a typed call, for handlers and for logging.
+
+pub use polished_syscall_abi::nr::*;
+
+/// A decoded system call with its arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syscall {
+    /// `Read(fd, ptr, len)`: reads up to `len` bytes from file descriptor `fd` into the user buffer at `ptr`.
+    Read(u64, u64, u64),
+    /// `Write(fd, ptr, len)`: writes `len` bytes from the user buffer at `ptr` to file descriptor `fd`.
+    Write(u64, u64, u64),
+    /// `Open(path, flags)`: opens the file at the NUL-terminated user path `path`.
+    Open(u64, u64),
+    /// `Close(fd)`: closes file descriptor `fd`.
+    Close(u64),
+    /// `Lseek(fd, offset, whence)`: moves the offset of file descriptor `fd`.
+    Lseek(u64, u64, u64),
+    /// `Mmap(len, prot)`: maps `len` bytes of anonymous memory with the `PROT_*` protections `prot`.
+    Mmap(u64, u64),
+    /// `Brk(addr)`: moves the program break to `addr` (0 queries it).
+    Brk(u64),
+    /// `Sigaction(sig, act, oldact)`: installs the user `sigaction` at `act` for signal `sig`, saving the previous one at `oldact`.
+    Sigaction(u64, u64, u64),
+    /// `Sigreturn`: returns from a signal handler.
+    Sigreturn,
+    /// `SchedYield`: lets other tasks run.
+    SchedYield,
+    /// `Getpid`: returns the calling task's ID.
+    Getpid,
+    /// `Exit(code)`: terminates the calling task with exit code `code`.
+    Exit(i32),
+    /// `Kill(pid, sig)`: sends signal `sig` to task `pid`.
+    Kill(u64, u64),
+    /// `Getppid`: returns the parent task's ID.
+    Getppid,
+    /// `Gettid`: returns the calling thread's ID.
+    Gettid,
+    /// `ClockGettime(clock, ptr)`: writes the time of `clock` to the user `timespec` at `ptr`.
+    ClockGettime(u64, u64),
+    /// `FutexWait(addr, expected)`: `futex(addr, FUTEX_WAIT, expected)`.
+    FutexWait(u64, u32),
+    /// `FutexWake(addr, count)`: `futex(addr, FUTEX_WAKE, count)`.
+    FutexWake(u64, u64),
+    /// `FbMap(info)`: maps the framebuffer and writes its description to the user `FbInfo` at `info`.
+    FbMap(u64),
+}
+
+impl Syscall {
+    /// Decodes syscall `number` with the argument registers `args`, or returns `None` for an unknown number.
+    pub fn decode(number: u64, args: [u64; 6]) -> Option<Self> {
+        match number {
+            SYS_READ => Some(Syscall::Read(args[0], args[1], args[2])),
+            SYS_WRITE => Some(Syscall::Write(args[0], args[1], args[2])),
+            SYS_OPEN => Some(Syscall::Open(args[0], args[1])),
+            SYS_CLOSE => Some(Syscall::Close(args[0])),
+            SYS_LSEEK => Some(Syscall::Lseek(args[0], args[1], args[2])),
+            SYS_MMAP => Some(Syscall::Mmap(args[0], args[1])),
+            SYS_BRK => Some(Syscall::Brk(args[0])),
+            SYS_SIGACTION => Some(Syscall::Sigaction(args[0], args[1], args[2])),
+            SYS_SIGRETURN => Some(Syscall::Sigreturn),
+            SYS_SCHED_YIELD => Some(Syscall::SchedYield),
+            SYS_GETPID => Some(Syscall::Getpid),
+            SYS_EXIT => Some(Syscall::Exit(args[0] as i32)),
+            SYS_KILL => Some(Syscall::Kill(args[0], args[1])),
+            SYS_GETPPID => Some(Syscall::Getppid),
+            SYS_GETTID => Some(Syscall::Gettid),
+            SYS_CLOCK_GETTIME => Some(Syscall::ClockGettime(args[0], args[1])),
+            SYS_FUTEX => match args[1] {
+                crate::futex::FUTEX_WAIT => Some(Syscall::FutexWait(args[0], args[2] as u32)),
+                crate::futex::FUTEX_WAKE => Some(Syscall::FutexWake(args[0], args[2])),
+                _ => None,
+            },
+            SYS_FB_MAP => Some(Syscall::FbMap(args[0])),
+            _ => None,
+        }
+    }
+
+    /// Returns the syscall number.
+    pub fn number(&self) -> u64 {
+        match self {
+            Syscall::Read(..) => SYS_READ,
+            Syscall::Write(..) => SYS_WRITE,
+            Syscall::Open(..) => SYS_OPEN,
+            Syscall::Close(_) => SYS_CLOSE,
+            Syscall::Lseek(..) => SYS_LSEEK,
+            Syscall::Mmap(..) => SYS_MMAP,
+            Syscall::Brk(_) => SYS_BRK,
+            Syscall::Sigaction(..) => SYS_SIGACTION,
+            Syscall::Sigreturn => SYS_SIGRETURN,
+            Syscall::SchedYield => SYS_SCHED_YIELD,
+            Syscall::Getpid => SYS_GETPID,
+            Syscall::Exit(_) => SYS_EXIT,
+            Syscall::Kill(..) => SYS_KILL,
+            Syscall::Getppid => SYS_GETPPID,
+            Syscall::Gettid => SYS_GETTID,
+            Syscall::ClockGettime(..) => SYS_CLOCK_GETTIME,
+            Syscall::FutexWait(..) | Syscall::FutexWake(..) => SYS_FUTEX,
+            Syscall::FbMap(_) => SYS_FB_MAP,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! # Time Syscalls
+//!
+//! `clock_gettime(clock, ts)` writes the current time of a clock to a user `timespec`:
+//! - **`CLOCK_REALTIME`:** Wall-clock time from the RTC, as seconds since the Unix epoch. The RTC has a resolution of one second, so `tv_nsec` is always 0.
+//! - **`CLOCK_MONOTONIC`:** Time since boot from `polished_interrupts::time::uptime_ns` (HPET, or the timer tick). It never goes backwards, which makes it the clock to use for benchmarks and timeouts.
+
+use polished_interrupts::{rtc, time};
+
+use crate::abi::{SYS_CLOCK_GETTIME, Syscall};
+use crate::error::{SyscallError, SyscallResult};
+use crate::user;
+
+/// Wall-clock time.
+pub const CLOCK_REALTIME: u64 = 0;
+/// Time since boot.
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// A point in time, laid out like the C `struct timespec` on x86_64.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timespec {
+    /// Whole seconds.
+    pub tv_sec: i64,
+    /// Nanoseconds (`0..1_000_000_000`).
+    pub tv_nsec: i64,
+}
+
+impl Timespec {
+    /// Splits a number of nanoseconds.
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Timespec {
+            tv_sec: (nanos / NANOS_PER_SEC) as i64,
+            tv_nsec: (nanos % NANOS_PER_SEC) as i64,
+        }
+    }
+
+    /// Returns the in-memory representation handed to user code.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.tv_sec.to_ne_bytes());
+        bytes[8..].copy_from_slice(&self.tv_nsec.to_ne_bytes());
+        bytes
+    }
+}
+
+/// Returns the current time of `clock`, or `None` for an unknown clock.
+pub fn clock_time(clock: u64) -> Option<Timespec> {
+    match clock {
+        CLOCK_REALTIME => Some(Timespec {
+            tv_sec: rtc::now().unix_timestamp() as i64,
+            tv_nsec: 0,
+        }),
+        CLOCK_MONOTONIC => Some(Timespec::from_nanos(time::uptime_ns())),
+        _ => None,
+    }
+}
+
+/// `clock_gettime(clock, ts)`: writes the time of `clock` to the user `timespec` at `ts` and returns 0.
+pub fn sys_clock_gettime(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::ClockGettime(clock, ptr)) = Syscall::decode(SYS_CLOCK_GETTIME, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    let now = clock_time(clock).ok_or(SyscallError::InvalidArgument)?;
+    user::copy_to_user(ptr, &now.to_bytes())?;
+    Ok(0)
+}
@@ -0,0 +1,152 @@
+//! # Console Syscalls
+//!
+//! `write` on the standard output and error descriptors (1 and 2) sends bytes to the kernel log output: the serial port and every log sink registered with `polished_serial_logging::register_sink`, such as a framebuffer console.
+//!
+//! `read` on standard input (0) takes decoded key presses from the input event queue (`polished_input`) and copies their characters, UTF-8 encoded, into the user buffer, so keys typing `é` or `ß` on non-US layouts arrive as two bytes rather than being dropped. A character that does not fit into the rest of the buffer is split: its remaining bytes are returned first by the next `read`. Mouse events popped on the way are discarded.
+//! It returns as soon as at least one byte is available; there is no line editing. By default it blocks, halting the CPU with interrupts enabled until the keyboard IRQ queues more events or a signal arrives (`EINTR`). With [`set_stdin_nonblocking`], it returns 0 immediately instead.
+//!
+//! Each input event goes to one consumer: keys the kernel main loop pops at the same time are not seen by standard input.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use polished_input::InputEvent;
+use polished_serial_logging::sink;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::abi::{SYS_READ, SYS_WRITE, Syscall};
+use crate::error::{SyscallError, SyscallResult};
+use crate::user;
+
+/// Size of the kernel buffer user data is copied through.
+const CHUNK_SIZE: usize = 256;
+
+/// Standard input file descriptor.
+pub const STDIN: u64 = 0;
+/// Standard output file descriptor.
+pub const STDOUT: u64 = 1;
+/// Standard error file descriptor.
+pub const STDERR: u64 = 2;
+
+static STDIN_NONBLOCKING: AtomicBool = AtomicBool::new(false);
+
+/// UTF-8 bytes of a character that did not fit into the previous `read`.
+struct PendingBytes {
+    bytes: [u8; 4],
+    start: usize,
+    end: usize,
+}
+
+impl PendingBytes {
+    /// Copies as many pending bytes as fit into `buffer`, returning how many were copied.
+    fn take_into(&mut self, buffer: &mut [u8]) -> usize {
+        let count = (self.end - self.start).min(buffer.len());
+        buffer[..count].copy_from_slice(&self.bytes[self.start..self.start + count]);
+        self.start += count;
+        count
+    }
+}
+
+/// Bytes left over from a split character; only used by `read`, which runs with interrupts disabled.
+static PENDING: Mutex<PendingBytes> = Mutex::new(PendingBytes {
+    bytes: [0; 4],
+    start: 0,
+    end: 0,
+});
+
+/// Selects whether `read` on standard input returns 0 instead of blocking when no input is pending.
+pub fn set_stdin_nonblocking(nonblocking: bool) {
+    STDIN_NONBLOCKING.store(nonblocking, Ordering::Relaxed);
+}
+
+/// Returns whether `read` on standard input is non-blocking.
+pub fn is_stdin_nonblocking() -> bool {
+    STDIN_NONBLOCKING.load(Ordering::Relaxed)
+}
+
+/// Moves the bytes of pending key presses into `buffer` as UTF-8, returning the number of bytes copied.
+///
+/// The bytes of the last character that do not fit are kept for the next call.
+fn drain_keyboard(buffer: &mut [u8]) -> usize {
+    let mut pending = PENDING.lock();
+    let mut copied = pending.take_into(buffer);
+    while copied < buffer.len() {
+        let Some(event) = polished_input::pop() else {
+            break;
+        };
+        let InputEvent::Key(key) = event else {
+            continue;
+        };
+        let mut encoded = [0; 4];
+        let Some(len) = key.encode_utf8(&mut encoded).map(<[u8]>::len) else {
+            continue;
+        };
+        *pending = PendingBytes {
+            bytes: encoded,
+            start: 0,
+            end: len,
+        };
+        copied += pending.take_into(&mut buffer[copied..]);
+    }
+    copied
+}
+
+/// `read(fd, buf, len)`: returns the number of bytes read (0 if non-blocking and no input is pending), or an error.
+///
+/// Descriptors other than standard input are files, read by [`crate::fs::read`].
+pub fn sys_read(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Read(fd, ptr, len)) = Syscall::decode(SYS_READ, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    if fd != STDIN {
+        return crate::fs::read(fd, ptr, len);
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    if !user::is_user_range(ptr, len) {
+        return Err(SyscallError::Fault);
+    }
+    let mut buffer = [0u8; CHUNK_SIZE];
+    let buffer = &mut buffer[..(len as usize).min(CHUNK_SIZE)];
+    loop {
+        let copied = drain_keyboard(buffer);
+        if copied > 0 || is_stdin_nonblocking() {
+            user::copy_to_user(ptr, &buffer[..copied])?;
+            return Ok(copied as u64);
+        }
+        if crate::signal::has_pending(crate::task::current_task_id()) {
+            return Err(SyscallError::Interrupted);
+        }
+        // Syscalls run with interrupts masked; let the keyboard IRQ in while waiting.
+        interrupts::enable_and_hlt();
+        interrupts::disable();
+    }
+}
+
+/// `write(fd, buf, len)`: returns the number of bytes written, or an error.
+pub fn sys_write(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Write(fd, ptr, len)) = Syscall::decode(SYS_WRITE, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    if fd != STDOUT && fd != STDERR {
+        return Err(SyscallError::BadFileDescriptor);
+    }
+    if !user::is_user_range(ptr, len) && len != 0 {
+        return Err(SyscallError::Fault);
+    }
+    let mut buffer = [0u8; CHUNK_SIZE];
+    let mut written = 0;
+    while written < len {
+        let chunk = &mut buffer[..((len - written) as usize).min(CHUNK_SIZE)];
+        if let Err(error) = user::copy_from_user(chunk, ptr + written) {
+            // Report a short write if part of the buffer made it out
+            return if written > 0 { Ok(written) } else { Err(error) };
+        }
+        if polished_serial_logging::is_serial_logging_enabled() {
+            sink::write_bytes(chunk);
+        }
+        written += chunk.len() as u64;
+    }
+    Ok(len)
+}
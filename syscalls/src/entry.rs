@@ -0,0 +1,182 @@
+//! # Syscall Entry Stub
+//!
+//! [`syscall_entry`] is the target of `IA32_LSTAR`. It runs in ring 0 on the **user** stack with interrupts disabled, and:
+//! 1. Executes `swapgs` to reach the per-CPU block, saves the user RSP in it and switches to the CPU's kernel syscall stack.
+//! 2. Pushes an `iretq` frame built from the user SS, RSP, RFLAGS (R11), CS, and RIP (RCX), then the general-purpose registers, forming a [`SyscallRegisters`] block.
+//! 3. Saves the caller's x87/SSE/AVX state below that block (`polished_x86_commands::simd::save_state`), since the dispatcher is compiled Rust that may use the SIMD registers.
+//! 4. Calls the Rust dispatcher with a pointer to the register block, from which it reads a `polished_syscall_abi::SyscallFrame`.
+//! 5. Restores the SIMD state and the registers (RAX now holds the return value), executes `swapgs` again, switches back to the user stack, and returns with `sysretq`.
+//!
+//! `sysretq` takes the user RIP and RFLAGS from RCX and R11. When the dispatcher has to restore those two registers as well (after `sigreturn`, see [`crate::signal`]), the stub returns with `iretq` through the frame instead.
+//!
+//! The user RSP and the kernel stack top live in the calling CPU's per-CPU block, reached as `gs:[offset]` (see the GS base section of the `polished_gdt` crate), so every CPU can take syscalls at the same time.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use polished_gdt::{GS_KERNEL_STACK_TOP, GS_USER_RSP_SCRATCH};
+use polished_syscall_abi::{KERNEL_STACK_ALIGNMENT, SyscallFrame, SyscallReturn};
+use polished_x86_commands::simd;
+use x86_64::VirtAddr;
+use x86_64::structures::gdt::SegmentSelector;
+
+use crate::abi::SYS_SIGRETURN;
+use crate::signal::{self, SignalContext};
+
+/// User code selector pushed into the `iretq` frame, set from the GDT by [`crate::init_syscalls`].
+static USER_CS: AtomicU64 = AtomicU64::new(0);
+
+/// User stack selector pushed into the `iretq` frame, set from the GDT by [`crate::init_syscalls`].
+static USER_SS: AtomicU64 = AtomicU64::new(0);
+
+/// Registers of the calling user code, as pushed by [`syscall_entry`] (R15 at the lowest address).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    /// User RFLAGS on entry; only restored on an `iretq` return.
+    pub r11: u64,
+    /// User RIP on entry; only restored on an `iretq` return.
+    pub rcx: u64,
+    /// Argument 6.
+    pub r9: u64,
+    /// Argument 5.
+    pub r8: u64,
+    /// Argument 4.
+    pub r10: u64,
+    /// Argument 3.
+    pub rdx: u64,
+    /// Argument 2.
+    pub rsi: u64,
+    /// Argument 1.
+    pub rdi: u64,
+    /// Syscall number on entry, return value on exit.
+    pub rax: u64,
+    /// User RIP (saved in RCX by `syscall`).
+    pub rip: u64,
+    /// User code selector.
+    pub cs: u64,
+    /// User RFLAGS (saved in R11 by `syscall`).
+    pub rflags: u64,
+    /// User stack pointer.
+    pub rsp: u64,
+    /// User stack selector.
+    pub ss: u64,
+}
+
+impl SyscallRegisters {
+    /// Returns the syscall number and arguments, in the register layout of `polished_syscall_abi`.
+    pub fn frame(&self) -> SyscallFrame {
+        SyscallFrame::new(
+            self.rax,
+            [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9],
+        )
+    }
+
+    /// Sets the value returned to user code in RAX.
+    pub fn set_return(&mut self, value: SyscallReturn) {
+        self.rax = value.0;
+    }
+}
+
+/// Sets the top of the kernel stack used by subsequent syscalls (and interrupts from user mode) on the calling CPU, e.g. the current task's kernel stack.
+///
+/// See `polished_gdt::set_kernel_stack`.
+///
+/// # Safety
+/// `top` must be the 16-byte aligned top of a mapped stack that no syscall in progress is using.
+pub unsafe fn set_kernel_stack(top: u64) {
+    // The stub pushes 20 registers (160 bytes) before calling the dispatcher, keeping the stack's alignment.
+    debug_assert!(top.is_multiple_of(KERNEL_STACK_ALIGNMENT as u64));
+    unsafe { polished_gdt::set_kernel_stack(VirtAddr::new(top)) };
+}
+
+/// Records the user selectors `sysret` loads, so that the frame built by the stub matches them.
+pub(crate) fn set_user_selectors(code: SegmentSelector, data: SegmentSelector) {
+    USER_CS.store(u64::from(code.0), Ordering::Relaxed);
+    USER_SS.store(u64::from(data.0), Ordering::Relaxed);
+}
+
+/// Returns the top of the kernel stack used by syscalls on the calling CPU.
+pub fn kernel_stack() -> u64 {
+    polished_gdt::kernel_stack().as_u64()
+}
+
+/// Called by [`syscall_entry`] with the saved user registers. Returns whether the stub must return with `iretq`.
+///
+/// `sigreturn` is handled here rather than in the syscall table, as it replaces the whole register block. Pending signals are delivered on the way out.
+extern "C" fn syscall_dispatch(registers: &mut SyscallRegisters) -> bool {
+    let frame = registers.frame();
+    let full_restore = frame.number == SYS_SIGRETURN;
+    if full_restore {
+        signal::sigreturn(registers);
+    } else {
+        registers.set_return(SyscallReturn(crate::syscall_handler(
+            frame.number,
+            frame.args,
+        )));
+    }
+    let mut context = SignalContext::from_syscall(registers);
+    signal::deliver(&mut context);
+    context.apply_to_syscall(registers);
+    full_restore
+}
+
+/// Entry point of the `syscall` instruction, installed in `IA32_LSTAR` by [`crate::init_syscalls`].
+#[unsafe(naked)]
+pub extern "C" fn syscall_entry() {
+    core::arch::naked_asm!(
+        "swapgs",
+        "mov gs:[{user_rsp}], rsp",
+        "mov rsp, gs:[{kernel_rsp}]",
+        "push qword ptr [rip + {user_ss}]",
+        "push qword ptr gs:[{user_rsp}]",
+        "push r11",
+        "push qword ptr [rip + {user_cs}]",
+        "push rcx",
+        "push rax", "push rdi", "push rsi", "push rdx", "push r10", "push r8", "push r9",
+        "push rcx", "push r11",
+        "push rbx", "push rbp", "push r12", "push r13", "push r14", "push r15",
+        "mov rbp, rsp",
+        // SIMD state below the registers, 64-byte aligned as `xsave` requires
+        "sub rsp, qword ptr [rip + {simd_size}]",
+        "and rsp, -64",
+        "cld",
+        "mov rdi, rsp",
+        "call {simd_save}",
+        "mov rdi, rbp",
+        "call {dispatch}",
+        // RBX is restored from the block below, so it can hold the result across the restore
+        "mov ebx, eax",
+        "mov rdi, rsp",
+        "call {simd_restore}",
+        "mov rsp, rbp",
+        // `pop` leaves the flags alone, so the test survives until the branch
+        "test bl, bl",
+        "pop r15", "pop r14", "pop r13", "pop r12", "pop rbp", "pop rbx",
+        "pop r11", "pop rcx",
+        "pop r9", "pop r8", "pop r10", "pop rdx", "pop rsi", "pop rdi", "pop rax",
+        // `swapgs` leaves the flags alone as well
+        "swapgs",
+        "jnz 2f",
+        "pop rcx",
+        "add rsp, 8",
+        "pop r11",
+        "pop rsp",
+        "sysretq",
+        "2:",
+        "iretq",
+        user_rsp = const GS_USER_RSP_SCRATCH,
+        kernel_rsp = const GS_KERNEL_STACK_TOP,
+        user_cs = sym USER_CS,
+        user_ss = sym USER_SS,
+        dispatch = sym syscall_dispatch,
+        simd_size = sym simd::ENTRY_STATE_SIZE,
+        simd_save = sym simd::save_state,
+        simd_restore = sym simd::restore_state,
+    );
+}
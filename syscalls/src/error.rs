@@ -0,0 +1,144 @@
+//! # Syscall Errors
+//!
+//! A syscall returns a single value in RAX, so success and failure share one `u64`. Like Linux, failures are encoded as the *negated* error number: values in `-4095..=-1` (as `u64`, the top 4095 values) are errors, everything else is a result.
+//! This keeps every valid user address and every realistic byte count unambiguous, unlike a single `u64::MAX` sentinel.
+//!
+//! Handlers return a [`SyscallResult`]; the dispatcher encodes it with [`encode`]. User code decodes the raw value with [`decode`].
+//!
+//! ## Example
+//! ```ignore
+//! fn sys_close(args: [u64; 6]) -> SyscallResult {
+//!     let fd = args[0];
+//!     if fd > MAX_FD {
+//!         return Err(SyscallError::BadFileDescriptor);
+//!     }
+//!     Ok(0)
+//! }
+//! ```
+
+use core::fmt;
+
+pub use polished_syscall_abi::MAX_ERRNO;
+use polished_syscall_abi::SyscallReturn;
+
+/// Result of a syscall handler: the value handed back in RAX, or an error.
+pub type SyscallResult = Result<u64, SyscallError>;
+
+/// Errors returned by syscalls, with the stable error numbers of Linux x86_64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum SyscallError {
+    /// `EPERM`: operation not permitted.
+    NotPermitted = 1,
+    /// `ENOENT`: no such file or directory.
+    NoEntry = 2,
+    /// `ESRCH`: no such task.
+    NoSuchTask = 3,
+    /// `EINTR`: interrupted by a signal.
+    Interrupted = 4,
+    /// `EIO`: I/O error.
+    Io = 5,
+    /// `EBADF`: bad file descriptor.
+    BadFileDescriptor = 9,
+    /// `EAGAIN`: try again (a non-blocking call would block).
+    WouldBlock = 11,
+    /// `ENOMEM`: out of memory.
+    NoMemory = 12,
+    /// `EFAULT`: bad user address.
+    Fault = 14,
+    /// `EEXIST`: already exists.
+    Exists = 17,
+    /// `EINVAL`: invalid argument.
+    InvalidArgument = 22,
+    /// `EMFILE`: too many open files.
+    TooManyFiles = 24,
+    /// `ENOSYS`: syscall not implemented.
+    NoSys = 38,
+    /// `ETIMEDOUT`: timed out.
+    TimedOut = 110,
+}
+
+impl SyscallError {
+    /// Every error, for decoding.
+    const ALL: [SyscallError; 14] = [
+        SyscallError::NotPermitted,
+        SyscallError::NoEntry,
+        SyscallError::NoSuchTask,
+        SyscallError::Interrupted,
+        SyscallError::Io,
+        SyscallError::BadFileDescriptor,
+        SyscallError::WouldBlock,
+        SyscallError::NoMemory,
+        SyscallError::Fault,
+        SyscallError::Exists,
+        SyscallError::InvalidArgument,
+        SyscallError::TooManyFiles,
+        SyscallError::NoSys,
+        SyscallError::TimedOut,
+    ];
+
+    /// Returns the (positive) error number.
+    pub const fn errno(self) -> u64 {
+        self as u64
+    }
+
+    /// Returns the raw value returned in RAX for this error: the negated error number.
+    pub const fn to_raw(self) -> u64 {
+        SyscallReturn::error(self.errno()).0
+    }
+
+    /// Returns the error with error number `errno`, if it is known.
+    pub fn from_errno(errno: u64) -> Option<Self> {
+        Self::ALL.into_iter().find(|error| error.errno() == errno)
+    }
+
+    /// Returns the symbolic name, e.g. `"EINVAL"`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            SyscallError::NotPermitted => "EPERM",
+            SyscallError::NoEntry => "ENOENT",
+            SyscallError::NoSuchTask => "ESRCH",
+            SyscallError::Interrupted => "EINTR",
+            SyscallError::Io => "EIO",
+            SyscallError::BadFileDescriptor => "EBADF",
+            SyscallError::WouldBlock => "EAGAIN",
+            SyscallError::NoMemory => "ENOMEM",
+            SyscallError::Fault => "EFAULT",
+            SyscallError::Exists => "EEXIST",
+            SyscallError::InvalidArgument => "EINVAL",
+            SyscallError::TooManyFiles => "EMFILE",
+            SyscallError::NoSys => "ENOSYS",
+            SyscallError::TimedOut => "ETIMEDOUT",
+        }
+    }
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.errno())
+    }
+}
+
+/// Returns whether the raw RAX value `raw` encodes an error.
+pub const fn is_error(raw: u64) -> bool {
+    SyscallReturn(raw).is_error()
+}
+
+/// Encodes a handler result as the raw value returned in RAX.
+pub const fn encode(result: SyscallResult) -> u64 {
+    match result {
+        Ok(value) => value,
+        Err(error) => error.to_raw(),
+    }
+}
+
+/// Decodes the raw RAX value of a syscall.
+///
+/// Error numbers this kernel does not define decode as [`SyscallError::Io`].
+pub fn decode(raw: u64) -> SyscallResult {
+    if is_error(raw) {
+        Err(SyscallError::from_errno(raw.wrapping_neg()).unwrap_or(SyscallError::Io))
+    } else {
+        Ok(raw)
+    }
+}
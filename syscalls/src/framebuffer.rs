@@ -0,0 +1,134 @@
+//! # Framebuffer Syscall
+//!
+//! `fb_map(info)` maps the boot framebuffer into the calling task's `mmap` area and fills the user [`FbInfo`] at `info` with its address and geometry, so a user program can draw directly.
+//!
+//! Only the framebuffer's own pages are mapped (writable, never executable); the task gains no other access to physical memory. The kernel describes the framebuffer once with [`set_framebuffer`]; until then, and for framebuffers without direct pixel access, `fb_map` fails with `ENOENT`.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::abi::{SYS_FB_MAP, Syscall};
+use crate::error::{SyscallError, SyscallResult};
+use crate::mm::{self, PAGE_SIZE};
+use crate::{task, user};
+
+/// Pixels are stored as 32-bit red, green, blue, reserved.
+pub const FB_FORMAT_RGB: u64 = 0;
+/// Pixels are stored as 32-bit blue, green, red, reserved.
+pub const FB_FORMAT_BGR: u64 = 1;
+/// Pixels use custom bit masks.
+pub const FB_FORMAT_BITMASK: u64 = 2;
+
+/// Framebuffer description written by `fb_map`, laid out as seven `u64`s.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FbInfo {
+    /// User address of the mapped framebuffer.
+    pub address: u64,
+    /// Size of the framebuffer in bytes.
+    pub size: u64,
+    /// Width in pixels.
+    pub width: u64,
+    /// Height in pixels.
+    pub height: u64,
+    /// Pixels per row (may be larger than `width`).
+    pub stride: u64,
+    /// Bytes per pixel.
+    pub bytes_per_pixel: u64,
+    /// Pixel format (`FB_FORMAT_*`).
+    pub format: u64,
+}
+
+impl FbInfo {
+    /// Returns the in-memory representation handed to user code.
+    pub fn to_bytes(&self) -> [u8; 56] {
+        let fields = [
+            self.address,
+            self.size,
+            self.width,
+            self.height,
+            self.stride,
+            self.bytes_per_pixel,
+            self.format,
+        ];
+        let mut bytes = [0; 56];
+        for (chunk, field) in bytes.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&field.to_ne_bytes());
+        }
+        bytes
+    }
+}
+
+/// The framebuffer as described by the kernel: `address` is physical.
+static FRAMEBUFFER: Mutex<Option<FbInfo>> = Mutex::new(None);
+
+/// Describes the framebuffer that `fb_map` hands out.
+///
+/// `physical_address` and `size` must cover exactly the framebuffer memory; `format` is one of the `FB_FORMAT_*` constants.
+///
+/// # Example
+/// ```ignore
+/// polished_syscalls::framebuffer::set_framebuffer(fb.physical_address, fb.size as u64, fb.width as u64,
+///     fb.height as u64, fb.stride as u64, FB_FORMAT_BGR);
+/// ```
+pub fn set_framebuffer(
+    physical_address: u64,
+    size: u64,
+    width: u64,
+    height: u64,
+    stride: u64,
+    format: u64,
+) {
+    let info = FbInfo {
+        address: physical_address,
+        size,
+        width,
+        height,
+        stride,
+        bytes_per_pixel: 4,
+        format,
+    };
+    interrupts::without_interrupts(|| *FRAMEBUFFER.lock() = Some(info));
+}
+
+/// `fb_map(info)`: maps the framebuffer, writes its [`FbInfo`] to the user pointer `info`, and returns the mapped address.
+pub fn sys_fb_map(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::FbMap(ptr)) = Syscall::decode(SYS_FB_MAP, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    let framebuffer =
+        interrupts::without_interrupts(|| *FRAMEBUFFER.lock()).ok_or(SyscallError::NoEntry)?;
+    if !user::is_user_range(ptr, size_of::<FbInfo>() as u64) {
+        return Err(SyscallError::Fault);
+    }
+    let mapper = mm::mapper().ok_or(SyscallError::NoMemory)?;
+
+    let id = task::current_task_id();
+    let offset = framebuffer.address % PAGE_SIZE;
+    let first_frame = framebuffer.address - offset;
+    let len = mm::page_align_up(offset + framebuffer.size);
+    let start = mm::reserve_mmap_range(id, len)?;
+    // With the kernel's page attribute table, `WRITE_THROUGH` alone selects write-combining,
+    // like the kernel's own framebuffer mapping.
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::USER_ACCESSIBLE
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    for page in (0..len).step_by(PAGE_SIZE as usize) {
+        let frame = PhysAddr::new(first_frame + page);
+        if !(mapper.map_physical)(id, VirtAddr::new(start + page), frame, flags) {
+            mm::unmap_range(mapper, id, start, start + page);
+            return Err(SyscallError::NoMemory);
+        }
+    }
+
+    let info = FbInfo {
+        address: start + offset,
+        ..framebuffer
+    };
+    user::copy_to_user(ptr, &info.to_bytes())?;
+    Ok(info.address)
+}
@@ -0,0 +1,192 @@
+//! # File Syscalls
+//!
+//! `open`, `close`, `read`, and `lseek` on files of the `polished_files` VFS (initramfs today, ext2 later), so user programs can read configuration and data files.
+//!
+//! ## File Descriptors
+//!
+//! Each task has its own descriptor numbers. Descriptors 0 to 2 are the console (see [`crate::console`]), so files get the lowest free number from [`FIRST_FILE_FD`] up. The kernel keeps one table of open files for all tasks, each entry tagged with its owner; a task sees only its own entries, and [`close_all`] drops them all when it exits.
+//! Files are read-only: `open` accepts only `O_RDONLY`.
+
+use polished_files::vfs::{self, File, VfsError};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::abi::{SYS_CLOSE, SYS_LSEEK, SYS_OPEN, Syscall};
+use crate::error::{SyscallError, SyscallResult};
+use crate::task::{self, TaskId};
+use crate::user;
+
+/// Maximum number of files open at once, across all tasks.
+pub const MAX_OPEN_FILES: usize = 64;
+/// Maximum number of files a single task can have open.
+pub const MAX_FILES_PER_TASK: u64 = 16;
+/// First descriptor number handed out for files.
+pub const FIRST_FILE_FD: u64 = 3;
+/// Maximum length of a path passed to `open`, including the terminating NUL.
+pub const PATH_MAX: usize = 256;
+
+/// Open for reading only.
+pub const O_RDONLY: u64 = 0;
+
+/// `lseek` from the start of the file.
+pub const SEEK_SET: u64 = 0;
+/// `lseek` from the current offset.
+pub const SEEK_CUR: u64 = 1;
+/// `lseek` from the end of the file.
+pub const SEEK_END: u64 = 2;
+
+/// Size of the kernel buffer file data is copied through.
+const CHUNK_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+struct OpenFile {
+    task: TaskId,
+    fd: u64,
+    file: File,
+    offset: u64,
+}
+
+static OPEN_FILES: Mutex<[Option<OpenFile>; MAX_OPEN_FILES]> = Mutex::new([None; MAX_OPEN_FILES]);
+
+fn with_open_files<R>(f: impl FnOnce(&mut [Option<OpenFile>; MAX_OPEN_FILES]) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut OPEN_FILES.lock()))
+}
+
+/// Runs `f` on the open file `fd` of `task`.
+fn with_file<R>(
+    task: TaskId,
+    fd: u64,
+    f: impl FnOnce(&mut OpenFile) -> R,
+) -> Result<R, SyscallError> {
+    with_open_files(|files| {
+        files
+            .iter_mut()
+            .flatten()
+            .find(|open| open.task == task && open.fd == fd)
+            .map(f)
+            .ok_or(SyscallError::BadFileDescriptor)
+    })
+}
+
+impl From<VfsError> for SyscallError {
+    fn from(error: VfsError) -> Self {
+        match error {
+            VfsError::NotFound => SyscallError::NoEntry,
+            VfsError::InvalidPath | VfsError::IsDirectory => SyscallError::InvalidArgument,
+            VfsError::Io | VfsError::TooManyMounts | VfsError::AlreadyMounted => SyscallError::Io,
+        }
+    }
+}
+
+/// Closes every file of `task`. Called when the task exits.
+pub fn close_all(task: TaskId) {
+    with_open_files(|files| {
+        for slot in files.iter_mut() {
+            if slot.is_some_and(|open| open.task == task) {
+                *slot = None;
+            }
+        }
+    });
+}
+
+/// `open(path, flags)`: opens the file at the NUL-terminated user path and returns its descriptor.
+pub fn sys_open(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Open(ptr, flags)) = Syscall::decode(SYS_OPEN, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    if flags != O_RDONLY {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let mut buffer = [0u8; PATH_MAX];
+    let len = user::strncpy_from_user(&mut buffer, ptr)?;
+    if len == PATH_MAX {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let path = core::str::from_utf8(&buffer[..len]).map_err(|_| SyscallError::InvalidArgument)?;
+    let file = vfs::open(path)?;
+
+    let task = task::current_task_id();
+    with_open_files(|files| {
+        let fd = (FIRST_FILE_FD..FIRST_FILE_FD + MAX_FILES_PER_TASK)
+            .find(|&fd| {
+                !files
+                    .iter()
+                    .flatten()
+                    .any(|open| open.task == task && open.fd == fd)
+            })
+            .ok_or(SyscallError::TooManyFiles)?;
+        let slot = files
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(SyscallError::TooManyFiles)?;
+        *slot = Some(OpenFile {
+            task,
+            fd,
+            file,
+            offset: 0,
+        });
+        Ok(fd)
+    })
+}
+
+/// `close(fd)`: closes file descriptor `fd` and returns 0.
+pub fn sys_close(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Close(fd)) = Syscall::decode(SYS_CLOSE, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    let task = task::current_task_id();
+    with_open_files(|files| {
+        let slot = files
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|open| open.task == task && open.fd == fd))
+            .ok_or(SyscallError::BadFileDescriptor)?;
+        *slot = None;
+        Ok(0)
+    })
+}
+
+/// Reads up to `len` bytes of file `fd` at its offset into the user buffer at `ptr`, advancing the offset.
+///
+/// Called by `read` for descriptors other than standard input.
+pub fn read(fd: u64, ptr: u64, len: u64) -> SyscallResult {
+    let task = task::current_task_id();
+    let (file, offset) = with_file(task, fd, |open| (open.file, open.offset))?;
+    if len != 0 && !user::is_user_range(ptr, len) {
+        return Err(SyscallError::Fault);
+    }
+    let mut buffer = [0u8; CHUNK_SIZE];
+    let mut total = 0;
+    while total < len {
+        let chunk = &mut buffer[..((len - total) as usize).min(CHUNK_SIZE)];
+        let read = file.read_at(offset + total, chunk)?;
+        if read == 0 {
+            break;
+        }
+        user::copy_to_user(ptr + total, &chunk[..read])?;
+        total += read as u64;
+    }
+    with_file(task, fd, |open| open.offset = offset + total)?;
+    Ok(total)
+}
+
+/// `lseek(fd, offset, whence)`: moves the offset of file `fd` and returns the new offset.
+pub fn sys_lseek(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Lseek(fd, offset, whence)) = Syscall::decode(SYS_LSEEK, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    let offset = offset as i64;
+    with_file(task::current_task_id(), fd, |open| {
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => open.offset as i64,
+            SEEK_END => open.file.size() as i64,
+            _ => return Err(SyscallError::InvalidArgument),
+        };
+        let new = base
+            .checked_add(offset)
+            .filter(|&new| new >= 0)
+            .ok_or(SyscallError::InvalidArgument)?;
+        open.offset = new as u64;
+        Ok(new as u64)
+    })?
+}
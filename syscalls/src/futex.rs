@@ -0,0 +1,121 @@
+//! # Futexes
+//!
+//! A futex ("fast userspace mutex") lets user code build mutexes and condition variables that only enter the kernel when they must wait. The lock word lives in user memory; the kernel only provides:
+//! - **`futex_wait(addr, expected)`:** If the 32-bit word at `addr` still equals `expected`, block until woken. Otherwise return `EAGAIN` at once, because the word changed between the user's check and the syscall.
+//! - **`futex_wake(addr, n)`:** Wake up to `n` tasks waiting on `addr`, returning how many were woken.
+//!
+//! Both are reached through the Linux-compatible `futex(addr, op, val)` syscall with `FUTEX_WAIT` or `FUTEX_WAKE`.
+//!
+//! ## Wait Queues
+//!
+//! Waiters are kept in a fixed hash table of [`BUCKETS`] wait queues, indexed by a hash of the futex address, so a wake only scans the waiters that can match.
+//! Futexes are keyed by user virtual address. Tasks in different address spaces using the same address share a key, which can only cause spurious wakeups; as with any futex, callers re-check the word after waking.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::abi::{SYS_FUTEX, Syscall};
+use crate::error::{SyscallError, SyscallResult};
+use crate::task::{self, TaskId};
+use crate::user;
+
+/// `futex` operation: wait while the word equals the value.
+pub const FUTEX_WAIT: u64 = 0;
+/// `futex` operation: wake waiters.
+pub const FUTEX_WAKE: u64 = 1;
+
+/// Number of wait queues in the hash table.
+pub const BUCKETS: usize = 64;
+/// Maximum number of waiters per wait queue.
+pub const WAITERS_PER_BUCKET: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Waiter {
+    task: TaskId,
+    addr: u64,
+    /// Set by `futex_wake`; the waiter frees its entry once it sees it.
+    woken: bool,
+}
+
+type WaitQueue = Mutex<[Option<Waiter>; WAITERS_PER_BUCKET]>;
+
+static QUEUES: [WaitQueue; BUCKETS] = [const { Mutex::new([None; WAITERS_PER_BUCKET]) }; BUCKETS];
+
+fn bucket(addr: u64) -> usize {
+    // Fibonacci hashing of the word index
+    ((addr >> 2).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 58) as usize % BUCKETS
+}
+
+fn with_queue<R>(addr: u64, f: impl FnOnce(&mut [Option<Waiter>; WAITERS_PER_BUCKET]) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut QUEUES[bucket(addr)].lock()))
+}
+
+fn read_word(addr: u64) -> Result<u32, SyscallError> {
+    if !addr.is_multiple_of(4) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let mut bytes = [0u8; 4];
+    user::copy_from_user(&mut bytes, addr)?;
+    Ok(u32::from_ne_bytes(bytes))
+}
+
+/// Blocks the current task while the word at `addr` equals `expected`, or until a signal arrives (`EINTR`).
+pub fn futex_wait(addr: u64, expected: u32) -> SyscallResult {
+    let task = task::current_task_id();
+    // The word is checked with the queue locked, so a wake between the check and queuing cannot be missed.
+    let position = with_queue(addr, |queue| {
+        if read_word(addr)? != expected {
+            return Err(SyscallError::WouldBlock);
+        }
+        let position = queue
+            .iter()
+            .position(|waiter| waiter.is_none())
+            .ok_or(SyscallError::NoMemory)?;
+        queue[position] = Some(Waiter {
+            task,
+            addr,
+            woken: false,
+        });
+        Ok(position)
+    })?;
+    let woken = task::block_current_until(|| {
+        with_queue(addr, |queue| {
+            queue[position].is_some_and(|waiter| waiter.woken)
+        })
+    });
+    with_queue(addr, |queue| queue[position] = None);
+    woken.map(|_| 0)
+}
+
+/// Wakes up to `count` tasks waiting on `addr`, returning how many were woken.
+pub fn futex_wake(addr: u64, count: u64) -> SyscallResult {
+    if !addr.is_multiple_of(4) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    with_queue(addr, |queue| {
+        let mut woken = 0;
+        for entry in queue.iter_mut() {
+            if woken == count {
+                break;
+            }
+            if let Some(waiter) = entry
+                && waiter.addr == addr
+                && !waiter.woken
+            {
+                waiter.woken = true;
+                task::wake_task(waiter.task);
+                woken += 1;
+            }
+        }
+        Ok(woken)
+    })
+}
+
+/// `futex(addr, op, val)`: `FUTEX_WAIT` returns 0 once woken, `FUTEX_WAKE` returns the number of tasks woken.
+pub fn sys_futex(args: [u64; 6]) -> SyscallResult {
+    match Syscall::decode(SYS_FUTEX, args) {
+        Some(Syscall::FutexWait(addr, expected)) => futex_wait(addr, expected),
+        Some(Syscall::FutexWake(addr, count)) => futex_wake(addr, count),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
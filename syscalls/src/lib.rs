@@ -0,0 +1,158 @@
+//! # System Calls
+//!
+//! This crate implements the kernel side of system calls on x86_64: the `syscall`/`sysret` fast path, the entry stub that moves from the user stack to a kernel stack, and the dispatch of syscall numbers to their implementations.
+//!
+//! ## How `syscall` Works
+//!
+//! The `syscall` instruction jumps from ring 3 to ring 0 without going through the IDT:
+//! - RIP is loaded from `IA32_LSTAR` (the entry stub), and the user RIP is saved in RCX.
+//! - RFLAGS is saved in R11 and then masked with `IA32_FMASK` (interrupts are disabled on entry).
+//! - CS and SS are loaded from `IA32_STAR`. The stack pointer is **not** changed: the entry stub must switch to a kernel stack itself.
+//!
+//! `sysret` reverses this. It derives the user selectors from `IA32_STAR` as well, which dictates the GDT layout (user data directly before user code, see the `polished_gdt` crate).
+//! None of this works until `EFER.SCE` is set; [`init_syscalls`] programs all of these registers.
+//!
+//! ## Calling Convention
+//!
+//! The kernel uses the Linux x86_64 register convention, defined once for the kernel and user programs by the `polished_syscall_abi` crate:
+//! - **RAX:** Syscall number on entry, return value on exit. Errors are returned as negated error numbers in `-4095..=-1`.
+//! - **RDI, RSI, RDX, R10, R8, R9:** Arguments 1-6 (R10 replaces RCX, which `syscall` overwrites).
+//! - **RCX, R11:** Clobbered. Every other register is preserved.
+//!
+//! ## Modules
+//! - `abi`: Syscall numbers and the typed [`abi::Syscall`] decoding of a call.
+//! - `clock`: `clock_gettime` for the monotonic (HPET or tick) and wall (RTC) clocks.
+//! - `console`: `read` from standard input (keyboard) and `write` to standard output and error (serial log and log sinks).
+//! - `entry`: The naked `syscall_entry` stub and the kernel stack it switches to.
+//! - `error`: `SyscallError` and the negated-errno encoding of syscall return values.
+//! - `framebuffer`: `fb_map`, mapping the boot framebuffer into a task for userspace graphics.
+//! - `fs`: `open`, `close`, `read`, and `lseek` on VFS files, with per-task file descriptors.
+//! - `futex`: `futex` wait and wake on user words, with hashed wait queues.
+//! - `signal`: `kill`, `sigaction`, and `sigreturn`, with delivery on syscall return and timer interrupts.
+//! - `mm`: `brk` and `mmap`, mapping pages through the memory subsystem's `UserPageMapper`.
+//! - `task`: Minimal per-task bookkeeping (ID, parent, state, exit code), `exit`, the identity syscalls (`getpid`, `gettid`, `getppid`), and `sched_yield`.
+//! - `table`: Registration of syscall handlers by number (`register_syscall`).
+//! - `trace`: Runtime-toggleable, rate-limited `strace`-style logging of every syscall.
+//! - `vdso`: The read-only time page mapped into every task, read without a syscall.
+//! - `user`: Fault-tolerant `copy_from_user`, `copy_to_user`, and `strncpy_from_user`; handlers never dereference user pointers directly.
+//!
+//! ## Example
+//! ```ignore
+//! polished_gdt::init_gdt();
+//! polished_syscalls::init_syscalls();
+//! ```
+
+#![no_std]
+
+use x86_64::VirtAddr;
+use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+
+/// Syscall numbers and decoding.
+pub mod abi;
+/// Time syscalls.
+pub mod clock;
+/// Console input and output syscalls.
+pub mod console;
+/// Syscall entry stub and kernel stack switching.
+pub mod entry;
+/// Errno-style syscall errors and their return value encoding.
+pub mod error;
+/// Framebuffer mapping syscall.
+pub mod framebuffer;
+/// File syscalls backed by the VFS.
+pub mod fs;
+/// Futex wait/wake primitives.
+pub mod futex;
+/// Memory allocation syscalls.
+pub mod mm;
+/// Signal delivery.
+pub mod signal;
+/// Registration-based syscall table.
+pub mod table;
+/// Task bookkeeping and process teardown.
+pub mod task;
+/// Syscall tracing.
+pub mod trace;
+/// Safe access to user memory.
+pub mod user;
+/// Shared time page.
+pub mod vdso;
+
+pub use abi::Syscall;
+pub use entry::SyscallRegisters;
+pub use error::{SyscallError, SyscallResult};
+pub use polished_syscall_abi::{SyscallFrame, SyscallReturn};
+pub use table::{SyscallHandler, SyscallTableError, register_syscall, unregister_syscall};
+
+/// Enables the `syscall`/`sysret` instructions and points them at [`entry::syscall_entry`].
+///
+/// This function:
+/// 1. Sets `EFER.SCE` (System Call Extensions).
+/// 2. Writes the kernel and user selectors of the GDT (`polished_gdt::selectors`) to `IA32_STAR`.
+/// 3. Writes the entry stub address to `IA32_LSTAR`.
+/// 4. Writes `IA32_FMASK` so that interrupts, single-stepping, and the direction and alignment-check flags are cleared on entry.
+/// 5. Installs the page fault hook that lets user copies fail with `EFAULT` (see [`user`]), and the timer's user return hook that delivers signals (see [`signal`]).
+/// 6. Registers the built-in syscalls (`read`, `write`, `open`, `close`, `lseek`, `mmap`, `brk`, `sigaction`, `sched_yield`, `getpid`, `exit`, `kill`, `getppid`, `gettid`, `clock_gettime`, `futex`, `fb_map`). `sigreturn` is handled by the entry stub.
+///
+/// Must be called after the GDT has been loaded, which also provides the kernel stack syscalls start on (see [`entry::set_kernel_stack`]).
+pub fn init_syscalls() {
+    let selectors = polished_gdt::selectors();
+    Star::write(
+        selectors.user_code,
+        selectors.user_data,
+        selectors.kernel_code,
+        selectors.kernel_data,
+    )
+    .expect("GDT layout does not match the syscall/sysret selector requirements");
+    entry::set_user_selectors(selectors.user_code, selectors.user_data);
+    LStar::write(VirtAddr::new(entry::syscall_entry as *const () as u64));
+    SFMask::write(
+        RFlags::INTERRUPT_FLAG
+            | RFlags::TRAP_FLAG
+            | RFlags::DIRECTION_FLAG
+            | RFlags::ALIGNMENT_CHECK,
+    );
+    unsafe { Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS)) };
+    user::install_fault_handler();
+    polished_interrupts::irq::set_user_return_hook(signal::deliver_on_interrupt_return);
+    let _ = register_syscall(abi::SYS_READ, console::sys_read);
+    let _ = register_syscall(abi::SYS_WRITE, console::sys_write);
+    let _ = register_syscall(abi::SYS_OPEN, fs::sys_open);
+    let _ = register_syscall(abi::SYS_CLOSE, fs::sys_close);
+    let _ = register_syscall(abi::SYS_LSEEK, fs::sys_lseek);
+    let _ = register_syscall(abi::SYS_MMAP, mm::sys_mmap);
+    let _ = register_syscall(abi::SYS_BRK, mm::sys_brk);
+    let _ = register_syscall(abi::SYS_SIGACTION, signal::sys_sigaction);
+    let _ = register_syscall(abi::SYS_SCHED_YIELD, task::sys_sched_yield);
+    let _ = register_syscall(abi::SYS_GETPID, task::sys_getpid);
+    let _ = register_syscall(abi::SYS_EXIT, sys_exit);
+    let _ = register_syscall(abi::SYS_KILL, signal::sys_kill);
+    let _ = register_syscall(abi::SYS_GETPPID, task::sys_getppid);
+    let _ = register_syscall(abi::SYS_GETTID, task::sys_gettid);
+    let _ = register_syscall(abi::SYS_CLOCK_GETTIME, clock::sys_clock_gettime);
+    let _ = register_syscall(abi::SYS_FUTEX, futex::sys_futex);
+    let _ = register_syscall(abi::SYS_FB_MAP, framebuffer::sys_fb_map);
+}
+
+/// `exit(code)`: terminates the calling task. Never returns to user mode.
+fn sys_exit(args: [u64; 6]) -> SyscallResult {
+    task::exit_current(args[0] as i32)
+}
+
+/// Dispatches the system call `number` with its six arguments to the handler registered in [`table`] and returns the value handed back in RAX.
+///
+/// While tracing is enabled (see [`trace`]), every call is logged with its result.
+/// Handler errors are returned as negated error numbers (see [`error`]). Unknown numbers are logged and return `-ENOSYS`.
+pub fn syscall_handler(number: u64, args: [u64; 6]) -> u64 {
+    trace::enter(number, args);
+    let result = match table::syscall(number) {
+        Some(handler) => handler(args),
+        None => {
+            polished_serial_logging::warn!("Unknown syscall {} (args {:#x?})", number, args);
+            Err(SyscallError::NoSys)
+        }
+    };
+    trace::exit(number, args, &result);
+    error::encode(result)
+}
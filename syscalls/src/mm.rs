@@ -0,0 +1,186 @@
+//! # Memory Allocation Syscalls
+//!
+//! `brk` and `mmap` give user programs memory to build a heap on. Both hand out whole 4 KiB pages in the calling task's address space:
+//! - **`brk(addr)`:** Moves the program break, the end of a contiguous heap that starts just above the loaded image ([`crate::task::set_heap_start`]). Growing the break maps fresh pages, shrinking it unmaps them. `brk(0)` returns the current break; like Linux, a failed `brk` returns the unchanged break rather than an error.
+//! - **`mmap(len, prot)`:** Maps `len` bytes (rounded up to pages) of zeroed, anonymous memory with the `PROT_*` protections at the next free address of the mmap area, and returns that address. When the memory subsystem supports it, the range is only reserved and each page is backed by a zeroed frame on first touch, so large mappings cost nothing up front.
+//!
+//! ## User Address Layout
+//!
+//! | Range | Use |
+//! | --- | --- |
+//! | `DEFAULT_HEAP_START..MMAP_START` | `brk` heap (unless the loader moves its start) |
+//! | `MMAP_START..MMAP_END` | `mmap` area, growing up |
+//! | `MMAP_END` | Shared time page (see [`crate::vdso`]) |
+//! | `MMAP_END + PAGE_SIZE..USER_SPACE_END` | Reserved for user stacks |
+//!
+//! ## Page Mapping
+//!
+//! Frames and page tables belong to the memory subsystem, which installs a [`UserPageMapper`] with [`set_user_page_mapper`]. Its `map` function allocates a zeroed physical frame and maps it at a page of the task's user page tables with the given flags, `map_physical` maps an existing frame of device memory, `reserve_on_demand` sets up a range to be populated by the page fault handler, and `unmap` reverses any of them. Without a mapper, `brk` cannot grow and `mmap` fails with `ENOMEM`.
+
+use spin::Mutex;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::abi::{SYS_BRK, SYS_MMAP, Syscall};
+use crate::error::{SyscallError, SyscallResult};
+use crate::task::{self, TaskId};
+
+/// Size of the pages handed out by `brk` and `mmap`.
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Default start of the `brk` heap.
+pub const DEFAULT_HEAP_START: u64 = 0x0000_1000_0000_0000;
+/// Start of the `mmap` area (and limit of the `brk` heap).
+pub const MMAP_START: u64 = 0x0000_4000_0000_0000;
+/// End of the `mmap` area.
+pub const MMAP_END: u64 = 0x0000_7000_0000_0000;
+
+/// Pages may be read.
+pub const PROT_READ: u64 = 1 << 0;
+/// Pages may be written.
+pub const PROT_WRITE: u64 = 1 << 1;
+/// Pages may be executed.
+pub const PROT_EXEC: u64 = 1 << 2;
+
+/// Page mapping functions provided by the memory subsystem.
+#[derive(Debug, Clone, Copy)]
+pub struct UserPageMapper {
+    /// Allocates a zeroed frame and maps it at `page` in the user page tables of `task`. Returns `false` if out of memory.
+    pub map: fn(task: TaskId, page: VirtAddr, flags: PageTableFlags) -> bool,
+    /// Maps the existing physical frame `frame` (device memory such as the framebuffer) at `page` in the user page tables of `task`.
+    pub map_physical:
+        fn(task: TaskId, page: VirtAddr, frame: PhysAddr, flags: PageTableFlags) -> bool,
+    /// Unmaps `page` from the user page tables of `task`, freeing its frame unless it was mapped with `map_physical`. Pages of a range reserved with `reserve_on_demand` that were never touched are ignored.
+    pub unmap: fn(task: TaskId, page: VirtAddr),
+    /// Reserves the pages of `start..end` in the address space of `task` to be mapped to zeroed frames with `flags` on first touch. Returns `false` if the range cannot be reserved, in which case the pages are mapped up front instead.
+    pub reserve_on_demand:
+        fn(task: TaskId, start: VirtAddr, end: VirtAddr, flags: PageTableFlags) -> bool,
+}
+
+static MAPPER: Mutex<Option<UserPageMapper>> = Mutex::new(None);
+
+/// Installs the functions used to map and unmap user pages.
+pub fn set_user_page_mapper(mapper: UserPageMapper) {
+    x86_64::instructions::interrupts::without_interrupts(|| *MAPPER.lock() = Some(mapper));
+}
+
+pub(crate) fn mapper() -> Option<UserPageMapper> {
+    x86_64::instructions::interrupts::without_interrupts(|| *MAPPER.lock())
+}
+
+/// Rounds `addr` up to a multiple of [`PAGE_SIZE`].
+pub const fn page_align_up(addr: u64) -> u64 {
+    (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// Converts `PROT_*` bits to page table flags for a user page, or `None` if unknown bits are set.
+pub fn prot_to_flags(prot: u64) -> Option<PageTableFlags> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return None;
+    }
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if prot & PROT_WRITE != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Some(flags)
+}
+
+/// Maps the pages of `start..end`, unmapping them again if one fails.
+fn map_range(
+    mapper: UserPageMapper,
+    task: TaskId,
+    start: u64,
+    end: u64,
+    flags: PageTableFlags,
+) -> bool {
+    let mut page = start;
+    while page < end {
+        if !(mapper.map)(task, VirtAddr::new(page), flags) {
+            unmap_range(mapper, task, start, page);
+            return false;
+        }
+        page += PAGE_SIZE;
+    }
+    true
+}
+
+pub(crate) fn unmap_range(mapper: UserPageMapper, task: TaskId, start: u64, end: u64) {
+    for page in (start..end).step_by(PAGE_SIZE as usize) {
+        (mapper.unmap)(task, VirtAddr::new(page));
+    }
+}
+
+/// `brk(addr)`: returns the new program break, or the unchanged one if `addr` is 0 or cannot be honored.
+pub fn sys_brk(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Brk(addr)) = Syscall::decode(SYS_BRK, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    let id = task::current_task_id();
+    let Some(current) = task::task(id) else {
+        return Err(SyscallError::NoMemory);
+    };
+    if addr < current.heap_start || addr > MMAP_START {
+        return Ok(current.brk);
+    }
+    let old_end = page_align_up(current.brk);
+    let new_end = page_align_up(addr);
+    if new_end != old_end {
+        let Some(mapper) = mapper() else {
+            return Ok(current.brk);
+        };
+        let flags = PageTableFlags::PRESENT
+            | PageTableFlags::USER_ACCESSIBLE
+            | PageTableFlags::WRITABLE
+            | PageTableFlags::NO_EXECUTE;
+        if new_end > old_end {
+            if !map_range(mapper, id, old_end, new_end, flags) {
+                return Ok(current.brk);
+            }
+        } else {
+            unmap_range(mapper, id, new_end, old_end);
+        }
+    }
+    task::update_task(id, |task| task.brk = addr);
+    Ok(addr)
+}
+
+/// Reserves `len` bytes (rounded up to pages) of the `mmap` area of `task`, returning the start address.
+pub(crate) fn reserve_mmap_range(task: TaskId, len: u64) -> Result<u64, SyscallError> {
+    task::update_task(task, |task| {
+        let start = task.mmap_next;
+        let end = start
+            .checked_add(page_align_up(len))
+            .filter(|&end| end <= MMAP_END)
+            .ok_or(SyscallError::NoMemory)?;
+        task.mmap_next = end;
+        Ok(start)
+    })
+    .ok_or(SyscallError::NoMemory)?
+}
+
+/// `mmap(len, prot)`: returns the user address of the new mapping, or an error.
+pub fn sys_mmap(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Mmap(len, prot)) = Syscall::decode(SYS_MMAP, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    let Some(flags) = prot_to_flags(prot) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    if len == 0 || len > MMAP_END - MMAP_START {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let id = task::current_task_id();
+    let mapper = mapper().ok_or(SyscallError::NoMemory)?;
+    let start = reserve_mmap_range(id, len)?;
+    let end = start + page_align_up(len);
+    if (mapper.reserve_on_demand)(id, VirtAddr::new(start), VirtAddr::new(end), flags) {
+        return Ok(start);
+    }
+    if !map_range(mapper, id, start, end, flags) {
+        return Err(SyscallError::NoMemory);
+    }
+    Ok(start)
+}
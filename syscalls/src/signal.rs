@@ -0,0 +1,569 @@
+//! # Signals
+//!
+//! A minimal form of POSIX signals, enough to interrupt or terminate a user program from the kernel (e.g. Ctrl-C in a shell) or from another task:
+//! - **`kill(pid, sig)`:** Marks signal `sig` pending for task `pid` ([`send_signal`] from kernel code). Signal 0 only checks that the task exists.
+//! - **`sigaction(sig, act, oldact)`:** Installs a handler for `sig`, with the Linux `struct sigaction` layout ([`SigAction`]).
+//! - **`sigreturn()`:** Called by the handler's restorer when the handler returns; resumes the interrupted code.
+//!
+//! ## Delivery
+//!
+//! Pending signals are delivered when the task is about to return to user mode: at the end of every syscall, and when the timer interrupt preempts user code (through `polished_interrupts::irq::set_user_return_hook`), so a task looping without syscalls can still be stopped.
+//! For the lowest pending signal that is not blocked:
+//! - **Default action** ([`SIG_DFL`]): the task exits with code `128 + sig`, as shells report it. [`SIGCHLD`] is ignored instead.
+//! - **Ignored** ([`SIG_IGN`]): the signal is discarded.
+//! - **Handler:** a [`SignalFrame`] with the interrupted registers is pushed on the user stack, below the 128-byte red zone, with the restorer as return address. The task resumes at the handler with the signal number in RDI and the signal blocked. The restorer calls `sigreturn`, which restores the saved registers and signal mask and returns with `iretq`, since `sysret` cannot restore RCX and R11.
+//!
+//! A task blocked in a syscall (`read` from the keyboard, `futex` wait) is woken by a signal and the syscall fails with `EINTR`. Syscalls are not restarted.
+//! [`SIGKILL`] cannot be caught, blocked, or ignored.
+
+use polished_interrupts::context::InterruptContext;
+use x86_64::registers::rflags::RFlags;
+
+use crate::abi::{SYS_KILL, SYS_SIGACTION, Syscall};
+use crate::entry::SyscallRegisters;
+use crate::error::{SyscallError, SyscallResult};
+use crate::task::{self, TaskId, TaskState};
+use crate::user;
+
+/// Number of signal numbers; valid signals are `1..NSIG`.
+pub const NSIG: usize = 32;
+
+/// Hangup.
+pub const SIGHUP: u64 = 1;
+/// Interrupt from the keyboard (Ctrl-C).
+pub const SIGINT: u64 = 2;
+/// Quit from the keyboard.
+pub const SIGQUIT: u64 = 3;
+/// Illegal instruction.
+pub const SIGILL: u64 = 4;
+/// Abort.
+pub const SIGABRT: u64 = 6;
+/// Kill; cannot be caught or ignored.
+pub const SIGKILL: u64 = 9;
+/// User-defined signal 1.
+pub const SIGUSR1: u64 = 10;
+/// Invalid memory reference.
+pub const SIGSEGV: u64 = 11;
+/// User-defined signal 2.
+pub const SIGUSR2: u64 = 12;
+/// Broken pipe.
+pub const SIGPIPE: u64 = 13;
+/// Timer expired.
+pub const SIGALRM: u64 = 14;
+/// Termination request.
+pub const SIGTERM: u64 = 15;
+/// Child task exited; ignored by default.
+pub const SIGCHLD: u64 = 17;
+
+/// Handler value selecting the default action.
+pub const SIG_DFL: u64 = 0;
+/// Handler value ignoring the signal.
+pub const SIG_IGN: u64 = 1;
+/// `sa_flags` bit: `sa_restorer` is valid. Required for handlers, as there is no kernel-provided restorer.
+pub const SA_RESTORER: u64 = 0x0400_0000;
+
+/// Size of the red zone below the user stack pointer that signal frames skip.
+const RED_ZONE: u64 = 128;
+
+/// RFLAGS bits user code may change through `sigreturn`.
+const USER_RFLAGS: RFlags = RFlags::CARRY_FLAG
+    .union(RFlags::PARITY_FLAG)
+    .union(RFlags::AUXILIARY_CARRY_FLAG)
+    .union(RFlags::ZERO_FLAG)
+    .union(RFlags::SIGN_FLAG)
+    .union(RFlags::TRAP_FLAG)
+    .union(RFlags::DIRECTION_FLAG)
+    .union(RFlags::OVERFLOW_FLAG)
+    .union(RFlags::ALIGNMENT_CHECK);
+
+/// Action for one signal, laid out like the Linux x86_64 kernel `struct sigaction` (four `u64`s).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SigAction {
+    /// Handler address, [`SIG_DFL`], or [`SIG_IGN`].
+    pub handler: u64,
+    /// `SA_*` flags.
+    pub flags: u64,
+    /// Code the handler returns to, which must call `sigreturn`.
+    pub restorer: u64,
+    /// Signals blocked while the handler runs (bit `n` for signal `n`), in addition to the signal itself.
+    pub mask: u64,
+}
+
+impl SigAction {
+    /// The default action.
+    pub const DEFAULT: SigAction = SigAction {
+        handler: SIG_DFL,
+        flags: 0,
+        restorer: 0,
+        mask: 0,
+    };
+
+    /// Returns the in-memory representation exchanged with user code.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        words_to_bytes([self.handler, self.flags, self.restorer, self.mask])
+    }
+
+    /// Reads the in-memory representation exchanged with user code.
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let [handler, flags, restorer, mask] = bytes_to_words(bytes);
+        SigAction {
+            handler,
+            flags,
+            restorer,
+            mask,
+        }
+    }
+}
+
+/// Per-task signal state, kept in the task's [`task::Task`] bookkeeping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalState {
+    /// Pending signals (bit `n` for signal `n`).
+    pub pending: u32,
+    /// Blocked signals (bit `n` for signal `n`).
+    pub blocked: u32,
+    /// Action for each signal number.
+    pub actions: [SigAction; NSIG],
+}
+
+impl SignalState {
+    /// No signals pending or blocked, default actions everywhere.
+    pub const fn new() -> Self {
+        SignalState {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::DEFAULT; NSIG],
+        }
+    }
+
+    /// Returns the lowest pending, unblocked signal.
+    fn next_deliverable(&self) -> Option<u64> {
+        let deliverable = self.pending & !self.blocked;
+        (deliverable != 0).then(|| deliverable.trailing_zeros() as u64)
+    }
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// User registers saved in a [`SignalFrame`] and restored by `sigreturn`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignalContext {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+}
+
+impl SignalContext {
+    /// Captures the registers saved by the syscall entry stub.
+    pub fn from_syscall(r: &SyscallRegisters) -> Self {
+        SignalContext {
+            rax: r.rax,
+            rbx: r.rbx,
+            rcx: r.rcx,
+            rdx: r.rdx,
+            rsi: r.rsi,
+            rdi: r.rdi,
+            rbp: r.rbp,
+            r8: r.r8,
+            r9: r.r9,
+            r10: r.r10,
+            r11: r.r11,
+            r12: r.r12,
+            r13: r.r13,
+            r14: r.r14,
+            r15: r.r15,
+            rip: r.rip,
+            rflags: r.rflags,
+            rsp: r.rsp,
+        }
+    }
+
+    /// Writes the registers back to the syscall entry stub's save area.
+    pub fn apply_to_syscall(&self, r: &mut SyscallRegisters) {
+        r.rax = self.rax;
+        r.rbx = self.rbx;
+        r.rcx = self.rcx;
+        r.rdx = self.rdx;
+        r.rsi = self.rsi;
+        r.rdi = self.rdi;
+        r.rbp = self.rbp;
+        r.r8 = self.r8;
+        r.r9 = self.r9;
+        r.r10 = self.r10;
+        r.r11 = self.r11;
+        r.r12 = self.r12;
+        r.r13 = self.r13;
+        r.r14 = self.r14;
+        r.r15 = self.r15;
+        r.rip = self.rip;
+        r.rflags = self.rflags;
+        r.rsp = self.rsp;
+    }
+
+    /// Captures the registers saved by an interrupt entry stub.
+    pub fn from_interrupt(context: &InterruptContext) -> Self {
+        let r = &context.registers;
+        SignalContext {
+            rax: r.rax,
+            rbx: r.rbx,
+            rcx: r.rcx,
+            rdx: r.rdx,
+            rsi: r.rsi,
+            rdi: r.rdi,
+            rbp: r.rbp,
+            r8: r.r8,
+            r9: r.r9,
+            r10: r.r10,
+            r11: r.r11,
+            r12: r.r12,
+            r13: r.r13,
+            r14: r.r14,
+            r15: r.r15,
+            rip: context.rip,
+            rflags: context.rflags,
+            rsp: context.rsp,
+        }
+    }
+
+    /// Writes the registers back to an interrupt entry stub's save area.
+    pub fn apply_to_interrupt(&self, context: &mut InterruptContext) {
+        let r = &mut context.registers;
+        r.rax = self.rax;
+        r.rbx = self.rbx;
+        r.rcx = self.rcx;
+        r.rdx = self.rdx;
+        r.rsi = self.rsi;
+        r.rdi = self.rdi;
+        r.rbp = self.rbp;
+        r.r8 = self.r8;
+        r.r9 = self.r9;
+        r.r10 = self.r10;
+        r.r11 = self.r11;
+        r.r12 = self.r12;
+        r.r13 = self.r13;
+        r.r14 = self.r14;
+        r.r15 = self.r15;
+        context.rip = self.rip;
+        context.rflags = self.rflags;
+        context.rsp = self.rsp;
+    }
+}
+
+/// Number of `u64`s in a [`SignalFrame`].
+const FRAME_WORDS: usize = 20;
+
+/// Frame pushed on the user stack for a handler, directly above the return address (the restorer).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignalFrame {
+    /// The signal being handled.
+    pub signal: u64,
+    /// Signal mask to restore on `sigreturn`.
+    pub blocked: u64,
+    /// Interrupted registers.
+    pub context: SignalContext,
+}
+
+impl SignalFrame {
+    fn to_words(self) -> [u64; FRAME_WORDS] {
+        let c = self.context;
+        [
+            self.signal,
+            self.blocked,
+            c.rax,
+            c.rbx,
+            c.rcx,
+            c.rdx,
+            c.rsi,
+            c.rdi,
+            c.rbp,
+            c.r8,
+            c.r9,
+            c.r10,
+            c.r11,
+            c.r12,
+            c.r13,
+            c.r14,
+            c.r15,
+            c.rip,
+            c.rflags,
+            c.rsp,
+        ]
+    }
+
+    fn from_words(words: [u64; FRAME_WORDS]) -> Self {
+        let [
+            signal,
+            blocked,
+            rax,
+            rbx,
+            rcx,
+            rdx,
+            rsi,
+            rdi,
+            rbp,
+            r8,
+            r9,
+            r10,
+            r11,
+            r12,
+            r13,
+            r14,
+            r15,
+            rip,
+            rflags,
+            rsp,
+        ] = words;
+        SignalFrame {
+            signal,
+            blocked,
+            context: SignalContext {
+                rax,
+                rbx,
+                rcx,
+                rdx,
+                rsi,
+                rdi,
+                rbp,
+                r8,
+                r9,
+                r10,
+                r11,
+                r12,
+                r13,
+                r14,
+                r15,
+                rip,
+                rflags,
+                rsp,
+            },
+        }
+    }
+}
+
+fn words_to_bytes<const N: usize, const B: usize>(words: [u64; N]) -> [u8; B] {
+    let mut bytes = [0; B];
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(words) {
+        chunk.copy_from_slice(&word.to_ne_bytes());
+    }
+    bytes
+}
+
+fn bytes_to_words<const N: usize, const B: usize>(bytes: &[u8; B]) -> [u64; N] {
+    let mut words = [0; N];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(8)) {
+        *word = u64::from_ne_bytes(chunk.try_into().unwrap());
+    }
+    words
+}
+
+fn is_valid(sig: u64) -> bool {
+    (1..NSIG as u64).contains(&sig)
+}
+
+/// Marks signal `sig` pending for task `id` and wakes it if it is blocked. Signal 0 only checks that the task is alive.
+///
+/// # Example
+/// ```ignore
+/// // Ctrl-C in the kernel shell
+/// polished_syscalls::signal::send_signal(foreground, polished_syscalls::signal::SIGINT)?;
+/// ```
+pub fn send_signal(id: TaskId, sig: u64) -> Result<(), SyscallError> {
+    if sig != 0 && !is_valid(sig) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    task::update_task(id, |task| {
+        if task.state == TaskState::Dead {
+            return Err(SyscallError::NoSuchTask);
+        }
+        if sig != 0 {
+            task.signals.pending |= 1 << sig;
+        }
+        Ok(())
+    })
+    .ok_or(SyscallError::NoSuchTask)??;
+    task::wake_task(id);
+    Ok(())
+}
+
+/// Returns whether task `id` has a pending signal that would interrupt a blocking syscall (not blocked, not ignored).
+pub fn has_pending(id: TaskId) -> bool {
+    task::task(id).is_some_and(|task| {
+        let state = task.signals;
+        (1..NSIG as u64)
+            .any(|sig| state.pending & !state.blocked & (1 << sig) != 0 && !is_ignored(&state, sig))
+    })
+}
+
+fn is_ignored(state: &SignalState, sig: u64) -> bool {
+    match state.actions[sig as usize].handler {
+        SIG_IGN => true,
+        SIG_DFL => sig == SIGCHLD,
+        _ => false,
+    }
+}
+
+/// What to do with a dequeued signal.
+enum Disposition {
+    Terminate(u64),
+    Handle(u64, SigAction, u32),
+}
+
+/// Removes the next deliverable signal of the current task, skipping ignored ones.
+fn dequeue() -> Option<Disposition> {
+    let id = task::current_task_id();
+    task::update_task(id, |task| {
+        let state = &mut task.signals;
+        while let Some(sig) = state.next_deliverable() {
+            state.pending &= !(1 << sig);
+            if is_ignored(state, sig) {
+                continue;
+            }
+            let action = state.actions[sig as usize];
+            if action.handler == SIG_DFL {
+                return Some(Disposition::Terminate(sig));
+            }
+            let blocked = state.blocked;
+            state.blocked |= (1 << sig) | action.mask as u32;
+            state.blocked &= !(1 << SIGKILL);
+            return Some(Disposition::Handle(sig, action, blocked));
+        }
+        None
+    })
+    .flatten()
+}
+
+/// Delivers pending signals of the current task to the user `context` it is about to resume.
+///
+/// Either redirects `context` to a handler or terminates the task. Must be called with interrupts disabled, right before returning to user mode.
+pub fn deliver(context: &mut SignalContext) {
+    let Some(disposition) = dequeue() else {
+        return;
+    };
+    match disposition {
+        Disposition::Terminate(sig) => task::exit_current(128 + sig as i32),
+        Disposition::Handle(sig, action, blocked) => {
+            let frame = SignalFrame {
+                signal: sig,
+                blocked: blocked as u64,
+                context: *context,
+            };
+            let frame_address = (context
+                .rsp
+                .wrapping_sub(RED_ZONE + size_of::<SignalFrame>() as u64))
+                & !0xF;
+            let return_address = frame_address.wrapping_sub(8);
+            let frame_bytes: [u8; FRAME_WORDS * 8] = words_to_bytes(frame.to_words());
+            let pushed = user::copy_to_user(frame_address, &frame_bytes)
+                .and_then(|_| user::copy_to_user(return_address, &action.restorer.to_ne_bytes()));
+            if pushed.is_err() {
+                // The stack is unusable, so the handler could never run.
+                task::exit_current(128 + SIGSEGV as i32);
+            }
+            context.rip = action.handler;
+            context.rsp = return_address;
+            context.rdi = sig;
+            context.rflags &= !(RFlags::DIRECTION_FLAG | RFlags::TRAP_FLAG).bits();
+        }
+    }
+}
+
+/// Delivers pending signals before the timer interrupt returns to user mode. Installed with `polished_interrupts::irq::set_user_return_hook`.
+pub fn deliver_on_interrupt_return(context: &mut InterruptContext) {
+    let mut signal_context = SignalContext::from_interrupt(context);
+    deliver(&mut signal_context);
+    signal_context.apply_to_interrupt(context);
+}
+
+/// `sigreturn()`: restores the registers and signal mask saved in the [`SignalFrame`] at the user stack pointer.
+///
+/// Called by the syscall entry stub, which must then return with `iretq`. Terminates the task with [`SIGSEGV`] if the frame is unreadable or invalid.
+pub fn sigreturn(registers: &mut SyscallRegisters) {
+    let mut bytes = [0u8; FRAME_WORDS * 8];
+    let frame = user::copy_from_user(&mut bytes, registers.rsp)
+        .map(|_| SignalFrame::from_words(bytes_to_words(&bytes)));
+    let Ok(mut frame) = frame else {
+        task::exit_current(128 + SIGSEGV as i32);
+    };
+    if !user::is_user_range(frame.context.rip, 1) || !user::is_user_range(frame.context.rsp, 0) {
+        task::exit_current(128 + SIGSEGV as i32);
+    }
+    frame.context.rflags = (RFlags::from_bits_truncate(frame.context.rflags) & USER_RFLAGS
+        | RFlags::INTERRUPT_FLAG)
+        .bits();
+    task::update_task(task::current_task_id(), |task| {
+        task.signals.blocked = frame.blocked as u32 & !(1 << SIGKILL);
+    });
+    frame.context.apply_to_syscall(registers);
+}
+
+/// `kill(pid, sig)`: sends `sig` to task `pid`.
+pub fn sys_kill(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Kill(pid, sig)) = Syscall::decode(SYS_KILL, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    send_signal(pid, sig).map(|_| 0)
+}
+
+/// `sigaction(sig, act, oldact)`: installs the [`SigAction`] at `act` (if non-null) for `sig` and writes the previous one to `oldact` (if non-null).
+pub fn sys_sigaction(args: [u64; 6]) -> SyscallResult {
+    let Some(Syscall::Sigaction(sig, act, oldact)) = Syscall::decode(SYS_SIGACTION, args) else {
+        return Err(SyscallError::InvalidArgument);
+    };
+    if !is_valid(sig) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let new = if act != 0 {
+        let mut bytes = [0u8; 32];
+        user::copy_from_user(&mut bytes, act)?;
+        let action = SigAction::from_bytes(&bytes);
+        if sig == SIGKILL {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let is_handler = action.handler != SIG_DFL && action.handler != SIG_IGN;
+        if is_handler
+            && (action.flags & SA_RESTORER == 0
+                || !user::is_user_range(action.handler, 1)
+                || !user::is_user_range(action.restorer, 1))
+        {
+            return Err(SyscallError::InvalidArgument);
+        }
+        Some(action)
+    } else {
+        None
+    };
+    let id = task::current_task_id();
+    let old = task::update_task(id, |task| {
+        let old = task.signals.actions[sig as usize];
+        if let Some(action) = new {
+            task.signals.actions[sig as usize] = action;
+        }
+        old
+    })
+    .ok_or(SyscallError::NoSuchTask)?;
+    if oldact != 0 {
+        user::copy_to_user(oldact, &old.to_bytes())?;
+    }
+    Ok(0)
+}
@@ -0,0 +1,72 @@
+//! # Syscall Table
+//!
+//! Syscall implementations are not hardcoded in the dispatcher: each subsystem registers its handlers at init with [`register_syscall`], and [`crate::syscall_handler`] looks the number up in this table.
+//!
+//! The table is an array of atomics indexed by syscall number, so registering never takes a lock that the syscall path could contend on. Numbers outside `0..MAX_SYSCALLS` or without a handler fail with `ENOSYS`.
+//!
+//! ## Example
+//! ```ignore
+//! fn sys_getpid(_args: [u64; 6]) -> SyscallResult {
+//!     Ok(current_task().id())
+//! }
+//! polished_syscalls::register_syscall(39, sys_getpid)?;
+//! ```
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::error::SyscallResult;
+
+/// Number of entries in the syscall table.
+pub const MAX_SYSCALLS: usize = 256;
+
+/// A syscall implementation, called with the six argument registers. Returns the value handed back in RAX, or an error that is returned negated.
+pub type SyscallHandler = fn(args: [u64; 6]) -> SyscallResult;
+
+/// Errors returned by [`register_syscall`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallTableError {
+    /// The number is outside `0..MAX_SYSCALLS`.
+    InvalidNumber,
+    /// Another handler is already registered for this number.
+    AlreadyRegistered,
+}
+
+static TABLE: [AtomicPtr<()>; MAX_SYSCALLS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_SYSCALLS];
+
+/// Registers `handler` for syscall `number`.
+///
+/// # Errors
+/// Returns [`SyscallTableError::InvalidNumber`] if `number` is out of range and [`SyscallTableError::AlreadyRegistered`] if it is taken.
+pub fn register_syscall(number: u64, handler: SyscallHandler) -> Result<(), SyscallTableError> {
+    let slot = usize::try_from(number)
+        .ok()
+        .and_then(|number| TABLE.get(number))
+        .ok_or(SyscallTableError::InvalidNumber)?;
+    slot.compare_exchange(
+        core::ptr::null_mut(),
+        handler as *mut (),
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    )
+    .map(|_| ())
+    .map_err(|_| SyscallTableError::AlreadyRegistered)
+}
+
+/// Removes the handler for syscall `number`, returning it if one was registered.
+pub fn unregister_syscall(number: u64) -> Option<SyscallHandler> {
+    let ptr = TABLE
+        .get(usize::try_from(number).ok()?)?
+        .swap(core::ptr::null_mut(), Ordering::AcqRel);
+    // Safety: only `SyscallHandler` function pointers are ever stored in the table.
+    (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), SyscallHandler>(ptr) })
+}
+
+/// Returns the handler registered for syscall `number`, if any.
+pub fn syscall(number: u64) -> Option<SyscallHandler> {
+    let ptr = TABLE
+        .get(usize::try_from(number).ok()?)?
+        .load(Ordering::Acquire);
+    // Safety: only `SyscallHandler` function pointers are ever stored in the table.
+    (!ptr.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), SyscallHandler>(ptr) })
+}
@@ -0,0 +1,370 @@
+//! # Task Bookkeeping
+//!
+//! Syscalls act on behalf of the *calling task*. Until the kernel has a full scheduler, this module keeps the minimal per-task state the syscalls need: an ID, the parent, a run state, the exit code, the layout of the task's `brk` heap and `mmap` area, and its signal state.
+//!
+//! ## Lifecycle
+//!
+//! - [`spawn_task`] allocates a task slot; the loader then makes it current with [`set_current_task`] before dropping to user mode.
+//! - The `exit` syscall calls [`exit_current`], which marks the task dead, records its exit code, asks the memory subsystem to release its user address space, and hands the CPU to the scheduler.
+//!
+//! - A task waiting for an event (a futex, input, ...) calls [`block_current_until`]; whoever produces the event calls [`wake_task`]. A pending signal ends the wait early.
+//! - A task may give up the CPU voluntarily with the `sched_yield` syscall, which calls [`yield_current`].
+//!
+//! The memory subsystem and the scheduler live outside this crate and plug in through hooks ([`set_address_space_switch_hook`], [`set_address_space_release_hook`], [`set_scheduler_hook`], [`set_block_hook`], [`set_yield_hook`]). Without a scheduler, an exiting task leaves the CPU idling in the kernel with interrupts enabled, which is the only safe option once its user context is gone.
+
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+use polished_serial_logging::kprint;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::error::{SyscallError, SyscallResult};
+use crate::signal::{self, SignalState};
+
+/// Maximum number of tasks (live or dead but not yet reaped).
+pub const MAX_TASKS: usize = 64;
+
+/// Identifies a task. ID 0 is the kernel itself.
+pub type TaskId = u64;
+
+/// ID of the kernel pseudo-task, current whenever no user task runs.
+pub const KERNEL_TASK: TaskId = 0;
+
+/// Run state of a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Ready to run.
+    Runnable,
+    /// Currently running on a CPU.
+    Running,
+    /// Waiting for an event.
+    Blocked,
+    /// Exited; the exit code is kept until the task is reaped.
+    Dead,
+}
+
+/// Bookkeeping for one task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Task {
+    /// Task ID.
+    pub id: TaskId,
+    /// ID of the task that spawned it.
+    pub parent: TaskId,
+    /// Run state.
+    pub state: TaskState,
+    /// Exit code, once the task is [`TaskState::Dead`].
+    pub exit_code: Option<i32>,
+    /// Start of the `brk` heap (just above the loaded image).
+    pub heap_start: u64,
+    /// Current program break.
+    pub brk: u64,
+    /// Next free address of the `mmap` area.
+    pub mmap_next: u64,
+    /// Pending and blocked signals and their actions.
+    pub signals: SignalState,
+}
+
+/// Errors returned by the task functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskError {
+    /// [`MAX_TASKS`] tasks already exist.
+    TableFull,
+    /// No task with this ID exists.
+    NoSuchTask,
+}
+
+static TASKS: Mutex<[Option<Task>; MAX_TASKS]> = Mutex::new([None; MAX_TASKS]);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+/// The one running task of the whole system, not one per CPU.
+static CURRENT: AtomicU64 = AtomicU64::new(KERNEL_TASK);
+
+/// Hook making the address space of `task` active, [`KERNEL_TASK`] for the kernel's.
+pub type AddressSpaceSwitchHook = fn(task: TaskId);
+
+/// Hook releasing the user address space of an exited task.
+pub type AddressSpaceReleaseHook = fn(task: TaskId);
+
+/// Hook switching to the next runnable task after the current one exited. Never returns.
+pub type SchedulerHook = fn() -> !;
+
+/// Hook running other tasks while the current one is blocked. Returns when the current task is scheduled again.
+pub type BlockHook = fn();
+
+/// Hook switching to another runnable task, if any. Returns when the current task is scheduled again.
+pub type YieldHook = fn();
+
+static ADDRESS_SPACE_SWITCH_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static ADDRESS_SPACE_RELEASE_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static SCHEDULER_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static BLOCK_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static YIELD_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Installs the hook the memory subsystem uses to load a task's page tables when it becomes current.
+pub fn set_address_space_switch_hook(hook: AddressSpaceSwitchHook) {
+    ADDRESS_SPACE_SWITCH_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Calls the address space switch hook, if any, for `id`.
+fn switch_address_space(id: TaskId) {
+    let hook = ADDRESS_SPACE_SWITCH_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // Safety: only `AddressSpaceSwitchHook` function pointers are ever stored.
+        unsafe { core::mem::transmute::<*mut (), AddressSpaceSwitchHook>(hook)(id) };
+    }
+}
+
+/// Installs the hook the memory subsystem uses to free an exited task's user mappings and frames.
+pub fn set_address_space_release_hook(hook: AddressSpaceReleaseHook) {
+    ADDRESS_SPACE_RELEASE_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Installs the hook that picks and switches to the next task when the current one exits.
+pub fn set_scheduler_hook(hook: SchedulerHook) {
+    SCHEDULER_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Installs the hook that runs other tasks while the current one is blocked.
+pub fn set_block_hook(hook: BlockHook) {
+    BLOCK_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Installs the hook that lets another task run when the current one yields.
+pub fn set_yield_hook(hook: YieldHook) {
+    YIELD_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+fn set_state(id: TaskId, state: TaskState) {
+    update_task(id, |task| task.state = state);
+}
+
+/// Blocks the current task until `ready` returns `true`.
+///
+/// While blocked, the task is [`TaskState::Blocked`] and the CPU runs other tasks through the block hook, or halts with interrupts enabled until the next interrupt if there is none.
+/// `ready` is checked with interrupts disabled, so an interrupt handler can safely make it true.
+///
+/// # Errors
+/// Returns [`SyscallError::Interrupted`] if a signal became pending before `ready` returned `true` (see [`crate::signal`]).
+pub fn block_current_until(ready: impl Fn() -> bool) -> Result<(), SyscallError> {
+    let id = current_task_id();
+    set_state(id, TaskState::Blocked);
+    let result = loop {
+        let was_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        let outcome = if ready() {
+            Some(Ok(()))
+        } else if signal::has_pending(id) {
+            Some(Err(SyscallError::Interrupted))
+        } else {
+            None
+        };
+        if let Some(outcome) = outcome {
+            if was_enabled {
+                interrupts::enable();
+            }
+            break outcome;
+        }
+        let hook = BLOCK_HOOK.load(Ordering::Acquire);
+        if hook.is_null() {
+            interrupts::enable_and_hlt();
+        } else {
+            // Safety: only `BlockHook` function pointers are ever stored.
+            unsafe { core::mem::transmute::<*mut (), BlockHook>(hook)() };
+        }
+        if !was_enabled {
+            interrupts::disable();
+        }
+    };
+    set_state(id, TaskState::Running);
+    result
+}
+
+/// Lets the scheduler run another task, returning once the current task runs again.
+///
+/// The task is [`TaskState::Runnable`] in the meantime. Without a yield hook there is nothing else to run, and this returns at once.
+pub fn yield_current() {
+    let hook = YIELD_HOOK.load(Ordering::Acquire);
+    if hook.is_null() {
+        return;
+    }
+    let id = current_task_id();
+    set_state(id, TaskState::Runnable);
+    // Safety: only `YieldHook` function pointers are ever stored.
+    unsafe { core::mem::transmute::<*mut (), YieldHook>(hook)() };
+    set_state(id, TaskState::Running);
+}
+
+/// Makes the blocked task `id` runnable again, e.g. after the event it waits for occurred.
+pub fn wake_task(id: TaskId) {
+    update_task(id, |task| {
+        if task.state == TaskState::Blocked {
+            task.state = TaskState::Runnable;
+        }
+    });
+}
+
+fn with_tasks<R>(f: impl FnOnce(&mut [Option<Task>; MAX_TASKS]) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut TASKS.lock()))
+}
+
+/// Creates a runnable task whose parent is the current task, returning its ID.
+///
+/// The shared time page is mapped into its address space (see [`crate::vdso`]).
+pub fn spawn_task() -> Result<TaskId, TaskError> {
+    let parent = current_task_id();
+    let id = with_tasks(|tasks| {
+        let slot = tasks
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(TaskError::TableFull)?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        *slot = Some(Task {
+            id,
+            parent,
+            state: TaskState::Runnable,
+            exit_code: None,
+            heap_start: crate::mm::DEFAULT_HEAP_START,
+            brk: crate::mm::DEFAULT_HEAP_START,
+            mmap_next: crate::mm::MMAP_START,
+            signals: SignalState::new(),
+        });
+        Ok(id)
+    })?;
+    crate::vdso::map_into(id);
+    Ok(id)
+}
+
+/// Returns a copy of the bookkeeping of task `id`.
+pub fn task(id: TaskId) -> Option<Task> {
+    with_tasks(|tasks| tasks.iter().flatten().find(|task| task.id == id).copied())
+}
+
+/// Runs `f` on the bookkeeping of task `id`, returning `None` if there is no such task.
+pub(crate) fn update_task<R>(id: TaskId, f: impl FnOnce(&mut Task) -> R) -> Option<R> {
+    with_tasks(|tasks| tasks.iter_mut().flatten().find(|task| task.id == id).map(f))
+}
+
+/// Places the `brk` heap of task `id` at `start` (page aligned up), e.g. right after its loaded ELF image.
+///
+/// Must be called before the task first calls `brk`.
+pub fn set_heap_start(id: TaskId, start: u64) -> Result<(), TaskError> {
+    let start = crate::mm::page_align_up(start);
+    update_task(id, |task| {
+        task.heap_start = start;
+        task.brk = start;
+    })
+    .ok_or(TaskError::NoSuchTask)
+}
+
+/// Returns the ID of the current task ([`KERNEL_TASK`] if none).
+///
+/// There is a single current task for the whole system, not one per CPU: user tasks only run on the CPU that calls [`set_current_task`], and the other CPUs never run task code. A scheduler that runs tasks on several CPUs must keep this per CPU.
+pub fn current_task_id() -> TaskId {
+    CURRENT.load(Ordering::Acquire)
+}
+
+/// Makes task `id` the system-wide current task and activates its address space on the calling CPU. The previous task, if any and still running, becomes runnable.
+pub fn set_current_task(id: TaskId) -> Result<(), TaskError> {
+    with_tasks(|tasks| {
+        let previous = current_task_id();
+        let next = tasks
+            .iter_mut()
+            .flatten()
+            .find(|task| task.id == id)
+            .ok_or(TaskError::NoSuchTask)?;
+        next.state = TaskState::Running;
+        if let Some(task) = tasks
+            .iter_mut()
+            .flatten()
+            .find(|task| task.id == previous && task.id != id && task.state == TaskState::Running)
+        {
+            task.state = TaskState::Runnable;
+        }
+        CURRENT.store(id, Ordering::Release);
+        Ok(())
+    })?;
+    switch_address_space(id);
+    Ok(())
+}
+
+/// Frees the slot of the dead task `id`, returning its exit code.
+pub fn reap_task(id: TaskId) -> Option<i32> {
+    with_tasks(|tasks| {
+        let slot = tasks
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|task| task.id == id && task.state == TaskState::Dead))?;
+        slot.take().and_then(|task| task.exit_code)
+    })
+}
+
+/// Prints the task table over serial, e.g. from a debug key combo.
+pub fn dump() {
+    kprint!("[INFO] Tasks (current {}):\r\n", current_task_id());
+    with_tasks(|tasks| {
+        for task in tasks.iter().flatten() {
+            kprint!(
+                "[INFO]   {} (parent {}): {:?}, exit code {:?}, brk {:#x}\r\n",
+                task.id,
+                task.parent,
+                task.state,
+                task.exit_code,
+                task.brk
+            );
+        }
+    });
+}
+
+/// `getpid()`: returns the ID of the calling task.
+pub fn sys_getpid(_args: [u64; 6]) -> SyscallResult {
+    Ok(current_task_id())
+}
+
+/// `gettid()`: returns the ID of the calling thread. Tasks have a single thread, so this is the task ID.
+pub fn sys_gettid(_args: [u64; 6]) -> SyscallResult {
+    Ok(current_task_id())
+}
+
+/// `getppid()`: returns the ID of the calling task's parent ([`KERNEL_TASK`] for tasks spawned by the kernel).
+pub fn sys_getppid(_args: [u64; 6]) -> SyscallResult {
+    task(current_task_id())
+        .map(|task| task.parent)
+        .ok_or(SyscallError::NoSuchTask)
+}
+
+/// `sched_yield()`: lets other tasks run (see [`yield_current`]). Always returns 0.
+pub fn sys_sched_yield(_args: [u64; 6]) -> SyscallResult {
+    yield_current();
+    Ok(0)
+}
+
+/// Terminates the current task with `code` and never returns to it.
+///
+/// Marks the task dead, records the exit code, closes its files, releases its user address space through the installed hook,
+/// and switches to the next task through the scheduler hook, or idles if there is none.
+pub fn exit_current(code: i32) -> ! {
+    let id = current_task_id();
+    with_tasks(|tasks| {
+        if let Some(task) = tasks.iter_mut().flatten().find(|task| task.id == id) {
+            task.state = TaskState::Dead;
+            task.exit_code = Some(code);
+        }
+    });
+    CURRENT.store(KERNEL_TASK, Ordering::Release);
+    switch_address_space(KERNEL_TASK);
+    crate::fs::close_all(id);
+    kprint!("[INFO] Task {} exited with code {}\r\n", id, code);
+
+    let release = ADDRESS_SPACE_RELEASE_HOOK.load(Ordering::Acquire);
+    if !release.is_null() {
+        // Safety: only `AddressSpaceReleaseHook` function pointers are ever stored.
+        unsafe { core::mem::transmute::<*mut (), AddressSpaceReleaseHook>(release)(id) };
+    }
+    let scheduler = SCHEDULER_HOOK.load(Ordering::Acquire);
+    if !scheduler.is_null() {
+        // Safety: only `SchedulerHook` function pointers are ever stored.
+        unsafe { core::mem::transmute::<*mut (), SchedulerHook>(scheduler)() };
+    }
+    // Nothing else to run: idle, still serving interrupts.
+    loop {
+        interrupts::enable_and_hlt();
+    }
+}
@@ -0,0 +1,104 @@
+//! # Syscall Tracing
+//!
+//! An `strace`-like mode for debugging early user programs without a debugger. While enabled with [`set_tracing`], [`crate::syscall_handler`] logs every syscall of the current task to the serial log:
+//!
+//! ```text
+//! [STRACE] task 1: Write(1, 17592186044416, 14) = 14
+//! [STRACE] task 1: Open(8192, 0) = -ENOENT (2)
+//! ```
+//!
+//! Calls are printed decoded (see [`crate::abi::Syscall`]) when the number is known, and as the raw number and arguments otherwise. `exit` is logged on entry, since it never returns.
+//!
+//! ## Rate Limiting
+//!
+//! A program in a tight syscall loop would otherwise flood the serial port and slow the whole system down. At most [`set_rate_limit`] lines are printed per second; the rest are counted and reported once the next second begins.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use polished_interrupts::time;
+use polished_serial_logging::kprint;
+
+use crate::abi::Syscall;
+use crate::error::SyscallResult;
+use crate::task;
+
+/// Default maximum number of trace lines per second.
+pub const DEFAULT_RATE_LIMIT: u64 = 100;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RATE_LIMIT: AtomicU64 = AtomicU64::new(DEFAULT_RATE_LIMIT);
+/// Second of uptime the current rate limiting window started at.
+static WINDOW: AtomicU64 = AtomicU64::new(0);
+/// Lines printed in the current window.
+static PRINTED: AtomicU64 = AtomicU64::new(0);
+/// Lines suppressed in the current window.
+static SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+/// Lines suppressed since boot.
+static TOTAL_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+/// Enables or disables syscall tracing.
+pub fn set_tracing(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether syscall tracing is enabled.
+pub fn is_tracing() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of trace lines printed per second (0 for no limit).
+pub fn set_rate_limit(lines_per_second: u64) {
+    RATE_LIMIT.store(lines_per_second, Ordering::Relaxed);
+}
+
+/// Returns the number of trace lines dropped by the rate limit since boot.
+pub fn suppressed_lines() -> u64 {
+    TOTAL_SUPPRESSED.load(Ordering::Relaxed)
+}
+
+/// Returns whether a line may be printed now, starting a new window (and reporting the previous one's drops) each second.
+fn admit() -> bool {
+    let second = time::uptime_ms() / 1000;
+    if WINDOW.swap(second, Ordering::Relaxed) != second {
+        PRINTED.store(0, Ordering::Relaxed);
+        let dropped = SUPPRESSED.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            kprint!("[STRACE] ... {} calls not shown (rate limit)\r\n", dropped);
+        }
+    }
+    let limit = RATE_LIMIT.load(Ordering::Relaxed);
+    if limit != 0 && PRINTED.fetch_add(1, Ordering::Relaxed) >= limit {
+        SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        TOTAL_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    true
+}
+
+fn print_call(number: u64, args: [u64; 6]) {
+    kprint!("[STRACE] task {}: ", task::current_task_id());
+    match Syscall::decode(number, args) {
+        Some(call) => kprint!("{:?}", call),
+        None => kprint!("syscall {}({:x?})", number, args),
+    }
+}
+
+/// Logs a call on entry if it does not return (`exit`). Called by [`crate::syscall_handler`].
+pub(crate) fn enter(number: u64, args: [u64; 6]) {
+    if is_tracing() && matches!(Syscall::decode(number, args), Some(Syscall::Exit(_))) && admit() {
+        print_call(number, args);
+        kprint!(" = ?\r\n");
+    }
+}
+
+/// Logs a completed call with its result. Called by [`crate::syscall_handler`].
+pub(crate) fn exit(number: u64, args: [u64; 6], result: &SyscallResult) {
+    if !is_tracing() || !admit() {
+        return;
+    }
+    print_call(number, args);
+    match result {
+        Ok(value) => kprint!(" = {}\r\n", value),
+        Err(error) => kprint!(" = -{}\r\n", error),
+    }
+}
@@ -0,0 +1,212 @@
+//! # User Memory Access
+//!
+//! Syscall arguments that point to memory come from untrusted user code. Handlers never dereference them directly; they go through the helpers here, which:
+//! 1. Check that the whole range lies in the part of the address space each task owns, `USER_SPACE_START..USER_SPACE_END`. Otherwise a user program could make the kernel read or overwrite kernel memory on its behalf: besides the upper half, PML4 entry 0 below `USER_SPACE_START` is kernel-owned and holds the kernel image.
+//! 2. Copy with a fault-tolerant routine. If a page of the range is not mapped in the current task, the page fault is caught and the helper returns [`SyscallError::Fault`] instead of crashing the kernel.
+//!
+//! ## Fault Handling
+//!
+//! All copies go through one naked routine whose only memory access is a `rep movsb`. [`install_fault_handler`] registers a page fault pre-hook for kernel-mode faults on exactly that instruction:
+//! - It first runs the VMM's demand paging (`polished_memory::vmm::handle_page_fault`) for the current task, so a copy into a lazily mapped page that was never touched maps the page and retries the `rep movsb`, which carries on where it stopped.
+//! - If that does not resolve the fault, it moves RIP to the instruction after the `rep movsb`, and the routine returns the number of bytes left in RCX instead of 0.
+//!
+//! Faults anywhere else are left to the regular page fault handling.
+//!
+//! ## Example
+//! ```ignore
+//! let mut path = [0u8; 256];
+//! let len = user::strncpy_from_user(&mut path, args[0])?;
+//! ```
+
+use polished_interrupts::exception_hooks::{self, Exception, ExceptionInfo, HookKind};
+pub use polished_memory::address_space::{USER_SPACE_END, USER_SPACE_START};
+use polished_memory::vmm;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::PageFaultErrorCode;
+
+use crate::error::SyscallError;
+use crate::task;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Returns whether `ptr..ptr + len` lies entirely within `USER_SPACE_START..USER_SPACE_END`.
+pub fn is_user_range(ptr: u64, len: u64) -> bool {
+    ptr >= USER_SPACE_START
+        && ptr
+            .checked_add(len)
+            .is_some_and(|end| end <= USER_SPACE_END)
+}
+
+unsafe extern "C" {
+    /// The `rep movsb` of [`user_copy`].
+    fn __polished_user_copy_access();
+    /// The instruction after it, where a faulting copy resumes.
+    fn __polished_user_copy_fixup();
+}
+
+/// Copies `len` bytes from `src` to `dst` and returns the number of bytes that were *not* copied (0 on success).
+#[unsafe(naked)]
+unsafe extern "C" fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    core::arch::naked_asm!(
+        "mov rcx, rdx",
+        ".global __polished_user_copy_access",
+        "__polished_user_copy_access:",
+        "rep movsb",
+        ".global __polished_user_copy_fixup",
+        "__polished_user_copy_fixup:",
+        "mov rax, rcx",
+        "ret",
+    );
+}
+
+/// Page fault pre-hook for [`user_copy`]: demand-pages the faulting address, or resumes the copy at its fixup.
+fn user_copy_fault_hook(info: &mut ExceptionInfo) -> bool {
+    let error_code = PageFaultErrorCode::from_bits_truncate(info.error_code.unwrap_or(0));
+    resolve_copy_fault(info, || {
+        vmm::handle_page_fault(task::current_task_id(), Cr2::read_raw(), error_code)
+    })
+}
+
+/// Handles a fault of [`user_copy`] if `info` describes one: retries the copy if `demand_page` maps the page, and moves RIP to the fixup otherwise.
+fn resolve_copy_fault(info: &mut ExceptionInfo, demand_page: impl FnOnce() -> bool) -> bool {
+    if info.is_user_mode() || info.rip != __polished_user_copy_access as *const () as u64 {
+        return false;
+    }
+    if !demand_page() {
+        info.rip = __polished_user_copy_fixup as *const () as u64;
+    }
+    true
+}
+
+/// Installs the page fault hook that turns faults during user copies into [`SyscallError::Fault`].
+///
+/// Called by [`crate::init_syscalls`]; until then, a copy from unmapped user memory is a kernel page fault.
+pub fn install_fault_handler() {
+    exception_hooks::set_exception_hook(Exception::PageFault, HookKind::Pre, user_copy_fault_hook);
+}
+
+/// Copies `dst.len()` bytes from the user address `src` into `dst`.
+///
+/// # Errors
+/// Returns [`SyscallError::Fault`] if the range is not in user space or not mapped.
+pub fn copy_from_user(dst: &mut [u8], src: u64) -> Result<(), SyscallError> {
+    if dst.is_empty() {
+        return Ok(());
+    }
+    if !is_user_range(src, dst.len() as u64) {
+        return Err(SyscallError::Fault);
+    }
+    // Safety: `dst` is a valid kernel buffer and faults on the user side are caught.
+    match unsafe { user_copy(dst.as_mut_ptr(), src as *const u8, dst.len()) } {
+        0 => Ok(()),
+        _ => Err(SyscallError::Fault),
+    }
+}
+
+/// Copies `src` to the user address `dst`.
+///
+/// # Errors
+/// Returns [`SyscallError::Fault`] if the range is not in user space or not mapped. Part of the data may have been written.
+pub fn copy_to_user(dst: u64, src: &[u8]) -> Result<(), SyscallError> {
+    if src.is_empty() {
+        return Ok(());
+    }
+    if !is_user_range(dst, src.len() as u64) {
+        return Err(SyscallError::Fault);
+    }
+    // Safety: `src` is a valid kernel buffer and faults on the user side are caught.
+    match unsafe { user_copy(dst as *mut u8, src.as_ptr(), src.len()) } {
+        0 => Ok(()),
+        _ => Err(SyscallError::Fault),
+    }
+}
+
+/// Copies the NUL-terminated user string at `src` into `dst`, returning its length without the terminator.
+///
+/// If there is no NUL within `dst.len()` bytes, `dst` is filled and `dst.len()` is returned, like Linux's `strncpy_from_user`; callers that need the whole string treat that as too long.
+/// Only the pages that hold the string are touched, so a string ending right before an unmapped page is fine.
+///
+/// # Errors
+/// Returns [`SyscallError::Fault`] if the string is not in mapped user memory.
+pub fn strncpy_from_user(dst: &mut [u8], src: u64) -> Result<usize, SyscallError> {
+    let mut copied = 0;
+    while copied < dst.len() {
+        let address = src.checked_add(copied as u64).ok_or(SyscallError::Fault)?;
+        let to_page_end = PAGE_SIZE - address % PAGE_SIZE;
+        let chunk = (dst.len() - copied).min(to_page_end as usize);
+        let buffer = &mut dst[copied..copied + chunk];
+        copy_from_user(buffer, address)?;
+        if let Some(nul) = buffer.iter().position(|&byte| byte == 0) {
+            return Ok(copied + nul);
+        }
+        copied += chunk;
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copy_fault(rip: u64, cs: u64) -> ExceptionInfo<'static> {
+        ExceptionInfo {
+            exception: Exception::PageFault,
+            error_code: Some(PageFaultErrorCode::CAUSED_BY_WRITE.bits()),
+            rip,
+            cs,
+            rflags: 0,
+            rsp: 0,
+            registers: None,
+        }
+    }
+
+    fn access() -> u64 {
+        __polished_user_copy_access as *const () as u64
+    }
+
+    #[test]
+    fn copy_into_untouched_lazy_mapping_is_retried() {
+        let mut info = copy_fault(access(), 0x08);
+        let mut demand_paged = false;
+        assert!(resolve_copy_fault(&mut info, || {
+            demand_paged = true;
+            true
+        }));
+        assert!(demand_paged);
+        assert_eq!(info.rip, access());
+    }
+
+    #[test]
+    fn unresolved_copy_fault_resumes_at_fixup() {
+        let mut info = copy_fault(access(), 0x08);
+        assert!(resolve_copy_fault(&mut info, || false));
+        assert_eq!(info.rip, __polished_user_copy_fixup as *const () as u64);
+    }
+
+    #[test]
+    fn other_faults_are_left_alone() {
+        let mut user = copy_fault(access(), 0x1B);
+        assert!(!resolve_copy_fault(&mut user, || unreachable!()));
+        let mut elsewhere = copy_fault(access() + 1, 0x08);
+        assert!(!resolve_copy_fault(&mut elsewhere, || unreachable!()));
+        assert_eq!(elsewhere.rip, access() + 1);
+    }
+
+    #[test]
+    fn user_copy_copies_everything() {
+        let src = *b"polished";
+        let mut dst = [0u8; 8];
+        let left = unsafe { user_copy(dst.as_mut_ptr(), src.as_ptr(), src.len()) };
+        assert_eq!(left, 0);
+        assert_eq!(dst, src);
+    }
+
+    #[test]
+    fn kernel_addresses_are_not_user_ranges() {
+        assert!(!is_user_range(0x10_0000, 16));
+        assert!(!is_user_range(USER_SPACE_START - 1, 2));
+        assert!(is_user_range(USER_SPACE_START, 16));
+        assert!(!is_user_range(USER_SPACE_END - 8, 16));
+        assert!(!is_user_range(u64::MAX, 1));
+    }
+}
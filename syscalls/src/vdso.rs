@@ -0,0 +1,117 @@
+//! # Shared Time Page
+//!
+//! Like the Linux vDSO data page, the kernel maintains one page of time data, [`VdsoData`], and maps it read-only into every user address space at [`VDSO_ADDRESS`]. User programs read the time from it directly, without the cost of a syscall round trip.
+//!
+//! ## Reading Consistently
+//!
+//! The kernel updates the page every [`UPDATE_INTERVAL_MS`] milliseconds from a periodic timer, while user code may be reading it. The page is guarded by a sequence counter (a seqlock):
+//! 1. The writer makes `sequence` odd, updates the fields, then makes it even again.
+//! 2. A reader copies the fields between two reads of `sequence`, and retries if they differ or are odd.
+//!
+//! Between updates, readers extrapolate with the TSC: `base_ns + (rdtsc() - base_tsc) * 10^9 / tsc_frequency_hz`. If the TSC frequency is unknown (0), `base_ns` alone is accurate to one update interval.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use polished_interrupts::time;
+use polished_interrupts::timer::{self, TimerError, TimerId};
+use polished_x86_commands::tsc::{self, rdtsc};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::mm::{self, MMAP_END};
+use crate::task::TaskId;
+
+/// User address of the shared time page, just above the `mmap` area.
+pub const VDSO_ADDRESS: u64 = MMAP_END;
+/// Interval between kernel updates of the page.
+pub const UPDATE_INTERVAL_MS: u64 = 10;
+
+/// Time data shared with user programs, laid out as `u64`s at the start of the page.
+#[repr(C)]
+pub struct VdsoData {
+    /// Seqlock counter; odd while an update is in progress.
+    pub sequence: AtomicU64,
+    /// Timer interrupts since boot.
+    pub ticks: AtomicU64,
+    /// Timer interrupt rate in Hz.
+    pub tick_rate_hz: AtomicU64,
+    /// TSC frequency in Hz, or 0 if unknown.
+    pub tsc_frequency_hz: AtomicU64,
+    /// Wall-clock time at boot, in seconds since the Unix epoch.
+    pub boot_time_unix: AtomicU64,
+    /// Monotonic nanoseconds since boot at the last update.
+    pub base_ns: AtomicU64,
+    /// TSC value at the last update.
+    pub base_tsc: AtomicU64,
+}
+
+#[repr(C, align(4096))]
+struct VdsoPage(VdsoData);
+
+static PAGE: VdsoPage = VdsoPage(VdsoData {
+    sequence: AtomicU64::new(0),
+    ticks: AtomicU64::new(0),
+    tick_rate_hz: AtomicU64::new(0),
+    tsc_frequency_hz: AtomicU64::new(0),
+    boot_time_unix: AtomicU64::new(0),
+    base_ns: AtomicU64::new(0),
+    base_tsc: AtomicU64::new(0),
+});
+
+/// Returns the kernel's view of the shared time page.
+pub fn data() -> &'static VdsoData {
+    &PAGE.0
+}
+
+/// Refreshes the page. Runs from the periodic timer.
+fn update(_timer: TimerId) {
+    refresh();
+}
+
+fn refresh() {
+    let data = data();
+    data.sequence.fetch_add(1, Ordering::AcqRel);
+    data.ticks.store(time::ticks(), Ordering::Relaxed);
+    data.base_ns.store(time::uptime_ns(), Ordering::Relaxed);
+    data.base_tsc.store(rdtsc(), Ordering::Relaxed);
+    data.sequence.fetch_add(1, Ordering::Release);
+}
+
+/// Fills in the page and starts updating it every [`UPDATE_INTERVAL_MS`] milliseconds.
+///
+/// Call after the timer and the RTC are set up, and after `polished_x86_commands::tsc::calibrate`.
+pub fn init() -> Result<(), TimerError> {
+    let data = data();
+    data.sequence.fetch_add(1, Ordering::AcqRel);
+    data.tick_rate_hz.store(
+        polished_interrupts::pit::tick_rate_hz() as u64,
+        Ordering::Relaxed,
+    );
+    data.tsc_frequency_hz
+        .store(tsc::frequency_hz(), Ordering::Relaxed);
+    let uptime_s = time::uptime_ns() / 1_000_000_000;
+    let now = polished_interrupts::rtc::now().unix_timestamp();
+    data.boot_time_unix
+        .store(now.saturating_sub(uptime_s), Ordering::Relaxed);
+    data.sequence.fetch_add(1, Ordering::Release);
+    refresh();
+    timer::every(UPDATE_INTERVAL_MS, update).map(|_| ())
+}
+
+/// Maps the shared time page read-only at [`VDSO_ADDRESS`] in the address space of `task`.
+///
+/// Called for every new task by [`crate::task::spawn_task`]. Returns `false` if no page mapper is installed or mapping failed.
+pub fn map_into(task: TaskId) -> bool {
+    let Some(mapper) = mm::mapper() else {
+        return false;
+    };
+    // The kernel image is identity mapped, so the page's virtual address is its physical address.
+    let frame = PhysAddr::new(&raw const PAGE as u64);
+    let flags =
+        PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::NO_EXECUTE;
+    let mapped = (mapper.map_physical)(task, VirtAddr::new(VDSO_ADDRESS), frame, flags);
+    if !mapped {
+        polished_serial_logging::warn!("Could not map the time page into task {}", task);
+    }
+    mapped
+}
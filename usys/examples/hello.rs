@@ -0,0 +1,34 @@
+//! Minimal user program: prints a greeting and the uptime, then exits.
+//!
+//! Build it as a static, freestanding ELF and load it with the ELF user loader:
+//! ```sh
+//! cargo build -p polished_usys --example hello --target x86_64-unknown-none
+//! ```
+//!
+//! For any other target (such as the host, when running `cargo test`) it builds as an empty program.
+
+#![cfg_attr(target_os = "none", no_std, no_main)]
+
+#[cfg(target_os = "none")]
+mod program {
+    use core::fmt::Write;
+
+    use polished_usys::{CLOCK_MONOTONIC, Stdout, clock_gettime, exit};
+
+    #[unsafe(no_mangle)]
+    extern "C" fn _start() -> ! {
+        let _ = writeln!(Stdout, "Hello from user mode!");
+        if let Ok(now) = clock_gettime(CLOCK_MONOTONIC) {
+            let _ = writeln!(Stdout, "Uptime: {}.{:09} s", now.tv_sec, now.tv_nsec);
+        }
+        exit(0)
+    }
+
+    #[panic_handler]
+    fn panic(_info: &core::panic::PanicInfo) -> ! {
+        exit(101)
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+fn main() {}
@@ -0,0 +1,352 @@
+//! # Userspace Syscall Wrappers
+//!
+//! This crate is the user side of the Polished OS system call ABI. It is linked into user programs (not the kernel) and wraps the `syscall` instruction in small safe functions such as [`write`], [`read`], and [`exit`].
+//!
+//! ## ABI
+//!
+//! The kernel (`polished_syscalls`) uses the Linux x86_64 register convention, defined for both sides by `polished_syscall_abi` ([`SyscallFrame`], [`SyscallReturn`]):
+//! - **RAX:** Syscall number on entry, return value on exit.
+//! - **RDI, RSI, RDX, R10, R8, R9:** Arguments 1-6.
+//! - **RCX, R11:** Clobbered by `syscall`. Every other register is preserved.
+//!
+//! Failures are returned as a negated error number in `-4095..=-1`; the wrappers decode them into an [`Errno`].
+//!
+//! ## Modules
+//! - `nr`: Syscall numbers, re-exported from `polished_syscall_abi` (the kernel uses the same module).
+//! - `vdso`: Time read from the kernel's shared time page, without a syscall.
+//! - `raw`: `syscall0`..`syscall6`, the unchecked inline-assembly primitives.
+//!
+//! ## Example
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! #[unsafe(no_mangle)]
+//! extern "C" fn _start() -> ! {
+//!     let _ = polished_usys::write(polished_usys::STDOUT, b"Hello from user mode!\n");
+//!     polished_usys::exit(0)
+//! }
+//! ```
+
+#![no_std]
+
+use core::fmt;
+
+/// Syscall numbers.
+pub use polished_syscall_abi::nr;
+/// Raw `syscall` instruction wrappers.
+pub mod raw;
+/// Syscall-free time from the shared time page.
+pub mod vdso;
+
+/// Standard input file descriptor.
+pub const STDIN: u64 = 0;
+/// Standard output file descriptor.
+pub const STDOUT: u64 = 1;
+/// Standard error file descriptor.
+pub const STDERR: u64 = 2;
+
+/// Open for reading only, for [`open`].
+pub const O_RDONLY: u64 = 0;
+
+/// Seek from the start of the file, for [`lseek`].
+pub const SEEK_SET: u64 = 0;
+/// Seek from the current offset, for [`lseek`].
+pub const SEEK_CUR: u64 = 1;
+/// Seek from the end of the file, for [`lseek`].
+pub const SEEK_END: u64 = 2;
+
+/// Wall-clock time, for [`clock_gettime`].
+pub const CLOCK_REALTIME: u64 = 0;
+/// Time since boot, for [`clock_gettime`].
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+/// Pages may be read, for [`mmap`].
+pub const PROT_READ: u64 = 1 << 0;
+/// Pages may be written, for [`mmap`].
+pub const PROT_WRITE: u64 = 1 << 1;
+/// Pages may be executed, for [`mmap`].
+pub const PROT_EXEC: u64 = 1 << 2;
+
+/// Interrupt from the keyboard (Ctrl-C).
+pub const SIGINT: u64 = 2;
+/// Kill; cannot be caught or ignored.
+pub const SIGKILL: u64 = 9;
+/// User-defined signal 1.
+pub const SIGUSR1: u64 = 10;
+/// User-defined signal 2.
+pub const SIGUSR2: u64 = 12;
+/// Termination request.
+pub const SIGTERM: u64 = 15;
+
+pub use polished_syscall_abi::{MAX_ERRNO, SyscallFrame, SyscallReturn};
+
+/// An error number returned by a syscall (as in Linux).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Errno(pub u64);
+
+impl Errno {
+    /// No such file or directory.
+    pub const ENOENT: Errno = Errno(2);
+    /// Bad file descriptor.
+    pub const EBADF: Errno = Errno(9);
+    /// No such task.
+    pub const ESRCH: Errno = Errno(3);
+    /// Interrupted by a signal.
+    pub const EINTR: Errno = Errno(4);
+    /// Try again.
+    pub const EAGAIN: Errno = Errno(11);
+    /// Out of memory.
+    pub const ENOMEM: Errno = Errno(12);
+    /// Bad address.
+    pub const EFAULT: Errno = Errno(14);
+    /// Invalid argument.
+    pub const EINVAL: Errno = Errno(22);
+    /// Syscall not implemented.
+    pub const ENOSYS: Errno = Errno(38);
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "errno {}", self.0)
+    }
+}
+
+/// Result of a syscall.
+pub type Result<T> = core::result::Result<T, Errno>;
+
+/// Decodes the raw value returned in RAX.
+pub const fn check(raw: u64) -> Result<u64> {
+    match SyscallReturn(raw).into_result() {
+        Ok(value) => Ok(value),
+        Err(errno) => Err(Errno(errno)),
+    }
+}
+
+/// Writes `buf` to file descriptor `fd`, returning the number of bytes written.
+pub fn write(fd: u64, buf: &[u8]) -> Result<usize> {
+    // Safety: the kernel only reads `buf.len()` bytes from `buf`.
+    let raw = unsafe { raw::syscall3(nr::SYS_WRITE, fd, buf.as_ptr() as u64, buf.len() as u64) };
+    check(raw).map(|written| written as usize)
+}
+
+/// Reads into `buf` from file descriptor `fd`, returning the number of bytes read.
+pub fn read(fd: u64, buf: &mut [u8]) -> Result<usize> {
+    // Safety: the kernel writes at most `buf.len()` bytes to `buf`.
+    let raw = unsafe { raw::syscall3(nr::SYS_READ, fd, buf.as_mut_ptr() as u64, buf.len() as u64) };
+    check(raw).map(|read| read as usize)
+}
+
+/// Opens the file at `path` with `flags` ([`O_RDONLY`]), returning its descriptor.
+///
+/// `path` must be NUL-terminated, e.g. `c"/etc/motd"`.
+pub fn open(path: &core::ffi::CStr, flags: u64) -> Result<u64> {
+    // Safety: the kernel only reads the NUL-terminated string at `path`.
+    check(unsafe { raw::syscall2(nr::SYS_OPEN, path.as_ptr() as u64, flags) })
+}
+
+/// Closes file descriptor `fd`.
+pub fn close(fd: u64) -> Result<()> {
+    // Safety: `close` touches no user memory.
+    check(unsafe { raw::syscall1(nr::SYS_CLOSE, fd) }).map(|_| ())
+}
+
+/// Moves the offset of file descriptor `fd` relative to `whence` ([`SEEK_SET`], [`SEEK_CUR`], or [`SEEK_END`]), returning the new offset.
+pub fn lseek(fd: u64, offset: i64, whence: u64) -> Result<u64> {
+    // Safety: `lseek` touches no user memory.
+    check(unsafe { raw::syscall3(nr::SYS_LSEEK, fd, offset as u64, whence) })
+}
+
+/// Terminates the calling task with exit code `code`.
+pub fn exit(code: i32) -> ! {
+    // Safety: `exit` does not return and touches no user memory.
+    unsafe { raw::syscall1(nr::SYS_EXIT, code as u64) };
+    // The kernel never returns from `exit`; spin in case it does.
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Moves the program break to `addr` and returns the new break (0 queries it).
+///
+/// If the break cannot be moved, the unchanged break is returned, as in Linux.
+pub fn brk(addr: u64) -> u64 {
+    // Safety: growing the heap only maps new memory.
+    unsafe { raw::syscall1(nr::SYS_BRK, addr) }
+}
+
+/// Maps `len` bytes of zeroed memory with the `PROT_*` protections `prot`, returning its address.
+pub fn mmap(len: u64, prot: u64) -> Result<*mut u8> {
+    // Safety: the kernel maps fresh memory that aliases nothing.
+    check(unsafe { raw::syscall2(nr::SYS_MMAP, len, prot) }).map(|address| address as *mut u8)
+}
+
+/// A point in time, laid out like the C `struct timespec` on x86_64.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timespec {
+    /// Whole seconds.
+    pub tv_sec: i64,
+    /// Nanoseconds (`0..1_000_000_000`).
+    pub tv_nsec: i64,
+}
+
+/// Returns the current time of `clock` ([`CLOCK_REALTIME`] or [`CLOCK_MONOTONIC`]).
+pub fn clock_gettime(clock: u64) -> Result<Timespec> {
+    let mut time = Timespec::default();
+    // Safety: the kernel writes one `Timespec` to `time`.
+    let raw = unsafe { raw::syscall2(nr::SYS_CLOCK_GETTIME, clock, &raw mut time as u64) };
+    check(raw).map(|_| time)
+}
+
+/// Blocks while the word at `word` equals `expected`.
+///
+/// Returns `Err(Errno::EAGAIN)` if the word already changed. Wakeups can be spurious: re-check the word after returning.
+pub fn futex_wait(word: &core::sync::atomic::AtomicU32, expected: u32) -> Result<()> {
+    // Safety: the kernel only reads the word.
+    let raw = unsafe { raw::syscall3(nr::SYS_FUTEX, word.as_ptr() as u64, 0, expected as u64) };
+    check(raw).map(|_| ())
+}
+
+/// Wakes up to `count` tasks waiting on `word`, returning how many were woken.
+pub fn futex_wake(word: &core::sync::atomic::AtomicU32, count: u64) -> Result<u64> {
+    // Safety: `FUTEX_WAKE` does not access the word.
+    check(unsafe { raw::syscall3(nr::SYS_FUTEX, word.as_ptr() as u64, 1, count) })
+}
+
+/// Returns the ID of the calling task.
+pub fn getpid() -> u64 {
+    // Safety: `getpid` touches no user memory.
+    unsafe { raw::syscall0(nr::SYS_GETPID) }
+}
+
+/// Returns the ID of the calling thread (equal to [`getpid`], as tasks are single-threaded).
+pub fn gettid() -> u64 {
+    // Safety: `gettid` touches no user memory.
+    unsafe { raw::syscall0(nr::SYS_GETTID) }
+}
+
+/// Returns the ID of the task that spawned this one (0 for the kernel).
+pub fn getppid() -> u64 {
+    // Safety: `getppid` touches no user memory.
+    unsafe { raw::syscall0(nr::SYS_GETPPID) }
+}
+
+/// Lets other tasks run before this one continues.
+pub fn sched_yield() {
+    // Safety: `sched_yield` touches no user memory.
+    unsafe { raw::syscall0(nr::SYS_SCHED_YIELD) };
+}
+
+/// Sends signal `sig` to task `pid` (0 only checks that the task exists).
+pub fn kill(pid: u64, sig: u64) -> Result<()> {
+    // Safety: `kill` touches no user memory.
+    check(unsafe { raw::syscall2(nr::SYS_KILL, pid, sig) }).map(|_| ())
+}
+
+/// What to do when a signal arrives, as passed to [`signal`].
+#[derive(Debug, Clone, Copy)]
+pub enum SignalHandler {
+    /// The default action (terminate, for most signals).
+    Default,
+    /// Discard the signal.
+    Ignore,
+    /// Call the function with the signal number.
+    Handler(extern "C" fn(u64)),
+}
+
+/// Kernel `struct sigaction` layout.
+#[repr(C)]
+struct SigAction {
+    handler: u64,
+    flags: u64,
+    restorer: u64,
+    mask: u64,
+}
+
+const SA_RESTORER: u64 = 0x0400_0000;
+
+/// Where signal handlers return to: calls `sigreturn` with the stack left by the kernel.
+#[unsafe(naked)]
+extern "C" fn signal_restorer() {
+    core::arch::naked_asm!(
+        "mov eax, {sigreturn}",
+        "syscall",
+        "ud2",
+        sigreturn = const nr::SYS_SIGRETURN,
+    );
+}
+
+/// Sets what happens when signal `sig` arrives. The signal is blocked while its handler runs.
+///
+/// # Example
+/// ```ignore
+/// extern "C" fn on_interrupt(_sig: u64) {
+///     let _ = polished_usys::write(polished_usys::STDOUT, b"Ctrl-C\n");
+/// }
+/// polished_usys::signal(polished_usys::SIGINT, polished_usys::SignalHandler::Handler(on_interrupt))?;
+/// ```
+pub fn signal(sig: u64, handler: SignalHandler) -> Result<()> {
+    let (handler, flags, restorer) = match handler {
+        SignalHandler::Default => (0, 0, 0),
+        SignalHandler::Ignore => (1, 0, 0),
+        SignalHandler::Handler(function) => (
+            function as usize as u64,
+            SA_RESTORER,
+            signal_restorer as *const () as u64,
+        ),
+    };
+    let action = SigAction {
+        handler,
+        flags,
+        restorer,
+        mask: 0,
+    };
+    // Safety: the kernel only reads one `SigAction` from `action`.
+    let raw = unsafe { raw::syscall3(nr::SYS_SIGACTION, sig, &raw const action as u64, 0) };
+    check(raw).map(|_| ())
+}
+
+/// Description of the framebuffer mapped by [`fb_map`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FbInfo {
+    /// Address of the mapped framebuffer.
+    pub address: u64,
+    /// Size of the framebuffer in bytes.
+    pub size: u64,
+    /// Width in pixels.
+    pub width: u64,
+    /// Height in pixels.
+    pub height: u64,
+    /// Pixels per row (may be larger than `width`).
+    pub stride: u64,
+    /// Bytes per pixel.
+    pub bytes_per_pixel: u64,
+    /// Pixel format: 0 RGB, 1 BGR, 2 bitmask.
+    pub format: u64,
+}
+
+/// Maps the framebuffer into this task and returns its description.
+pub fn fb_map() -> Result<FbInfo> {
+    let mut info = FbInfo::default();
+    // Safety: the kernel writes one `FbInfo` to `info`.
+    let raw = unsafe { raw::syscall1(nr::SYS_FB_MAP, &raw mut info as u64) };
+    check(raw).map(|_| info)
+}
+
+/// Writer for standard output, for use with `write!`.
+pub struct Stdout;
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            match write(STDOUT, bytes) {
+                Ok(0) | Err(_) => return Err(fmt::Error),
+                Ok(written) => bytes = &bytes[written..],
+            }
+        }
+        Ok(())
+    }
+}
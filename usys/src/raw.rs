@@ -0,0 +1,93 @@
+//! The `syscall` instruction with 0 to 6 arguments. Each function returns the raw RAX value; decode it with [`crate::check`].
+//!
+//! All of them go through [`syscall`], the only place registers are loaded, following the [`SyscallFrame`] layout of `polished_syscall_abi` that the kernel entry stub reads back.
+//!
+//! # Safety
+//! All functions are `unsafe`: the kernel may read or write memory through pointer arguments, so the caller must pass arguments that are valid for the syscall `number`.
+
+use core::arch::asm;
+
+use polished_syscall_abi::{SyscallFrame, SyscallReturn};
+
+/// Performs the system call described by `frame`.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall(frame: SyscallFrame) -> SyscallReturn {
+    let [a1, a2, a3, a4, a5, a6] = frame.args;
+    let ret;
+    unsafe {
+        asm!(
+            "syscall",
+            inlateout("rax") frame.number => ret,
+            in("rdi") a1, in("rsi") a2, in("rdx") a3, in("r10") a4, in("r8") a5, in("r9") a6,
+            out("rcx") _, out("r11") _,
+            options(nostack),
+        );
+    }
+    SyscallReturn(ret)
+}
+
+/// Performs syscall `number` without arguments.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall0(number: u64) -> u64 {
+    unsafe { syscall(SyscallFrame::with_args(number, &[])) }.0
+}
+
+/// Performs syscall `number` with one argument.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall1(number: u64, a1: u64) -> u64 {
+    unsafe { syscall(SyscallFrame::with_args(number, &[a1])) }.0
+}
+
+/// Performs syscall `number` with two arguments.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall2(number: u64, a1: u64, a2: u64) -> u64 {
+    unsafe { syscall(SyscallFrame::with_args(number, &[a1, a2])) }.0
+}
+
+/// Performs syscall `number` with three arguments.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall3(number: u64, a1: u64, a2: u64, a3: u64) -> u64 {
+    unsafe { syscall(SyscallFrame::with_args(number, &[a1, a2, a3])) }.0
+}
+
+/// Performs syscall `number` with four arguments.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall4(number: u64, a1: u64, a2: u64, a3: u64, a4: u64) -> u64 {
+    unsafe { syscall(SyscallFrame::with_args(number, &[a1, a2, a3, a4])) }.0
+}
+
+/// Performs syscall `number` with five arguments.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall5(number: u64, a1: u64, a2: u64, a3: u64, a4: u64, a5: u64) -> u64 {
+    unsafe { syscall(SyscallFrame::with_args(number, &[a1, a2, a3, a4, a5])) }.0
+}
+
+/// Performs syscall `number` with six arguments.
+///
+/// # Safety
+/// See the [module documentation](self).
+#[inline(always)]
+pub unsafe fn syscall6(number: u64, a1: u64, a2: u64, a3: u64, a4: u64, a5: u64, a6: u64) -> u64 {
+    unsafe { syscall(SyscallFrame::with_args(number, &[a1, a2, a3, a4, a5, a6])) }.0
+}
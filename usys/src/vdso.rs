@@ -0,0 +1,74 @@
+//! Reading the kernel's shared time page, which is mapped read-only into every task. Must match `polished_syscalls::vdso`.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Address of the shared time page.
+pub const VDSO_ADDRESS: u64 = 0x0000_7000_0000_0000;
+
+/// Layout of the shared time page.
+#[repr(C)]
+struct VdsoData {
+    sequence: AtomicU64,
+    ticks: AtomicU64,
+    tick_rate_hz: AtomicU64,
+    tsc_frequency_hz: AtomicU64,
+    boot_time_unix: AtomicU64,
+    base_ns: AtomicU64,
+    base_tsc: AtomicU64,
+}
+
+/// A consistent copy of the time page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeSnapshot {
+    /// Timer interrupts since boot.
+    pub ticks: u64,
+    /// Timer interrupt rate in Hz.
+    pub tick_rate_hz: u64,
+    /// TSC frequency in Hz, or 0 if unknown.
+    pub tsc_frequency_hz: u64,
+    /// Wall-clock time at boot, in seconds since the Unix epoch.
+    pub boot_time_unix: u64,
+    /// Nanoseconds since boot at the last kernel update.
+    pub base_ns: u64,
+    /// TSC value at the last kernel update.
+    pub base_tsc: u64,
+}
+
+fn page() -> &'static VdsoData {
+    // Safety: the kernel maps the page read-only at `VDSO_ADDRESS` in every task for its whole lifetime.
+    unsafe { &*(VDSO_ADDRESS as *const VdsoData) }
+}
+
+/// Reads the time page, retrying while the kernel is updating it.
+pub fn snapshot() -> TimeSnapshot {
+    let data = page();
+    loop {
+        let before = data.sequence.load(Ordering::Acquire);
+        if before % 2 == 1 {
+            core::hint::spin_loop();
+            continue;
+        }
+        let snapshot = TimeSnapshot {
+            ticks: data.ticks.load(Ordering::Relaxed),
+            tick_rate_hz: data.tick_rate_hz.load(Ordering::Relaxed),
+            tsc_frequency_hz: data.tsc_frequency_hz.load(Ordering::Relaxed),
+            boot_time_unix: data.boot_time_unix.load(Ordering::Relaxed),
+            base_ns: data.base_ns.load(Ordering::Relaxed),
+            base_tsc: data.base_tsc.load(Ordering::Relaxed),
+        };
+        if data.sequence.load(Ordering::Acquire) == before {
+            return snapshot;
+        }
+    }
+}
+
+/// Returns nanoseconds since boot without a syscall, extrapolating from the last update with the TSC.
+pub fn uptime_ns() -> u64 {
+    let time = snapshot();
+    if time.tsc_frequency_hz == 0 {
+        return time.base_ns;
+    }
+    // Safety: `rdtsc` has no side effects.
+    let cycles = unsafe { core::arch::x86_64::_rdtsc() }.wrapping_sub(time.base_tsc);
+    time.base_ns + (cycles as u128 * 1_000_000_000 / time.tsc_frequency_hz as u128) as u64
+}
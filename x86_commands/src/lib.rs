@@ -19,12 +19,21 @@
 
 #![no_std]
 
-use core::arch::asm;
+/// Model-specific register access (`rdmsr`/`wrmsr`) and register numbers.
+pub mod msr;
+/// Legacy 8259 PIC remapping, masking, and EOI.
+pub mod pic8259;
+/// Typed I/O port access.
+pub mod port;
+/// SSE/AVX enablement and FPU/SIMD state save areas.
+pub mod simd;
+/// Time stamp counter reads, calibration, and busy-wait delays.
+pub mod tsc;
 
 /// Disables the legacy Programmable Interrupt Controller (PIC) on x86/x86_64 systems.
 ///
 /// # Architecture
-/// This function is specific to x86-family CPUs. It uses the `out` instruction to write to the PIC's I/O ports (0x21 for the master PIC, 0xA1 for the slave PIC).
+/// This function is specific to x86-family CPUs. It uses the `out` instruction to write to the PIC's I/O ports (0x21 for the master PIC, 0xA1 for the slave PIC), through [`pic8259::disable`].
 ///
 /// - On modern x86_64 systems, the legacy PIC is often replaced by the APIC, but the PIC must still be masked to prevent spurious interrupts.
 /// - This function is a no-op on non-x86 architectures and will not compile there.
@@ -37,16 +46,7 @@ use core::arch::asm;
 /// x86_commands::disable_pic();
 /// ```
 pub fn disable_pic() {
-    unsafe {
-        // Mask all interrupts on both PICs by writing 0xFF to their data ports.
-        // 0x21: Master PIC data port
-        // 0xA1: Slave PIC data port
-        // This disables all IRQs from the legacy PIC, which is required before enabling the APIC.
-        asm!(
-            "mov al, 0xFF", // Set AL register to 0xFF (all bits set)
-            "out 0xA1, al", // Write AL to slave PIC data port (0xA1)
-            "out 0x21, al", // Write AL to master PIC data port (0x21)
-            options(nostack, nomem, preserves_flags)
-        );
-    }
+    // Mask all interrupts on both PICs (0x21: master data port, 0xA1: slave data port).
+    // This disables all IRQs from the legacy PIC, which is required before enabling the APIC.
+    pic8259::disable();
 }
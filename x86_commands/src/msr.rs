@@ -0,0 +1,77 @@
+//! # Model-Specific Registers
+//!
+//! Model-specific registers (MSRs) hold CPU configuration that has no instruction of its own: long mode and `syscall` enables in `IA32_EFER`, the `syscall` entry point in `IA32_LSTAR`, the Local APIC base, the `gs` bases used for per-CPU data, and more. They are addressed by a 32-bit number and read and written as 64-bit values with `rdmsr` and `wrmsr`, which only run at CPL 0.
+//!
+//! [`rdmsr`] and [`wrmsr`] wrap the two instructions; the constants name the registers and bits the kernel uses, so callers do not repeat magic numbers.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use polished_x86_commands::msr::{self, EFER_NXE, IA32_EFER};
+//! unsafe { msr::wrmsr(IA32_EFER, msr::rdmsr(IA32_EFER) | EFER_NXE) };
+//! ```
+
+use core::arch::asm;
+
+/// `IA32_APIC_BASE`: physical base address and enable bits of the Local APIC.
+pub const IA32_APIC_BASE: u32 = 0x1B;
+/// `IA32_PAT`: page attribute table.
+pub const IA32_PAT: u32 = 0x277;
+/// `IA32_EFER`: extended feature enables (see the `EFER_*` bits).
+pub const IA32_EFER: u32 = 0xC000_0080;
+/// `IA32_STAR`: segment selectors loaded by `syscall` and `sysret`.
+pub const IA32_STAR: u32 = 0xC000_0081;
+/// `IA32_LSTAR`: 64-bit `syscall` entry point.
+pub const IA32_LSTAR: u32 = 0xC000_0082;
+/// `IA32_FMASK`: RFLAGS bits cleared by `syscall`.
+pub const IA32_FMASK: u32 = 0xC000_0084;
+/// `IA32_FS_BASE`: base address of `fs`.
+pub const IA32_FS_BASE: u32 = 0xC000_0100;
+/// `IA32_GS_BASE`: base address of `gs`.
+pub const IA32_GS_BASE: u32 = 0xC000_0101;
+/// `IA32_KERNEL_GS_BASE`: base address swapped into `gs` by `swapgs`.
+pub const IA32_KERNEL_GS_BASE: u32 = 0xC000_0102;
+
+/// `IA32_EFER` bit: `syscall`/`sysret` enable.
+pub const EFER_SCE: u64 = 1 << 0;
+/// `IA32_EFER` bit: long mode enable.
+pub const EFER_LME: u64 = 1 << 8;
+/// `IA32_EFER` bit: long mode active (read-only).
+pub const EFER_LMA: u64 = 1 << 10;
+/// `IA32_EFER` bit: no-execute page protection enable.
+pub const EFER_NXE: u64 = 1 << 11;
+
+/// Reads MSR `msr` with `rdmsr`.
+///
+/// # Safety
+/// Must run at CPL 0, and `msr` must exist on this CPU; reading an unknown MSR raises a general protection fault.
+#[inline]
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!(
+            "rdmsr",
+            in("ecx") msr,
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    u64::from(high) << 32 | u64::from(low)
+}
+
+/// Writes `value` to MSR `msr` with `wrmsr`.
+///
+/// # Safety
+/// Must run at CPL 0, `msr` must exist on this CPU and accept `value` (reserved bits clear), or a general protection fault is raised. Many MSRs change how the CPU executes code, e.g. `IA32_EFER` or `IA32_LSTAR`; the caller must keep the system consistent.
+#[inline]
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack, preserves_flags)
+        );
+    }
+}
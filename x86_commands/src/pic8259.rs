@@ -0,0 +1,135 @@
+//! # 8259 Programmable Interrupt Controller
+//!
+//! This module drives the pair of cascaded Intel 8259 PICs found in every PC: the master handles IRQs 0-7 and the slave, connected to the master's IRQ 2, handles IRQs 8-15.
+//!
+//! ## Ports
+//! - `0x20` / `0x21`: Master command / data (interrupt mask).
+//! - `0xA0` / `0xA1`: Slave command / data (interrupt mask).
+//!
+//! ## Remapping
+//!
+//! At boot the master delivers its IRQs on vectors 0x08-0x0F, which collide with CPU exceptions. [`remap`] runs the initialization sequence (ICW1-ICW4) to move both PICs to new vector offsets, usually 0x20 and 0x28.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use polished_x86_commands::pic8259;
+//! pic8259::remap(0x20, 0x28);
+//! pic8259::unmask(1); // keyboard
+//! // ... in the keyboard handler:
+//! pic8259::eoi(1);
+//! ```
+
+use crate::port::Port;
+
+/// Master PIC command port.
+pub const PIC1_COMMAND: u16 = 0x20;
+/// Master PIC data (mask) port.
+pub const PIC1_DATA: u16 = 0x21;
+/// Slave PIC command port.
+pub const PIC2_COMMAND: u16 = 0xA0;
+/// Slave PIC data (mask) port.
+pub const PIC2_DATA: u16 = 0xA1;
+
+/// ICW1: initialization, ICW4 will follow.
+const ICW1_INIT: u8 = 0x11;
+/// ICW4: 8086/88 mode.
+const ICW4_8086: u8 = 0x01;
+/// OCW2: non-specific end of interrupt.
+const OCW2_EOI: u8 = 0x20;
+/// OCW3: read the In-Service Register on the next command port read.
+const OCW3_READ_ISR: u8 = 0x0B;
+/// OCW3: read the Interrupt Request Register on the next command port read.
+const OCW3_READ_IRR: u8 = 0x0A;
+/// IRQ line of the master that the slave is cascaded on.
+const CASCADE_IRQ: u8 = 2;
+
+#[inline]
+fn outb(port: u16, value: u8) {
+    unsafe { Port::<u8>::new(port).write(value) }
+}
+
+#[inline]
+fn inb(port: u16) -> u8 {
+    unsafe { Port::<u8>::new(port).read() }
+}
+
+/// Reinitializes both PICs so that IRQs 0-7 arrive on vectors `offset1..offset1 + 8` and IRQs 8-15 on `offset2..offset2 + 8`.
+///
+/// The interrupt masks are preserved across the initialization sequence.
+pub fn remap(offset1: u8, offset2: u8) {
+    let masks = get_masks();
+    outb(PIC1_COMMAND, ICW1_INIT);
+    outb(PIC2_COMMAND, ICW1_INIT);
+    outb(PIC1_DATA, offset1); // ICW2: vector offset
+    outb(PIC2_DATA, offset2);
+    outb(PIC1_DATA, 1 << CASCADE_IRQ); // ICW3: slave on IRQ 2
+    outb(PIC2_DATA, CASCADE_IRQ); // ICW3: cascade identity
+    outb(PIC1_DATA, ICW4_8086);
+    outb(PIC2_DATA, ICW4_8086);
+    set_masks(masks);
+}
+
+/// Returns the interrupt masks of both PICs (master in the low byte, slave in the high byte). A set bit means the IRQ is masked.
+pub fn get_masks() -> u16 {
+    inb(PIC1_DATA) as u16 | (inb(PIC2_DATA) as u16) << 8
+}
+
+/// Sets the interrupt masks of both PICs (master in the low byte, slave in the high byte).
+pub fn set_masks(masks: u16) {
+    outb(PIC1_DATA, masks as u8);
+    outb(PIC2_DATA, (masks >> 8) as u8);
+}
+
+/// Masks (disables) IRQ line `irq` (0-15).
+pub fn mask(irq: u8) {
+    let (port, bit) = mask_port(irq);
+    outb(port, inb(port) | bit);
+}
+
+/// Unmasks (enables) IRQ line `irq` (0-15).
+///
+/// Unmasking a slave IRQ (8-15) also unmasks the cascade line on the master.
+pub fn unmask(irq: u8) {
+    let (port, bit) = mask_port(irq);
+    outb(port, inb(port) & !bit);
+    if irq >= 8 {
+        outb(PIC1_DATA, inb(PIC1_DATA) & !(1 << CASCADE_IRQ));
+    }
+}
+
+fn mask_port(irq: u8) -> (u16, u8) {
+    if irq < 8 {
+        (PIC1_DATA, 1 << irq)
+    } else {
+        (PIC2_DATA, 1 << ((irq - 8) & 7))
+    }
+}
+
+/// Masks every IRQ line on both PICs.
+pub fn disable() {
+    set_masks(0xFFFF);
+}
+
+/// Signals end-of-interrupt for IRQ line `irq`.
+///
+/// IRQs from the slave (8-15) need an EOI on both PICs; IRQs 0-7 only on the master.
+pub fn eoi(irq: u8) {
+    if irq >= 8 {
+        outb(PIC2_COMMAND, OCW2_EOI);
+    }
+    outb(PIC1_COMMAND, OCW2_EOI);
+}
+
+/// Returns the combined In-Service Register (master in the low byte, slave in the high byte): the IRQs currently being serviced.
+pub fn in_service() -> u16 {
+    outb(PIC1_COMMAND, OCW3_READ_ISR);
+    outb(PIC2_COMMAND, OCW3_READ_ISR);
+    inb(PIC1_COMMAND) as u16 | (inb(PIC2_COMMAND) as u16) << 8
+}
+
+/// Returns the combined Interrupt Request Register (master in the low byte, slave in the high byte): the IRQs raised but not yet serviced.
+pub fn requested() -> u16 {
+    outb(PIC1_COMMAND, OCW3_READ_IRR);
+    outb(PIC2_COMMAND, OCW3_READ_IRR);
+    inb(PIC1_COMMAND) as u16 | (inb(PIC2_COMMAND) as u16) << 8
+}
@@ -0,0 +1,117 @@
+//! # I/O Ports
+//!
+//! x86 devices such as the PIC, the PIT and the PS/2 controller are reached through the separate 16-bit I/O address space with the `in` and `out` instructions. A [`Port`] names one address and the width of the values read and written there, so drivers keep their port numbers in typed constants instead of passing bare `u16`s to inline assembly.
+//!
+//! Ports are 8, 16 or 32 bits wide (`Port<u8>`, `Port<u16>`, `Port<u32>`, see [`PortValue`]). The Polished OS crates do all their port I/O through it, instead of inline assembly or the `x86_64` crate's port type.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use polished_x86_commands::port::Port;
+//! const STATUS: Port<u8> = Port::new(0x64);
+//! let status = unsafe { STATUS.read() };
+//! ```
+
+use core::arch::asm;
+use core::marker::PhantomData;
+
+/// An I/O port transferring values of type `T`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Port<T> {
+    port: u16,
+    _value: PhantomData<T>,
+}
+
+impl<T> Port<T> {
+    /// Creates a handle for I/O port `port`.
+    pub const fn new(port: u16) -> Self {
+        Port {
+            port,
+            _value: PhantomData,
+        }
+    }
+
+    /// Returns the port number.
+    pub const fn number(&self) -> u16 {
+        self.port
+    }
+}
+
+impl<T: PortValue> Port<T> {
+    /// Reads a value from the port with `in`.
+    ///
+    /// # Safety
+    /// Reading a device register can have side effects, e.g. pop a byte from a FIFO. The caller must own the device.
+    #[inline]
+    pub unsafe fn read(&self) -> T {
+        unsafe { T::read_from(self.port) }
+    }
+
+    /// Writes a value to the port with `out`.
+    ///
+    /// # Safety
+    /// Writing a device register can reconfigure the device or the whole machine. The caller must own the device.
+    #[inline]
+    pub unsafe fn write(&self, value: T) {
+        unsafe { T::write_to(self.port, value) }
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/// A value width the `in` and `out` instructions support: [`u8`], [`u16`] or [`u32`].
+///
+/// Sealed, so every port access in the kernel goes through the implementations below.
+pub trait PortValue: private::Sealed + Copy {
+    /// Reads a value of this width from I/O port `port`.
+    ///
+    /// # Safety
+    /// See [`Port::read`].
+    unsafe fn read_from(port: u16) -> Self;
+
+    /// Writes `value` to I/O port `port`.
+    ///
+    /// # Safety
+    /// See [`Port::write`].
+    unsafe fn write_to(port: u16, value: Self);
+}
+
+macro_rules! impl_port_value {
+    ($type:ty, $register:tt) => {
+        impl PortValue for $type {
+            #[inline]
+            unsafe fn read_from(port: u16) -> Self {
+                let value: $type;
+                unsafe {
+                    asm!(
+                        concat!("in ", $register, ", dx"),
+                        in("dx") port,
+                        out($register) value,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+                value
+            }
+
+            #[inline]
+            unsafe fn write_to(port: u16, value: Self) {
+                unsafe {
+                    asm!(
+                        concat!("out dx, ", $register),
+                        in("dx") port,
+                        in($register) value,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+    };
+}
+
+impl_port_value!(u8, "al");
+impl_port_value!(u16, "ax");
+impl_port_value!(u32, "eax");
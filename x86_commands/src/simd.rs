@@ -0,0 +1,342 @@
+//! # SSE/AVX Enablement and FPU State
+//!
+//! The kernel target does not disable SSE, so the compiler emits SSE instructions for `f32`/`f64` math (the graphics crate uses `libm`) and for vectorized copies. Those fault unless the CPU is told that the OS manages the extended state, and AVX additionally needs the XSAVE feature set. Firmware usually leaves SSE usable on the bootstrap processor, but application processors start from reset, so every CPU calls [`enable_simd`] before running compiled Rust code that may use it.
+//!
+//! ## Control Bits
+//!
+//! - `CR0`: `MP` set and `EM` cleared (no x87 emulation), `TS` cleared (no lazy-switch trap), `NE` set (native x87 error reporting).
+//! - `CR4`: `OSFXSR` (SSE and `fxsave`), `OSXMMEXCPT` (SIMD floating-point exceptions raise `#XM`), and `OSXSAVE` when the CPU has XSAVE.
+//! - `XCR0`: the state components `xsave` manages: x87 and SSE always, AVX when supported, and the AVX-512 components when supported and the whole state still fits in a [`SaveArea`].
+//!
+//! ## Saving State
+//!
+//! Interrupted code may be in the middle of SIMD work, and the registers belong to whoever was running, so the kernel must not clobber them:
+//! - The register-saving entry stubs (`syscall`, and the `polished_interrupts` stubs for the timer, page faults, general protection faults and double faults) call compiled Rust code. They reserve [`entry_state_size`] bytes of stack, 64-byte aligned, and call [`save_state`] before the handler and [`restore_state`] after it.
+//! - `x86-interrupt` handlers need nothing extra: LLVM saves every register such a handler clobbers, including the `xmm` registers. The kernel is not compiled for AVX, and legacy SSE instructions leave the upper halves of the `ymm` and `zmm` registers alone.
+//!
+//! Both routines use `xsave`/`xrstor` for every component in `XCR0` after [`enable_simd`] turned XSAVE on, and `fxsave`/`fxrstor` (x87 and SSE only) otherwise. A [`SaveArea`] holds one copy of the state in memory, e.g. for a context switch between user tasks; a new area holds the reset state, so restoring it gives a fresh task clean registers.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use polished_x86_commands::simd::{self, SaveArea};
+//! let features = simd::enable_simd();
+//! let mut area = SaveArea::new();
+//! area.save();
+//! // ... run code that clobbers the SIMD registers ...
+//! area.restore();
+//! ```
+
+use core::arch::asm;
+use core::arch::x86_64::__cpuid;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// `CR0` bit: monitor coprocessor (`wait` honors `TS`).
+const CR0_MP: u64 = 1 << 1;
+/// `CR0` bit: x87 emulation.
+const CR0_EM: u64 = 1 << 2;
+/// `CR0` bit: task switched (first FPU/SIMD use traps with `#NM`).
+const CR0_TS: u64 = 1 << 3;
+/// `CR0` bit: native x87 error reporting.
+const CR0_NE: u64 = 1 << 5;
+/// `CR4` bit: OS supports `fxsave`/`fxrstor` and SSE.
+const CR4_OSFXSR: u64 = 1 << 9;
+/// `CR4` bit: OS handles unmasked SIMD floating-point exceptions.
+const CR4_OSXMMEXCPT: u64 = 1 << 10;
+/// `CR4` bit: OS supports `xsave` and `XCR0`.
+const CR4_OSXSAVE: u64 = 1 << 18;
+
+/// `XCR0` bit: x87 state.
+pub const XCR0_X87: u64 = 1 << 0;
+/// `XCR0` bit: SSE state (`xmm0`-`xmm15`, `mxcsr`).
+pub const XCR0_SSE: u64 = 1 << 1;
+/// `XCR0` bit: upper halves of `ymm0`-`ymm15`.
+pub const XCR0_AVX: u64 = 1 << 2;
+/// `XCR0` bits: AVX-512 opmask registers, upper halves of `zmm0`-`zmm15`, and `zmm16`-`zmm31`.
+pub const XCR0_AVX512: u64 = 0b111 << 5;
+
+/// CPUID.01h:EDX bit: `fxsave`/`fxrstor`.
+const CPUID_FXSR: u32 = 1 << 24;
+/// CPUID.01h:ECX bit: `xsave` and `XCR0`.
+const CPUID_XSAVE: u32 = 1 << 26;
+/// CPUID.01h:ECX bit: AVX.
+const CPUID_AVX: u32 = 1 << 28;
+/// CPUID.07h:EBX bit: AVX-512 Foundation.
+const CPUID_AVX512F: u32 = 1 << 16;
+
+/// Size of a [`SaveArea`]; `XCR0` is limited to components whose state fits.
+pub const SAVE_AREA_SIZE: usize = 4096;
+/// Size of the `fxsave` area.
+pub const FXSAVE_AREA_SIZE: usize = 512;
+/// Default `mxcsr`: all exceptions masked, round to nearest.
+pub const MXCSR_DEFAULT: u32 = 0x1F80;
+/// Default x87 control word: all exceptions masked, 64-bit precision, round to nearest.
+pub const FCW_DEFAULT: u16 = 0x037F;
+
+/// Components saved by `xsave`, or 0 if [`SaveArea`] uses `fxsave`.
+static XSAVE_MASK: AtomicU64 = AtomicU64::new(0);
+/// Bytes the entry stubs reserve for [`save_state`]; see [`entry_state_size`].
+#[doc(hidden)]
+pub static ENTRY_STATE_SIZE: AtomicU64 = AtomicU64::new(FXSAVE_AREA_SIZE as u64);
+
+/// SIMD features turned on by [`enable_simd`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimdFeatures {
+    /// `xsave` manages the state, so [`SaveArea`] uses it.
+    pub xsave: bool,
+    /// AVX (`ymm` registers) is usable.
+    pub avx: bool,
+    /// AVX-512 (`zmm` and opmask registers) is usable.
+    pub avx512: bool,
+}
+
+/// Reads extended control register `xcr` with `xgetbv`.
+///
+/// # Safety
+/// `CR4.OSXSAVE` must be set, and `xcr` must exist (0 is `XCR0`).
+pub unsafe fn xgetbv(xcr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!(
+            "xgetbv",
+            in("ecx") xcr,
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    (high as u64) << 32 | low as u64
+}
+
+/// Writes extended control register `xcr` with `xsetbv`.
+///
+/// # Safety
+/// Must run at CPL 0 with `CR4.OSXSAVE` set; `value` must be a valid combination of supported components, or `xsetbv` raises `#GP`.
+pub unsafe fn xsetbv(xcr: u32, value: u64) {
+    unsafe {
+        asm!(
+            "xsetbv",
+            in("ecx") xcr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+unsafe fn read_cr0() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+unsafe fn write_cr0(value: u64) {
+    unsafe { asm!("mov cr0, {}", in(reg) value, options(nostack, preserves_flags)) };
+}
+
+unsafe fn read_cr4() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+unsafe fn write_cr4(value: u64) {
+    unsafe { asm!("mov cr4, {}", in(reg) value, options(nostack, preserves_flags)) };
+}
+
+/// Enables SSE on the calling CPU, plus XSAVE, AVX and AVX-512 where supported, and resets the x87 and SSE state.
+///
+/// Must run on every CPU, at CPL 0, before code that may use SIMD instructions. All CPUs are assumed to support the same features, so the last call decides what [`SaveArea`] saves.
+pub fn enable_simd() -> SimdFeatures {
+    let leaf1 = __cpuid(1);
+    let xsave = leaf1.ecx & CPUID_XSAVE != 0;
+    let mut features = SimdFeatures {
+        xsave,
+        avx: xsave && leaf1.ecx & CPUID_AVX != 0,
+        avx512: false,
+    };
+    // Safety: the bits only describe what the OS supports, for features CPUID reports.
+    unsafe {
+        write_cr0(read_cr0() & !(CR0_EM | CR0_TS) | CR0_MP | CR0_NE);
+        let mut cr4 = read_cr4();
+        if leaf1.edx & CPUID_FXSR != 0 {
+            cr4 |= CR4_OSFXSR | CR4_OSXMMEXCPT;
+        }
+        if xsave {
+            cr4 |= CR4_OSXSAVE;
+        }
+        write_cr4(cr4);
+    }
+    let mut mask = 0;
+    if xsave {
+        mask = XCR0_X87 | XCR0_SSE;
+        if features.avx {
+            mask |= XCR0_AVX;
+        }
+        // CPUID.0Dh:EAX lists the components XCR0 accepts.
+        let supported = u64::from(__cpuid(0xD).eax);
+        if __cpuid(0).eax >= 7
+            && __cpuid(7).ebx & CPUID_AVX512F != 0
+            && supported & XCR0_AVX512 == XCR0_AVX512
+        {
+            mask |= XCR0_AVX512;
+        }
+        // Safety: OSXSAVE is set and every bit in `mask` is supported.
+        unsafe { xsetbv(0, mask) };
+        if mask & XCR0_AVX512 != 0 && __cpuid(0xD).ebx as usize > SAVE_AREA_SIZE {
+            mask &= !XCR0_AVX512;
+            unsafe { xsetbv(0, mask) };
+        }
+        features.avx512 = mask & XCR0_AVX512 != 0;
+    }
+    XSAVE_MASK.store(mask, Ordering::Release);
+    ENTRY_STATE_SIZE.store(state_size() as u64, Ordering::Release);
+    // Safety: SSE is enabled now; these only reset the x87 and SSE control state.
+    unsafe {
+        asm!("fninit", options(nomem, nostack));
+        asm!("ldmxcsr [{}]", in(reg) &MXCSR_DEFAULT, options(nostack, readonly));
+    }
+    features
+}
+
+/// Returns the state components [`SaveArea`] saves with `xsave`, or 0 if it uses `fxsave`.
+pub fn xsave_mask() -> u64 {
+    XSAVE_MASK.load(Ordering::Acquire)
+}
+
+/// Returns how many bytes of a [`SaveArea`] the enabled components use.
+pub fn state_size() -> usize {
+    match xsave_mask() {
+        0 => FXSAVE_AREA_SIZE,
+        // CPUID.0Dh:EBX is the size for the components currently enabled in XCR0.
+        _ => __cpuid(0xD).ebx as usize,
+    }
+}
+
+/// Returns how many bytes of stack an entry stub reserves, below a 64-byte boundary, for [`save_state`].
+pub fn entry_state_size() -> u64 {
+    ENTRY_STATE_SIZE.load(Ordering::Acquire)
+}
+
+/// Saves the calling CPU's SIMD state to `area`, for the entry stubs.
+///
+/// Only touches RAX, RDX and the flags, and uses no SIMD registers itself, so it can run before any compiled Rust code.
+///
+/// # Safety
+/// `area` must be 64-byte aligned and hold at least [`entry_state_size`] writable bytes.
+#[unsafe(naked)]
+pub unsafe extern "C" fn save_state(area: *mut u8) {
+    core::arch::naked_asm!(
+        "mov rax, qword ptr [rip + {mask}]",
+        "test rax, rax",
+        "jz 2f",
+        // `xsave` only writes XSTATE_BV of the header, and `xrstor` faults unless the rest of it is zero
+        "mov qword ptr [rdi + 520], 0",
+        "mov qword ptr [rdi + 528], 0",
+        "mov rdx, rax",
+        "shr rdx, 32",
+        "xsave64 [rdi]",
+        "ret",
+        "2:",
+        "fxsave64 [rdi]",
+        "ret",
+        mask = sym XSAVE_MASK,
+    );
+}
+
+/// Loads the SIMD state [`save_state`] stored at `area`, for the entry stubs.
+///
+/// # Safety
+/// `area` must hold state written by [`save_state`] on a CPU with the same `XCR0`.
+#[unsafe(naked)]
+pub unsafe extern "C" fn restore_state(area: *const u8) {
+    core::arch::naked_asm!(
+        "mov rax, qword ptr [rip + {mask}]",
+        "test rax, rax",
+        "jz 2f",
+        "mov rdx, rax",
+        "shr rdx, 32",
+        "xrstor64 [rdi]",
+        "ret",
+        "2:",
+        "fxrstor64 [rdi]",
+        "ret",
+        mask = sym XSAVE_MASK,
+    );
+}
+
+/// Storage for the x87, SSE and (with XSAVE) AVX state of one CPU context, in `fxsave`/`xsave` layout.
+#[repr(C, align(64))]
+pub struct SaveArea {
+    bytes: [u8; SAVE_AREA_SIZE],
+}
+
+impl Default for SaveArea {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveArea {
+    /// An area holding the reset state: default x87 control word and `mxcsr`, zeroed registers, and an empty XSAVE header.
+    pub const fn new() -> Self {
+        let mut bytes = [0; SAVE_AREA_SIZE];
+        let fcw = FCW_DEFAULT.to_le_bytes();
+        bytes[0] = fcw[0];
+        bytes[1] = fcw[1];
+        let mxcsr = MXCSR_DEFAULT.to_le_bytes();
+        let mut i = 0;
+        while i < 4 {
+            bytes[24 + i] = mxcsr[i];
+            i += 1;
+        }
+        SaveArea { bytes }
+    }
+
+    /// Saves the calling CPU's SIMD state into the area.
+    pub fn save(&mut self) {
+        let mask = xsave_mask();
+        // Safety: the area is large enough and 64-byte aligned, as both instructions require.
+        unsafe {
+            if mask == 0 {
+                asm!("fxsave64 [{}]", in(reg) self.bytes.as_mut_ptr(), options(nostack, preserves_flags));
+            } else {
+                asm!(
+                    "xsave64 [{}]",
+                    in(reg) self.bytes.as_mut_ptr(),
+                    in("eax") mask as u32,
+                    in("edx") (mask >> 32) as u32,
+                    options(nostack, preserves_flags)
+                );
+            }
+        }
+    }
+
+    /// Loads the SIMD state held by the area into the calling CPU's registers.
+    pub fn restore(&self) {
+        let mask = xsave_mask();
+        // Safety: the area holds the reset state or state saved with the same instruction, so it is well-formed.
+        unsafe {
+            if mask == 0 {
+                asm!("fxrstor64 [{}]", in(reg) self.bytes.as_ptr(), options(nostack, preserves_flags, readonly));
+            } else {
+                asm!(
+                    "xrstor64 [{}]",
+                    in(reg) self.bytes.as_ptr(),
+                    in("eax") mask as u32,
+                    in("edx") (mask >> 32) as u32,
+                    options(nostack, preserves_flags, readonly)
+                );
+            }
+        }
+    }
+
+    /// Returns the saved `mxcsr`.
+    pub fn mxcsr(&self) -> u32 {
+        u32::from_le_bytes([
+            self.bytes[24],
+            self.bytes[25],
+            self.bytes[26],
+            self.bytes[27],
+        ])
+    }
+}
@@ -0,0 +1,143 @@
+//! # Time Stamp Counter and Delays
+//!
+//! The TSC is a 64-bit counter that the CPU increments at a fixed rate (on every CPU with an invariant TSC, which is every x86_64 CPU Polished OS targets in practice). Reading it with [`rdtsc`] costs a few dozen cycles and needs no device, so once its rate is known it is the cheapest clock for short busy-waits.
+//!
+//! ## Calibration
+//!
+//! [`calibrate`] finds the rate once at boot and stores it for [`frequency_hz`] and the delay helpers:
+//! - CPUID leaf `0x15` gives it directly on CPUs that report their crystal clock ([`frequency_from_cpuid`]).
+//! - Otherwise it is measured over a 10 ms one-shot of PIT channel 2 ([`measure_with_pit`]), which needs neither interrupts nor PIT channel 0.
+//!
+//! ## Delays
+//!
+//! [`delay_us`], [`delay_ms`] and [`poll_us`] spin on the TSC. Before calibration they fall back to writes to port `0x80`, each of which takes about a microsecond on the ISA bus, so early drivers get bounded waits of roughly the right length either way.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use polished_x86_commands::tsc;
+//! tsc::calibrate();
+//! tsc::delay_ms(10);
+//! ```
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::port::Port;
+
+/// Input clock frequency of the PIT in Hz.
+const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+/// Length of the PIT measurement in milliseconds.
+const PIT_CALIBRATION_MS: u64 = 10;
+/// PIT channel 2 data port.
+const PIT_CHANNEL2: Port<u8> = Port::new(0x42);
+/// PIT mode/command register.
+const PIT_COMMAND: Port<u8> = Port::new(0x43);
+/// Command: channel 2, access lobyte/hibyte, mode 0 (interrupt on terminal count), binary.
+const CMD_CHANNEL2_ONESHOT: u8 = 0xB0;
+/// Channel 2 gate (bit 0) and output (bit 5); bit 1 enables the speaker.
+const SPEAKER_CONTROL: Port<u8> = Port::new(0x61);
+const SPEAKER_CONTROL_OUT2: u8 = 0x20;
+/// POST diagnostic port; writing it only takes time.
+const IO_WAIT_PORT: Port<u8> = Port::new(0x80);
+
+/// TSC ticks per second, 0 until [`calibrate`] ran.
+static FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the time stamp counter.
+#[inline]
+pub fn rdtsc() -> u64 {
+    // Safety: `rdtsc` has no side effects.
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Returns the TSC frequency reported by CPUID leaf `0x15`, if the CPU reports its crystal clock.
+pub fn frequency_from_cpuid() -> Option<u64> {
+    let max_leaf = core::arch::x86_64::__cpuid(0).eax;
+    if max_leaf < 0x15 {
+        return None;
+    }
+    // EAX: denominator, EBX: numerator of the TSC/crystal ratio, ECX: crystal frequency in Hz.
+    let leaf = core::arch::x86_64::__cpuid(0x15);
+    if leaf.eax == 0 || leaf.ebx == 0 || leaf.ecx == 0 {
+        return None;
+    }
+    Some(u64::from(leaf.ecx) * u64::from(leaf.ebx) / u64::from(leaf.eax))
+}
+
+/// Measures the TSC frequency over a 10 ms one-shot of PIT channel 2.
+///
+/// Reprograms channel 2 and uses the speaker gate at port `0x61`, leaving the speaker itself off. Channel 0 and the PIC are untouched, so this works with interrupts disabled.
+pub fn measure_with_pit() -> u64 {
+    let count = (PIT_FREQUENCY_HZ * PIT_CALIBRATION_MS / 1000) as u16;
+    // Safety: channel 2 and the speaker gate are only used for polled waits at boot.
+    let cycles = unsafe {
+        // Gate low and speaker output disabled while programming
+        let control = SPEAKER_CONTROL.read() & !0x03;
+        SPEAKER_CONTROL.write(control);
+        PIT_COMMAND.write(CMD_CHANNEL2_ONESHOT);
+        PIT_CHANNEL2.write((count & 0xFF) as u8);
+        PIT_CHANNEL2.write((count >> 8) as u8);
+        // A rising edge on the gate starts the count
+        let start = rdtsc();
+        SPEAKER_CONTROL.write(control | 0x01);
+        while SPEAKER_CONTROL.read() & SPEAKER_CONTROL_OUT2 == 0 {
+            core::hint::spin_loop();
+        }
+        let end = rdtsc();
+        SPEAKER_CONTROL.write(control);
+        end.wrapping_sub(start)
+    };
+    cycles * 1000 / PIT_CALIBRATION_MS
+}
+
+/// Determines the TSC frequency, from CPUID if possible and against the PIT otherwise, and returns it.
+///
+/// Call once at boot, before anything relies on [`delay_us`] being accurate.
+pub fn calibrate() -> u64 {
+    let hz = frequency_from_cpuid().unwrap_or_else(measure_with_pit);
+    FREQUENCY_HZ.store(hz, Ordering::Relaxed);
+    hz
+}
+
+/// Returns the TSC frequency in Hz, or 0 before [`calibrate`].
+pub fn frequency_hz() -> u64 {
+    FREQUENCY_HZ.load(Ordering::Relaxed)
+}
+
+/// Waits about a microsecond with a write to port `0x80`.
+#[inline]
+pub fn io_wait() {
+    // Safety: port 0x80 is the POST diagnostic port and has no other function.
+    unsafe { IO_WAIT_PORT.write(0) };
+}
+
+/// Calls `condition` until it returns `true` or `us` microseconds have passed, and returns whether it did.
+pub fn poll_us(us: u64, mut condition: impl FnMut() -> bool) -> bool {
+    let hz = frequency_hz();
+    if hz == 0 {
+        return (0..us).any(|_| {
+            io_wait();
+            condition()
+        });
+    }
+    let start = rdtsc();
+    let ticks = (us as u128 * hz as u128 / 1_000_000) as u64;
+    loop {
+        if condition() {
+            return true;
+        }
+        if rdtsc().wrapping_sub(start) >= ticks {
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Busy-waits for `us` microseconds.
+pub fn delay_us(us: u64) {
+    poll_us(us, || false);
+}
+
+/// Busy-waits for `ms` milliseconds.
+pub fn delay_ms(ms: u64) {
+    delay_us(ms.saturating_mul(1000));
+}